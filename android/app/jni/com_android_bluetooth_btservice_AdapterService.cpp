@@ -29,8 +29,10 @@
 #include <shared_mutex>
 
 #include "com_android_bluetooth.h"
+#include "gd/common/init_flags.h"
 #include "hardware/bt_sock.h"
 #include "os/logging/log_redaction.h"
+#include "src/gatt/ffi.rs.h"
 #include "utils/Log.h"
 #include "utils/misc.h"
 
@@ -129,6 +131,15 @@ static int get_properties(int num_properties, bt_property_t* properties,
   return 0;
 }
 
+// The GATT servers of the Rust stack serve the adapter name in their own GAP
+// service, so keep it in sync with the adapter. The rest of the GAP
+// configuration is left as is.
+static void update_gap_device_name(const bt_property_t& name) {
+  const uint8_t* value = static_cast<const uint8_t*>(name.val);
+  bluetooth::gatt::set_gap_device_name(
+      ::rust::Slice<const uint8_t>(value, name.len));
+}
+
 static void adapter_properties_callback(bt_status_t status, int num_properties,
                                         bt_property_t* properties) {
   std::shared_lock<std::shared_timed_mutex> lock(jniObjMutex);
@@ -147,6 +158,14 @@ static void adapter_properties_callback(bt_status_t status, int num_properties,
     return;
   }
 
+  if (bluetooth::common::init_flags::private_gatt_is_enabled()) {
+    for (int i = 0; i < num_properties; i++) {
+      if (properties[i].type == BT_PROPERTY_BDNAME) {
+        update_gap_device_name(properties[i]);
+      }
+    }
+  }
+
   ScopedLocalRef<jbyteArray> val(
       sCallbackEnv.get(),
       (jbyteArray)sCallbackEnv->NewByteArray(num_properties));
@@ -169,6 +169,7 @@ static jmethodID method_onServerPhyUpdate;
 static jmethodID method_onServerPhyRead;
 static jmethodID method_onServerConnUpdate;
 static jmethodID method_onServerSubrateChange;
+static jmethodID method_onServerRequestCancelled;
 
 /**
  * Advertiser callback methods
@@ -663,8 +664,9 @@ void btgatts_service_added_cb(int status, int server_if,
       auto& curr_service = service[i];
       service_records.push_back(bluetooth::gatt::GattRecord{
           curr_service.uuid, (bluetooth::gatt::GattRecordType)curr_service.type,
-          curr_service.attribute_handle, curr_service.properties,
-          curr_service.extended_properties, curr_service.permissions});
+          curr_service.attribute_handle, curr_service.start_handle,
+          curr_service.properties, curr_service.extended_properties,
+          curr_service.permissions});
     }
     bluetooth::gatt::add_service(server_if, std::move(service_records));
   }
@@ -852,6 +854,15 @@ void btgatts_subrate_change_cb(int conn_id, uint16_t subrate_factor,
                                timeout, status);
 }
 
+void btgatts_request_cancelled_cb(int conn_id, int trans_id) {
+  std::shared_lock<std::shared_mutex> lock(callbacks_mutex);
+  CallbackEnv sCallbackEnv(__func__);
+  if (!sCallbackEnv.valid() || !mCallbacksObj) return;
+
+  sCallbackEnv->CallVoidMethod(mCallbacksObj, method_onServerRequestCancelled,
+                               conn_id, trans_id);
+}
+
 static const btgatt_server_callbacks_t sGattServerCallbacks = {
     btgatts_register_app_cb,
     btgatts_connection_cb,
@@ -870,6 +881,7 @@ static const btgatt_server_callbacks_t sGattServerCallbacks = {
     btgatts_phy_updated_cb,
     btgatts_conn_updated_cb,
     btgatts_subrate_change_cb,
+    btgatts_request_cancelled_cb,
 };
 
 /**
@@ -1343,6 +1355,8 @@ static void classInitNative(JNIEnv* env, jclass clazz) {
       env->GetMethodID(clazz, "onServerConnUpdate", "(IIIII)V");
   method_onServerSubrateChange =
       env->GetMethodID(clazz, "onServerSubrateChange", "(IIIIII)V");
+  method_onServerRequestCancelled =
+      env->GetMethodID(clazz, "onServerRequestCancelled", "(II)V");
 
   info("classInitNative: Success!");
 }
@@ -2039,6 +2053,12 @@ static void gattServerDisconnectNative(JNIEnv* env, jobject object,
   sGattIf->server->disconnect(serverIf, str2addr(env, address), conn_id);
 }
 
+static void gattServerHandOverConnectionNative(JNIEnv* env, jobject object,
+                                               jint server_if, jint conn_id) {
+  if (!sGattIf) return;
+  bluetooth::gatt::hand_over_connection(server_if, conn_id);
+}
+
 static void gattServerSetPreferredPhyNative(JNIEnv* env, jobject object,
                                             jint serverIf, jstring address,
                                             jint tx_phy, jint rx_phy,
@@ -2355,6 +2375,12 @@ static void ble_advertising_set_started_cb(int reg_id, int server_if,
   if (status == 0 /* AdvertisingCallback::AdvertisingStatus::SUCCESS */ &&
       server_if != 0) {
     bluetooth::gatt::associate_server_with_advertiser(server_if, advertiser_id);
+    if (bluetooth::common::init_flags::
+            private_gatt_shared_gap_service_is_enabled()) {
+      bluetooth::gatt::set_advertiser_isolation_policy(
+          advertiser_id,
+          bluetooth::gatt::IsolationPolicy::IsolatedWithSharedServices);
+    }
   }
 
   sCallbackEnv->CallVoidMethod(mAdvertiseCallbacksObj,
@@ -2820,6 +2846,8 @@ static JNINativeMethod sMethods[] = {
      (void*)gattServerConnectNative},
     {"gattServerDisconnectNative", "(ILjava/lang/String;I)V",
      (void*)gattServerDisconnectNative},
+    {"gattServerHandOverConnectionNative", "(II)V",
+     (void*)gattServerHandOverConnectionNative},
     {"gattServerSetPreferredPhyNative", "(ILjava/lang/String;III)V",
      (void*)gattServerSetPreferredPhyNative},
     {"gattServerReadPhyNative", "(ILjava/lang/String;)V",
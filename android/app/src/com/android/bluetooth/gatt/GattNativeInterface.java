@@ -288,6 +288,10 @@ public class GattNativeInterface {
         getGattService().onExecuteWrite(address, connId, transId, execWrite);
     }
 
+    void onServerRequestCancelled(int connId, int transId) {
+        getGattService().onServerRequestCancelled(connId, transId);
+    }
+
     void onResponseSendCompleted(int status, int attrHandle) {
         getGattService().onResponseSendCompleted(status, attrHandle);
     }
@@ -346,6 +350,7 @@ public class GattNativeInterface {
     private native void gattServerConnectNative(int serverIf, String address, boolean isDirect,
             int transport);
     private native void gattServerDisconnectNative(int serverIf, String address, int connId);
+    private native void gattServerHandOverConnectionNative(int serverIf, int connId);
     private native void gattServerSetPreferredPhyNative(int clientIf, String address, int txPhy,
             int rxPhy, int phyOptions);
     private native void gattServerReadPhyNative(int clientIf, String address);
@@ -587,6 +592,13 @@ public class GattNativeInterface {
         gattServerDisconnectNative(serverIf, address, connId);
     }
 
+    /**
+     * Hands a connection isolated to this server over to the shared database
+     */
+    public void gattServerHandOverConnection(int serverIf, int connId) {
+        gattServerHandOverConnectionNative(serverIf, connId);
+    }
+
     /**
      * Set the preferred connection PHY as a GATT server role
      */
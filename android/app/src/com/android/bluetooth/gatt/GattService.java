@@ -4423,6 +4423,13 @@ public class GattService extends ProfileService {
         app.callback.onExecuteWrite(address, transId, execWrite == 1);
     }
 
+    void onServerRequestCancelled(int connId, int transId) {
+        Log.w(TAG, "onServerRequestCancelled() connId=" + connId + ", transId=" + transId);
+
+        // the stack no longer waits for a response, so forget the request
+        mHandleMap.deleteRequest(transId);
+    }
+
     void onResponseSendCompleted(int status, int attrHandle) {
         if (DBG) {
             Log.d(TAG, "onResponseSendCompleted() handle=" + attrHandle);
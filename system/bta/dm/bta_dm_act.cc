@@ -3853,6 +3853,7 @@ static uint8_t bta_dm_ble_smp_cback(tBTM_LE_EVT event, const RawAddress& bda,
 
     case BTM_LE_COMPLT_EVT:
       sec_event.auth_cmpl.bd_addr = bda;
+      sec_event.auth_cmpl.is_ctkd = p_data->complt.smp_over_br;
       BTM_ReadDevInfo(bda, &sec_event.auth_cmpl.dev_type,
                       &sec_event.auth_cmpl.addr_type);
       p_name = BTM_SecReadDevName(bda);
@@ -37,10 +37,12 @@
 #include "btif/include/stack_manager.h"
 #include "device/include/controller.h"
 #include "device/include/interop.h"
+#include "gd/common/init_flags.h"
 #include "main/shim/dumpsys.h"
 #include "osi/include/allocator.h"
 #include "osi/include/log.h"
 #include "osi/include/osi.h"  // UNUSED_ATTR
+#include "rust/src/connection/ffi/connection_shim.h"
 #include "stack/include/bt_hdr.h"
 #include "stack/include/btm_ble_api_types.h"
 #include "stack/include/btu.h"  // do_in_main_thread
@@ -112,6 +114,11 @@ static const char* bta_gattc_op_code_name[] = {
     "Indication"    /* GATTC_OPTYPE_INDICATION */
 };
 
+/* Priority of background connections when the LE accept list is full: those
+ * of stack profiles are scheduled before those of apps */
+static constexpr uint8_t kAppBackgroundConnectionPriority = 0;
+static constexpr uint8_t kProfileBackgroundConnectionPriority = 1;
+
 /*****************************************************************************
  *  Action Functions
  ****************************************************************************/
@@ -170,7 +177,8 @@ static void bta_gattc_start_if(uint8_t client_if) {
 
 /** Register a GATT client application with BTA */
 void bta_gattc_register(const Uuid& app_uuid, tBTA_GATTC_CBACK* p_cback,
-                        BtaAppRegisterCallback cb, bool eatt_support) {
+                        BtaAppRegisterCallback cb, bool eatt_support,
+                        bool is_app) {
   tGATT_STATUS status = GATT_NO_RESOURCES;
   uint8_t client_if = 0;
   LOG_DEBUG("state: %d, uuid=%s", +bta_gattc_cb.state,
@@ -204,6 +212,13 @@ void bta_gattc_register(const Uuid& app_uuid, tBTA_GATTC_CBACK* p_cback,
             "main thread",
             +client_if, app_uuid.ToString().c_str());
 
+        if (bluetooth::common::init_flags::
+                use_unified_connection_manager_is_enabled()) {
+          bluetooth::connection::GetConnectionManager().set_client_priority(
+              client_if, is_app ? kAppBackgroundConnectionPriority
+                                : kProfileBackgroundConnectionPriority);
+        }
+
         do_in_main_thread(FROM_HERE,
                           base::Bind(&bta_gattc_start_if, client_if));
 
@@ -79,8 +79,9 @@ void BTA_GATTC_Disable(void) {
  * |cb| one time callback when registration is finished
  */
 void BTA_GATTC_AppRegister(tBTA_GATTC_CBACK* p_client_cb,
-                           BtaAppRegisterCallback cb, bool eatt_support) {
-  LOG_DEBUG("eatt_support=%d", eatt_support);
+                           BtaAppRegisterCallback cb, bool eatt_support,
+                           bool is_app) {
+  LOG_DEBUG("eatt_support=%d is_app=%d", eatt_support, is_app);
   if (!bta_sys_is_register(BTA_ID_GATTC)) {
     LOG_DEBUG("BTA_ID_GATTC not registered in BTA, registering it");
     bta_sys_register(BTA_ID_GATTC, &bta_gattc_reg);
@@ -88,7 +89,7 @@ void BTA_GATTC_AppRegister(tBTA_GATTC_CBACK* p_client_cb,
 
   do_in_main_thread(
       FROM_HERE, base::Bind(&bta_gattc_register, Uuid::GetRandom(), p_client_cb,
-                            std::move(cb), eatt_support));
+                            std::move(cb), eatt_support, is_app));
 }
 
 static void app_deregister_impl(tGATT_IF client_if) {
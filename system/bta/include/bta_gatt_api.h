@@ -443,9 +443,13 @@ using BtaAppRegisterCallback =
  * This function is called to register application callbacks with BTA GATTC
  *module.
  * p_client_cb - pointer to the application callback function.
+ * is_app - whether the client is an app rather than a stack profile. When the
+ *          LE accept list is full, background connections of stack profiles
+ *          are scheduled before those of apps.
  **/
 void BTA_GATTC_AppRegister(tBTA_GATTC_CBACK* p_client_cb,
-                           BtaAppRegisterCallback cb, bool eatt_support);
+                           BtaAppRegisterCallback cb, bool eatt_support,
+                           bool is_app = false);
 
 /*******************************************************************************
  *
@@ -24,6 +24,7 @@
 #include <hardware/bt_sdp.h>
 
 #include <cstdint>
+#include <vector>
 
 #include "bta/include/bta_sdp_api.h"
 #include "bta/sdp/bta_sdp_int.h"
@@ -481,8 +482,125 @@ static void bta_create_dip_sdp_record(bluetooth_sdp_record* record,
   }
 }
 
+/* Attribute lists of the raw records found by the last search */
+static std::vector<uint8_t> bta_sdp_raw_attr_lists[BTA_SDP_MAX_RECORDS];
+
+/* Appends the header of a data element of |type| with a |len| bytes value */
+static void bta_sdp_append_de_header(std::vector<uint8_t>& out, uint8_t type,
+                                     uint32_t len) {
+  switch (type) {
+    case TEXT_STR_DESC_TYPE:
+    case DATA_ELE_SEQ_DESC_TYPE:
+    case DATA_ELE_ALT_DESC_TYPE:
+    case URL_DESC_TYPE:
+      if (len <= UINT8_MAX) {
+        out.push_back((type << 3) | SIZE_IN_NEXT_BYTE);
+      } else if (len <= UINT16_MAX) {
+        out.push_back((type << 3) | SIZE_IN_NEXT_WORD);
+        out.push_back(len >> 8);
+      } else {
+        out.push_back((type << 3) | SIZE_IN_NEXT_LONG);
+        out.push_back(len >> 24);
+        out.push_back(len >> 16);
+        out.push_back(len >> 8);
+      }
+      out.push_back(len);
+      break;
+    default:
+      switch (len) {
+        case 2:
+          out.push_back((type << 3) | SIZE_TWO_BYTES);
+          break;
+        case 4:
+          out.push_back((type << 3) | SIZE_FOUR_BYTES);
+          break;
+        case 8:
+          out.push_back((type << 3) | SIZE_EIGHT_BYTES);
+          break;
+        case 16:
+          out.push_back((type << 3) | SIZE_SIXTEEN_BYTES);
+          break;
+        default:
+          out.push_back((type << 3) | SIZE_ONE_BYTE);
+          break;
+      }
+      break;
+  }
+}
+
+/* Appends the data element of a discovered attribute value, including the
+ * values of its sub-attributes for sequences and alternatives */
+static void bta_sdp_append_de(std::vector<uint8_t>& out,
+                              const tSDP_DISC_ATTR* p_attr) {
+  uint8_t type = SDP_DISC_ATTR_TYPE(p_attr->attr_len_type);
+  uint32_t len = SDP_DISC_ATTR_LEN(p_attr->attr_len_type);
+
+  switch (type) {
+    case UINT_DESC_TYPE:
+    case TWO_COMP_INT_DESC_TYPE:
+    case UUID_DESC_TYPE:
+    case BOOLEAN_DESC_TYPE:
+      bta_sdp_append_de_header(out, type, len);
+      if (len == 1) {
+        out.push_back(p_attr->attr_value.v.u8);
+      } else if (len == 2) {
+        out.push_back(p_attr->attr_value.v.u16 >> 8);
+        out.push_back(p_attr->attr_value.v.u16);
+      } else if (len == 4) {
+        out.push_back(p_attr->attr_value.v.u32 >> 24);
+        out.push_back(p_attr->attr_value.v.u32 >> 16);
+        out.push_back(p_attr->attr_value.v.u32 >> 8);
+        out.push_back(p_attr->attr_value.v.u32);
+      } else {
+        out.insert(out.end(), p_attr->attr_value.v.array,
+                   p_attr->attr_value.v.array + len);
+      }
+      break;
+    case TEXT_STR_DESC_TYPE:
+    case URL_DESC_TYPE:
+      bta_sdp_append_de_header(out, type, len);
+      out.insert(out.end(), p_attr->attr_value.v.array,
+                 p_attr->attr_value.v.array + len);
+      break;
+    case DATA_ELE_SEQ_DESC_TYPE:
+    case DATA_ELE_ALT_DESC_TYPE: {
+      std::vector<uint8_t> elements;
+      for (const tSDP_DISC_ATTR* p_sub = p_attr->attr_value.v.p_sub_attr;
+           p_sub != NULL; p_sub = p_sub->p_next_attr) {
+        bta_sdp_append_de(elements, p_sub);
+      }
+      bta_sdp_append_de_header(out, type, elements.size());
+      out.insert(out.end(), elements.begin(), elements.end());
+      break;
+    }
+    default:
+      /* Nil */
+      out.push_back(0);
+      break;
+  }
+}
+
+/* Builds the attribute list of a discovered record: a data element sequence of
+ * attribute IDs, each followed by the attribute value */
+static void bta_sdp_build_attr_list(std::vector<uint8_t>& out,
+                                    const tSDP_DISC_REC* p_rec) {
+  std::vector<uint8_t> attrs;
+  for (const tSDP_DISC_ATTR* p_attr = p_rec->p_first_attr; p_attr != NULL;
+       p_attr = p_attr->p_next_attr) {
+    attrs.push_back((UINT_DESC_TYPE << 3) | SIZE_TWO_BYTES);
+    attrs.push_back(p_attr->attr_id >> 8);
+    attrs.push_back(p_attr->attr_id);
+    bta_sdp_append_de(attrs, p_attr);
+  }
+
+  out.clear();
+  bta_sdp_append_de_header(out, DATA_ELE_SEQ_DESC_TYPE, attrs.size());
+  out.insert(out.end(), attrs.begin(), attrs.end());
+}
+
 static void bta_create_raw_sdp_record(bluetooth_sdp_record* record,
-                                      tSDP_DISC_REC* p_rec) {
+                                      tSDP_DISC_REC* p_rec,
+                                      std::vector<uint8_t>& attr_list) {
   tSDP_DISC_ATTR* p_attr;
   tSDP_PROTOCOL_ELEM pe;
 
@@ -511,8 +629,11 @@ static void bta_create_raw_sdp_record(bluetooth_sdp_record* record,
   if (SDP_FindProtocolListElemInRec(p_rec, UUID_PROTOCOL_RFCOMM, &pe)) {
     record->pse.hdr.rfcomm_channel_number = pe.params[0];
   }
-  record->hdr.user1_ptr_len = p_bta_sdp_cfg->p_sdp_db->raw_size;
-  record->hdr.user1_ptr = p_bta_sdp_cfg->p_sdp_db->raw_data;
+
+  /* Pass all the attributes of the record */
+  bta_sdp_build_attr_list(attr_list, p_rec);
+  record->hdr.user1_ptr_len = attr_list.size();
+  record->hdr.user1_ptr = attr_list.data();
 }
 
 /** Callback from btm after search is completed */
@@ -580,13 +701,8 @@ static void bta_sdp_search_cback(tSDP_RESULT result, const void* user_data) {
         /* we do not have specific structure for this */
         APPL_TRACE_DEBUG("%s() - profile not identified. using raw data",
                          __func__);
-        bta_create_raw_sdp_record(&evt_data.records[count], p_rec);
-        p_rec = NULL;  // Terminate loop
-        /* For raw, we only extract the first entry, and then return the
-           entire raw data chunk.
-           TODO: Find a way to split the raw data into record chunks, and
-           iterate to extract generic data for each chunk - e.g. rfcomm
-           channel and service name. */
+        bta_create_raw_sdp_record(&evt_data.records[count], p_rec,
+                                  bta_sdp_raw_attr_lists[count]);
       }
       count++;
     } while (p_rec != NULL && count < BTA_SDP_MAX_RECORDS);
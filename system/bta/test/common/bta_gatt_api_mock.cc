@@ -28,7 +28,8 @@ void gatt::SetMockBtaGattInterface(
 }
 
 void BTA_GATTC_AppRegister(tBTA_GATTC_CBACK* p_client_cb,
-                           BtaAppRegisterCallback cb, bool eatt_support) {
+                           BtaAppRegisterCallback cb, bool eatt_support,
+                           bool is_app) {
   LOG_ASSERT(gatt_interface) << "Mock GATT interface not set!";
   gatt_interface->AppRegister(p_client_cb, cb, eatt_support);
 }
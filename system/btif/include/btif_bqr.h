@@ -91,6 +91,10 @@ static constexpr uint32_t kQualityEventMaskLmpMessageTrace = 0x1 << 16;
 static constexpr uint32_t kQualityEventMaskBtSchedulingTrace = 0x1 << 17;
 static constexpr uint32_t kQualityEventMaskControllerDbgInfo = 0x1 << 18;
 static constexpr uint32_t kQualityEventMaskVendorSpecificTrace = 0x1 << 31;
+// The quality events delivered through the link quality report callback.
+static constexpr uint32_t kQualityEventMaskLinkQuality =
+    kQualityEventMaskMonitorMode | kQualityEventMaskApproachLsto |
+    kQualityEventMaskA2dpAudioChoppy | kQualityEventMaskScoVoiceChoppy;
 static constexpr uint32_t kQualityEventMaskAll =
     kQualityEventMaskMonitorMode | kQualityEventMaskApproachLsto |
     kQualityEventMaskA2dpAudioChoppy | kQualityEventMaskScoVoiceChoppy |
@@ -384,6 +388,13 @@ std::string PacketTypeToString(uint8_t packet_type);
 //   mechanism in the Bluetooth controller.
 void EnableBtQualityReport(bool is_enable);
 
+// Enable/Disable the link quality related events of Bluetooth Quality Report,
+// independently of the properties read by EnableBtQualityReport.
+//
+// @param is_enable True/False to enable/disable the link quality events.
+// @param min_report_interval_ms The minimum time interval between two reports.
+void EnableLinkQualityReport(bool is_enable, uint16_t min_report_interval_ms);
+
 // Configure Bluetooth Quality Report setting to the Bluetooth controller.
 //
 // @param bqr_config The struct of configuration parameters.
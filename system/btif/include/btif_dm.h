@@ -19,6 +19,8 @@
 #ifndef BTIF_DM_H
 #define BTIF_DM_H
 
+#include <hardware/bluetooth.h>
+
 #include "bta/include/bta_api.h"
 #include "bte_appl.h"
 #include "btif_uid.h"
@@ -75,6 +77,13 @@ void btif_dm_proc_io_rsp(const RawAddress& bd_addr, tBTM_IO_CAP io_cap,
  */
 void btif_dm_get_local_class_of_device(DEV_CLASS device_class);
 
+/**
+ * Fills the security state of the bond with the remote device and of the
+ * current links to it, without any of the keys
+ */
+void btif_dm_get_remote_security_info(const RawAddress& bd_addr,
+                                      bt_remote_security_info_t* info);
+
 /**
  * Out-of-band functions
  */
@@ -22,6 +22,8 @@
 #include <bluetooth/uuid.h>
 #include <hardware/bluetooth.h>
 
+#include <vector>
+
 #include "bt_target.h"
 #include "stack/include/bt_device_type.h"
 #include "stack/include/bt_octets.h"
@@ -187,6 +189,32 @@ bt_status_t btif_storage_add_bonded_device(RawAddress* remote_bd_addr,
  ******************************************************************************/
 bt_status_t btif_storage_remove_bonded_device(const RawAddress* remote_bd_addr);
 
+/*******************************************************************************
+ *
+ * Function         btif_storage_set_remote_ctkd
+ *
+ * Description      BTIF storage API - Records that keys of the bonded device
+ *                  were derived from the other transport
+ *
+ * Returns          BT_STATUS_SUCCESS if the store was successful,
+ *                  BT_STATUS_FAIL otherwise
+ *
+ ******************************************************************************/
+bt_status_t btif_storage_set_remote_ctkd(const RawAddress* remote_bd_addr);
+
+/*******************************************************************************
+ *
+ * Function         btif_storage_get_remote_bond_security_info
+ *
+ * Description      BTIF storage API - Fills the stored bond part of the
+ *                  security info of the remote device: the link key type, the
+ *                  LE keys distributed and whether keys were derived. The keys
+ *                  themselves are not read.
+ *
+ ******************************************************************************/
+void btif_storage_get_remote_bond_security_info(
+    const RawAddress* remote_bd_addr, bt_remote_security_info_t* info);
+
 /*******************************************************************************
  *
  * Function         btif_storage_load_le_devices
@@ -294,6 +322,17 @@ Octet16 btif_storage_get_gatt_cl_db_hash(const RawAddress& bd_addr);
 /** Remove last server database hash for remote client */
 void btif_storage_remove_gatt_cl_db_hash(const RawAddress& bd_addr);
 
+/** Store the state of a remote client of the Rust GATT server */
+void btif_storage_set_gatt_cl_rust_state(const RawAddress& bd_addr,
+                                         std::vector<uint8_t> state);
+
+/** Remove the state of a remote client of the Rust GATT server */
+void btif_storage_remove_gatt_cl_rust_state(const RawAddress& bd_addr);
+
+/** Get the state of a remote client of the Rust GATT server (empty if none) */
+std::vector<uint8_t> btif_storage_get_gatt_cl_rust_state(
+    const RawAddress& bd_addr);
+
 /** Get the hearing aid device properties. */
 bool btif_storage_get_hearing_aid_prop(
     const RawAddress& address, uint8_t* capabilities, uint64_t* hi_sync_id,
@@ -28,6 +28,7 @@
 #define LOG_TAG "bt_btif"
 
 #include <base/logging.h>
+#include <future>
 #include <hardware/bluetooth.h>
 #include <hardware/bluetooth_headset_interface.h>
 #include <hardware/bt_av.h>
@@ -91,6 +92,8 @@
 #include "device/include/interop_config.h"
 #include "gd/common/init_flags.h"
 #include "gd/os/parameter_provider.h"
+#include "main/shim/acl_api.h"
+#include "main/shim/btm_api.h"
 #include "main/shim/dumpsys.h"
 #include "main/shim/shim.h"
 #include "osi/include/alarm.h"
@@ -100,11 +103,13 @@
 #include "osi/include/osi.h"
 #include "osi/include/wakelock.h"
 #include "profile_log_levels.h"
+#include "rust/src/gatt/ffi/gatt_shim.h"
 #include "stack/btm/btm_sco_hfp_hal.h"
 #include "stack/gatt/connection_manager.h"
 #include "stack/include/a2dp_api.h"
 #include "stack/include/avdt_api.h"
 #include "stack/include/btm_api.h"
+#include "stack/include/btm_ble_api.h"
 #include "stack/include/btu.h"
 #include "stack/include/hfp_msbc_decoder.h"
 #include "stack/include/hfp_msbc_encoder.h"
@@ -825,6 +830,95 @@ static bool pbap_pse_dynamic_version_upgrade_is_enabled() {
   return false;
 }
 
+static int set_link_quality_report(bool enable,
+                                   uint16_t min_report_interval_ms) {
+  if (!interface_ready()) return BT_STATUS_NOT_READY;
+
+  do_in_main_thread(FROM_HERE,
+                    base::BindOnce(bluetooth::bqr::EnableLinkQualityReport,
+                                   enable, min_report_interval_ms));
+  return BT_STATUS_SUCCESS;
+}
+
+static int set_le_privacy_parameters(bool use_non_resolvable_address,
+                                     uint32_t rpa_rotation_timeout_ms) {
+  if (!interface_ready()) return BT_STATUS_NOT_READY;
+
+  bluetooth::shim::ACL_SetLePrivacyParameters(
+      use_non_resolvable_address,
+      std::chrono::milliseconds(rpa_rotation_timeout_ms));
+  do_in_main_thread(FROM_HERE, base::BindOnce([]() {
+                      if (BTM_BleLocalPrivacyEnabled()) {
+                        BTM_BleConfigPrivacy(true);
+                      }
+                    }));
+  return BT_STATUS_SUCCESS;
+}
+
+static int get_local_irk(uint8_t* irk) {
+  if (!interface_ready()) return BT_STATUS_NOT_READY;
+
+  Octet16 key;
+  bt_status_t status =
+      btif_storage_get_ble_local_key(BTIF_DM_LE_LOCAL_KEY_IRK, &key);
+  if (status != BT_STATUS_SUCCESS) return status;
+
+  memcpy(irk, key.data(), key.size());
+  return BT_STATUS_SUCCESS;
+}
+
+static int get_remote_security_info(const RawAddress* bd_addr,
+                                    bt_remote_security_info_t* info) {
+  if (!interface_ready()) return BT_STATUS_NOT_READY;
+
+  // The link state is owned by the main thread, so it's read there.
+  std::promise<bt_remote_security_info_t> promise;
+  auto future = promise.get_future();
+  bt_status_t status = do_in_main_thread(
+      FROM_HERE, base::BindOnce(
+                     [](RawAddress addr,
+                        std::promise<bt_remote_security_info_t> promise) {
+                       bt_remote_security_info_t info = {};
+                       btif_dm_get_remote_security_info(addr, &info);
+                       promise.set_value(info);
+                     },
+                     *bd_addr, std::move(promise)));
+  if (status != BT_STATUS_SUCCESS) return status;
+
+  if (future.wait_for(std::chrono::seconds(1)) != std::future_status::ready) {
+    LOG_WARN("Timed out reading the security info of %s",
+             ADDRESS_TO_LOGGABLE_CSTR(*bd_addr));
+    return BT_STATUS_FAIL;
+  }
+
+  *info = future.get();
+  return BT_STATUS_SUCCESS;
+}
+
+static int regenerate_local_irk() {
+  if (!interface_ready()) return BT_STATUS_NOT_READY;
+
+  do_in_main_thread(FROM_HERE,
+                    base::BindOnce(base::IgnoreResult(
+                        &bluetooth::shim::BTM_BleResetId)));
+  return BT_STATUS_SUCCESS;
+}
+
+static int apply_scan_parameters() {
+  if (!interface_ready()) return BT_STATUS_NOT_READY;
+
+  do_in_main_thread(FROM_HERE, base::BindOnce(BTM_ApplyScanParameters));
+  return BT_STATUS_SUCCESS;
+}
+
+static int set_gap_configuration(
+    const bt_gap_configuration_t* configuration) {
+  if (!interface_ready()) return BT_STATUS_NOT_READY;
+
+  bluetooth::gatt::SetGapConfiguration(*configuration);
+  return BT_STATUS_SUCCESS;
+}
+
 static const void* get_profile_interface(const char* profile_id) {
   LOG_INFO("%s: id = %s", __func__, profile_id);
 
@@ -1134,6 +1228,13 @@ EXPORT_SYMBOL bt_interface_t bluetoothInterface = {
     .get_remote_pbap_pce_version = get_remote_pbap_pce_version,
     .pbap_pse_dynamic_version_upgrade_is_enabled =
         pbap_pse_dynamic_version_upgrade_is_enabled,
+    .set_link_quality_report = set_link_quality_report,
+    .set_le_privacy_parameters = set_le_privacy_parameters,
+    .get_local_irk = get_local_irk,
+    .get_remote_security_info = get_remote_security_info,
+    .regenerate_local_irk = regenerate_local_irk,
+    .apply_scan_parameters = apply_scan_parameters,
+    .set_gap_configuration = set_gap_configuration,
 };
 
 // callback reporting helpers
@@ -1383,22 +1484,31 @@ void invoke_energy_info_cb(bt_activity_energy_info energy_info,
           energy_info, uid_data));
 }
 
-void invoke_link_quality_report_cb(uint64_t timestamp, int report_id, int rssi,
-                                   int snr, int retransmission_count,
+void invoke_link_quality_report_cb(RawAddress bd_addr, uint64_t timestamp,
+                                   int report_id, int rssi, int snr,
+                                   int retransmission_count,
                                    int packets_not_receive_count,
                                    int negative_acknowledgement_count) {
   do_in_jni_thread(
       FROM_HERE,
       base::BindOnce(
-          [](uint64_t timestamp, int report_id, int rssi, int snr,
-             int retransmission_count, int packets_not_receive_count,
+          [](RawAddress bd_addr, uint64_t timestamp, int report_id, int rssi,
+             int snr, int retransmission_count, int packets_not_receive_count,
              int negative_acknowledgement_count) {
+            if (bt_hal_cbacks &&
+                bt_hal_cbacks->link_quality_report_with_address_cb) {
+              HAL_CBACK(bt_hal_cbacks, link_quality_report_with_address_cb,
+                        &bd_addr, timestamp, report_id, rssi, snr,
+                        retransmission_count, packets_not_receive_count,
+                        negative_acknowledgement_count);
+              return;
+            }
             HAL_CBACK(bt_hal_cbacks, link_quality_report_cb, timestamp,
                       report_id, rssi, snr, retransmission_count,
                       packets_not_receive_count,
                       negative_acknowledgement_count);
           },
-          timestamp, report_id, rssi, snr, retransmission_count,
+          bd_addr, timestamp, report_id, rssi, snr, retransmission_count,
           packets_not_receive_count, negative_acknowledgement_count));
 }
 
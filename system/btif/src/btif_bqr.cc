@@ -35,6 +35,7 @@
 #include "osi/include/properties.h"
 #include "raw_address.h"
 #include "stack/btm/btm_dev.h"
+#include "stack/include/acl_api.h"
 
 namespace bluetooth {
 namespace bqr {
@@ -339,6 +340,41 @@ void EnableBtQualityReport(bool is_enable) {
   ConfigureBqr(bqr_config);
 }
 
+void EnableLinkQualityReport(bool is_enable, uint16_t min_report_interval_ms) {
+  LOG(INFO) << __func__ << ": is_enable: " << logbool(is_enable)
+            << ", Interval: " << min_report_interval_ms;
+
+  BqrConfiguration bqr_config = {};
+  bqr_config.quality_event_mask = kQualityEventMaskLinkQuality;
+  if (is_enable) {
+    bqr_config.report_action = REPORT_ACTION_ADD;
+    bqr_config.minimum_report_interval_ms = min_report_interval_ms;
+  } else {
+    // Keep the events enabled through the properties by EnableBtQualityReport.
+    char bqr_prop_evtmask[PROPERTY_VALUE_MAX] = {0};
+    char bqr_prop_interval_ms[PROPERTY_VALUE_MAX] = {0};
+    osi_property_get(kpPropertyEventMask, bqr_prop_evtmask, "");
+    osi_property_get(kpPropertyMinReportIntervalMs, bqr_prop_interval_ms, "");
+    if (strlen(bqr_prop_evtmask) != 0 && strlen(bqr_prop_interval_ms) != 0) {
+      bqr_config.quality_event_mask &=
+          ~static_cast<uint32_t>(atoi(bqr_prop_evtmask));
+    }
+    if (bqr_config.quality_event_mask == kQualityEventMaskAllOff) {
+      LOG(INFO) << __func__
+                << ": Link quality events are enabled by the properties";
+      return;
+    }
+    bqr_config.report_action = REPORT_ACTION_DELETE;
+    bqr_config.minimum_report_interval_ms = kMinReportIntervalNoLimit;
+  }
+
+  tBTM_BLE_VSC_CB cmn_vsc_cb;
+  BTM_BleGetVendorCapabilities(&cmn_vsc_cb);
+  vendor_cap_supported_version = cmn_vsc_cb.version_supported;
+
+  ConfigureBqr(bqr_config);
+}
+
 void ConfigureBqr(const BqrConfiguration& bqr_config) {
   if (bqr_config.report_action > REPORT_ACTION_CLEAR ||
       bqr_config.quality_event_mask > kQualityEventMaskAll ||
@@ -539,10 +575,16 @@ void AddLinkQualityEventToQueue(uint8_t length,
   RawAddress bd_addr;
 
   p_bqr_event->ParseBqrLinkQualityEvt(length, p_link_quality_event);
+  bd_addr = p_bqr_event->bqr_link_quality_event_.bdaddr;
+  if (bd_addr.IsEmpty()) {
+    // Older controllers do not include the remote address in the report.
+    bd_addr = acl_address_from_handle(
+        p_bqr_event->bqr_link_quality_event_.connection_handle);
+  }
 
   LOG(WARNING) << *p_bqr_event;
   GetInterfaceToProfiles()->events->invoke_link_quality_report_cb(
-      bluetooth::common::time_get_os_boottime_ms(),
+      bd_addr, bluetooth::common::time_get_os_boottime_ms(),
       p_bqr_event->bqr_link_quality_event_.quality_report_id,
       p_bqr_event->bqr_link_quality_event_.rssi,
       p_bqr_event->bqr_link_quality_event_.snr,
@@ -886,6 +886,37 @@ uint16_t btif_dm_get_connection_state(const RawAddress* bd_addr) {
   return rc;
 }
 
+/*******************************************************************************
+ *
+ * Function         btif_dm_get_remote_security_info
+ *
+ * Description      Fills the security state of the bond with the remote device
+ *                  and of the current links to it, without any of the keys
+ *
+ ******************************************************************************/
+void btif_dm_get_remote_security_info(const RawAddress& bd_addr,
+                                      bt_remote_security_info_t* info) {
+  btif_storage_get_remote_bond_security_info(&bd_addr, info);
+
+  info->bredr_encrypted = BTM_IsEncrypted(bd_addr, BT_TRANSPORT_BR_EDR);
+  info->bredr_authenticated =
+      info->bredr_encrypted &&
+      BTM_IsLinkKeyAuthed(bd_addr, BT_TRANSPORT_BR_EDR);
+  info->le_encrypted = BTM_IsEncrypted(bd_addr, BT_TRANSPORT_LE);
+  info->le_authenticated =
+      info->le_encrypted && BTM_IsLinkKeyAuthed(bd_addr, BT_TRANSPORT_LE);
+
+  info->encryption_key_size = 0;
+  if (info->bredr_encrypted) {
+    tBTM_SEC_DEV_REC* p_dev_rec = btm_find_dev(bd_addr);
+    if (p_dev_rec != nullptr) {
+      info->encryption_key_size = p_dev_rec->get_encryption_key_size();
+    }
+  } else if (info->le_encrypted) {
+    info->encryption_key_size = btm_ble_read_sec_key_size(bd_addr);
+  }
+}
+
 /******************************************************************************
  *
  *  BTIF DM callback events
@@ -1163,6 +1194,9 @@ static void btif_dm_auth_cmpl_evt(tBTA_DM_AUTH_CMPL* p_auth_cmpl) {
         ret = BT_STATUS_FAIL;
       }
       ASSERTC(ret == BT_STATUS_SUCCESS, "storing link key failed", ret);
+      if (ret == BT_STATUS_SUCCESS && p_auth_cmpl->is_ctkd) {
+        btif_storage_set_remote_ctkd(&bd_addr);
+      }
     } else {
       BTIF_TRACE_DEBUG(
           "%s: Temporary key. Not storing. key_type=0x%x, bond_type=%d",
@@ -3442,6 +3476,9 @@ static void btif_dm_ble_auth_cmpl_evt(tBTA_DM_AUTH_CMPL* p_auth_cmpl) {
       state = BT_BOND_STATE_NONE;
     } else {
       btif_dm_save_ble_bonding_keys(bd_addr);
+      if (p_auth_cmpl->is_ctkd) {
+        btif_storage_set_remote_ctkd(&bd_addr);
+      }
 
       if (pairing_cb.gatt_over_le ==
           btif_dm_pairing_cb_t::ServiceDiscoveryState::NOT_STARTED) {
@@ -259,7 +259,7 @@ static bt_status_t btif_gattc_register_app(const Uuid& uuid,
                       uuid, client_id, status));
                 },
                 uuid),
-            eatt_support);
+            eatt_support, /* is_app= */ true);
       },
       uuid, eatt_support));
 }
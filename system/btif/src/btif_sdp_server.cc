@@ -81,6 +81,7 @@ static int add_pbaps_sdp(const bluetooth_sdp_pse_record* rec);
 static int add_opps_sdp(const bluetooth_sdp_ops_record* rec);
 static int add_saps_sdp(const bluetooth_sdp_sap_record* rec);
 static int add_mps_sdp(const bluetooth_sdp_mps_record* rec);
+static int add_custom_sdp(const bluetooth_sdp_hdr_overlay* rec);
 bt_status_t remove_sdp_record(int record_id);
 static int free_sdp_slot(int id);
 
@@ -389,6 +390,9 @@ void on_create_record_event(int id) {
       case SDP_TYPE_MPS:
         handle = add_mps_sdp(&record->mps);
         break;
+      case SDP_TYPE_CUSTOM:
+        handle = add_custom_sdp(&record->hdr);
+        break;
       case SDP_TYPE_RAW:
         if (record->hdr.rfcomm_channel_number > 0) {
           handle = add_rfc_sdp_rec(record->hdr.service_name, record->hdr.uuid,
@@ -960,3 +964,85 @@ static int add_mps_sdp(const bluetooth_sdp_mps_record* rec) {
                    sdp_handle);
   return sdp_handle;
 }
+
+/* Create an SDP record from the attribute list stored in user1_ptr of a
+ * bluetooth_sdp_hdr_overlay */
+static int add_custom_sdp(const bluetooth_sdp_hdr_overlay* rec) {
+  bool status = true;
+  uint32_t sdp_handle = 0;
+  uint8_t* p = (uint8_t*)rec->user1_ptr;
+  uint8_t* p_end = p + rec->user1_ptr_len;
+  uint8_t type;
+  uint32_t len;
+
+  /* The attribute list is a single data element sequence */
+  if (p == NULL || rec->user1_ptr_len < 2 ||
+      (*p >> 3) != DATA_ELE_SEQ_DESC_TYPE) {
+    LOG_ERROR("Custom record has no attribute list");
+    return sdp_handle;
+  }
+  type = *p++;
+  p = sdpu_get_len_from_type(p, p_end, type, &len);
+  if (p == NULL || len > (uint32_t)(p_end - p)) {
+    LOG_ERROR("Custom record has a bad attribute list length");
+    return sdp_handle;
+  }
+  p_end = p + len;
+
+  sdp_handle = get_legacy_stack_sdp_api()->handle.SDP_CreateRecord();
+  if (sdp_handle == 0) {
+    LOG_ERROR("Unable to register custom record");
+    return sdp_handle;
+  }
+
+  while (status && p < p_end) {
+    uint16_t attr_id;
+
+    /* Each attribute starts with its ID as a 16-bit unsigned integer */
+    if (p_end - p < 3 || *p != ((UINT_DESC_TYPE << 3) | SIZE_TWO_BYTES)) {
+      status = false;
+      break;
+    }
+    p++;
+    BE_STREAM_TO_UINT16(attr_id, p);
+
+    /* The record handle is assigned by the stack */
+    if (attr_id == ATTR_ID_SERVICE_RECORD_HDL) {
+      LOG_ERROR("Custom record can't set the service record handle");
+      status = false;
+      break;
+    }
+
+    /* Followed by the data element of the value, which is stored without its
+     * header */
+    if (p >= p_end) {
+      status = false;
+      break;
+    }
+    type = *p++;
+    if (type == 0) {
+      /* A nil data element has no value */
+      len = 0;
+    } else {
+      p = sdpu_get_len_from_type(p, p_end, type, &len);
+      if (p == NULL || len > (uint32_t)(p_end - p)) {
+        status = false;
+        break;
+      }
+    }
+
+    status &= get_legacy_stack_sdp_api()->handle.SDP_AddAttribute(
+        sdp_handle, attr_id, (uint8_t)(type >> 3), len, p);
+    p += len;
+  }
+
+  if (!status) {
+    get_legacy_stack_sdp_api()->handle.SDP_DeleteRecord(sdp_handle);
+    sdp_handle = 0;
+    APPL_TRACE_ERROR("%s() FAILED", __func__);
+    return sdp_handle;
+  }
+  APPL_TRACE_DEBUG("%s():  SDP Registered (handle 0x%08x)", __func__,
+                   sdp_handle);
+  return sdp_handle;
+}
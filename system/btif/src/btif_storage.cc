@@ -89,6 +89,8 @@ using bluetooth::Uuid;
 #define BTIF_STORAGE_KEY_GATT_CLIENT_SUPPORTED "GattClientSupportedFeatures"
 #define BTIF_STORAGE_KEY_GATT_CLIENT_DB_HASH "GattClientDatabaseHash"
 #define BTIF_STORAGE_KEY_GATT_SERVER_SUPPORTED "GattServerSupportedFeatures"
+#define BTIF_STORAGE_KEY_GATT_CLIENT_RUST_STATE "GattClientRustState"
+#define BTIF_STORAGE_KEY_CTKD "CrossTransportKeyDerived"
 
 #define BTIF_STORAGE_PATH_VENDOR_ID_SOURCE "VendorIdSource"
 #define BTIF_STORAGE_PATH_VENDOR_ID "VendorId"
@@ -883,6 +885,12 @@ bt_status_t btif_storage_remove_bonded_device(
   if (btif_config_exist(bdstr, BTIF_STORAGE_KEY_GATT_SERVER_SUPPORTED)) {
     ret &= btif_config_remove(bdstr, BTIF_STORAGE_KEY_GATT_SERVER_SUPPORTED);
   }
+  if (btif_config_exist(bdstr, BTIF_STORAGE_KEY_GATT_CLIENT_RUST_STATE)) {
+    ret &= btif_config_remove(bdstr, BTIF_STORAGE_KEY_GATT_CLIENT_RUST_STATE);
+  }
+  if (btif_config_exist(bdstr, BTIF_STORAGE_KEY_CTKD)) {
+    ret &= btif_config_remove(bdstr, BTIF_STORAGE_KEY_CTKD);
+  }
 
   /* Check the length of the paired devices, and if 0 then reset IRK */
   auto paired_devices = btif_config_get_paired_devices();
@@ -894,6 +902,52 @@ bt_status_t btif_storage_remove_bonded_device(
   return ret ? BT_STATUS_SUCCESS : BT_STATUS_FAIL;
 }
 
+/*******************************************************************************
+ *
+ * Function         btif_storage_set_remote_ctkd
+ *
+ * Description      BTIF storage API - Records that keys of the bonded device
+ *                  were derived from the other transport
+ *
+ * Returns          BT_STATUS_SUCCESS if the store was successful,
+ *                  BT_STATUS_FAIL otherwise
+ *
+ ******************************************************************************/
+bt_status_t btif_storage_set_remote_ctkd(const RawAddress* remote_bd_addr) {
+  int ret = btif_config_set_int(remote_bd_addr->ToString(),
+                                BTIF_STORAGE_KEY_CTKD, 1);
+  return ret ? BT_STATUS_SUCCESS : BT_STATUS_FAIL;
+}
+
+/*******************************************************************************
+ *
+ * Function         btif_storage_get_remote_bond_security_info
+ *
+ * Description      BTIF storage API - Fills the stored bond part of the
+ *                  security info of the remote device: the link key type, the
+ *                  LE keys distributed and whether keys were derived. The keys
+ *                  themselves are not read.
+ *
+ ******************************************************************************/
+void btif_storage_get_remote_bond_security_info(
+    const RawAddress* remote_bd_addr, bt_remote_security_info_t* info) {
+  std::string bdstr = remote_bd_addr->ToString();
+  int val;
+
+  info->link_key_type = 0xFF;
+  if (btif_config_exist(bdstr, "LinkKey") &&
+      btif_config_get_int(bdstr, "LinkKeyType", &val)) {
+    info->link_key_type = (uint8_t)val;
+  }
+
+  info->le_ltk_present = btif_config_exist(bdstr, "LE_KEY_PENC");
+  info->le_irk_present = btif_config_exist(bdstr, "LE_KEY_PID");
+  info->le_csrk_present = btif_config_exist(bdstr, "LE_KEY_PCSRK");
+
+  info->is_ctkd =
+      btif_config_get_int(bdstr, BTIF_STORAGE_KEY_CTKD, &val) && val != 0;
+}
+
 /* Some devices hardcode sample LTK value from spec, instead of generating one.
  * Treat such devices as insecure, and remove such bonds when bluetooth
  * restarts. Removing them after disconnection is handled separately.
@@ -1621,6 +1675,53 @@ void btif_storage_remove_gatt_cl_db_hash(const RawAddress& bd_addr) {
                        bd_addr));
 }
 
+/** Store the state of a remote client of the Rust GATT server */
+void btif_storage_set_gatt_cl_rust_state(const RawAddress& bd_addr,
+                                         std::vector<uint8_t> state) {
+  do_in_jni_thread(
+      FROM_HERE, Bind(
+                     [](const RawAddress& bd_addr, std::vector<uint8_t> state) {
+                       auto bdstr = bd_addr.ToString();
+                       btif_config_set_bin(
+                           bdstr, BTIF_STORAGE_KEY_GATT_CLIENT_RUST_STATE,
+                           state.data(), state.size());
+                     },
+                     bd_addr, std::move(state)));
+}
+
+/** Remove the state of a remote client of the Rust GATT server */
+void btif_storage_remove_gatt_cl_rust_state(const RawAddress& bd_addr) {
+  do_in_jni_thread(FROM_HERE,
+                   Bind(
+                       [](const RawAddress& bd_addr) {
+                         auto bdstr = bd_addr.ToString();
+                         if (btif_config_exist(
+                                 bdstr,
+                                 BTIF_STORAGE_KEY_GATT_CLIENT_RUST_STATE)) {
+                           btif_config_remove(
+                               bdstr, BTIF_STORAGE_KEY_GATT_CLIENT_RUST_STATE);
+                         }
+                       },
+                       bd_addr));
+}
+
+/** Get the state of a remote client of the Rust GATT server (empty if none) */
+std::vector<uint8_t> btif_storage_get_gatt_cl_rust_state(
+    const RawAddress& bd_addr) {
+  auto bdstr = bd_addr.ToString();
+
+  size_t size =
+      btif_config_get_bin_length(bdstr, BTIF_STORAGE_KEY_GATT_CLIENT_RUST_STATE);
+  std::vector<uint8_t> state(size);
+  if (size == 0 ||
+      !btif_config_get_bin(bdstr, BTIF_STORAGE_KEY_GATT_CLIENT_RUST_STATE,
+                           state.data(), &size)) {
+    return {};
+  }
+
+  return state;
+}
+
 void btif_debug_linkkey_type_dump(int fd) {
   dprintf(fd, "\nLink Key Types:\n");
   for (const auto& bd_addr : btif_config_get_paired_devices()) {
@@ -139,6 +139,8 @@ bt_callbacks_t bt_callbacks = {
     .switch_buffer_size_cb = nullptr,       // switch_buffer_size_callback
     .switch_codec_cb = nullptr,             // switch_codec_callback
     .le_rand_cb = nullptr,                  // le_rand_callback
+    .link_quality_report_with_address_cb =
+        nullptr,  // link_quality_report_with_address_callback
 };
 
 bthh_callbacks_t bthh_callbacks = {
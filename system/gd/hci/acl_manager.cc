@@ -264,6 +264,14 @@ void AclManager::CreateLeConnection(AddressWithType address_with_type, bool is_d
   CallOn(pimpl_->le_impl_, &le_impl::create_le_connection, address_with_type, true, is_direct);
 }
 
+void AclManager::CreateLeConnection(
+    AddressWithType address_with_type,
+    bool is_direct,
+    std::optional<acl_manager::LeConnectionParameters> parameters) {
+  CallOn(pimpl_->le_impl_, &le_impl::set_connection_parameters, address_with_type, is_direct, parameters);
+  CreateLeConnection(address_with_type, is_direct);
+}
+
 void AclManager::IsOnBackgroundList(AddressWithType address_with_type, std::promise<bool> promise) {
   CallOn(pimpl_->le_impl_, &le_impl::is_on_background_connection_list, address_with_type, std::move(promise));
 }
@@ -19,11 +19,13 @@
 #include <functional>
 #include <future>
 #include <memory>
+#include <optional>
 
 #include "common/bidi_queue.h"
 #include "common/callback.h"
 #include "hci/acl_manager/connection_callbacks.h"
 #include "hci/acl_manager/le_acceptlist_callbacks.h"
+#include "hci/acl_manager/le_connection_parameters.h"
 #include "hci/acl_manager/le_connection_callbacks.h"
 #include "hci/address.h"
 #include "hci/address_with_type.h"
@@ -89,6 +91,13 @@ public:
  // Generates OnLeConnectSuccess if connected, or OnLeConnectFail otherwise
  virtual void CreateLeConnection(AddressWithType address_with_type, bool is_direct);
 
+ // As above, but scanning and connecting with the given parameters (if any) instead of the system
+ // defaults, for as long as this direct or background connection is pending
+ void CreateLeConnection(
+     AddressWithType address_with_type,
+     bool is_direct,
+     std::optional<acl_manager::LeConnectionParameters> parameters);
+
  // Ask the controller for specific data parameters
  virtual void SetLeSuggestedDefaultDataParameters(uint16_t octets, uint16_t time);
 
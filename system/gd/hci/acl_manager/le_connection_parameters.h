@@ -0,0 +1,54 @@
+/*
+ * Copyright 2023 The Android Open Source Project
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#pragma once
+
+#include <algorithm>
+#include <cstdint>
+
+namespace bluetooth {
+namespace hci {
+namespace acl_manager {
+
+/// Scan and connection parameters requested for a single LE connection attempt
+struct LeConnectionParameters {
+  uint16_t scan_interval;
+  uint16_t scan_window;
+  uint16_t conn_interval_min;
+  uint16_t conn_interval_max;
+  uint16_t conn_latency;
+  uint16_t supervision_timeout;
+  /// Bitmask of initiating PHYs (as in LE Extended Create Connection)
+  uint8_t initiating_phys;
+
+  /// Combine the parameters of two attempts sharing one create connection, so
+  /// that the most demanding requirements of either are satisfied
+  LeConnectionParameters Merge(const LeConnectionParameters& other) const {
+    LeConnectionParameters merged;
+    merged.scan_interval = std::min(scan_interval, other.scan_interval);
+    merged.scan_window = std::min(std::max(scan_window, other.scan_window), merged.scan_interval);
+    merged.conn_interval_min = std::min(conn_interval_min, other.conn_interval_min);
+    merged.conn_interval_max = std::min(conn_interval_max, other.conn_interval_max);
+    merged.conn_latency = std::min(conn_latency, other.conn_latency);
+    merged.supervision_timeout = std::min(supervision_timeout, other.supervision_timeout);
+    merged.initiating_phys = initiating_phys | other.initiating_phys;
+    return merged;
+  }
+};
+
+}  // namespace acl_manager
+}  // namespace hci
+}  // namespace bluetooth
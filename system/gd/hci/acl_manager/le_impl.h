@@ -24,6 +24,7 @@
 #include <memory>
 #include <optional>
 #include <string>
+#include <unordered_map>
 #include <unordered_set>
 
 #include "common/bind.h"
@@ -31,6 +32,7 @@
 #include "crypto_toolbox/crypto_toolbox.h"
 #include "hci/acl_manager/assembler.h"
 #include "hci/acl_manager/le_acceptlist_callbacks.h"
+#include "hci/acl_manager/le_connection_parameters.h"
 #include "hci/acl_manager/le_connection_management_callbacks.h"
 #include "hci/acl_manager/round_robin_scheduler.h"
 #include "hci/controller.h"
@@ -823,6 +825,7 @@ struct le_impl : public bluetooth::hci::LeAddressManagerCallback {
     connect_list.erase(address_with_type);
     connecting_le_.erase(address_with_type);
     direct_connections_.erase(address_with_type);
+    direct_connection_parameters_.erase(address_with_type);
     register_with_address_manager();
     le_address_manager_->RemoveDeviceFromFilterAcceptList(
         address_with_type.ToFilterAcceptListAddressType(), address_with_type.GetAddress());
@@ -935,6 +938,44 @@ struct le_impl : public bluetooth::hci::LeAddressManagerCallback {
     uint16_t conn_latency = os::GetSystemPropertyUint32(kPropertyConnLatency, kConnLatency);
     uint16_t supervision_timeout = os::GetSystemPropertyUint32(kPropertyConnSupervisionTimeout, kSupervisionTimeout);
     ASSERT(check_connection_parameters(conn_interval_min, conn_interval_max, conn_latency, supervision_timeout));
+    uint8_t allowed_phys = PHY_LE_1M | PHY_LE_2M | PHY_LE_CODED;
+
+    // Attempts made with explicit parameters replace the defaults, merged across all of them. If
+    // some devices are connected to with the defaults, those are merged in as well.
+    std::optional<LeConnectionParameters> requested;
+    bool uses_defaults = false;
+    for (const auto& address_with_type : connecting_le_) {
+      auto parameters = get_requested_connection_parameters(address_with_type);
+      if (!parameters.has_value()) {
+        uses_defaults = true;
+        continue;
+      }
+      requested = requested.has_value() ? requested->Merge(*parameters) : *parameters;
+    }
+    if (requested.has_value() && uses_defaults) {
+      requested = requested->Merge(LeConnectionParameters{
+          .scan_interval = le_scan_interval,
+          .scan_window = le_scan_window,
+          .conn_interval_min = conn_interval_min,
+          .conn_interval_max = conn_interval_max,
+          .conn_latency = conn_latency,
+          .supervision_timeout = supervision_timeout,
+          .initiating_phys = allowed_phys,
+      });
+    }
+    if (requested.has_value()) {
+      if (!system_suspend_) {
+        le_scan_interval = requested->scan_interval;
+        le_scan_window = requested->scan_window;
+        le_scan_window_2m = le_scan_window;
+        le_scan_window_coded = le_scan_window;
+      }
+      conn_interval_min = requested->conn_interval_min;
+      conn_interval_max = requested->conn_interval_max;
+      conn_latency = requested->conn_latency;
+      supervision_timeout = requested->supervision_timeout;
+      allowed_phys = requested->initiating_phys;
+    }
 
     AddressWithType address_with_type = connection_peer_address_with_type_;
     if (initiator_filter_policy == InitiatorFilterPolicy::USE_FILTER_ACCEPT_LIST) {
@@ -957,7 +998,7 @@ struct le_impl : public bluetooth::hci::LeAddressManagerCallback {
       scan_parameters.max_ce_length_ = 0x00;
       parameters.push_back(scan_parameters);
 
-      if (controller_->SupportsBle2mPhy() && !only_init_1m_phy) {
+      if (controller_->SupportsBle2mPhy() && !only_init_1m_phy && (allowed_phys & PHY_LE_2M)) {
         LeCreateConnPhyScanParameters scan_parameters_2m;
         scan_parameters_2m.scan_interval_ = le_scan_interval;
         scan_parameters_2m.scan_window_ = le_scan_window_2m;
@@ -970,7 +1011,7 @@ struct le_impl : public bluetooth::hci::LeAddressManagerCallback {
         parameters.push_back(scan_parameters_2m);
         initiating_phys |= PHY_LE_2M;
       }
-      if (controller_->SupportsBleCodedPhy() && !only_init_1m_phy) {
+      if (controller_->SupportsBleCodedPhy() && !only_init_1m_phy && (allowed_phys & PHY_LE_CODED)) {
         LeCreateConnPhyScanParameters scan_parameters_coded;
         scan_parameters_coded.scan_interval_ = le_scan_interval;
         scan_parameters_coded.scan_window_ = le_scan_window_coded;
@@ -1131,6 +1172,7 @@ struct le_impl : public bluetooth::hci::LeAddressManagerCallback {
 
       if (background_connections_.find(address_with_type) != background_connections_.end()) {
         direct_connections_.erase(address_with_type);
+        direct_connection_parameters_.erase(address_with_type);
         disarm_connectability();
       } else {
         cancel_connect(address_with_type);
@@ -1251,12 +1293,59 @@ struct le_impl : public bluetooth::hci::LeAddressManagerCallback {
     return true;
   }
 
+  // Set the parameters of the direct or background connection to this device, or go back to the
+  // system defaults if none are given. They are kept until that connection attempt ends.
+  void set_connection_parameters(
+      AddressWithType address_with_type, bool is_direct, std::optional<LeConnectionParameters> parameters) {
+    auto& connection_parameters = is_direct ? direct_connection_parameters_ : background_connection_parameters_;
+    if (!parameters.has_value()) {
+      connection_parameters.erase(address_with_type);
+      return;
+    }
+    if (parameters->scan_window > parameters->scan_interval ||
+        !check_connection_parameters(
+            parameters->conn_interval_min,
+            parameters->conn_interval_max,
+            parameters->conn_latency,
+            parameters->supervision_timeout)) {
+      LOG_WARN(
+          "Ignoring invalid connection parameters for %s", ADDRESS_TO_LOGGABLE_CSTR(address_with_type));
+      connection_parameters.erase(address_with_type);
+      return;
+    }
+    connection_parameters.insert_or_assign(address_with_type, *parameters);
+  }
+
+  // The parameters requested to connect to this device (merged across its direct and background
+  // connections), or none if any of these uses the system defaults
+  std::optional<LeConnectionParameters> get_requested_connection_parameters(AddressWithType address_with_type) {
+    auto direct = direct_connection_parameters_.find(address_with_type);
+    auto background = background_connection_parameters_.find(address_with_type);
+    bool has_direct = direct != direct_connection_parameters_.end();
+    bool has_background = background != background_connection_parameters_.end();
+    if ((direct_connections_.count(address_with_type) != 0 && !has_direct) ||
+        (background_connections_.count(address_with_type) != 0 && !has_background)) {
+      return std::nullopt;
+    }
+    if (has_direct && has_background) {
+      return direct->second.Merge(background->second);
+    }
+    if (has_direct) {
+      return direct->second;
+    }
+    if (has_background) {
+      return background->second;
+    }
+    return std::nullopt;
+  }
+
   void add_device_to_background_connection_list(AddressWithType address_with_type) {
     background_connections_.insert(address_with_type);
   }
 
   void remove_device_from_background_connection_list(AddressWithType address_with_type) {
     background_connections_.erase(address_with_type);
+    background_connection_parameters_.erase(address_with_type);
   }
 
   void is_on_background_connection_list(AddressWithType address_with_type, std::promise<bool> promise) {
@@ -1344,6 +1433,10 @@ struct le_impl : public bluetooth::hci::LeAddressManagerCallback {
   std::unordered_set<AddressWithType> connecting_le_{};
   bool arm_on_resume_{};
   std::unordered_set<AddressWithType> direct_connections_{};
+  // Parameters requested for a direct connection, instead of the system defaults
+  std::unordered_map<AddressWithType, LeConnectionParameters> direct_connection_parameters_{};
+  // Parameters requested for a background connection, kept across reconnections
+  std::unordered_map<AddressWithType, LeConnectionParameters> background_connection_parameters_{};
   // Set of devices that will not be removed from connect list after direct connect timeout
   std::unordered_set<AddressWithType> background_connections_;
   std::unordered_set<AddressWithType> connect_list;
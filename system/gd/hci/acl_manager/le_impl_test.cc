@@ -787,6 +787,76 @@ TEST_F(LeImplTest, connection_complete_with_central_role) {
   ASSERT_EQ(ConnectabilityState::DISARMED, le_impl_->connectability_state_);
 }
 
+TEST_F(LeImplTest, create_le_connection_with_connection_parameters) {
+  set_random_device_address_policy();
+
+  hci::Address remote_address;
+  Address::FromString("D0:05:04:03:02:01", remote_address);
+  hci::AddressWithType address_with_type(remote_address, hci::AddressType::PUBLIC_DEVICE_ADDRESS);
+  le_impl_->set_connection_parameters(
+      address_with_type,
+      true,
+      LeConnectionParameters{
+          .scan_interval = 0x0010,
+          .scan_window = 0x0010,
+          .conn_interval_min = 0x0006,
+          .conn_interval_max = 0x0008,
+          .conn_latency = 0x0000,
+          .supervision_timeout = 0x0064,
+          .initiating_phys = PHY_LE_1M,
+      });
+
+  // Create connection
+  ASSERT_NO_FATAL_FAILURE(hci_layer_->SetCommandFuture());
+  le_impl_->create_le_connection(address_with_type, true, false);
+  hci_layer_->GetCommand(OpCode::LE_ADD_DEVICE_TO_FILTER_ACCEPT_LIST);
+  ASSERT_NO_FATAL_FAILURE(hci_layer_->SetCommandFuture());
+  hci_layer_->CommandCompleteCallback(LeAddDeviceToFilterAcceptListCompleteBuilder::Create(0x01, ErrorCode::SUCCESS));
+  auto command = LeCreateConnectionView::Create(LeConnectionManagementCommandView::Create(
+      AclCommandView::Create(hci_layer_->GetCommand(OpCode::LE_CREATE_CONNECTION))));
+  ASSERT_TRUE(command.IsValid());
+  ASSERT_EQ(0x0010, command.GetLeScanInterval());
+  ASSERT_EQ(0x0010, command.GetLeScanWindow());
+  ASSERT_EQ(0x0006, command.GetConnIntervalMin());
+  ASSERT_EQ(0x0008, command.GetConnIntervalMax());
+  ASSERT_EQ(0x0000, command.GetConnLatency());
+  ASSERT_EQ(0x0064, command.GetSupervisionTimeout());
+
+  // Parameters are forgotten once the device leaves the connect list
+  le_impl_->remove_device_from_connect_list(address_with_type);
+  ASSERT_TRUE(le_impl_->direct_connection_parameters_.empty());
+}
+
+TEST_F(LeImplTest, background_connection_parameters_kept_until_background_connection_removed) {
+  hci::Address remote_address;
+  Address::FromString("D0:05:04:03:02:01", remote_address);
+  hci::AddressWithType address_with_type(remote_address, hci::AddressType::PUBLIC_DEVICE_ADDRESS);
+  auto parameters = LeConnectionParameters{
+      .scan_interval = 0x0800,
+      .scan_window = 0x0030,
+      .conn_interval_min = 0x0006,
+      .conn_interval_max = 0x0008,
+      .conn_latency = 0x0000,
+      .supervision_timeout = 0x0064,
+      .initiating_phys = PHY_LE_1M,
+  };
+  le_impl_->add_device_to_background_connection_list(address_with_type);
+  le_impl_->set_connection_parameters(address_with_type, false, parameters);
+  le_impl_->add_device_to_connect_list(address_with_type);
+
+  // The device leaves the connect list when it connects, but will be re-added on disconnection
+  le_impl_->remove_device_from_connect_list(address_with_type);
+  ASSERT_TRUE(le_impl_->get_requested_connection_parameters(address_with_type).has_value());
+
+  // A direct connection with the system defaults overrides the background parameters
+  le_impl_->direct_connections_.insert(address_with_type);
+  ASSERT_FALSE(le_impl_->get_requested_connection_parameters(address_with_type).has_value());
+  le_impl_->direct_connections_.erase(address_with_type);
+
+  le_impl_->remove_device_from_background_connection_list(address_with_type);
+  ASSERT_FALSE(le_impl_->get_requested_connection_parameters(address_with_type).has_value());
+}
+
 TEST_F(LeImplTest, enhanced_connection_complete_with_central_role) {
   set_random_device_address_policy();
 
@@ -374,6 +374,7 @@ init_flags!(
         finite_att_timeout = true,
         gatt_robust_caching_client = true,
         gatt_robust_caching_server,
+        gatt_server_transaction_timeout_ms: i32 = 15000,
         gd_core,
         gd_hal_snoop_logger_socket = true,
         gd_hal_snoop_logger_filtering = true,
@@ -389,10 +390,15 @@ init_flags!(
         pbap_pse_dynamic_version_upgrade = false,
         periodic_advertising_adi = true,
         private_gatt = true,
+        private_gatt_client,
+        private_gatt_shared_gap_service,
         queue_l2cap_coc_while_encrypting = true,
         read_encryption_key_size = true,
         redact_log = true,
         rust_event_loop = true,
+        rust_event_loop_abort_on_stall,
+        rust_event_loop_queue_depth_threshold: i32 = 0,
+        rust_event_loop_stall_threshold_ms: i32 = 0,
         sco_codec_select_lc3,
         sco_codec_timeout_clear,
         sdp_serialization = true,
@@ -423,6 +429,8 @@ init_flags!(
     }
     dependencies: {
         always_use_private_gatt_for_debugging => private_gatt,
+        private_gatt_client => private_gatt,
+        private_gatt_shared_gap_service => private_gatt,
         private_gatt => rust_event_loop
     }
 );
@@ -8,11 +8,15 @@ use crate::dbus_iface::{
 };
 use crate::ClientContext;
 use crate::{console_red, console_yellow, print_error, print_info};
-use bt_topshim::btif::{BtBondState, BtPropertyType, BtSspVariant, BtStatus, Uuid128Bit};
+use bt_topshim::btif::{
+    BtActivityEnergyInfo, BtBondState, BtOobData, BtPropertyType, BtSspVariant, BtStatus,
+    BtTransport, Uuid128Bit,
+};
 use bt_topshim::profiles::gatt::{AdvertisingStatus, GattStatus, LePhy};
 use bt_topshim::profiles::sdp::BtSdpRecord;
 use btstack::bluetooth::{
     BluetoothDevice, IBluetooth, IBluetoothCallback, IBluetoothConnectionCallback,
+    LinkQualityReport,
 };
 use btstack::bluetooth_admin::{IBluetoothAdminPolicyCallback, PolicyEffect};
 use btstack::bluetooth_adv::IAdvertisingSetCallback;
@@ -267,8 +271,23 @@ impl IBluetoothCallback for BtCallback {
             UuidWrapper(&searched_uuid),
             sdp_records.len()
         );
-        if !sdp_records.is_empty() {
-            print_info!("{:?}", sdp_records);
+        for record in &sdp_records {
+            let attributes = match record {
+                BtSdpRecord::HeaderOverlay(hdr) => hdr.attributes(),
+                _ => None,
+            };
+            match attributes {
+                Some(attributes) => {
+                    print_info!("Record with {} attributes:", attributes.len());
+                    for attribute in &attributes {
+                        match attribute.decode_value() {
+                            Some(value) => print_info!("  0x{:04x}: {:?}", attribute.id, value),
+                            None => print_info!("  0x{:04x}: (malformed)", attribute.id),
+                        }
+                    }
+                }
+                None => print_info!("{:?}", record),
+            }
         }
     }
 
@@ -278,6 +297,66 @@ impl IBluetoothCallback for BtCallback {
             self.context.lock().unwrap().mps_sdp_handle = Some(handle);
         }
     }
+
+    fn on_local_oob_data_generated(&mut self, transport: BtTransport, data: BtOobData) {
+        if !data.is_valid {
+            print_error!("Failed to generate local OOB data for transport {:?}", transport);
+            return;
+        }
+        print_info!(
+            "Local OOB data for transport {:?}: c={} r={}",
+            transport,
+            hex::encode(&data.c),
+            hex::encode(&data.r)
+        );
+    }
+
+    fn on_energy_info(&mut self, info: BtActivityEnergyInfo) {
+        print_info!(
+            "Energy info: status={} ctrl_state={} tx={}ms rx={}ms idle={}ms energy_used={}",
+            info.status,
+            info.ctrl_state,
+            info.tx_time_ms,
+            info.rx_time_ms,
+            info.idle_time_ms,
+            info.energy_used
+        );
+    }
+
+    fn on_link_quality_report(
+        &mut self,
+        remote_device: BluetoothDevice,
+        report: LinkQualityReport,
+    ) {
+        print_info!(
+            "Link quality of {} ({}): report_id={} rssi={} snr={} retransmissions={} \
+             packets_not_received={} nacks={}",
+            remote_device.address,
+            remote_device.name,
+            report.report_id,
+            report.rssi,
+            report.snr,
+            report.retransmission_count,
+            report.packets_not_receive_count,
+            report.negative_acknowledgement_count
+        );
+    }
+
+    fn on_local_irk_regenerated(
+        &mut self,
+        status: BtStatus,
+        blocking_devices: Vec<BluetoothDevice>,
+    ) {
+        if status == BtStatus::Success {
+            print_info!("Local IRK regenerated");
+            return;
+        }
+
+        print_error!("Failed to regenerate the local IRK: {:?}", status);
+        for device in blocking_devices {
+            print_error!("  Blocked by bonded device {} ({})", device.address, device.name);
+        }
+    }
 }
 
 impl RPCProxy for BtCallback {
@@ -392,6 +471,12 @@ impl IScannerCallback for ScannerCallback {
         }
     }
 
+    fn on_batch_scan_results(&mut self, scanner_id: u8, scan_results: Vec<ScanResult>) {
+        if self.context.lock().unwrap().active_scanner_ids.len() > 0 {
+            print_info!("Batch scan results for scanner_id {} : {:#?}", scanner_id, scan_results);
+        }
+    }
+
     fn on_suspend_mode_change(&mut self, suspend_mode: SuspendMode) {
         if self.context.lock().unwrap().active_scanner_ids.len() > 0 {
             print_info!("Scan suspend mode change: {:#?}", suspend_mode);
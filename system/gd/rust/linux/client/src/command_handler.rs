@@ -9,14 +9,21 @@ use crate::bt_gatt::AuthReq;
 use crate::callbacks::{BtGattCallback, BtGattServerCallback};
 use crate::ClientContext;
 use crate::{console_red, console_yellow, print_error, print_info};
-use bt_topshim::btif::{BtConnectionState, BtDiscMode, BtStatus, BtTransport};
+use bt_topshim::btif::{
+    BtConnectionState, BtDiscMode, BtOobData, BtStatus, BtTransport, RawAddress,
+};
 use bt_topshim::profiles::hid_host::BthhReportType;
 use bt_topshim::profiles::sdp::{BtSdpMpsRecord, BtSdpRecord};
 use bt_topshim::profiles::{gatt::LePhy, ProfileConnectionState};
-use btstack::bluetooth::{BluetoothDevice, IBluetooth, IBluetoothQALegacy};
-use btstack::bluetooth_gatt::{GattWriteType, IBluetoothGatt, ScanSettings, ScanType};
+use btstack::bluetooth::{BluetoothDevice, DiscoveryFilter, IBluetooth, IBluetoothQALegacy};
+use btstack::bluetooth_gatt::{
+    BatchScanMode, GattWriteType, IBluetoothGatt, ScanSettings, ScanType,
+};
 use btstack::bluetooth_media::IBluetoothTelephony;
 use btstack::bluetooth_qa::IBluetoothQA;
+use btstack::connection_policy::{ConnectionPolicy, ProfileConnectionPolicy};
+use btstack::le_privacy::LePrivacyConfig;
+use btstack::scan_params::ScanParameterProfile;
 use btstack::socket_manager::{IBluetoothSocketManager, SocketResult};
 use btstack::uuid::{Profile, UuidHelper, UuidWrapper};
 use manager_service::iface_bluetooth_manager::IBluetoothManager;
@@ -129,6 +136,14 @@ fn build_commands() -> HashMap<String, CommandOption> {
                 String::from("adapter discoverable <on|limited|off> <duration>"),
                 String::from("adapter connectable <on|off>"),
                 String::from("adapter set-name <name>"),
+                String::from("adapter energy-info"),
+                String::from("adapter link-quality <on|off> [min-interval-ms]"),
+                String::from("adapter privacy show"),
+                String::from(
+                    "adapter privacy set <resolvable|non-resolvable> [rotation-timeout-ms]",
+                ),
+                String::from("adapter privacy regenerate-irk"),
+                String::from("adapter scan-profile [low-latency|balanced|low-power]"),
             ],
             description: String::from(
                 "Enable/Disable/Show default bluetooth adapter. (e.g. adapter enable)\n
@@ -141,7 +156,11 @@ fn build_commands() -> HashMap<String, CommandOption> {
     command_options.insert(
         String::from("bond"),
         CommandOption {
-            rules: vec![String::from("bond <add|remove|cancel> <address>")],
+            rules: vec![
+                String::from("bond <add|remove|cancel> <address>"),
+                String::from("bond add-oob <address> <Bredr|LE> <c> <r> [<Public|Random>]"),
+                String::from("bond generate-oob <Bredr|LE>"),
+            ],
             description: String::from("Creates a bond with a device."),
             function_pointer: CommandHandler::cmd_bond,
         },
@@ -155,6 +174,12 @@ fn build_commands() -> HashMap<String, CommandOption> {
                 String::from("device set-pairing-pin <address> <pin|reject>"),
                 String::from("device set-pairing-passkey <address> <passkey|reject>"),
                 String::from("device set-alias <address> <new-alias>"),
+                String::from("device get-policy <address>"),
+                String::from("device security <address>"),
+                String::from(
+                    "device set-policy <address> <uuid> <Manual|AutoConnect|Forbidden> [priority]",
+                ),
+                String::from("device set-reconnect <address> <max-attempts> <backoff-ms>"),
             ],
             description: String::from("Take action on a remote device. (i.e. info)"),
             function_pointer: CommandHandler::cmd_device,
@@ -163,7 +188,10 @@ fn build_commands() -> HashMap<String, CommandOption> {
     command_options.insert(
         String::from("discovery"),
         CommandOption {
-            rules: vec![String::from("discovery <start|stop>")],
+            rules: vec![
+                String::from("discovery <start|stop>"),
+                String::from("discovery start-filtered <Auto|Bredr|LE> [duration-ms] [min-rssi]"),
+            ],
             description: String::from("Start and stop device discovery. (e.g. discovery start)"),
             function_pointer: CommandHandler::cmd_discovery,
         },
@@ -212,8 +240,9 @@ fn build_commands() -> HashMap<String, CommandOption> {
             rules: vec![
                 String::from("le-scan register-scanner"),
                 String::from("le-scan unregister-scanner <scanner-id>"),
-                String::from("le-scan start-scan <scanner-id>"),
+                String::from("le-scan start-scan <scanner-id> [report-delay-ms]"),
                 String::from("le-scan stop-scan <scanner-id>"),
+                String::from("le-scan flush-batch-results <scanner-id>"),
             ],
             description: String::from("LE scanning utilities."),
             function_pointer: CommandHandler::cmd_le_scan,
@@ -297,9 +326,13 @@ fn build_commands() -> HashMap<String, CommandOption> {
     command_options.insert(
         String::from("list"),
         CommandOption {
-            rules: vec![String::from("list <bonded|found|connected>")],
+            rules: vec![
+                String::from("list <bonded|found|connected|recent>"),
+                String::from("list recent clear"),
+            ],
             description: String::from(
-                "List bonded or found remote devices. Use: list <bonded|found>",
+                "List bonded, found, connected or recently seen remote devices, or forget the \
+                recently seen devices.",
             ),
             function_pointer: CommandHandler::cmd_list_devices,
         },
@@ -600,6 +633,154 @@ impl CommandHandler {
                     println!("usage: adapter set-name <name>");
                 }
             }
+            "energy-info" => {
+                if !self.lock_context().adapter_ready {
+                    return Err(self.adapter_not_ready());
+                }
+
+                if !self.lock_context().adapter_dbus.as_ref().unwrap().request_energy_info() {
+                    return Err("Failed to request energy info".into());
+                }
+            }
+            "link-quality" => {
+                if !self.lock_context().adapter_ready {
+                    return Err(self.adapter_not_ready());
+                }
+
+                let enable = match &get_arg(args, 1)?[..] {
+                    "on" => true,
+                    "off" => false,
+                    other => return Err(format!("Invalid argument '{}'", other).into()),
+                };
+                let min_report_interval_ms = match args.get(2) {
+                    Some(interval) => {
+                        interval.parse::<u16>().or(Err("Failed parsing min-interval-ms"))?
+                    }
+                    None => 0,
+                };
+
+                let ret = self
+                    .lock_context()
+                    .adapter_dbus
+                    .as_ref()
+                    .unwrap()
+                    .set_link_quality_report(enable, min_report_interval_ms);
+                print_info!(
+                    "Set link quality report {} {}",
+                    if enable { "on" } else { "off" },
+                    if ret { "succeeded" } else { "failed" }
+                );
+            }
+            "privacy" => {
+                if !self.lock_context().adapter_ready {
+                    return Err(self.adapter_not_ready());
+                }
+
+                match &get_arg(args, 1)?[..] {
+                    "show" => {
+                        let (config, identity_address, irk) = {
+                            let ctx = self.lock_context();
+                            let adapter_dbus = ctx.adapter_dbus.as_ref().unwrap();
+                            (
+                                adapter_dbus.get_le_privacy_config(),
+                                adapter_dbus.get_le_identity_address(),
+                                adapter_dbus.get_local_irk(),
+                            )
+                        };
+                        print_info!("Identity address: {}", identity_address);
+                        print_info!("Local IRK: {}", hex::encode(irk));
+                        print_info!(
+                            "Address type: {}",
+                            if config.use_non_resolvable_address {
+                                "non-resolvable"
+                            } else {
+                                "resolvable"
+                            }
+                        );
+                        print_info!(
+                            "Rotation timeout: {}",
+                            match config.rpa_rotation_timeout_ms {
+                                0 => String::from("default"),
+                                ms => format!("{} ms", ms),
+                            }
+                        );
+                    }
+                    "set" => {
+                        let use_non_resolvable_address = match &get_arg(args, 2)?[..] {
+                            "resolvable" => false,
+                            "non-resolvable" => true,
+                            other => return Err(format!("Invalid argument '{}'", other).into()),
+                        };
+                        let rpa_rotation_timeout_ms = match args.get(3) {
+                            Some(timeout) => timeout
+                                .parse::<u32>()
+                                .or(Err("Failed parsing rotation-timeout-ms"))?,
+                            None => 0,
+                        };
+
+                        let ret = self
+                            .lock_context()
+                            .adapter_dbus
+                            .as_mut()
+                            .unwrap()
+                            .set_le_privacy_config(LePrivacyConfig {
+                                use_non_resolvable_address,
+                                rpa_rotation_timeout_ms,
+                            });
+                        if !ret {
+                            return Err("Failed to set the LE privacy configuration".into());
+                        }
+                        print_info!(
+                            "The address type applies the next time the adapter is enabled"
+                        );
+                    }
+                    "regenerate-irk" => {
+                        let ret = self
+                            .lock_context()
+                            .adapter_dbus
+                            .as_mut()
+                            .unwrap()
+                            .regenerate_local_irk();
+                        print_info!(
+                            "Regenerating the local IRK {}",
+                            if ret { "started" } else { "rejected" }
+                        );
+                    }
+                    _ => return Err(CommandError::InvalidArgs),
+                }
+            }
+            "scan-profile" => {
+                if !self.lock_context().adapter_ready {
+                    return Err(self.adapter_not_ready());
+                }
+
+                match args.get(1) {
+                    None => {
+                        let profile = self
+                            .lock_context()
+                            .adapter_dbus
+                            .as_ref()
+                            .unwrap()
+                            .get_scan_parameter_profile();
+                        print_info!("Scan parameter profile: {}", profile.name());
+                    }
+                    Some(name) => {
+                        let profile = ScanParameterProfile::from_name(name)
+                            .ok_or(format!("Invalid argument '{}'", name))?;
+                        let ret = self
+                            .lock_context()
+                            .adapter_dbus
+                            .as_mut()
+                            .unwrap()
+                            .set_scan_parameter_profile(profile);
+                        print_info!(
+                            "Set scan parameter profile {} {}",
+                            name,
+                            if ret { "succeeded" } else { "failed" }
+                        );
+                    }
+                }
+            }
 
             _ => return Err(CommandError::InvalidArgs),
         };
@@ -628,6 +809,38 @@ impl CommandHandler {
             "start" => {
                 self.lock_context().adapter_dbus.as_mut().unwrap().start_discovery();
             }
+            "start-filtered" => {
+                let transport = match &get_arg(args, 1)?[..] {
+                    "Auto" => BtTransport::Auto,
+                    "Bredr" => BtTransport::Bredr,
+                    "LE" => BtTransport::Le,
+                    _ => {
+                        return Err("Failed to parse transport".into());
+                    }
+                };
+                let duration_ms = match args.get(2) {
+                    Some(duration) => {
+                        duration.parse::<u32>().or(Err("Failed parsing duration-ms"))?
+                    }
+                    None => 0,
+                };
+                let rssi_threshold = match args.get(3) {
+                    Some(rssi) => Some(rssi.parse::<i8>().or(Err("Failed parsing min-rssi"))?),
+                    None => None,
+                };
+
+                let filter = DiscoveryFilter {
+                    transport,
+                    duration_ms,
+                    rssi_threshold,
+                    ..Default::default()
+                };
+                self.lock_context()
+                    .adapter_dbus
+                    .as_mut()
+                    .unwrap()
+                    .start_discovery_with_filter(filter);
+            }
             "stop" => {
                 self.lock_context().adapter_dbus.as_mut().unwrap().cancel_discovery();
             }
@@ -688,6 +901,78 @@ impl CommandHandler {
 
                 self.lock_context().adapter_dbus.as_ref().unwrap().cancel_bond_process(device);
             }
+            "add-oob" => {
+                let device = BluetoothDevice {
+                    address: String::from(get_arg(args, 1)?),
+                    name: String::from("Classic Device"),
+                };
+                let transport = match &get_arg(args, 2)?[..] {
+                    "Bredr" => BtTransport::Bredr,
+                    "LE" => BtTransport::Le,
+                    _ => {
+                        return Err("Failed to parse transport".into());
+                    }
+                };
+                let c = hex::decode(get_arg(args, 3)?).or(Err("Failed parsing c"))?;
+                let r = hex::decode(get_arg(args, 4)?).or(Err("Failed parsing r"))?;
+                // The OOB address is the device address followed by its type, which
+                // defaults to random for LE, as with the stack.
+                let address_type: u8 = match args.get(5).map(|arg| &arg[..]) {
+                    Some("Public") => 0,
+                    Some("Random") => 1,
+                    None if transport == BtTransport::Le => 1,
+                    None => 0,
+                    Some(other) => return Err(format!("Invalid address type '{}'", other).into()),
+                };
+                let mut address = RawAddress::from_string(device.address.clone())
+                    .ok_or("Failed parsing address")?
+                    .to_byte_arr()
+                    .to_vec();
+                address.push(address_type);
+
+                let bonding_attempt = &self.lock_context().bonding_attempt.as_ref().cloned();
+
+                if bonding_attempt.is_some() {
+                    return Err(format!(
+                        "Already bonding [{}]. Cancel bonding first.",
+                        bonding_attempt.as_ref().unwrap().address,
+                    )
+                    .into());
+                }
+
+                // Only the P-256 data is provided, as with Secure Connections.
+                let p256_data = BtOobData { is_valid: true, address, c, r, ..Default::default() };
+                let success =
+                    self.lock_context().adapter_dbus.as_mut().unwrap().create_bond_out_of_band(
+                        device.clone(),
+                        transport,
+                        BtOobData::default(),
+                        p256_data,
+                    );
+
+                if success {
+                    self.lock_context().bonding_attempt = Some(device);
+                }
+            }
+            "generate-oob" => {
+                let transport = match &get_arg(args, 1)?[..] {
+                    "Bredr" => BtTransport::Bredr,
+                    "LE" => BtTransport::Le,
+                    _ => {
+                        return Err("Failed to parse transport".into());
+                    }
+                };
+
+                if !self
+                    .lock_context()
+                    .adapter_dbus
+                    .as_ref()
+                    .unwrap()
+                    .generate_local_oob_data(transport)
+                {
+                    return Err("Failed to generate local OOB data".into());
+                }
+            }
             other => {
                 println!("Invalid argument '{}'", other);
             }
@@ -832,6 +1117,129 @@ impl CommandHandler {
                     .unwrap()
                     .set_remote_alias(device.clone(), new_alias.clone());
             }
+            "security" => {
+                let device = BluetoothDevice {
+                    address: String::from(get_arg(args, 1)?),
+                    name: String::from(""),
+                };
+                let info = self
+                    .lock_context()
+                    .adapter_dbus
+                    .as_ref()
+                    .unwrap()
+                    .get_remote_security_info(device.clone());
+
+                print_info!("Security of {}:", device.address);
+                print_info!("  Link Key Type: {:?}", info.link_key_type);
+                print_info!(
+                    "  LE Keys: LTK {}, IRK {}, CSRK {}",
+                    info.le_ltk_present,
+                    info.le_irk_present,
+                    info.le_csrk_present
+                );
+                print_info!("  Cross-Transport Key Derivation: {}", info.is_ctkd);
+                print_info!("  Encryption Key Size: {}", info.encryption_key_size);
+                print_info!("  Security Level: {:?}", info.security_level);
+            }
+            "get-policy" => {
+                let device = BluetoothDevice {
+                    address: String::from(get_arg(args, 1)?),
+                    name: String::from(""),
+                };
+                let policy = self
+                    .lock_context()
+                    .adapter_dbus
+                    .as_ref()
+                    .unwrap()
+                    .get_connection_policy(device.clone());
+
+                print_info!("Connection policy of {}:", device.address);
+                for profile in policy.profiles.iter() {
+                    print_info!(
+                        "  {}: {:?} (priority {})",
+                        UuidHelper::known_uuid_to_string(&profile.profile),
+                        profile.policy,
+                        profile.priority
+                    );
+                }
+                print_info!(
+                    "  Reconnect attempts after link loss: {}, backoff: {} ms",
+                    policy.max_reconnect_attempts,
+                    policy.reconnect_backoff_ms
+                );
+            }
+            "set-policy" => {
+                let device = BluetoothDevice {
+                    address: String::from(get_arg(args, 1)?),
+                    name: String::from(""),
+                };
+                let profile = match UuidHelper::parse_string(get_arg(args, 2)?) {
+                    Some(uu) => uu.uu,
+                    None => return Err(CommandError::Failed("Invalid UUID".into())),
+                };
+                let connection_policy = match &get_arg(args, 3)?[..] {
+                    "Manual" => ConnectionPolicy::Manual,
+                    "AutoConnect" => ConnectionPolicy::AutoConnect,
+                    "Forbidden" => ConnectionPolicy::Forbidden,
+                    other => return Err(format!("Invalid argument '{}'", other).into()),
+                };
+                let priority = match args.get(4) {
+                    Some(priority) => priority.parse::<i32>().or(Err("Failed parsing priority"))?,
+                    None => 0,
+                };
+
+                let mut policy = self
+                    .lock_context()
+                    .adapter_dbus
+                    .as_ref()
+                    .unwrap()
+                    .get_connection_policy(device.clone());
+                policy.profiles.retain(|p| p.profile != profile);
+                policy.profiles.push(ProfileConnectionPolicy {
+                    profile,
+                    policy: connection_policy,
+                    priority,
+                });
+
+                let success = self
+                    .lock_context()
+                    .adapter_dbus
+                    .as_mut()
+                    .unwrap()
+                    .set_connection_policy(device, policy);
+                if !success {
+                    return Err("Failed to set connection policy".into());
+                }
+            }
+            "set-reconnect" => {
+                let device = BluetoothDevice {
+                    address: String::from(get_arg(args, 1)?),
+                    name: String::from(""),
+                };
+                let max_reconnect_attempts =
+                    get_arg(args, 2)?.parse::<u32>().or(Err("Failed parsing max-attempts"))?;
+                let reconnect_backoff_ms =
+                    get_arg(args, 3)?.parse::<u32>().or(Err("Failed parsing backoff-ms"))?;
+
+                let mut policy = self
+                    .lock_context()
+                    .adapter_dbus
+                    .as_ref()
+                    .unwrap()
+                    .get_connection_policy(device.clone());
+                policy.max_reconnect_attempts = max_reconnect_attempts;
+                policy.reconnect_backoff_ms = reconnect_backoff_ms;
+
+                let success = self
+                    .lock_context()
+                    .adapter_dbus
+                    .as_mut()
+                    .unwrap()
+                    .set_connection_policy(device, policy);
+                if !success {
+                    return Err("Failed to set connection policy".into());
+                }
+            }
             "set-pairing-confirmation" => {
                 let device = BluetoothDevice {
                     address: String::from(get_arg(args, 1)?),
@@ -1231,12 +1639,24 @@ impl CommandHandler {
                 let scanner_id = String::from(get_arg(args, 1)?)
                     .parse::<u8>()
                     .or(Err("Failed parsing scanner id"))?;
+                let report_delay_millis = match args.get(2) {
+                    Some(delay) => {
+                        delay.parse::<u32>().or(Err("Failed parsing report-delay-ms"))?
+                    }
+                    None => 0,
+                };
 
                 self.lock_context().gatt_dbus.as_mut().unwrap().start_scan(
                     scanner_id,
                     // TODO(b/254870159): Construct real settings and filters depending on
                     // command line options.
-                    ScanSettings { interval: 0, window: 0, scan_type: ScanType::Active },
+                    ScanSettings {
+                        interval: 0,
+                        window: 0,
+                        scan_type: ScanType::Active,
+                        report_delay_millis,
+                        batch_mode: BatchScanMode::Full,
+                    },
                     Some(btstack::bluetooth_gatt::ScanFilter {
                         rssi_high_threshold: 0,
                         rssi_low_threshold: 0,
@@ -1256,6 +1676,23 @@ impl CommandHandler {
                 self.lock_context().gatt_dbus.as_mut().unwrap().stop_scan(scanner_id);
                 self.lock_context().active_scanner_ids.remove(&scanner_id);
             }
+            "flush-batch-results" => {
+                let scanner_id = String::from(get_arg(args, 1)?)
+                    .parse::<u8>()
+                    .or(Err("Failed parsing scanner id"))?;
+
+                let status = self
+                    .lock_context()
+                    .gatt_dbus
+                    .as_mut()
+                    .unwrap()
+                    .flush_pending_batch_results(scanner_id);
+                if status != BtStatus::Success {
+                    return Err(
+                        format!("Failed to flush batch results, status = {:?}", status).into()
+                    );
+                }
+            }
             _ => return Err(CommandError::InvalidArgs),
         }
 
@@ -1729,6 +2166,25 @@ impl CommandHandler {
                     print_info!("[{:17}] {}", device.address, device.name);
                 }
             }
+            "recent" if args.get(1).map(String::as_str) == Some("clear") => {
+                self.lock_context().adapter_dbus.as_mut().unwrap().clear_recent_devices();
+                print_info!("Cleared recently seen devices");
+            }
+            "recent" => {
+                print_info!("Recently seen devices:");
+                let devices =
+                    self.lock_context().adapter_dbus.as_ref().unwrap().get_recent_devices();
+                for recent in devices.iter() {
+                    print_info!(
+                        "[{:17}] {} (class: {:#x}, appearance: {:#x}, last seen: {})",
+                        recent.device.address,
+                        recent.device.name,
+                        recent.class,
+                        recent.appearance,
+                        recent.last_seen_ms
+                    );
+                }
+            }
             other => {
                 println!("Invalid argument '{}'", other);
             }
@@ -1,22 +1,23 @@
 //! D-Bus proxy implementations of the APIs.
 
 use bt_topshim::btif::{
-    BtBondState, BtConnectionState, BtDeviceType, BtDiscMode, BtPropertyType, BtSspVariant,
-    BtStatus, BtTransport, Uuid, Uuid128Bit,
+    BtActivityEnergyInfo, BtBondState, BtConnectionState, BtDeviceType, BtDiscMode, BtOobData,
+    BtPropertyType, BtSspVariant, BtStatus, BtTransport, Uuid, Uuid128Bit,
 };
 use bt_topshim::profiles::gatt::{AdvertisingStatus, GattStatus, LePhy};
 use bt_topshim::profiles::hid_host::BthhReportType;
 use bt_topshim::profiles::sdp::{
-    BtSdpDipRecord, BtSdpHeaderOverlay, BtSdpMasRecord, BtSdpMnsRecord, BtSdpMpsRecord,
-    BtSdpOpsRecord, BtSdpPceRecord, BtSdpPseRecord, BtSdpRecord, BtSdpSapRecord, BtSdpType,
-    SupportedDependencies, SupportedFormatsList, SupportedScenarios,
+    BtSdpAttribute, BtSdpCustomRecord, BtSdpDipRecord, BtSdpHeaderOverlay, BtSdpMasRecord,
+    BtSdpMnsRecord, BtSdpMpsRecord, BtSdpOpsRecord, BtSdpPceRecord, BtSdpPseRecord, BtSdpRecord,
+    BtSdpSapRecord, BtSdpType, SupportedDependencies, SupportedFormatsList, SupportedScenarios,
 };
 use bt_topshim::profiles::socket::SocketType;
 use bt_topshim::profiles::ProfileConnectionState;
 
 use btstack::bluetooth::{
-    BluetoothDevice, IBluetooth, IBluetoothCallback, IBluetoothConnectionCallback,
-    IBluetoothQALegacy,
+    BluetoothDevice, DiscoveryFilter, GapConfiguration, IBluetooth, IBluetoothCallback,
+    IBluetoothConnectionCallback, IBluetoothQALegacy, LinkKeyType, LinkQualityReport,
+    PreferredConnectionParameters, RemoteSecurityInfo, SecurityLevel,
 };
 use btstack::bluetooth_admin::{IBluetoothAdmin, IBluetoothAdminPolicyCallback, PolicyEffect};
 use btstack::bluetooth_adv::{
@@ -24,13 +25,19 @@ use btstack::bluetooth_adv::{
     PeriodicAdvertisingParameters,
 };
 use btstack::bluetooth_gatt::{
-    BluetoothGattCharacteristic, BluetoothGattDescriptor, BluetoothGattService,
+    BatchScanMode, BluetoothGattCharacteristic, BluetoothGattDescriptor, BluetoothGattService,
     GattWriteRequestStatus, GattWriteType, IBluetoothGatt, IBluetoothGattCallback,
-    IBluetoothGattServerCallback, IScannerCallback, ScanFilter, ScanFilterCondition,
+    IBluetoothGattServerCallback, IScannerCallback, ScanFilter, ScanFilterCondition, ScanFilterIrk,
     ScanFilterPattern, ScanResult, ScanSettings, ScanType,
 };
 use btstack::bluetooth_media::IBluetoothTelephony;
 use btstack::bluetooth_qa::IBluetoothQA;
+use btstack::connection_policy::{
+    ConnectionPolicy, DeviceConnectionPolicy, ProfileConnectionPolicy,
+};
+use btstack::device_cache::RecentDevice;
+use btstack::le_privacy::LePrivacyConfig;
+use btstack::scan_params::ScanParameterProfile;
 use btstack::socket_manager::{
     BluetoothServerSocket, BluetoothSocket, CallbackId, IBluetoothSocketManager,
     IBluetoothSocketManagerCallbacks, SocketId, SocketResult,
@@ -67,6 +74,7 @@ fn make_object_path(idx: i32, name: &str) -> dbus::Path {
 }
 
 impl_dbus_arg_enum!(AdvertisingStatus);
+impl_dbus_arg_enum!(BatchScanMode);
 impl_dbus_arg_enum!(BtBondState);
 impl_dbus_arg_enum!(BtConnectionState);
 impl_dbus_arg_enum!(BtDeviceType);
@@ -195,6 +203,18 @@ pub struct BtSdpMpsRecordDBus {
     supported_dependencies: SupportedDependencies,
 }
 
+#[dbus_propmap(BtSdpAttribute)]
+pub struct BtSdpAttributeDBus {
+    id: u16,
+    value: Vec<u8>,
+}
+
+#[dbus_propmap(BtSdpCustomRecord)]
+pub struct BtSdpCustomRecordDBus {
+    hdr: BtSdpHeaderOverlay,
+    attributes: Vec<BtSdpAttribute>,
+}
+
 fn read_propmap_value<T: 'static + DirectDBus>(
     propmap: &dbus::arg::PropMap,
     key: &str,
@@ -296,6 +316,10 @@ impl DBusArg for BtSdpRecord {
                 let arg_0 = parse_propmap_value::<BtSdpMpsRecord>(&data, "0")?;
                 BtSdpRecord::Mps(arg_0)
             }
+            BtSdpType::Custom => {
+                let arg_0 = parse_propmap_value::<BtSdpCustomRecord>(&data, "0")?;
+                BtSdpRecord::Custom(arg_0)
+            }
         };
         Ok(record)
     }
@@ -335,6 +359,11 @@ impl DBusArg for BtSdpRecord {
             BtSdpRecord::Mps(mps_record) => {
                 write_propmap_value::<BtSdpMpsRecord>(&mut map, mps_record, &String::from("0"))?
             }
+            BtSdpRecord::Custom(custom_record) => write_propmap_value::<BtSdpCustomRecord>(
+                &mut map,
+                custom_record,
+                &String::from("0"),
+            )?,
         }
         Ok(map)
     }
@@ -373,11 +402,104 @@ pub struct BluetoothDeviceDBus {
     name: String,
 }
 
-#[dbus_propmap(ScanSettings)]
-struct ScanSettingsDBus {
-    interval: i32,
-    window: i32,
-    scan_type: ScanType,
+#[dbus_propmap(BtOobData)]
+pub struct BtOobDataDBus {
+    is_valid: bool,
+    address: Vec<u8>,
+    c: Vec<u8>,
+    r: Vec<u8>,
+    device_name: Vec<u8>,
+    oob_data_length: Vec<u8>,
+    class_of_device: Vec<u8>,
+    le_device_role: u8,
+    sm_tk: Vec<u8>,
+    le_flags: u8,
+    le_appearance: Vec<u8>,
+}
+
+#[dbus_propmap(DiscoveryFilter)]
+pub struct DiscoveryFilterDBus {
+    transport: BtTransport,
+    duration_ms: u32,
+    rssi_threshold: Option<i8>,
+    uuids: Vec<Uuid128Bit>,
+    major_device_class: Option<u8>,
+}
+
+#[dbus_propmap(RecentDevice)]
+pub struct RecentDeviceDBus {
+    device: BluetoothDevice,
+    appearance: u16,
+    class: u32,
+    uuids: Vec<Uuid128Bit>,
+    last_seen_ms: u64,
+}
+
+#[dbus_propmap(BtActivityEnergyInfo)]
+pub struct BtActivityEnergyInfoDBus {
+    status: u8,
+    ctrl_state: u8,
+    tx_time_ms: u64,
+    rx_time_ms: u64,
+    idle_time_ms: u64,
+    energy_used: u64,
+}
+
+#[dbus_propmap(LinkQualityReport)]
+pub struct LinkQualityReportDBus {
+    timestamp_ms: u64,
+    report_id: i32,
+    rssi: i32,
+    snr: i32,
+    retransmission_count: i32,
+    packets_not_receive_count: i32,
+    negative_acknowledgement_count: i32,
+}
+
+// Manually converts ScanSettings from/into D-Bus, since clients that don't batch the scan results
+// leave out the batching keys.
+//
+// ScanSettings is represented as a D-Bus dictionary with the keys "interval", "window",
+// "scan_type" and, optionally, "report_delay_millis" and "batch_mode". Without a report delay, the
+// scan results aren't batched.
+impl DBusArg for ScanSettings {
+    type DBusType = dbus::arg::PropMap;
+    fn from_dbus(
+        data: dbus::arg::PropMap,
+        _conn: Option<std::sync::Arc<dbus::nonblock::SyncConnection>>,
+        _remote: Option<dbus::strings::BusName<'static>>,
+        _disconnect_watcher: Option<
+            std::sync::Arc<std::sync::Mutex<dbus_projection::DisconnectWatcher>>,
+        >,
+    ) -> Result<ScanSettings, Box<dyn std::error::Error>> {
+        let report_delay_millis = match data.contains_key("report_delay_millis") {
+            true => parse_propmap_value(&data, "report_delay_millis")?,
+            false => 0,
+        };
+        let batch_mode = match data.contains_key("batch_mode") {
+            true => parse_propmap_value(&data, "batch_mode")?,
+            false => BatchScanMode::default(),
+        };
+        Ok(ScanSettings {
+            interval: parse_propmap_value(&data, "interval")?,
+            window: parse_propmap_value(&data, "window")?,
+            scan_type: parse_propmap_value(&data, "scan_type")?,
+            report_delay_millis,
+            batch_mode,
+        })
+    }
+
+    fn to_dbus(settings: ScanSettings) -> Result<dbus::arg::PropMap, Box<dyn std::error::Error>> {
+        let mut map: dbus::arg::PropMap = std::collections::HashMap::new();
+        write_propmap_value(&mut map, settings.interval, "interval")?;
+        write_propmap_value(&mut map, settings.window, "window")?;
+        write_propmap_value(&mut map, settings.scan_type, "scan_type")?;
+        if settings.report_delay_millis > 0 {
+            write_propmap_value(&mut map, settings.report_delay_millis, "report_delay_millis")?;
+            write_propmap_value(&mut map, settings.batch_mode, "batch_mode")?;
+        }
+        return Ok(map);
+    }
 }
 
 #[dbus_propmap(ScanFilterPattern)]
@@ -387,6 +509,13 @@ struct ScanFilterPatternDBus {
     content: Vec<u8>,
 }
 
+#[dbus_propmap(ScanFilterIrk)]
+struct ScanFilterIrkDBus {
+    address: String,
+    address_type: u8,
+    irk: Vec<u8>,
+}
+
 // Manually converts enum variant from/into D-Bus.
 //
 // The ScanFilterCondition enum variant is represented as a D-Bus dictionary with one and only one
@@ -408,6 +537,8 @@ struct ScanFilterPatternDBus {
 //        )
 //     ]
 //
+// The other variants use the keys "uuid", "irk" and "address".
+//
 // If enum variant is used many times, we should find a way to avoid boilerplate.
 impl DBusArg for ScanFilterCondition {
     type DBusType = dbus::arg::PropMap;
@@ -419,32 +550,22 @@ impl DBusArg for ScanFilterCondition {
             std::sync::Arc<std::sync::Mutex<dbus_projection::DisconnectWatcher>>,
         >,
     ) -> Result<ScanFilterCondition, Box<dyn std::error::Error>> {
-        let variant = match data.get("patterns") {
-            Some(variant) => variant,
-            None => {
-                return Err(Box::new(DBusArgError::new(String::from(format!(
-                    "ScanFilterCondition does not contain any enum variant",
-                )))));
-            }
-        };
-
-        match variant.arg_type() {
-            dbus::arg::ArgType::Variant => {}
-            _ => {
-                return Err(Box::new(DBusArgError::new(String::from(format!(
-                    "ScanFilterCondition::Patterns must be a variant",
-                )))));
-            }
+        let condition = if data.contains_key("all") {
+            ScanFilterCondition::All
+        } else if data.contains_key("patterns") {
+            ScanFilterCondition::Patterns(parse_propmap_value(&data, "patterns")?)
+        } else if data.contains_key("uuid") {
+            ScanFilterCondition::Uuid(parse_propmap_value(&data, "uuid")?)
+        } else if data.contains_key("irk") {
+            ScanFilterCondition::Irk(parse_propmap_value(&data, "irk")?)
+        } else if data.contains_key("address") {
+            ScanFilterCondition::BluetoothAddress(parse_propmap_value(&data, "address")?)
+        } else {
+            return Err(Box::new(DBusArgError::new(String::from(format!(
+                "ScanFilterCondition does not contain any enum variant",
+            )))));
         };
-
-        let patterns =
-            <<Vec<ScanFilterPattern> as DBusArg>::DBusType as RefArgToRust>::ref_arg_to_rust(
-                variant.as_static_inner(0).unwrap(),
-                format!("ScanFilterCondition::Patterns"),
-            )?;
-
-        let patterns = Vec::<ScanFilterPattern>::from_dbus(patterns, None, None, None)?;
-        return Ok(ScanFilterCondition::Patterns(patterns));
+        Ok(condition)
     }
 
     fn to_dbus(
@@ -452,13 +573,21 @@ impl DBusArg for ScanFilterCondition {
     ) -> Result<dbus::arg::PropMap, Box<dyn std::error::Error>> {
         let mut map: dbus::arg::PropMap = std::collections::HashMap::new();
         match condition {
+            ScanFilterCondition::All => {
+                write_propmap_value(&mut map, String::from("unit"), "all")?;
+            }
             ScanFilterCondition::Patterns(patterns) => {
-                map.insert(
-                    String::from("patterns"),
-                    dbus::arg::Variant(Box::new(DBusArg::to_dbus(patterns)?)),
-                );
+                write_propmap_value(&mut map, patterns, "patterns")?;
+            }
+            ScanFilterCondition::Uuid(uuid) => {
+                write_propmap_value(&mut map, uuid, "uuid")?;
+            }
+            ScanFilterCondition::Irk(irk) => {
+                write_propmap_value(&mut map, irk, "irk")?;
+            }
+            ScanFilterCondition::BluetoothAddress(address) => {
+                write_propmap_value(&mut map, address, "address")?;
             }
-            _ => {}
         }
         return Ok(map);
     }
@@ -552,6 +681,28 @@ impl IBluetoothCallback for IBluetoothCallbackDBus {
 
     #[dbus_method("OnSdpRecordCreated")]
     fn on_sdp_record_created(&mut self, record: BtSdpRecord, handle: i32) {}
+
+    #[dbus_method("OnLocalOobDataGenerated")]
+    fn on_local_oob_data_generated(&mut self, transport: BtTransport, data: BtOobData) {}
+
+    #[dbus_method("OnEnergyInfo")]
+    fn on_energy_info(&mut self, info: BtActivityEnergyInfo) {}
+
+    #[dbus_method("OnLinkQualityReport")]
+    fn on_link_quality_report(
+        &mut self,
+        remote_device: BluetoothDevice,
+        report: LinkQualityReport,
+    ) {
+    }
+
+    #[dbus_method("OnLocalIrkRegenerated")]
+    fn on_local_irk_regenerated(
+        &mut self,
+        status: BtStatus,
+        blocking_devices: Vec<BluetoothDevice>,
+    ) {
+    }
 }
 
 struct IBluetoothConnectionCallbackDBus {}
@@ -599,13 +750,73 @@ impl IScannerCallback for IScannerCallbackDBus {
         dbus_generated!()
     }
 
+    #[dbus_method("OnBatchScanResults")]
+    fn on_batch_scan_results(&mut self, scanner_id: u8, scan_results: Vec<ScanResult>) {
+        dbus_generated!()
+    }
+
     #[dbus_method("OnSuspendModeChange")]
     fn on_suspend_mode_change(&mut self, suspend_mode: SuspendMode) {
         dbus_generated!()
     }
 }
 
+#[dbus_propmap(ProfileConnectionPolicy)]
+pub struct ProfileConnectionPolicyDBus {
+    profile: Uuid128Bit,
+    policy: ConnectionPolicy,
+    priority: i32,
+}
+
+#[dbus_propmap(DeviceConnectionPolicy)]
+pub struct DeviceConnectionPolicyDBus {
+    profiles: Vec<ProfileConnectionPolicy>,
+    max_reconnect_attempts: u32,
+    reconnect_backoff_ms: u32,
+}
+
+#[dbus_propmap(LePrivacyConfig)]
+pub struct LePrivacyConfigDBus {
+    use_non_resolvable_address: bool,
+    rpa_rotation_timeout_ms: u32,
+}
+
+#[dbus_propmap(PreferredConnectionParameters)]
+pub struct PreferredConnectionParametersDBus {
+    min_interval: u16,
+    max_interval: u16,
+    peripheral_latency: u16,
+    supervision_timeout: u16,
+}
+
+#[dbus_propmap(GapConfiguration)]
+pub struct GapConfigurationDBus {
+    device_name_readable: bool,
+    device_name_writable: bool,
+    persist_written_device_name: bool,
+    appearance: u16,
+    appearance_writable: bool,
+    preferred_connection_parameters: Option<PreferredConnectionParameters>,
+    central_address_resolution: Option<bool>,
+    rpa_only: bool,
+}
+
+#[dbus_propmap(RemoteSecurityInfo)]
+pub struct RemoteSecurityInfoDBus {
+    link_key_type: LinkKeyType,
+    le_ltk_present: bool,
+    le_irk_present: bool,
+    le_csrk_present: bool,
+    encryption_key_size: u8,
+    is_ctkd: bool,
+    security_level: SecurityLevel,
+}
+
 impl_dbus_arg_enum!(BtDiscMode);
+impl_dbus_arg_enum!(ConnectionPolicy);
+impl_dbus_arg_enum!(LinkKeyType);
+impl_dbus_arg_enum!(ScanParameterProfile);
+impl_dbus_arg_enum!(SecurityLevel);
 
 // Implements RPC-friendly wrapper methods for calling IBluetooth, generated by
 // `generate_dbus_interface_client` below.
@@ -726,6 +937,11 @@ impl IBluetooth for BluetoothDBus {
         dbus_generated!()
     }
 
+    #[dbus_method("StartDiscoveryWithFilter")]
+    fn start_discovery_with_filter(&mut self, filter: DiscoveryFilter) -> bool {
+        dbus_generated!()
+    }
+
     #[dbus_method("CancelDiscovery")]
     fn cancel_discovery(&mut self) -> bool {
         dbus_generated!()
@@ -746,6 +962,22 @@ impl IBluetooth for BluetoothDBus {
         dbus_generated!()
     }
 
+    #[dbus_method("CreateBondOutOfBand")]
+    fn create_bond_out_of_band(
+        &mut self,
+        device: BluetoothDevice,
+        transport: BtTransport,
+        p192_data: BtOobData,
+        p256_data: BtOobData,
+    ) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GenerateLocalOobData")]
+    fn generate_local_oob_data(&self, transport: BtTransport) -> bool {
+        dbus_generated!()
+    }
+
     #[dbus_method("CancelBondProcess")]
     fn cancel_bond_process(&self, device: BluetoothDevice) -> bool {
         dbus_generated!()
@@ -761,11 +993,26 @@ impl IBluetooth for BluetoothDBus {
         dbus_generated!()
     }
 
+    #[dbus_method("GetRecentDevices")]
+    fn get_recent_devices(&self) -> Vec<RecentDevice> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ClearRecentDevices")]
+    fn clear_recent_devices(&mut self) {
+        dbus_generated!()
+    }
+
     #[dbus_method("GetBondState")]
     fn get_bond_state(&self, device: BluetoothDevice) -> BtBondState {
         dbus_generated!()
     }
 
+    #[dbus_method("GetRemoteSecurityInfo")]
+    fn get_remote_security_info(&self, device: BluetoothDevice) -> RemoteSecurityInfo {
+        dbus_generated!()
+    }
+
     #[dbus_method("SetPin")]
     fn set_pin(&self, device: BluetoothDevice, accept: bool, pin_code: Vec<u8>) -> bool {
         dbus_generated!()
@@ -871,6 +1118,75 @@ impl IBluetooth for BluetoothDBus {
         dbus_generated!()
     }
 
+    #[dbus_method("GetConnectionPolicy")]
+    fn get_connection_policy(&self, device: BluetoothDevice) -> DeviceConnectionPolicy {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetConnectionPolicy")]
+    fn set_connection_policy(
+        &mut self,
+        device: BluetoothDevice,
+        policy: DeviceConnectionPolicy,
+    ) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RequestEnergyInfo")]
+    fn request_energy_info(&self) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetLinkQualityReport")]
+    fn set_link_quality_report(&self, enable: bool, min_report_interval_ms: u16) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetLePrivacyConfig")]
+    fn get_le_privacy_config(&self) -> LePrivacyConfig {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetLePrivacyConfig")]
+    fn set_le_privacy_config(&mut self, config: LePrivacyConfig) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetLeIdentityAddress")]
+    fn get_le_identity_address(&self) -> String {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetLocalIrk")]
+    fn get_local_irk(&self) -> Vec<u8> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RegenerateLocalIrk")]
+    fn regenerate_local_irk(&mut self) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetGapConfiguration")]
+    fn get_gap_configuration(&self) -> GapConfiguration {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetGapConfiguration")]
+    fn set_gap_configuration(&mut self, config: GapConfiguration) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetScanParameterProfile")]
+    fn get_scan_parameter_profile(&self) -> ScanParameterProfile {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetScanParameterProfile")]
+    fn set_scan_parameter_profile(&mut self, profile: ScanParameterProfile) -> bool {
+        dbus_generated!()
+    }
+
     #[dbus_method("IsWbsSupported")]
     fn is_wbs_supported(&self) -> bool {
         dbus_generated!()
@@ -1325,6 +1641,11 @@ impl IBluetoothGatt for BluetoothGattDBus {
         dbus_generated!()
     }
 
+    #[dbus_method("FlushPendingBatchResults")]
+    fn flush_pending_batch_results(&mut self, _scanner_id: u8) -> BtStatus {
+        dbus_generated!()
+    }
+
     #[dbus_method("GetScanSuspendMode")]
     fn get_scan_suspend_mode(&self) -> SuspendMode {
         dbus_generated!()
@@ -1,6 +1,6 @@
 use bt_topshim::btif::{
-    BtBondState, BtConnectionState, BtDeviceType, BtDiscMode, BtPropertyType, BtSspVariant,
-    BtStatus, BtTransport, Uuid, Uuid128Bit,
+    BtActivityEnergyInfo, BtBondState, BtConnectionState, BtDeviceType, BtDiscMode, BtOobData,
+    BtPropertyType, BtSspVariant, BtStatus, BtTransport, Uuid, Uuid128Bit,
 };
 use bt_topshim::profiles::socket::SocketType;
 use bt_topshim::profiles::ProfileConnectionState;
@@ -8,15 +8,22 @@ use bt_topshim::profiles::ProfileConnectionState;
 use bt_topshim::profiles::hid_host::BthhReportType;
 
 use bt_topshim::profiles::sdp::{
-    BtSdpDipRecord, BtSdpHeaderOverlay, BtSdpMasRecord, BtSdpMnsRecord, BtSdpMpsRecord,
-    BtSdpOpsRecord, BtSdpPceRecord, BtSdpPseRecord, BtSdpRecord, BtSdpSapRecord, BtSdpType,
-    SupportedDependencies, SupportedFormatsList, SupportedScenarios,
+    BtSdpAttribute, BtSdpCustomRecord, BtSdpDipRecord, BtSdpHeaderOverlay, BtSdpMasRecord,
+    BtSdpMnsRecord, BtSdpMpsRecord, BtSdpOpsRecord, BtSdpPceRecord, BtSdpPseRecord, BtSdpRecord,
+    BtSdpSapRecord, BtSdpType, SupportedDependencies, SupportedFormatsList, SupportedScenarios,
 };
 
 use btstack::bluetooth::{
-    Bluetooth, BluetoothDevice, IBluetooth, IBluetoothCallback, IBluetoothConnectionCallback,
-    IBluetoothQALegacy,
+    Bluetooth, BluetoothDevice, DiscoveryFilter, GapConfiguration, IBluetooth, IBluetoothCallback,
+    IBluetoothConnectionCallback, IBluetoothQALegacy, LinkKeyType, LinkQualityReport,
+    PreferredConnectionParameters, RemoteSecurityInfo, SecurityLevel,
 };
+use btstack::connection_policy::{
+    ConnectionPolicy, DeviceConnectionPolicy, ProfileConnectionPolicy,
+};
+use btstack::device_cache::RecentDevice;
+use btstack::le_privacy::LePrivacyConfig;
+use btstack::scan_params::ScanParameterProfile;
 use btstack::socket_manager::{
     BluetoothServerSocket, BluetoothSocket, BluetoothSocketManager, CallbackId,
     IBluetoothSocketManager, IBluetoothSocketManagerCallbacks, SocketId, SocketResult,
@@ -138,6 +145,84 @@ impl IBluetoothCallback for BluetoothCallbackDBus {
     fn on_sdp_record_created(&mut self, record: BtSdpRecord, handle: i32) {
         dbus_generated!()
     }
+    #[dbus_method("OnLocalOobDataGenerated")]
+    fn on_local_oob_data_generated(&mut self, transport: BtTransport, data: BtOobData) {
+        dbus_generated!()
+    }
+    #[dbus_method("OnEnergyInfo")]
+    fn on_energy_info(&mut self, info: BtActivityEnergyInfo) {
+        dbus_generated!()
+    }
+    #[dbus_method("OnLinkQualityReport")]
+    fn on_link_quality_report(
+        &mut self,
+        remote_device: BluetoothDevice,
+        report: LinkQualityReport,
+    ) {
+        dbus_generated!()
+    }
+    #[dbus_method("OnLocalIrkRegenerated")]
+    fn on_local_irk_regenerated(
+        &mut self,
+        status: BtStatus,
+        blocking_devices: Vec<BluetoothDevice>,
+    ) {
+        dbus_generated!()
+    }
+}
+
+#[dbus_propmap(BtOobData)]
+pub struct BtOobDataDBus {
+    is_valid: bool,
+    address: Vec<u8>,
+    c: Vec<u8>,
+    r: Vec<u8>,
+    device_name: Vec<u8>,
+    oob_data_length: Vec<u8>,
+    class_of_device: Vec<u8>,
+    le_device_role: u8,
+    sm_tk: Vec<u8>,
+    le_flags: u8,
+    le_appearance: Vec<u8>,
+}
+
+#[dbus_propmap(DiscoveryFilter)]
+pub struct DiscoveryFilterDBus {
+    transport: BtTransport,
+    duration_ms: u32,
+    rssi_threshold: Option<i8>,
+    uuids: Vec<Uuid128Bit>,
+    major_device_class: Option<u8>,
+}
+
+#[dbus_propmap(RecentDevice)]
+pub struct RecentDeviceDBus {
+    device: BluetoothDevice,
+    appearance: u16,
+    class: u32,
+    uuids: Vec<Uuid128Bit>,
+    last_seen_ms: u64,
+}
+
+#[dbus_propmap(BtActivityEnergyInfo)]
+pub struct BtActivityEnergyInfoDBus {
+    status: u8,
+    ctrl_state: u8,
+    tx_time_ms: u64,
+    rx_time_ms: u64,
+    idle_time_ms: u64,
+    energy_used: u64,
+}
+
+#[dbus_propmap(LinkQualityReport)]
+pub struct LinkQualityReportDBus {
+    timestamp_ms: u64,
+    report_id: i32,
+    rssi: i32,
+    snr: i32,
+    retransmission_count: i32,
+    packets_not_receive_count: i32,
+    negative_acknowledgement_count: i32,
 }
 
 impl_dbus_arg_enum!(BtBondState);
@@ -244,6 +329,18 @@ pub struct BtSdpMpsRecordDBus {
     supported_dependencies: SupportedDependencies,
 }
 
+#[dbus_propmap(BtSdpAttribute)]
+pub struct BtSdpAttributeDBus {
+    id: u16,
+    value: Vec<u8>,
+}
+
+#[dbus_propmap(BtSdpCustomRecord)]
+pub struct BtSdpCustomRecordDBus {
+    hdr: BtSdpHeaderOverlay,
+    attributes: Vec<BtSdpAttribute>,
+}
+
 fn read_propmap_value<T: 'static + DirectDBus>(
     propmap: &dbus::arg::PropMap,
     key: &str,
@@ -262,7 +359,7 @@ fn read_propmap_value<T: 'static + DirectDBus>(
     Ok(output)
 }
 
-fn parse_propmap_value<T: DBusArg>(
+pub(crate) fn parse_propmap_value<T: DBusArg>(
     propmap: &dbus::arg::PropMap,
     key: &str,
 ) -> Result<T, Box<dyn std::error::Error>>
@@ -284,7 +381,7 @@ where
     Ok(output)
 }
 
-fn write_propmap_value<T: DBusArg>(
+pub(crate) fn write_propmap_value<T: DBusArg>(
     propmap: &mut dbus::arg::PropMap,
     value: T,
     key: &str,
@@ -345,6 +442,10 @@ impl DBusArg for BtSdpRecord {
                 let arg_0 = parse_propmap_value::<BtSdpMpsRecord>(&data, "0")?;
                 BtSdpRecord::Mps(arg_0)
             }
+            BtSdpType::Custom => {
+                let arg_0 = parse_propmap_value::<BtSdpCustomRecord>(&data, "0")?;
+                BtSdpRecord::Custom(arg_0)
+            }
         };
         Ok(record)
     }
@@ -384,12 +485,72 @@ impl DBusArg for BtSdpRecord {
             BtSdpRecord::Mps(mps_record) => {
                 write_propmap_value::<BtSdpMpsRecord>(&mut map, mps_record, &String::from("0"))?
             }
+            BtSdpRecord::Custom(custom_record) => write_propmap_value::<BtSdpCustomRecord>(
+                &mut map,
+                custom_record,
+                &String::from("0"),
+            )?,
         }
         Ok(map)
     }
 }
 
+#[dbus_propmap(ProfileConnectionPolicy)]
+pub struct ProfileConnectionPolicyDBus {
+    profile: Uuid128Bit,
+    policy: ConnectionPolicy,
+    priority: i32,
+}
+
+#[dbus_propmap(DeviceConnectionPolicy)]
+pub struct DeviceConnectionPolicyDBus {
+    profiles: Vec<ProfileConnectionPolicy>,
+    max_reconnect_attempts: u32,
+    reconnect_backoff_ms: u32,
+}
+
+#[dbus_propmap(LePrivacyConfig)]
+pub struct LePrivacyConfigDBus {
+    use_non_resolvable_address: bool,
+    rpa_rotation_timeout_ms: u32,
+}
+
+#[dbus_propmap(PreferredConnectionParameters)]
+pub struct PreferredConnectionParametersDBus {
+    min_interval: u16,
+    max_interval: u16,
+    peripheral_latency: u16,
+    supervision_timeout: u16,
+}
+
+#[dbus_propmap(GapConfiguration)]
+pub struct GapConfigurationDBus {
+    device_name_readable: bool,
+    device_name_writable: bool,
+    persist_written_device_name: bool,
+    appearance: u16,
+    appearance_writable: bool,
+    preferred_connection_parameters: Option<PreferredConnectionParameters>,
+    central_address_resolution: Option<bool>,
+    rpa_only: bool,
+}
+
+#[dbus_propmap(RemoteSecurityInfo)]
+pub struct RemoteSecurityInfoDBus {
+    link_key_type: LinkKeyType,
+    le_ltk_present: bool,
+    le_irk_present: bool,
+    le_csrk_present: bool,
+    encryption_key_size: u8,
+    is_ctkd: bool,
+    security_level: SecurityLevel,
+}
+
 impl_dbus_arg_enum!(BtDiscMode);
+impl_dbus_arg_enum!(ConnectionPolicy);
+impl_dbus_arg_enum!(LinkKeyType);
+impl_dbus_arg_enum!(ScanParameterProfile);
+impl_dbus_arg_enum!(SecurityLevel);
 
 #[allow(dead_code)]
 struct IBluetoothDBus {}
@@ -490,6 +651,11 @@ impl IBluetooth for IBluetoothDBus {
         dbus_generated!()
     }
 
+    #[dbus_method("StartDiscoveryWithFilter")]
+    fn start_discovery_with_filter(&mut self, filter: DiscoveryFilter) -> bool {
+        dbus_generated!()
+    }
+
     #[dbus_method("CancelDiscovery")]
     fn cancel_discovery(&mut self) -> bool {
         dbus_generated!()
@@ -510,6 +676,22 @@ impl IBluetooth for IBluetoothDBus {
         dbus_generated!()
     }
 
+    #[dbus_method("CreateBondOutOfBand")]
+    fn create_bond_out_of_band(
+        &mut self,
+        device: BluetoothDevice,
+        transport: BtTransport,
+        p192_data: BtOobData,
+        p256_data: BtOobData,
+    ) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GenerateLocalOobData")]
+    fn generate_local_oob_data(&self, transport: BtTransport) -> bool {
+        dbus_generated!()
+    }
+
     #[dbus_method("CancelBondProcess")]
     fn cancel_bond_process(&self, device: BluetoothDevice) -> bool {
         dbus_generated!()
@@ -525,11 +707,26 @@ impl IBluetooth for IBluetoothDBus {
         dbus_generated!()
     }
 
+    #[dbus_method("GetRecentDevices")]
+    fn get_recent_devices(&self) -> Vec<RecentDevice> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("ClearRecentDevices")]
+    fn clear_recent_devices(&mut self) {
+        dbus_generated!()
+    }
+
     #[dbus_method("GetBondState")]
     fn get_bond_state(&self, device: BluetoothDevice) -> BtBondState {
         dbus_generated!()
     }
 
+    #[dbus_method("GetRemoteSecurityInfo")]
+    fn get_remote_security_info(&self, device: BluetoothDevice) -> RemoteSecurityInfo {
+        dbus_generated!()
+    }
+
     #[dbus_method("SetPin")]
     fn set_pin(&self, device: BluetoothDevice, accept: bool, pin_code: Vec<u8>) -> bool {
         dbus_generated!()
@@ -635,6 +832,75 @@ impl IBluetooth for IBluetoothDBus {
         dbus_generated!()
     }
 
+    #[dbus_method("GetConnectionPolicy")]
+    fn get_connection_policy(&self, device: BluetoothDevice) -> DeviceConnectionPolicy {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetConnectionPolicy")]
+    fn set_connection_policy(
+        &mut self,
+        device: BluetoothDevice,
+        policy: DeviceConnectionPolicy,
+    ) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RequestEnergyInfo")]
+    fn request_energy_info(&self) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetLinkQualityReport")]
+    fn set_link_quality_report(&self, enable: bool, min_report_interval_ms: u16) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetLePrivacyConfig")]
+    fn get_le_privacy_config(&self) -> LePrivacyConfig {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetLePrivacyConfig")]
+    fn set_le_privacy_config(&mut self, config: LePrivacyConfig) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetLeIdentityAddress")]
+    fn get_le_identity_address(&self) -> String {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetLocalIrk")]
+    fn get_local_irk(&self) -> Vec<u8> {
+        dbus_generated!()
+    }
+
+    #[dbus_method("RegenerateLocalIrk")]
+    fn regenerate_local_irk(&mut self) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetGapConfiguration")]
+    fn get_gap_configuration(&self) -> GapConfiguration {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetGapConfiguration")]
+    fn set_gap_configuration(&mut self, config: GapConfiguration) -> bool {
+        dbus_generated!()
+    }
+
+    #[dbus_method("GetScanParameterProfile")]
+    fn get_scan_parameter_profile(&self) -> ScanParameterProfile {
+        dbus_generated!()
+    }
+
+    #[dbus_method("SetScanParameterProfile")]
+    fn set_scan_parameter_profile(&mut self, profile: ScanParameterProfile) -> bool {
+        dbus_generated!()
+    }
+
     #[dbus_method("IsWbsSupported")]
     fn is_wbs_supported(&self) -> bool {
         dbus_generated!()
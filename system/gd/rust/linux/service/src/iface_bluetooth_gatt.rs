@@ -6,9 +6,9 @@ use btstack::bluetooth_adv::{
     PeriodicAdvertisingParameters,
 };
 use btstack::bluetooth_gatt::{
-    BluetoothGattCharacteristic, BluetoothGattDescriptor, BluetoothGattService,
+    BatchScanMode, BluetoothGattCharacteristic, BluetoothGattDescriptor, BluetoothGattService,
     GattWriteRequestStatus, GattWriteType, IBluetoothGatt, IBluetoothGattCallback,
-    IBluetoothGattServerCallback, IScannerCallback, ScanFilter, ScanFilterCondition,
+    IBluetoothGattServerCallback, IScannerCallback, ScanFilter, ScanFilterCondition, ScanFilterIrk,
     ScanFilterPattern, ScanResult, ScanSettings, ScanType,
 };
 use btstack::{RPCProxy, SuspendMode};
@@ -30,6 +30,7 @@ use std::convert::TryInto;
 use std::sync::Arc;
 
 use crate::dbus_arg::{DBusArg, DBusArgError, RefArgToRust};
+use crate::iface_bluetooth::{parse_propmap_value, write_propmap_value};
 
 #[allow(dead_code)]
 struct BluetoothGattCallbackDBus {}
@@ -315,6 +316,11 @@ impl IScannerCallback for ScannerCallbackDBus {
         dbus_generated!()
     }
 
+    #[dbus_method("OnBatchScanResults")]
+    fn on_batch_scan_results(&mut self, scanner_id: u8, scan_results: Vec<ScanResult>) {
+        dbus_generated!()
+    }
+
     #[dbus_method("OnSuspendModeChange")]
     fn on_suspend_mode_change(&mut self, suspend_mode: SuspendMode) {
         dbus_generated!()
@@ -348,11 +354,50 @@ pub struct BluetoothGattServiceDBus {
     included_services: Vec<BluetoothGattService>,
 }
 
-#[dbus_propmap(ScanSettings)]
-struct ScanSettingsDBus {
-    interval: i32,
-    window: i32,
-    scan_type: ScanType,
+// Manually converts ScanSettings from/into D-Bus, since clients that don't batch the scan results
+// leave out the batching keys.
+//
+// ScanSettings is represented as a D-Bus dictionary with the keys "interval", "window",
+// "scan_type" and, optionally, "report_delay_millis" and "batch_mode". Without a report delay, the
+// scan results aren't batched.
+impl DBusArg for ScanSettings {
+    type DBusType = dbus::arg::PropMap;
+    fn from_dbus(
+        data: dbus::arg::PropMap,
+        _conn: Option<std::sync::Arc<dbus::nonblock::SyncConnection>>,
+        _remote: Option<dbus::strings::BusName<'static>>,
+        _disconnect_watcher: Option<
+            std::sync::Arc<std::sync::Mutex<dbus_projection::DisconnectWatcher>>,
+        >,
+    ) -> Result<ScanSettings, Box<dyn std::error::Error>> {
+        let report_delay_millis = match data.contains_key("report_delay_millis") {
+            true => parse_propmap_value(&data, "report_delay_millis")?,
+            false => 0,
+        };
+        let batch_mode = match data.contains_key("batch_mode") {
+            true => parse_propmap_value(&data, "batch_mode")?,
+            false => BatchScanMode::default(),
+        };
+        Ok(ScanSettings {
+            interval: parse_propmap_value(&data, "interval")?,
+            window: parse_propmap_value(&data, "window")?,
+            scan_type: parse_propmap_value(&data, "scan_type")?,
+            report_delay_millis,
+            batch_mode,
+        })
+    }
+
+    fn to_dbus(settings: ScanSettings) -> Result<dbus::arg::PropMap, Box<dyn std::error::Error>> {
+        let mut map: dbus::arg::PropMap = std::collections::HashMap::new();
+        write_propmap_value(&mut map, settings.interval, "interval")?;
+        write_propmap_value(&mut map, settings.window, "window")?;
+        write_propmap_value(&mut map, settings.scan_type, "scan_type")?;
+        if settings.report_delay_millis > 0 {
+            write_propmap_value(&mut map, settings.report_delay_millis, "report_delay_millis")?;
+            write_propmap_value(&mut map, settings.batch_mode, "batch_mode")?;
+        }
+        return Ok(map);
+    }
 }
 
 #[dbus_propmap(ScanResult)]
@@ -375,6 +420,7 @@ struct ScanResultDBus {
 }
 
 impl_dbus_arg_enum!(AdvertisingStatus);
+impl_dbus_arg_enum!(BatchScanMode);
 impl_dbus_arg_enum!(GattStatus);
 impl_dbus_arg_enum!(GattWriteRequestStatus);
 impl_dbus_arg_enum!(GattWriteType);
@@ -389,6 +435,13 @@ struct ScanFilterPatternDBus {
     content: Vec<u8>,
 }
 
+#[dbus_propmap(ScanFilterIrk)]
+struct ScanFilterIrkDBus {
+    address: String,
+    address_type: u8,
+    irk: Vec<u8>,
+}
+
 // Manually converts enum variant from/into D-Bus.
 //
 // The ScanFilterCondition enum variant is represented as a D-Bus dictionary with one and only one
@@ -410,6 +463,8 @@ struct ScanFilterPatternDBus {
 //        )
 //     ]
 //
+// The other variants use the keys "uuid", "irk" and "address".
+//
 // If enum variant is used many times, we should find a way to avoid boilerplate.
 impl DBusArg for ScanFilterCondition {
     type DBusType = dbus::arg::PropMap;
@@ -421,32 +476,22 @@ impl DBusArg for ScanFilterCondition {
             std::sync::Arc<std::sync::Mutex<dbus_projection::DisconnectWatcher>>,
         >,
     ) -> Result<ScanFilterCondition, Box<dyn std::error::Error>> {
-        let variant = match data.get("patterns") {
-            Some(variant) => variant,
-            None => {
-                return Err(Box::new(DBusArgError::new(String::from(format!(
-                    "ScanFilterCondition does not contain any enum variant",
-                )))));
-            }
-        };
-
-        match variant.arg_type() {
-            dbus::arg::ArgType::Variant => {}
-            _ => {
-                return Err(Box::new(DBusArgError::new(String::from(format!(
-                    "ScanFilterCondition::Patterns must be a variant",
-                )))));
-            }
+        let condition = if data.contains_key("all") {
+            ScanFilterCondition::All
+        } else if data.contains_key("patterns") {
+            ScanFilterCondition::Patterns(parse_propmap_value(&data, "patterns")?)
+        } else if data.contains_key("uuid") {
+            ScanFilterCondition::Uuid(parse_propmap_value(&data, "uuid")?)
+        } else if data.contains_key("irk") {
+            ScanFilterCondition::Irk(parse_propmap_value(&data, "irk")?)
+        } else if data.contains_key("address") {
+            ScanFilterCondition::BluetoothAddress(parse_propmap_value(&data, "address")?)
+        } else {
+            return Err(Box::new(DBusArgError::new(String::from(format!(
+                "ScanFilterCondition does not contain any enum variant",
+            )))));
         };
-
-        let patterns =
-            <<Vec<ScanFilterPattern> as DBusArg>::DBusType as RefArgToRust>::ref_arg_to_rust(
-                variant.as_static_inner(0).unwrap(),
-                format!("ScanFilterCondition::Patterns"),
-            )?;
-
-        let patterns = Vec::<ScanFilterPattern>::from_dbus(patterns, None, None, None)?;
-        return Ok(ScanFilterCondition::Patterns(patterns));
+        Ok(condition)
     }
 
     fn to_dbus(
@@ -454,13 +499,21 @@ impl DBusArg for ScanFilterCondition {
     ) -> Result<dbus::arg::PropMap, Box<dyn std::error::Error>> {
         let mut map: dbus::arg::PropMap = std::collections::HashMap::new();
         match condition {
+            ScanFilterCondition::All => {
+                write_propmap_value(&mut map, String::from("unit"), "all")?;
+            }
             ScanFilterCondition::Patterns(patterns) => {
-                map.insert(
-                    String::from("patterns"),
-                    dbus::arg::Variant(Box::new(DBusArg::to_dbus(patterns)?)),
-                );
+                write_propmap_value(&mut map, patterns, "patterns")?;
+            }
+            ScanFilterCondition::Uuid(uuid) => {
+                write_propmap_value(&mut map, uuid, "uuid")?;
+            }
+            ScanFilterCondition::Irk(irk) => {
+                write_propmap_value(&mut map, irk, "irk")?;
+            }
+            ScanFilterCondition::BluetoothAddress(address) => {
+                write_propmap_value(&mut map, address, "address")?;
             }
-            _ => {}
         }
         return Ok(map);
     }
@@ -639,6 +692,11 @@ impl IBluetoothGatt for IBluetoothGattDBus {
         dbus_generated!()
     }
 
+    #[dbus_method("FlushPendingBatchResults")]
+    fn flush_pending_batch_results(&mut self, scanner_id: u8) -> BtStatus {
+        dbus_generated!()
+    }
+
     #[dbus_method("GetScanSuspendMode")]
     fn get_scan_suspend_mode(&self) -> SuspendMode {
         dbus_generated!()
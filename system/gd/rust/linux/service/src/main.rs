@@ -25,7 +25,11 @@ use btstack::{
     bluetooth_gatt::BluetoothGatt,
     bluetooth_logging::BluetoothLogging,
     bluetooth_media::BluetoothMedia,
+    connection_policy::ConnectionPolicies,
+    device_cache::{DeviceCache, DEFAULT_MAX_CACHED_DEVICES, DEFAULT_MAX_CACHED_DEVICE_AGE},
     dis::DeviceInformation,
+    le_privacy::LePrivacySettings,
+    scan_params::ScanParameterSettings,
     socket_manager::BluetoothSocketManager,
     suspend::Suspend,
     Message, Stack,
@@ -46,6 +50,10 @@ mod iface_logging;
 
 const DBUS_SERVICE_NAME: &str = "org.chromium.bluetooth";
 const ADMIN_SETTINGS_FILE_PATH: &str = "/var/lib/bluetooth/admin_policy.json";
+const DEVICE_CACHE_FILE_PATH: &str = "/var/lib/bluetooth/device_cache.json";
+const CONNECTION_POLICY_FILE_PATH: &str = "/var/lib/bluetooth/connection_policy.json";
+const LE_PRIVACY_FILE_PATH: &str = "/var/lib/bluetooth/le_privacy.json";
+const SCAN_PARAMETERS_FILE_PATH: &str = "/var/lib/bluetooth/scan_parameters.json";
 // The maximum ACL disconnect timeout is 3.5s defined by BTA_DM_DISABLE_TIMER_MS
 // and BTA_DM_DISABLE_TIMER_RETRIAL_MS
 const STACK_TURN_OFF_TIMEOUT_MS: Duration = Duration::from_millis(4000);
@@ -168,6 +176,14 @@ fn main() -> Result<(), Box<dyn Error>> {
         bluetooth_admin.clone(),
         bluetooth_gatt.clone(),
         bluetooth_media.clone(),
+        DeviceCache::new(
+            String::from(DEVICE_CACHE_FILE_PATH),
+            DEFAULT_MAX_CACHED_DEVICES,
+            DEFAULT_MAX_CACHED_DEVICE_AGE,
+        ),
+        ConnectionPolicies::new(String::from(CONNECTION_POLICY_FILE_PATH)),
+        LePrivacySettings::new(String::from(LE_PRIVACY_FILE_PATH)),
+        ScanParameterSettings::new(String::from(SCAN_PARAMETERS_FILE_PATH)),
     ))));
     let suspend = Arc::new(Mutex::new(Box::new(Suspend::new(
         bluetooth.clone(),
@@ -0,0 +1,226 @@
+//! Monitoring of advertisements in the host, with the same semantics as the advertisement
+//! monitors of the MSFT extension. Used for the scan filters that the controller can't monitor.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::bluetooth_gatt::{ScanFilter, ScanResult};
+
+/// Unit of `ScanFilter::rssi_sampling_period`.
+const SAMPLING_PERIOD_UNIT: Duration = Duration::from_millis(100);
+
+/// A change of whether a device is monitored, and the scan result that caused it.
+#[derive(Debug)]
+pub enum AdvMonitorEvent {
+    Found(ScanResult),
+    Lost(ScanResult),
+}
+
+/// The state of a device whose advertisements match the filter.
+struct MonitoredDevice {
+    /// Whether the device was reported found and not lost since.
+    is_found: bool,
+    /// The last sampled advertisement.
+    last_result: ScanResult,
+    last_sampled: Instant,
+    /// Since when the RSSI has been at or below the low threshold, if it is.
+    low_rssi_since: Option<Instant>,
+}
+
+/// Reports the devices found and lost by the advertisements matching a scan filter.
+///
+/// A device is found as soon as an advertisement reaches `rssi_high_threshold`. A found device is
+/// lost once its RSSI stays at or below `rssi_low_threshold` for `rssi_low_timeout`, or when it
+/// isn't heard from during `rssi_low_timeout`.
+pub struct AdvMonitor {
+    filter: ScanFilter,
+    devices: HashMap<String, MonitoredDevice>,
+}
+
+impl AdvMonitor {
+    pub fn new(filter: ScanFilter) -> AdvMonitor {
+        AdvMonitor { filter, devices: HashMap::new() }
+    }
+
+    fn rssi_high_threshold(&self) -> i8 {
+        self.filter.rssi_high_threshold as i8
+    }
+
+    fn rssi_low_threshold(&self) -> i8 {
+        self.filter.rssi_low_threshold as i8
+    }
+
+    fn rssi_low_timeout(&self) -> Duration {
+        Duration::from_secs(self.filter.rssi_low_timeout.into())
+    }
+
+    fn sampling_period(&self) -> Duration {
+        SAMPLING_PERIOD_UNIT * self.filter.rssi_sampling_period.into()
+    }
+
+    /// Processes |result| received at |now|, and returns the event it causes if any.
+    pub fn on_scan_result(&mut self, result: &ScanResult, now: Instant) -> Option<AdvMonitorEvent> {
+        if !self.filter.condition.matches(result) {
+            return None;
+        }
+
+        let (high, low, low_timeout) =
+            (self.rssi_high_threshold(), self.rssi_low_threshold(), self.rssi_low_timeout());
+        let sampling_period = self.sampling_period();
+
+        if let Some(device) = self.devices.get(&result.address) {
+            if now < device.last_sampled + sampling_period {
+                return None;
+            }
+        } else if result.rssi < high {
+            // Devices are only tracked from the first advertisement that can find them.
+            return None;
+        }
+
+        let device = self.devices.entry(result.address.clone()).or_insert(MonitoredDevice {
+            is_found: false,
+            last_result: result.clone(),
+            last_sampled: now,
+            low_rssi_since: None,
+        });
+        device.last_result = result.clone();
+        device.last_sampled = now;
+
+        if !device.is_found {
+            if result.rssi >= high {
+                device.is_found = true;
+                device.low_rssi_since = None;
+                return Some(AdvMonitorEvent::Found(result.clone()));
+            }
+            return None;
+        }
+
+        if result.rssi > low {
+            device.low_rssi_since = None;
+            return None;
+        }
+
+        let low_rssi_since = *device.low_rssi_since.get_or_insert(now);
+        if now >= low_rssi_since + low_timeout {
+            device.is_found = false;
+            device.low_rssi_since = None;
+            return Some(AdvMonitorEvent::Lost(result.clone()));
+        }
+        None
+    }
+
+    /// Loses the found devices that haven't been heard from during `rssi_low_timeout` as of
+    /// |now|, and returns their last scan results.
+    pub fn check_lost(&mut self, now: Instant) -> Vec<ScanResult> {
+        let low_timeout = self.rssi_low_timeout();
+        let mut lost = vec![];
+        self.devices.retain(|_address, device| {
+            if now < device.last_sampled + low_timeout {
+                return true;
+            }
+            if device.is_found {
+                lost.push(device.last_result.clone());
+            }
+            false
+        });
+        lost
+    }
+
+    /// Returns when the next found device will be lost if it isn't heard from again.
+    pub fn next_lost_deadline(&self) -> Option<Instant> {
+        self.devices
+            .values()
+            .filter(|device| device.is_found)
+            .map(|device| device.last_sampled + self.rssi_low_timeout())
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bluetooth_gatt::ScanFilterCondition;
+
+    const ADDRESS: &str = "11:22:33:44:55:66";
+
+    fn monitor(sampling_period: u8) -> AdvMonitor {
+        AdvMonitor::new(ScanFilter {
+            rssi_high_threshold: -60i8 as u8,
+            rssi_low_threshold: -80i8 as u8,
+            rssi_low_timeout: 3,
+            rssi_sampling_period: sampling_period,
+            condition: ScanFilterCondition::BluetoothAddress(String::from(ADDRESS)),
+        })
+    }
+
+    fn result(address: &str, rssi: i8) -> ScanResult {
+        ScanResult { address: String::from(address), rssi, ..Default::default() }
+    }
+
+    fn is_found(event: Option<AdvMonitorEvent>) -> bool {
+        matches!(event, Some(AdvMonitorEvent::Found(_)))
+    }
+
+    fn is_lost(event: Option<AdvMonitorEvent>) -> bool {
+        matches!(event, Some(AdvMonitorEvent::Lost(_)))
+    }
+
+    #[test]
+    fn test_found_and_lost_by_rssi() {
+        let mut monitor = monitor(0);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(monitor.on_scan_result(&result("11:22:33:44:55:67", -40), at(0)).is_none());
+        assert!(monitor.on_scan_result(&result(ADDRESS, -70), at(0)).is_none());
+        assert!(is_found(monitor.on_scan_result(&result(ADDRESS, -60), at(0))));
+        assert!(monitor.on_scan_result(&result(ADDRESS, -50), at(1)).is_none());
+
+        // The RSSI must stay low for the whole timeout.
+        assert!(monitor.on_scan_result(&result(ADDRESS, -80), at(1)).is_none());
+        assert!(monitor.on_scan_result(&result(ADDRESS, -79), at(2)).is_none());
+        assert!(monitor.on_scan_result(&result(ADDRESS, -85), at(3)).is_none());
+        assert!(monitor.on_scan_result(&result(ADDRESS, -85), at(5)).is_none());
+        assert!(is_lost(monitor.on_scan_result(&result(ADDRESS, -90), at(6))));
+
+        assert!(monitor.on_scan_result(&result(ADDRESS, -70), at(7)).is_none());
+        assert!(is_found(monitor.on_scan_result(&result(ADDRESS, -55), at(7))));
+    }
+
+    #[test]
+    fn test_lost_by_timeout() {
+        let mut monitor = monitor(0);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(monitor.next_lost_deadline(), None);
+        assert!(is_found(monitor.on_scan_result(&result(ADDRESS, -50), at(0))));
+        assert!(monitor.on_scan_result(&result(ADDRESS, -70), at(1)).is_none());
+        assert_eq!(monitor.next_lost_deadline(), Some(at(4)));
+
+        assert!(monitor.check_lost(at(3)).is_empty());
+        let lost = monitor.check_lost(at(4));
+        assert_eq!(lost.len(), 1);
+        assert_eq!(lost[0].rssi, -70);
+        assert_eq!(monitor.next_lost_deadline(), None);
+        assert!(monitor.check_lost(at(10)).is_empty());
+    }
+
+    #[test]
+    fn test_sampling_period() {
+        // A sampling period of 1 second.
+        let mut monitor = monitor(10);
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        assert!(is_found(monitor.on_scan_result(&result(ADDRESS, -50), at(0))));
+        // Not sampled, so the low RSSI doesn't start the timeout.
+        assert!(monitor.on_scan_result(&result(ADDRESS, -90), at(500)).is_none());
+        assert!(monitor.on_scan_result(&result(ADDRESS, -90), at(1000)).is_none());
+        assert!(monitor.on_scan_result(&result(ADDRESS, -90), at(2000)).is_none());
+        assert!(monitor.on_scan_result(&result(ADDRESS, -90), at(3500)).is_none());
+        // Not sampled either, so the device isn't lost before the next sample.
+        assert!(monitor.on_scan_result(&result(ADDRESS, -90), at(4000)).is_none());
+        assert!(is_lost(monitor.on_scan_result(&result(ADDRESS, -90), at(4500))));
+    }
+}
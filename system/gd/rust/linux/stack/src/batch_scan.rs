@@ -0,0 +1,255 @@
+//! Batching of scan results, either stored by the controller (batch scan of the Android HCI
+//! extension) or buffered in the host when the controller can't store them.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use bt_topshim::btif::RawAddress;
+use bt_topshim::profiles::gatt::LePhy;
+use bt_utils::adv_parser;
+
+use crate::bluetooth_gatt::{BatchScanMode, ScanResult};
+
+/// Length of a truncated record: address, address type, TX power, RSSI and timestamp.
+const TRUNCATED_RECORD_LEN: usize = 11;
+
+/// Length of the fields of a full record preceding the advertisement data, which are the fields
+/// of a truncated record.
+const FULL_RECORD_HEADER_LEN: usize = TRUNCATED_RECORD_LEN;
+
+/// Fill percentage of the controller storage at which the controller notifies the host.
+pub(crate) const NOTIFY_THRESHOLD_PERCENT: i32 = 95;
+
+/// Discard rule of the controller storage that discards the oldest results when it's full.
+pub(crate) const DISCARD_OLDEST: i32 = 0;
+
+/// Default scan interval of the controller storage, in units of 0.625 ms. Batching is meant for
+/// background scans, so it uses a low duty cycle.
+pub(crate) const DEFAULT_SCAN_INTERVAL: u16 = 8000;
+
+/// Default scan window of the controller storage, in units of 0.625 ms.
+pub(crate) const DEFAULT_SCAN_WINDOW: u16 = 800;
+
+/// Number of scan results buffered in the host for a scanner, after which the oldest ones are
+/// discarded like the controller does when its storage is full.
+const MAX_BUFFERED_RESULTS: usize = 1024;
+
+/// The batching requested by a scanner.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BatchScanSettings {
+    pub(crate) mode: BatchScanMode,
+    pub(crate) report_delay: Duration,
+    /// Scan interval of the controller storage, in units of 0.625 ms.
+    pub(crate) scan_interval: u16,
+    /// Scan window of the controller storage, in units of 0.625 ms.
+    pub(crate) scan_window: u16,
+}
+
+fn scan_result(
+    address: &[u8],
+    addr_type: u8,
+    tx_power: i8,
+    rssi: i8,
+    adv_data: Vec<u8>,
+) -> Option<ScanResult> {
+    // The address is stored least significant byte first.
+    let address: Vec<u8> = address.iter().rev().cloned().collect();
+    let address = RawAddress::from_bytes(&address)?;
+
+    Some(ScanResult {
+        name: adv_parser::extract_name(adv_data.as_slice()),
+        address: address.to_string(),
+        addr_type,
+        event_type: 0, /* not stored */
+        primary_phy: LePhy::Phy1m as u8,
+        secondary_phy: 0,      /* not stored */
+        advertising_sid: 0xff, /* not present */
+        tx_power,
+        rssi,
+        periodic_adv_int: 0, /* not stored */
+        flags: adv_parser::extract_flags(adv_data.as_slice()),
+        service_uuids: adv_parser::extract_service_uuids(adv_data.as_slice()),
+        service_data: adv_parser::extract_service_data(adv_data.as_slice()),
+        manufacturer_data: adv_parser::extract_manufacturer_data(adv_data.as_slice()),
+        adv_data,
+    })
+}
+
+/// Parses the |num_records| records of |data| read from the controller storage in
+/// |report_format|, and returns the scan results. Parsing stops at the first malformed record.
+pub(crate) fn parse_reports(
+    report_format: BatchScanMode,
+    num_records: usize,
+    data: &[u8],
+) -> Vec<ScanResult> {
+    let mut results = vec![];
+    let mut rest = data;
+
+    while results.len() < num_records && rest.len() >= TRUNCATED_RECORD_LEN {
+        let (address, addr_type, tx_power, rssi) =
+            (&rest[0..6], rest[6], rest[7] as i8, rest[8] as i8);
+
+        let adv_data = match report_format {
+            BatchScanMode::Truncated => {
+                rest = &rest[TRUNCATED_RECORD_LEN..];
+                vec![]
+            }
+            BatchScanMode::Full => {
+                // The advertisement and the scan response are each preceded by their length.
+                let mut adv_data = vec![];
+                let mut offset = FULL_RECORD_HEADER_LEN;
+                for _ in 0..2 {
+                    let len = match rest.get(offset) {
+                        Some(&len) => usize::from(len),
+                        None => return results,
+                    };
+                    match rest.get(offset + 1..offset + 1 + len) {
+                        Some(data) => adv_data.extend_from_slice(data),
+                        None => return results,
+                    }
+                    offset += 1 + len;
+                }
+                rest = &rest[offset..];
+                adv_data
+            }
+        };
+
+        match scan_result(address, addr_type, tx_power, rssi, adv_data) {
+            Some(result) => results.push(result),
+            None => break,
+        }
+    }
+
+    if results.len() < num_records {
+        log::warn!("Parsed {} of the {} batch scan records", results.len(), num_records);
+    }
+    results
+}
+
+/// Buffers the scan results of a scanner in the host, for controllers that can't store them.
+pub(crate) struct BatchScanBuffer {
+    mode: BatchScanMode,
+    results: VecDeque<ScanResult>,
+}
+
+impl BatchScanBuffer {
+    pub(crate) fn new(mode: BatchScanMode) -> BatchScanBuffer {
+        BatchScanBuffer { mode, results: VecDeque::new() }
+    }
+
+    /// Buffers |result|, keeping only what the controller would store in the buffer's mode.
+    pub(crate) fn push(&mut self, result: &ScanResult) {
+        let result = match self.mode {
+            BatchScanMode::Full => result.clone(),
+            BatchScanMode::Truncated => ScanResult {
+                address: result.address.clone(),
+                addr_type: result.addr_type,
+                primary_phy: LePhy::Phy1m as u8,
+                advertising_sid: 0xff,
+                tx_power: result.tx_power,
+                rssi: result.rssi,
+                ..Default::default()
+            },
+        };
+
+        if self.results.len() == MAX_BUFFERED_RESULTS {
+            self.results.pop_front();
+        }
+        self.results.push_back(result);
+    }
+
+    /// Returns the buffered scan results, oldest first, and empties the buffer.
+    pub(crate) fn take(&mut self) -> Vec<ScanResult> {
+        self.results.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "11:22:33:44:55:66";
+    const ADDRESS_LSB_FIRST: [u8; 6] = [0x66, 0x55, 0x44, 0x33, 0x22, 0x11];
+
+    fn record_header(rssi: i8) -> Vec<u8> {
+        [&ADDRESS_LSB_FIRST[..], &[1, 0xf6, rssi as u8, 0x10, 0x00]].concat()
+    }
+
+    #[test]
+    fn test_parse_truncated_reports() {
+        let data = [record_header(-40), record_header(-70)].concat();
+
+        let results = parse_reports(BatchScanMode::Truncated, 2, &data);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].address, ADDRESS);
+        assert_eq!(results[0].addr_type, 1);
+        assert_eq!(results[0].tx_power, -10);
+        assert_eq!(results[0].rssi, -40);
+        assert!(results[0].adv_data.is_empty());
+        assert_eq!(results[1].rssi, -70);
+
+        // A record cut short is dropped.
+        assert_eq!(parse_reports(BatchScanMode::Truncated, 2, &data[..20]).len(), 1);
+    }
+
+    #[test]
+    fn test_parse_full_reports() {
+        let name = [0x04, 0x09, b'a', b'b', b'c'];
+        let flags = [0x02, 0x01, 0x06];
+        let data = [
+            record_header(-50),
+            vec![name.len() as u8],
+            name.to_vec(),
+            vec![flags.len() as u8],
+            flags.to_vec(),
+            record_header(-60),
+            vec![0, 0],
+        ]
+        .concat();
+
+        let results = parse_reports(BatchScanMode::Full, 2, &data);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].address, ADDRESS);
+        assert_eq!(results[0].rssi, -50);
+        assert_eq!(results[0].name, "abc");
+        assert_eq!(results[0].flags, 0x06);
+        assert_eq!(results[0].adv_data, [&name[..], &flags[..]].concat());
+        assert_eq!(results[1].rssi, -60);
+        assert!(results[1].adv_data.is_empty());
+
+        // The scan response of the last record is missing.
+        assert_eq!(parse_reports(BatchScanMode::Full, 2, &data[..data.len() - 1]).len(), 1);
+    }
+
+    #[test]
+    fn test_buffer() {
+        let result = ScanResult {
+            name: String::from("abc"),
+            address: String::from(ADDRESS),
+            rssi: -50,
+            adv_data: vec![0x04, 0x09, b'a', b'b', b'c'],
+            ..Default::default()
+        };
+
+        let mut full = BatchScanBuffer::new(BatchScanMode::Full);
+        let mut truncated = BatchScanBuffer::new(BatchScanMode::Truncated);
+        for i in 0..MAX_BUFFERED_RESULTS + 2 {
+            let result = ScanResult { rssi: -((i % 100) as i8), ..result.clone() };
+            full.push(&result);
+            truncated.push(&result);
+        }
+
+        let results = full.take();
+        assert_eq!(results.len(), MAX_BUFFERED_RESULTS);
+        // The oldest results are discarded.
+        assert_eq!(results[0].rssi, -2);
+        assert_eq!(results[0].name, "abc");
+        assert!(full.take().is_empty());
+
+        let results = truncated.take();
+        assert_eq!(results[0].address, ADDRESS);
+        assert_eq!(results[0].rssi, -2);
+        assert!(results[0].name.is_empty());
+        assert!(results[0].adv_data.is_empty());
+    }
+}
@@ -2,10 +2,10 @@
 
 use bt_topshim::btif::{
     BaseCallbacks, BaseCallbacksDispatcher, BluetoothInterface, BluetoothProperty, BtAclState,
-    BtBondState, BtConnectionDirection, BtConnectionState, BtDeviceType, BtDiscMode,
-    BtDiscoveryState, BtHciErrorCode, BtPinCode, BtPropertyType, BtScanMode, BtSspVariant, BtState,
-    BtStatus, BtTransport, BtVendorProductInfo, DisplayAddress, RawAddress, ToggleableProfile,
-    Uuid, Uuid128Bit,
+    BtActivityEnergyInfo, BtBondState, BtConnectionDirection, BtConnectionState, BtDeviceType,
+    BtDiscMode, BtDiscoveryState, BtGapConfiguration, BtHciErrorCode, BtOobData, BtPinCode,
+    BtPropertyType, BtRemoteSecurityInfo, BtScanMode, BtSspVariant, BtState, BtStatus, BtTransport,
+    BtVendorProductInfo, DisplayAddress, OobData, RawAddress, ToggleableProfile, Uuid, Uuid128Bit,
 };
 use bt_topshim::{
     metrics,
@@ -23,11 +23,12 @@ use bt_utils::array_utils;
 use bt_utils::cod::{is_cod_hid_combo, is_cod_hid_keyboard};
 use btif_macros::{btif_callback, btif_callbacks_dispatcher};
 
-use log::{debug, warn};
+use log::{debug, info, warn};
+use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::cast::ToPrimitive;
 use num_traits::pow;
-use std::collections::HashMap;
-use std::convert::TryInto;
+use std::collections::{HashMap, HashSet};
+use std::convert::{TryFrom, TryInto};
 use std::fs::File;
 use std::hash::Hash;
 use std::io::Write;
@@ -44,6 +45,12 @@ use crate::bluetooth_admin::{BluetoothAdmin, IBluetoothAdmin};
 use crate::bluetooth_gatt::{BluetoothGatt, IBluetoothGatt, IScannerCallback, ScanResult};
 use crate::bluetooth_media::{BluetoothMedia, IBluetoothMedia, MediaActions};
 use crate::callbacks::Callbacks;
+use crate::connection_policy::{
+    self, ConnectionPolicies, ConnectionPolicy, DeviceConnectionPolicy,
+};
+use crate::device_cache::{self, DeviceCache, RecentDevice};
+use crate::le_privacy::{LePrivacyConfig, LePrivacySettings};
+use crate::scan_params::{ScanParameterProfile, ScanParameterSettings};
 use crate::uuid::{Profile, UuidHelper, HOGP};
 use crate::{Message, RPCProxy, SuspendMode};
 
@@ -56,6 +63,9 @@ const MIN_ADV_INSTANCES_FOR_MULTI_ADV: u8 = 5;
 /// clear event should be sent to clients.
 const FOUND_DEVICE_FRESHNESS: Duration = Duration::from_secs(30);
 
+/// Delay before reconnecting the devices with auto-connect profiles after the adapter is enabled.
+const AUTO_RECONNECT_ON_ENABLE_DELAY: Duration = Duration::from_secs(2);
+
 /// This is the value returned from Bluetooth Interface calls.
 // TODO(241930383): Add enum to topshim
 const BTM_SUCCESS: i32 = 0;
@@ -120,9 +130,13 @@ pub trait IBluetooth {
     /// Returns whether LE extended advertising is supported.
     fn is_le_extended_advertising_supported(&self) -> bool;
 
-    /// Starts BREDR Inquiry.
+    /// Starts discovery on all transports without filtering the found devices.
     fn start_discovery(&mut self) -> bool;
 
+    /// Starts discovery on the transports selected by |filter|. Only the found devices matching
+    /// it are reported through `IBluetoothCallback::on_device_found`.
+    fn start_discovery_with_filter(&mut self, filter: DiscoveryFilter) -> bool;
+
     /// Cancels BREDR Inquiry.
     fn cancel_discovery(&mut self) -> bool;
 
@@ -135,6 +149,20 @@ pub trait IBluetooth {
     /// Initiates pairing to a remote device. Triggers connection if not already started.
     fn create_bond(&mut self, device: BluetoothDevice, transport: BtTransport) -> bool;
 
+    /// Initiates pairing to a remote device using out of band data received from it, e.g. over
+    /// NFC. Either of the P-192 and P-256 data may be left invalid if not available.
+    fn create_bond_out_of_band(
+        &mut self,
+        device: BluetoothDevice,
+        transport: BtTransport,
+        p192_data: BtOobData,
+        p256_data: BtOobData,
+    ) -> bool;
+
+    /// Generates local out of band data for the given transport, to be sent to a remote device.
+    /// The data is returned through `IBluetoothCallback::on_local_oob_data_generated`.
+    fn generate_local_oob_data(&self, transport: BtTransport) -> bool;
+
     /// Cancels any pending bond attempt on given device.
     fn cancel_bond_process(&self, device: BluetoothDevice) -> bool;
 
@@ -144,9 +172,20 @@ pub trait IBluetooth {
     /// Returns a list of known bonded devices.
     fn get_bonded_devices(&self) -> Vec<BluetoothDevice>;
 
+    /// Returns the devices that were seen recently but aren't bonded, including those seen before
+    /// the daemon restarted, the most recently seen first.
+    fn get_recent_devices(&self) -> Vec<RecentDevice>;
+
+    /// Forgets the recently seen devices, including those persisted to disk.
+    fn clear_recent_devices(&mut self);
+
     /// Gets the bond state of a single device.
     fn get_bond_state(&self, device: BluetoothDevice) -> BtBondState;
 
+    /// Returns how a remote device is bonded and how its current links are secured, without
+    /// revealing any key.
+    fn get_remote_security_info(&self, device: BluetoothDevice) -> RemoteSecurityInfo;
+
     /// Set pin on bonding device.
     fn set_pin(&self, device: BluetoothDevice, accept: bool, pin_code: Vec<u8>) -> bool;
 
@@ -198,7 +237,8 @@ pub trait IBluetooth {
     /// Triggers SDP and searches for a specific UUID on a remote device.
     fn sdp_search(&self, device: BluetoothDevice, uuid: Uuid128Bit) -> bool;
 
-    /// Creates a new SDP record.
+    /// Creates a new SDP record. Services without a dedicated record type can use a
+    /// `BtSdpRecord::Custom` record built from their own attributes.
     fn create_sdp_record(&mut self, sdp_record: BtSdpRecord) -> bool;
 
     /// Removes the SDP record associated with the provided handle.
@@ -210,6 +250,63 @@ pub trait IBluetooth {
     /// Disconnect all profiles supported by device and enabled on adapter.
     fn disconnect_all_enabled_profiles(&mut self, device: BluetoothDevice) -> bool;
 
+    /// Returns the connection policy of a remote device.
+    fn get_connection_policy(&self, device: BluetoothDevice) -> DeviceConnectionPolicy;
+
+    /// Sets the connection policy of a remote device. It decides which profiles
+    /// `connect_all_enabled_profiles` connects, in which order, and which of them are reconnected
+    /// automatically.
+    fn set_connection_policy(
+        &mut self,
+        device: BluetoothDevice,
+        policy: DeviceConnectionPolicy,
+    ) -> bool;
+
+    /// Requests the activity and energy counters of the controller. They are returned through
+    /// `IBluetoothCallback::on_energy_info`.
+    fn request_energy_info(&self) -> bool;
+
+    /// Enables or disables the link quality reports of the controller, which are returned through
+    /// `IBluetoothCallback::on_link_quality_report`.
+    fn set_link_quality_report(&self, enable: bool, min_report_interval_ms: u16) -> bool;
+
+    /// Returns how the local address is made private when LE privacy is enabled.
+    fn get_le_privacy_config(&self) -> LePrivacyConfig;
+
+    /// Sets how the local address is made private when LE privacy is enabled. Returns false if
+    /// the rotation timeout is out of bounds.
+    fn set_le_privacy_config(&mut self, config: LePrivacyConfig) -> bool;
+
+    /// Returns the LE identity address of the local adapter, which remote devices resolve its
+    /// private addresses to.
+    fn get_le_identity_address(&self) -> String;
+
+    /// Returns the local Identity Resolving Key, or an empty list if it isn't available.
+    fn get_local_irk(&self) -> Vec<u8>;
+
+    /// Regenerates the local Identity Resolving Key. Remote devices can't resolve the local
+    /// address with the previous key anymore, so the LE-only bonds are removed first and the key
+    /// is regenerated once they all are. Since removing the bond of a dual-mode device would also
+    /// remove its BR/EDR keys, the key isn't regenerated while any is bonded. Returns false if
+    /// the request is rejected, otherwise its outcome is reported through
+    /// `IBluetoothCallback::on_local_irk_regenerated`.
+    fn regenerate_local_irk(&mut self) -> bool;
+
+    /// Returns the configuration of the GAP service served by the GATT servers.
+    fn get_gap_configuration(&self) -> GapConfiguration;
+
+    /// Configures the GAP service served by the GATT servers, which serves the adapter name along
+    /// with this configuration. Returns false if the preferred connection parameters are invalid.
+    fn set_gap_configuration(&mut self, config: GapConfiguration) -> bool;
+
+    /// Returns the profile of the page scan, inquiry scan and LE background scan parameters.
+    fn get_scan_parameter_profile(&self) -> ScanParameterProfile;
+
+    /// Selects the profile of the page scan, inquiry scan and LE background scan parameters. The
+    /// LE background scan uses it the next time it's restarted. While the system is suspended,
+    /// the profile is applied on resume.
+    fn set_scan_parameter_profile(&mut self, profile: ScanParameterProfile) -> bool;
+
     /// Returns whether WBS is supported.
     fn is_wbs_supported(&self) -> bool;
 
@@ -255,14 +352,301 @@ pub trait IBluetoothQALegacy {
     fn send_hid_data(&mut self, addr: String, data: String) -> BtStatus;
 }
 
+/// Options of a discovery started with `IBluetooth::start_discovery_with_filter`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscoveryFilter {
+    /// Transports to discover on. `Auto` interleaves BR/EDR inquiry with LE scanning.
+    pub transport: BtTransport,
+    /// How long to discover for, in milliseconds. 0 uses the default duration. Since the length
+    /// of an inquiry is fixed by the stack, longer durations only apply to LE only discovery.
+    pub duration_ms: u32,
+    /// Only report devices received with at least this RSSI.
+    pub rssi_threshold: Option<i8>,
+    /// Only report devices exposing any of these services. Empty reports all devices.
+    pub uuids: Vec<Uuid128Bit>,
+    /// Only report devices of this major device class (bits 8-12 of the class of device).
+    pub major_device_class: Option<u8>,
+}
+
+impl Default for DiscoveryFilter {
+    fn default() -> Self {
+        DiscoveryFilter {
+            transport: BtTransport::Auto,
+            duration_ms: 0,
+            rssi_threshold: None,
+            uuids: vec![],
+            major_device_class: None,
+        }
+    }
+}
+
+/// Peripheral Preferred Connection Parameters served by the GAP service, in controller units.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PreferredConnectionParameters {
+    /// Minimum connection interval, in units of 1.25ms. 0xFFFF for no specific value.
+    pub min_interval: u16,
+    /// Maximum connection interval, in units of 1.25ms. 0xFFFF for no specific value.
+    pub max_interval: u16,
+    /// Peripheral latency, in connection events.
+    pub peripheral_latency: u16,
+    /// Supervision timeout, in units of 10ms. 0xFFFF for no specific value.
+    pub supervision_timeout: u16,
+}
+
+impl PreferredConnectionParameters {
+    const NO_SPECIFIC_VALUE: u16 = 0xFFFF;
+
+    fn is_valid(&self) -> bool {
+        self.min_interval == Self::NO_SPECIFIC_VALUE
+            || self.max_interval == Self::NO_SPECIFIC_VALUE
+            || self.min_interval <= self.max_interval
+    }
+}
+
+/// Configuration of the GAP service served by the GATT servers, besides the device name which is
+/// always the adapter name.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GapConfiguration {
+    /// Whether remote devices may read the device name.
+    pub device_name_readable: bool,
+    /// Whether remote devices may write the device name.
+    pub device_name_writable: bool,
+    /// Whether a name written by a remote device renames the adapter persistently. Otherwise it
+    /// is only reported through `IBluetoothCallback::on_name_changed`.
+    pub persist_written_device_name: bool,
+    /// Appearance of the adapter (Assigned Numbers 2.6).
+    pub appearance: u16,
+    /// Whether remote devices may write the appearance.
+    pub appearance_writable: bool,
+    /// If set, the Peripheral Preferred Connection Parameters characteristic is served.
+    pub preferred_connection_parameters: Option<PreferredConnectionParameters>,
+    /// If set, the Central Address Resolution characteristic is served with this value.
+    pub central_address_resolution: Option<bool>,
+    /// Whether the Resolvable Private Address Only characteristic is served.
+    pub rpa_only: bool,
+}
+
+impl DiscoveryFilter {
+    fn includes_transport(&self, transport: BtTransport) -> bool {
+        self.transport == BtTransport::Auto || self.transport == transport
+    }
+
+    /// How long a discovery with this filter lasts.
+    fn duration(&self) -> Duration {
+        let default_duration = Duration::from_millis(DEFAULT_DISCOVERY_TIMEOUT_MS);
+        match self.duration_ms {
+            0 => default_duration,
+            ms if self.transport == BtTransport::Le => Duration::from_millis(ms.into()),
+            ms => std::cmp::min(Duration::from_millis(ms.into()), default_duration),
+        }
+    }
+
+    /// Whether the discovery has to be stopped once its duration elapses. A discovery including
+    /// BR/EDR of the default duration ends with the inquiry of the stack instead.
+    fn needs_timeout(&self) -> bool {
+        self.transport == BtTransport::Le
+            || self.duration() < Duration::from_millis(DEFAULT_DISCOVERY_TIMEOUT_MS)
+    }
+
+    /// Whether a found device with the given properties should be reported.
+    fn matches(&self, properties: &HashMap<BtPropertyType, BluetoothProperty>) -> bool {
+        match (self.transport, properties.get(&BtPropertyType::TypeOfDevice)) {
+            (BtTransport::Bredr, Some(BluetoothProperty::TypeOfDevice(BtDeviceType::Ble)))
+            | (BtTransport::Le, Some(BluetoothProperty::TypeOfDevice(BtDeviceType::Bredr))) => {
+                return false;
+            }
+            _ => (),
+        }
+
+        if let Some(threshold) = self.rssi_threshold {
+            match properties.get(&BtPropertyType::RemoteRssi) {
+                Some(BluetoothProperty::RemoteRssi(rssi)) if *rssi >= threshold => (),
+                _ => return false,
+            }
+        }
+
+        if !self.uuids.is_empty() {
+            match properties.get(&BtPropertyType::Uuids) {
+                Some(BluetoothProperty::Uuids(uuids))
+                    if uuids.iter().any(|uuid| self.uuids.contains(&uuid.uu)) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(major_device_class) = self.major_device_class {
+            match properties.get(&BtPropertyType::ClassOfDevice) {
+                Some(BluetoothProperty::ClassOfDevice(cod))
+                    if ((cod >> 8) & 0x1f) as u8 == major_device_class => {}
+                _ => return false,
+            }
+        }
+
+        true
+    }
+}
+
+/// Type of the BR/EDR link key of a bond.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum LinkKeyType {
+    /// Not bonded over BR/EDR.
+    None = 0,
+    /// Combination key of a legacy pairing with a PIN code.
+    Legacy,
+    /// Debug combination key, which isn't secret.
+    Debug,
+    UnauthenticatedP192,
+    AuthenticatedP192,
+    UnauthenticatedP256,
+    AuthenticatedP256,
+}
+
+impl LinkKeyType {
+    fn from_hci(key_type: u8) -> LinkKeyType {
+        match key_type {
+            0x00 | 0x01 | 0x02 | 0x06 => LinkKeyType::Legacy,
+            0x03 => LinkKeyType::Debug,
+            0x04 => LinkKeyType::UnauthenticatedP192,
+            0x05 => LinkKeyType::AuthenticatedP192,
+            0x07 => LinkKeyType::UnauthenticatedP256,
+            0x08 => LinkKeyType::AuthenticatedP256,
+            _ => LinkKeyType::None,
+        }
+    }
+}
+
+/// Security level of the current links to a remote device, the highest of both transports.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, PartialOrd)]
+#[repr(u32)]
+pub enum SecurityLevel {
+    /// Not connected or not encrypted.
+    None = 0,
+    /// Encrypted with a key from an unauthenticated pairing.
+    Unauthenticated,
+    /// Encrypted with a key from a MITM protected pairing.
+    Authenticated,
+    /// Encrypted with a key from a MITM protected Secure Connections pairing. LE links are
+    /// reported as `Authenticated` since the stack doesn't tell LE Secure Connections apart.
+    AuthenticatedSecureConnections,
+}
+
+/// How a remote device is bonded and how its current links are secured.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemoteSecurityInfo {
+    pub link_key_type: LinkKeyType,
+    /// LE keys distributed by the remote device.
+    pub le_ltk_present: bool,
+    pub le_irk_present: bool,
+    pub le_csrk_present: bool,
+    /// Encryption key size of the current link in bytes, 0 if it isn't encrypted.
+    pub encryption_key_size: u8,
+    /// Whether the keys of one transport were derived from the bond over the other one.
+    pub is_ctkd: bool,
+    pub security_level: SecurityLevel,
+}
+
+impl Default for RemoteSecurityInfo {
+    fn default() -> Self {
+        RemoteSecurityInfo {
+            link_key_type: LinkKeyType::None,
+            le_ltk_present: false,
+            le_irk_present: false,
+            le_csrk_present: false,
+            encryption_key_size: 0,
+            is_ctkd: false,
+            security_level: SecurityLevel::None,
+        }
+    }
+}
+
+impl From<BtRemoteSecurityInfo> for RemoteSecurityInfo {
+    fn from(info: BtRemoteSecurityInfo) -> Self {
+        let link_key_type = LinkKeyType::from_hci(info.link_key_type);
+
+        let bredr_level = match (info.bredr_encrypted, info.bredr_authenticated) {
+            (false, _) => SecurityLevel::None,
+            (true, false) => SecurityLevel::Unauthenticated,
+            (true, true) if link_key_type == LinkKeyType::AuthenticatedP256 => {
+                SecurityLevel::AuthenticatedSecureConnections
+            }
+            (true, true) => SecurityLevel::Authenticated,
+        };
+        let le_level = match (info.le_encrypted, info.le_authenticated) {
+            (false, _) => SecurityLevel::None,
+            (true, false) => SecurityLevel::Unauthenticated,
+            (true, true) => SecurityLevel::Authenticated,
+        };
+
+        RemoteSecurityInfo {
+            link_key_type,
+            le_ltk_present: info.le_ltk_present,
+            le_irk_present: info.le_irk_present,
+            le_csrk_present: info.le_csrk_present,
+            encryption_key_size: info.encryption_key_size,
+            is_ctkd: info.is_ctkd,
+            security_level: if bredr_level > le_level { bredr_level } else { le_level },
+        }
+    }
+}
+
+/// Returns the type of a device known to be of type |known| that was found as |found|, i.e. dual
+/// mode if it was found on both transports.
+fn merge_device_types(known: BtDeviceType, found: BtDeviceType) -> BtDeviceType {
+    match (known, found) {
+        (BtDeviceType::Unknown, found) => found,
+        (known, BtDeviceType::Unknown) => known,
+        (known, found) if known == found => known,
+        _ => BtDeviceType::Dual,
+    }
+}
+
+/// Marks the device with |address| as bonded, under the address it's tracked under if it was
+/// consolidated. A device already known, e.g. from the consolidation, keeps its context.
+fn add_bonded_device(
+    bonded_devices: &mut HashMap<String, BluetoothDeviceContext>,
+    found_devices: &mut HashMap<String, BluetoothDeviceContext>,
+    address_aliases: &HashMap<String, String>,
+    address: &str,
+) {
+    let address = address_aliases.get(address).cloned().unwrap_or_else(|| address.to_string());
+
+    let mut device = bonded_devices
+        .remove(&address)
+        .or_else(|| found_devices.remove(&address))
+        .unwrap_or_else(|| {
+            BluetoothDeviceContext::new(
+                BtBondState::Bonded,
+                BtAclState::Disconnected,
+                BluetoothDevice::new(address.clone(), "".to_string()),
+                Instant::now(),
+                vec![],
+            )
+        });
+    device.bond_state = BtBondState::Bonded;
+    bonded_devices.insert(address, device);
+}
+
 /// Delayed actions from adapter events.
 pub enum DelayedActions {
     /// Check whether the current set of found devices are still fresh.
     DeviceFreshnessCheck,
 
+    /// The duration of the ongoing discovery has elapsed.
+    DiscoveryTimeout,
+
     /// Connect to all supported profiles on target device.
     ConnectAllProfiles(BluetoothDevice),
 
+    /// Reconnect the profiles reconnected automatically of a device that lost its link.
+    ReconnectDevice(BluetoothDevice),
+
+    /// Reconnect the profiles reconnected automatically of all bonded devices.
+    ReconnectAutoConnectDevices,
+
+    /// Reconnect all the profiles of the devices connected before suspend, then the profiles
+    /// reconnected automatically of the other bonded devices.
+    ReconnectOnResume(Vec<BluetoothDevice>),
+
     /// Scanner for BLE discovery is registered with given status and scanner id.
     BleDiscoveryScannerRegistered(Uuid128Bit, u8, GattStatus),
 
@@ -302,6 +686,20 @@ impl BluetoothDevice {
     }
 }
 
+/// A link quality report of the controller for an ACL connection.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LinkQualityReport {
+    /// Time of the report, in milliseconds since boot.
+    pub timestamp_ms: u64,
+    /// Why the report was sent, i.e. the Bluetooth Quality Report ID (e.g. A2DP audio choppy).
+    pub report_id: i32,
+    pub rssi: i32,
+    pub snr: i32,
+    pub retransmission_count: i32,
+    pub packets_not_receive_count: i32,
+    pub negative_acknowledgement_count: i32,
+}
+
 /// Internal data structure that keeps a map of cached properties for a remote device.
 struct BluetoothDeviceContext {
     /// Transport type reported by ACL connection (if completed).
@@ -309,6 +707,9 @@ struct BluetoothDeviceContext {
 
     pub acl_state: BtAclState,
     pub bond_state: BtBondState,
+    /// The other address of the same device once the stack associated both: the LE address of a
+    /// dual-mode device, or the identity address of an LE-only device.
+    pub consolidated_address: Option<String>,
     pub info: BluetoothDevice,
     pub last_seen: Instant,
     pub properties: HashMap<BtPropertyType, BluetoothProperty>,
@@ -333,6 +734,7 @@ impl BluetoothDeviceContext {
             acl_reported_transport: BtTransport::Auto,
             acl_state,
             bond_state,
+            consolidated_address: None,
             info,
             last_seen,
             properties: HashMap::new(),
@@ -368,6 +770,32 @@ impl BluetoothDeviceContext {
     pub(crate) fn seen(&mut self) {
         self.last_seen = Instant::now();
     }
+
+    /// Merges the state tracked under another address of the same device. Properties already
+    /// known for this device take precedence, while bond and connection state are those of the
+    /// most advanced transport.
+    pub(crate) fn merge(&mut self, other: BluetoothDeviceContext) {
+        for (prop_type, prop) in other.properties {
+            if prop_type != BtPropertyType::BdAddr {
+                self.properties.entry(prop_type).or_insert(prop);
+            }
+        }
+        if self.info.name.is_empty() {
+            self.info.name = other.info.name;
+        }
+        if other.bond_state > self.bond_state {
+            self.bond_state = other.bond_state;
+        }
+        if other.acl_state == BtAclState::Connected {
+            self.acl_state = other.acl_state;
+            self.acl_reported_transport = other.acl_reported_transport;
+        }
+        if other.last_seen > self.last_seen {
+            self.last_seen = other.last_seen;
+        }
+        self.services_resolved |= other.services_resolved;
+        self.wait_to_connect |= other.wait_to_connect;
+    }
 }
 
 /// The interface for adapter callbacks registered through `IBluetooth::register_callback`.
@@ -411,7 +839,8 @@ pub trait IBluetoothCallback: RPCProxy {
     /// When a bonding attempt has completed.
     fn on_bond_state_changed(&mut self, status: u32, device_address: String, state: u32);
 
-    /// When an SDP search has completed.
+    /// When an SDP search has completed. Records of services without a dedicated record type are
+    /// reported as `BtSdpRecord::HeaderOverlay`, whose user1 data holds all their attributes.
     fn on_sdp_search_complete(
         &mut self,
         remote_device: BluetoothDevice,
@@ -421,6 +850,23 @@ pub trait IBluetoothCallback: RPCProxy {
 
     /// When an SDP record has been successfully created.
     fn on_sdp_record_created(&mut self, record: BtSdpRecord, handle: i32);
+
+    /// When local out of band data has been generated. The data is invalid if generation failed.
+    fn on_local_oob_data_generated(&mut self, transport: BtTransport, data: BtOobData);
+
+    /// When the activity and energy counters of the controller have been read.
+    fn on_energy_info(&mut self, info: BtActivityEnergyInfo);
+
+    /// When the controller reports the quality of the link to a remote device.
+    fn on_link_quality_report(&mut self, remote_device: BluetoothDevice, report: LinkQualityReport);
+
+    /// When a regeneration of the local IRK requested with `IBluetooth::regenerate_local_irk`
+    /// completes. On failure, |blocking_devices| lists the bonded devices that prevented it.
+    fn on_local_irk_regenerated(
+        &mut self,
+        status: BtStatus,
+        blocking_devices: Vec<BluetoothDevice>,
+    );
 }
 
 /// An interface for other modules to track found remote devices.
@@ -437,6 +883,10 @@ pub trait IBluetoothDeviceCallback {
         remote_device: BluetoothDevice,
         properties: Vec<BluetoothProperty>,
     );
+
+    /// When |secondary_address| is found to belong to the same device as |remote_device|, which
+    /// tracks both addresses from then on.
+    fn on_device_consolidated(&mut self, remote_device: BluetoothDevice, secondary_address: String);
 }
 
 pub trait IBluetoothConnectionCallback: RPCProxy {
@@ -452,6 +902,9 @@ pub struct Bluetooth {
     intf: Arc<Mutex<BluetoothInterface>>,
 
     adapter_index: i32,
+    /// Secondary addresses of consolidated devices, mapped to the address the device is tracked
+    /// under.
+    address_aliases: HashMap<String, String>,
     bonded_devices: HashMap<String, BluetoothDeviceContext>,
     ble_scanner_id: Option<u8>,
     ble_scanner_uuid: Option<Uuid128Bit>,
@@ -460,19 +913,34 @@ pub struct Bluetooth {
     bluetooth_media: Arc<Mutex<Box<BluetoothMedia>>>,
     callbacks: Callbacks<dyn IBluetoothCallback + Send>,
     connection_callbacks: Callbacks<dyn IBluetoothConnectionCallback + Send>,
+    connection_policies: ConnectionPolicies,
+    device_cache: DeviceCache,
     discovering_started: Instant,
+    discovery_filter: DiscoveryFilter,
+    /// Devices already reported to clients during the ongoing discovery.
+    discovery_reported: HashSet<String>,
+    discovery_timeout: Option<JoinHandle<()>>,
     hh: Option<HidHost>,
     is_connectable: bool,
     is_discovering: bool,
     is_discovering_before_suspend: bool,
     is_discovery_paused: bool,
     discovery_suspend_mode: SuspendMode,
+    gap_configuration: GapConfiguration,
+    /// LE bonds still being removed before the local IRK is regenerated.
+    irk_regeneration_pending: Option<HashSet<String>>,
+    le_privacy_settings: LePrivacySettings,
     local_address: Option<RawAddress>,
     pending_discovery: bool,
     properties: HashMap<BtPropertyType, BluetoothProperty>,
     profiles_ready: bool,
     found_devices: HashMap<String, BluetoothDeviceContext>,
     freshness_check: Option<JoinHandle<()>>,
+    /// Devices being reconnected after link loss, with the number of the ongoing attempt.
+    reconnects: HashMap<String, (u32, JoinHandle<()>)>,
+    scan_parameter_settings: ScanParameterSettings,
+    /// Whether the suspend profile of the scan parameters is in use.
+    scan_parameters_suspended: bool,
     sdp: Option<Sdp>,
     state: BtState,
     tx: Sender<Message>,
@@ -493,9 +961,14 @@ impl Bluetooth {
         bluetooth_admin: Arc<Mutex<Box<BluetoothAdmin>>>,
         bluetooth_gatt: Arc<Mutex<Box<BluetoothGatt>>>,
         bluetooth_media: Arc<Mutex<Box<BluetoothMedia>>>,
+        device_cache: DeviceCache,
+        connection_policies: ConnectionPolicies,
+        le_privacy_settings: LePrivacySettings,
+        scan_parameter_settings: ScanParameterSettings,
     ) -> Bluetooth {
         Bluetooth {
             adapter_index,
+            address_aliases: HashMap::new(),
             bonded_devices: HashMap::new(),
             callbacks: Callbacks::new(tx.clone(), Message::AdapterCallbackDisconnected),
             connection_callbacks: Callbacks::new(
@@ -508,19 +981,30 @@ impl Bluetooth {
             bluetooth_admin,
             bluetooth_gatt,
             bluetooth_media,
+            connection_policies,
+            device_cache,
             discovering_started: Instant::now(),
+            discovery_filter: DiscoveryFilter::default(),
+            discovery_reported: HashSet::new(),
+            discovery_timeout: None,
             intf,
             is_connectable: false,
             is_discovering: false,
             is_discovering_before_suspend: false,
             is_discovery_paused: false,
             discovery_suspend_mode: SuspendMode::Normal,
+            gap_configuration: GapConfiguration::default(),
+            irk_regeneration_pending: None,
+            le_privacy_settings,
             local_address: None,
             pending_discovery: false,
             properties: HashMap::new(),
             profiles_ready: false,
             found_devices: HashMap::new(),
             freshness_check: None,
+            reconnects: HashMap::new(),
+            scan_parameter_settings,
+            scan_parameters_suspended: false,
             sdp: None,
             state: BtState::Off,
             tx,
@@ -530,6 +1014,53 @@ impl Bluetooth {
         }
     }
 
+    /// Regenerates the local IRK once the last LE bond waiting on it is removed, or gives up if
+    /// removing one fails.
+    fn continue_irk_regeneration(&mut self, address: &String, bond_state: &BtBondState) {
+        let pending = match self.irk_regeneration_pending.as_mut() {
+            Some(pending) if pending.contains(address) => pending,
+            _ => return,
+        };
+
+        match bond_state {
+            BtBondState::NotBonded => {
+                pending.remove(address);
+                if !pending.is_empty() {
+                    return;
+                }
+
+                self.irk_regeneration_pending = None;
+                self.finish_irk_regeneration();
+            }
+            BtBondState::Bonded => {
+                warn!("Failed to unbond {}, keeping the local IRK", address);
+                self.irk_regeneration_pending = None;
+                let device = self
+                    .get_remote_device_info_if_found(address)
+                    .unwrap_or_else(|| BluetoothDevice::new(address.clone(), String::new()));
+                self.report_irk_regenerated(BtStatus::Fail, vec![device]);
+            }
+            _ => (),
+        }
+    }
+
+    /// Regenerates the local IRK once no bond uses it anymore.
+    fn finish_irk_regeneration(&mut self) {
+        let status = if self.intf.lock().unwrap().regenerate_local_irk() == BTM_SUCCESS {
+            BtStatus::Success
+        } else {
+            warn!("Failed to regenerate the local IRK");
+            BtStatus::Fail
+        };
+        self.report_irk_regenerated(status, vec![]);
+    }
+
+    fn report_irk_regenerated(&mut self, status: BtStatus, blocking_devices: Vec<BluetoothDevice>) {
+        self.callbacks.for_all_callbacks(|callback| {
+            callback.on_local_irk_regenerated(status, blocking_devices.clone());
+        });
+    }
+
     fn disable_profile(&mut self, profile: &Profile) {
         if !UuidHelper::is_profile_supported(profile) {
             return;
@@ -658,6 +1189,7 @@ impl Bluetooth {
 
     fn update_local_address(&mut self, addr: &RawAddress) {
         self.local_address = Some(addr.clone());
+        self.device_cache.set_adapter_address(&addr.to_string());
 
         self.callbacks.for_all_callbacks(|callback| {
             callback.on_address_changed(addr.to_string());
@@ -672,127 +1204,459 @@ impl Bluetooth {
         self.connection_callbacks.remove_callback(id);
     }
 
+    /// Returns the address a device is tracked under, which differs from |address| if it's the
+    /// secondary address of a consolidated device.
+    fn resolve_address(&self, address: &str) -> String {
+        self.address_aliases.get(address).cloned().unwrap_or_else(|| address.to_string())
+    }
+
+    /// Returns all known addresses of the device with |address|, the one it's tracked under
+    /// first.
+    fn get_consolidated_addresses(&self, address: &str) -> Vec<String> {
+        let mut addresses = vec![self.resolve_address(address)];
+        if let Some(secondary) =
+            self.get_remote_device_if_found(address).and_then(|d| d.consolidated_address.clone())
+        {
+            addresses.push(secondary);
+        }
+        addresses
+    }
+
     fn get_remote_device_if_found(&self, address: &str) -> Option<&BluetoothDeviceContext> {
-        self.bonded_devices.get(address).or_else(|| self.found_devices.get(address))
+        let address = self.resolve_address(address);
+        self.bonded_devices.get(&address).or_else(|| self.found_devices.get(&address))
     }
 
     fn get_remote_device_if_found_mut(
         &mut self,
         address: &str,
     ) -> Option<&mut BluetoothDeviceContext> {
-        match self.bonded_devices.get_mut(address) {
-            None => self.found_devices.get_mut(address),
+        let address = self.resolve_address(address);
+        match self.bonded_devices.get_mut(&address) {
+            None => self.found_devices.get_mut(&address),
             some => some,
         }
     }
 
-    fn get_remote_device_info_if_found(&self, remote_address: &str) -> Option<BluetoothDevice> {
-        self.get_remote_device_if_found(remote_address)
-            .map(|device_context| device_context.info.clone())
-    }
-
-    fn get_remote_device_property(
-        &self,
-        device: &BluetoothDevice,
-        property_type: &BtPropertyType,
-    ) -> Option<BluetoothProperty> {
-        self.get_remote_device_if_found(&device.address)
-            .and_then(|d| d.properties.get(property_type).and_then(|p| Some(p.clone())))
-    }
+    /// Tracks the device known under |secondary_address| as the device with |address| from now
+    /// on, so both addresses of a dual-mode or LE device show up as a single device.
+    fn consolidate_device(&mut self, address: String, secondary_address: String, is_dual: bool) {
+        if address == secondary_address
+            || self.address_aliases.get(&secondary_address) == Some(&address)
+        {
+            return;
+        }
 
-    fn set_remote_device_property(
-        &mut self,
-        device: &BluetoothDevice,
-        property_type: BtPropertyType,
-        property: BluetoothProperty,
-    ) -> Result<(), ()> {
-        let remote_device = match self.get_remote_device_if_found_mut(&device.address) {
-            Some(d) => d,
-            None => {
-                return Err(());
+        let primary =
+            self.bonded_devices.remove(&address).or_else(|| self.found_devices.remove(&address));
+        let secondary = self
+            .bonded_devices
+            .remove(&secondary_address)
+            .or_else(|| self.found_devices.remove(&secondary_address));
+
+        let reported_secondary = secondary.as_ref().map(|d| d.info.clone());
+        let mut device = match (primary, secondary) {
+            (Some(mut primary), Some(secondary)) => {
+                primary.merge(secondary);
+                primary
             }
+            (Some(primary), None) => primary,
+            (None, Some(mut secondary)) => {
+                secondary.info.address = address.clone();
+                secondary.properties.remove(&BtPropertyType::BdAddr);
+                secondary
+            }
+            (None, None) => BluetoothDeviceContext::new(
+                BtBondState::NotBonded,
+                BtAclState::Disconnected,
+                BluetoothDevice::new(address.clone(), String::from("")),
+                Instant::now(),
+                vec![],
+            ),
         };
-
-        let mut addr = RawAddress::from_string(device.address.clone());
-        if addr.is_none() {
-            return Err(());
+        device.consolidated_address = Some(secondary_address.clone());
+        if is_dual {
+            device.update_properties(&vec![BluetoothProperty::TypeOfDevice(BtDeviceType::Dual)]);
         }
-        let addr = addr.as_mut().unwrap();
-
-        // TODO: Determine why a callback isn't invoked to do this.
-        remote_device.properties.insert(property_type, property.clone());
-        self.intf.lock().unwrap().set_remote_device_property(addr, property);
-        Ok(())
-    }
 
-    /// Returns whether the adapter is connectable.
-    pub(crate) fn get_connectable_internal(&self) -> bool {
-        match self.properties.get(&BtPropertyType::AdapterScanMode) {
-            Some(prop) => match prop {
-                BluetoothProperty::AdapterScanMode(mode) => match *mode {
-                    BtScanMode::Connectable | BtScanMode::ConnectableDiscoverable => true,
-                    _ => false,
-                },
-                _ => false,
-            },
-            _ => false,
+        let info = device.info.clone();
+        if device.bond_state == BtBondState::Bonded {
+            self.bonded_devices.insert(address.clone(), device);
+        } else {
+            self.found_devices.insert(address.clone(), device);
         }
-    }
+        self.address_aliases.insert(secondary_address.clone(), address.clone());
+        self.device_cache.remove(&secondary_address);
+        self.discovery_reported.remove(&secondary_address);
 
-    /// Sets the adapter's connectable mode for classic connections.
-    pub(crate) fn set_connectable_internal(&mut self, mode: bool) -> bool {
-        self.is_connectable = mode;
-        if mode && self.get_discoverable() {
-            return true;
+        // Clients drop the device they knew under the secondary address.
+        if let Some(secondary) = reported_secondary {
+            self.callbacks.for_all_callbacks(|callback| {
+                callback.on_device_cleared(secondary.clone());
+            });
         }
-        self.intf.lock().unwrap().set_adapter_property(BluetoothProperty::AdapterScanMode(
-            if mode { BtScanMode::Connectable } else { BtScanMode::None_ },
-        )) == 0
-    }
 
-    /// Returns adapter's discoverable mode.
-    pub fn get_discoverable_mode_internal(&self) -> BtDiscMode {
-        let off_mode = BtDiscMode::NonDiscoverable;
+        self.bluetooth_admin.lock().unwrap().on_device_consolidated(info, secondary_address);
+    }
 
-        match self.properties.get(&BtPropertyType::AdapterScanMode) {
-            Some(prop) => match prop {
-                BluetoothProperty::AdapterScanMode(mode) => match *mode {
-                    BtScanMode::ConnectableDiscoverable => BtDiscMode::GeneralDiscoverable,
-                    BtScanMode::ConnectableLimitedDiscoverable => BtDiscMode::LimitedDiscoverable,
-                    _ => off_mode,
-                },
-                _ => off_mode,
-            },
-            _ => off_mode,
+    /// Forgets the secondary address of the device tracked under |address|.
+    fn remove_consolidated_address(&mut self, address: &str) {
+        if let Some(secondary) =
+            self.get_remote_device_if_found_mut(address).and_then(|d| d.consolidated_address.take())
+        {
+            self.address_aliases.remove(&secondary);
         }
     }
 
-    /// Caches the discoverable mode into BluetoothQA.
-    pub fn cache_discoverable_mode_into_qa(&self) {
-        let disc_mode = self.get_discoverable_mode_internal();
-
-        let txl = self.tx.clone();
-        tokio::spawn(async move {
-            let _ = txl.send(Message::QaOnDiscoverableModeChanged(disc_mode)).await;
-        });
-    }
+    /// Records the current metadata of a found (i.e. not bonded) device in the device cache.
+    fn update_device_cache(&mut self, address: &str) {
+        let device = match self.found_devices.get(address) {
+            Some(d) => d,
+            None => return,
+        };
 
-    /// Returns all bonded and connected devices.
-    pub(crate) fn get_bonded_and_connected_devices(&mut self) -> Vec<BluetoothDevice> {
-        self.bonded_devices
-            .values()
-            .filter(|v| v.acl_state == BtAclState::Connected && v.bond_state == BtBondState::Bonded)
-            .map(|v| v.info.clone())
-            .collect()
+        let mut recent = RecentDevice {
+            device: device.info.clone(),
+            last_seen_ms: device_cache::now_ms(),
+            ..Default::default()
+        };
+        for prop in device.properties.values() {
+            match prop {
+                BluetoothProperty::Appearance(appearance) => recent.appearance = *appearance,
+                BluetoothProperty::ClassOfDevice(class) => recent.class = *class,
+                BluetoothProperty::Uuids(uuids) => {
+                    recent.uuids = uuids.iter().map(|uuid| uuid.uu).collect()
+                }
+                _ => (),
+            }
+        }
+        self.device_cache.update(recent);
     }
 
-    /// Gets the bond state of a single device with its address.
-    pub fn get_bond_state_by_addr(&self, addr: &String) -> BtBondState {
-        match self.bonded_devices.get(addr) {
-            Some(device) => device.bond_state.clone(),
-            None => BtBondState::NotBonded,
+    /// Connects the profiles supported by |device| and enabled on the adapter that its connection
+    /// policy allows, by priority. With |auto_connect_only|, only the profiles it reconnects
+    /// automatically are connected.
+    fn connect_profiles(&mut self, device: BluetoothDevice, auto_connect_only: bool) -> bool {
+        // Profile init must be complete before this api is callable
+        if !self.profiles_ready {
+            return false;
         }
-    }
+
+        let mut addr = match RawAddress::from_string(device.address.clone()) {
+            Some(v) => v,
+            None => {
+                warn!("Can't connect profiles on invalid address [{}]", &device.address);
+                return false;
+            }
+        };
+
+        let is_connected = self
+            .get_remote_device_if_found(&device.address)
+            .map_or(false, |d| d.acl_state == BtAclState::Connected);
+        if !is_connected {
+            // log ACL connection attempt if it's not already connected.
+            metrics::acl_connect_attempt(addr, BtAclState::Connected);
+            // Pause discovery before connecting, or the ACL connection request may conflict with
+            // the ongoing inquiry.
+            self.pause_discovery();
+        }
+
+        // Check all remote uuids to see if they match enabled profiles and connect them.
+        let mut has_enabled_uuids = false;
+        let mut has_media_profile = false;
+        let mut has_supported_profile = false;
+        let mut media_profiles = HashSet::new();
+        let policy = self.connection_policies.get(&device.address);
+        let mut uuids = self.get_remote_uuids(device.clone());
+        uuids.sort_by_key(|uuid| std::cmp::Reverse(policy.get_priority(uuid)));
+        for uuid in uuids.iter() {
+            match UuidHelper::is_known_profile(uuid) {
+                Some(p) => {
+                    let allowed = match policy.get_policy(uuid) {
+                        ConnectionPolicy::Forbidden => false,
+                        ConnectionPolicy::Manual => !auto_connect_only,
+                        ConnectionPolicy::AutoConnect => true,
+                    };
+                    if UuidHelper::is_profile_supported(&p) && allowed {
+                        match p {
+                            Profile::Hid | Profile::Hogp => {
+                                has_supported_profile = true;
+                                let status = self.hh.as_ref().unwrap().connect(&mut addr);
+                                metrics::profile_connection_state_changed(
+                                    addr,
+                                    p as u32,
+                                    BtStatus::Success,
+                                    BthhConnectionState::Connecting as u32,
+                                );
+
+                                if status != BtStatus::Success {
+                                    metrics::profile_connection_state_changed(
+                                        addr,
+                                        p as u32,
+                                        status,
+                                        BthhConnectionState::Disconnected as u32,
+                                    );
+                                }
+                            }
+
+                            // Connected together by the media module below.
+                            Profile::A2dpSink | Profile::A2dpSource | Profile::Hfp => {
+                                has_supported_profile = true;
+                                has_media_profile = true;
+                                media_profiles.insert(p);
+                            }
+                            Profile::AvrcpController => {
+                                media_profiles.insert(p);
+                            }
+
+                            Profile::Bas => {
+                                has_supported_profile = true;
+                                let tx = self.tx.clone();
+                                let transport =
+                                    match self.get_remote_device_if_found(&device.address) {
+                                        Some(context) => context.acl_reported_transport,
+                                        None => return false,
+                                    };
+                                let device_to_send = device.clone();
+                                let transport = match self.get_remote_type(device.clone()) {
+                                    BtDeviceType::Bredr => BtTransport::Bredr,
+                                    BtDeviceType::Ble => BtTransport::Le,
+                                    _ => transport,
+                                };
+                                topstack::get_runtime().spawn(async move {
+                                    let _ = tx
+                                        .send(Message::BatteryService(
+                                            BatteryServiceActions::Connect(
+                                                device_to_send,
+                                                transport,
+                                            ),
+                                        ))
+                                        .await;
+                                });
+                            }
+
+                            // We don't connect most profiles
+                            _ => (),
+                        }
+                    }
+                    has_enabled_uuids = true;
+                }
+                _ => {}
+            }
+        }
+
+        if has_media_profile {
+            let txl = self.tx.clone();
+            let address = device.address.clone();
+            topstack::get_runtime().spawn(async move {
+                let _ = txl
+                    .send(Message::Media(MediaActions::ConnectProfiles(address, media_profiles)))
+                    .await;
+            });
+        }
+
+        // If SDP isn't completed yet, we wait for it to complete and retry the connection again.
+        // Otherwise, this connection request is done, no retry is required.
+        if !has_enabled_uuids {
+            warn!("[{}] SDP hasn't completed for device, wait to connect.", DisplayAddress(&addr));
+            if let Some(d) = self.get_remote_device_if_found_mut(&device.address) {
+                if uuids.len() == 0 || !d.services_resolved {
+                    d.wait_to_connect = true;
+                }
+            }
+        }
+
+        // If the SDP has not been completed or the device does not have a profile that we are
+        // interested in connecting to, resume discovery now. Other cases will be handled in the
+        // ACL connection state or bond state callbacks.
+        if !has_enabled_uuids || !has_supported_profile {
+            self.resume_discovery();
+        }
+
+        return true;
+    }
+
+    /// Connects the profiles reconnected automatically of the bonded devices that aren't connected,
+    /// except those with an address in |except|.
+    fn reconnect_auto_connect_devices(&mut self, except: &HashSet<String>) {
+        let devices: Vec<BluetoothDevice> = self
+            .bonded_devices
+            .values()
+            .filter(|d| {
+                d.acl_state != BtAclState::Connected
+                    && !except.contains(&d.info.address)
+                    && self.connection_policies.get(&d.info.address).has_auto_connect()
+            })
+            .map(|d| d.info.clone())
+            .collect();
+
+        for device in devices {
+            self.connect_profiles(device, true);
+        }
+    }
+
+    /// Schedules the reconnection attempt number |attempt| to a device that lost its link, with
+    /// the backoff of its connection policy.
+    fn schedule_reconnect(&mut self, device: BluetoothDevice, attempt: u32) {
+        self.cancel_reconnect(&device.address);
+
+        let delay = match self.connection_policies.get(&device.address).get_reconnect_delay(attempt)
+        {
+            Some(delay) => delay,
+            None => {
+                if attempt > 0 {
+                    info!(
+                        "Giving up reconnecting to {} after {} attempts",
+                        device.address, attempt
+                    );
+                }
+                return;
+            }
+        };
+
+        let address = device.address.clone();
+        let txl = self.tx.clone();
+        let handle = tokio::spawn(async move {
+            time::sleep(delay).await;
+            let _ = txl
+                .send(Message::DelayedAdapterActions(DelayedActions::ReconnectDevice(device)))
+                .await;
+        });
+        self.reconnects.insert(address, (attempt, handle));
+    }
+
+    /// Stops reconnecting to the device with |address|.
+    fn cancel_reconnect(&mut self, address: &str) {
+        if let Some((_, handle)) = self.reconnects.remove(address) {
+            handle.abort();
+        }
+    }
+
+    fn get_remote_device_info_if_found(&self, remote_address: &str) -> Option<BluetoothDevice> {
+        self.get_remote_device_if_found(remote_address)
+            .map(|device_context| device_context.info.clone())
+    }
+
+    fn get_remote_device_property(
+        &self,
+        device: &BluetoothDevice,
+        property_type: &BtPropertyType,
+    ) -> Option<BluetoothProperty> {
+        self.get_remote_device_if_found(&device.address)
+            .and_then(|d| d.properties.get(property_type).and_then(|p| Some(p.clone())))
+    }
+
+    fn set_remote_device_property(
+        &mut self,
+        device: &BluetoothDevice,
+        property_type: BtPropertyType,
+        property: BluetoothProperty,
+    ) -> Result<(), ()> {
+        let remote_device = match self.get_remote_device_if_found_mut(&device.address) {
+            Some(d) => d,
+            None => {
+                return Err(());
+            }
+        };
+
+        let mut addr = RawAddress::from_string(remote_device.info.address.clone());
+        if addr.is_none() {
+            return Err(());
+        }
+        let addr = addr.as_mut().unwrap();
+
+        // TODO: Determine why a callback isn't invoked to do this.
+        remote_device.properties.insert(property_type, property.clone());
+        self.intf.lock().unwrap().set_remote_device_property(addr, property);
+        Ok(())
+    }
+
+    /// Returns whether the adapter is connectable.
+    pub(crate) fn get_connectable_internal(&self) -> bool {
+        match self.properties.get(&BtPropertyType::AdapterScanMode) {
+            Some(prop) => match prop {
+                BluetoothProperty::AdapterScanMode(mode) => match *mode {
+                    BtScanMode::Connectable | BtScanMode::ConnectableDiscoverable => true,
+                    _ => false,
+                },
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Sets the adapter's connectable mode for classic connections.
+    pub(crate) fn set_connectable_internal(&mut self, mode: bool) -> bool {
+        self.is_connectable = mode;
+        if mode && self.get_discoverable() {
+            return true;
+        }
+        self.intf.lock().unwrap().set_adapter_property(BluetoothProperty::AdapterScanMode(
+            if mode { BtScanMode::Connectable } else { BtScanMode::None_ },
+        )) == 0
+    }
+
+    /// Returns adapter's discoverable mode.
+    pub fn get_discoverable_mode_internal(&self) -> BtDiscMode {
+        let off_mode = BtDiscMode::NonDiscoverable;
+
+        match self.properties.get(&BtPropertyType::AdapterScanMode) {
+            Some(prop) => match prop {
+                BluetoothProperty::AdapterScanMode(mode) => match *mode {
+                    BtScanMode::ConnectableDiscoverable => BtDiscMode::GeneralDiscoverable,
+                    BtScanMode::ConnectableLimitedDiscoverable => BtDiscMode::LimitedDiscoverable,
+                    _ => off_mode,
+                },
+                _ => off_mode,
+            },
+            _ => off_mode,
+        }
+    }
+
+    /// Caches the discoverable mode into BluetoothQA.
+    pub fn cache_discoverable_mode_into_qa(&self) {
+        let disc_mode = self.get_discoverable_mode_internal();
+
+        let txl = self.tx.clone();
+        tokio::spawn(async move {
+            let _ = txl.send(Message::QaOnDiscoverableModeChanged(disc_mode)).await;
+        });
+    }
+
+    /// Returns all bonded and connected devices.
+    pub(crate) fn get_bonded_and_connected_devices(&mut self) -> Vec<BluetoothDevice> {
+        self.bonded_devices
+            .values()
+            .filter(|v| v.acl_state == BtAclState::Connected && v.bond_state == BtBondState::Bonded)
+            .map(|v| v.info.clone())
+            .collect()
+    }
+
+    /// Gets the bond state of a single device with its address.
+    pub fn get_bond_state_by_addr(&self, addr: &String) -> BtBondState {
+        match self.bonded_devices.get(&self.resolve_address(addr)) {
+            Some(device) => device.bond_state.clone(),
+            None => BtBondState::NotBonded,
+        }
+    }
+
+    /// Returns the number of advertisement filters (APCF) of the controller, or 0 if they aren't
+    /// supported.
+    pub fn get_max_adv_filter_supported(&self) -> u8 {
+        match self.properties.get(&BtPropertyType::LocalLeFeatures) {
+            Some(BluetoothProperty::LocalLeFeatures(llf)) => llf.max_adv_filter_supported,
+            _ => 0,
+        }
+    }
+
+    /// Returns the size of the scan result storage of the controller for batch scans, or 0 if
+    /// batch scans aren't supported.
+    pub fn get_le_scan_result_storage_size(&self) -> u16 {
+        match self.properties.get(&BtPropertyType::LocalLeFeatures) {
+            Some(BluetoothProperty::LocalLeFeatures(llf)) => llf.scan_result_storage_size,
+            _ => 0,
+        }
+    }
 
     /// Check whether found devices are still fresh. If they're outside the
     /// freshness window, send a notification to clear the device from clients.
@@ -803,6 +1667,9 @@ impl Bluetooth {
             self.freshness_check = None;
         }
 
+        // Piggyback on the periodic check to persist the devices seen since the last one.
+        self.device_cache.flush();
+
         // A found device is considered fresh if:
         // * It was last seen less than |FOUND_DEVICE_FRESHNESS| ago.
         // * It is currently connected.
@@ -819,15 +1686,20 @@ impl Bluetooth {
             .map(|(_, d)| d.info.clone())
             .collect();
 
-        // Retain only devices that are fresh.
+        // Retain only devices that are fresh, along with the secondary addresses of those.
         self.found_devices.retain(|_, d| is_fresh(d, &now));
+        let found_devices = &self.found_devices;
+        let bonded_devices = &self.bonded_devices;
+        self.address_aliases.retain(|_, address| {
+            found_devices.contains_key(address) || bonded_devices.contains_key(address)
+        });
 
         for d in stale_devices {
             self.callbacks.for_all_callbacks(|callback| {
                 callback.on_device_cleared(d.clone());
             });
 
-            self.bluetooth_admin.lock().unwrap().on_device_cleared(&d);
+            self.bluetooth_admin.lock().unwrap().on_device_cleared(d);
         }
 
         // If we have any fresh devices remaining, re-queue a freshness check.
@@ -889,10 +1761,38 @@ impl Bluetooth {
                 self.trigger_freshness_check();
             }
 
+            DelayedActions::DiscoveryTimeout => {
+                self.discovery_timeout = None;
+                self.cancel_discovery();
+            }
+
             DelayedActions::ConnectAllProfiles(device) => {
                 self.connect_all_enabled_profiles(device);
             }
 
+            DelayedActions::ReconnectDevice(device) => {
+                let is_connected = self
+                    .get_remote_device_if_found(&device.address)
+                    .map_or(false, |d| d.acl_state == BtAclState::Connected);
+                if is_connected || !self.bonded_devices.contains_key(&device.address) {
+                    self.cancel_reconnect(&device.address);
+                } else {
+                    self.connect_profiles(device, true);
+                }
+            }
+
+            DelayedActions::ReconnectAutoConnectDevices => {
+                self.reconnect_auto_connect_devices(&HashSet::new());
+            }
+
+            DelayedActions::ReconnectOnResume(devices) => {
+                let addresses = devices.iter().map(|d| d.address.clone()).collect();
+                for device in devices {
+                    self.connect_all_enabled_profiles(device);
+                }
+                self.reconnect_auto_connect_devices(&addresses);
+            }
+
             DelayedActions::BleDiscoveryScannerRegistered(uuid, scanner_id, status) => {
                 if let Some(app_uuid) = self.ble_scanner_uuid {
                     if app_uuid == uuid {
@@ -912,6 +1812,7 @@ impl Bluetooth {
                     Some(v) => {
                         let mut props = vec![];
                         props.push(BluetoothProperty::BdName(result.name.clone()));
+                        props.push(BluetoothProperty::TypeOfDevice(BtDeviceType::Ble));
                         props.push(BluetoothProperty::BdAddr(v.clone()));
                         if result.service_uuids.len() > 0 {
                             props.push(BluetoothProperty::Uuids(
@@ -931,27 +1832,83 @@ impl Bluetooth {
                     }
                 };
 
-                // Generate a vector of properties from ScanResult.
-                let device = BluetoothDevice::from_properties(&properties);
-                let address = device.address.clone();
-
-                if let Some(existing) = self.found_devices.get_mut(&address) {
-                    existing.update_properties(&properties);
-                    existing.seen();
-                } else {
-                    let device_with_props = BluetoothDeviceContext::new(
-                        BtBondState::NotBonded,
-                        BtAclState::Disconnected,
-                        device,
-                        Instant::now(),
-                        properties,
-                    );
-                    self.found_devices.insert(address.clone(), device_with_props);
-                }
+                self.on_discovery_result(properties, BtTransport::Le);
             }
         }
     }
 
+    /// Merges a device found on |transport| into the found devices and reports it if it matches
+    /// the discovery filter. The address is resolved first: the stack reports the private
+    /// addresses of bonded devices under the address their bond is kept under, which is then
+    /// mapped to the address a consolidated device is tracked under. A dual-mode device found on
+    /// both transports is thus merged into a single device. LE results are reported once per
+    /// discovery since advertisements are received much more often than inquiry results.
+    fn on_discovery_result(
+        &mut self,
+        mut properties: Vec<BluetoothProperty>,
+        transport: BtTransport,
+    ) {
+        let mut device = BluetoothDevice::from_properties(&properties);
+        let address = self.resolve_address(&device.address);
+
+        // Results for the secondary address of a consolidated device update that device.
+        if address != device.address {
+            properties.retain(|prop| prop.get_type() != BtPropertyType::BdAddr);
+            device.address = address.clone();
+        }
+
+        // A bonded device knows its type even if it wasn't found on the other transport yet.
+        let known_type = match self
+            .get_remote_device_if_found(&address)
+            .and_then(|d| d.properties.get(&BtPropertyType::TypeOfDevice))
+        {
+            Some(BluetoothProperty::TypeOfDevice(device_type)) => device_type.clone(),
+            _ => BtDeviceType::Unknown,
+        };
+        let found_type = properties.iter().find_map(|prop| match prop {
+            BluetoothProperty::TypeOfDevice(device_type) => Some(device_type.clone()),
+            _ => None,
+        });
+        if let Some(found_type) = found_type {
+            properties.retain(|prop| prop.get_type() != BtPropertyType::TypeOfDevice);
+            properties
+                .push(BluetoothProperty::TypeOfDevice(merge_device_types(known_type, found_type)));
+        }
+
+        if let Some(existing) = self.found_devices.get_mut(&address) {
+            existing.update_properties(&properties);
+            existing.seen();
+        } else {
+            let device_with_props = BluetoothDeviceContext::new(
+                BtBondState::NotBonded,
+                BtAclState::Disconnected,
+                device,
+                Instant::now(),
+                properties,
+            );
+            self.found_devices.insert(address.clone(), device_with_props);
+        }
+
+        self.update_device_cache(&address);
+
+        let device = self.found_devices.get(&address).unwrap();
+
+        self.bluetooth_admin.lock().unwrap().on_device_found(device.info.clone());
+
+        if !self.discovery_filter.matches(&device.properties) {
+            return;
+        }
+
+        let first_report = self.discovery_reported.insert(address.clone());
+        if transport == BtTransport::Le && !first_report {
+            return;
+        }
+
+        self.callbacks.for_all_callbacks(|callback| {
+            callback.on_device_found(device.info.clone());
+        });
+    }
+
     /// Creates a file to notify btmanagerd the adapter is enabled.
     fn create_pid_file(&self) -> std::io::Result<()> {
         let file_name = format!("{}/bluetooth{}.pid", PID_DIR, self.adapter_index);
@@ -1004,13 +1961,60 @@ impl Bluetooth {
 
         if self.is_discovering_before_suspend {
             self.is_discovering_before_suspend = false;
-            self.start_discovery();
+            self.start_discovery_with_filter(self.discovery_filter.clone());
         }
         self.set_discovery_suspend_mode(SuspendMode::Normal);
 
         return BtStatus::Success;
     }
 
+    /// Configures the stack with the scan parameters of |profile|.
+    fn apply_scan_parameter_profile(&self, profile: ScanParameterProfile) -> bool {
+        debug!("Applying scan parameter profile {}", profile.name());
+        self.scan_parameter_settings.get_parameters(profile).store_sysprops();
+        self.intf.lock().unwrap().apply_scan_parameters() == BTM_SUCCESS
+    }
+
+    /// Configures the GAP service of the GATT servers with the adapter name.
+    fn apply_gap_configuration(&self) -> bool {
+        let config = &self.gap_configuration;
+        let parameters = config.preferred_connection_parameters.clone().unwrap_or_default();
+        let mut gap_configuration = BtGapConfiguration {
+            device_name_readable: config.device_name_readable,
+            device_name_writable: config.device_name_writable,
+            persist_written_device_name: config.persist_written_device_name,
+            appearance: config.appearance,
+            appearance_writable: config.appearance_writable,
+            has_preferred_connection_parameters: config.preferred_connection_parameters.is_some(),
+            min_interval: parameters.min_interval,
+            max_interval: parameters.max_interval,
+            peripheral_latency: parameters.peripheral_latency,
+            supervision_timeout: parameters.supervision_timeout,
+            has_central_address_resolution: config.central_address_resolution.is_some(),
+            central_address_resolution: config.central_address_resolution.unwrap_or_default(),
+            rpa_only: config.rpa_only,
+            ..Default::default()
+        };
+        // Leave room for the NUL terminator.
+        let name = self.get_name();
+        let len = std::cmp::min(name.len(), gap_configuration.device_name.name.len() - 1);
+        gap_configuration.device_name.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+
+        self.intf.lock().unwrap().set_gap_configuration(&gap_configuration) == BTM_SUCCESS
+    }
+
+    /// Switches to the scan parameter profile used while suspended.
+    pub fn scan_parameters_enter_suspend(&mut self) {
+        self.scan_parameters_suspended = true;
+        self.apply_scan_parameter_profile(self.scan_parameter_settings.get_suspend_profile());
+    }
+
+    /// Switches back to the selected scan parameter profile.
+    pub fn scan_parameters_exit_suspend(&mut self) {
+        self.scan_parameters_suspended = false;
+        self.apply_scan_parameter_profile(self.scan_parameter_settings.get_profile());
+    }
+
     /// Temporarily stop the discovery process and mark it as paused so that clients cannot restart
     /// it.
     fn pause_discovery(&mut self) {
@@ -1023,7 +2027,7 @@ impl Bluetooth {
     fn resume_discovery(&mut self) {
         if self.pending_discovery {
             self.pending_discovery = false;
-            self.start_discovery();
+            self.start_discovery_with_filter(self.discovery_filter.clone());
         }
         self.is_discovery_paused = false;
     }
@@ -1071,6 +2075,12 @@ pub(crate) trait BtifBluetoothCallbacks {
     ) {
     }
 
+    #[btif_callback(AddressConsolidate)]
+    fn address_consolidate(&mut self, main_addr: RawAddress, secondary_addr: RawAddress) {}
+
+    #[btif_callback(LeAddressAssociate)]
+    fn le_address_associate(&mut self, main_addr: RawAddress, secondary_addr: RawAddress) {}
+
     #[btif_callback(RemoteDeviceProperties)]
     fn remote_device_properties_changed(
         &mut self,
@@ -1094,6 +2104,26 @@ pub(crate) trait BtifBluetoothCallbacks {
     ) {
     }
 
+    #[btif_callback(EnergyInfo)]
+    fn energy_info(&mut self, info: BtActivityEnergyInfo) {}
+
+    #[btif_callback(LinkQualityReport)]
+    fn link_quality_report(
+        &mut self,
+        addr: RawAddress,
+        timestamp_ms: u64,
+        report_id: i32,
+        rssi: i32,
+        snr: i32,
+        retransmission_count: i32,
+        packets_not_receive_count: i32,
+        negative_acknowledgement_count: i32,
+    ) {
+    }
+
+    #[btif_callback(GenerateLocalOobData)]
+    fn generate_local_oob_data(&mut self, transport: u8, data: OobData) {}
+
     #[btif_callback(LeRandCallback)]
     fn le_rand_cb(&mut self, random: u64) {}
 
@@ -1167,6 +2197,14 @@ impl BtifBluetoothCallbacks for Bluetooth {
         match self.state {
             BtState::Off => {
                 self.properties.clear();
+                self.device_cache.flush();
+                for (_, (_, handle)) in self.reconnects.drain() {
+                    handle.abort();
+                }
+                if self.irk_regeneration_pending.take().is_some() {
+                    warn!("Adapter turned off before the local IRK was regenerated");
+                    self.report_irk_regenerated(BtStatus::NotReady, vec![]);
+                }
                 match self.remove_pid_file() {
                     Err(err) => warn!("remove_pid_file() error: {}", err),
                     _ => (),
@@ -1187,6 +2225,12 @@ impl BtifBluetoothCallbacks for Bluetooth {
                 // Also need to manually request some properties
                 self.intf.lock().unwrap().get_adapter_property(BtPropertyType::ClassOfDevice);
 
+                // Board tuned parameters are only replaced once a profile is chosen.
+                self.scan_parameter_settings.save_stack_parameters();
+                if let Some(profile) = self.scan_parameter_settings.get_chosen_profile() {
+                    self.apply_scan_parameter_profile(profile);
+                }
+
                 // Initialize the BLE scanner for discovery.
                 let callback_id = self.bluetooth_gatt.lock().unwrap().register_scanner_callback(
                     Box::new(BleDiscoveryCallbacks::new(self.tx.clone())),
@@ -1212,6 +2256,18 @@ impl BtifBluetoothCallbacks for Bluetooth {
                 tokio::spawn(async move {
                     let _ = txl.send(Message::AdapterReady).await;
                 });
+
+                // Reconnect the devices whose connection policy asks for it, once the bonded
+                // devices have been reported.
+                let txl = self.tx.clone();
+                tokio::spawn(async move {
+                    time::sleep(AUTO_RECONNECT_ON_ENABLE_DELAY).await;
+                    let _ = txl
+                        .send(Message::DelayedAdapterActions(
+                            DelayedActions::ReconnectAutoConnectDevices,
+                        ))
+                        .await;
+                });
             }
         }
     }
@@ -1236,24 +2292,21 @@ impl BtifBluetoothCallbacks for Bluetooth {
                     self.update_local_address(&bdaddr);
                 }
                 BluetoothProperty::AdapterBondedDevices(bondlist) => {
+                    // The stack loads LE devices, and consolidates their addresses, before it
+                    // reports the bonded devices, so both addresses of a device show up here.
                     for addr in bondlist.iter() {
-                        let address = addr.to_string();
-
-                        // Update bonded state if already in the list. Otherwise create a new
-                        // context with empty properties and name.
-                        self.bonded_devices
-                            .entry(address.clone())
-                            .and_modify(|d| d.bond_state = BtBondState::Bonded)
-                            .or_insert(BluetoothDeviceContext::new(
-                                BtBondState::Bonded,
-                                BtAclState::Disconnected,
-                                BluetoothDevice::new(address.clone(), "".to_string()),
-                                Instant::now(),
-                                vec![],
-                            ));
+                        add_bonded_device(
+                            &mut self.bonded_devices,
+                            &mut self.found_devices,
+                            &self.address_aliases,
+                            &addr.to_string(),
+                        );
                     }
                 }
                 BluetoothProperty::BdName(bdname) => {
+                    // The GAP service serves the adapter name.
+                    self.apply_gap_configuration();
+
                     self.callbacks.for_all_callbacks(|callback| {
                         callback.on_name_changed(bdname.clone());
                     });
@@ -1276,30 +2329,7 @@ impl BtifBluetoothCallbacks for Bluetooth {
     }
 
     fn device_found(&mut self, _n: i32, properties: Vec<BluetoothProperty>) {
-        let device = BluetoothDevice::from_properties(&properties);
-        let address = device.address.clone();
-
-        if let Some(existing) = self.found_devices.get_mut(&address) {
-            existing.update_properties(&properties);
-            existing.seen();
-        } else {
-            let device_with_props = BluetoothDeviceContext::new(
-                BtBondState::NotBonded,
-                BtAclState::Disconnected,
-                device,
-                Instant::now(),
-                properties,
-            );
-            self.found_devices.insert(address.clone(), device_with_props);
-        }
-
-        let device = self.found_devices.get(&address).unwrap();
-
-        self.callbacks.for_all_callbacks(|callback| {
-            callback.on_device_found(device.info.clone());
-        });
-
-        self.bluetooth_admin.lock().unwrap().on_device_found(&device.info);
+        self.on_discovery_result(properties, BtTransport::Bredr);
     }
 
     fn discovery_state(&mut self, state: BtDiscoveryState) {
@@ -1314,6 +2344,20 @@ impl BtifBluetoothCallbacks for Bluetooth {
         self.is_discovering = &state == &BtDiscoveryState::Started;
         if self.is_discovering {
             self.discovering_started = Instant::now();
+            self.discovery_reported.clear();
+
+            if self.discovery_filter.needs_timeout() {
+                let txl = self.tx.clone();
+                let duration = self.discovery_filter.duration();
+                self.discovery_timeout = Some(tokio::spawn(async move {
+                    time::sleep(duration).await;
+                    let _ = txl
+                        .send(Message::DelayedAdapterActions(DelayedActions::DiscoveryTimeout))
+                        .await;
+                }));
+            }
+        } else if let Some(handle) = self.discovery_timeout.take() {
+            handle.abort();
         }
 
         // Prevent sending out discovering changes or freshness checks when
@@ -1337,8 +2381,12 @@ impl BtifBluetoothCallbacks for Bluetooth {
             self.trigger_freshness_check();
         }
 
-        // Start or stop BLE scanning based on discovering state
+        // Start or stop BLE scanning based on discovering state, unless discovering BR/EDR only.
         if let Some(scanner_id) = self.ble_scanner_id {
+            if !self.discovery_filter.includes_transport(BtTransport::Le) {
+                return;
+            }
+
             if is_discovering {
                 self.bluetooth_gatt.lock().unwrap().start_active_scan(scanner_id);
             } else {
@@ -1406,6 +2454,54 @@ impl BtifBluetoothCallbacks for Bluetooth {
         }
     }
 
+    fn energy_info(&mut self, info: BtActivityEnergyInfo) {
+        self.callbacks.for_all_callbacks(|callback| {
+            callback.on_energy_info(info.clone());
+        });
+    }
+
+    fn link_quality_report(
+        &mut self,
+        addr: RawAddress,
+        timestamp_ms: u64,
+        report_id: i32,
+        rssi: i32,
+        snr: i32,
+        retransmission_count: i32,
+        packets_not_receive_count: i32,
+        negative_acknowledgement_count: i32,
+    ) {
+        let address = addr.to_string();
+        let device = self
+            .get_remote_device_info_if_found(&address)
+            .unwrap_or_else(|| BluetoothDevice::new(address, String::from("")));
+        let report = LinkQualityReport {
+            timestamp_ms,
+            report_id,
+            rssi,
+            snr,
+            retransmission_count,
+            packets_not_receive_count,
+            negative_acknowledgement_count,
+        };
+
+        self.callbacks.for_all_callbacks(|callback| {
+            callback.on_link_quality_report(device.clone(), report.clone());
+        });
+    }
+
+    fn generate_local_oob_data(&mut self, transport: u8, data: OobData) {
+        let transport = BtTransport::from(transport as i32);
+        let data = BtOobData::from(data);
+        if !data.is_valid {
+            warn!("Failed to generate local OOB data for transport {:?}", transport);
+        }
+
+        self.callbacks.for_all_callbacks(|callback| {
+            callback.on_local_oob_data_generated(transport, data.clone());
+        });
+    }
+
     fn bond_state(
         &mut self,
         status: BtStatus,
@@ -1413,7 +2509,8 @@ impl BtifBluetoothCallbacks for Bluetooth {
         bond_state: BtBondState,
         fail_reason: i32,
     ) {
-        let address = addr.to_string();
+        // Both addresses of a consolidated device share its bond.
+        let address = self.resolve_address(&addr.to_string());
 
         // Get the device type before the device is potentially deleted.
         let device_type =
@@ -1422,7 +2519,13 @@ impl BtifBluetoothCallbacks for Bluetooth {
         // Easy case of not bonded -- we remove the device from the bonded list and change the bond
         // state in the found list (in case it was previously bonding).
         if &bond_state == &BtBondState::NotBonded {
-            self.bonded_devices.remove(&address);
+            if self.bonded_devices.contains_key(&address) {
+                // The stack forgets which addresses belong together along with the keys.
+                self.remove_consolidated_address(&address);
+                self.bonded_devices.remove(&address);
+                self.connection_policies.remove(&address);
+                self.cancel_reconnect(&address);
+            }
             self.found_devices
                 .entry(address.clone())
                 .and_modify(|d| d.bond_state = bond_state.clone());
@@ -1447,6 +2550,9 @@ impl BtifBluetoothCallbacks for Bluetooth {
             };
             let device_info = device.info.clone();
 
+            // Bonded devices are persisted by the stack, so they no longer need caching.
+            self.device_cache.remove(&address);
+
             // Since this is a newly bonded device, we also need to trigger SDP
             // on it.
             device.services_resolved = false;
@@ -1474,18 +2580,52 @@ impl BtifBluetoothCallbacks for Bluetooth {
             );
         });
 
+        self.continue_irk_regeneration(&address, &bond_state);
+
         metrics::bond_state_changed(addr, device_type, status, bond_state, fail_reason);
     }
 
+    fn address_consolidate(&mut self, main_addr: RawAddress, secondary_addr: RawAddress) {
+        // The secondary address of a dual-mode device is its BR/EDR identity address, which
+        // profiles connect to. The LE address it was paired over becomes the secondary one.
+        debug!(
+            "Consolidating {} into {}",
+            DisplayAddress(&main_addr),
+            DisplayAddress(&secondary_addr)
+        );
+        self.consolidate_device(secondary_addr.to_string(), main_addr.to_string(), true);
+    }
+
+    fn le_address_associate(&mut self, main_addr: RawAddress, secondary_addr: RawAddress) {
+        // An LE-only device stays tracked under the address it was paired over, which the stack
+        // keeps its bond under.
+        debug!(
+            "Associating {} with {}",
+            DisplayAddress(&secondary_addr),
+            DisplayAddress(&main_addr)
+        );
+        self.consolidate_device(main_addr.to_string(), secondary_addr.to_string(), false);
+    }
+
     fn remote_device_properties_changed(
         &mut self,
         _status: BtStatus,
         addr: RawAddress,
         _num_properties: i32,
-        properties: Vec<BluetoothProperty>,
+        mut properties: Vec<BluetoothProperty>,
     ) {
-        let address = addr.to_string();
+        let address = self.resolve_address(&addr.to_string());
         let txl = self.tx.clone();
+
+        // The address and type of a consolidated device are kept when the properties of its
+        // secondary address change.
+        if address != addr.to_string() {
+            properties.retain(|prop| match prop.get_type() {
+                BtPropertyType::BdAddr | BtPropertyType::TypeOfDevice => false,
+                _ => true,
+            });
+        }
+
         let device = match self.get_remote_device_if_found_mut(&address) {
             None => {
                 self.found_devices.insert(
@@ -1540,10 +2680,12 @@ impl BtifBluetoothCallbacks for Bluetooth {
                 self.bluetooth_admin
                     .lock()
                     .unwrap()
-                    .on_remote_device_properties_changed(&info, &properties);
+                    .on_remote_device_properties_changed(info, properties);
             }
             None => (),
         }
+
+        self.update_device_cache(&address);
     }
 
     fn acl_state(
@@ -1575,10 +2717,35 @@ impl BtifBluetoothCallbacks for Bluetooth {
                 conn_direction,
                 hci_reason,
             );
+
+            // Retry if this was an attempt to reconnect after link loss.
+            let address = self.resolve_address(&addr.to_string());
+            if let Some((attempt, _)) = self.reconnects.get(&address) {
+                let next_attempt = attempt + 1;
+                if let Some(device) = self.bonded_devices.get(&address) {
+                    self.schedule_reconnect(device.info.clone(), next_attempt);
+                }
+            }
+            return;
+        }
+
+        let address = self.resolve_address(&addr.to_string());
+
+        // A consolidated device stays connected as long as any of its addresses is.
+        if state == BtAclState::Disconnected
+            && self
+                .get_consolidated_addresses(&address)
+                .iter()
+                .filter(|a| **a != addr.to_string())
+                .filter_map(|a| RawAddress::from_string(a.clone()))
+                .any(|a| {
+                    self.intf.lock().unwrap().get_connection_state(&a)
+                        != BtConnectionState::NotConnected
+                })
+        {
             return;
         }
 
-        let address = addr.to_string();
         let device = match self.get_remote_device_if_found_mut(&address) {
             None => {
                 self.found_devices.insert(
@@ -1620,6 +2787,7 @@ impl BtifBluetoothCallbacks for Bluetooth {
                             let bluetooth_device = found.info.clone();
                             let acl_reported_transport = found.acl_reported_transport.clone();
                             Bluetooth::send_metrics_remote_device_info(found);
+                            self.cancel_reconnect(&address);
                             self.connection_callbacks.for_all_callbacks(|callback| {
                                 callback.on_device_connected(device.clone());
                             });
@@ -1639,6 +2807,11 @@ impl BtifBluetoothCallbacks for Bluetooth {
                             self.connection_callbacks.for_all_callbacks(|callback| {
                                 callback.on_device_disconnected(device.clone());
                             });
+                            if connection_policy::is_link_loss(hci_reason)
+                                && self.bonded_devices.contains_key(&address)
+                            {
+                                self.schedule_reconnect(device.clone(), 0);
+                            }
                             let tx = self.tx.clone();
                             tokio::spawn(async move {
                                 let _ = tx.send(Message::OnAclDisconnected(device.clone())).await;
@@ -1688,6 +2861,7 @@ impl IScannerCallback for BleDiscoveryCallbacks {
 
     fn on_advertisement_found(&mut self, _scanner_id: u8, _scan_result: ScanResult) {}
     fn on_advertisement_lost(&mut self, _scanner_id: u8, _scan_result: ScanResult) {}
+    fn on_batch_scan_results(&mut self, _scanner_id: u8, _scan_results: Vec<ScanResult>) {}
     fn on_suspend_mode_change(&mut self, _suspend_mode: SuspendMode) {}
 }
 
@@ -1715,6 +2889,13 @@ impl IBluetooth for Bluetooth {
     }
 
     fn enable(&mut self) -> bool {
+        // The address type used with LE privacy is picked up when the stack starts.
+        let privacy = self.le_privacy_settings.get();
+        self.intf.lock().unwrap().set_le_privacy_parameters(
+            privacy.use_non_resolvable_address,
+            privacy.rpa_rotation_timeout_ms,
+        );
+
         self.intf.lock().unwrap().enable() == 0
     }
 
@@ -1859,6 +3040,10 @@ impl IBluetooth for Bluetooth {
     }
 
     fn start_discovery(&mut self) -> bool {
+        self.start_discovery_with_filter(DiscoveryFilter::default())
+    }
+
+    fn start_discovery_with_filter(&mut self, filter: DiscoveryFilter) -> bool {
         // Short-circuit to avoid sending multiple start discovery calls.
         if self.is_discovering {
             return true;
@@ -1866,6 +3051,7 @@ impl IBluetooth for Bluetooth {
 
         // Short-circuit if paused and add the discovery intent to the queue.
         if self.is_discovery_paused {
+            self.discovery_filter = filter;
             self.pending_discovery = true;
             debug!("Queue the discovery request during paused state");
             return true;
@@ -1879,6 +3065,19 @@ impl IBluetooth for Bluetooth {
             return false;
         }
 
+        self.discovery_filter = filter;
+
+        // LE only discovery doesn't involve an inquiry, so the discovery state follows the BLE
+        // scanner instead.
+        if self.discovery_filter.transport == BtTransport::Le {
+            if self.ble_scanner_id.is_none() {
+                warn!("LE discovery is not available before the BLE scanner is registered.");
+                return false;
+            }
+            self.discovery_state(BtDiscoveryState::Started);
+            return true;
+        }
+
         self.intf.lock().unwrap().start_discovery() == 0
     }
 
@@ -1904,6 +3103,11 @@ impl IBluetooth for Bluetooth {
             return false;
         }
 
+        if self.discovery_filter.transport == BtTransport::Le {
+            self.discovery_state(BtDiscoveryState::Stopped);
+            return true;
+        }
+
         self.intf.lock().unwrap().cancel_discovery() == 0
     }
 
@@ -1916,11 +3120,12 @@ impl IBluetooth for Bluetooth {
             return 0;
         }
 
+        let duration_ms = self.discovery_filter.duration().as_millis() as u64;
         let elapsed_ms = self.discovering_started.elapsed().as_millis() as u64;
-        if elapsed_ms >= DEFAULT_DISCOVERY_TIMEOUT_MS {
+        if elapsed_ms >= duration_ms {
             0
         } else {
-            DEFAULT_DISCOVERY_TIMEOUT_MS - elapsed_ms
+            duration_ms - elapsed_ms
         }
     }
 
@@ -1978,6 +3183,79 @@ impl IBluetooth for Bluetooth {
         return true;
     }
 
+    fn create_bond_out_of_band(
+        &mut self,
+        device: BluetoothDevice,
+        transport: BtTransport,
+        p192_data: BtOobData,
+        p256_data: BtOobData,
+    ) -> bool {
+        let address = match RawAddress::from_string(device.address.clone()) {
+            Some(addr) => addr,
+            None => {
+                metrics::bond_create_attempt(RawAddress::default(), BtDeviceType::Unknown);
+                metrics::bond_state_changed(
+                    RawAddress::default(),
+                    BtDeviceType::Unknown,
+                    BtStatus::InvalidParam,
+                    BtBondState::NotBonded,
+                    0,
+                );
+                warn!("Can't create bond out of band. Address {} is not valid", device.address);
+                return false;
+            }
+        };
+
+        if !p192_data.is_valid && !p256_data.is_valid {
+            warn!("Can't create bond out of band with {}. No valid OOB data", device.address);
+            return false;
+        }
+
+        let (p192_data, p256_data) =
+            match (OobData::try_from(p192_data), OobData::try_from(p256_data)) {
+                (Ok(p192_data), Ok(p256_data)) => (p192_data, p256_data),
+                (Err(err), _) | (_, Err(err)) => {
+                    warn!(
+                        "Can't create bond out of band with {}. Invalid OOB data: {}",
+                        device.address, err
+                    );
+                    return false;
+                }
+            };
+
+        let device_type = match transport {
+            BtTransport::Bredr => BtDeviceType::Bredr,
+            BtTransport::Le => BtDeviceType::Ble,
+            _ => self.get_remote_type(device.clone()),
+        };
+        metrics::bond_create_attempt(address, device_type.clone());
+
+        // BREDR connection won't work when Inquiry is in progress.
+        self.pause_discovery();
+        let status = self
+            .intf
+            .lock()
+            .unwrap()
+            .create_bond_out_of_band(&address, transport, &p192_data, &p256_data);
+
+        if status != 0 {
+            metrics::bond_state_changed(
+                address,
+                device_type,
+                BtStatus::from(status as u32),
+                BtBondState::NotBonded,
+                0,
+            );
+            return false;
+        }
+
+        true
+    }
+
+    fn generate_local_oob_data(&self, transport: BtTransport) -> bool {
+        self.intf.lock().unwrap().generate_local_oob_data(transport.into()) == BTM_SUCCESS
+    }
+
     fn cancel_bond_process(&self, device: BluetoothDevice) -> bool {
         let addr = RawAddress::from_string(device.address.clone());
 
@@ -2030,10 +3308,39 @@ impl IBluetooth for Bluetooth {
         devices
     }
 
+    fn get_recent_devices(&self) -> Vec<RecentDevice> {
+        self.device_cache
+            .get_recent_devices()
+            .into_iter()
+            .filter(|d| !self.bonded_devices.contains_key(&d.device.address))
+            .collect()
+    }
+
+    fn clear_recent_devices(&mut self) {
+        self.device_cache.clear();
+        self.device_cache.flush();
+    }
+
     fn get_bond_state(&self, device: BluetoothDevice) -> BtBondState {
         self.get_bond_state_by_addr(&device.address)
     }
 
+    fn get_remote_security_info(&self, device: BluetoothDevice) -> RemoteSecurityInfo {
+        let addr = match RawAddress::from_string(device.address.clone()) {
+            Some(addr) => addr,
+            None => {
+                warn!("Can't get security info. Address {} is not valid.", device.address);
+                return RemoteSecurityInfo::default();
+            }
+        };
+
+        self.intf
+            .lock()
+            .unwrap()
+            .get_remote_security_info(&addr)
+            .map_or(RemoteSecurityInfo::default(), RemoteSecurityInfo::from)
+    }
+
     fn set_pin(&self, device: BluetoothDevice, accept: bool, pin_code: Vec<u8>) -> bool {
         let addr = RawAddress::from_string(device.address.clone());
 
@@ -2194,16 +3501,20 @@ impl IBluetooth for Bluetooth {
     }
 
     fn get_connection_state(&self, device: BluetoothDevice) -> BtConnectionState {
-        let addr = RawAddress::from_string(device.address.clone());
-
-        if addr.is_none() {
+        if RawAddress::from_string(device.address.clone()).is_none() {
             warn!("Can't check connection state. Address {} is not valid.", device.address);
             return BtConnectionState::NotConnected;
         }
 
         // The underlying api adds whether this is ENCRYPTED_BREDR or ENCRYPTED_LE.
-        // As long as it is non-zero, it is connected.
-        self.intf.lock().unwrap().get_connection_state(&addr.unwrap())
+        // As long as it is non-zero, it is connected. A consolidated device is connected if any
+        // of its addresses is.
+        self.get_consolidated_addresses(&device.address)
+            .iter()
+            .filter_map(|a| RawAddress::from_string(a.clone()))
+            .map(|a| self.intf.lock().unwrap().get_connection_state(&a))
+            .find(|state| *state != BtConnectionState::NotConnected)
+            .unwrap_or(BtConnectionState::NotConnected)
     }
 
     fn get_profile_connection_state(&self, profile: Uuid128Bit) -> ProfileConnectionState {
@@ -2294,95 +3605,62 @@ impl IBluetooth for Bluetooth {
     }
 
     fn connect_all_enabled_profiles(&mut self, device: BluetoothDevice) -> bool {
-        // Profile init must be complete before this api is callable
+        self.connect_profiles(device, false)
+    }
+
+    fn disconnect_all_enabled_profiles(&mut self, device: BluetoothDevice) -> bool {
         if !self.profiles_ready {
             return false;
         }
 
-        let mut addr = match RawAddress::from_string(device.address.clone()) {
-            Some(v) => v,
-            None => {
-                warn!("Can't connect profiles on invalid address [{}]", &device.address);
-                return false;
-            }
-        };
+        let addr = RawAddress::from_string(device.address.clone());
+        if addr.is_none() {
+            warn!("Can't connect profiles on invalid address [{}]", &device.address);
+            return false;
+        }
 
+        // log ACL disconnection attempt if it's not already disconnected.
         let is_connected = self
             .get_remote_device_if_found(&device.address)
             .map_or(false, |d| d.acl_state == BtAclState::Connected);
-        if !is_connected {
-            // log ACL connection attempt if it's not already connected.
-            metrics::acl_connect_attempt(addr, BtAclState::Connected);
-            // Pause discovery before connecting, or the ACL connection request may conflict with
-            // the ongoing inquiry.
-            self.pause_discovery();
+        if is_connected {
+            metrics::acl_connect_attempt(addr.unwrap(), BtAclState::Disconnected);
         }
 
-        // Check all remote uuids to see if they match enabled profiles and connect them.
-        let mut has_enabled_uuids = false;
-        let mut has_media_profile = false;
-        let mut has_supported_profile = false;
         let uuids = self.get_remote_uuids(device.clone());
+        let mut has_media_profile = false;
         for uuid in uuids.iter() {
             match UuidHelper::is_known_profile(uuid) {
                 Some(p) => {
                     if UuidHelper::is_profile_supported(&p) {
                         match p {
                             Profile::Hid | Profile::Hogp => {
-                                has_supported_profile = true;
-                                let status = self.hh.as_ref().unwrap().connect(&mut addr);
-                                metrics::profile_connection_state_changed(
-                                    addr,
-                                    p as u32,
-                                    BtStatus::Success,
-                                    BthhConnectionState::Connecting as u32,
-                                );
-
-                                if status != BtStatus::Success {
-                                    metrics::profile_connection_state_changed(
-                                        addr,
-                                        p as u32,
-                                        status,
-                                        BthhConnectionState::Disconnected as u32,
-                                    );
-                                }
+                                self.hh.as_ref().unwrap().disconnect(&mut addr.unwrap());
                             }
 
-                            Profile::A2dpSink | Profile::A2dpSource | Profile::Hfp
+                            Profile::A2dpSink
+                            | Profile::A2dpSource
+                            | Profile::Hfp
+                            | Profile::AvrcpController
                                 if !has_media_profile =>
                             {
-                                has_supported_profile = true;
                                 has_media_profile = true;
                                 let txl = self.tx.clone();
                                 let address = device.address.clone();
                                 topstack::get_runtime().spawn(async move {
                                     let _ = txl
-                                        .send(Message::Media(MediaActions::Connect(address)))
+                                        .send(Message::Media(MediaActions::Disconnect(address)))
                                         .await;
                                 });
                             }
 
                             Profile::Bas => {
-                                has_supported_profile = true;
                                 let tx = self.tx.clone();
-                                let transport =
-                                    match self.get_remote_device_if_found(&device.address) {
-                                        Some(context) => context.acl_reported_transport,
-                                        None => return false,
-                                    };
                                 let device_to_send = device.clone();
-                                let transport = match self.get_remote_type(device.clone()) {
-                                    BtDeviceType::Bredr => BtTransport::Bredr,
-                                    BtDeviceType::Ble => BtTransport::Le,
-                                    _ => transport,
-                                };
                                 topstack::get_runtime().spawn(async move {
                                     let _ = tx
                                         .send(Message::BatteryService(
-                                            BatteryServiceActions::Connect(
-                                                device_to_send,
-                                                transport,
-                                            ),
+                                            BatteryServiceActions::Disconnect(device_to_send),
                                         ))
                                         .await;
                                 });
@@ -2392,101 +3670,176 @@ impl IBluetooth for Bluetooth {
                             _ => (),
                         }
                     }
-                    has_enabled_uuids = true;
                 }
                 _ => {}
             }
         }
 
-        // If SDP isn't completed yet, we wait for it to complete and retry the connection again.
-        // Otherwise, this connection request is done, no retry is required.
-        if !has_enabled_uuids {
-            warn!("[{}] SDP hasn't completed for device, wait to connect.", DisplayAddress(&addr));
-            if let Some(d) = self.get_remote_device_if_found_mut(&device.address) {
-                if uuids.len() == 0 || !d.services_resolved {
-                    d.wait_to_connect = true;
-                }
+        return true;
+    }
+
+    fn get_connection_policy(&self, device: BluetoothDevice) -> DeviceConnectionPolicy {
+        self.connection_policies.get(&device.address)
+    }
+
+    fn set_connection_policy(
+        &mut self,
+        device: BluetoothDevice,
+        policy: DeviceConnectionPolicy,
+    ) -> bool {
+        if RawAddress::from_string(device.address.clone()).is_none() {
+            warn!("Can't set connection policy of invalid address [{}]", &device.address);
+            return false;
+        }
+
+        if !policy.has_auto_connect() {
+            self.cancel_reconnect(&device.address);
+        }
+        self.connection_policies.set(device.address, policy);
+        true
+    }
+
+    fn request_energy_info(&self) -> bool {
+        self.intf.lock().unwrap().read_energy_info() == BTM_SUCCESS
+    }
+
+    fn set_link_quality_report(&self, enable: bool, min_report_interval_ms: u16) -> bool {
+        self.intf.lock().unwrap().set_link_quality_report(enable, min_report_interval_ms)
+            == BTM_SUCCESS
+    }
+
+    fn get_le_privacy_config(&self) -> LePrivacyConfig {
+        self.le_privacy_settings.get()
+    }
+
+    fn set_le_privacy_config(&mut self, config: LePrivacyConfig) -> bool {
+        if !config.is_valid() {
+            warn!("Invalid RPA rotation timeout {} ms", config.rpa_rotation_timeout_ms);
+            return false;
+        }
+
+        let status = self.intf.lock().unwrap().set_le_privacy_parameters(
+            config.use_non_resolvable_address,
+            config.rpa_rotation_timeout_ms,
+        );
+        if status != BTM_SUCCESS {
+            return false;
+        }
+
+        self.le_privacy_settings.set(config);
+        true
+    }
+
+    fn get_gap_configuration(&self) -> GapConfiguration {
+        self.gap_configuration.clone()
+    }
+
+    fn set_gap_configuration(&mut self, config: GapConfiguration) -> bool {
+        if let Some(parameters) = &config.preferred_connection_parameters {
+            if !parameters.is_valid() {
+                warn!("Invalid preferred connection parameters {:?}", parameters);
+                return false;
             }
         }
 
-        // If the SDP has not been completed or the device does not have a profile that we are
-        // interested in connecting to, resume discovery now. Other cases will be handled in the
-        // ACL connection state or bond state callbacks.
-        if !has_enabled_uuids || !has_supported_profile {
-            self.resume_discovery();
+        self.gap_configuration = config;
+        // Otherwise it's applied once the adapter name is known.
+        if self.state == BtState::On {
+            return self.apply_gap_configuration();
         }
+        true
+    }
 
-        return true;
+    fn get_scan_parameter_profile(&self) -> ScanParameterProfile {
+        self.scan_parameter_settings.get_profile()
     }
 
-    fn disconnect_all_enabled_profiles(&mut self, device: BluetoothDevice) -> bool {
-        if !self.profiles_ready {
+    fn set_scan_parameter_profile(&mut self, profile: ScanParameterProfile) -> bool {
+        if self.state == BtState::On
+            && !self.scan_parameters_suspended
+            && !self.apply_scan_parameter_profile(profile)
+        {
             return false;
         }
 
-        let addr = RawAddress::from_string(device.address.clone());
-        if addr.is_none() {
-            warn!("Can't connect profiles on invalid address [{}]", &device.address);
+        self.scan_parameter_settings.set_profile(profile);
+        true
+    }
+
+    fn get_le_identity_address(&self) -> String {
+        // The public address of the adapter is always used as its identity address.
+        self.get_address()
+    }
+
+    fn get_local_irk(&self) -> Vec<u8> {
+        self.intf.lock().unwrap().get_local_irk().map_or(vec![], |irk| irk.to_vec())
+    }
+
+    fn regenerate_local_irk(&mut self) -> bool {
+        if self.state != BtState::On {
+            warn!("Can't regenerate the local IRK while the adapter is off");
             return false;
         }
 
-        // log ACL disconnection attempt if it's not already disconnected.
-        let is_connected = self
-            .get_remote_device_if_found(&device.address)
-            .map_or(false, |d| d.acl_state == BtAclState::Connected);
-        if is_connected {
-            metrics::acl_connect_attempt(addr.unwrap(), BtAclState::Disconnected);
+        if self.irk_regeneration_pending.is_some() {
+            warn!("The local IRK is already being regenerated");
+            return false;
         }
 
-        let uuids = self.get_remote_uuids(device.clone());
-        let mut has_media_profile = false;
-        for uuid in uuids.iter() {
-            match UuidHelper::is_known_profile(uuid) {
-                Some(p) => {
-                    if UuidHelper::is_profile_supported(&p) {
-                        match p {
-                            Profile::Hid | Profile::Hogp => {
-                                self.hh.as_ref().unwrap().disconnect(&mut addr.unwrap());
-                            }
+        if self.found_devices.values().any(|d| d.bond_state == BtBondState::Bonding) {
+            warn!("Can't regenerate the local IRK while bonding");
+            return false;
+        }
 
-                            Profile::A2dpSink
-                            | Profile::A2dpSource
-                            | Profile::Hfp
-                            | Profile::AvrcpController
-                                if !has_media_profile =>
-                            {
-                                has_media_profile = true;
-                                let txl = self.tx.clone();
-                                let address = device.address.clone();
-                                topstack::get_runtime().spawn(async move {
-                                    let _ = txl
-                                        .send(Message::Media(MediaActions::Disconnect(address)))
-                                        .await;
-                                });
-                            }
+        // Only LE bonds can be removed for the IRK to be replaced. Devices of unknown type may
+        // also have LE keys, so they block it like dual-mode ones.
+        let mut le_bonded_devices = vec![];
+        let mut blocking_devices = vec![];
+        for device in self.get_bonded_devices() {
+            match self.get_remote_type(device.clone()) {
+                BtDeviceType::Ble => le_bonded_devices.push(device),
+                BtDeviceType::Bredr => (),
+                _ => blocking_devices.push(device),
+            }
+        }
 
-                            Profile::Bas => {
-                                let tx = self.tx.clone();
-                                let device_to_send = device.clone();
-                                topstack::get_runtime().spawn(async move {
-                                    let _ = tx
-                                        .send(Message::BatteryService(
-                                            BatteryServiceActions::Disconnect(device_to_send),
-                                        ))
-                                        .await;
-                                });
-                            }
+        if !blocking_devices.is_empty() {
+            warn!(
+                "Can't regenerate the local IRK while dual-mode devices are bonded: {:?}",
+                blocking_devices.iter().map(|d| d.address.clone()).collect::<Vec<String>>()
+            );
+            self.report_irk_regenerated(BtStatus::Fail, blocking_devices);
+            return true;
+        }
 
-                            // We don't connect most profiles
-                            _ => (),
-                        }
-                    }
-                }
-                _ => {}
+        // Check every bond up front, so that none is removed if the IRK can't be regenerated.
+        if let Some(device) =
+            le_bonded_devices.iter().find(|d| RawAddress::from_string(d.address.clone()).is_none())
+        {
+            warn!("Can't unbond {}, keeping the local IRK", device.address);
+            return false;
+        }
+
+        if le_bonded_devices.is_empty() {
+            self.finish_irk_regeneration();
+            return true;
+        }
+
+        // The IRK is regenerated once the stack reports every LE bond removed, so that no bond is
+        // left using the previous key.
+        self.irk_regeneration_pending =
+            Some(le_bonded_devices.iter().map(|d| d.address.clone()).collect());
+        for device in le_bonded_devices {
+            if !self.remove_bond(device.clone()) {
+                // The stack only rejects removals once it's shutting down, which happens before
+                // the first bond is removed.
+                warn!("Failed to unbond {}, keeping the local IRK", device.address);
+                self.irk_regeneration_pending = None;
+                return false;
             }
         }
 
-        return true;
+        true
     }
 
     fn is_wbs_supported(&self) -> bool {
@@ -2530,6 +3883,7 @@ impl BtifSdpCallbacks for Bluetooth {
                 BtSdpRecord::SapServer(record) => record.hdr.uuid = uuid.clone(),
                 BtSdpRecord::Dip(record) => record.hdr.uuid = uuid.clone(),
                 BtSdpRecord::Mps(record) => record.hdr.uuid = uuid.clone(),
+                BtSdpRecord::Custom(record) => record.hdr.uuid = uuid.clone(),
             };
         });
         self.callbacks.for_all_callbacks(|callback| {
@@ -2562,6 +3916,7 @@ impl BtifHHCallbacks for Bluetooth {
             }
         };
 
+        let is_connected = state == BthhConnectionState::Connected;
         metrics::profile_connection_state_changed(
             address,
             profile as u32,
@@ -2575,6 +3930,15 @@ impl BtifHHCallbacks for Bluetooth {
                 DisplayAddress(&address)
             );
             self.hh.as_ref().unwrap().disconnect(&mut address);
+            return;
+        }
+
+        let policy = self.connection_policies.get(&self.resolve_address(&address.to_string()));
+        let is_forbidden = UuidHelper::get_profile_uuid(&profile)
+            .map_or(false, |uuid| policy.get_policy(uuid) == ConnectionPolicy::Forbidden);
+        if is_connected && is_forbidden {
+            warn!("[{}]: Disconnecting forbidden {:?}", DisplayAddress(&address), profile);
+            self.hh.as_ref().unwrap().disconnect(&mut address);
         }
     }
 
@@ -2678,3 +4042,229 @@ impl IBluetoothQALegacy for Bluetooth {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(props: Vec<BluetoothProperty>) -> HashMap<BtPropertyType, BluetoothProperty> {
+        props.into_iter().map(|prop| (prop.get_type(), prop)).collect()
+    }
+
+    #[test]
+    fn test_default_discovery_filter_matches_all() {
+        let filter = DiscoveryFilter::default();
+        assert!(filter.matches(&properties(vec![])));
+        assert!(
+            filter.matches(&properties(vec![BluetoothProperty::TypeOfDevice(BtDeviceType::Ble)]))
+        );
+        assert_eq!(filter.duration(), Duration::from_millis(DEFAULT_DISCOVERY_TIMEOUT_MS));
+    }
+
+    #[test]
+    fn test_discovery_filter_transport() {
+        let filter = DiscoveryFilter { transport: BtTransport::Bredr, ..Default::default() };
+        let ble = properties(vec![BluetoothProperty::TypeOfDevice(BtDeviceType::Ble)]);
+        let dual = properties(vec![BluetoothProperty::TypeOfDevice(BtDeviceType::Dual)]);
+        assert!(!filter.matches(&ble));
+        assert!(filter.matches(&dual));
+        assert!(!filter.includes_transport(BtTransport::Le));
+    }
+
+    #[test]
+    fn test_discovery_filter_rssi_uuids_and_class() {
+        let uuid: Uuid128Bit = [1; 16];
+        let filter = DiscoveryFilter {
+            rssi_threshold: Some(-70),
+            uuids: vec![uuid],
+            major_device_class: Some(0x04),
+            ..Default::default()
+        };
+        let matching = vec![
+            BluetoothProperty::RemoteRssi(-60),
+            BluetoothProperty::Uuids(vec![Uuid::from(uuid)]),
+            BluetoothProperty::ClassOfDevice(0x240404),
+        ];
+        assert!(filter.matches(&properties(matching.clone())));
+
+        let mut weak = matching.clone();
+        weak[0] = BluetoothProperty::RemoteRssi(-80);
+        assert!(!filter.matches(&properties(weak)));
+
+        let mut other_service = matching.clone();
+        other_service[1] = BluetoothProperty::Uuids(vec![Uuid::from([2; 16])]);
+        assert!(!filter.matches(&properties(other_service)));
+
+        let mut peripheral = matching.clone();
+        peripheral[2] = BluetoothProperty::ClassOfDevice(0x002540);
+        assert!(!filter.matches(&properties(peripheral)));
+
+        // Devices with unknown properties can't be matched.
+        assert!(!filter.matches(&properties(vec![])));
+    }
+
+    #[test]
+    fn test_discovery_filter_duration() {
+        let short = DiscoveryFilter { duration_ms: 5000, ..Default::default() };
+        assert_eq!(short.duration(), Duration::from_millis(5000));
+
+        let long = DiscoveryFilter { duration_ms: 60000, ..Default::default() };
+        assert_eq!(long.duration(), Duration::from_millis(DEFAULT_DISCOVERY_TIMEOUT_MS));
+
+        let long_le = DiscoveryFilter {
+            transport: BtTransport::Le,
+            duration_ms: 60000,
+            ..Default::default()
+        };
+        assert_eq!(long_le.duration(), Duration::from_millis(60000));
+    }
+
+    #[test]
+    fn test_discovery_filter_needs_timeout() {
+        // The default discovery ends with the inquiry.
+        assert!(!DiscoveryFilter::default().needs_timeout());
+
+        let short = DiscoveryFilter { duration_ms: 5000, ..Default::default() };
+        assert!(short.needs_timeout());
+
+        let long = DiscoveryFilter { duration_ms: 60000, ..Default::default() };
+        assert!(!long.needs_timeout());
+
+        let le = DiscoveryFilter { transport: BtTransport::Le, ..Default::default() };
+        assert!(le.needs_timeout());
+    }
+
+    #[test]
+    fn test_merge_device_types() {
+        assert_eq!(merge_device_types(BtDeviceType::Unknown, BtDeviceType::Ble), BtDeviceType::Ble);
+        assert_eq!(merge_device_types(BtDeviceType::Ble, BtDeviceType::Ble), BtDeviceType::Ble);
+        assert_eq!(merge_device_types(BtDeviceType::Bredr, BtDeviceType::Ble), BtDeviceType::Dual);
+        assert_eq!(merge_device_types(BtDeviceType::Dual, BtDeviceType::Bredr), BtDeviceType::Dual);
+    }
+
+    #[test]
+    fn test_merge_consolidated_device() {
+        let mut bredr = BluetoothDeviceContext::new(
+            BtBondState::Bonded,
+            BtAclState::Disconnected,
+            BluetoothDevice::new(String::from("11:22:33:44:55:66"), String::from("")),
+            Instant::now(),
+            vec![BluetoothProperty::TypeOfDevice(BtDeviceType::Dual)],
+        );
+        let le = BluetoothDeviceContext::new(
+            BtBondState::NotBonded,
+            BtAclState::Connected,
+            BluetoothDevice::new(String::from("C0:11:22:33:44:55"), String::from("Earbuds")),
+            Instant::now(),
+            vec![
+                BluetoothProperty::TypeOfDevice(BtDeviceType::Ble),
+                BluetoothProperty::Appearance(0x0941),
+            ],
+        );
+
+        bredr.merge(le);
+
+        assert_eq!(bredr.info.address, "11:22:33:44:55:66");
+        assert_eq!(bredr.info.name, "Earbuds");
+        assert_eq!(bredr.bond_state, BtBondState::Bonded);
+        assert_eq!(bredr.acl_state, BtAclState::Connected);
+        assert!(matches!(
+            bredr.properties.get(&BtPropertyType::TypeOfDevice),
+            Some(BluetoothProperty::TypeOfDevice(BtDeviceType::Dual))
+        ));
+        assert!(matches!(
+            bredr.properties.get(&BtPropertyType::Appearance),
+            Some(BluetoothProperty::Appearance(0x0941))
+        ));
+    }
+
+    #[test]
+    fn test_add_consolidated_bonded_device() {
+        let le_address = String::from("C0:11:22:33:44:55");
+        let bredr_address = String::from("11:22:33:44:55:66");
+        let mut bonded_devices = HashMap::new();
+        let mut found_devices = HashMap::new();
+
+        // The stack consolidates the LE address of a dual-mode device before reporting its bonds.
+        let mut device = BluetoothDeviceContext::new(
+            BtBondState::NotBonded,
+            BtAclState::Disconnected,
+            BluetoothDevice::new(bredr_address.clone(), String::from("")),
+            Instant::now(),
+            vec![BluetoothProperty::TypeOfDevice(BtDeviceType::Dual)],
+        );
+        device.consolidated_address = Some(le_address.clone());
+        found_devices.insert(bredr_address.clone(), device);
+        let address_aliases = HashMap::from([(le_address.clone(), bredr_address.clone())]);
+
+        // Bonded devices are reported again every time the adapter properties are read.
+        for _ in 0..2 {
+            for address in [&le_address, &bredr_address] {
+                add_bonded_device(
+                    &mut bonded_devices,
+                    &mut found_devices,
+                    &address_aliases,
+                    address,
+                );
+            }
+
+            assert!(found_devices.is_empty());
+            assert_eq!(bonded_devices.len(), 1);
+            let device = bonded_devices.get(&bredr_address).unwrap();
+            assert_eq!(device.bond_state, BtBondState::Bonded);
+            assert_eq!(device.consolidated_address, Some(le_address.clone()));
+        }
+    }
+
+    fn security_info(link_key_type: u8) -> BtRemoteSecurityInfo {
+        BtRemoteSecurityInfo { link_key_type, ..Default::default() }
+    }
+
+    #[test]
+    fn test_link_key_type() {
+        assert_eq!(RemoteSecurityInfo::from(security_info(0xff)).link_key_type, LinkKeyType::None);
+        assert_eq!(
+            RemoteSecurityInfo::from(security_info(0x00)).link_key_type,
+            LinkKeyType::Legacy
+        );
+        assert_eq!(
+            RemoteSecurityInfo::from(security_info(0x07)).link_key_type,
+            LinkKeyType::UnauthenticatedP256
+        );
+    }
+
+    #[test]
+    fn test_security_level() {
+        let disconnected = RemoteSecurityInfo::from(security_info(0x08));
+        assert_eq!(disconnected.security_level, SecurityLevel::None);
+
+        let bredr_sc = BtRemoteSecurityInfo {
+            bredr_encrypted: true,
+            bredr_authenticated: true,
+            ..security_info(0x08)
+        };
+        assert_eq!(
+            RemoteSecurityInfo::from(bredr_sc).security_level,
+            SecurityLevel::AuthenticatedSecureConnections
+        );
+
+        let bredr_legacy = BtRemoteSecurityInfo {
+            bredr_encrypted: true,
+            bredr_authenticated: true,
+            ..security_info(0x05)
+        };
+        assert_eq!(
+            RemoteSecurityInfo::from(bredr_legacy).security_level,
+            SecurityLevel::Authenticated
+        );
+
+        // The highest level of both transports is reported.
+        let mixed = BtRemoteSecurityInfo {
+            bredr_encrypted: true,
+            le_encrypted: true,
+            le_authenticated: true,
+            ..security_info(0x04)
+        };
+        assert_eq!(RemoteSecurityInfo::from(mixed).security_level, SecurityLevel::Authenticated);
+    }
+}
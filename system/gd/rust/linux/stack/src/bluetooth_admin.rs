@@ -5,7 +5,7 @@ use std::fs::File;
 use std::io::{Read, Result, Write};
 use std::sync::{Arc, Mutex};
 
-use crate::bluetooth::{Bluetooth, BluetoothDevice, IBluetooth};
+use crate::bluetooth::{Bluetooth, BluetoothDevice, IBluetooth, IBluetoothDeviceCallback};
 use crate::callbacks::Callbacks;
 use crate::uuid::UuidHelper;
 use crate::{Message, RPCProxy};
@@ -157,47 +157,6 @@ impl BluetoothAdmin {
             PolicyEffect { service_blocked, affected }
         })
     }
-
-    pub fn on_device_found(&mut self, remote_device: &BluetoothDevice) {
-        self.device_policy_affect_cache.insert(remote_device.clone(), None).or_else(|| {
-            self.callbacks.for_all_callbacks(|cb| {
-                cb.on_device_policy_effect_changed(remote_device.clone(), None);
-            });
-            None
-        });
-    }
-
-    pub fn on_device_cleared(&mut self, remote_device: &BluetoothDevice) {
-        self.device_policy_affect_cache.remove(remote_device);
-    }
-
-    pub fn on_remote_device_properties_changed(
-        &mut self,
-        remote_device: &BluetoothDevice,
-        properties: &Vec<BluetoothProperty>,
-    ) {
-        let new_uuids = properties.iter().find_map(|p| match p {
-            BluetoothProperty::Uuids(uuids) => {
-                Some(uuids.iter().map(|&x| x.uu.clone()).collect::<Vec<Uuid128Bit>>())
-            }
-            _ => None,
-        });
-
-        // No need to update policy effect if remote UUID is not changed.
-        if new_uuids.is_none() {
-            return;
-        }
-
-        let new_effect = self.new_device_policy_effect(new_uuids);
-        let cur_effect = self.device_policy_affect_cache.get(remote_device);
-
-        if cur_effect.is_none() || *cur_effect.unwrap() != new_effect.clone() {
-            self.callbacks.for_all_callbacks(|cb| {
-                cb.on_device_policy_effect_changed(remote_device.clone(), new_effect.clone())
-            });
-            self.device_policy_affect_cache.insert(remote_device.clone(), new_effect.clone());
-        }
-    }
 }
 
 impl IBluetoothAdmin for BluetoothAdmin {
@@ -276,6 +235,58 @@ impl IBluetoothAdmin for BluetoothAdmin {
     }
 }
 
+impl IBluetoothDeviceCallback for BluetoothAdmin {
+    fn on_device_found(&mut self, remote_device: BluetoothDevice) {
+        self.device_policy_affect_cache.insert(remote_device.clone(), None).or_else(|| {
+            self.callbacks.for_all_callbacks(|cb| {
+                cb.on_device_policy_effect_changed(remote_device.clone(), None);
+            });
+            None
+        });
+    }
+
+    fn on_device_cleared(&mut self, remote_device: BluetoothDevice) {
+        self.device_policy_affect_cache.remove(&remote_device);
+    }
+
+    fn on_remote_device_properties_changed(
+        &mut self,
+        remote_device: BluetoothDevice,
+        properties: Vec<BluetoothProperty>,
+    ) {
+        let new_uuids = properties.iter().find_map(|p| match p {
+            BluetoothProperty::Uuids(uuids) => {
+                Some(uuids.iter().map(|&x| x.uu.clone()).collect::<Vec<Uuid128Bit>>())
+            }
+            _ => None,
+        });
+
+        // No need to update policy effect if remote UUID is not changed.
+        if new_uuids.is_none() {
+            return;
+        }
+
+        let new_effect = self.new_device_policy_effect(new_uuids);
+        let cur_effect = self.device_policy_affect_cache.get(&remote_device);
+
+        if cur_effect.is_none() || *cur_effect.unwrap() != new_effect.clone() {
+            self.callbacks.for_all_callbacks(|cb| {
+                cb.on_device_policy_effect_changed(remote_device.clone(), new_effect.clone())
+            });
+            self.device_policy_affect_cache.insert(remote_device.clone(), new_effect.clone());
+        }
+    }
+
+    fn on_device_consolidated(
+        &mut self,
+        _remote_device: BluetoothDevice,
+        secondary_address: String,
+    ) {
+        // The policy effect is tracked under the address the device is consolidated into.
+        self.device_policy_affect_cache.retain(|d, _| d.address != secondary_address);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::bluetooth_admin::{BluetoothAdmin, IBluetoothAdmin};
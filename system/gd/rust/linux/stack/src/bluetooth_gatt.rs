@@ -5,19 +5,22 @@ use btif_macros::{btif_callback, btif_callbacks_dispatcher};
 use bt_topshim::bindings::root::bluetooth::Uuid;
 use bt_topshim::btif::{BluetoothInterface, BtStatus, BtTransport, RawAddress, Uuid128Bit};
 use bt_topshim::profiles::gatt::{
-    ffi::RustAdvertisingTrackInfo, AdvertisingStatus, BtGattDbElement, BtGattNotifyParams,
-    BtGattReadParams, BtGattResponse, BtGattValue, Gatt, GattAdvCallbacks,
+    ffi::RustAdvertisingTrackInfo, AdvertisingStatus, ApcfCommand, BtGattDbElement,
+    BtGattNotifyParams, BtGattReadParams, BtGattResponse, BtGattValue, Gatt, GattAdvCallbacks,
     GattAdvCallbacksDispatcher, GattAdvInbandCallbacksDispatcher, GattClientCallbacks,
-    GattClientCallbacksDispatcher, GattScannerCallbacks, GattScannerCallbacksDispatcher,
-    GattScannerInbandCallbacks, GattScannerInbandCallbacksDispatcher, GattServerCallbacks,
-    GattServerCallbacksDispatcher, GattStatus, LePhy, MsftAdvMonitor, MsftAdvMonitorPattern,
+    GattClientCallbacksDispatcher, GattFilterParam, GattScannerCallbacks,
+    GattScannerCallbacksDispatcher, GattScannerInbandCallbacks,
+    GattScannerInbandCallbacksDispatcher, GattServerCallbacks, GattServerCallbacksDispatcher,
+    GattStatus, LePhy, MsftAdvMonitor, MsftAdvMonitorPattern,
 };
 use bt_topshim::sysprop;
 use bt_topshim::topstack;
 use bt_utils::adv_parser;
 use bt_utils::array_utils;
 
+use crate::adv_monitor::{AdvMonitor, AdvMonitorEvent};
 use crate::async_helper::{AsyncHelper, CallbackSender};
+use crate::batch_scan::{self, BatchScanBuffer, BatchScanSettings};
 use crate::bluetooth::{Bluetooth, IBluetooth};
 use crate::bluetooth_adv::{
     AdvertiseData, Advertisers, AdvertisingSetInfo, AdvertisingSetParameters,
@@ -26,6 +29,8 @@ use crate::bluetooth_adv::{
 use crate::callbacks::Callbacks;
 use crate::uuid::UuidHelper;
 use crate::{Message, RPCProxy, SuspendMode};
+use aes::cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit};
+use aes::Aes128;
 use log::{debug, warn};
 use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::cast::{FromPrimitive, ToPrimitive};
@@ -35,7 +40,10 @@ use rand::{RngCore, SeedableRng};
 use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::Sender;
+use tokio::task::JoinHandle;
+use tokio::time;
 
 struct Client {
     id: Option<i32>,
@@ -394,6 +402,10 @@ pub trait IBluetoothGatt {
     /// Deactivate scan of the given scanner id.
     fn stop_scan(&mut self, scanner_id: u8) -> BtStatus;
 
+    /// Reports the scan results batched by the given scanner id without waiting for the report
+    /// delay of its `ScanSettings`.
+    fn flush_pending_batch_results(&mut self, scanner_id: u8) -> BtStatus;
+
     /// Returns the current suspend mode.
     fn get_scan_suspend_mode(&self) -> SuspendMode;
 
@@ -1107,6 +1119,12 @@ pub trait IScannerCallback: RPCProxy {
     // on_advertisement_found.
     fn on_advertisement_lost(&mut self, scanner_id: u8, scan_result: ScanResult);
 
+    /// When the scan results batched by a scanner are reported, after the report delay of its
+    /// `ScanSettings` or when flushed with `IBluetoothGatt::flush_pending_batch_results`. The
+    /// batched scan results aren't reported to on_scan_result, unless another active scanner of
+    /// the client doesn't batch them.
+    fn on_batch_scan_results(&mut self, scanner_id: u8, scan_results: Vec<ScanResult>);
+
     /// When LE Scan module changes suspend mode due to system suspend/resume.
     fn on_suspend_mode_change(&mut self, suspend_mode: SuspendMode);
 }
@@ -1158,6 +1176,22 @@ impl Default for ScanType {
     }
 }
 
+#[derive(Debug, FromPrimitive, ToPrimitive, Clone, Copy, PartialEq)]
+#[repr(u32)]
+/// How batched scan results are stored.
+pub enum BatchScanMode {
+    /// Only the address, TX power and RSSI of the advertisements are stored.
+    Truncated = 1,
+    /// The advertisement and scan response data are stored too.
+    Full = 2,
+}
+
+impl Default for BatchScanMode {
+    fn default() -> Self {
+        BatchScanMode::Full
+    }
+}
+
 /// Represents scanning configurations to be passed to `IBluetoothGatt::start_scan`.
 ///
 /// This configuration is general and supported on all Bluetooth hardware, irrelevant of the
@@ -1167,10 +1201,38 @@ pub struct ScanSettings {
     pub interval: i32,
     pub window: i32,
     pub scan_type: ScanType,
+    /// Delay in milliseconds between the reports of the batched scan results to
+    /// `IScannerCallback::on_batch_scan_results`, or 0 to not batch the scan results.
+    pub report_delay_millis: u32,
+    /// How the scan results are stored while batched.
+    pub batch_mode: BatchScanMode,
+}
+
+impl ScanSettings {
+    /// Returns the batching of the scan results, if requested. The scan interval and window also
+    /// apply to the batching in the controller, and default to a low duty cycle when unset.
+    fn batch_settings(&self) -> Option<BatchScanSettings> {
+        if self.report_delay_millis == 0 {
+            return None;
+        }
+
+        let interval: Option<u16> = self.interval.try_into().ok();
+        let window: Option<u16> = self.window.try_into().ok();
+        Some(BatchScanSettings {
+            mode: self.batch_mode,
+            report_delay: Duration::from_millis(self.report_delay_millis.into()),
+            scan_interval: interval
+                .filter(|&interval| interval > 0)
+                .unwrap_or(batch_scan::DEFAULT_SCAN_INTERVAL),
+            scan_window: window
+                .filter(|&window| window > 0)
+                .unwrap_or(batch_scan::DEFAULT_SCAN_WINDOW),
+        })
+    }
 }
 
 /// Represents scan result
-#[derive(Debug)]
+#[derive(Debug, Clone, Default)]
 pub struct ScanResult {
     pub name: String,
     pub address: String,
@@ -1203,9 +1265,24 @@ pub struct ScanFilterPattern {
     pub content: Vec<u8>,
 }
 
+/// The Identity Resolving Key of a device to match advertisements with.
+#[derive(Debug, Clone)]
+pub struct ScanFilterIrk {
+    /// The identity address of the device.
+    pub address: String,
+
+    /// The type of the identity address, 0 for public and 1 for random static.
+    pub address_type: u8,
+
+    /// The 16 bytes of the key, least significant byte first.
+    pub irk: Vec<u8>,
+}
+
 /// Represents the condition for matching advertisements.
 ///
-/// Only pattern-based matching is implemented.
+/// Patterns are monitored by MSFT advertisement monitors when the controller supports them. The
+/// other conditions are monitored in the host, and offloaded to APCF filters when the controller
+/// supports them to reduce the scan results.
 #[derive(Debug, Clone)]
 pub enum ScanFilterCondition {
     /// All advertisements are matched.
@@ -1214,14 +1291,153 @@ pub enum ScanFilterCondition {
     /// Match by pattern anywhere in the advertisement data. Multiple patterns are "OR"-ed.
     Patterns(Vec<ScanFilterPattern>),
 
-    /// Match by UUID (not implemented).
-    Uuid,
+    /// Match by service UUID.
+    Uuid(Uuid128Bit),
+
+    /// Match if the IRK resolves the advertiser's address, or the advertiser uses the identity
+    /// address.
+    Irk(ScanFilterIrk),
+
+    /// Match by Bluetooth address.
+    BluetoothAddress(String),
+}
+
+/// BLE address type of a random advertiser address.
+const BLE_ADDR_RANDOM: u8 = 0x01;
+
+// APCF filter types, actions and logic types, as in btm_ble_api_types.h.
+const APCF_FILTER_TYPE_ADDRESS: u8 = 0;
+const APCF_FILTER_TYPE_SERVICE_UUID: u8 = 2;
+const APCF_ACTION_ADD: u8 = 0;
+const APCF_ACTION_DELETE: u8 = 1;
+const APCF_LIST_LOGIC_OR: u16 = 0;
+const APCF_FILTER_LOGIC_AND: u8 = 1;
+
+/// The address type of an APCF address filter matching all types of addresses.
+const APCF_ADDRESS_TYPE_ALL: u8 = 2;
+
+// APCF delivery modes of the advertisements matching a filter.
+const APCF_DELIVERY_MODE_IMMEDIATE: u8 = 0;
+const APCF_DELIVERY_MODE_BATCH: u8 = 2;
+
+impl ScanFilterCondition {
+    /// Whether the addresses and keys of the condition are well-formed.
+    fn is_valid(&self) -> bool {
+        match self {
+            ScanFilterCondition::Irk(irk) => {
+                RawAddress::from_string(irk.address.clone()).is_some() && irk.irk.len() == 16
+            }
+            ScanFilterCondition::BluetoothAddress(address) => {
+                RawAddress::from_string(address.clone()).is_some()
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether the condition is matched by an MSFT advertisement monitor.
+    fn is_msft_offloadable(&self) -> bool {
+        matches!(self, ScanFilterCondition::Patterns(_))
+    }
+
+    /// Returns the APCF filter matching the condition, if it can be matched by one.
+    fn to_apcf_command(&self) -> Option<ApcfCommand> {
+        let (type_, address, addr_type, uuid, irk) = match self {
+            ScanFilterCondition::Uuid(uuid) => {
+                (APCF_FILTER_TYPE_SERVICE_UUID, [0; 6], 0, *uuid, [0; 16])
+            }
+            ScanFilterCondition::Irk(irk) => (
+                APCF_FILTER_TYPE_ADDRESS,
+                RawAddress::from_string(irk.address.clone())?.to_byte_arr(),
+                irk.address_type,
+                [0; 16],
+                irk.irk.clone().try_into().ok()?,
+            ),
+            ScanFilterCondition::BluetoothAddress(address) => (
+                APCF_FILTER_TYPE_ADDRESS,
+                RawAddress::from_string(address.clone())?.to_byte_arr(),
+                APCF_ADDRESS_TYPE_ALL,
+                [0; 16],
+                [0; 16],
+            ),
+            _ => return None,
+        };
 
-    /// Match if the IRK resolves an advertisement (not implemented).
-    Irk,
+        Some(ApcfCommand {
+            type_,
+            address: RawAddress { address },
+            addr_type,
+            uuid: Uuid::from(uuid).into(),
+            uuid_mask: Uuid::from([0xff; 16]).into(),
+            name: vec![],
+            company: 0,
+            company_mask: 0,
+            ad_type: 0,
+            org_id: 0,
+            tds_flags: 0,
+            tds_flags_mask: 0,
+            meta_data_type: 0,
+            meta_data: vec![],
+            data: vec![],
+            data_mask: vec![],
+            irk,
+        })
+    }
+
+    /// Whether the advertisement of |result| matches the condition.
+    pub(crate) fn matches(&self, result: &ScanResult) -> bool {
+        let address = match RawAddress::from_string(result.address.clone()) {
+            Some(address) => address,
+            None => return false,
+        };
+        let adv_data = result.adv_data.as_slice();
 
-    /// Match by Bluetooth address (not implemented).
-    BluetoothAddress,
+        match self {
+            ScanFilterCondition::All => true,
+            ScanFilterCondition::Patterns(patterns) => patterns.iter().any(|pattern| {
+                let start = pattern.start_position as usize;
+                adv_parser::extract_data_by_type(adv_data, pattern.ad_type).iter().any(|data| {
+                    data.get(start..start + pattern.content.len())
+                        == Some(pattern.content.as_slice())
+                })
+            }),
+            ScanFilterCondition::Uuid(uuid) => {
+                adv_parser::extract_service_uuids(adv_data).contains(uuid)
+            }
+            ScanFilterCondition::Irk(irk) => {
+                RawAddress::from_string(irk.address.clone()) == Some(address)
+                    || (result.addr_type == BLE_ADDR_RANDOM
+                        && irk
+                            .irk
+                            .as_slice()
+                            .try_into()
+                            .map_or(false, |irk| rpa_matches_irk(&address, irk)))
+            }
+            ScanFilterCondition::BluetoothAddress(filter_address) => {
+                RawAddress::from_string(filter_address.clone()) == Some(address)
+            }
+        }
+    }
+}
+
+/// Whether |address| is a resolvable private address generated with |irk|, which is least
+/// significant byte first. See Core Spec 5.3 Vol 6 Part B 1.3.2.3.
+fn rpa_matches_irk(address: &RawAddress, irk: &[u8; 16]) -> bool {
+    let [prand0, prand1, prand2, hash0, hash1, hash2] = address.to_byte_arr();
+    if prand0 >> 6 != 0b01 {
+        return false;
+    }
+
+    // The AES key takes the most significant byte first.
+    let mut key = *irk;
+    key.reverse();
+    let cipher = Aes128::new(&GenericArray::from(key));
+
+    // ah(k, r) = e(k, padding || r) mod 2^24
+    let mut block = [0u8; 16];
+    block[13..].copy_from_slice(&[prand0, prand1, prand2]);
+    let mut block = GenericArray::from(block);
+    cipher.encrypt_block(&mut block);
+    block[13..] == [hash0, hash1, hash2]
 }
 
 /// Represents a scan filter to be passed to `IBluetoothGatt::start_scan`.
@@ -1242,7 +1458,8 @@ pub struct ScanFilter {
     /// being considered "lost".
     pub rssi_low_timeout: u8,
 
-    /// The sampling interval in milliseconds.
+    /// The sampling interval in units of 100 ms. An advertisement received within this period
+    /// after the last sampled one of the same device is ignored. 0 samples every advertisement.
     pub rssi_sampling_period: u8,
 
     /// The condition to match advertisements with.
@@ -1308,12 +1525,19 @@ impl GattAsyncIntf {
     }
 
     /// Updates the topshim's scan state depending on the states of registered scanners. Scan is
-    /// enabled if there is at least 1 active registered scanner.
+    /// enabled if there is at least 1 active registered scanner whose scan results aren't batched
+    /// by the controller, which scans on its own while batching.
     ///
     /// Note: this does not need to be async, but declared as async for consistency in this struct.
     /// May be converted into real async in the future if btif supports it.
     async fn update_scan(&mut self) {
-        if self.scanners.lock().unwrap().values().find(|scanner| scanner.is_active).is_some() {
+        if self
+            .scanners
+            .lock()
+            .unwrap()
+            .values()
+            .any(|scanner| scanner.is_active && !scanner.is_batched_by_controller)
+        {
             // Toggle the scan off and on so that we reset the scan parameters based on whether
             // we have active scanners using hardware filtering.
             // TODO(b/266752123): We can do more bookkeeping to optimize when we really need to
@@ -1342,6 +1566,9 @@ pub struct BluetoothGatt {
     scanners: Arc<Mutex<ScannersMap>>,
     scan_suspend_mode: SuspendMode,
     paused_scanner_ids: Vec<u8>,
+    is_apcf_enabled: bool,
+    // The pending check of the devices lost by the adv monitors of the host, and when it's due.
+    adv_monitor_lost_check: Option<(Instant, JoinHandle<()>)>,
     advertisers: Advertisers,
 
     adv_mon_add_cb_sender: CallbackSender<(u8, u8)>,
@@ -1353,6 +1580,8 @@ pub struct BluetoothGatt {
     small_rng: SmallRng,
 
     gatt_async: Arc<tokio::sync::Mutex<GattAsyncIntf>>,
+
+    tx: Sender<Message>,
 }
 
 impl BluetoothGatt {
@@ -1374,6 +1603,8 @@ impl BluetoothGatt {
             scanners: scanners.clone(),
             scan_suspend_mode: SuspendMode::Normal,
             paused_scanner_ids: Vec::new(),
+            is_apcf_enabled: false,
+            adv_monitor_lost_check: None,
             small_rng: SmallRng::from_entropy(),
             advertisers: Advertisers::new(tx.clone()),
             adv_mon_add_cb_sender: async_helper_msft_adv_monitor_add.get_callback_sender(),
@@ -1386,6 +1617,7 @@ impl BluetoothGatt {
                 async_helper_msft_adv_monitor_remove,
                 async_helper_msft_adv_monitor_enable,
             })),
+            tx,
         }
     }
 
@@ -1607,6 +1839,8 @@ impl BluetoothGatt {
             }
         };
 
+        // The batching is started first, so that the scan is updated for how it's batched.
+        self.start_batch_scan(scanner_id);
         self.add_monitor_and_update_scan(scanner_id, filter)
     }
 
@@ -1615,16 +1849,40 @@ impl BluetoothGatt {
         scanner_id: u8,
         filter: Option<ScanFilter>,
     ) -> BtStatus {
-        let has_active_unfiltered_scanner = self
-            .scanners
-            .lock()
-            .unwrap()
-            .iter()
-            .any(|(_uuid, scanner)| scanner.is_active && scanner.filter.is_none());
+        let is_msft_supported = self.is_msft_supported();
+        let mut has_active_unfiltered_scanner = self.has_active_unmonitored_scanner();
+        let is_batched_by_controller =
+            Self::find_scanner_by_id(&mut self.scanners.lock().unwrap(), scanner_id)
+                .map_or(false, |scanner| scanner.is_batched_by_controller);
+
+        // Conditions that MSFT monitors can't match are monitored in the host instead, and
+        // offloaded to APCF filters to reduce the scan results. The results batched by the
+        // controller are only reported in batches, so their filter is always an APCF filter in
+        // batch delivery mode. A filter from a previous scan is replaced.
+        self.remove_apcf_filter(scanner_id);
+        let filter = match filter {
+            _ if is_batched_by_controller => {
+                self.add_apcf_filter(scanner_id, filter.as_ref());
+                None
+            }
+            Some(filter) if is_msft_supported && filter.condition.is_msft_offloadable() => {
+                Some(filter)
+            }
+            Some(filter) => {
+                self.add_apcf_filter(scanner_id, Some(&filter));
+                if let Some(scanner) =
+                    Self::find_scanner_by_id(&mut self.scanners.lock().unwrap(), scanner_id)
+                {
+                    scanner.adv_monitor = Some(AdvMonitor::new(filter));
+                }
+                None
+            }
+            None => None,
+        };
+        self.update_apcf_enable();
 
         let gatt_async = self.gatt_async.clone();
         let scanners = self.scanners.clone();
-        let is_msft_supported = self.is_msft_supported();
 
         tokio::spawn(async move {
             // The three operations below (monitor add, monitor enable, update scan) happen one
@@ -1638,23 +1896,23 @@ impl BluetoothGatt {
             // Add and enable the monitor filter only when the MSFT extension is supported.
             if is_msft_supported {
                 if let Some(filter) = filter {
-                    let monitor_handle =
-                        match gatt_async.msft_adv_monitor_add((&filter).into()).await {
-                            Ok((handle, 0)) => handle,
-                            _ => {
-                                log::error!("Error adding advertisement monitor");
-                                return;
-                            }
-                        };
-
-                    if let Some(scanner) =
-                        Self::find_scanner_by_id(&mut scanners.lock().unwrap(), scanner_id)
-                    {
-                        // The monitor handle is needed in stop_scan().
-                        scanner.monitor_handle = Some(monitor_handle);
+                    let result = gatt_async.msft_adv_monitor_add((&filter).into()).await;
+                    match (
+                        result,
+                        Self::find_scanner_by_id(&mut scanners.lock().unwrap(), scanner_id),
+                    ) {
+                        (Ok((monitor_handle, 0)), Some(scanner)) => {
+                            // The monitor handle is needed in stop_scan().
+                            scanner.monitor_handle = Some(monitor_handle);
+                            log::debug!("Added adv monitor handle = {}", monitor_handle);
+                        }
+                        (_, Some(scanner)) => {
+                            log::error!("Error adding advertisement monitor, monitoring in host");
+                            scanner.adv_monitor = Some(AdvMonitor::new(filter));
+                            has_active_unfiltered_scanner = true;
+                        }
+                        (_, None) => {}
                     }
-
-                    log::debug!("Added adv monitor handle = {}", monitor_handle);
                 }
 
                 if !gatt_async
@@ -1682,6 +1940,342 @@ impl BluetoothGatt {
             .remove_callback(callback_id, &mut self.gatt.as_ref().unwrap().lock().unwrap())
     }
 
+    /// Whether an active scanner needs the advertisements that aren't matched by any MSFT
+    /// monitor. The controller batches the results of its scanner on its own.
+    fn has_active_unmonitored_scanner(&self) -> bool {
+        self.scanners.lock().unwrap().values().any(|scanner| {
+            scanner.is_active
+                && !scanner.is_batched_by_controller
+                && !scanner.filter.as_ref().map_or(false, |f| f.condition.is_msft_offloadable())
+        })
+    }
+
+    fn get_max_adv_filter_supported(&self) -> u8 {
+        match &self.adapter {
+            Some(adapter) => adapter.lock().unwrap().get_max_adv_filter_supported(),
+            None => 0,
+        }
+    }
+
+    /// Offloads the condition of |filter| to a free APCF filter of the controller, if there is one
+    /// and the condition can be matched by it. The scan results batched by the controller are
+    /// delivered to its storage by the filter, which matches all the advertisements if the
+    /// condition can't be matched by it.
+    fn add_apcf_filter(&mut self, scanner_id: u8, filter: Option<&ScanFilter>) {
+        let is_batched_by_controller =
+            match Self::find_scanner_by_id(&mut self.scanners.lock().unwrap(), scanner_id) {
+                Some(scanner) => scanner.is_batched_by_controller,
+                None => return,
+            };
+        let command = filter.and_then(|filter| filter.condition.to_apcf_command());
+        if command.is_none() && !is_batched_by_controller {
+            return;
+        }
+
+        let max_filters = self.get_max_adv_filter_supported();
+        let filter_index = {
+            let mut scanners = self.scanners.lock().unwrap();
+            let used: HashSet<u8> =
+                scanners.values().filter_map(|scanner| scanner.apcf_filter_index).collect();
+            let filter_index = match (0..max_filters).find(|index| !used.contains(index)) {
+                Some(filter_index) => filter_index,
+                None => {
+                    log::debug!("No APCF filter available for scanner {}", scanner_id);
+                    return;
+                }
+            };
+
+            match Self::find_scanner_by_id(&mut scanners, scanner_id) {
+                Some(scanner) => scanner.apcf_filter_index = Some(filter_index),
+                None => return,
+            }
+            filter_index
+        };
+
+        // Without a condition, no feature is selected and all the advertisements match.
+        let (feat_seln, rssi_high_thres, rssi_low_thres) = match (&command, filter) {
+            (Some(command), Some(filter)) => {
+                (1 << command.type_, filter.rssi_high_threshold, filter.rssi_low_threshold)
+            }
+            _ => (0, i8::MIN as u8, i8::MIN as u8),
+        };
+        let delay_mode = if is_batched_by_controller {
+            APCF_DELIVERY_MODE_BATCH
+        } else {
+            APCF_DELIVERY_MODE_IMMEDIATE
+        };
+        let param = GattFilterParam {
+            feat_seln,
+            list_logic_type: APCF_LIST_LOGIC_OR,
+            filt_logic_type: APCF_FILTER_LOGIC_AND,
+            rssi_high_thres,
+            rssi_low_thres,
+            delay_mode,
+            ..Default::default()
+        };
+
+        let mut gatt = self.gatt.as_ref().unwrap().lock().unwrap();
+        gatt.scanner.scan_filter_setup(scanner_id, APCF_ACTION_ADD, filter_index, param);
+        if let Some(command) = command {
+            gatt.scanner.scan_filter_add(filter_index, vec![command]);
+        }
+        log::debug!("Added APCF filter index = {}", filter_index);
+    }
+
+    /// Removes the APCF filter of |scanner_id|, if it has one.
+    fn remove_apcf_filter(&mut self, scanner_id: u8) {
+        let filter_index =
+            match Self::find_scanner_by_id(&mut self.scanners.lock().unwrap(), scanner_id) {
+                Some(scanner) => scanner.apcf_filter_index.take(),
+                None => None,
+            };
+
+        if let Some(filter_index) = filter_index {
+            let mut gatt = self.gatt.as_ref().unwrap().lock().unwrap();
+            gatt.scanner.scan_filter_clear(filter_index);
+            gatt.scanner.scan_filter_setup(
+                scanner_id,
+                APCF_ACTION_DELETE,
+                filter_index,
+                GattFilterParam::default(),
+            );
+        }
+    }
+
+    /// APCF filtering drops the advertisements that don't match any filter, so it's only enabled
+    /// when all the active scanners have an APCF filter.
+    fn update_apcf_enable(&mut self) {
+        let enable = {
+            let scanners = self.scanners.lock().unwrap();
+            let mut active = scanners.values().filter(|scanner| scanner.is_active).peekable();
+            active.peek().is_some() && active.all(|scanner| scanner.apcf_filter_index.is_some())
+        };
+
+        if enable == self.is_apcf_enabled {
+            return;
+        }
+        self.is_apcf_enabled = enable;
+
+        let mut gatt = self.gatt.as_ref().unwrap().lock().unwrap();
+        if enable {
+            gatt.scanner.scan_filter_enable();
+        } else {
+            gatt.scanner.scan_filter_disable();
+        }
+    }
+
+    fn dispatch_adv_monitor_events(&mut self, events: Vec<(u32, u8, AdvMonitorEvent)>) {
+        for (callback_id, scanner_id, event) in events {
+            if let Some(callback) = self.scanner_callbacks.get_by_id_mut(callback_id) {
+                match event {
+                    AdvMonitorEvent::Found(result) => {
+                        callback.on_advertisement_found(scanner_id, result)
+                    }
+                    AdvMonitorEvent::Lost(result) => {
+                        callback.on_advertisement_lost(scanner_id, result)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Schedules a check of the devices lost by the adv monitors of the host, for when the next
+    /// found device times out.
+    fn schedule_adv_monitor_lost_check(&mut self) {
+        let deadline = self
+            .scanners
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|scanner| scanner.adv_monitor.as_ref()?.next_lost_deadline())
+            .min();
+        let deadline = match deadline {
+            Some(deadline) => deadline,
+            None => return,
+        };
+
+        // A device found since the check was scheduled may time out before it's due.
+        match &self.adv_monitor_lost_check {
+            Some((pending, _)) if *pending <= deadline => return,
+            Some((_, handle)) => handle.abort(),
+            None => (),
+        }
+
+        let tx = self.tx.clone();
+        self.adv_monitor_lost_check = Some((
+            deadline,
+            tokio::spawn(async move {
+                time::sleep_until(deadline.into()).await;
+                let _ = tx.send(Message::AdvMonitorLostCheck).await;
+            }),
+        ));
+    }
+
+    /// Reports the devices of the adv monitors of the host that haven't been heard from in time.
+    pub fn check_adv_monitor_lost(&mut self) {
+        // The message may come from a check that was rescheduled after it was sent.
+        if let Some((_, handle)) = self.adv_monitor_lost_check.take() {
+            handle.abort();
+        }
+
+        let now = Instant::now();
+        let events: Vec<(u32, u8, AdvMonitorEvent)> =
+            self.scanners
+                .lock()
+                .unwrap()
+                .values_mut()
+                .filter_map(|scanner| {
+                    let lost = scanner.adv_monitor.as_mut()?.check_lost(now);
+                    let (callback_id, scanner_id) = (scanner.callback_id, scanner.scanner_id?);
+                    Some(lost.into_iter().map(move |result| {
+                        (callback_id, scanner_id, AdvMonitorEvent::Lost(result))
+                    }))
+                })
+                .flatten()
+                .collect();
+        self.dispatch_adv_monitor_events(events);
+        self.schedule_adv_monitor_lost_check();
+    }
+
+    fn is_le_batch_scan_supported(&self) -> bool {
+        match &self.adapter {
+            Some(adapter) => adapter.lock().unwrap().get_le_scan_result_storage_size() > 0,
+            None => false,
+        }
+    }
+
+    /// Starts batching the scan results of |scanner_id| if its settings request it. The results
+    /// are stored by the controller when it supports it and no other scanner uses its storage,
+    /// and buffered in the host otherwise.
+    fn start_batch_scan(&mut self, scanner_id: u8) {
+        let use_controller = self.is_le_batch_scan_supported()
+            && !self.scanners.lock().unwrap().values().any(|s| s.is_batched_by_controller);
+
+        let settings = {
+            let mut scanners = self.scanners.lock().unwrap();
+            let scanner = match Self::find_scanner_by_id(&mut scanners, scanner_id) {
+                Some(scanner) => scanner,
+                None => return,
+            };
+            let settings = match scanner.batch_settings {
+                Some(settings) => settings,
+                None => return,
+            };
+
+            if use_controller {
+                scanner.is_batched_by_controller = true;
+            } else {
+                scanner.batch_buffer = Some(BatchScanBuffer::new(settings.mode));
+            }
+
+            let tx = self.tx.clone();
+            scanner.batch_report_timer = Some(tokio::spawn(async move {
+                let mut interval = time::interval(settings.report_delay);
+                // The first tick completes immediately.
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    let _ = tx.send(Message::BatchScanReport(scanner_id)).await;
+                }
+            }));
+            settings
+        };
+
+        if use_controller {
+            // The storage is entirely allocated to the results of the requested mode.
+            let (full_percent, truncated_percent) = match settings.mode {
+                BatchScanMode::Full => (100, 0),
+                BatchScanMode::Truncated => (0, 100),
+            };
+            let mut gatt = self.gatt.as_ref().unwrap().lock().unwrap();
+            gatt.scanner.batchscan_config_storage(
+                scanner_id,
+                full_percent,
+                truncated_percent,
+                batch_scan::NOTIFY_THRESHOLD_PERCENT,
+            );
+            gatt.scanner.batchscan_enable(
+                settings.mode as i32,
+                settings.scan_interval,
+                settings.scan_window,
+                /*addr_type=*/ 0,
+                batch_scan::DISCARD_OLDEST,
+            );
+        }
+    }
+
+    /// Stops batching the scan results of |scanner_id|, and reports the pending ones.
+    fn stop_batch_scan(&mut self, scanner_id: u8) {
+        let (mode, results, was_batched_by_controller) = {
+            let mut scanners = self.scanners.lock().unwrap();
+            let scanner = match Self::find_scanner_by_id(&mut scanners, scanner_id) {
+                Some(scanner) => scanner,
+                None => return,
+            };
+            match scanner.batch_report_timer.take() {
+                Some(timer) => timer.abort(),
+                None => return,
+            }
+            (
+                scanner.batch_settings.map(|settings| settings.mode),
+                scanner.batch_buffer.take().map(|mut buffer| buffer.take()),
+                std::mem::take(&mut scanner.is_batched_by_controller),
+            )
+        };
+
+        if let Some(results) = results {
+            self.report_batch_scan_results_to_client(scanner_id, results);
+        }
+
+        if was_batched_by_controller {
+            let mut gatt = self.gatt.as_ref().unwrap().lock().unwrap();
+            if let Some(mode) = mode {
+                gatt.scanner.batchscan_read_reports(scanner_id, mode as i32);
+            }
+            gatt.scanner.batchscan_disable();
+        }
+    }
+
+    /// Reports the scan results batched by |scanner_id|. Results stored by the controller are
+    /// reported once read, in on_batch_scan_reports.
+    pub fn report_batch_scan_results(&mut self, scanner_id: u8) {
+        let (mode, results, is_batched_by_controller) =
+            match Self::find_scanner_by_id(&mut self.scanners.lock().unwrap(), scanner_id) {
+                Some(scanner) => (
+                    scanner.batch_settings.map(|settings| settings.mode),
+                    scanner.batch_buffer.as_mut().map(|buffer| buffer.take()),
+                    scanner.is_batched_by_controller,
+                ),
+                None => return,
+            };
+
+        if is_batched_by_controller {
+            if let Some(mode) = mode {
+                self.gatt
+                    .as_ref()
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .scanner
+                    .batchscan_read_reports(scanner_id, mode as i32);
+            }
+        } else if let Some(results) = results {
+            self.report_batch_scan_results_to_client(scanner_id, results);
+        }
+    }
+
+    fn report_batch_scan_results_to_client(&mut self, scanner_id: u8, results: Vec<ScanResult>) {
+        let callback_id =
+            match Self::find_scanner_by_id(&mut self.scanners.lock().unwrap(), scanner_id) {
+                Some(scanner) => scanner.callback_id,
+                None => return,
+            };
+
+        if let Some(callback) = self.scanner_callbacks.get_by_id_mut(callback_id) {
+            callback.on_batch_scan_results(scanner_id, results);
+        }
+    }
+
     fn get_adapter_name(&self) -> String {
         if let Some(adapter) = &self.adapter {
             adapter.lock().unwrap().get_name()
@@ -1800,6 +2394,18 @@ struct ScannerInfo {
     filter: Option<ScanFilter>,
     // Adv monitor handle, if exists.
     monitor_handle: Option<u8>,
+    // APCF filter index, if the filter is offloaded to an APCF filter.
+    apcf_filter_index: Option<u8>,
+    // Monitor of the filter in the host, if it isn't monitored by the controller.
+    adv_monitor: Option<AdvMonitor>,
+    // Batching of the scan results, if requested.
+    batch_settings: Option<BatchScanSettings>,
+    // Batched scan results, if they are batched in the host instead of the controller.
+    batch_buffer: Option<BatchScanBuffer>,
+    // Periodic report of the batched scan results, while batching.
+    batch_report_timer: Option<JoinHandle<()>>,
+    // Whether the scan results are batched by the controller, which then scans on its own.
+    is_batched_by_controller: bool,
     // Used by start_scan() to determine if it is called because of system resuming.
     is_suspended: bool,
 }
@@ -1812,6 +2418,12 @@ impl ScannerInfo {
             is_active: false,
             filter: None,
             monitor_handle: None,
+            apcf_filter_index: None,
+            adv_monitor: None,
+            batch_settings: None,
+            batch_buffer: None,
+            batch_report_timer: None,
+            is_batched_by_controller: false,
             is_suspended: false,
         }
     }
@@ -1895,7 +2507,7 @@ impl IBluetoothGatt for BluetoothGatt {
     fn start_scan(
         &mut self,
         scanner_id: u8,
-        _settings: ScanSettings,
+        settings: ScanSettings,
         filter: Option<ScanFilter>,
     ) -> BtStatus {
         let scan_suspend_mode = self.get_scan_suspend_mode();
@@ -1903,6 +2515,14 @@ impl IBluetoothGatt for BluetoothGatt {
             return BtStatus::Busy;
         }
 
+        if !filter.as_ref().map_or(true, |filter| filter.condition.is_valid()) {
+            log::warn!("Invalid scan filter condition for scanner {}", scanner_id);
+            return BtStatus::InvalidParam;
+        }
+
+        // The batching of a previous scan is replaced.
+        self.stop_batch_scan(scanner_id);
+
         // Multiplexing scanners happens at this layer. The implementations of start_scan
         // and stop_scan maintains the state of all registered scanners and based on the states
         // update the scanning and/or filter states of libbluetooth.
@@ -1912,13 +2532,16 @@ impl IBluetoothGatt for BluetoothGatt {
             if let Some(scanner) = Self::find_scanner_by_id(&mut scanners_lock, scanner_id) {
                 scanner.is_active = true;
                 scanner.filter = filter.clone();
+                scanner.batch_settings = settings.batch_settings();
             } else {
                 log::warn!("Scanner {} not found", scanner_id);
                 return BtStatus::Fail;
             }
         }
 
-        return self.add_monitor_and_update_scan(scanner_id, filter);
+        // The batching is started first, so that the scan is updated for how it's batched.
+        self.start_batch_scan(scanner_id);
+        self.add_monitor_and_update_scan(scanner_id, filter)
     }
 
     fn stop_scan(&mut self, scanner_id: u8) -> BtStatus {
@@ -1933,7 +2556,8 @@ impl IBluetoothGatt for BluetoothGatt {
 
             if let Some(scanner) = Self::find_scanner_by_id(&mut scanners_lock, scanner_id) {
                 scanner.is_active = false;
-                scanner.monitor_handle
+                scanner.adv_monitor = None;
+                scanner.monitor_handle.take()
             } else {
                 log::warn!("Scanner {} not found", scanner_id);
                 // Clients can assume success of the removal since the scanner does not exist.
@@ -1941,12 +2565,11 @@ impl IBluetoothGatt for BluetoothGatt {
             }
         };
 
-        let has_active_unfiltered_scanner = self
-            .scanners
-            .lock()
-            .unwrap()
-            .iter()
-            .any(|(_uuid, scanner)| scanner.is_active && scanner.filter.is_none());
+        self.stop_batch_scan(scanner_id);
+        self.remove_apcf_filter(scanner_id);
+        self.update_apcf_enable();
+
+        let has_active_unfiltered_scanner = self.has_active_unmonitored_scanner();
 
         let gatt_async = self.gatt_async.clone();
         let is_msft_supported = self.is_msft_supported();
@@ -1978,6 +2601,25 @@ impl IBluetoothGatt for BluetoothGatt {
         BtStatus::Success
     }
 
+    fn flush_pending_batch_results(&mut self, scanner_id: u8) -> BtStatus {
+        let is_batching =
+            match Self::find_scanner_by_id(&mut self.scanners.lock().unwrap(), scanner_id) {
+                Some(scanner) => scanner.batch_report_timer.is_some(),
+                None => {
+                    log::warn!("Scanner {} not found", scanner_id);
+                    return BtStatus::Fail;
+                }
+            };
+
+        if !is_batching {
+            log::warn!("Scanner {} isn't batching scan results", scanner_id);
+            return BtStatus::Fail;
+        }
+
+        self.report_batch_scan_results(scanner_id);
+        BtStatus::Success
+    }
+
     fn get_scan_suspend_mode(&self) -> SuspendMode {
         self.scan_suspend_mode.clone()
     }
@@ -3837,6 +4479,19 @@ pub(crate) trait BtifGattScannerCallbacks {
 
     #[btif_callback(OnTrackAdvFoundLost)]
     fn on_track_adv_found_lost(&mut self, adv_track_info: RustAdvertisingTrackInfo);
+
+    #[btif_callback(OnBatchScanReports)]
+    fn on_batch_scan_reports(
+        &mut self,
+        client_if: i32,
+        status: i32,
+        report_format: i32,
+        num_records: i32,
+        data: Vec<u8>,
+    );
+
+    #[btif_callback(OnBatchScanThresholdCrossed)]
+    fn on_batch_scan_threshold_crossed(&mut self, client_if: i32);
 }
 
 #[btif_callbacks_dispatcher(dispatch_le_scanner_inband_callbacks, GattScannerInbandCallbacks)]
@@ -4094,24 +4749,65 @@ impl BtifGattScannerCallbacks for BluetoothGatt {
         periodic_adv_int: u16,
         adv_data: Vec<u8>,
     ) {
-        self.scanner_callbacks.for_all_callbacks(|callback| {
-            callback.on_scan_result(ScanResult {
-                name: adv_parser::extract_name(adv_data.as_slice()),
-                address: address.to_string(),
-                addr_type,
-                event_type,
-                primary_phy,
-                secondary_phy,
-                advertising_sid,
-                tx_power,
-                rssi,
-                periodic_adv_int,
-                flags: adv_parser::extract_flags(adv_data.as_slice()),
-                service_uuids: adv_parser::extract_service_uuids(adv_data.as_slice()),
-                service_data: adv_parser::extract_service_data(adv_data.as_slice()),
-                manufacturer_data: adv_parser::extract_manufacturer_data(adv_data.as_slice()),
-                adv_data: adv_data.clone(),
-            });
+        let scan_result = ScanResult {
+            name: adv_parser::extract_name(adv_data.as_slice()),
+            address: address.to_string(),
+            addr_type,
+            event_type,
+            primary_phy,
+            secondary_phy,
+            advertising_sid,
+            tx_power,
+            rssi,
+            periodic_adv_int,
+            flags: adv_parser::extract_flags(adv_data.as_slice()),
+            service_uuids: adv_parser::extract_service_uuids(adv_data.as_slice()),
+            service_data: adv_parser::extract_service_data(adv_data.as_slice()),
+            manufacturer_data: adv_parser::extract_manufacturer_data(adv_data.as_slice()),
+            adv_data: adv_data.clone(),
+        };
+
+        let now = Instant::now();
+        let events: Vec<(u32, u8, AdvMonitorEvent)> = self
+            .scanners
+            .lock()
+            .unwrap()
+            .values_mut()
+            .filter(|scanner| scanner.is_active)
+            .filter_map(|scanner| {
+                let event = scanner.adv_monitor.as_mut()?.on_scan_result(&scan_result, now)?;
+                Some((scanner.callback_id, scanner.scanner_id?, event))
+            })
+            .collect();
+        self.dispatch_adv_monitor_events(events);
+        self.schedule_adv_monitor_lost_check();
+
+        for scanner in self.scanners.lock().unwrap().values_mut().filter(|s| s.is_active) {
+            if let Some(buffer) = scanner.batch_buffer.as_mut() {
+                if scanner.filter.as_ref().map_or(true, |f| f.condition.matches(&scan_result)) {
+                    buffer.push(&scan_result);
+                }
+            }
+        }
+
+        // The clients whose active scanners all batch their scan results only receive them in
+        // batches.
+        let mut batching_callback_ids = HashSet::new();
+        let mut other_callback_ids = HashSet::new();
+        for scanner in self.scanners.lock().unwrap().values().filter(|s| s.is_active) {
+            if scanner.batch_report_timer.is_some() {
+                batching_callback_ids.insert(scanner.callback_id);
+            } else {
+                other_callback_ids.insert(scanner.callback_id);
+            }
+        }
+
+        self.scanner_callbacks.for_all_callbacks_with_id(|callback_id, callback| {
+            if !batching_callback_ids.contains(&callback_id)
+                || other_callback_ids.contains(&callback_id)
+            {
+                callback.on_scan_result(scan_result.clone());
+            }
         });
     }
 
@@ -4159,6 +4855,65 @@ impl BtifGattScannerCallbacks for BluetoothGatt {
             }
         });
     }
+
+    fn on_batch_scan_reports(
+        &mut self,
+        client_if: i32,
+        status: i32,
+        report_format: i32,
+        num_records: i32,
+        data: Vec<u8>,
+    ) {
+        log::debug!(
+            "on_batch_scan_reports scanner_id = {}, status = {}, num_records = {}",
+            client_if,
+            status,
+            num_records
+        );
+
+        if status != 0 {
+            log::error!("Error reading batch scan reports of scanner {}", client_if);
+            return;
+        }
+
+        let report_format = match BatchScanMode::from_i32(report_format) {
+            Some(report_format) => report_format,
+            None => {
+                log::warn!("Unknown batch scan report format {}", report_format);
+                return;
+            }
+        };
+        let scanner_id: u8 = match client_if.try_into() {
+            Ok(scanner_id) => scanner_id,
+            Err(_) => return,
+        };
+
+        let results = batch_scan::parse_reports(
+            report_format,
+            num_records.try_into().unwrap_or(0),
+            data.as_slice(),
+        );
+        let results = match Self::find_scanner_by_id(&mut self.scanners.lock().unwrap(), scanner_id)
+        {
+            // Truncated results have no advertisement data to match the filter with.
+            Some(scanner) => match (&scanner.filter, report_format) {
+                (Some(filter), BatchScanMode::Full) => {
+                    results.into_iter().filter(|result| filter.condition.matches(result)).collect()
+                }
+                _ => results,
+            },
+            None => return,
+        };
+        self.report_batch_scan_results_to_client(scanner_id, results);
+    }
+
+    fn on_batch_scan_threshold_crossed(&mut self, client_if: i32) {
+        log::debug!("on_batch_scan_threshold_crossed scanner_id = {}", client_if);
+
+        if let Ok(scanner_id) = client_if.try_into() {
+            self.report_batch_scan_results(scanner_id);
+        }
+    }
 }
 
 #[btif_callbacks_dispatcher(dispatch_le_adv_callbacks, GattAdvCallbacks)]
@@ -4579,4 +5334,64 @@ mod tests {
         assert!(found.is_some());
         assert_eq!(4, found.unwrap());
     }
+
+    #[test]
+    fn test_rpa_matches_irk() {
+        // The sample data of Core Spec 5.3 Vol 3 Part H D.7.
+        let irk: [u8; 16] = [
+            0x9b, 0x7d, 0x39, 0x0a, 0xa6, 0x10, 0x10, 0x34, 0x05, 0xad, 0xc8, 0x57, 0xa3, 0x34,
+            0x02, 0xec,
+        ];
+        let rpa = RawAddress::from_string("70:81:94:0D:FB:AA").unwrap();
+        assert!(rpa_matches_irk(&rpa, &irk));
+        assert!(!rpa_matches_irk(&RawAddress::from_string("70:81:94:0D:FB:AB").unwrap(), &irk));
+        assert!(!rpa_matches_irk(&rpa, &[0; 16]));
+    }
+
+    #[test]
+    fn test_scan_filter_condition_matches() {
+        // A 16-bit service UUID list of 0xFE2C, and manufacturer data 0x00E0 0x01 0x02.
+        let adv_data: Vec<u8> = vec![3, 0x03, 0x2C, 0xFE, 5, 0xFF, 0xE0, 0x00, 0x01, 0x02];
+        let result = |address: &str, addr_type| ScanResult {
+            address: String::from(address),
+            addr_type,
+            adv_data: adv_data.clone(),
+            ..Default::default()
+        };
+        let public = result("11:22:33:44:55:66", 0);
+
+        assert!(ScanFilterCondition::All.matches(&public));
+
+        let pattern =
+            |start_position, content| ScanFilterPattern { start_position, ad_type: 0xFF, content };
+        let patterns = ScanFilterCondition::Patterns(vec![pattern(2, vec![0x01, 0x02])]);
+        assert!(patterns.matches(&public));
+        let patterns = ScanFilterCondition::Patterns(vec![pattern(3, vec![0x02, 0x03])]);
+        assert!(!patterns.matches(&public));
+
+        let uuid = UuidHelper::from_string("0000fe2c-0000-1000-8000-00805f9b34fb").unwrap();
+        assert!(ScanFilterCondition::Uuid(uuid).matches(&public));
+        assert!(!ScanFilterCondition::Uuid([0; 16]).matches(&public));
+
+        let condition = ScanFilterCondition::BluetoothAddress(String::from("11:22:33:44:55:66"));
+        assert!(condition.is_valid());
+        assert!(condition.matches(&public));
+        assert!(!condition.matches(&result("11:22:33:44:55:67", 0)));
+        assert!(!ScanFilterCondition::BluetoothAddress(String::from("bogus")).is_valid());
+
+        let irk = ScanFilterIrk {
+            address: String::from("11:22:33:44:55:66"),
+            address_type: 0,
+            irk: vec![
+                0x9b, 0x7d, 0x39, 0x0a, 0xa6, 0x10, 0x10, 0x34, 0x05, 0xad, 0xc8, 0x57, 0xa3, 0x34,
+                0x02, 0xec,
+            ],
+        };
+        let condition = ScanFilterCondition::Irk(irk.clone());
+        assert!(condition.is_valid());
+        assert!(condition.matches(&public));
+        assert!(condition.matches(&result("70:81:94:0D:FB:AA", BLE_ADDR_RANDOM)));
+        assert!(!condition.matches(&result("70:81:94:0D:FB:AA", 0)));
+        assert!(!ScanFilterCondition::Irk(ScanFilterIrk { irk: vec![0; 15], ..irk }).is_valid());
+    }
 }
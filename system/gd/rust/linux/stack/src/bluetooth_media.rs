@@ -37,6 +37,7 @@ use crate::battery_provider_manager::{
 };
 use crate::bluetooth::{Bluetooth, BluetoothDevice, IBluetooth};
 use crate::callbacks::Callbacks;
+use crate::connection_policy::ConnectionPolicy;
 use crate::uuid;
 use crate::uuid::Profile;
 use crate::{Message, RPCProxy};
@@ -217,6 +218,8 @@ impl BluetoothAudioDevice {
 /// Actions that `BluetoothMedia` can take on behalf of the stack.
 pub enum MediaActions {
     Connect(String),
+    /// Connect only the given audio profiles of a device, e.g. those reconnected automatically.
+    ConnectProfiles(String, HashSet<Profile>),
     Disconnect(String),
     ForceEnterConnected(String), // Only used for qualification.
 }
@@ -254,6 +257,9 @@ pub struct BluetoothMedia {
     uinput: UInput,
     delay_enable_profiles: HashSet<uuid::Profile>,
     connected_profiles: HashMap<RawAddress, HashSet<uuid::Profile>>,
+    /// The audio profiles connected for devices whose connection was requested for only some of
+    /// them. Other devices connect all their audio profiles that aren't forbidden.
+    requested_profiles: HashMap<RawAddress, HashSet<uuid::Profile>>,
     device_states: Arc<Mutex<HashMap<RawAddress, DeviceConnectionStates>>>,
     delay_volume_update: HashMap<uuid::Profile, u8>,
     telephony_device_status: TelephonyDeviceStatus,
@@ -301,6 +307,7 @@ impl BluetoothMedia {
             uinput: UInput::new(),
             delay_enable_profiles: HashSet::new(),
             connected_profiles: HashMap::new(),
+            requested_profiles: HashMap::new(),
             device_states: Arc::new(Mutex::new(HashMap::new())),
             delay_volume_update: HashMap::new(),
             telephony_device_status: TelephonyDeviceStatus::new(),
@@ -450,6 +457,13 @@ impl BluetoothMedia {
                     BtavConnectionState::Connected => {
                         info!("[{}]: a2dp connected.", DisplayAddress(&addr));
                         self.a2dp_states.insert(addr, state);
+                        if self.is_profile_forbidden(addr, uuid::Profile::A2dpSink) {
+                            warn!("[{}]: Disconnecting forbidden a2dp.", DisplayAddress(&addr));
+                            if let Some(a2dp) = self.a2dp.as_mut() {
+                                a2dp.disconnect(addr);
+                            }
+                            return;
+                        }
                         self.add_connected_profile(addr, uuid::Profile::A2dpSink);
                     }
                     BtavConnectionState::Disconnected => {
@@ -458,7 +472,9 @@ impl BluetoothMedia {
                         self.a2dp_caps.remove(&addr);
                         self.a2dp_audio_state.remove(&addr);
                         self.rm_connected_profile(addr, uuid::Profile::A2dpSink, true);
-                        if self.is_complete_profiles_required() {
+                        if self.is_complete_profiles_required()
+                            && !self.is_profile_forbidden(addr, uuid::Profile::A2dpSink)
+                        {
                             self.disconnect(addr.to_string());
                         }
                     }
@@ -609,7 +625,13 @@ impl BluetoothMedia {
 
     pub fn dispatch_media_actions(&mut self, action: MediaActions) {
         match action {
-            MediaActions::Connect(address) => self.connect(address),
+            MediaActions::Connect(address) => self.connect_missing_profiles(address),
+            MediaActions::ConnectProfiles(address, profiles) => {
+                if let Some(addr) = RawAddress::from_string(address.clone()) {
+                    self.requested_profiles.insert(addr, profiles);
+                }
+                self.connect_missing_profiles(address);
+            }
             MediaActions::Disconnect(address) => self.disconnect(address),
             MediaActions::ForceEnterConnected(address) => self.force_enter_connected(address),
         }
@@ -632,6 +654,12 @@ impl BluetoothMedia {
                 match state {
                     BthfConnectionState::Connected => {
                         info!("[{}]: hfp connected.", DisplayAddress(&addr));
+                        if self.is_profile_forbidden(addr, uuid::Profile::Hfp) {
+                            warn!("[{}]: Disconnecting forbidden hfp.", DisplayAddress(&addr));
+                            if let Some(hfp) = self.hfp.as_mut() {
+                                hfp.disconnect(addr);
+                            }
+                        }
                     }
                     BthfConnectionState::SlcConnected => {
                         info!("[{}]: hfp slc connected.", DisplayAddress(&addr));
@@ -655,7 +683,9 @@ impl BluetoothMedia {
                         self.hfp_cap.remove(&addr);
                         self.hfp_audio_state.remove(&addr);
                         self.rm_connected_profile(addr, uuid::Profile::Hfp, true);
-                        if self.is_complete_profiles_required() {
+                        if self.is_complete_profiles_required()
+                            && !self.is_profile_forbidden(addr, uuid::Profile::Hfp)
+                        {
                             self.disconnect(addr.to_string());
                         }
                     }
@@ -1046,6 +1076,7 @@ impl BluetoothMedia {
         if is_profile_cleared {
             info!("[{}]: Device connection state: Disconnected.", DisplayAddress(&addr));
             self.connected_profiles.remove(&addr);
+            self.requested_profiles.remove(&addr);
             states.remove(&addr);
             guard.remove(&addr);
             return;
@@ -1208,24 +1239,219 @@ impl BluetoothMedia {
         }
     }
 
+    /// Returns the audio profiles of the device to connect, i.e. those its connection policy
+    /// doesn't forbid, limited to the requested ones if any.
     fn adapter_get_audio_profiles(&self, addr: RawAddress) -> HashSet<uuid::Profile> {
         let device = BluetoothDevice::new(addr.to_string(), "".to_string());
         if let Some(adapter) = &self.adapter {
+            let adapter = adapter.lock().unwrap();
+            let policy = adapter.get_connection_policy(device.clone());
+            let requested = self.requested_profiles.get(&addr);
             adapter
-                .lock()
-                .unwrap()
                 .get_remote_uuids(device)
                 .into_iter()
+                .filter(|u| policy.get_policy(u) != ConnectionPolicy::Forbidden)
                 .map(|u| uuid::UuidHelper::is_known_profile(&u))
                 .filter(|u| u.is_some())
                 .map(|u| u.unwrap())
                 .filter(|u| MEDIA_AUDIO_PROFILES.contains(&u))
+                .filter(|u| requested.map_or(true, |r| r.contains(u)))
                 .collect()
         } else {
             HashSet::new()
         }
     }
 
+    /// Connects the audio profiles of the device with |address| that aren't connected yet.
+    fn connect_missing_profiles(&mut self, address: String) {
+        let addr = match RawAddress::from_string(address.clone()) {
+            None => {
+                warn!("Invalid device address for connecting");
+                return;
+            }
+            Some(addr) => addr,
+        };
+
+        let available_profiles = self.adapter_get_audio_profiles(addr);
+
+        info!(
+            "[{}]: Connecting to device, available profiles: {:?}.",
+            DisplayAddress(&addr),
+            available_profiles
+        );
+
+        let connected_profiles = self.connected_profiles.entry(addr).or_insert_with(HashSet::new);
+
+        // Sort here so the order of connection is always consistent
+        let missing_profiles =
+            available_profiles.difference(&connected_profiles).sorted().collect::<Vec<_>>();
+
+        // Connect the profiles one-by-one so it won't stuck at the lower layer.
+        // Therefore, just connect to one profile for now.
+        // connect() will be called again after the first profile is successfully connected.
+        let mut is_connect = false;
+        for profile in missing_profiles {
+            match profile {
+                uuid::Profile::A2dpSink => {
+                    metrics::profile_connection_state_changed(
+                        addr,
+                        Profile::A2dpSink as u32,
+                        BtStatus::Success,
+                        BtavConnectionState::Connecting as u32,
+                    );
+                    match self.a2dp.as_mut() {
+                        Some(a2dp) => {
+                            let status: BtStatus = a2dp.connect(addr);
+                            if BtStatus::Success != status {
+                                metrics::profile_connection_state_changed(
+                                    addr,
+                                    Profile::A2dpSink as u32,
+                                    status,
+                                    BtavConnectionState::Disconnected as u32,
+                                );
+                            } else {
+                                is_connect = true;
+                                break;
+                            }
+                        }
+                        None => {
+                            warn!("Uninitialized A2DP to connect {}", DisplayAddress(&addr));
+                            metrics::profile_connection_state_changed(
+                                addr,
+                                Profile::A2dpSink as u32,
+                                BtStatus::NotReady,
+                                BtavConnectionState::Disconnected as u32,
+                            );
+                        }
+                    };
+                }
+                uuid::Profile::Hfp => {
+                    metrics::profile_connection_state_changed(
+                        addr,
+                        Profile::Hfp as u32,
+                        BtStatus::Success,
+                        BtavConnectionState::Connecting as u32,
+                    );
+                    match self.hfp.as_mut() {
+                        Some(hfp) => {
+                            let status: BtStatus = hfp.connect(addr);
+                            if BtStatus::Success != status {
+                                metrics::profile_connection_state_changed(
+                                    addr,
+                                    Profile::Hfp as u32,
+                                    status,
+                                    BthfConnectionState::Disconnected as u32,
+                                );
+                            } else {
+                                is_connect = true;
+                                break;
+                            }
+                        }
+                        None => {
+                            warn!("Uninitialized HFP to connect {}", DisplayAddress(&addr));
+                            metrics::profile_connection_state_changed(
+                                addr,
+                                Profile::Hfp as u32,
+                                BtStatus::NotReady,
+                                BthfConnectionState::Disconnected as u32,
+                            );
+                        }
+                    };
+                }
+                uuid::Profile::AvrcpController => {
+                    // Fluoride will resolve AVRCP as a part of A2DP connection request.
+                    // Explicitly connect to it only when it is considered missing, and don't
+                    // bother about it when A2DP is not connected.
+                    if !connected_profiles.contains(&Profile::A2dpSink) {
+                        continue;
+                    }
+
+                    metrics::profile_connection_state_changed(
+                        addr,
+                        Profile::AvrcpController as u32,
+                        BtStatus::Success,
+                        BtavConnectionState::Connecting as u32,
+                    );
+                    match self.avrcp.as_mut() {
+                        Some(avrcp) => {
+                            self.avrcp_direction = BtConnectionDirection::Outgoing;
+                            let status: BtStatus = avrcp.connect(addr);
+                            if BtStatus::Success != status {
+                                // Reset direction to unknown.
+                                self.avrcp_direction = BtConnectionDirection::Unknown;
+                                metrics::profile_connection_state_changed(
+                                    addr,
+                                    Profile::AvrcpController as u32,
+                                    status,
+                                    BtavConnectionState::Disconnected as u32,
+                                );
+                            } else {
+                                is_connect = true;
+                                break;
+                            }
+                        }
+
+                        None => {
+                            warn!("Uninitialized AVRCP to connect {}", DisplayAddress(&addr));
+                            metrics::profile_connection_state_changed(
+                                addr,
+                                Profile::AvrcpController as u32,
+                                BtStatus::NotReady,
+                                BtavConnectionState::Disconnected as u32,
+                            );
+                        }
+                    };
+                }
+                _ => warn!("Unknown profile: {:?}", profile),
+            }
+        }
+
+        if is_connect {
+            let mut tasks = self.fallback_tasks.lock().unwrap();
+            let mut states = self.device_states.lock().unwrap();
+            if !tasks.contains_key(&addr) {
+                states.insert(addr, DeviceConnectionStates::Initiating);
+
+                let fallback_tasks = self.fallback_tasks.clone();
+                let device_states = self.device_states.clone();
+                let now_ts = Instant::now();
+                let task = topstack::get_runtime().spawn(async move {
+                    sleep(Duration::from_secs(CONNECT_AS_INITIATOR_TIMEOUT_SEC)).await;
+
+                    // If here the task is not yet aborted, probably connection is failed,
+                    // therefore here we release the states. Even if later the connection is
+                    // actually successful, we will just treat this as if the connection is
+                    // initiated by the peer and will reconnect the missing profiles after
+                    // some time, so it's safe.
+                    {
+                        device_states.lock().unwrap().remove(&addr);
+                        fallback_tasks.lock().unwrap().remove(&addr);
+                    }
+                });
+                tasks.insert(addr, Some((task, now_ts)));
+            }
+        } else if self.connected_profiles.get(&addr).map_or(true, |p| p.is_empty()) {
+            // Nothing is being connected, so a connection from the peer isn't limited.
+            self.requested_profiles.remove(&addr);
+        }
+    }
+
+    /// Whether the connection policy of the device with |addr| forbids |profile|.
+    fn is_profile_forbidden(&self, addr: RawAddress, profile: uuid::Profile) -> bool {
+        let uuid = match uuid::UuidHelper::get_profile_uuid(&profile) {
+            Some(uuid) => uuid.clone(),
+            None => return false,
+        };
+        match &self.adapter {
+            Some(adapter) => {
+                let device = BluetoothDevice::new(addr.to_string(), "".to_string());
+                adapter.lock().unwrap().get_connection_policy(device).get_policy(&uuid)
+                    == ConnectionPolicy::Forbidden
+            }
+            None => false,
+        }
+    }
+
     pub fn get_hfp_connection_state(&self) -> ProfileConnectionState {
         if self.hfp_audio_state.values().any(|state| *state == BthfAudioState::Connected) {
             ProfileConnectionState::Active
@@ -1694,173 +1920,11 @@ impl IBluetoothMedia for BluetoothMedia {
     }
 
     fn connect(&mut self, address: String) {
-        let addr = match RawAddress::from_string(address.clone()) {
-            None => {
-                warn!("Invalid device address for connecting");
-                return;
-            }
-            Some(addr) => addr,
-        };
-
-        let available_profiles = self.adapter_get_audio_profiles(addr);
-
-        info!(
-            "[{}]: Connecting to device, available profiles: {:?}.",
-            DisplayAddress(&addr),
-            available_profiles
-        );
-
-        let connected_profiles = self.connected_profiles.entry(addr).or_insert_with(HashSet::new);
-
-        // Sort here so the order of connection is always consistent
-        let missing_profiles =
-            available_profiles.difference(&connected_profiles).sorted().collect::<Vec<_>>();
-
-        // Connect the profiles one-by-one so it won't stuck at the lower layer.
-        // Therefore, just connect to one profile for now.
-        // connect() will be called again after the first profile is successfully connected.
-        let mut is_connect = false;
-        for profile in missing_profiles {
-            match profile {
-                uuid::Profile::A2dpSink => {
-                    metrics::profile_connection_state_changed(
-                        addr,
-                        Profile::A2dpSink as u32,
-                        BtStatus::Success,
-                        BtavConnectionState::Connecting as u32,
-                    );
-                    match self.a2dp.as_mut() {
-                        Some(a2dp) => {
-                            let status: BtStatus = a2dp.connect(addr);
-                            if BtStatus::Success != status {
-                                metrics::profile_connection_state_changed(
-                                    addr,
-                                    Profile::A2dpSink as u32,
-                                    status,
-                                    BtavConnectionState::Disconnected as u32,
-                                );
-                            } else {
-                                is_connect = true;
-                                break;
-                            }
-                        }
-                        None => {
-                            warn!("Uninitialized A2DP to connect {}", DisplayAddress(&addr));
-                            metrics::profile_connection_state_changed(
-                                addr,
-                                Profile::A2dpSink as u32,
-                                BtStatus::NotReady,
-                                BtavConnectionState::Disconnected as u32,
-                            );
-                        }
-                    };
-                }
-                uuid::Profile::Hfp => {
-                    metrics::profile_connection_state_changed(
-                        addr,
-                        Profile::Hfp as u32,
-                        BtStatus::Success,
-                        BtavConnectionState::Connecting as u32,
-                    );
-                    match self.hfp.as_mut() {
-                        Some(hfp) => {
-                            let status: BtStatus = hfp.connect(addr);
-                            if BtStatus::Success != status {
-                                metrics::profile_connection_state_changed(
-                                    addr,
-                                    Profile::Hfp as u32,
-                                    status,
-                                    BthfConnectionState::Disconnected as u32,
-                                );
-                            } else {
-                                is_connect = true;
-                                break;
-                            }
-                        }
-                        None => {
-                            warn!("Uninitialized HFP to connect {}", DisplayAddress(&addr));
-                            metrics::profile_connection_state_changed(
-                                addr,
-                                Profile::Hfp as u32,
-                                BtStatus::NotReady,
-                                BthfConnectionState::Disconnected as u32,
-                            );
-                        }
-                    };
-                }
-                uuid::Profile::AvrcpController => {
-                    // Fluoride will resolve AVRCP as a part of A2DP connection request.
-                    // Explicitly connect to it only when it is considered missing, and don't
-                    // bother about it when A2DP is not connected.
-                    if !connected_profiles.contains(&Profile::A2dpSink) {
-                        continue;
-                    }
-
-                    metrics::profile_connection_state_changed(
-                        addr,
-                        Profile::AvrcpController as u32,
-                        BtStatus::Success,
-                        BtavConnectionState::Connecting as u32,
-                    );
-                    match self.avrcp.as_mut() {
-                        Some(avrcp) => {
-                            self.avrcp_direction = BtConnectionDirection::Outgoing;
-                            let status: BtStatus = avrcp.connect(addr);
-                            if BtStatus::Success != status {
-                                // Reset direction to unknown.
-                                self.avrcp_direction = BtConnectionDirection::Unknown;
-                                metrics::profile_connection_state_changed(
-                                    addr,
-                                    Profile::AvrcpController as u32,
-                                    status,
-                                    BtavConnectionState::Disconnected as u32,
-                                );
-                            } else {
-                                is_connect = true;
-                                break;
-                            }
-                        }
-
-                        None => {
-                            warn!("Uninitialized AVRCP to connect {}", DisplayAddress(&addr));
-                            metrics::profile_connection_state_changed(
-                                addr,
-                                Profile::AvrcpController as u32,
-                                BtStatus::NotReady,
-                                BtavConnectionState::Disconnected as u32,
-                            );
-                        }
-                    };
-                }
-                _ => warn!("Unknown profile: {:?}", profile),
-            }
-        }
-
-        if is_connect {
-            let mut tasks = self.fallback_tasks.lock().unwrap();
-            let mut states = self.device_states.lock().unwrap();
-            if !tasks.contains_key(&addr) {
-                states.insert(addr, DeviceConnectionStates::Initiating);
-
-                let fallback_tasks = self.fallback_tasks.clone();
-                let device_states = self.device_states.clone();
-                let now_ts = Instant::now();
-                let task = topstack::get_runtime().spawn(async move {
-                    sleep(Duration::from_secs(CONNECT_AS_INITIATOR_TIMEOUT_SEC)).await;
-
-                    // If here the task is not yet aborted, probably connection is failed,
-                    // therefore here we release the states. Even if later the connection is
-                    // actually successful, we will just treat this as if the connection is
-                    // initiated by the peer and will reconnect the missing profiles after
-                    // some time, so it's safe.
-                    {
-                        device_states.lock().unwrap().remove(&addr);
-                        fallback_tasks.lock().unwrap().remove(&addr);
-                    }
-                });
-                tasks.insert(addr, Some((task, now_ts)));
-            }
+        // Connections requested by clients include all the audio profiles that aren't forbidden.
+        if let Some(addr) = RawAddress::from_string(address.clone()) {
+            self.requested_profiles.remove(&addr);
         }
+        self.connect_missing_profiles(address);
     }
 
     fn cleanup(&mut self) -> bool {
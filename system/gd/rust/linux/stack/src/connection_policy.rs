@@ -0,0 +1,298 @@
+//! Per-device connection policies, deciding which profiles of a remote device are connected and
+//! which of them are reconnected automatically.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::time::Duration;
+
+use bt_topshim::btif::{BtHciErrorCode, Uuid128Bit};
+use log::{info, warn};
+use num_derive::{FromPrimitive, ToPrimitive};
+use num_traits::cast::{FromPrimitive, ToPrimitive};
+use serde_json::{json, Value};
+
+use crate::json_file::JsonFile;
+use crate::uuid::UuidHelper;
+
+/// HCI reasons of a disconnection caused by losing the link rather than by either side.
+const HCI_CONNECTION_TIMEOUT: BtHciErrorCode = 0x08;
+const HCI_LMP_RESPONSE_TIMEOUT: BtHciErrorCode = 0x22;
+
+/// The delay between reconnection attempts stops growing at this value.
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(300);
+
+/// How a profile of a remote device is connected.
+///
+/// Audio profiles are connected together by the media module, which only connects those the
+/// policy allows and disconnects the forbidden ones when the remote device connects them.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum ConnectionPolicy {
+    /// Connected when requested only. This is the default.
+    Manual = 0,
+    /// Connected when requested, and reconnected automatically when the adapter is enabled, on
+    /// resume and after link loss.
+    AutoConnect,
+    /// Never connected.
+    Forbidden,
+}
+
+/// The connection policy of a profile of a remote device.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProfileConnectionPolicy {
+    /// UUID of the profile.
+    pub profile: Uuid128Bit,
+    pub policy: ConnectionPolicy,
+    /// Profiles with a higher priority are connected first.
+    pub priority: i32,
+}
+
+/// The connection policy of a remote device. Profiles that aren't listed are connected manually
+/// with the lowest priority.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceConnectionPolicy {
+    pub profiles: Vec<ProfileConnectionPolicy>,
+    /// How many times to try reconnecting after link loss. 0 doesn't reconnect.
+    pub max_reconnect_attempts: u32,
+    /// Delay before the first reconnection attempt after link loss, in milliseconds. It doubles
+    /// after each failed attempt.
+    pub reconnect_backoff_ms: u32,
+}
+
+impl DeviceConnectionPolicy {
+    fn get_profile_policy(&self, profile: &Uuid128Bit) -> Option<&ProfileConnectionPolicy> {
+        self.profiles.iter().find(|p| &p.profile == profile)
+    }
+
+    /// Returns how |profile| is connected.
+    pub fn get_policy(&self, profile: &Uuid128Bit) -> ConnectionPolicy {
+        self.get_profile_policy(profile).map_or(ConnectionPolicy::Manual, |p| p.policy)
+    }
+
+    /// Returns the connection priority of |profile|.
+    pub fn get_priority(&self, profile: &Uuid128Bit) -> i32 {
+        self.get_profile_policy(profile).map_or(i32::MIN, |p| p.priority)
+    }
+
+    /// Whether any profile is reconnected automatically.
+    pub fn has_auto_connect(&self) -> bool {
+        self.profiles.iter().any(|p| p.policy == ConnectionPolicy::AutoConnect)
+    }
+
+    /// Returns the delay before the reconnection attempt number |attempt|, counting from 0, or
+    /// None if there are no attempts left.
+    pub fn get_reconnect_delay(&self, attempt: u32) -> Option<Duration> {
+        if !self.has_auto_connect() || attempt >= self.max_reconnect_attempts {
+            return None;
+        }
+
+        let backoff = Duration::from_millis(self.reconnect_backoff_ms.into());
+        Some(
+            backoff
+                .checked_mul(2u32.saturating_pow(attempt))
+                .map_or(MAX_RECONNECT_BACKOFF, |delay| std::cmp::min(delay, MAX_RECONNECT_BACKOFF)),
+        )
+    }
+
+    fn to_json(&self, address: &str) -> Value {
+        json!({
+            "address": address,
+            "max_reconnect_attempts": self.max_reconnect_attempts,
+            "reconnect_backoff_ms": self.reconnect_backoff_ms,
+            "profiles": self
+                .profiles
+                .iter()
+                .map(|p| {
+                    json!({
+                        "uuid": UuidHelper::to_string(&p.profile),
+                        "policy": p.policy.to_u32().unwrap(),
+                        "priority": p.priority,
+                    })
+                })
+                .collect::<Vec<Value>>(),
+        })
+    }
+
+    fn from_json(json: &Value) -> Option<(String, DeviceConnectionPolicy)> {
+        let address = json.get("address")?.as_str()?.to_string();
+        let policy = DeviceConnectionPolicy {
+            profiles: json
+                .get("profiles")?
+                .as_array()?
+                .iter()
+                .filter_map(|p| {
+                    Some(ProfileConnectionPolicy {
+                        profile: UuidHelper::from_string(p.get("uuid")?.as_str()?)?,
+                        policy: ConnectionPolicy::from_u64(p.get("policy")?.as_u64()?)?,
+                        priority: p.get("priority")?.as_i64()?.try_into().ok()?,
+                    })
+                })
+                .collect(),
+            max_reconnect_attempts: json
+                .get("max_reconnect_attempts")?
+                .as_u64()?
+                .try_into()
+                .ok()?,
+            reconnect_backoff_ms: json.get("reconnect_backoff_ms")?.as_u64()?.try_into().ok()?,
+        };
+        Some((address, policy))
+    }
+}
+
+/// Whether a disconnection with |hci_reason| means the link to the device was lost.
+pub fn is_link_loss(hci_reason: BtHciErrorCode) -> bool {
+    hci_reason == HCI_CONNECTION_TIMEOUT || hci_reason == HCI_LMP_RESPONSE_TIMEOUT
+}
+
+/// The connection policies of the remote devices, persisted to a JSON file.
+pub struct ConnectionPolicies {
+    path: String,
+    policies: HashMap<String, DeviceConnectionPolicy>,
+}
+
+impl ConnectionPolicies {
+    pub fn new(path: String) -> ConnectionPolicies {
+        let mut policies = ConnectionPolicies { path, policies: HashMap::new() };
+
+        match policies.load() {
+            Ok(()) => info!("Loaded connection policies of {} devices", policies.policies.len()),
+            Err(e) => warn!("Failed to load connection policies: {}", e),
+        }
+        policies
+    }
+
+    /// Returns the connection policy of the device with |address|.
+    pub fn get(&self, address: &str) -> DeviceConnectionPolicy {
+        self.policies.get(address).cloned().unwrap_or_default()
+    }
+
+    /// Sets the connection policy of the device with |address|.
+    pub fn set(&mut self, address: String, policy: DeviceConnectionPolicy) {
+        if policy == DeviceConnectionPolicy::default() {
+            self.policies.remove(&address);
+        } else {
+            self.policies.insert(address, policy);
+        }
+        self.write_policies();
+    }
+
+    /// Drops the connection policy of the device with |address|, e.g. once it's unbonded.
+    pub fn remove(&mut self, address: &str) {
+        if self.policies.remove(address).is_some() {
+            self.write_policies();
+        }
+    }
+
+    fn write_policies(&self) {
+        if let Err(e) = self.store() {
+            warn!("Failed to write connection policies to {}: {}", &self.path, e);
+        }
+    }
+}
+
+impl JsonFile for ConnectionPolicies {
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "devices": self
+                .policies
+                .iter()
+                .map(|(address, policy)| policy.to_json(address))
+                .collect::<Vec<Value>>()
+        })
+    }
+
+    fn load_from_json(&mut self, json: &Value) {
+        let devices = json.get("devices").and_then(|v| v.as_array());
+        self.policies = devices
+            .map(|devices| devices.iter().filter_map(DeviceConnectionPolicy::from_json).collect())
+            .unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_file;
+
+    fn profile_policy(
+        profile: u8,
+        policy: ConnectionPolicy,
+        priority: i32,
+    ) -> ProfileConnectionPolicy {
+        ProfileConnectionPolicy { profile: [profile; 16], policy, priority }
+    }
+
+    #[test]
+    fn test_unlisted_profiles_are_manual() {
+        let policy = DeviceConnectionPolicy {
+            profiles: vec![profile_policy(1, ConnectionPolicy::Forbidden, 1)],
+            ..Default::default()
+        };
+
+        assert_eq!(policy.get_policy(&[1; 16]), ConnectionPolicy::Forbidden);
+        assert_eq!(policy.get_policy(&[2; 16]), ConnectionPolicy::Manual);
+        assert!(policy.get_priority(&[1; 16]) > policy.get_priority(&[2; 16]));
+        assert!(!policy.has_auto_connect());
+    }
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let policy = DeviceConnectionPolicy {
+            profiles: vec![profile_policy(1, ConnectionPolicy::AutoConnect, 0)],
+            max_reconnect_attempts: 3,
+            reconnect_backoff_ms: 1000,
+        };
+
+        assert_eq!(policy.get_reconnect_delay(0), Some(Duration::from_millis(1000)));
+        assert_eq!(policy.get_reconnect_delay(1), Some(Duration::from_millis(2000)));
+        assert_eq!(policy.get_reconnect_delay(2), Some(Duration::from_millis(4000)));
+        assert_eq!(policy.get_reconnect_delay(3), None);
+
+        let long = DeviceConnectionPolicy { max_reconnect_attempts: 100, ..policy };
+        assert_eq!(long.get_reconnect_delay(40), Some(MAX_RECONNECT_BACKOFF));
+    }
+
+    #[test]
+    fn test_no_reconnect_without_auto_connect() {
+        let policy = DeviceConnectionPolicy {
+            profiles: vec![profile_policy(1, ConnectionPolicy::Manual, 0)],
+            max_reconnect_attempts: 3,
+            reconnect_backoff_ms: 1000,
+        };
+
+        assert_eq!(policy.get_reconnect_delay(0), None);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut policies = ConnectionPolicies { path: String::from(""), policies: HashMap::new() };
+        policies.policies.insert(
+            String::from("00:00:00:00:00:01"),
+            DeviceConnectionPolicy {
+                profiles: vec![
+                    profile_policy(1, ConnectionPolicy::AutoConnect, 2),
+                    profile_policy(2, ConnectionPolicy::Forbidden, -1),
+                ],
+                max_reconnect_attempts: 5,
+                reconnect_backoff_ms: 500,
+            },
+        );
+
+        let mut loaded = ConnectionPolicies { path: String::from(""), policies: HashMap::new() };
+        json_file::reload(&policies, &mut loaded);
+
+        assert_eq!(loaded.policies, policies.policies);
+    }
+
+    #[test]
+    fn test_link_loss() {
+        assert!(is_link_loss(HCI_CONNECTION_TIMEOUT));
+        assert!(is_link_loss(HCI_LMP_RESPONSE_TIMEOUT));
+        // Remote user terminated connection.
+        assert!(!is_link_loss(0x13));
+    }
+}
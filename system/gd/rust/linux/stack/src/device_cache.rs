@@ -0,0 +1,407 @@
+//! On-disk cache of remote devices that were seen recently but are not bonded.
+//!
+//! Bonded devices are persisted by the stack itself. The cache keeps the last known metadata of
+//! other devices so that they can be listed right after the daemon restarts. It belongs to the
+//! adapter that saw the devices, and is cleared when used with a different adapter.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bt_topshim::btif::Uuid128Bit;
+use log::{info, warn};
+use serde_json::{json, Value};
+
+use crate::bluetooth::BluetoothDevice;
+use crate::json_file::JsonFile;
+use crate::uuid::UuidHelper;
+
+/// The default maximum number of devices kept in the cache.
+pub const DEFAULT_MAX_CACHED_DEVICES: usize = 100;
+
+/// Devices that haven't been seen for longer than this are dropped from the cache by default.
+pub const DEFAULT_MAX_CACHED_DEVICE_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// A remote device that was seen recently, with its last known metadata.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecentDevice {
+    pub device: BluetoothDevice,
+    pub appearance: u16,
+    pub class: u32,
+    pub uuids: Vec<Uuid128Bit>,
+    /// When the device was last seen, in milliseconds since the Unix epoch.
+    pub last_seen_ms: u64,
+}
+
+impl RecentDevice {
+    fn to_json(&self) -> Value {
+        json!({
+            "address": self.device.address,
+            "name": self.device.name,
+            "appearance": self.appearance,
+            "class": self.class,
+            "uuids": self.uuids.iter().map(UuidHelper::to_string).collect::<Vec<String>>(),
+            "last_seen_ms": self.last_seen_ms,
+        })
+    }
+
+    fn from_json(json: &Value) -> Option<RecentDevice> {
+        Some(RecentDevice {
+            device: BluetoothDevice::new(
+                json.get("address")?.as_str()?.to_string(),
+                json.get("name")?.as_str()?.to_string(),
+            ),
+            appearance: json.get("appearance")?.as_u64()?.try_into().ok()?,
+            class: json.get("class")?.as_u64()?.try_into().ok()?,
+            uuids: json
+                .get("uuids")?
+                .as_array()?
+                .iter()
+                .filter_map(|v| UuidHelper::from_string(v.as_str()?))
+                .collect(),
+            last_seen_ms: json.get("last_seen_ms")?.as_u64()?,
+        })
+    }
+
+    /// Merges newer metadata of the same device. Unknown (empty) values don't overwrite known ones.
+    fn merge(&mut self, newer: RecentDevice) {
+        if !newer.device.name.is_empty() {
+            self.device.name = newer.device.name;
+        }
+        if newer.appearance != 0 {
+            self.appearance = newer.appearance;
+        }
+        if newer.class != 0 {
+            self.class = newer.class;
+        }
+        if !newer.uuids.is_empty() {
+            self.uuids = newer.uuids;
+        }
+        self.last_seen_ms = std::cmp::max(self.last_seen_ms, newer.last_seen_ms);
+    }
+}
+
+/// Returns the current time in milliseconds since the Unix epoch.
+pub fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Keeps at most |max_devices| recently seen devices, evicting the least recently seen first, and
+/// persists them to a JSON file.
+pub struct DeviceCache {
+    path: String,
+    max_devices: usize,
+    max_age: Duration,
+    /// Address of the adapter the devices were seen by, empty until it's known.
+    adapter_address: String,
+    devices: HashMap<String, RecentDevice>,
+    /// Whether the cache changed since it was last written.
+    dirty: bool,
+}
+
+impl DeviceCache {
+    pub fn new(path: String, max_devices: usize, max_age: Duration) -> DeviceCache {
+        let mut cache = DeviceCache {
+            path,
+            max_devices,
+            max_age,
+            adapter_address: String::new(),
+            devices: HashMap::new(),
+            dirty: false,
+        };
+
+        match cache.load() {
+            Ok(()) => info!("Loaded {} devices from {}", cache.devices.len(), &cache.path),
+            Err(e) => warn!("Failed to load device cache: {}", e),
+        }
+        cache.evict_expired(now_ms());
+        cache
+    }
+
+    /// Sets the address of the adapter in use. The cached devices are dropped if they were seen
+    /// by another adapter.
+    pub fn set_adapter_address(&mut self, address: &str) {
+        if self.adapter_address == address {
+            return;
+        }
+
+        if !self.adapter_address.is_empty() {
+            info!("Adapter changed, clearing {} cached devices", self.devices.len());
+            self.devices.clear();
+        }
+        self.adapter_address = address.to_string();
+        self.dirty = true;
+    }
+
+    /// Records that a device was seen, with its current metadata.
+    pub fn update(&mut self, device: RecentDevice) {
+        match self.devices.get_mut(&device.device.address) {
+            Some(existing) => existing.merge(device),
+            None => {
+                self.devices.insert(device.device.address.clone(), device);
+                if self.devices.len() > self.max_devices {
+                    self.evict_least_recently_seen();
+                }
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Drops a device from the cache, e.g. once it's bonded.
+    pub fn remove(&mut self, address: &str) {
+        if self.devices.remove(address).is_some() {
+            self.dirty = true;
+        }
+    }
+
+    /// Drops all the cached devices. They're removed from disk on the next flush.
+    pub fn clear(&mut self) {
+        self.devices.clear();
+        self.dirty = true;
+    }
+
+    /// Returns the cached devices that haven't expired, the most recently seen first.
+    pub fn get_recent_devices(&self) -> Vec<RecentDevice> {
+        let now_ms = now_ms();
+        let mut devices: Vec<RecentDevice> =
+            self.devices.values().filter(|d| !self.is_expired(d, now_ms)).cloned().collect();
+        devices.sort_by_key(|d| std::cmp::Reverse(d.last_seen_ms));
+        devices
+    }
+
+    /// Writes the cache to disk if it changed since it was last written. Expired devices are
+    /// dropped first.
+    pub fn flush(&mut self) {
+        self.evict_expired(now_ms());
+        if !self.dirty {
+            return;
+        }
+
+        match self.store() {
+            Ok(()) => self.dirty = false,
+            Err(e) => warn!("Failed to write device cache to {}: {}", &self.path, e),
+        }
+    }
+
+    fn is_expired(&self, device: &RecentDevice, now_ms: u64) -> bool {
+        now_ms.saturating_sub(device.last_seen_ms) > self.max_age.as_millis() as u64
+    }
+
+    /// Drops the devices that expired as of |now_ms|.
+    fn evict_expired(&mut self, now_ms: u64) {
+        let count = self.devices.len();
+        let max_age_ms = self.max_age.as_millis() as u64;
+        self.devices.retain(|_, d| now_ms.saturating_sub(d.last_seen_ms) <= max_age_ms);
+        if self.devices.len() != count {
+            self.dirty = true;
+        }
+    }
+
+    fn evict_least_recently_seen(&mut self) {
+        let oldest = self.devices.values().min_by_key(|d| d.last_seen_ms);
+        if let Some(address) = oldest.map(|d| d.device.address.clone()) {
+            self.devices.remove(&address);
+        }
+    }
+}
+
+impl JsonFile for DeviceCache {
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "adapter_address": self.adapter_address,
+            "devices": self
+                .get_recent_devices()
+                .iter()
+                .map(RecentDevice::to_json)
+                .collect::<Vec<Value>>()
+        })
+    }
+
+    fn load_from_json(&mut self, json: &Value) {
+        self.adapter_address = json
+            .get("adapter_address")
+            .and_then(Value::as_str)
+            .map(String::from)
+            .unwrap_or_default();
+
+        let devices = json.get("devices").and_then(|v| v.as_array());
+        self.devices = devices
+            .map(|devices| {
+                devices
+                    .iter()
+                    .filter_map(RecentDevice::from_json)
+                    .map(|d| (d.device.address.clone(), d))
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn recent_device(address: &str, name: &str, last_seen_ms: u64) -> RecentDevice {
+        RecentDevice {
+            device: BluetoothDevice::new(String::from(address), String::from(name)),
+            last_seen_ms,
+            ..Default::default()
+        }
+    }
+
+    fn cache_path(dir: &TempDir) -> String {
+        dir.path().join("devices.json").to_str().unwrap().to_string()
+    }
+
+    fn new_cache(dir: &TempDir, max_devices: usize) -> DeviceCache {
+        DeviceCache::new(cache_path(dir), max_devices, DEFAULT_MAX_CACHED_DEVICE_AGE)
+    }
+
+    #[test]
+    fn test_recent_devices_sorted_by_last_seen() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = now_ms();
+        let mut cache = new_cache(&dir, 10);
+        cache.update(recent_device("00:00:00:00:00:01", "first", now - 2000));
+        cache.update(recent_device("00:00:00:00:00:02", "second", now));
+        cache.update(recent_device("00:00:00:00:00:03", "third", now - 1000));
+
+        let names: Vec<String> =
+            cache.get_recent_devices().into_iter().map(|d| d.device.name).collect();
+        assert_eq!(names, vec!["second", "third", "first"]);
+    }
+
+    #[test]
+    fn test_update_merges_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = now_ms();
+        let mut cache = new_cache(&dir, 10);
+        let uuid: Uuid128Bit = [1; 16];
+        cache.update(RecentDevice {
+            class: 0x240404,
+            uuids: vec![uuid],
+            ..recent_device("00:00:00:00:00:01", "speaker", now - 1000)
+        });
+
+        // A later sighting without a name or services keeps the known ones.
+        cache.update(RecentDevice {
+            appearance: 0x0941,
+            ..recent_device("00:00:00:00:00:01", "", now)
+        });
+
+        let devices = cache.get_recent_devices();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].device.name, "speaker");
+        assert_eq!(devices[0].class, 0x240404);
+        assert_eq!(devices[0].appearance, 0x0941);
+        assert_eq!(devices[0].uuids, vec![uuid]);
+        assert_eq!(devices[0].last_seen_ms, now);
+    }
+
+    #[test]
+    fn test_least_recently_seen_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = now_ms();
+        let mut cache = new_cache(&dir, 2);
+        cache.update(recent_device("00:00:00:00:00:01", "oldest", now - 2000));
+        cache.update(recent_device("00:00:00:00:00:02", "older", now - 1000));
+        cache.update(recent_device("00:00:00:00:00:03", "newest", now));
+
+        let names: Vec<String> =
+            cache.get_recent_devices().into_iter().map(|d| d.device.name).collect();
+        assert_eq!(names, vec!["newest", "older"]);
+    }
+
+    #[test]
+    fn test_expired_devices_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = now_ms();
+        let mut cache = DeviceCache::new(cache_path(&dir), 10, Duration::from_secs(60));
+        cache.update(recent_device("00:00:00:00:00:01", "expired", now - 120_000));
+        cache.update(recent_device("00:00:00:00:00:02", "fresh", now));
+
+        let names: Vec<String> =
+            cache.get_recent_devices().into_iter().map(|d| d.device.name).collect();
+        assert_eq!(names, vec!["fresh"]);
+    }
+
+    #[test]
+    fn test_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = new_cache(&dir, 10);
+        cache.update(recent_device("00:00:00:00:00:01", "bonded", now_ms()));
+
+        cache.remove("00:00:00:00:00:01");
+
+        assert!(cache.get_recent_devices().is_empty());
+    }
+
+    #[test]
+    fn test_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = new_cache(&dir, 10);
+        cache.update(recent_device("00:00:00:00:00:01", "seen", now_ms()));
+
+        cache.clear();
+
+        assert!(cache.get_recent_devices().is_empty());
+        assert!(cache.dirty);
+    }
+
+    #[test]
+    fn test_adapter_change_clears() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = new_cache(&dir, 10);
+        cache.update(recent_device("00:00:00:00:00:01", "before", now_ms()));
+
+        // The adapter becoming known keeps the devices seen so far.
+        cache.set_adapter_address("00:00:00:00:00:0A");
+        assert_eq!(cache.get_recent_devices().len(), 1);
+
+        cache.set_adapter_address("00:00:00:00:00:0B");
+        assert!(cache.get_recent_devices().is_empty());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = now_ms();
+        let mut cache = new_cache(&dir, 10);
+        cache.set_adapter_address("00:00:00:00:00:0A");
+        cache.update(RecentDevice {
+            appearance: 0x03c1,
+            class: 0x2540,
+            uuids: vec![[2; 16]],
+            ..recent_device("00:00:00:00:00:01", "keyboard", now)
+        });
+
+        cache.flush();
+        let loaded = new_cache(&dir, 10);
+
+        assert_eq!(loaded.adapter_address, cache.adapter_address);
+        assert_eq!(loaded.get_recent_devices(), cache.get_recent_devices());
+    }
+
+    #[test]
+    fn test_malformed_entries_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = new_cache(&dir, 10);
+        let json = json!({
+            "devices": [
+                { "address": "00:00:00:00:00:01" },
+                recent_device("00:00:00:00:00:02", "valid", now_ms()).to_json(),
+            ]
+        });
+
+        cache.load_from_json(&json);
+
+        let names: Vec<String> =
+            cache.get_recent_devices().into_iter().map(|d| d.device.name).collect();
+        assert_eq!(names, vec!["valid"]);
+    }
+}
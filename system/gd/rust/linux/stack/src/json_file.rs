@@ -0,0 +1,83 @@
+//! Settings persisted to JSON files, since the daemon is restarted every time the adapter is
+//! enabled.
+
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+use serde_json::Value;
+
+/// Settings that are loaded from and stored to a JSON file.
+pub trait JsonFile {
+    /// Path of the file.
+    fn path(&self) -> &str;
+
+    /// Returns the settings to store.
+    fn to_json(&self) -> Value;
+
+    /// Loads the settings from |json|. Missing or invalid entries are skipped.
+    fn load_from_json(&mut self, json: &Value);
+
+    /// Loads the settings from the file. Fails if the file can't be read or isn't valid JSON.
+    fn load(&mut self) -> Result<()> {
+        let json = read(self.path())?;
+        self.load_from_json(&json);
+        Ok(())
+    }
+
+    /// Stores the settings to the file.
+    fn store(&self) -> Result<()> {
+        write(self.path(), &self.to_json())
+    }
+}
+
+/// Reads and parses the JSON file at |path|.
+pub fn read(path: &str) -> Result<Value> {
+    let mut file = File::open(path)?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    serde_json::from_str::<Value>(contents.as_str())
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+/// Writes |json| to a temporary file which then replaces the file at |path|, so that the file is
+/// never left truncated if the daemon stops while writing it.
+pub fn write(path: &str, json: &Value) -> Result<()> {
+    let contents = serde_json::to_string_pretty(json)?;
+    let tmp_path = format!("{}.tmp", path);
+
+    let mut f = File::create(&tmp_path)?;
+    f.write_all(contents.as_bytes())?;
+    f.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Stores |from| and loads it back into |to| through the same serialization as the file.
+#[cfg(test)]
+pub(crate) fn reload<T: JsonFile>(from: &T, to: &mut T) {
+    let contents = serde_json::to_string_pretty(&from.to_json()).unwrap();
+    to.load_from_json(&serde_json::from_str::<Value>(contents.as_str()).unwrap());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_write_then_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        let path = path.to_str().unwrap();
+        let json = json!({ "devices": [ { "address": "00:00:00:00:00:01" } ] });
+
+        write(path, &json).unwrap();
+        assert_eq!(read(path).unwrap(), json);
+        assert!(!std::path::Path::new(&format!("{}.tmp", path)).exists());
+
+        fs::write(path, "{ \"devices\": [").unwrap();
+        assert_eq!(read(path).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        fs::remove_file(path).unwrap();
+        assert_eq!(read(path).unwrap_err().kind(), ErrorKind::NotFound);
+    }
+}
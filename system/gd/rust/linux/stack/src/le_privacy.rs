@@ -0,0 +1,134 @@
+//! Configuration of the local private address used with LE privacy.
+
+use std::convert::TryInto;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde_json::{json, Value};
+
+use crate::json_file::JsonFile;
+
+/// Bounds of a configured rotation timeout of the private address.
+const MIN_RPA_ROTATION_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_RPA_ROTATION_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// How the local address is made private when LE privacy is enabled.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LePrivacyConfig {
+    /// Advertise and scan with non-resolvable private addresses instead of resolvable ones. The
+    /// address type changes the next time the adapter is enabled.
+    pub use_non_resolvable_address: bool,
+    /// How long a private address is used before it's rotated, in milliseconds. 0 uses the
+    /// default rotation time of 7 to 15 minutes.
+    pub rpa_rotation_timeout_ms: u32,
+}
+
+impl LePrivacyConfig {
+    /// Whether the rotation timeout is either the default or within the supported bounds.
+    pub fn is_valid(&self) -> bool {
+        let timeout = Duration::from_millis(self.rpa_rotation_timeout_ms.into());
+        self.rpa_rotation_timeout_ms == 0
+            || (MIN_RPA_ROTATION_TIMEOUT..=MAX_RPA_ROTATION_TIMEOUT).contains(&timeout)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "use_non_resolvable_address": self.use_non_resolvable_address,
+            "rpa_rotation_timeout_ms": self.rpa_rotation_timeout_ms,
+        })
+    }
+
+    fn from_json(json: &Value) -> Option<LePrivacyConfig> {
+        let config = LePrivacyConfig {
+            use_non_resolvable_address: json.get("use_non_resolvable_address")?.as_bool()?,
+            rpa_rotation_timeout_ms: json
+                .get("rpa_rotation_timeout_ms")?
+                .as_u64()?
+                .try_into()
+                .ok()?,
+        };
+        Some(config).filter(|c| c.is_valid())
+    }
+}
+
+/// The LE privacy configuration of the adapter, persisted to a JSON file.
+pub struct LePrivacySettings {
+    path: String,
+    config: LePrivacyConfig,
+}
+
+impl LePrivacySettings {
+    pub fn new(path: String) -> LePrivacySettings {
+        let mut settings = LePrivacySettings { path, config: LePrivacyConfig::default() };
+
+        if settings.load().is_err() {
+            info!("No LE privacy configuration loaded, using the defaults");
+        }
+        settings
+    }
+
+    pub fn get(&self) -> LePrivacyConfig {
+        self.config.clone()
+    }
+
+    /// Stores |config|, which must be valid.
+    pub fn set(&mut self, config: LePrivacyConfig) {
+        self.config = config;
+
+        if let Err(e) = self.store() {
+            warn!("Failed to write LE privacy configuration to {}: {}", &self.path, e);
+        }
+    }
+}
+
+impl JsonFile for LePrivacySettings {
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn to_json(&self) -> Value {
+        self.config.to_json()
+    }
+
+    fn load_from_json(&mut self, json: &Value) {
+        match LePrivacyConfig::from_json(json) {
+            Some(config) => self.config = config,
+            None => warn!("LE privacy configuration {} is malformed", &self.path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_file;
+
+    #[test]
+    fn test_rotation_timeout_bounds() {
+        let config = |rpa_rotation_timeout_ms| LePrivacyConfig {
+            use_non_resolvable_address: false,
+            rpa_rotation_timeout_ms,
+        };
+
+        assert!(config(0).is_valid());
+        assert!(!config(999).is_valid());
+        assert!(config(1000).is_valid());
+        assert!(config(3_600_000).is_valid());
+        assert!(!config(3_600_001).is_valid());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let settings = |config| LePrivacySettings { path: String::from(""), config };
+        let config =
+            LePrivacyConfig { use_non_resolvable_address: true, rpa_rotation_timeout_ms: 60_000 };
+
+        let mut loaded = settings(LePrivacyConfig::default());
+        json_file::reload(&settings(config.clone()), &mut loaded);
+        assert_eq!(loaded.get(), config);
+
+        // An incomplete configuration keeps the current one.
+        loaded.load_from_json(&json!({ "rpa_rotation_timeout_ms": 1000 }));
+        assert_eq!(loaded.get(), config);
+    }
+}
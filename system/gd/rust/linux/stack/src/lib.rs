@@ -3,7 +3,9 @@
 //! This crate provides the API implementation of the Fluoride/GD Bluetooth
 //! stack, independent of any RPC projection.
 
+pub mod adv_monitor;
 pub mod async_helper;
+pub mod batch_scan;
 pub mod battery_manager;
 pub mod battery_provider_manager;
 pub mod battery_service;
@@ -15,7 +17,12 @@ pub mod bluetooth_logging;
 pub mod bluetooth_media;
 pub mod bluetooth_qa;
 pub mod callbacks;
+pub mod connection_policy;
+pub mod device_cache;
 pub mod dis;
+pub mod json_file;
+pub mod le_privacy;
+pub mod scan_params;
 pub mod socket_manager;
 pub mod suspend;
 pub mod uuid;
@@ -101,6 +108,8 @@ pub enum Message {
 
     // Scanner related
     ScannerCallbackDisconnected(u32),
+    AdvMonitorLostCheck,
+    BatchScanReport(u8),
 
     // Advertising related
     AdvertiserCallbackDisconnected(u32),
@@ -305,6 +314,14 @@ impl Stack {
                     bluetooth_gatt.lock().unwrap().remove_scanner_callback(id);
                 }
 
+                Message::AdvMonitorLostCheck => {
+                    bluetooth_gatt.lock().unwrap().check_adv_monitor_lost();
+                }
+
+                Message::BatchScanReport(scanner_id) => {
+                    bluetooth_gatt.lock().unwrap().report_batch_scan_results(scanner_id);
+                }
+
                 Message::AdvertiserCallbackDisconnected(id) => {
                     bluetooth_gatt.lock().unwrap().remove_adv_callback(id);
                 }
@@ -0,0 +1,291 @@
+//! Named profiles of the page scan, inquiry scan and LE background scan parameters.
+
+use bt_topshim::sysprop::{self, PropertyI32};
+use log::{info, warn};
+use num_derive::{FromPrimitive, ToPrimitive};
+use serde_json::{json, Value};
+
+use crate::json_file::JsonFile;
+
+/// Trade-off between how fast the adapter is found and connected to, and how much power its
+/// scans use.
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq)]
+#[repr(u32)]
+pub enum ScanParameterProfile {
+    LowLatency = 0,
+    /// The parameters the stack starts with, from the board configuration or its defaults.
+    Balanced,
+    LowPower,
+}
+
+impl ScanParameterProfile {
+    /// Returns the name of the profile used in the settings file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScanParameterProfile::LowLatency => "low-latency",
+            ScanParameterProfile::Balanced => "balanced",
+            ScanParameterProfile::LowPower => "low-power",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ScanParameterProfile> {
+        match name {
+            "low-latency" => Some(ScanParameterProfile::LowLatency),
+            "balanced" => Some(ScanParameterProfile::Balanced),
+            "low-power" => Some(ScanParameterProfile::LowPower),
+            _ => None,
+        }
+    }
+}
+
+/// Scan type values of the page scan and inquiry scan.
+const SCAN_TYPE_STANDARD: i32 = 0;
+const SCAN_TYPE_INTERLACED: i32 = 1;
+
+/// Scan timing of a profile. Intervals and windows are in units of 0.625 ms.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanParameters {
+    pub page_scan_type: i32,
+    pub page_scan_interval: i32,
+    pub page_scan_window: i32,
+    pub inquiry_scan_type: i32,
+    pub inquiry_scan_interval: i32,
+    pub inquiry_scan_window: i32,
+    /// The LE scan used to reconnect devices in the background.
+    pub le_background_scan_interval: i32,
+    pub le_background_scan_window: i32,
+}
+
+impl ScanParameters {
+    /// Returns the parameters of |profile|, or None for the balanced profile, which keeps the
+    /// parameters the stack started with.
+    pub fn for_profile(profile: ScanParameterProfile) -> Option<ScanParameters> {
+        match profile {
+            ScanParameterProfile::LowLatency => Some(ScanParameters {
+                page_scan_type: SCAN_TYPE_INTERLACED,
+                page_scan_interval: 256,
+                page_scan_window: 36,
+                inquiry_scan_type: SCAN_TYPE_INTERLACED,
+                inquiry_scan_interval: 1024,
+                inquiry_scan_window: 36,
+                le_background_scan_interval: 96,
+                le_background_scan_window: 48,
+            }),
+            ScanParameterProfile::Balanced => None,
+            ScanParameterProfile::LowPower => Some(ScanParameters {
+                page_scan_type: SCAN_TYPE_STANDARD,
+                page_scan_interval: 2048,
+                page_scan_window: 18,
+                inquiry_scan_type: SCAN_TYPE_STANDARD,
+                inquiry_scan_interval: 4096,
+                inquiry_scan_window: 18,
+                le_background_scan_interval: 4096,
+                le_background_scan_window: 18,
+            }),
+        }
+    }
+
+    /// Reads the parameters from the sysprops, which the stack loads from the board configuration
+    /// when it starts.
+    pub fn from_sysprops() -> ScanParameters {
+        ScanParameters {
+            page_scan_type: sysprop::get_i32(PropertyI32::PageScanType),
+            page_scan_interval: sysprop::get_i32(PropertyI32::PageScanInterval),
+            page_scan_window: sysprop::get_i32(PropertyI32::PageScanWindow),
+            inquiry_scan_type: sysprop::get_i32(PropertyI32::InquiryScanType),
+            inquiry_scan_interval: sysprop::get_i32(PropertyI32::InquiryScanInterval),
+            inquiry_scan_window: sysprop::get_i32(PropertyI32::InquiryScanWindow),
+            le_background_scan_interval: sysprop::get_i32(
+                PropertyI32::LeConnectionScanIntervalSlow,
+            ),
+            le_background_scan_window: sysprop::get_i32(PropertyI32::LeConnectionScanWindowSlow),
+        }
+    }
+
+    /// Stores the parameters to the sysprops the stack reads them from.
+    pub fn store_sysprops(&self) {
+        let props = [
+            (PropertyI32::PageScanType, self.page_scan_type),
+            (PropertyI32::PageScanInterval, self.page_scan_interval),
+            (PropertyI32::PageScanWindow, self.page_scan_window),
+            (PropertyI32::InquiryScanType, self.inquiry_scan_type),
+            (PropertyI32::InquiryScanInterval, self.inquiry_scan_interval),
+            (PropertyI32::InquiryScanWindow, self.inquiry_scan_window),
+            (PropertyI32::LeConnectionScanIntervalSlow, self.le_background_scan_interval),
+            (PropertyI32::LeConnectionScanWindowSlow, self.le_background_scan_window),
+        ];
+        for (prop, value) in props {
+            if !sysprop::set_i32(prop, value) {
+                warn!("Failed to set scan parameter sysprop to {}", value);
+            }
+        }
+    }
+}
+
+/// The scan parameter profiles of the adapter, persisted to a JSON file.
+pub struct ScanParameterSettings {
+    path: String,
+    /// Profile chosen by the user. The parameters the stack started with are kept until one is.
+    profile: Option<ScanParameterProfile>,
+    /// Profile used while the system is suspended, which can only be changed in the file.
+    suspend_profile: ScanParameterProfile,
+    /// Parameters the stack started with, possibly tuned for the board, used by the balanced
+    /// profile.
+    stack_parameters: Option<ScanParameters>,
+}
+
+impl ScanParameterSettings {
+    pub fn new(path: String) -> ScanParameterSettings {
+        let mut settings = ScanParameterSettings {
+            path,
+            profile: None,
+            suspend_profile: ScanParameterProfile::LowPower,
+            stack_parameters: None,
+        };
+
+        if settings.load().is_err() {
+            info!("No scan parameter configuration loaded, using the defaults");
+        }
+        settings
+    }
+
+    pub fn get_profile(&self) -> ScanParameterProfile {
+        self.profile.unwrap_or(ScanParameterProfile::Balanced)
+    }
+
+    /// Returns the profile chosen by the user, if any.
+    pub fn get_chosen_profile(&self) -> Option<ScanParameterProfile> {
+        self.profile
+    }
+
+    pub fn get_suspend_profile(&self) -> ScanParameterProfile {
+        self.suspend_profile
+    }
+
+    pub fn set_profile(&mut self, profile: ScanParameterProfile) {
+        self.profile = Some(profile);
+
+        if let Err(e) = self.store() {
+            warn!("Failed to write scan parameter configuration to {}: {}", &self.path, e);
+        }
+    }
+
+    /// Keeps the parameters the stack started with for the balanced profile. It must be called
+    /// once the stack is up, before any profile is applied.
+    pub fn save_stack_parameters(&mut self) {
+        if self.stack_parameters.is_none() {
+            self.stack_parameters = Some(ScanParameters::from_sysprops());
+        }
+    }
+
+    /// Returns the parameters to apply for |profile|.
+    pub fn get_parameters(&self, profile: ScanParameterProfile) -> ScanParameters {
+        ScanParameters::for_profile(profile)
+            .or_else(|| self.stack_parameters.clone())
+            .unwrap_or_else(ScanParameters::from_sysprops)
+    }
+}
+
+impl JsonFile for ScanParameterSettings {
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn to_json(&self) -> Value {
+        let mut json = json!({ "suspend_profile": self.suspend_profile.name() });
+        if let Some(profile) = self.profile {
+            json["profile"] = json!(profile.name());
+        }
+        json
+    }
+
+    /// Reads the profiles from |json|. A missing or unknown profile keeps its current value.
+    fn load_from_json(&mut self, json: &Value) {
+        let profile =
+            |key| json.get(key).and_then(Value::as_str).and_then(ScanParameterProfile::from_name);
+
+        if let Some(profile) = profile("profile") {
+            self.profile = Some(profile);
+        }
+        if let Some(profile) = profile("suspend_profile") {
+            self.suspend_profile = profile;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_file;
+
+    #[test]
+    fn test_profile_names() {
+        for profile in [
+            ScanParameterProfile::LowLatency,
+            ScanParameterProfile::Balanced,
+            ScanParameterProfile::LowPower,
+        ] {
+            assert_eq!(ScanParameterProfile::from_name(profile.name()), Some(profile));
+
+            let params = match ScanParameters::for_profile(profile) {
+                Some(params) => params,
+                None => continue,
+            };
+            assert!(params.page_scan_window <= params.page_scan_interval);
+            assert!(params.inquiry_scan_window <= params.inquiry_scan_interval);
+            assert!(params.le_background_scan_window <= params.le_background_scan_interval);
+        }
+        assert_eq!(ScanParameterProfile::from_name("turbo"), None);
+    }
+
+    #[test]
+    fn test_balanced_profile_keeps_stack_parameters() {
+        let stack_parameters = ScanParameters {
+            page_scan_type: SCAN_TYPE_STANDARD,
+            page_scan_interval: 800,
+            page_scan_window: 24,
+            inquiry_scan_type: SCAN_TYPE_STANDARD,
+            inquiry_scan_interval: 4096,
+            inquiry_scan_window: 24,
+            le_background_scan_interval: 1024,
+            le_background_scan_window: 32,
+        };
+        let settings = ScanParameterSettings {
+            path: String::from(""),
+            profile: None,
+            suspend_profile: ScanParameterProfile::LowPower,
+            stack_parameters: Some(stack_parameters.clone()),
+        };
+
+        assert_eq!(settings.get_parameters(ScanParameterProfile::Balanced), stack_parameters);
+        assert_eq!(
+            Some(settings.get_parameters(ScanParameterProfile::LowPower)),
+            ScanParameters::for_profile(ScanParameterProfile::LowPower)
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let settings = || ScanParameterSettings {
+            path: String::from(""),
+            profile: None,
+            suspend_profile: ScanParameterProfile::LowPower,
+            stack_parameters: None,
+        };
+
+        // Until a profile is chosen, none is stored.
+        let mut loaded = settings();
+        json_file::reload(&settings(), &mut loaded);
+        assert_eq!(loaded.get_chosen_profile(), None);
+        assert_eq!(loaded.get_profile(), ScanParameterProfile::Balanced);
+
+        let mut updated = settings();
+        updated.load_from_json(&json!({ "profile": "low-latency", "suspend_profile": "bogus" }));
+        assert_eq!(updated.get_profile(), ScanParameterProfile::LowLatency);
+        assert_eq!(updated.get_suspend_profile(), ScanParameterProfile::LowPower);
+
+        let mut loaded = settings();
+        json_file::reload(&updated, &mut loaded);
+        assert_eq!(loaded.get_chosen_profile(), Some(ScanParameterProfile::LowLatency));
+    }
+}
@@ -59,8 +59,9 @@ pub trait ISuspendCallback: RPCProxy {
 /// Bit 19 = Mode Change.
 const MASKED_EVENTS_FOR_SUSPEND: u64 = (1u64 << 4) | (1u64 << 19);
 
-/// When we resume, we will want to reconnect audio devices that were previously connected.
-/// However, we will need to delay a few seconds to avoid co-ex issues with Wi-Fi reconnection.
+/// When we resume, we will want to reconnect audio devices that were previously connected, and
+/// devices whose connection policy reconnects them automatically. However, we will need to delay
+/// a few seconds to avoid co-ex issues with Wi-Fi reconnection.
 const RECONNECT_AUDIO_ON_RESUME_DELAY_MS: u64 = 3000;
 
 #[derive(FromPrimitive, ToPrimitive)]
@@ -198,6 +199,7 @@ impl ISuspend for Suspend {
         self.discoverable_mode_to_restore =
             self.bt.lock().unwrap().get_discoverable_mode_internal();
         self.bt.lock().unwrap().set_connectable_internal(false);
+        self.bt.lock().unwrap().scan_parameters_enter_suspend();
         self.intf.lock().unwrap().clear_event_filter();
         self.intf.lock().unwrap().clear_filter_accept_list();
 
@@ -260,44 +262,40 @@ impl ISuspend for Suspend {
         self.intf.lock().unwrap().clear_event_filter();
         self.intf.lock().unwrap().clear_filter_accept_list();
         self.intf.lock().unwrap().restore_filter_accept_list();
+        self.bt.lock().unwrap().scan_parameters_exit_suspend();
         self.bt.lock().unwrap().set_connectable_internal(self.connectable_to_restore);
         if self.discoverable_mode_to_restore != BtDiscMode::NonDiscoverable {
             self.bt.lock().unwrap().set_discoverable(self.discoverable_mode_to_restore.clone(), 0);
         }
 
-        if !self.audio_reconnect_list.is_empty() {
-            let reconnect_list = self.audio_reconnect_list.clone();
-            let txl = self.tx.clone();
+        let reconnect_list = self.audio_reconnect_list.clone();
+        let txl = self.tx.clone();
 
-            // Cancel any existing reconnect attempt.
-            if let Some(joinhandle) = &self.audio_reconnect_joinhandle {
-                joinhandle.abort();
-                self.audio_reconnect_joinhandle = None;
-            }
-
-            self.audio_reconnect_joinhandle = Some(tokio::spawn(async move {
-                // Wait a few seconds to avoid co-ex issues with wi-fi.
-                tokio::time::sleep(tokio::time::Duration::from_millis(
-                    RECONNECT_AUDIO_ON_RESUME_DELAY_MS,
-                ))
-                .await;
-
-                // Queue up connections.
-                for device in reconnect_list {
-                    let _unused: Option<()> = txl
-                        .send(Message::DelayedAdapterActions(DelayedActions::ConnectAllProfiles(
-                            device,
-                        )))
-                        .await
-                        .ok();
-                }
-
-                // Mark that we're done.
-                let _unused: Option<()> =
-                    txl.send(Message::AudioReconnectOnResumeComplete).await.ok();
-            }));
+        // Cancel any existing reconnect attempt.
+        if let Some(joinhandle) = &self.audio_reconnect_joinhandle {
+            joinhandle.abort();
+            self.audio_reconnect_joinhandle = None;
         }
 
+        self.audio_reconnect_joinhandle = Some(tokio::spawn(async move {
+            // Wait a few seconds to avoid co-ex issues with wi-fi.
+            tokio::time::sleep(tokio::time::Duration::from_millis(
+                RECONNECT_AUDIO_ON_RESUME_DELAY_MS,
+            ))
+            .await;
+
+            // Queue up connections.
+            let _unused: Option<()> = txl
+                .send(Message::DelayedAdapterActions(DelayedActions::ReconnectOnResume(
+                    reconnect_list,
+                )))
+                .await
+                .ok();
+
+            // Mark that we're done.
+            let _unused: Option<()> = txl.send(Message::AudioReconnectOnResumeComplete).await.ok();
+        }));
+
         self.bt.lock().unwrap().discovery_exit_suspend();
         self.gatt.lock().unwrap().advertising_exit_suspend();
         self.gatt.lock().unwrap().scan_exit_suspend();
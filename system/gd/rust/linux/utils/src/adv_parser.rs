@@ -7,8 +7,11 @@ use bt_topshim::btif::Uuid128Bit;
 
 // Advertising data types.
 const FLAGS: u8 = 0x01;
+const INCOMPLETE_LIST_16_BIT_SERVICE_UUIDS: u8 = 0x02;
 const COMPLETE_LIST_16_BIT_SERVICE_UUIDS: u8 = 0x03;
+const INCOMPLETE_LIST_32_BIT_SERVICE_UUIDS: u8 = 0x04;
 const COMPLETE_LIST_32_BIT_SERVICE_UUIDS: u8 = 0x05;
+const INCOMPLETE_LIST_128_BIT_SERVICE_UUIDS: u8 = 0x06;
 const COMPLETE_LIST_128_BIT_SERVICE_UUIDS: u8 = 0x07;
 const SHORTENED_LOCAL_NAME: u8 = 0x08;
 const COMPLETE_LOCAL_NAME: u8 = 0x09;
@@ -54,22 +57,28 @@ pub fn extract_flags(bytes: &[u8]) -> u8 {
     iterate_adv_data(bytes, FLAGS).next().map_or(0, |v| v[0])
 }
 
-// Helper function to extract service uuids (128bit) from advertising data
+// Helper function to extract the data of all elements having the given AD type
+pub fn extract_data_by_type(bytes: &[u8], data_type: u8) -> Vec<&[u8]> {
+    iterate_adv_data(bytes, data_type).collect()
+}
+
+// Helper function to extract service uuids (128bit) from the complete and incomplete lists of
+// advertising data
 pub fn extract_service_uuids(bytes: &[u8]) -> Vec<Uuid128Bit> {
-    iterate_adv_data(bytes, COMPLETE_LIST_16_BIT_SERVICE_UUIDS)
-        .flat_map(|slice| slice.chunks(2))
-        .filter_map(|chunk| Uuid::try_from_little_endian(chunk).ok().map(|uuid| uuid.uu))
-        .chain(
-            iterate_adv_data(bytes, COMPLETE_LIST_32_BIT_SERVICE_UUIDS)
-                .flat_map(|slice| slice.chunks(4))
-                .filter_map(|chunk| Uuid::try_from_little_endian(chunk).ok().map(|uuid| uuid.uu)),
-        )
-        .chain(
-            iterate_adv_data(bytes, COMPLETE_LIST_128_BIT_SERVICE_UUIDS)
-                .flat_map(|slice| slice.chunks(16))
-                .filter_map(|chunk| Uuid::try_from_little_endian(chunk).ok().map(|uuid| uuid.uu)),
-        )
-        .collect()
+    [
+        (COMPLETE_LIST_16_BIT_SERVICE_UUIDS, 2),
+        (COMPLETE_LIST_32_BIT_SERVICE_UUIDS, 4),
+        (COMPLETE_LIST_128_BIT_SERVICE_UUIDS, 16),
+        (INCOMPLETE_LIST_16_BIT_SERVICE_UUIDS, 2),
+        (INCOMPLETE_LIST_32_BIT_SERVICE_UUIDS, 4),
+        (INCOMPLETE_LIST_128_BIT_SERVICE_UUIDS, 16),
+    ]
+    .iter()
+    .flat_map(|&(data_type, size)| {
+        iterate_adv_data(bytes, data_type).flat_map(move |slice| slice.chunks(size))
+    })
+    .filter_map(|chunk| Uuid::try_from_little_endian(chunk).ok().map(|uuid| uuid.uu))
+    .collect()
 }
 
 // Helper function to extract name from advertising data
@@ -201,6 +210,19 @@ mod tests {
             .uu
         );
         assert_eq!(uuids[2], Uuid::from([15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0]).uu);
+
+        let payload: Vec<u8> = vec![3, INCOMPLETE_LIST_16_BIT_SERVICE_UUIDS, 0x2C, 0xFE];
+        let uuids = extract_service_uuids(payload.as_slice());
+        assert_eq!(uuids.len(), 1);
+        assert_eq!(uuids[0], Uuid::try_from_little_endian(&[0x2C, 0xFE]).unwrap().uu);
+    }
+
+    #[test]
+    fn test_extract_data_by_type() {
+        let payload: Vec<u8> = vec![2, FLAGS, 3, 3, 0x30, 1, 2, 2, 0x30, 3];
+        let data = extract_data_by_type(payload.as_slice(), 0x30);
+        assert_eq!(data, vec![&[1u8, 2][..], &[3u8][..]]);
+        assert!(extract_data_by_type(payload.as_slice(), 0x31).is_empty());
     }
 
     #[test]
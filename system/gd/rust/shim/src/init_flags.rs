@@ -43,6 +43,7 @@ mod ffi {
         fn pbap_pse_dynamic_version_upgrade_is_enabled() -> bool;
         fn periodic_advertising_adi_is_enabled() -> bool;
         fn private_gatt_is_enabled() -> bool;
+        fn private_gatt_shared_gap_service_is_enabled() -> bool;
         fn queue_l2cap_coc_while_encrypting_is_enabled() -> bool;
         fn read_encryption_key_size_is_enabled() -> bool;
         fn redact_log_is_enabled() -> bool;
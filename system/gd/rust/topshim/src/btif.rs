@@ -348,8 +348,10 @@ impl From<bindings::bt_io_cap_t> for BtIoCap {
 }
 
 pub type BtHciErrorCode = u8;
+pub type BtGapConfiguration = bindings::bt_gap_configuration_t;
 pub type BtLocalLeFeatures = bindings::bt_local_le_features_t;
 pub type BtPinCode = bindings::bt_pin_code_t;
+pub type BtRemoteSecurityInfo = bindings::bt_remote_security_info_t;
 pub type BtRemoteVersion = bindings::bt_remote_version_t;
 pub type BtVendorProductInfo = bindings::bt_vendor_product_info_t;
 pub type Uuid = bindings::bluetooth::Uuid;
@@ -860,6 +862,113 @@ impl<'a> Display for DisplayAddress<'a> {
     }
 }
 
+/// Out of band pairing data exchanged with a remote device over another channel
+/// (e.g. NFC). The fixed size arrays of `OobData` are held as byte vectors.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BtOobData {
+    pub is_valid: bool,
+    pub address: Vec<u8>,
+    pub c: Vec<u8>,
+    pub r: Vec<u8>,
+    pub device_name: Vec<u8>,
+    pub oob_data_length: Vec<u8>,
+    pub class_of_device: Vec<u8>,
+    pub le_device_role: u8,
+    pub sm_tk: Vec<u8>,
+    pub le_flags: u8,
+    pub le_appearance: Vec<u8>,
+}
+
+impl From<OobData> for BtOobData {
+    fn from(item: OobData) -> Self {
+        BtOobData {
+            is_valid: item.is_valid,
+            address: item.address.to_vec(),
+            c: item.c.to_vec(),
+            r: item.r.to_vec(),
+            device_name: item.device_name.to_vec(),
+            oob_data_length: item.oob_data_length.to_vec(),
+            class_of_device: item.class_of_device.to_vec(),
+            le_device_role: item.le_device_role,
+            sm_tk: item.sm_tk.to_vec(),
+            le_flags: item.le_flags,
+            le_appearance: item.le_appearance.to_vec(),
+        }
+    }
+}
+
+impl TryFrom<BtOobData> for OobData {
+    type Error = &'static str;
+
+    /// Fails if a field doesn't fit, or if valid data lacks its address, C or R.
+    fn try_from(item: BtOobData) -> std::result::Result<Self, Self::Error> {
+        // Copies |src| into the start of |dst|, leaving the rest zeroed.
+        fn copy_into(
+            dst: &mut [u8],
+            src: &[u8],
+            error: &'static str,
+        ) -> std::result::Result<(), &'static str> {
+            if src.len() > dst.len() {
+                return Err(error);
+            }
+            dst[..src.len()].copy_from_slice(src);
+            Ok(())
+        }
+
+        // Fields which valid data must fill entirely.
+        fn copy_exact(
+            dst: &mut [u8],
+            src: &[u8],
+            is_valid: bool,
+            error: &'static str,
+        ) -> std::result::Result<(), &'static str> {
+            if src.len() != dst.len() && (is_valid || !src.is_empty()) {
+                return Err(error);
+            }
+            copy_into(dst, src, error)
+        }
+
+        let mut data = OobData::default();
+        data.is_valid = item.is_valid;
+        copy_exact(&mut data.address, &item.address, item.is_valid, "address must be 7 bytes")?;
+        copy_exact(&mut data.c, &item.c, item.is_valid, "C must be 16 bytes")?;
+        copy_exact(&mut data.r, &item.r, item.is_valid, "R must be 16 bytes")?;
+        copy_into(&mut data.device_name, &item.device_name, "device name is too long")?;
+        copy_into(&mut data.oob_data_length, &item.oob_data_length, "OOB data length is too long")?;
+        copy_into(&mut data.class_of_device, &item.class_of_device, "class of device is too long")?;
+        data.le_device_role = item.le_device_role;
+        copy_into(&mut data.sm_tk, &item.sm_tk, "TK is too long")?;
+        data.le_flags = item.le_flags;
+        copy_into(&mut data.le_appearance, &item.le_appearance, "appearance is too long")?;
+        Ok(data)
+    }
+}
+
+/// Controller activity and energy counters, as returned by `read_energy_info`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BtActivityEnergyInfo {
+    pub status: u8,
+    /// Controller state: active (1), scan (2) or idle (3); 0 if the request failed.
+    pub ctrl_state: u8,
+    pub tx_time_ms: u64,
+    pub rx_time_ms: u64,
+    pub idle_time_ms: u64,
+    pub energy_used: u64,
+}
+
+impl From<bindings::bt_activity_energy_info> for BtActivityEnergyInfo {
+    fn from(item: bindings::bt_activity_energy_info) -> Self {
+        BtActivityEnergyInfo {
+            status: item.status,
+            ctrl_state: item.ctrl_state,
+            tx_time_ms: item.tx_time,
+            rx_time_ms: item.rx_time,
+            idle_time_ms: item.idle_time,
+            energy_used: item.energy_used,
+        }
+    }
+}
+
 /// An enum representing `bt_callbacks_t` from btif.
 #[derive(Clone, Debug)]
 pub enum BaseCallbacks {
@@ -882,10 +991,10 @@ pub enum BaseCallbacks {
         BtConnectionDirection,
         u16,
     ),
+    EnergyInfo(BtActivityEnergyInfo),
+    LinkQualityReport(RawAddress, u64, i32, i32, i32, i32, i32, i32),
     // Unimplemented so far:
     // thread_evt_cb
-    // energy_info_cb
-    // link_quality_report_cb
     // switch_buffer_size_cb
     // switch_codec_cb
     GenerateLocalOobData(u8, OobData),
@@ -946,6 +1055,17 @@ u32 -> BtStatus, *mut RawAddress, bindings::bt_acl_state_t -> BtAclState, i32 ->
     let _1 = unsafe { *(_1 as *const RawAddress) };
 });
 
+// The per-app traffic is dropped, since there are no app uids to attribute it to outside Android.
+cb_variant!(BaseCb, energy_info_cb -> BaseCallbacks::EnergyInfo,
+*mut bindings::bt_activity_energy_info, *mut bindings::bt_uid_traffic_t -> _, {
+    let _0 = BtActivityEnergyInfo::from(unsafe { *_0 });
+});
+
+cb_variant!(BaseCb, link_quality_report_with_address_cb -> BaseCallbacks::LinkQualityReport,
+*mut RawAddress, u64, i32, i32, i32, i32, i32, i32, {
+    let _0 = unsafe { *(_0 as *const RawAddress) };
+});
+
 cb_variant!(BaseCb, generate_local_oob_data_cb -> BaseCallbacks::GenerateLocalOobData, u8, OobData);
 
 cb_variant!(BaseCb, le_rand_cb -> BaseCallbacks::LeRandCallback, u64);
@@ -1070,12 +1190,13 @@ impl BluetoothInterface {
             le_address_associate_cb: Some(le_address_associate_cb),
             acl_state_changed_cb: Some(acl_state_cb),
             thread_evt_cb: None,
-            energy_info_cb: None,
+            energy_info_cb: Some(energy_info_cb),
             link_quality_report_cb: None,
             generate_local_oob_data_cb: Some(generate_local_oob_data_cb),
             switch_buffer_size_cb: None,
             switch_codec_cb: None,
             le_rand_cb: Some(le_rand_cb),
+            link_quality_report_with_address_cb: Some(link_quality_report_with_address_cb),
         });
 
         let cb_ptr = LTCheckedPtrMut::from(&mut callbacks);
@@ -1188,6 +1309,27 @@ impl BluetoothInterface {
         ccall!(self, create_bond, addr_ptr.into(), ctransport)
     }
 
+    pub fn create_bond_out_of_band(
+        &self,
+        addr: &RawAddress,
+        transport: BtTransport,
+        p192_data: &OobData,
+        p256_data: &OobData,
+    ) -> i32 {
+        let ctransport: i32 = transport.into();
+        let addr_ptr = LTCheckedPtr::from_ref(addr);
+        let p192_ptr = LTCheckedPtr::from_ref(p192_data);
+        let p256_ptr = LTCheckedPtr::from_ref(p256_data);
+        ccall!(
+            self,
+            create_bond_out_of_band,
+            addr_ptr.into(),
+            ctransport,
+            p192_ptr.into(),
+            p256_ptr.into()
+        )
+    }
+
     pub fn remove_bond(&self, addr: &RawAddress) -> i32 {
         let addr_ptr = LTCheckedPtr::from_ref(addr);
         ccall!(self, remove_bond, addr_ptr.into())
@@ -1259,6 +1401,58 @@ impl BluetoothInterface {
         ccall!(self, le_rand)
     }
 
+    pub fn read_energy_info(&self) -> i32 {
+        ccall!(self, read_energy_info)
+    }
+
+    pub fn set_link_quality_report(&self, enable: bool, min_report_interval_ms: u16) -> i32 {
+        ccall!(self, set_link_quality_report, enable, min_report_interval_ms)
+    }
+
+    pub fn set_le_privacy_parameters(
+        &self,
+        use_non_resolvable_address: bool,
+        rpa_rotation_timeout_ms: u32,
+    ) -> i32 {
+        ccall!(self, set_le_privacy_parameters, use_non_resolvable_address, rpa_rotation_timeout_ms)
+    }
+
+    /// Returns the local Identity Resolving Key, or None if it can't be read.
+    pub fn get_local_irk(&self) -> Option<[u8; 16]> {
+        let mut irk = [0u8; 16];
+        let irk_ptr = LTCheckedPtrMut::from(&mut irk[..]);
+        match BtStatus::from(ccall!(self, get_local_irk, irk_ptr.into()) as u32) {
+            BtStatus::Success => Some(irk),
+            _ => None,
+        }
+    }
+
+    /// Returns how the remote device is bonded and how the links to it are secured, or None if
+    /// the stack can't read them.
+    pub fn get_remote_security_info(&self, addr: &RawAddress) -> Option<BtRemoteSecurityInfo> {
+        let mut info = BtRemoteSecurityInfo::default();
+        let addr_ptr = LTCheckedPtr::from_ref(addr);
+        let info_ptr = LTCheckedPtrMut::from_ref(&mut info);
+        let status = ccall!(self, get_remote_security_info, addr_ptr.into(), info_ptr.into());
+        match BtStatus::from(status as u32) {
+            BtStatus::Success => Some(info),
+            _ => None,
+        }
+    }
+
+    pub fn regenerate_local_irk(&self) -> i32 {
+        ccall!(self, regenerate_local_irk)
+    }
+
+    pub fn apply_scan_parameters(&self) -> i32 {
+        ccall!(self, apply_scan_parameters)
+    }
+
+    pub fn set_gap_configuration(&self, configuration: &BtGapConfiguration) -> i32 {
+        let configuration_ptr = LTCheckedPtr::from_ref(configuration);
+        ccall!(self, set_gap_configuration, configuration_ptr.into())
+    }
+
     pub fn generate_local_oob_data(&self, transport: i32) -> i32 {
         ccall!(self, generate_local_oob_data, transport as u8)
     }
@@ -1354,6 +1548,83 @@ mod tests {
         assert_eq!("".to_string(), String::from(invalid_bdname));
     }
 
+    #[test]
+    fn test_oob_data_conversions() {
+        let data = BtOobData {
+            is_valid: true,
+            address: vec![1, 2, 3, 4, 5, 6, 0],
+            c: vec![0xc; 16],
+            r: vec![0xd; 16],
+            device_name: "FooBar".as_bytes().to_vec(),
+            le_device_role: 2,
+            ..Default::default()
+        };
+
+        let raw = OobData::try_from(data.clone()).unwrap();
+        assert!(raw.is_valid);
+        assert_eq!([1, 2, 3, 4, 5, 6, 0], raw.address);
+        assert_eq!(&"FooBar".as_bytes()[..], &raw.device_name[..6]);
+        assert_eq!(0, raw.device_name[6]);
+
+        let converted: BtOobData = raw.into();
+        assert_eq!(data.address, converted.address);
+        assert_eq!(data.c, converted.c);
+        assert_eq!(data.le_device_role, converted.le_device_role);
+        assert_eq!(256, converted.device_name.len());
+
+        // Empty data (e.g. for an unused P-192 slot) converts to zeroes.
+        let raw = OobData::try_from(BtOobData::default()).unwrap();
+        assert!(!raw.is_valid);
+        assert_eq!([0; 16], raw.c);
+    }
+
+    #[test]
+    fn test_oob_data_invalid_lengths() {
+        let data = BtOobData {
+            is_valid: true,
+            address: vec![1, 2, 3, 4, 5, 6, 0],
+            c: vec![0xc; 16],
+            r: vec![0xd; 16],
+            ..Default::default()
+        };
+
+        let oversized = BtOobData { c: vec![0xc; 32], ..data.clone() };
+        assert!(OobData::try_from(oversized).is_err());
+
+        let short = BtOobData { r: vec![0xd; 15], ..data.clone() };
+        assert!(OobData::try_from(short).is_err());
+
+        let no_address = BtOobData { address: vec![], ..data.clone() };
+        assert!(OobData::try_from(no_address).is_err());
+
+        let long_name = BtOobData { device_name: vec![0x41; 257], ..data };
+        assert!(OobData::try_from(long_name).is_err());
+    }
+
+    #[test]
+    fn test_energy_info_conversion() {
+        let raw = bindings::bt_activity_energy_info {
+            status: 0,
+            ctrl_state: 1,
+            tx_time: 10,
+            rx_time: 20,
+            idle_time: 30,
+            energy_used: 40,
+        };
+
+        assert_eq!(
+            BtActivityEnergyInfo {
+                status: 0,
+                ctrl_state: 1,
+                tx_time_ms: 10,
+                rx_time_ms: 20,
+                idle_time_ms: 30,
+                energy_used: 40,
+            },
+            BtActivityEnergyInfo::from(raw)
+        );
+    }
+
     #[test]
     fn test_ptr_to_vec() {
         let arr: [i32; 3] = [1, 2, 3];
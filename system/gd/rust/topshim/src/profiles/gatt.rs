@@ -55,7 +55,7 @@ pub mod ffi {
     }
 
     // Original definition exists in C++.
-    #[derive(Debug, Clone)]
+    #[derive(Debug, Clone, Default)]
     pub struct RustGattFilterParam {
         feat_seln: u16,
         list_logic_type: u16,
@@ -1872,6 +1872,8 @@ impl Gatt {
             phy_updated_cb: Some(gs_phy_updated_cb),
             conn_updated_cb: Some(gs_conn_updated_cb),
             subrate_chg_cb: Some(gs_subrate_chg_cb),
+            // Only used by the Rust GATT server, which Floss doesn't run.
+            request_cancelled_cb: None,
         });
 
         let gatt_scanner_callbacks = Box::new(btgatt_scanner_callbacks_t {
@@ -1,6 +1,6 @@
 use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::cast::{FromPrimitive, ToPrimitive};
-use std::convert::TryFrom;
+use std::convert::{TryFrom, TryInto};
 use std::os::raw::c_char;
 use std::sync::{Arc, Mutex};
 use std::vec::Vec;
@@ -8,6 +8,7 @@ use std::vec::Vec;
 use crate::bindings::root as bindings;
 use crate::btif::{
     ascii_to_string, ptr_to_vec, BluetoothInterface, BtStatus, RawAddress, SupportedProfiles, Uuid,
+    Uuid128Bit,
 };
 use crate::ccall;
 use crate::topstack::get_dispatchers;
@@ -26,6 +27,7 @@ pub enum BtSdpType {
     SapServer,
     Dip,
     Mps,
+    Custom,
 }
 
 impl From<bindings::bluetooth_sdp_types> for BtSdpType {
@@ -46,6 +48,7 @@ impl From<&BtSdpRecord> for BtSdpType {
             BtSdpRecord::SapServer(record) => record.hdr.sdp_type.clone(),
             BtSdpRecord::Dip(record) => record.hdr.sdp_type.clone(),
             BtSdpRecord::Mps(record) => record.hdr.sdp_type.clone(),
+            BtSdpRecord::Custom(record) => record.hdr.sdp_type.clone(),
         }
     }
 }
@@ -286,6 +289,338 @@ impl From<bindings::_bluetooth_sdp_mps_record> for BtSdpMpsRecord {
     }
 }
 
+/// Data element types of the SDP data element header.
+const SDP_DE_NIL: u8 = 0;
+const SDP_DE_UINT: u8 = 1;
+const SDP_DE_INT: u8 = 2;
+const SDP_DE_UUID: u8 = 3;
+const SDP_DE_TEXT: u8 = 4;
+const SDP_DE_BOOL: u8 = 5;
+const SDP_DE_SEQUENCE: u8 = 6;
+const SDP_DE_ALTERNATIVE: u8 = 7;
+const SDP_DE_URL: u8 = 8;
+
+/// Attribute IDs used by the |BtSdpCustomRecord| builder methods.
+pub const SDP_ATTR_ID_SERVICE_CLASS_ID_LIST: u16 = 0x0001;
+pub const SDP_ATTR_ID_PROTOCOL_DESC_LIST: u16 = 0x0004;
+pub const SDP_ATTR_ID_BROWSE_GROUP_LIST: u16 = 0x0005;
+pub const SDP_ATTR_ID_SERVICE_NAME: u16 = 0x0100;
+
+const SDP_UUID_PROTOCOL_RFCOMM: u16 = 0x0003;
+const SDP_UUID_PROTOCOL_L2CAP: u16 = 0x0100;
+const SDP_UUID_PUBLIC_BROWSE_GROUP: u16 = 0x1002;
+
+/// A data element of an SDP attribute value. Integers and UUIDs are encoded in big endian.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BtSdpDataElement {
+    Nil,
+    Uint8(u8),
+    Uint16(u16),
+    Uint32(u32),
+    Uint64(u64),
+    Uint128(u128),
+    Int8(i8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    Int128(i128),
+    Uuid16(u16),
+    Uuid32(u32),
+    Uuid128(Uuid128Bit),
+    Text(String),
+    Bool(bool),
+    Sequence(Vec<BtSdpDataElement>),
+    Alternative(Vec<BtSdpDataElement>),
+    Url(String),
+}
+
+impl BtSdpDataElement {
+    /// Appends the encoded data element to |out|.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            BtSdpDataElement::Nil => out.push(SDP_DE_NIL << 3),
+            BtSdpDataElement::Uint8(v) => Self::encode_fixed(out, SDP_DE_UINT, &v.to_be_bytes()),
+            BtSdpDataElement::Uint16(v) => Self::encode_fixed(out, SDP_DE_UINT, &v.to_be_bytes()),
+            BtSdpDataElement::Uint32(v) => Self::encode_fixed(out, SDP_DE_UINT, &v.to_be_bytes()),
+            BtSdpDataElement::Uint64(v) => Self::encode_fixed(out, SDP_DE_UINT, &v.to_be_bytes()),
+            BtSdpDataElement::Uint128(v) => Self::encode_fixed(out, SDP_DE_UINT, &v.to_be_bytes()),
+            BtSdpDataElement::Int8(v) => Self::encode_fixed(out, SDP_DE_INT, &v.to_be_bytes()),
+            BtSdpDataElement::Int16(v) => Self::encode_fixed(out, SDP_DE_INT, &v.to_be_bytes()),
+            BtSdpDataElement::Int32(v) => Self::encode_fixed(out, SDP_DE_INT, &v.to_be_bytes()),
+            BtSdpDataElement::Int64(v) => Self::encode_fixed(out, SDP_DE_INT, &v.to_be_bytes()),
+            BtSdpDataElement::Int128(v) => Self::encode_fixed(out, SDP_DE_INT, &v.to_be_bytes()),
+            BtSdpDataElement::Uuid16(v) => Self::encode_fixed(out, SDP_DE_UUID, &v.to_be_bytes()),
+            BtSdpDataElement::Uuid32(v) => Self::encode_fixed(out, SDP_DE_UUID, &v.to_be_bytes()),
+            BtSdpDataElement::Uuid128(v) => Self::encode_fixed(out, SDP_DE_UUID, v),
+            BtSdpDataElement::Text(s) => Self::encode_variable(out, SDP_DE_TEXT, s.as_bytes()),
+            BtSdpDataElement::Bool(b) => Self::encode_fixed(out, SDP_DE_BOOL, &[*b as u8]),
+            BtSdpDataElement::Sequence(elements) | BtSdpDataElement::Alternative(elements) => {
+                let de_type = match self {
+                    BtSdpDataElement::Sequence(_) => SDP_DE_SEQUENCE,
+                    _ => SDP_DE_ALTERNATIVE,
+                };
+                let mut value = vec![];
+                for element in elements {
+                    element.encode(&mut value);
+                }
+                Self::encode_variable(out, de_type, &value);
+            }
+            BtSdpDataElement::Url(s) => Self::encode_variable(out, SDP_DE_URL, s.as_bytes()),
+        }
+    }
+
+    fn encode_fixed(out: &mut Vec<u8>, de_type: u8, value: &[u8]) {
+        let size_index = match value.len() {
+            1 => 0,
+            2 => 1,
+            4 => 2,
+            8 => 3,
+            _ => 4,
+        };
+        out.push(de_type << 3 | size_index);
+        out.extend_from_slice(value);
+    }
+
+    fn encode_variable(out: &mut Vec<u8>, de_type: u8, value: &[u8]) {
+        if let Ok(len) = u8::try_from(value.len()) {
+            out.push(de_type << 3 | 5);
+            out.push(len);
+        } else if let Ok(len) = u16::try_from(value.len()) {
+            out.push(de_type << 3 | 6);
+            out.extend_from_slice(&len.to_be_bytes());
+        } else {
+            out.push(de_type << 3 | 7);
+            out.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        }
+        out.extend_from_slice(value);
+    }
+
+    /// Decodes the data element at the start of |data|. Returns the element and the number of
+    /// bytes it was encoded in, or None if it's malformed.
+    pub fn decode(data: &[u8]) -> Option<(BtSdpDataElement, usize)> {
+        let (&header, rest) = data.split_first()?;
+        let de_type = header >> 3;
+        let (len, offset): (usize, usize) = match header & 0x07 {
+            0 if de_type == SDP_DE_NIL => (0, 1),
+            0 => (1, 1),
+            1 => (2, 1),
+            2 => (4, 1),
+            3 => (8, 1),
+            4 => (16, 1),
+            5 => (usize::from(*rest.first()?), 2),
+            6 => (usize::from(u16::from_be_bytes(rest.get(..2)?.try_into().ok()?)), 3),
+            _ => (u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize, 5),
+        };
+        let value = data.get(offset..offset.checked_add(len)?)?;
+
+        let element = match (de_type, len) {
+            (SDP_DE_NIL, 0) => BtSdpDataElement::Nil,
+            (SDP_DE_UINT, 1) => BtSdpDataElement::Uint8(value[0]),
+            (SDP_DE_UINT, 2) => {
+                BtSdpDataElement::Uint16(u16::from_be_bytes(value.try_into().ok()?))
+            }
+            (SDP_DE_UINT, 4) => {
+                BtSdpDataElement::Uint32(u32::from_be_bytes(value.try_into().ok()?))
+            }
+            (SDP_DE_UINT, 8) => {
+                BtSdpDataElement::Uint64(u64::from_be_bytes(value.try_into().ok()?))
+            }
+            (SDP_DE_UINT, 16) => {
+                BtSdpDataElement::Uint128(u128::from_be_bytes(value.try_into().ok()?))
+            }
+            (SDP_DE_INT, 1) => BtSdpDataElement::Int8(value[0] as i8),
+            (SDP_DE_INT, 2) => BtSdpDataElement::Int16(i16::from_be_bytes(value.try_into().ok()?)),
+            (SDP_DE_INT, 4) => BtSdpDataElement::Int32(i32::from_be_bytes(value.try_into().ok()?)),
+            (SDP_DE_INT, 8) => BtSdpDataElement::Int64(i64::from_be_bytes(value.try_into().ok()?)),
+            (SDP_DE_INT, 16) => {
+                BtSdpDataElement::Int128(i128::from_be_bytes(value.try_into().ok()?))
+            }
+            (SDP_DE_UUID, 2) => {
+                BtSdpDataElement::Uuid16(u16::from_be_bytes(value.try_into().ok()?))
+            }
+            (SDP_DE_UUID, 4) => {
+                BtSdpDataElement::Uuid32(u32::from_be_bytes(value.try_into().ok()?))
+            }
+            (SDP_DE_UUID, 16) => BtSdpDataElement::Uuid128(value.try_into().ok()?),
+            (SDP_DE_TEXT, _) => BtSdpDataElement::Text(String::from_utf8_lossy(value).into_owned()),
+            (SDP_DE_BOOL, 1) => BtSdpDataElement::Bool(value[0] != 0),
+            (SDP_DE_SEQUENCE, _) | (SDP_DE_ALTERNATIVE, _) => {
+                let mut elements = vec![];
+                let mut rest = value;
+                while !rest.is_empty() {
+                    let (element, used) = BtSdpDataElement::decode(rest)?;
+                    elements.push(element);
+                    rest = &rest[used..];
+                }
+                match de_type {
+                    SDP_DE_SEQUENCE => BtSdpDataElement::Sequence(elements),
+                    _ => BtSdpDataElement::Alternative(elements),
+                }
+            }
+            (SDP_DE_URL, _) => BtSdpDataElement::Url(String::from_utf8_lossy(value).into_owned()),
+            _ => return None,
+        };
+        Some((element, offset + len))
+    }
+}
+
+/// An attribute of a |BtSdpCustomRecord|. The value is an encoded |BtSdpDataElement|.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BtSdpAttribute {
+    pub id: u16,
+    pub value: Vec<u8>,
+}
+
+impl BtSdpAttribute {
+    pub fn new(id: u16, value: &BtSdpDataElement) -> Self {
+        let mut encoded = vec![];
+        value.encode(&mut encoded);
+        BtSdpAttribute { id, value: encoded }
+    }
+
+    /// Decodes the value, which must be exactly one data element.
+    pub fn decode_value(&self) -> Option<BtSdpDataElement> {
+        match BtSdpDataElement::decode(&self.value) {
+            Some((element, len)) if len == self.value.len() => Some(element),
+            _ => None,
+        }
+    }
+}
+
+impl BtSdpHeaderOverlay {
+    /// Decodes the attribute list that raw search results carry in the user1 data, or None if
+    /// there isn't a valid one.
+    pub fn attributes(&self) -> Option<Vec<BtSdpAttribute>> {
+        BtSdpCustomRecord::decode_attributes(&self.user1_data)
+    }
+}
+
+/// A record built from arbitrary attributes, used to publish services that have no dedicated
+/// record type. The service record handle (attribute 0x0000) is assigned by the stack and can't
+/// be set.
+#[derive(Clone, Debug)]
+pub struct BtSdpCustomRecord {
+    pub hdr: BtSdpHeaderOverlay,
+    pub attributes: Vec<BtSdpAttribute>,
+}
+
+impl BtSdpCustomRecord {
+    /// Creates a record of the service |uuid|, with its service class ID list as the only
+    /// attribute.
+    pub fn new(uuid: Uuid) -> Self {
+        let mut record = BtSdpCustomRecord {
+            hdr: BtSdpHeaderOverlay {
+                sdp_type: BtSdpType::Custom,
+                uuid,
+                service_name_length: 0,      // Not used
+                service_name: String::new(), // Not used
+                rfcomm_channel_number: 0,    // Not used
+                l2cap_psm: 0,                // Not used
+                profile_version: 0,          // Not used
+                user1_len: 0,                // Set from the attributes
+                user1_data: vec![],          // Set from the attributes
+                user2_len: 0,                // Not used
+                user2_data: vec![],          // Not used
+            },
+            attributes: vec![],
+        };
+        record.add_attribute(
+            SDP_ATTR_ID_SERVICE_CLASS_ID_LIST,
+            &BtSdpDataElement::Sequence(vec![BtSdpDataElement::Uuid128(uuid.uu)]),
+        );
+        record
+    }
+
+    /// Adds the attribute |id|, replacing the existing value if there is one.
+    pub fn add_attribute(&mut self, id: u16, value: &BtSdpDataElement) -> &mut Self {
+        let attribute = BtSdpAttribute::new(id, value);
+        match self.attributes.iter_mut().find(|a| a.id == id) {
+            Some(existing) => *existing = attribute,
+            None => self.attributes.push(attribute),
+        }
+        self
+    }
+
+    pub fn add_service_name(&mut self, name: &str) -> &mut Self {
+        self.add_attribute(SDP_ATTR_ID_SERVICE_NAME, &BtSdpDataElement::Text(name.to_string()))
+    }
+
+    /// Makes the service reachable on the RFCOMM |channel|.
+    pub fn add_rfcomm_channel(&mut self, channel: u8) -> &mut Self {
+        self.add_attribute(
+            SDP_ATTR_ID_PROTOCOL_DESC_LIST,
+            &BtSdpDataElement::Sequence(vec![
+                BtSdpDataElement::Sequence(vec![BtSdpDataElement::Uuid16(SDP_UUID_PROTOCOL_L2CAP)]),
+                BtSdpDataElement::Sequence(vec![
+                    BtSdpDataElement::Uuid16(SDP_UUID_PROTOCOL_RFCOMM),
+                    BtSdpDataElement::Uint8(channel),
+                ]),
+            ]),
+        )
+    }
+
+    /// Makes the service reachable on the L2CAP |psm|.
+    pub fn add_l2cap_psm(&mut self, psm: u16) -> &mut Self {
+        self.add_attribute(
+            SDP_ATTR_ID_PROTOCOL_DESC_LIST,
+            &BtSdpDataElement::Sequence(vec![BtSdpDataElement::Sequence(vec![
+                BtSdpDataElement::Uuid16(SDP_UUID_PROTOCOL_L2CAP),
+                BtSdpDataElement::Uint16(psm),
+            ])]),
+        )
+    }
+
+    /// Makes the service browsable.
+    pub fn add_public_browse_group(&mut self) -> &mut Self {
+        self.add_attribute(
+            SDP_ATTR_ID_BROWSE_GROUP_LIST,
+            &BtSdpDataElement::Sequence(vec![BtSdpDataElement::Uuid16(
+                SDP_UUID_PUBLIC_BROWSE_GROUP,
+            )]),
+        )
+    }
+
+    /// Encodes the attributes into an attribute list: a data element sequence of the attribute
+    /// IDs, each followed by the attribute value.
+    fn encode_attributes(&self) -> Vec<u8> {
+        let mut list = vec![];
+        for attribute in &self.attributes {
+            BtSdpDataElement::Uint16(attribute.id).encode(&mut list);
+            list.extend_from_slice(&attribute.value);
+        }
+
+        let mut out = vec![];
+        BtSdpDataElement::encode_variable(&mut out, SDP_DE_SEQUENCE, &list);
+        out
+    }
+
+    fn decode_attributes(data: &[u8]) -> Option<Vec<BtSdpAttribute>> {
+        let elements = match BtSdpDataElement::decode(data)? {
+            (BtSdpDataElement::Sequence(elements), len) if len == data.len() => elements,
+            _ => return None,
+        };
+        if elements.len() % 2 != 0 {
+            return None;
+        }
+        elements
+            .chunks(2)
+            .map(|pair| match &pair[0] {
+                BtSdpDataElement::Uint16(id) => Some(BtSdpAttribute::new(*id, &pair[1])),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Converts a header carrying the attribute list of the record in the user1 data.
+    fn from_raw(mut hdr: BtSdpHeaderOverlay) -> Option<Self> {
+        let attributes = hdr.attributes()?;
+        hdr.sdp_type = BtSdpType::Custom;
+        hdr.user1_len = 0;
+        hdr.user1_data = vec![];
+        Some(BtSdpCustomRecord { hdr, attributes })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum BtSdpRecord {
     HeaderOverlay(BtSdpHeaderOverlay),
@@ -297,6 +632,7 @@ pub enum BtSdpRecord {
     SapServer(BtSdpSapRecord),
     Dip(BtSdpDipRecord),
     Mps(BtSdpMpsRecord),
+    Custom(BtSdpCustomRecord),
 }
 
 impl From<bindings::bluetooth_sdp_record> for BtSdpRecord {
@@ -307,6 +643,13 @@ impl From<bindings::bluetooth_sdp_record> for BtSdpRecord {
             BtSdpType::Raw => unsafe {
                 BtSdpRecord::HeaderOverlay(BtSdpHeaderOverlay::from(item.hdr))
             },
+            BtSdpType::Custom => {
+                let hdr = unsafe { BtSdpHeaderOverlay::from(item.hdr) };
+                match BtSdpCustomRecord::from_raw(hdr.clone()) {
+                    Some(record) => BtSdpRecord::Custom(record),
+                    None => BtSdpRecord::HeaderOverlay(hdr),
+                }
+            }
             BtSdpType::MapMas => unsafe { BtSdpRecord::MapMas(BtSdpMasRecord::from(item.mas)) },
             BtSdpType::MapMns => unsafe { BtSdpRecord::MapMns(BtSdpMnsRecord::from(item.mns)) },
             BtSdpType::PbapPse => unsafe { BtSdpRecord::PbapPse(BtSdpPseRecord::from(item.pse)) },
@@ -406,6 +749,12 @@ impl BtSdpRecord {
                     supported_dependencies: mps.supported_dependencies,
                 },
             },
+            BtSdpRecord::Custom(custom) => {
+                custom.hdr.sdp_type = BtSdpType::Custom;
+                custom.hdr.user1_data = custom.encode_attributes();
+                custom.hdr.user1_len = custom.hdr.user1_data.len() as i32;
+                bindings::bluetooth_sdp_record { hdr: BtSdpRecord::convert_header(&mut custom.hdr) }
+            }
         }
     }
 }
@@ -491,3 +840,55 @@ impl Sdp {
         BtStatus::from(ccall!(self, remove_sdp_record, handle))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_element_round_trip() {
+        let element = BtSdpDataElement::Sequence(vec![
+            BtSdpDataElement::Nil,
+            BtSdpDataElement::Uint8(0x12),
+            BtSdpDataElement::Int16(-2),
+            BtSdpDataElement::Uuid16(0x1101),
+            BtSdpDataElement::Uuid128([0xab; 16]),
+            BtSdpDataElement::Bool(true),
+            BtSdpDataElement::Text("x".repeat(300)),
+            BtSdpDataElement::Alternative(vec![BtSdpDataElement::Url(String::from("a.b"))]),
+        ]);
+        let mut encoded = vec![];
+        element.encode(&mut encoded);
+
+        assert_eq!(&encoded[..3], &[0x36, 0x01, 0x52]);
+        assert_eq!(BtSdpDataElement::decode(&encoded), Some((element, encoded.len())));
+
+        // Truncated value and unknown type.
+        assert_eq!(BtSdpDataElement::decode(&[0x09, 0x01]), None);
+        assert_eq!(BtSdpDataElement::decode(&[0x48, 0x00]), None);
+    }
+
+    #[test]
+    fn test_custom_record_attributes() {
+        let mut record = BtSdpCustomRecord::new(Uuid::from([0x11; 16]));
+        record.add_service_name("Serial").add_rfcomm_channel(3).add_rfcomm_channel(5);
+        assert_eq!(record.attributes.len(), 3);
+        assert_eq!(
+            record.attributes[1].decode_value(),
+            Some(BtSdpDataElement::Text(String::from("Serial")))
+        );
+
+        let mut hdr = record.hdr.clone();
+        hdr.sdp_type = BtSdpType::Raw;
+        hdr.user1_data = record.encode_attributes();
+        assert_eq!(hdr.attributes(), Some(record.attributes.clone()));
+        let decoded = BtSdpCustomRecord::from_raw(hdr).unwrap();
+        assert_eq!(decoded.attributes, record.attributes);
+        assert_eq!(decoded.hdr.sdp_type, BtSdpType::Custom);
+
+        // An attribute ID without a value.
+        let mut list = vec![];
+        BtSdpDataElement::Sequence(vec![BtSdpDataElement::Uint16(1)]).encode(&mut list);
+        assert!(BtSdpCustomRecord::decode_attributes(&list).is_none());
+    }
+}
@@ -6,9 +6,19 @@ use crate::utils::LTCheckedPtr;
 /// List of properties accessible to Rust. Add new ones here as they become
 /// necessary.
 pub enum PropertyI32 {
+    // bluetooth.core.classic
+    PageScanType,
+    PageScanInterval,
+    PageScanWindow,
+    InquiryScanType,
+    InquiryScanInterval,
+    InquiryScanWindow,
+
     // bluetooth.core.le
     LeInquiryScanInterval,
     LeInquiryScanWindow,
+    LeConnectionScanIntervalSlow,
+    LeConnectionScanWindowSlow,
 
     // bluetooth.device_id
     ProductId,
@@ -21,12 +31,39 @@ impl Into<(Vec<u8>, i32)> for PropertyI32 {
     /// Convert the property into the property key name and a default value.
     fn into(self) -> (Vec<u8>, i32) {
         let (key, default_value) = match self {
+            // Scan type is 0 for standard and 1 for interlaced.
+            PropertyI32::PageScanType => ("bluetooth.core.classic.page_scan_type", 1),
+
+            // Page scan interval = N * 0.625 ms; value of 1024 = 640ms
+            PropertyI32::PageScanInterval => ("bluetooth.core.classic.page_scan_interval", 1024),
+
+            // Page scan window = N * 0.625 ms; value of 18 = 11.25ms
+            PropertyI32::PageScanWindow => ("bluetooth.core.classic.page_scan_window", 18),
+
+            PropertyI32::InquiryScanType => ("bluetooth.core.classic.inq_scan_type", 1),
+
+            // Inquiry scan interval = N * 0.625 ms; value of 2048 = 1.28s
+            PropertyI32::InquiryScanInterval => ("bluetooth.core.classic.inq_scan_interval", 2048),
+
+            // Inquiry scan window = N * 0.625 ms; value of 18 = 11.25ms
+            PropertyI32::InquiryScanWindow => ("bluetooth.core.classic.inq_scan_window", 18),
+
             // Inquiry scan interval  = N * 0.625 ms; value of 432 = 270ms
             PropertyI32::LeInquiryScanInterval => ("bluetooth.core.le.inquiry_scan_interval", 430),
 
             //Inquiry scan window  = N * 0.625 ms; value of 216 = 135ms
             PropertyI32::LeInquiryScanWindow => ("bluetooth.core.le.inquiry_scan_window", 216),
 
+            // Background connection scan interval = N * 0.625 ms; value of 2048 = 1.28s
+            PropertyI32::LeConnectionScanIntervalSlow => {
+                ("bluetooth.core.le.connection_scan_interval_slow", 2048)
+            }
+
+            // Background connection scan window = N * 0.625 ms; value of 48 = 30ms
+            PropertyI32::LeConnectionScanWindowSlow => {
+                ("bluetooth.core.le.connection_scan_window_slow", 48)
+            }
+
             PropertyI32::ProductId => ("bluetooth.device_id.product_id", 0),
             PropertyI32::ProductVersion => ("bluetooth.device_id.product_version", 0),
 
@@ -53,3 +90,18 @@ pub fn get_i32(prop: PropertyI32) -> i32 {
         )
     }
 }
+
+/// Set the i32 value for a system property. Returns whether it was set.
+pub fn set_i32(prop: PropertyI32, value: i32) -> bool {
+    let (key, _) = prop.into();
+    let value = value.to_string().bytes().chain("\0".bytes()).collect::<Vec<u8>>();
+    let key_cptr = LTCheckedPtr::from(&key);
+    let value_cptr = LTCheckedPtr::from(&value);
+
+    unsafe {
+        bindings::osi_property_set(
+            key_cptr.cast_into::<std::os::raw::c_char>(),
+            value_cptr.cast_into::<std::os::raw::c_char>(),
+        ) == 0
+    }
+}
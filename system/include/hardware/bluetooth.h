@@ -235,6 +235,50 @@ typedef struct {
   uint16_t version;
 } bt_vendor_product_info_t;
 
+/** Bluetooth security state of a remote device. It describes the stored bond
+ * and the current links without including any key */
+typedef struct {
+  /* HCI link key type of the BR/EDR bond, 0xFF if there is none */
+  uint8_t link_key_type;
+  /* LE keys distributed by the remote device */
+  bool le_ltk_present;
+  bool le_irk_present;
+  bool le_csrk_present;
+  /* Whether the keys of one transport were derived from the other one */
+  bool is_ctkd;
+  /* State of the current links */
+  bool bredr_encrypted;
+  bool bredr_authenticated;
+  bool le_encrypted;
+  bool le_authenticated;
+  /* Encryption key size of the encrypted link, BR/EDR first, 0 if none */
+  uint8_t encryption_key_size;
+} bt_remote_security_info_t;
+
+/** Configuration of the GAP service served by the GATT servers */
+typedef struct {
+  bt_bdname_t device_name;
+  /* Whether remote devices may read and write the device name */
+  bool device_name_readable;
+  bool device_name_writable;
+  /* Whether a name written by a remote device renames the adapter
+   * persistently. Otherwise it is only reported to the upper layers. */
+  bool persist_written_device_name;
+  uint16_t appearance;
+  bool appearance_writable;
+  /* Peripheral Preferred Connection Parameters, in controller units */
+  bool has_preferred_connection_parameters;
+  uint16_t min_interval;
+  uint16_t max_interval;
+  uint16_t peripheral_latency;
+  uint16_t supervision_timeout;
+  /* Central Address Resolution, if present */
+  bool has_central_address_resolution;
+  bool central_address_resolution;
+  /* Whether the Resolvable Private Address Only characteristic is present */
+  bool rpa_only;
+} bt_gap_configuration_t;
+
 /* Stored the default/maximum/minimum buffer time for dynamic audio buffer.
  * For A2DP offload usage, the unit is millisecond.
  * For A2DP legacy usage, the unit is buffer queue size*/
@@ -542,6 +586,13 @@ typedef void (*link_quality_report_callback)(
     int retransmission_count, int packets_not_receive_count,
     int negative_acknowledgement_count);
 
+/** Bluetooth link quality report callback including the remote address. If
+ * set, it is called instead of link_quality_report_cb */
+typedef void (*link_quality_report_with_address_callback)(
+    RawAddress* remote_bd_addr, uint64_t timestamp, int report_id, int rssi,
+    int snr, int retransmission_count, int packets_not_receive_count,
+    int negative_acknowledgement_count);
+
 /** Switch the buffer size callback */
 typedef void (*switch_buffer_size_callback)(bool is_low_latency_buffer_size);
 
@@ -597,6 +648,7 @@ typedef struct {
   switch_buffer_size_callback switch_buffer_size_cb;
   switch_codec_callback switch_codec_cb;
   le_rand_callback le_rand_cb;
+  link_quality_report_with_address_callback link_quality_report_with_address_cb;
 } bt_callbacks_t;
 
 typedef void (*alarm_cb)(void* data);
@@ -940,6 +992,68 @@ typedef struct {
   /** check if pbap pse dynamic version upgrade is enable */
   bool (*pbap_pse_dynamic_version_upgrade_is_enabled)();
 
+  /**
+   * Enable or disable the link quality reports of the controller, which are
+   * delivered through link_quality_report_cb or
+   * link_quality_report_with_address_cb.
+   *
+   * @param enable true to enable link quality reports
+   * @param min_report_interval_ms minimum interval between two reports
+   */
+  int (*set_link_quality_report)(bool enable, uint16_t min_report_interval_ms);
+
+  /**
+   * Configure the local address used with LE privacy. The address type takes
+   * effect the next time the adapter is enabled, the rotation timeout when
+   * privacy is configured again.
+   *
+   * @param use_non_resolvable_address use non-resolvable private addresses
+   * instead of resolvable ones for advertising and scanning
+   * @param rpa_rotation_timeout_ms rotation timeout of the private address, 0
+   * for the default
+   */
+  int (*set_le_privacy_parameters)(bool use_non_resolvable_address,
+                                   uint32_t rpa_rotation_timeout_ms);
+
+  /**
+   * Read the local Identity Resolving Key.
+   *
+   * @param irk buffer of 16 bytes receiving the key
+   */
+  int (*get_local_irk)(uint8_t* irk);
+
+  /**
+   * Read how the remote device is bonded and how the current links to it are
+   * secured. Blocks until the stack has read them.
+   *
+   * @param bd_addr address of the remote device
+   * @param info receives the security info
+   */
+  int (*get_remote_security_info)(const RawAddress* bd_addr,
+                                  bt_remote_security_info_t* info);
+
+  /**
+   * Regenerate the local identity keys. Devices bonded over LE can no longer
+   * resolve the local address afterwards.
+   */
+  int (*regenerate_local_irk)(void);
+
+  /**
+   * Write the page scan and inquiry scan type, interval and window configured
+   * in the system properties to the controller. The LE connection scan
+   * parameters are read every time the background connection scan starts.
+   */
+  int (*apply_scan_parameters)(void);
+
+  /**
+   * Configure the GAP service of the GATT servers of the Rust stack. It has
+   * no effect on the legacy GAP service.
+   *
+   * @param configuration the name, appearance and optional characteristics
+   * served, and whether remote devices may write them
+   */
+  int (*set_gap_configuration)(const bt_gap_configuration_t* configuration);
+
 } bt_interface_t;
 
 #define BLUETOOTH_INTERFACE_STRING "bluetoothInterface"
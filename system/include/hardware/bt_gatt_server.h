@@ -123,6 +123,14 @@ typedef void (*conn_updated_callback)(int conn_id, uint16_t interval,
 typedef void (*subrate_change_callback)(int conn_id, uint16_t subrate_factor,
                                         uint16_t latency, uint16_t cont_num,
                                         uint16_t timeout, uint8_t status);
+
+/**
+ * Callback invoked when a read, write or execute write request will no longer
+ * be answered, because it timed out or its connection went away. Any response
+ * sent for it afterwards is dropped.
+ */
+typedef void (*request_cancelled_callback)(int conn_id, int trans_id);
+
 typedef struct {
   register_server_callback register_server_cb;
   connection_callback connection_cb;
@@ -141,6 +149,7 @@ typedef struct {
   phy_updated_callback phy_updated_cb;
   conn_updated_callback conn_updated_cb;
   subrate_change_callback subrate_chg_cb;
+  request_cancelled_callback request_cancelled_cb;
 } btgatt_server_callbacks_t;
 
 /** Represents the standard BT-GATT server interface. */
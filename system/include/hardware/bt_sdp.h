@@ -37,7 +37,8 @@ typedef enum {
   SDP_TYPE_OPP_SERVER,  // Object Push Profile
   SDP_TYPE_SAP_SERVER,  // SIM Access Profile
   SDP_TYPE_DIP,         // Device Identification Profile
-  SDP_TYPE_MPS          // Multi-Profile Specification
+  SDP_TYPE_MPS,         // Multi-Profile Specification
+  SDP_TYPE_CUSTOM       // Record built from the attribute list in user1_ptr
 } bluetooth_sdp_types;
 
 typedef struct _bluetooth_sdp_hdr {
@@ -63,7 +64,10 @@ typedef struct _bluetooth_sdp_hdr_overlay {
   int32_t l2cap_psm;
   int32_t profile_version;
 
-  // User pointers, only used for some signals - see bluetooth_sdp_ops_record
+  // User pointers, only used for some signals - see bluetooth_sdp_ops_record.
+  // For SDP_TYPE_RAW search results and SDP_TYPE_CUSTOM records, user1_ptr
+  // holds the attribute list of the record: a data element sequence of
+  // attribute IDs, each followed by the data element of the attribute value.
   int user1_ptr_len;
   const uint8_t* user1_ptr;
   int user2_ptr_len;
@@ -16,6 +16,8 @@
 
 #include "main/shim/acl_api.h"
 
+#include <atomic>
+#include <chrono>
 #include <cstddef>
 #include <cstdint>
 #include <future>
@@ -72,16 +74,39 @@ void bluetooth::shim::ACL_WriteData(uint16_t handle, BT_HDR* p_buf) {
   osi_free(p_buf);
 }
 
+namespace {
+std::atomic_bool use_non_resolvable_address_{false};
+std::atomic<std::chrono::milliseconds::rep> rpa_rotation_timeout_ms_{0};
+}  // namespace
+
+void bluetooth::shim::ACL_SetLePrivacyParameters(
+    bool use_non_resolvable_address,
+    std::chrono::milliseconds rpa_rotation_timeout) {
+  use_non_resolvable_address_ = use_non_resolvable_address;
+  rpa_rotation_timeout_ms_ = rpa_rotation_timeout.count();
+}
+
 void bluetooth::shim::ACL_ConfigureLePrivacy(bool is_le_privacy_enabled) {
   hci::LeAddressManager::AddressPolicy address_policy =
-      is_le_privacy_enabled
-          ? hci::LeAddressManager::AddressPolicy::USE_RESOLVABLE_ADDRESS
-          : hci::LeAddressManager::AddressPolicy::USE_PUBLIC_ADDRESS;
+      hci::LeAddressManager::AddressPolicy::USE_PUBLIC_ADDRESS;
+  if (is_le_privacy_enabled) {
+    address_policy =
+        use_non_resolvable_address_
+            ? hci::LeAddressManager::AddressPolicy::USE_NON_RESOLVABLE_ADDRESS
+            : hci::LeAddressManager::AddressPolicy::USE_RESOLVABLE_ADDRESS;
+  }
   hci::AddressWithType empty_address_with_type(
       hci::Address{}, hci::AddressType::RANDOM_DEVICE_ADDRESS);
   /* 7 minutes minimum, 15 minutes maximum for random address refreshing */
-  auto minimum_rotation_time = std::chrono::minutes(7);
-  auto maximum_rotation_time = std::chrono::minutes(15);
+  std::chrono::milliseconds minimum_rotation_time = std::chrono::minutes(7);
+  std::chrono::milliseconds maximum_rotation_time = std::chrono::minutes(15);
+  /* A configured timeout rotates the address within its last tenth, the
+   * rotation time being randomized between minimum and maximum */
+  std::chrono::milliseconds rpa_rotation_timeout(rpa_rotation_timeout_ms_);
+  if (rpa_rotation_timeout.count() >= 10) {
+    minimum_rotation_time = rpa_rotation_timeout - rpa_rotation_timeout / 10;
+    maximum_rotation_time = rpa_rotation_timeout;
+  }
 
   Stack::GetInstance()
       ->GetStackManager()
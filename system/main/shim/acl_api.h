@@ -16,6 +16,7 @@
 
 #pragma once
 
+#include <chrono>
 #include <optional>
 
 #include "stack/include/bt_hdr.h"
@@ -37,6 +38,10 @@ void ACL_Disconnect(uint16_t handle, bool is_classic, tHCI_STATUS reason,
                     std::string comment);
 void ACL_WriteData(uint16_t handle, BT_HDR* p_buf);
 void ACL_ConfigureLePrivacy(bool is_le_privacy_enabled);
+// Sets the local address type and rotation timeout used by the next
+// |ACL_ConfigureLePrivacy|. A zero timeout keeps the default rotation time.
+void ACL_SetLePrivacyParameters(bool use_non_resolvable_address,
+                                std::chrono::milliseconds rpa_rotation_timeout);
 void ACL_Shutdown();
 void ACL_IgnoreAllLeConnections();
 
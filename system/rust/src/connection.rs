@@ -4,7 +4,12 @@
 //! and retries failed connections
 
 use std::{
-    cell::RefCell, collections::HashSet, fmt::Debug, future::Future, hash::Hash, ops::Deref,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    future::Future,
+    hash::Hash,
+    ops::Deref,
     time::Duration,
 };
 
@@ -18,20 +23,36 @@ use crate::{
 
 use self::{
     acceptlist_manager::{determine_target_state, LeAcceptlistManager},
-    attempt_manager::{ConnectionAttempts, ConnectionMode},
-    le_manager::{ErrorCode, InactiveLeAclManager, LeAclManagerConnectionCallbacks},
+    address_resolver::AddressResolver,
+    attempt_manager::{ConnectionAttempt, ConnectionAttempts},
+    background_scheduler::{BackgroundScheduler, ROTATION_INTERVAL},
+    diagnostics::{AttemptSnapshot, EventListeners},
+    le_manager::{
+        ConnectionParameters, ErrorCode, InactiveLeAclManager, LeAclManagerConnectionCallbacks,
+    },
 };
 
 mod acceptlist_manager;
+mod address_resolver;
 mod attempt_manager;
+mod background_scheduler;
+mod diagnostics;
 mod ffi;
 pub mod le_manager;
 mod mocks;
 
+pub use address_resolver::Irk;
+pub use attempt_manager::ConnectionMode;
+pub use background_scheduler::ClientPriority;
+pub use diagnostics::{ConnectionManagerEvent, ConnectionManagerSnapshot, EventHistory};
 pub use ffi::{register_callbacks, LeAclManagerImpl, LeAclManagerShim};
 use log::info;
 use scopeguard::ScopeGuard;
-use tokio::{task::spawn_local, time::timeout};
+use tokio::{
+    sync::mpsc::UnboundedReceiver,
+    task::{spawn_local, JoinHandle},
+    time::{sleep, timeout},
+};
 
 /// Possible errors returned when making a connection attempt
 #[derive(Debug, PartialEq, Eq)]
@@ -86,18 +107,44 @@ pub struct ConnectionManager {
 struct ConnectionManagerState {
     /// All pending connection attempts (unresolved direct + all background)
     attempts: ConnectionAttempts,
-    /// The addresses we are currently connected to
-    current_connections: HashSet<AddressWithType>,
+    /// The identity addresses we are currently connected to, mapped to the address
+    /// reported in the connection complete event (which may be an RPA)
+    current_connections: HashMap<AddressWithType, AddressWithType>,
+    /// Resolves RPAs of bonded peers to their identity addresses
+    address_resolver: AddressResolver,
     /// Tracks the state of the LE connect list, and updates it to drive to a
     /// specified target state
     acceptlist_manager: LeAcceptlistManager,
+    /// Decides which background attempts fit on the accept list
+    background_scheduler: BackgroundScheduler,
+    /// The priority of each client's background attempts (zero if not set)
+    client_priorities: HashMap<ConnectionManagerClient, ClientPriority>,
+    /// Subscribers to connection manager events
+    listeners: EventListeners,
+    /// Rotates the background attempts, while some are waiting for a slot
+    rotation_task: Option<JoinHandle<()>>,
+    /// Handle to the manager, for the rotation task
+    manager: WeakBox<ConnectionManager>,
+}
+
+impl ConnectionManagerState {
+    fn on_attempts_cancelled(&mut self, attempts: Vec<ConnectionAttempt>) {
+        for attempt in attempts {
+            self.listeners.emit(ConnectionManagerEvent::AttemptCancelled {
+                client: attempt.client,
+                address: attempt.remote_address,
+                mode: attempt.mode,
+            });
+        }
+    }
 }
 
 struct ConnectionManagerCallbackHandler(WeakBox<ConnectionManager>);
 
-const DIRECT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(
-    29, /* ugly hack to avoid fighting with le_impl timeout, until I remove that timeout */
-);
+/// The timeout used for direct connections, unless the client specifies otherwise.
+/// Note that le_impl also times out direct connections at 30s, so longer timeouts
+/// are not yet effective.
+pub const DEFAULT_DIRECT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(29);
 
 impl LeAclManagerConnectionCallbacks for ConnectionManagerCallbackHandler {
     fn on_le_connect(&self, address: AddressWithType, result: Result<LeConnection, ErrorCode>) {
@@ -118,23 +165,91 @@ impl ConnectionManagerCallbackHandler {
 impl ConnectionManager {
     /// Constructor
     pub fn new(le_manager: impl InactiveLeAclManager) -> SharedBox<Self> {
-        SharedBox::new_cyclic(|weak| Self {
-            state: RefCell::new(ConnectionManagerState {
-                attempts: ConnectionAttempts::new(),
-                current_connections: HashSet::new(),
-                acceptlist_manager: LeAcceptlistManager::new(
-                    le_manager.register_callbacks(ConnectionManagerCallbackHandler(weak)),
-                ),
-            }),
+        SharedBox::new_cyclic(|weak| {
+            let le_manager =
+                le_manager.register_callbacks(ConnectionManagerCallbackHandler(weak.clone()));
+            let mut background_scheduler = BackgroundScheduler::new();
+            background_scheduler.set_capacity(le_manager.acceptlist_capacity());
+            Self {
+                state: RefCell::new(ConnectionManagerState {
+                    attempts: ConnectionAttempts::new(),
+                    current_connections: HashMap::new(),
+                    address_resolver: AddressResolver::new(),
+                    acceptlist_manager: LeAcceptlistManager::new(le_manager),
+                    background_scheduler,
+                    client_priorities: HashMap::new(),
+                    listeners: EventListeners::default(),
+                    rotation_task: None,
+                    manager: weak,
+                }),
+            }
         })
     }
 }
 
+/// While some background attempts are waiting for a slot on the accept list, rotate
+/// them after ROTATION_INTERVAL so none of them starve. Otherwise, nothing is scheduled.
+fn schedule_rotation(state: &mut ConnectionManagerState) {
+    if !state.background_scheduler.is_over_capacity() {
+        if let Some(task) = state.rotation_task.take() {
+            task.abort();
+        }
+        return;
+    }
+    if state.rotation_task.is_some() {
+        return;
+    }
+    let manager = state.manager.clone();
+    state.rotation_task = Some(spawn_local(async move {
+        sleep(ROTATION_INTERVAL).await;
+        manager.with(|manager| {
+            if let Some(manager) = manager {
+                manager.rotate_background_connections();
+            }
+        });
+    }));
+}
+
 /// Make the state of the LeAcceptlistManager consistent with the attempts tracked in ConnectionAttempts
 fn reconcile_state(state: &mut ConnectionManagerState) {
-    state
-        .acceptlist_manager
-        .drive_to_state(determine_target_state(&state.attempts.active_attempts()));
+    let previous_lists = state.acceptlist_manager.current_lists();
+    let mut target = determine_target_state(&state.attempts.active_attempts());
+    // The LE manager only matches exact addresses, so it does not know that we are
+    // connected to peers whose connection complete event reported an RPA. Stop it
+    // from trying to connect to their identity addresses again.
+    for (identity, address) in &state.current_connections {
+        if identity != address {
+            target.background_list.remove(identity);
+            target.direct_list.remove(identity);
+        }
+    }
+    // If the accept list is over capacity, only keep the background attempts that
+    // are currently scheduled
+    let mut priorities = HashMap::new();
+    for (attempt, _) in state.attempts.active_attempts() {
+        if attempt.mode == ConnectionMode::Background {
+            let priority =
+                state.client_priorities.get(&attempt.client).copied().unwrap_or_default();
+            let entry = priorities.entry(attempt.remote_address).or_default();
+            *entry = priority.max(*entry);
+        }
+    }
+    let connected = state.current_connections.keys().copied().collect::<HashSet<_>>();
+    state.background_scheduler.select(&mut target, &priorities, &connected);
+    schedule_rotation(state);
+    state.acceptlist_manager.drive_to_state(target);
+
+    let current_lists = state.acceptlist_manager.current_lists();
+    let mut removed = previous_lists.difference(&current_lists).copied().collect::<Vec<_>>();
+    removed.sort_by_key(|(address, _)| address.address);
+    for (address, mode) in removed {
+        state.listeners.emit(ConnectionManagerEvent::AcceptlistRemoved { address, mode });
+    }
+    let mut added = current_lists.difference(&previous_lists).copied().collect::<Vec<_>>();
+    added.sort_by_key(|(address, _)| address.address);
+    for (address, mode) in added {
+        state.listeners.emit(ConnectionManagerEvent::AcceptlistAdded { address, mode });
+    }
 }
 
 impl WeakBoxRef<'_, ConnectionManager> {
@@ -145,7 +260,26 @@ impl WeakBoxRef<'_, ConnectionManager> {
         client: ConnectionManagerClient,
         address: AddressWithType,
     ) -> Result<(), CreateConnectionFailure> {
-        spawn_local(timeout(DIRECT_CONNECTION_TIMEOUT, self.direct_connection(client, address)?));
+        self.start_direct_connection_with_parameters(
+            client,
+            address,
+            None,
+            DEFAULT_DIRECT_CONNECTION_TIMEOUT,
+        )
+    }
+
+    /// Start a direct connection to a peer device from a specified client, with the
+    /// given parameters (or the system defaults) and timeout. While this attempt is
+    /// pending, the parameters are merged with those of any other direct attempts to
+    /// the same peer.
+    pub fn start_direct_connection_with_parameters(
+        &self,
+        client: ConnectionManagerClient,
+        address: AddressWithType,
+        parameters: Option<ConnectionParameters>,
+        attempt_timeout: Duration,
+    ) -> Result<(), CreateConnectionFailure> {
+        spawn_local(timeout(attempt_timeout, self.direct_connection(client, address, parameters)?));
         Ok(())
     }
 
@@ -158,6 +292,7 @@ impl WeakBoxRef<'_, ConnectionManager> {
         &self,
         client: ConnectionManagerClient,
         address: AddressWithType,
+        parameters: Option<ConnectionParameters>,
     ) -> Result<
         impl Future<Output = Result<LeConnection, ConnectionFailure>>,
         CreateConnectionFailure,
@@ -165,11 +300,18 @@ impl WeakBoxRef<'_, ConnectionManager> {
         let mut state = self.state.borrow_mut();
 
         // if connected, this is a no-op
-        let attempt_and_guard = if state.current_connections.contains(&address) {
+        let connected_address = state.current_connections.get(&address).copied();
+        let attempt_and_guard = if connected_address.is_some() {
             None
         } else {
-            let pending_attempt = state.attempts.register_direct_connection(client, address)?;
+            let pending_attempt =
+                state.attempts.register_direct_connection(client, address, parameters)?;
             let attempt_id = pending_attempt.id;
+            state.listeners.emit(ConnectionManagerEvent::AttemptRegistered {
+                client,
+                address,
+                mode: ConnectionMode::Direct,
+            });
             reconcile_state(&mut state);
             Some((
                 pending_attempt,
@@ -179,7 +321,8 @@ impl WeakBoxRef<'_, ConnectionManager> {
                         this.map(|this| {
                             info!("Cancelling attempt {attempt_id:?}");
                             let mut state = this.state.borrow_mut();
-                            state.attempts.cancel_attempt_with_id(attempt_id);
+                            let cancelled = state.attempts.cancel_attempt_with_id(attempt_id);
+                            state.on_attempts_cancelled(cancelled.into_iter().collect());
                             reconcile_state(&mut state);
                         })
                     });
@@ -190,7 +333,7 @@ impl WeakBoxRef<'_, ConnectionManager> {
         Ok(async move {
             let Some((attempt, guard)) = attempt_and_guard else {
                 // if we did not make an attempt, the connection must be ready
-                return Ok(LeConnection { remote_address: address })
+                return Ok(LeConnection { remote_address: connected_address.unwrap() })
             };
             // otherwise, wait until the attempt resolves
             let ret = attempt.await;
@@ -202,14 +345,31 @@ impl WeakBoxRef<'_, ConnectionManager> {
 }
 
 impl ConnectionManager {
-    /// Start a background connection to a peer device with given parameters from a specified client.
+    /// Start a background connection to a peer device from a specified client.
     pub fn add_background_connection(
         &self,
         client: ConnectionManagerClient,
         address: AddressWithType,
+    ) -> Result<(), CreateConnectionFailure> {
+        self.add_background_connection_with_parameters(client, address, None)
+    }
+
+    /// Start a background connection to a peer device with given parameters (or the system
+    /// defaults) from a specified client. The parameters are merged with those of any other
+    /// background attempts to the same peer.
+    pub fn add_background_connection_with_parameters(
+        &self,
+        client: ConnectionManagerClient,
+        address: AddressWithType,
+        parameters: Option<ConnectionParameters>,
     ) -> Result<(), CreateConnectionFailure> {
         let mut state = self.state.borrow_mut();
-        state.attempts.register_background_connection(client, address)?;
+        state.attempts.register_background_connection(client, address, parameters)?;
+        state.listeners.emit(ConnectionManagerEvent::AttemptRegistered {
+            client,
+            address,
+            mode: ConnectionMode::Background,
+        });
         reconcile_state(&mut state);
         Ok(())
     }
@@ -223,6 +383,7 @@ impl ConnectionManager {
     ) -> Result<(), CancelConnectFailure> {
         let mut state = self.state.borrow_mut();
         state.attempts.cancel_attempt(client, address, mode)?;
+        state.listeners.emit(ConnectionManagerEvent::AttemptCancelled { client, address, mode });
         reconcile_state(&mut state);
         Ok(())
     }
@@ -230,41 +391,151 @@ impl ConnectionManager {
     /// Cancel all connection attempts to this address
     pub fn cancel_unconditionally(&self, address: AddressWithType) {
         let mut state = self.state.borrow_mut();
-        state.attempts.remove_unconditionally(address);
+        let cancelled = state.attempts.remove_unconditionally(address);
+        state.on_attempts_cancelled(cancelled);
         reconcile_state(&mut state);
     }
 
     /// Cancel all connection attempts from this client
     pub fn remove_client(&self, client: ConnectionManagerClient) {
         let mut state = self.state.borrow_mut();
-        state.attempts.remove_client(client);
+        let cancelled = state.attempts.remove_client(client);
+        state.on_attempts_cancelled(cancelled);
+        state.client_priorities.remove(&client);
+        reconcile_state(&mut state);
+    }
+
+    /// Register the IRK of a bonded peer, so connections from its RPAs are matched
+    /// with attempts made to its identity address
+    pub fn add_bonded_identity(&self, identity: AddressWithType, irk: Irk) {
+        self.state.borrow_mut().address_resolver.add_identity(identity, irk);
+    }
+
+    /// Forget the IRK of a peer (e.g. after it is unbonded)
+    pub fn remove_bonded_identity(&self, identity: AddressWithType) {
+        self.state.borrow_mut().address_resolver.remove_identity(identity);
+    }
+
+    /// Set the number of addresses the controller's filter accept list can hold. If
+    /// there are more background attempts than free slots, they are rotated through
+    /// the accept list.
+    pub fn set_acceptlist_capacity(&self, capacity: Option<usize>) {
+        let mut state = self.state.borrow_mut();
+        state.background_scheduler.set_capacity(capacity);
+        reconcile_state(&mut state);
+    }
+
+    /// Set the priority of this client's background attempts. When the accept list is
+    /// over capacity, attempts from higher priority clients are scheduled first.
+    pub fn set_client_priority(&self, client: ConnectionManagerClient, priority: ClientPriority) {
+        let mut state = self.state.borrow_mut();
+        state.client_priorities.insert(client, priority);
         reconcile_state(&mut state);
     }
 
+    /// Subscribe to connection manager events. Events are delivered until the
+    /// receiver is dropped.
+    pub fn subscribe(&self) -> UnboundedReceiver<ConnectionManagerEvent> {
+        self.state.borrow_mut().listeners.subscribe()
+    }
+
+    /// Get a snapshot of the pending attempts, the accept list, and the current
+    /// connections
+    pub fn snapshot(&self) -> ConnectionManagerSnapshot {
+        let state = self.state.borrow();
+
+        let mut attempts = state
+            .attempts
+            .attempt_ages()
+            .into_iter()
+            .map(|(attempt, age)| AttemptSnapshot {
+                client: attempt.client,
+                address: attempt.remote_address,
+                mode: attempt.mode,
+                age,
+            })
+            .collect::<Vec<_>>();
+        attempts.sort_by_key(|attempt| std::cmp::Reverse(attempt.age));
+
+        let mut acceptlist =
+            state.acceptlist_manager.current_lists().into_iter().collect::<Vec<_>>();
+        acceptlist
+            .sort_by_key(|(address, mode)| (address.address, *mode == ConnectionMode::Direct));
+
+        let mut connections = state
+            .current_connections
+            .iter()
+            .map(|(identity, reported)| (*identity, *reported))
+            .collect::<Vec<_>>();
+        connections.sort_by_key(|(identity, _)| identity.address);
+
+        ConnectionManagerSnapshot { attempts, acceptlist, connections }
+    }
+
+    /// Dump the state of the connection manager, for dumpsys
+    pub fn dump(&self) -> String {
+        self.snapshot().to_string()
+    }
+
+    fn rotate_background_connections(&self) {
+        let mut state = self.state.borrow_mut();
+        // this is invoked by the rotation task, which is now done
+        state.rotation_task = None;
+        if state.background_scheduler.rotate() {
+            reconcile_state(&mut state);
+        }
+    }
+
     fn on_le_connect(&self, address: AddressWithType, result: Result<LeConnection, ErrorCode>) {
         let mut state = self.state.borrow_mut();
+        // attempts are registered against identity addresses, so resolve any RPA
+        let identity = state.address_resolver.resolve(address);
+        if identity != address {
+            info!("Resolved {address:?} to identity address {identity:?}");
+        }
         // record this connection while it exists
-        state.current_connections.insert(address);
-        // all completed connections remove the address from the direct list
+        if result.is_ok() {
+            state.current_connections.insert(identity, address);
+        }
+        // all completed connections remove the (exact) address from the direct list
         state.acceptlist_manager.on_connect_complete(address);
         // invoke any pending callbacks, update set of attempts
-        state.attempts.process_connection(address, result);
+        let mut clients = vec![];
+        for attempt in state.attempts.process_connection(identity, result) {
+            if !clients.contains(&attempt.client) {
+                clients.push(attempt.client);
+            }
+        }
+        state.listeners.emit(match result {
+            Ok(_) => ConnectionManagerEvent::Connected {
+                address: identity,
+                reported_address: address,
+                clients,
+            },
+            Err(error) => {
+                ConnectionManagerEvent::ConnectionFailed { address: identity, error, clients }
+            }
+        });
         // update the acceptlist
         reconcile_state(&mut state);
     }
 
     fn on_disconnect(&self, address: AddressWithType) {
         let mut state = self.state.borrow_mut();
-        state.current_connections.remove(&address);
+        state.current_connections.retain(|_, connected_address| *connected_address != address);
+        state.listeners.emit(ConnectionManagerEvent::Disconnected { address });
         reconcile_state(&mut state);
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{core::address::AddressType, utils::task::block_on_locally};
+    use crate::{
+        core::address::AddressType,
+        utils::task::{block_on_locally, try_await},
+    };
 
-    use super::{mocks::mock_le_manager::MockLeAclManager, *};
+    use super::{le_manager::InitiatingPhys, mocks::mock_le_manager::MockLeAclManager, *};
 
     const CLIENT_1: ConnectionManagerClient = ConnectionManagerClient::GattClient(1);
     const CLIENT_2: ConnectionManagerClient = ConnectionManagerClient::GattClient(2);
@@ -274,6 +545,28 @@ mod test {
 
     const ERROR: ErrorCode = ErrorCode(1);
 
+    const SLOW: ConnectionParameters = ConnectionParameters {
+        scan_interval: 0x0800,
+        scan_window: 0x0030,
+        conn_interval_min: 0x0018,
+        conn_interval_max: 0x0028,
+        conn_latency: 0x0000,
+        supervision_timeout: 0x01f4,
+        initiating_phys: InitiatingPhys::LE_1M,
+    };
+    const FAST: ConnectionParameters =
+        ConnectionParameters { scan_interval: 0x0010, scan_window: 0x0010, ..SLOW };
+
+    /// The IRK and RPA from the sample data in 5.3 Vol 3H D.7
+    const IRK: Irk = [
+        0x9b, 0x7d, 0x39, 0x0a, 0xa6, 0x10, 0x10, 0x34, 0x05, 0xad, 0xc8, 0x57, 0xa3, 0x34, 0x02,
+        0xec,
+    ];
+    const RPA: AddressWithType = AddressWithType {
+        address: [0xaa, 0xfb, 0x0d, 0x94, 0x81, 0x70],
+        address_type: AddressType::Random,
+    };
+
     #[test]
     fn test_single_direct_connection() {
         block_on_locally(async {
@@ -399,7 +692,7 @@ mod test {
             connection_manager.as_ref().start_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
 
             // act: let it timeout
-            tokio::time::sleep(DIRECT_CONNECTION_TIMEOUT).await;
+            tokio::time::sleep(DEFAULT_DIRECT_CONNECTION_TIMEOUT).await;
             // go forward one tick to ensure all timers are fired
             // (since we are using fake time, this is not a race condition)
             tokio::time::sleep(Duration::from_millis(1)).await;
@@ -418,14 +711,548 @@ mod test {
 
             // act: start a direct connection
             connection_manager.as_ref().start_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
-            tokio::time::sleep(DIRECT_CONNECTION_TIMEOUT * 3 / 4).await;
+            tokio::time::sleep(DEFAULT_DIRECT_CONNECTION_TIMEOUT * 3 / 4).await;
             // act: after some time, start a second one
             connection_manager.as_ref().start_direct_connection(CLIENT_2, ADDRESS_1).unwrap();
             // act: wait for the first one (but not the second) to time out
-            tokio::time::sleep(DIRECT_CONNECTION_TIMEOUT * 3 / 4).await;
+            tokio::time::sleep(DEFAULT_DIRECT_CONNECTION_TIMEOUT * 3 / 4).await;
 
             // assert: we are still doing a direct connection
             assert_eq!(mock_le_manager.current_connection_mode(), Some(ConnectionMode::Direct));
         });
     }
+
+    #[test]
+    fn test_direct_connection_resolved_from_rpa() {
+        block_on_locally(async {
+            // arrange: a pending direct connection to the identity address of a bonded peer
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager.add_bonded_identity(ADDRESS_1, IRK);
+            let pending =
+                connection_manager.as_ref().direct_connection(CLIENT_1, ADDRESS_1, None).unwrap();
+
+            // act: the peer connects using an RPA
+            mock_le_manager.on_le_connect(RPA, ErrorCode::SUCCESS);
+
+            // assert: the attempt resolves with the connection, and we stop connecting
+            assert_eq!(pending.await, Ok(LeConnection { remote_address: RPA }));
+            assert_eq!(mock_le_manager.current_connection_mode(), None);
+            assert!(mock_le_manager.current_acceptlist().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_background_connection_resolved_from_rpa() {
+        block_on_locally(async {
+            // arrange: a background connection to the identity address of a bonded peer
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager.add_bonded_identity(ADDRESS_1, IRK);
+            connection_manager.as_ref().add_background_connection(CLIENT_1, ADDRESS_1).unwrap();
+
+            // act: the peer connects using an RPA
+            mock_le_manager.on_le_connect(RPA, ErrorCode::SUCCESS);
+
+            // assert: we do not keep trying to connect to its identity address
+            assert_eq!(mock_le_manager.current_connection_mode(), None);
+            assert!(mock_le_manager.current_acceptlist().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_background_connection_resumes_after_rpa_disconnect() {
+        block_on_locally(async {
+            // arrange: a background connection that completed using an RPA
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager.add_bonded_identity(ADDRESS_1, IRK);
+            connection_manager.as_ref().add_background_connection(CLIENT_1, ADDRESS_1).unwrap();
+            mock_le_manager.on_le_connect(RPA, ErrorCode::SUCCESS);
+
+            // act: the peer disconnects
+            mock_le_manager.on_le_disconnect(RPA);
+
+            // assert: the background connection to the identity address has resumed
+            assert_eq!(mock_le_manager.current_connection_mode(), Some(ConnectionMode::Background));
+            assert!(mock_le_manager.current_acceptlist().contains(&ADDRESS_1));
+        });
+    }
+
+    #[test]
+    fn test_no_duplicate_connection_to_identity_connected_via_rpa() {
+        block_on_locally(async {
+            // arrange: a bonded peer connected to us using an RPA
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager.add_bonded_identity(ADDRESS_1, IRK);
+            connection_manager.as_ref().add_background_connection(CLIENT_1, ADDRESS_1).unwrap();
+            mock_le_manager.on_le_connect(RPA, ErrorCode::SUCCESS);
+
+            // act: start a direct connection to its identity address
+            let pending =
+                connection_manager.as_ref().direct_connection(CLIENT_2, ADDRESS_1, None).unwrap();
+
+            // assert: it resolves immediately with the existing connection
+            let Ok(result) = try_await(pending).await else { panic!("should resolve immediately") };
+            assert_eq!(result, Ok(LeConnection { remote_address: RPA }));
+            assert_eq!(mock_le_manager.current_connection_mode(), None);
+        });
+    }
+
+    #[test]
+    fn test_rpa_of_unbonded_peer_not_resolved() {
+        block_on_locally(async {
+            // arrange: a pending direct connection, but no IRK for the peer
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager.as_ref().start_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
+
+            // act: a connection arrives from an RPA
+            mock_le_manager.on_le_connect(RPA, ErrorCode::SUCCESS);
+
+            // assert: the direct connection is still pending
+            assert_eq!(mock_le_manager.current_connection_mode(), Some(ConnectionMode::Direct));
+            assert!(mock_le_manager.current_acceptlist().contains(&ADDRESS_1));
+        });
+    }
+
+    #[test]
+    fn test_default_parameters_not_sent() {
+        block_on_locally(async {
+            // arrange
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+
+            // act: start a direct connection without parameters
+            connection_manager.as_ref().start_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
+
+            // assert: le_impl picks its own parameters
+            assert_eq!(mock_le_manager.current_connection_mode(), Some(ConnectionMode::Direct));
+            assert_eq!(mock_le_manager.current_parameters(ADDRESS_1), None);
+        });
+    }
+
+    #[test]
+    fn test_overlapping_direct_connections_merge_parameters() {
+        block_on_locally(async {
+            // arrange
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+
+            // act: start two direct connections with different parameters
+            for (client, parameters) in [(CLIENT_1, SLOW), (CLIENT_2, FAST)] {
+                connection_manager
+                    .as_ref()
+                    .start_direct_connection_with_parameters(
+                        client,
+                        ADDRESS_1,
+                        Some(parameters),
+                        DEFAULT_DIRECT_CONNECTION_TIMEOUT,
+                    )
+                    .unwrap();
+            }
+
+            // assert: the most aggressive parameters are used
+            assert_eq!(mock_le_manager.current_parameters(ADDRESS_1), Some(SLOW.merge(FAST)));
+        });
+    }
+
+    #[test]
+    fn test_parameters_relax_when_aggressive_attempt_cancelled() {
+        block_on_locally(async {
+            // arrange: a relaxed and an aggressive direct connection
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            for (client, parameters) in [(CLIENT_1, SLOW), (CLIENT_2, FAST)] {
+                connection_manager
+                    .as_ref()
+                    .start_direct_connection_with_parameters(
+                        client,
+                        ADDRESS_1,
+                        Some(parameters),
+                        DEFAULT_DIRECT_CONNECTION_TIMEOUT,
+                    )
+                    .unwrap();
+            }
+
+            // act: cancel the aggressive one
+            connection_manager
+                .cancel_connection(CLIENT_2, ADDRESS_1, ConnectionMode::Direct)
+                .unwrap();
+
+            // assert: we fall back to the remaining attempt's parameters
+            assert_eq!(mock_le_manager.current_parameters(ADDRESS_1), Some(SLOW));
+        });
+    }
+
+    #[test]
+    fn test_background_connection_with_parameters() {
+        block_on_locally(async {
+            // arrange
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            let parameters =
+                ConnectionParameters { initiating_phys: InitiatingPhys::LE_CODED, ..SLOW };
+
+            // act
+            connection_manager
+                .add_background_connection_with_parameters(CLIENT_1, ADDRESS_1, Some(parameters))
+                .unwrap();
+
+            // assert
+            assert_eq!(mock_le_manager.current_connection_mode(), Some(ConnectionMode::Background));
+            assert_eq!(mock_le_manager.current_parameters(ADDRESS_1), Some(parameters));
+        });
+    }
+
+    #[test]
+    fn test_direct_connection_custom_timeout() {
+        block_on_locally(async {
+            // arrange: a direct connection with a short timeout
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager
+                .as_ref()
+                .start_direct_connection_with_parameters(
+                    CLIENT_1,
+                    ADDRESS_1,
+                    None,
+                    Duration::from_secs(5),
+                )
+                .unwrap();
+
+            // act: let it time out
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            tokio::time::sleep(Duration::from_millis(1)).await;
+
+            // assert: it is cancelled and we are idle again
+            assert_eq!(mock_le_manager.current_connection_mode(), None);
+        });
+    }
+
+    fn address(n: u8) -> AddressWithType {
+        AddressWithType { address: [n, 0, 0, 0, 0, 0], address_type: AddressType::Public }
+    }
+
+    #[test]
+    fn test_acceptlist_capacity_limits_background_connections() {
+        block_on_locally(async {
+            // arrange
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager.set_acceptlist_capacity(Some(2));
+
+            // act: add more background connections than fit
+            for n in 1..=3 {
+                connection_manager.add_background_connection(CLIENT_1, address(n)).unwrap();
+            }
+
+            // assert
+            assert_eq!(mock_le_manager.current_acceptlist().len(), 2);
+        });
+    }
+
+    #[test]
+    fn test_direct_connection_preempts_background_connection() {
+        block_on_locally(async {
+            // arrange: a full accept list
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager.set_acceptlist_capacity(Some(1));
+            connection_manager.add_background_connection(CLIENT_1, address(1)).unwrap();
+
+            // act: make a direct connection
+            connection_manager.as_ref().start_direct_connection(CLIENT_2, address(2)).unwrap();
+
+            // assert: the direct connection took the only slot
+            assert_eq!(mock_le_manager.current_connection_mode(), Some(ConnectionMode::Direct));
+            assert_eq!(mock_le_manager.current_acceptlist(), [address(2)].into());
+        });
+    }
+
+    #[test]
+    fn test_high_priority_client_scheduled_first() {
+        block_on_locally(async {
+            // arrange
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager.set_acceptlist_capacity(Some(1));
+            connection_manager.set_client_priority(CLIENT_2, 10);
+
+            // act
+            connection_manager.add_background_connection(CLIENT_1, address(1)).unwrap();
+            connection_manager.add_background_connection(CLIENT_2, address(2)).unwrap();
+
+            // assert
+            assert_eq!(mock_le_manager.current_acceptlist(), [address(2)].into());
+        });
+    }
+
+    #[test]
+    fn test_background_connections_rotate_without_starvation() {
+        block_on_locally(async {
+            // arrange: one slot, contended by a high priority client and two others
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager.set_acceptlist_capacity(Some(1));
+            connection_manager.set_client_priority(CLIENT_2, 3);
+            connection_manager.add_background_connection(CLIENT_1, address(1)).unwrap();
+            connection_manager.add_background_connection(CLIENT_1, address(2)).unwrap();
+            connection_manager.add_background_connection(CLIENT_2, address(3)).unwrap();
+            let mut ever_scheduled = mock_le_manager.current_acceptlist();
+
+            // act: wait for a number of rotations
+            for _ in 0..10 {
+                tokio::time::sleep(ROTATION_INTERVAL).await;
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                assert_eq!(mock_le_manager.current_acceptlist().len(), 1);
+                ever_scheduled.extend(mock_le_manager.current_acceptlist());
+            }
+
+            // assert: every address was on the accept list at some point
+            assert_eq!(ever_scheduled, [address(1), address(2), address(3)].into());
+        });
+    }
+
+    #[test]
+    fn test_removing_capacity_limit_schedules_all() {
+        block_on_locally(async {
+            // arrange
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager.set_acceptlist_capacity(Some(1));
+            connection_manager.add_background_connection(CLIENT_1, address(1)).unwrap();
+            connection_manager.add_background_connection(CLIENT_1, address(2)).unwrap();
+
+            // act
+            connection_manager.set_acceptlist_capacity(None);
+
+            // assert
+            assert_eq!(mock_le_manager.current_acceptlist(), [address(1), address(2)].into());
+        });
+    }
+
+    #[test]
+    fn test_rotation_only_scheduled_while_over_capacity() {
+        block_on_locally(async {
+            // arrange
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager.set_acceptlist_capacity(Some(1));
+            connection_manager.add_background_connection(CLIENT_1, address(1)).unwrap();
+            assert!(connection_manager.state.borrow().rotation_task.is_none());
+
+            // act: go over capacity, then back under it
+            connection_manager.add_background_connection(CLIENT_1, address(2)).unwrap();
+            let over_capacity = connection_manager.state.borrow().rotation_task.is_some();
+            connection_manager
+                .cancel_connection(CLIENT_1, address(2), ConnectionMode::Background)
+                .unwrap();
+
+            // assert
+            assert!(over_capacity);
+            assert!(connection_manager.state.borrow().rotation_task.is_none());
+        });
+    }
+
+    fn drain_events(
+        rx: &mut UnboundedReceiver<ConnectionManagerEvent>,
+    ) -> Vec<ConnectionManagerEvent> {
+        let mut events = vec![];
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        events
+    }
+
+    #[test]
+    fn test_events_for_successful_direct_connection() {
+        block_on_locally(async {
+            // arrange
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            let mut events = connection_manager.subscribe();
+
+            // act: initiate a direct connection, that succeeds, then disconnects
+            connection_manager.as_ref().start_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
+            mock_le_manager.on_le_connect(ADDRESS_1, ErrorCode::SUCCESS);
+            mock_le_manager.on_le_disconnect(ADDRESS_1);
+
+            // assert
+            assert_eq!(
+                drain_events(&mut events),
+                vec![
+                    ConnectionManagerEvent::AttemptRegistered {
+                        client: CLIENT_1,
+                        address: ADDRESS_1,
+                        mode: ConnectionMode::Direct
+                    },
+                    ConnectionManagerEvent::AcceptlistAdded {
+                        address: ADDRESS_1,
+                        mode: ConnectionMode::Direct
+                    },
+                    ConnectionManagerEvent::Connected {
+                        address: ADDRESS_1,
+                        reported_address: ADDRESS_1,
+                        clients: vec![CLIENT_1]
+                    },
+                    ConnectionManagerEvent::Disconnected { address: ADDRESS_1 },
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_event_history_records_events() {
+        block_on_locally(async {
+            // arrange
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            let history = EventHistory::record(connection_manager.subscribe());
+
+            // act
+            connection_manager.add_background_connection(CLIENT_1, ADDRESS_1).unwrap();
+            tokio::time::sleep(Duration::from_millis(1)).await;
+
+            // assert
+            let dump = history.to_string();
+            assert!(dump.contains("AttemptRegistered"));
+            assert!(dump.contains("AcceptlistAdded"));
+        });
+    }
+
+    #[test]
+    fn test_events_for_failed_connection() {
+        block_on_locally(async {
+            // arrange: a direct and background attempt from different clients
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager.as_ref().start_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
+            connection_manager.add_background_connection(CLIENT_2, ADDRESS_1).unwrap();
+            let mut events = connection_manager.subscribe();
+
+            // act: the connection fails
+            mock_le_manager.on_le_connect(ADDRESS_1, ERROR);
+
+            // assert: both clients are attributed
+            let ConnectionManagerEvent::ConnectionFailed { address, error, clients } =
+                drain_events(&mut events).remove(0)
+            else {
+                panic!("expected a connection failure");
+            };
+            assert_eq!(address, ADDRESS_1);
+            assert_eq!(error, ERROR);
+            assert_eq!(clients.into_iter().collect::<HashSet<_>>(), [CLIENT_1, CLIENT_2].into());
+        });
+    }
+
+    #[test]
+    fn test_events_for_cancelled_attempts() {
+        block_on_locally(async {
+            // arrange
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager.add_background_connection(CLIENT_1, ADDRESS_1).unwrap();
+            let mut events = connection_manager.subscribe();
+
+            // act: the client goes away
+            connection_manager.remove_client(CLIENT_1);
+
+            // assert
+            assert_eq!(
+                drain_events(&mut events),
+                vec![
+                    ConnectionManagerEvent::AttemptCancelled {
+                        client: CLIENT_1,
+                        address: ADDRESS_1,
+                        mode: ConnectionMode::Background
+                    },
+                    ConnectionManagerEvent::AcceptlistRemoved {
+                        address: ADDRESS_1,
+                        mode: ConnectionMode::Background
+                    },
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_event_for_timed_out_attempt() {
+        block_on_locally(async {
+            // arrange
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager.as_ref().start_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
+            let mut events = connection_manager.subscribe();
+
+            // act: let the attempt time out
+            tokio::time::sleep(DEFAULT_DIRECT_CONNECTION_TIMEOUT).await;
+            tokio::time::sleep(Duration::from_millis(1)).await;
+
+            // assert
+            assert!(drain_events(&mut events).contains(
+                &ConnectionManagerEvent::AttemptCancelled {
+                    client: CLIENT_1,
+                    address: ADDRESS_1,
+                    mode: ConnectionMode::Direct
+                }
+            ));
+        });
+    }
+
+    #[test]
+    fn test_snapshot() {
+        block_on_locally(async {
+            // arrange: a background attempt, a direct attempt a while later, and a connection
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            connection_manager.add_background_connection(CLIENT_1, address(1)).unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            connection_manager.as_ref().start_direct_connection(CLIENT_2, address(2)).unwrap();
+            mock_le_manager.on_le_connect(address(3), ErrorCode::SUCCESS);
+
+            // act
+            let snapshot = connection_manager.snapshot();
+
+            // assert
+            assert_eq!(
+                snapshot
+                    .attempts
+                    .iter()
+                    .map(|attempt| (attempt.client, attempt.address, attempt.mode, attempt.age))
+                    .collect::<Vec<_>>(),
+                vec![
+                    (CLIENT_1, address(1), ConnectionMode::Background, Duration::from_secs(10)),
+                    (CLIENT_2, address(2), ConnectionMode::Direct, Duration::ZERO),
+                ]
+            );
+            assert_eq!(
+                snapshot.acceptlist,
+                vec![
+                    (address(1), ConnectionMode::Background),
+                    (address(2), ConnectionMode::Direct)
+                ]
+            );
+            assert_eq!(snapshot.connections, vec![(address(3), address(3))]);
+            assert!(connection_manager.dump().contains("connections:"));
+        });
+    }
+
+    #[test]
+    fn test_dropped_subscriber_is_pruned() {
+        block_on_locally(async {
+            // arrange
+            let mock_le_manager = MockLeAclManager::new();
+            let connection_manager = ConnectionManager::new(mock_le_manager.clone());
+            drop(connection_manager.subscribe());
+            let mut events = connection_manager.subscribe();
+
+            // act
+            connection_manager.add_background_connection(CLIENT_1, ADDRESS_1).unwrap();
+
+            // assert: the remaining subscriber still gets events
+            assert!(!drain_events(&mut events).is_empty());
+        });
+    }
 }
@@ -1,7 +1,7 @@
 //! This module takes the set of attempts from the AttemptManager, determines
 //! the target state of the LE manager, and drives it to this target state
 
-use std::collections::HashSet;
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 
 use log::info;
 
@@ -9,31 +9,43 @@ use crate::core::address::AddressWithType;
 
 use super::{
     attempt_manager::{ConnectionAttempt, ConnectionMode},
-    le_manager::LeAclManager,
+    le_manager::{ConnectionParameters, LeAclManager},
 };
 
 /// This struct represents the target state of the LeManager based on the
 /// set of all active connection attempts
 pub struct TargetState {
-    /// These addresses should go to the LE background connect list
-    pub background_list: HashSet<AddressWithType>,
-    /// These addresses should go to the direct list (we are not connected to any of them)
-    pub direct_list: HashSet<AddressWithType>,
+    /// These addresses should go to the LE background connect list, with the
+    /// given (merged) parameters, if any were requested
+    pub background_list: HashMap<AddressWithType, Option<ConnectionParameters>>,
+    /// These addresses should go to the direct list (we are not connected to any of them),
+    /// with the given (merged) parameters, if any were requested
+    pub direct_list: HashMap<AddressWithType, Option<ConnectionParameters>>,
 }
 
-/// Takes a list of connection attempts, and determines the target state of the LE ACL manager
-pub fn determine_target_state(attempts: &[ConnectionAttempt]) -> TargetState {
-    let background_list = attempts
-        .iter()
-        .filter(|attempt| attempt.mode == ConnectionMode::Background)
-        .map(|attempt| attempt.remote_address)
-        .collect();
-
-    let direct_list = attempts
-        .iter()
-        .filter(|attempt| attempt.mode == ConnectionMode::Direct)
-        .map(|attempt| attempt.remote_address)
-        .collect();
+/// Takes a list of connection attempts, and determines the target state of the LE ACL manager.
+/// If several attempts of the same mode target the same address, their parameters are merged.
+pub fn determine_target_state(
+    attempts: &[(ConnectionAttempt, Option<ConnectionParameters>)],
+) -> TargetState {
+    let mut background_list = HashMap::new();
+    let mut direct_list = HashMap::new();
+
+    for (attempt, parameters) in attempts {
+        let list = match attempt.mode {
+            ConnectionMode::Background => &mut background_list,
+            ConnectionMode::Direct => &mut direct_list,
+        };
+        match list.entry(attempt.remote_address) {
+            Entry::Vacant(entry) => {
+                entry.insert(*parameters);
+            }
+            Entry::Occupied(mut entry) => {
+                let merged = ConnectionParameters::merge_requested(*entry.get(), *parameters);
+                entry.insert(merged);
+            }
+        }
+    }
 
     TargetState { background_list, direct_list }
 }
@@ -42,10 +54,11 @@ pub fn determine_target_state(attempts: &[ConnectionAttempt]) -> TargetState {
 /// and drives it to the target state.
 #[derive(Debug)]
 pub struct LeAcceptlistManager {
-    /// The connect list in the ACL manager
-    direct_list: HashSet<AddressWithType>,
-    /// The background connect list in the ACL manager
-    background_list: HashSet<AddressWithType>,
+    /// The connect list in the ACL manager, with the parameters each address was added with
+    direct_list: HashMap<AddressWithType, Option<ConnectionParameters>>,
+    /// The background connect list in the ACL manager, with the parameters each address was
+    /// added with
+    background_list: HashMap<AddressWithType, Option<ConnectionParameters>>,
     /// An interface into the LE ACL manager (le_impl.h)
     le_manager: Box<dyn LeAclManager>,
 }
@@ -54,8 +67,8 @@ impl LeAcceptlistManager {
     /// Constructor
     pub fn new(le_manager: impl LeAclManager + 'static) -> Self {
         Self {
-            direct_list: HashSet::new(),
-            background_list: HashSet::new(),
+            direct_list: HashMap::new(),
+            background_list: HashMap::new(),
             le_manager: Box::new(le_manager),
         }
     }
@@ -69,33 +82,51 @@ impl LeAcceptlistManager {
         self.direct_list.remove(&address);
     }
 
+    /// The addresses currently in the direct and background lists of the ACL manager
+    pub fn current_lists(&self) -> HashSet<(AddressWithType, ConnectionMode)> {
+        let direct = self.direct_list.keys().map(|address| (*address, ConnectionMode::Direct));
+        let background =
+            self.background_list.keys().map(|address| (*address, ConnectionMode::Background));
+        direct.chain(background).collect()
+    }
+
     /// Drive the state of the connect list to the target state
     pub fn drive_to_state(&mut self, target: TargetState) {
-        // First, pull out anything in the ACL manager that we don't need
+        // First, pull out anything in the ACL manager that we don't need, or that
+        // needs different parameters (since these are fixed once added)
         // recall that cancel_connect() removes addresses from *both* lists (!)
-        for address in self.direct_list.difference(&target.direct_list) {
-            info!("Cancelling connection attempt to {address:?}");
-            self.le_manager.remove_from_all_lists(*address);
-            self.background_list.remove(address);
+        for (address, parameters) in &self.direct_list {
+            if target.direct_list.get(address) != Some(parameters) {
+                info!("Cancelling connection attempt to {address:?}");
+                self.le_manager.remove_from_all_lists(*address);
+                self.background_list.remove(address);
+            }
         }
-        self.direct_list = self.direct_list.intersection(&target.direct_list).copied().collect();
-
-        for address in self.background_list.difference(&target.background_list) {
-            info!("Cancelling connection attempt to {address:?}");
-            self.le_manager.remove_from_all_lists(*address);
-            self.direct_list.remove(address);
+        self.direct_list
+            .retain(|address, parameters| target.direct_list.get(address) == Some(parameters));
+
+        for (address, parameters) in &self.background_list {
+            if target.background_list.get(address) != Some(parameters) {
+                info!("Cancelling connection attempt to {address:?}");
+                self.le_manager.remove_from_all_lists(*address);
+                self.direct_list.remove(address);
+            }
         }
-        self.background_list =
-            self.background_list.intersection(&target.background_list).copied().collect();
+        self.background_list
+            .retain(|address, parameters| target.background_list.get(address) == Some(parameters));
 
         // now everything extra has been removed, we can put things back in
-        for address in target.direct_list.difference(&self.direct_list) {
-            info!("Starting direct connection to {address:?}");
-            self.le_manager.add_to_direct_list(*address);
+        for (address, parameters) in &target.direct_list {
+            if !self.direct_list.contains_key(address) {
+                info!("Starting direct connection to {address:?} with {parameters:?}");
+                self.le_manager.add_to_direct_list(*address, *parameters);
+            }
         }
-        for address in target.background_list.difference(&self.background_list) {
-            info!("Starting background connection to {address:?}");
-            self.le_manager.add_to_background_list(*address);
+        for (address, parameters) in &target.background_list {
+            if !self.background_list.contains_key(address) {
+                info!("Starting background connection to {address:?} with {parameters:?}");
+                self.le_manager.add_to_background_list(*address, *parameters);
+            }
         }
 
         // we should now be in a consistent state!
@@ -108,7 +139,8 @@ impl LeAcceptlistManager {
 mod test {
     use crate::{
         connection::{
-            le_manager::ErrorCode, mocks::mock_le_manager::MockActiveLeAclManager,
+            le_manager::{ErrorCode, InitiatingPhys},
+            mocks::mock_le_manager::MockActiveLeAclManager,
             ConnectionManagerClient,
         },
         core::address::AddressType,
@@ -118,6 +150,19 @@ mod test {
 
     const CLIENT: ConnectionManagerClient = ConnectionManagerClient::GattClient(1);
 
+    const PARAMETERS: Option<ConnectionParameters> = None;
+
+    const SLOW: ConnectionParameters = ConnectionParameters {
+        scan_interval: 0x0800,
+        scan_window: 0x0030,
+        conn_interval_min: 0x0018,
+        conn_interval_max: 0x0028,
+        conn_latency: 0x0000,
+        supervision_timeout: 0x01f4,
+        initiating_phys: InitiatingPhys::LE_1M,
+    };
+    const FAST: ConnectionParameters = ConnectionParameters { scan_interval: 0x0010, ..SLOW };
+
     const ADDRESS_1: AddressWithType =
         AddressWithType { address: [1, 2, 3, 4, 5, 6], address_type: AddressType::Public };
     const ADDRESS_2: AddressWithType =
@@ -128,39 +173,54 @@ mod test {
     #[test]
     fn test_determine_target_state() {
         let target = determine_target_state(&[
-            ConnectionAttempt {
-                client: CLIENT,
-                mode: ConnectionMode::Background,
-                remote_address: ADDRESS_1,
-            },
-            ConnectionAttempt {
-                client: CLIENT,
-                mode: ConnectionMode::Background,
-                remote_address: ADDRESS_1,
-            },
-            ConnectionAttempt {
-                client: CLIENT,
-                mode: ConnectionMode::Background,
-                remote_address: ADDRESS_2,
-            },
-            ConnectionAttempt {
-                client: CLIENT,
-                mode: ConnectionMode::Direct,
-                remote_address: ADDRESS_2,
-            },
-            ConnectionAttempt {
-                client: CLIENT,
-                mode: ConnectionMode::Direct,
-                remote_address: ADDRESS_3,
-            },
+            (
+                ConnectionAttempt {
+                    client: CLIENT,
+                    mode: ConnectionMode::Background,
+                    remote_address: ADDRESS_1,
+                },
+                PARAMETERS,
+            ),
+            (
+                ConnectionAttempt {
+                    client: CLIENT,
+                    mode: ConnectionMode::Background,
+                    remote_address: ADDRESS_1,
+                },
+                PARAMETERS,
+            ),
+            (
+                ConnectionAttempt {
+                    client: CLIENT,
+                    mode: ConnectionMode::Background,
+                    remote_address: ADDRESS_2,
+                },
+                PARAMETERS,
+            ),
+            (
+                ConnectionAttempt {
+                    client: CLIENT,
+                    mode: ConnectionMode::Direct,
+                    remote_address: ADDRESS_2,
+                },
+                PARAMETERS,
+            ),
+            (
+                ConnectionAttempt {
+                    client: CLIENT,
+                    mode: ConnectionMode::Direct,
+                    remote_address: ADDRESS_3,
+                },
+                PARAMETERS,
+            ),
         ]);
 
         assert_eq!(target.background_list.len(), 2);
-        assert!(target.background_list.contains(&ADDRESS_1));
-        assert!(target.background_list.contains(&ADDRESS_2));
+        assert!(target.background_list.contains_key(&ADDRESS_1));
+        assert!(target.background_list.contains_key(&ADDRESS_2));
         assert_eq!(target.direct_list.len(), 2);
-        assert!(target.direct_list.contains(&ADDRESS_2));
-        assert!(target.direct_list.contains(&ADDRESS_3));
+        assert!(target.direct_list.contains_key(&ADDRESS_2));
+        assert!(target.direct_list.contains_key(&ADDRESS_3));
     }
 
     #[test]
@@ -172,7 +232,7 @@ mod test {
         // act: request a device to be present in the direct list
         manager.drive_to_state(TargetState {
             background_list: [].into(),
-            direct_list: [ADDRESS_1].into(),
+            direct_list: [(ADDRESS_1, PARAMETERS)].into(),
         });
 
         // assert: that the device has been added
@@ -189,7 +249,7 @@ mod test {
 
         // act: request a device to be present in the direct list
         manager.drive_to_state(TargetState {
-            background_list: [ADDRESS_1].into(),
+            background_list: [(ADDRESS_1, PARAMETERS)].into(),
             direct_list: [].into(),
         });
 
@@ -205,14 +265,14 @@ mod test {
         let mock_le_manager = MockActiveLeAclManager::new();
         let mut manager = LeAcceptlistManager::new(mock_le_manager.clone());
         manager.drive_to_state(TargetState {
-            background_list: [ADDRESS_1].into(),
+            background_list: [(ADDRESS_1, PARAMETERS)].into(),
             direct_list: [].into(),
         });
 
         // act: initiate a direct connection to the same device
         manager.drive_to_state(TargetState {
-            background_list: [ADDRESS_1].into(),
-            direct_list: [ADDRESS_1].into(),
+            background_list: [(ADDRESS_1, PARAMETERS)].into(),
+            direct_list: [(ADDRESS_1, PARAMETERS)].into(),
         });
 
         // assert: we are now doing a direct connection
@@ -225,17 +285,17 @@ mod test {
         let mock_le_manager = MockActiveLeAclManager::new();
         let mut manager = LeAcceptlistManager::new(mock_le_manager.clone());
         manager.drive_to_state(TargetState {
-            background_list: [ADDRESS_1].into(),
+            background_list: [(ADDRESS_1, PARAMETERS)].into(),
             direct_list: [].into(),
         });
 
         // act: initiate a direct connection to the same device, then remove it
         manager.drive_to_state(TargetState {
-            background_list: [ADDRESS_1].into(),
-            direct_list: [ADDRESS_1].into(),
+            background_list: [(ADDRESS_1, PARAMETERS)].into(),
+            direct_list: [(ADDRESS_1, PARAMETERS)].into(),
         });
         manager.drive_to_state(TargetState {
-            background_list: [ADDRESS_1].into(),
+            background_list: [(ADDRESS_1, PARAMETERS)].into(),
             direct_list: [].into(),
         });
 
@@ -249,22 +309,22 @@ mod test {
         let mock_le_manager = MockActiveLeAclManager::new();
         let mut manager = LeAcceptlistManager::new(mock_le_manager.clone());
         manager.drive_to_state(TargetState {
-            background_list: [ADDRESS_1].into(),
+            background_list: [(ADDRESS_1, PARAMETERS)].into(),
             direct_list: [].into(),
         });
 
         // act: initiate a direct connection to the same device, cancel it, then resume
         manager.drive_to_state(TargetState {
-            background_list: [ADDRESS_1].into(),
-            direct_list: [ADDRESS_1].into(),
+            background_list: [(ADDRESS_1, PARAMETERS)].into(),
+            direct_list: [(ADDRESS_1, PARAMETERS)].into(),
         });
         manager.drive_to_state(TargetState {
-            background_list: [ADDRESS_1].into(),
+            background_list: [(ADDRESS_1, PARAMETERS)].into(),
             direct_list: [].into(),
         });
         manager.drive_to_state(TargetState {
-            background_list: [ADDRESS_1].into(),
-            direct_list: [ADDRESS_1].into(),
+            background_list: [(ADDRESS_1, PARAMETERS)].into(),
+            direct_list: [(ADDRESS_1, PARAMETERS)].into(),
         });
 
         // assert: we have returned to a direct connection
@@ -279,7 +339,7 @@ mod test {
 
         // act: add then remove a background connection
         manager.drive_to_state(TargetState {
-            background_list: [ADDRESS_1].into(),
+            background_list: [(ADDRESS_1, PARAMETERS)].into(),
             direct_list: [].into(),
         });
         manager.drive_to_state(TargetState { background_list: [].into(), direct_list: [].into() });
@@ -296,12 +356,12 @@ mod test {
 
         // act: add, remove, then re-add a background connection
         manager.drive_to_state(TargetState {
-            background_list: [ADDRESS_1].into(),
+            background_list: [(ADDRESS_1, PARAMETERS)].into(),
             direct_list: [].into(),
         });
         manager.drive_to_state(TargetState { background_list: [].into(), direct_list: [].into() });
         manager.drive_to_state(TargetState {
-            background_list: [ADDRESS_1].into(),
+            background_list: [(ADDRESS_1, PARAMETERS)].into(),
             direct_list: [].into(),
         });
 
@@ -317,7 +377,7 @@ mod test {
         // act: initiate a direct connection
         manager.drive_to_state(TargetState {
             background_list: [].into(),
-            direct_list: [ADDRESS_1].into(),
+            direct_list: [(ADDRESS_1, PARAMETERS)].into(),
         });
         // act: the connection succeeds (and later disconnects)
         mock_le_manager.on_le_connect(ADDRESS_1, ErrorCode::SUCCESS);
@@ -327,7 +387,7 @@ mod test {
         // act: retry the direct connection
         manager.drive_to_state(TargetState {
             background_list: [].into(),
-            direct_list: [ADDRESS_1].into(),
+            direct_list: [(ADDRESS_1, PARAMETERS)].into(),
         });
 
         // assert: we have resumed the direct connection
@@ -343,24 +403,110 @@ mod test {
         let mut manager = LeAcceptlistManager::new(mock_le_manager.clone());
         manager.drive_to_state(TargetState {
             background_list: [].into(),
-            direct_list: [ADDRESS_1].into(),
+            direct_list: [(ADDRESS_1, PARAMETERS)].into(),
         });
 
         // act: add, remove, then re-add a background connection
         manager.drive_to_state(TargetState {
-            background_list: [ADDRESS_1].into(),
-            direct_list: [ADDRESS_1].into(),
+            background_list: [(ADDRESS_1, PARAMETERS)].into(),
+            direct_list: [(ADDRESS_1, PARAMETERS)].into(),
         });
         manager.drive_to_state(TargetState {
             background_list: [].into(),
-            direct_list: [ADDRESS_1].into(),
+            direct_list: [(ADDRESS_1, PARAMETERS)].into(),
         });
         manager.drive_to_state(TargetState {
-            background_list: [ADDRESS_1].into(),
-            direct_list: [ADDRESS_1].into(),
+            background_list: [(ADDRESS_1, PARAMETERS)].into(),
+            direct_list: [(ADDRESS_1, PARAMETERS)].into(),
         });
 
         // assert: we remain doing our direct connection
         assert_eq!(mock_le_manager.current_connection_mode(), Some(ConnectionMode::Direct));
     }
+
+    #[test]
+    fn test_determine_target_state_merges_parameters() {
+        // arrange: two direct attempts to the same peer with different parameters
+        let attempt = |client| ConnectionAttempt {
+            client,
+            mode: ConnectionMode::Direct,
+            remote_address: ADDRESS_1,
+        };
+
+        // act
+        let target = determine_target_state(&[
+            (attempt(CLIENT), Some(SLOW)),
+            (attempt(ConnectionManagerClient::GattClient(2)), Some(FAST)),
+        ]);
+
+        // assert: the merged parameters are used
+        assert_eq!(target.direct_list.len(), 1);
+        assert_eq!(target.direct_list[&ADDRESS_1], Some(SLOW.merge(FAST)));
+    }
+
+    #[test]
+    fn test_determine_target_state_keeps_requested_parameters() {
+        // arrange: a background attempt with default parameters, and one with explicit parameters
+        let attempt = |client| ConnectionAttempt {
+            client,
+            mode: ConnectionMode::Background,
+            remote_address: ADDRESS_1,
+        };
+
+        // act
+        let target = determine_target_state(&[
+            (attempt(CLIENT), None),
+            (attempt(ConnectionManagerClient::GattClient(2)), Some(FAST)),
+        ]);
+
+        // assert: the explicit parameters are used
+        assert_eq!(target.background_list[&ADDRESS_1], Some(FAST));
+    }
+
+    #[test]
+    fn test_parameter_change_readds_address() {
+        // arrange: a pending direct connection
+        let mock_le_manager = MockActiveLeAclManager::new();
+        let mut manager = LeAcceptlistManager::new(mock_le_manager.clone());
+        manager.drive_to_state(TargetState {
+            background_list: [].into(),
+            direct_list: [(ADDRESS_1, PARAMETERS)].into(),
+        });
+
+        // act: the target parameters change
+        manager.drive_to_state(TargetState {
+            background_list: [].into(),
+            direct_list: [(ADDRESS_1, Some(FAST))].into(),
+        });
+
+        // assert: the device was re-added with the new parameters
+        assert_eq!(mock_le_manager.current_connection_mode(), Some(ConnectionMode::Direct));
+        assert_eq!(mock_le_manager.current_parameters(ADDRESS_1), Some(FAST));
+    }
+
+    #[test]
+    fn test_parameter_change_of_direct_restores_background() {
+        // arrange: a pending direct and background connection to the same device
+        let mock_le_manager = MockActiveLeAclManager::new();
+        let mut manager = LeAcceptlistManager::new(mock_le_manager.clone());
+        manager.drive_to_state(TargetState {
+            background_list: [(ADDRESS_1, Some(SLOW))].into(),
+            direct_list: [(ADDRESS_1, Some(SLOW))].into(),
+        });
+
+        // act: the direct parameters change (which removes from both lists)
+        manager.drive_to_state(TargetState {
+            background_list: [(ADDRESS_1, Some(SLOW))].into(),
+            direct_list: [(ADDRESS_1, Some(FAST))].into(),
+        });
+        // act: then the direct connection is cancelled
+        manager.drive_to_state(TargetState {
+            background_list: [(ADDRESS_1, Some(SLOW))].into(),
+            direct_list: [].into(),
+        });
+
+        // assert: we are still doing the background connection
+        assert_eq!(mock_le_manager.current_connection_mode(), Some(ConnectionMode::Background));
+        assert_eq!(mock_le_manager.current_parameters(ADDRESS_1), Some(SLOW));
+    }
 }
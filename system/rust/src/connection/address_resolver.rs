@@ -0,0 +1,164 @@
+//! This module maps resolvable private addresses (RPAs) back to the identity
+//! addresses of bonded peers, using their identity resolving keys (IRKs).
+//! See: 5.3 Vol 6B 1.3.2.3 Private device address resolution
+
+use std::collections::HashMap;
+
+use aes::{
+    cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit},
+    Aes128,
+};
+
+use crate::core::address::{AddressType, AddressWithType};
+
+/// An identity resolving key, stored in little-endian format (like addresses)
+pub type Irk = [u8; 16];
+
+/// Tracks the IRKs of bonded peers and resolves RPAs to identity addresses
+#[derive(Debug, Default)]
+pub struct AddressResolver {
+    irks: HashMap<AddressWithType, Irk>,
+}
+
+impl AddressResolver {
+    /// Constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the IRK of a bonded peer with the given identity address. Replaces
+    /// any IRK previously registered for this identity.
+    pub fn add_identity(&mut self, identity: AddressWithType, irk: Irk) {
+        self.irks.insert(identity, irk);
+    }
+
+    /// Forget the IRK of a peer (e.g. on unbonding)
+    pub fn remove_identity(&mut self, identity: AddressWithType) {
+        self.irks.remove(&identity);
+    }
+
+    /// Map an address to the identity address of a bonded peer, if it is an RPA
+    /// that resolves with one of the known IRKs. Otherwise, returns the address
+    /// unchanged.
+    pub fn resolve(&self, address: AddressWithType) -> AddressWithType {
+        if !is_resolvable_private_address(address) {
+            return address;
+        }
+        self.irks
+            .iter()
+            .find(|(_, irk)| rpa_matches_irk(address, irk))
+            .map(|(identity, _)| *identity)
+            .unwrap_or(address)
+    }
+}
+
+/// Whether this address is an RPA (random, with the two most significant bits
+/// set to 0b01)
+fn is_resolvable_private_address(address: AddressWithType) -> bool {
+    address.address_type == AddressType::Random && address.address[5] >> 6 == 0b01
+}
+
+/// Whether the hash of this RPA was generated from its prand using this IRK
+fn rpa_matches_irk(address: AddressWithType, irk: &Irk) -> bool {
+    // the address is little-endian, with the hash in the lower 24 bits
+    let [hash0, hash1, hash2, prand0, prand1, prand2] = address.address;
+    ah(irk, [prand2, prand1, prand0]) == [hash2, hash1, hash0]
+}
+
+/// The random address hash function ah, with the prand and hash in big-endian
+/// format. See: 5.3 Vol 3H 2.2.2
+fn ah(irk: &Irk, prand: [u8; 3]) -> [u8; 3] {
+    // e() takes the most significant octet first, so reverse our little-endian key
+    let mut key = *irk;
+    key.reverse();
+    let cipher = Aes128::new(&GenericArray::from(key));
+
+    // r' = padding || prand
+    let mut block = [0u8; 16];
+    block[13..].copy_from_slice(&prand);
+    let mut block = GenericArray::from(block);
+    cipher.encrypt_block(&mut block);
+
+    // ah = e(k, r') mod 2^24
+    [block[13], block[14], block[15]]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The IRK from the sample data in 5.3 Vol 3H D.7
+    const IRK: Irk = [
+        0x9b, 0x7d, 0x39, 0x0a, 0xa6, 0x10, 0x10, 0x34, 0x05, 0xad, 0xc8, 0x57, 0xa3, 0x34, 0x02,
+        0xec,
+    ];
+    /// The RPA from the same sample data (prand = 0x708194, hash = 0x0dfbaa)
+    const RPA: AddressWithType = AddressWithType {
+        address: [0xaa, 0xfb, 0x0d, 0x94, 0x81, 0x70],
+        address_type: AddressType::Random,
+    };
+
+    const IDENTITY: AddressWithType =
+        AddressWithType { address: [1, 2, 3, 4, 5, 6], address_type: AddressType::Public };
+    const OTHER_IDENTITY: AddressWithType =
+        AddressWithType { address: [1, 2, 3, 4, 5, 7], address_type: AddressType::Public };
+
+    #[test]
+    fn test_ah_sample_data() {
+        assert_eq!(ah(&IRK, [0x70, 0x81, 0x94]), [0x0d, 0xfb, 0xaa]);
+    }
+
+    #[test]
+    fn test_resolve_rpa() {
+        // arrange
+        let mut resolver = AddressResolver::new();
+        resolver.add_identity(OTHER_IDENTITY, [0; 16]);
+        resolver.add_identity(IDENTITY, IRK);
+
+        // act
+        let resolved = resolver.resolve(RPA);
+
+        // assert
+        assert_eq!(resolved, IDENTITY);
+    }
+
+    #[test]
+    fn test_unresolvable_rpa_is_unchanged() {
+        // arrange
+        let mut resolver = AddressResolver::new();
+        resolver.add_identity(IDENTITY, [0; 16]);
+
+        // act
+        let resolved = resolver.resolve(RPA);
+
+        // assert
+        assert_eq!(resolved, RPA);
+    }
+
+    #[test]
+    fn test_non_rpa_is_unchanged() {
+        // arrange: a public address with the same bytes as a valid RPA
+        let mut resolver = AddressResolver::new();
+        resolver.add_identity(IDENTITY, IRK);
+        let address = AddressWithType { address_type: AddressType::Public, ..RPA };
+
+        // act
+        let resolved = resolver.resolve(address);
+
+        // assert
+        assert_eq!(resolved, address);
+    }
+
+    #[test]
+    fn test_removed_identity_does_not_resolve() {
+        // arrange
+        let mut resolver = AddressResolver::new();
+        resolver.add_identity(IDENTITY, IRK);
+
+        // act
+        resolver.remove_identity(IDENTITY);
+
+        // assert
+        assert_eq!(resolver.resolve(RPA), RPA);
+    }
+}
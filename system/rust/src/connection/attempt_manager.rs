@@ -1,26 +1,34 @@
 use std::{
     collections::{hash_map::Entry, HashMap},
     future::{Future, IntoFuture},
+    time::Duration,
 };
 
-use tokio::sync::oneshot;
+use tokio::{sync::oneshot, time::Instant};
 
 use crate::core::address::AddressWithType;
 
 use super::{
-    le_manager::ErrorCode, CancelConnectFailure, ConnectionFailure, ConnectionManagerClient,
-    CreateConnectionFailure, LeConnection,
+    le_manager::{ConnectionParameters, ErrorCode},
+    CancelConnectFailure, ConnectionFailure, ConnectionManagerClient, CreateConnectionFailure,
+    LeConnection,
 };
 
+/// The type of a connection attempt
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum ConnectionMode {
+    /// Connect whenever the peer is seen, with no timeout
     Background,
+    /// Connect as soon as possible, with a timeout
     Direct,
 }
 
 #[derive(Debug)]
 struct ConnectionAttemptData {
     id: AttemptId,
+    /// None if the client did not request any parameters
+    parameters: Option<ConnectionParameters>,
+    registered_at: Instant,
     conn_tx: Option<oneshot::Sender<Result<LeConnection, ErrorCode>>>,
 }
 
@@ -83,6 +91,7 @@ impl ConnectionAttempts {
         &mut self,
         client: ConnectionManagerClient,
         address: AddressWithType,
+        parameters: Option<ConnectionParameters>,
     ) -> Result<
         PendingConnectionAttempt<impl Future<Output = Result<LeConnection, ConnectionFailure>>>,
         CreateConnectionFailure,
@@ -95,7 +104,12 @@ impl ConnectionAttempts {
             return Err(CreateConnectionFailure::ConnectionAlreadyPending)
         };
         let (tx, rx) = oneshot::channel();
-        entry.insert(ConnectionAttemptData { conn_tx: Some(tx), id });
+        entry.insert(ConnectionAttemptData {
+            conn_tx: Some(tx),
+            id,
+            parameters,
+            registered_at: Instant::now(),
+        });
 
         Ok(PendingConnectionAttempt {
             id,
@@ -114,6 +128,7 @@ impl ConnectionAttempts {
         &mut self,
         client: ConnectionManagerClient,
         address: AddressWithType,
+        parameters: Option<ConnectionParameters>,
     ) -> Result<AttemptId, CreateConnectionFailure> {
         let attempt =
             ConnectionAttempt { client, mode: ConnectionMode::Background, remote_address: address };
@@ -122,7 +137,12 @@ impl ConnectionAttempts {
         let Entry::Vacant(entry) = self.attempts.entry(attempt) else {
             return Err(CreateConnectionFailure::ConnectionAlreadyPending)
         };
-        entry.insert(ConnectionAttemptData { conn_tx: None, id });
+        entry.insert(ConnectionAttemptData {
+            conn_tx: None,
+            id,
+            parameters,
+            registered_at: Instant::now(),
+        });
 
         Ok(id)
     }
@@ -145,34 +165,62 @@ impl ConnectionAttempts {
         }
     }
 
-    /// Cancel the connection attempt with the given ID.
-    pub fn cancel_attempt_with_id(&mut self, id: AttemptId) {
-        self.attempts.retain(|_, attempt| attempt.id != id);
+    /// Cancel the connection attempt with the given ID. Returns the attempt, if it
+    /// was still pending.
+    pub fn cancel_attempt_with_id(&mut self, id: AttemptId) -> Option<ConnectionAttempt> {
+        self.remove_matching(|_, data| data.id == id).pop()
     }
 
-    /// Cancel all connection attempts to this address
-    pub fn remove_unconditionally(&mut self, address: AddressWithType) {
-        self.attempts.retain(|attempt, _| attempt.remote_address != address);
+    /// Cancel all connection attempts to this address. Returns the cancelled attempts.
+    pub fn remove_unconditionally(&mut self, address: AddressWithType) -> Vec<ConnectionAttempt> {
+        self.remove_matching(|attempt, _| attempt.remote_address == address)
     }
 
-    /// Cancel all connection attempts from this client
-    pub fn remove_client(&mut self, client: ConnectionManagerClient) {
-        self.attempts.retain(|attempt, _| attempt.client != client);
+    /// Cancel all connection attempts from this client. Returns the cancelled attempts.
+    pub fn remove_client(&mut self, client: ConnectionManagerClient) -> Vec<ConnectionAttempt> {
+        self.remove_matching(|attempt, _| attempt.client == client)
     }
 
-    /// List all active connection attempts. Note that we can have active background (but NOT) direct
-    /// connection attempts to connected devices, as we will resume the connection attempt when the
-    /// peer disconnects from us.
-    pub fn active_attempts(&self) -> Vec<ConnectionAttempt> {
-        self.attempts.keys().cloned().collect()
+    fn remove_matching(
+        &mut self,
+        f: impl Fn(&ConnectionAttempt, &ConnectionAttemptData) -> bool,
+    ) -> Vec<ConnectionAttempt> {
+        let mut removed = vec![];
+        self.attempts.retain(|attempt, data| {
+            if f(attempt, data) {
+                removed.push(*attempt);
+                false
+            } else {
+                true
+            }
+        });
+        removed
+    }
+
+    /// List all active connection attempts, along with the parameters requested for each
+    /// (if any).
+    /// Note that we can have active background (but NOT) direct connection attempts to
+    /// connected devices, as we will resume the connection attempt when the peer
+    /// disconnects from us.
+    pub fn active_attempts(&self) -> Vec<(ConnectionAttempt, Option<ConnectionParameters>)> {
+        self.attempts.iter().map(|(attempt, data)| (*attempt, data.parameters)).collect()
     }
 
-    /// Handle a successful connection by notifying clients and resolving direct connect attempts
+    /// List all active connection attempts, along with how long ago each was registered
+    pub fn attempt_ages(&self) -> Vec<(ConnectionAttempt, Duration)> {
+        self.attempts
+            .iter()
+            .map(|(attempt, data)| (*attempt, data.registered_at.elapsed()))
+            .collect()
+    }
+
+    /// Handle a successful connection by notifying clients and resolving direct connect attempts.
+    /// Returns all attempts (direct and background) that were pending to this address.
     pub fn process_connection(
         &mut self,
         address: AddressWithType,
         result: Result<LeConnection, ErrorCode>,
-    ) {
+    ) -> Vec<ConnectionAttempt> {
         let interested_clients = self
             .attempts
             .keys()
@@ -180,14 +228,16 @@ impl ConnectionAttempts {
             .copied()
             .collect::<Vec<_>>();
 
-        for attempt in interested_clients {
+        for attempt in &interested_clients {
             if attempt.mode == ConnectionMode::Direct {
                 // TODO(aryarahul): clean up these unwraps
-                let _ = self.attempts.remove(&attempt).unwrap().conn_tx.unwrap().send(result);
+                let _ = self.attempts.remove(attempt).unwrap().conn_tx.unwrap().send(result);
             } else {
                 // TODO(aryarahul): inform background clients of the connection
             }
         }
+
+        interested_clients
     }
 }
 
@@ -208,6 +258,8 @@ mod test {
     const ADDRESS_2: AddressWithType =
         AddressWithType { address: [1, 2, 3, 4, 5, 6], address_type: AddressType::Random };
 
+    const PARAMETERS: Option<ConnectionParameters> = None;
+
     const CONNECTION_1: LeConnection = LeConnection { remote_address: ADDRESS_1 };
     const CONNECTION_2: LeConnection = LeConnection { remote_address: ADDRESS_2 };
 
@@ -218,13 +270,14 @@ mod test {
             let mut attempts = ConnectionAttempts::new();
 
             // act: start a pending direct connection
-            let _ = attempts.register_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
+            let _ = attempts.register_direct_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
 
             // assert: this attempt is pending
             assert_eq!(attempts.active_attempts().len(), 1);
-            assert_eq!(attempts.active_attempts()[0].client, CLIENT_1);
-            assert_eq!(attempts.active_attempts()[0].mode, ConnectionMode::Direct);
-            assert_eq!(attempts.active_attempts()[0].remote_address, ADDRESS_1);
+            assert_eq!(attempts.active_attempts()[0].0.client, CLIENT_1);
+            assert_eq!(attempts.active_attempts()[0].0.mode, ConnectionMode::Direct);
+            assert_eq!(attempts.active_attempts()[0].0.remote_address, ADDRESS_1);
+            assert_eq!(attempts.active_attempts()[0].1, PARAMETERS);
         });
     }
 
@@ -234,7 +287,7 @@ mod test {
             // arrange: one pending direct connection
             let mut attempts = ConnectionAttempts::new();
             let pending_direct_connection =
-                attempts.register_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
+                attempts.register_direct_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
 
             // act: cancel it
             attempts.cancel_attempt(CLIENT_1, ADDRESS_1, ConnectionMode::Direct).unwrap();
@@ -253,8 +306,8 @@ mod test {
             let mut attempts = ConnectionAttempts::new();
 
             // act: start two direct connections
-            attempts.register_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
-            attempts.register_direct_connection(CLIENT_2, ADDRESS_1).unwrap();
+            attempts.register_direct_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
+            attempts.register_direct_connection(CLIENT_2, ADDRESS_1, PARAMETERS).unwrap();
 
             // assert: both attempts are pending
             assert_eq!(attempts.active_attempts().len(), 2);
@@ -266,15 +319,15 @@ mod test {
         block_on_locally(async {
             // arrange: two pending direct connections
             let mut attempts = ConnectionAttempts::new();
-            attempts.register_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
-            attempts.register_direct_connection(CLIENT_2, ADDRESS_1).unwrap();
+            attempts.register_direct_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
+            attempts.register_direct_connection(CLIENT_2, ADDRESS_1, PARAMETERS).unwrap();
 
             // act: cancel one
             attempts.cancel_attempt(CLIENT_1, ADDRESS_1, ConnectionMode::Direct).unwrap();
 
             // assert: one attempt is still pending
             assert_eq!(attempts.active_attempts().len(), 1);
-            assert_eq!(attempts.active_attempts()[0].client, CLIENT_2);
+            assert_eq!(attempts.active_attempts()[0].0.client, CLIENT_2);
         });
     }
 
@@ -284,9 +337,11 @@ mod test {
         let mut attempts = ConnectionAttempts::new();
 
         // act: start one pending direct connection, cancel it, restart it, and then drop the first future
-        let pending_1 = attempts.register_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
+        let pending_1 =
+            attempts.register_direct_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
         attempts.cancel_attempt(CLIENT_1, ADDRESS_1, ConnectionMode::Direct).unwrap();
-        let _pending_2 = attempts.register_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
+        let _pending_2 =
+            attempts.register_direct_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
         drop(pending_1);
 
         // assert: the restart is still pending
@@ -300,13 +355,13 @@ mod test {
             let mut attempts = ConnectionAttempts::new();
 
             // act: start a pending background connection
-            attempts.register_background_connection(CLIENT_1, ADDRESS_1).unwrap();
+            attempts.register_background_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
 
             // assert: this attempt is pending
             assert_eq!(attempts.active_attempts().len(), 1);
-            assert_eq!(attempts.active_attempts()[0].client, CLIENT_1);
-            assert_eq!(attempts.active_attempts()[0].mode, ConnectionMode::Background);
-            assert_eq!(attempts.active_attempts()[0].remote_address, ADDRESS_1);
+            assert_eq!(attempts.active_attempts()[0].0.client, CLIENT_1);
+            assert_eq!(attempts.active_attempts()[0].0.mode, ConnectionMode::Background);
+            assert_eq!(attempts.active_attempts()[0].0.remote_address, ADDRESS_1);
         });
     }
 
@@ -317,8 +372,9 @@ mod test {
             let mut attempts = ConnectionAttempts::new();
 
             // act: start two background connections with the same parameters
-            let _fut = attempts.register_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
-            let ret = attempts.register_direct_connection(CLIENT_1, ADDRESS_1);
+            let _fut =
+                attempts.register_direct_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
+            let ret = attempts.register_direct_connection(CLIENT_1, ADDRESS_1, PARAMETERS);
 
             // assert: this attempt is pending
             assert!(matches!(ret, Err(CreateConnectionFailure::ConnectionAlreadyPending)));
@@ -332,8 +388,8 @@ mod test {
             let mut attempts = ConnectionAttempts::new();
 
             // act: start two background connections with the same parameters
-            attempts.register_background_connection(CLIENT_1, ADDRESS_1).unwrap();
-            let ret = attempts.register_background_connection(CLIENT_1, ADDRESS_1);
+            attempts.register_background_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
+            let ret = attempts.register_background_connection(CLIENT_1, ADDRESS_1, PARAMETERS);
 
             // assert: this attempt is pending
             assert_eq!(ret, Err(CreateConnectionFailure::ConnectionAlreadyPending));
@@ -345,7 +401,8 @@ mod test {
         block_on_locally(async {
             // arrange: one pending direct connection
             let mut attempts = ConnectionAttempts::new();
-            let pending_conn = attempts.register_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
+            let pending_conn =
+                attempts.register_direct_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
 
             // act: resolve with an incoming connection
             attempts.process_connection(ADDRESS_1, Ok(CONNECTION_1));
@@ -361,7 +418,8 @@ mod test {
         block_on_locally(async {
             // arrange: one pending direct connection
             let mut attempts = ConnectionAttempts::new();
-            let pending_conn = attempts.register_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
+            let pending_conn =
+                attempts.register_direct_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
 
             // act: resolve with an incoming connection
             attempts.process_connection(ADDRESS_1, Err(ErrorCode(1)));
@@ -377,7 +435,7 @@ mod test {
         block_on_locally(async {
             // arrange: one pending direct connection
             let mut attempts = ConnectionAttempts::new();
-            attempts.register_background_connection(CLIENT_1, ADDRESS_1).unwrap();
+            attempts.register_background_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
 
             // act: resolve with an incoming connection
             attempts.process_connection(ADDRESS_1, Ok(CONNECTION_1));
@@ -392,7 +450,8 @@ mod test {
         block_on_locally(async {
             // arrange: one pending direct connection
             let mut attempts = ConnectionAttempts::new();
-            let pending_conn = attempts.register_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
+            let pending_conn =
+                attempts.register_direct_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
 
             // act: an incoming connection arrives to a different address
             attempts.process_connection(ADDRESS_2, Ok(CONNECTION_2));
@@ -408,10 +467,12 @@ mod test {
         block_on_locally(async {
             // arrange: one pending direct connection and one background connection to each of two addresses
             let mut attempts = ConnectionAttempts::new();
-            let pending_conn_1 = attempts.register_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
-            let pending_conn_2 = attempts.register_direct_connection(CLIENT_1, ADDRESS_2).unwrap();
-            attempts.register_background_connection(CLIENT_1, ADDRESS_1).unwrap();
-            attempts.register_background_connection(CLIENT_1, ADDRESS_2).unwrap();
+            let pending_conn_1 =
+                attempts.register_direct_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
+            let pending_conn_2 =
+                attempts.register_direct_connection(CLIENT_1, ADDRESS_2, PARAMETERS).unwrap();
+            attempts.register_background_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
+            attempts.register_background_connection(CLIENT_1, ADDRESS_2, PARAMETERS).unwrap();
 
             // act: an incoming connection arrives to the first address
             attempts.process_connection(ADDRESS_1, Ok(CONNECTION_1));
@@ -429,7 +490,7 @@ mod test {
         block_on_locally(async {
             // arrange: one pending background connection
             let mut attempts = ConnectionAttempts::new();
-            attempts.register_background_connection(CLIENT_1, ADDRESS_1).unwrap();
+            attempts.register_background_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
 
             // act: remove it
             attempts.cancel_attempt(CLIENT_1, ADDRESS_1, ConnectionMode::Background).unwrap();
@@ -458,10 +519,12 @@ mod test {
         block_on_locally(async {
             // arrange: one pending direct connection, and one background connection, to each address
             let mut attempts = ConnectionAttempts::new();
-            let pending_conn_1 = attempts.register_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
-            let pending_conn_2 = attempts.register_direct_connection(CLIENT_1, ADDRESS_2).unwrap();
-            attempts.register_background_connection(CLIENT_1, ADDRESS_1).unwrap();
-            attempts.register_background_connection(CLIENT_1, ADDRESS_2).unwrap();
+            let pending_conn_1 =
+                attempts.register_direct_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
+            let pending_conn_2 =
+                attempts.register_direct_connection(CLIENT_1, ADDRESS_2, PARAMETERS).unwrap();
+            attempts.register_background_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
+            attempts.register_background_connection(CLIENT_1, ADDRESS_2, PARAMETERS).unwrap();
 
             // act: cancel all connections to the first address
             attempts.remove_unconditionally(ADDRESS_1);
@@ -471,8 +534,8 @@ mod test {
             assert!(try_await(pending_conn_2).await.is_err());
             // assert: two attempts remain, both to the other address
             assert_eq!(attempts.active_attempts().len(), 2);
-            assert_eq!(attempts.active_attempts()[0].remote_address, ADDRESS_2);
-            assert_eq!(attempts.active_attempts()[1].remote_address, ADDRESS_2);
+            assert_eq!(attempts.active_attempts()[0].0.remote_address, ADDRESS_2);
+            assert_eq!(attempts.active_attempts()[1].0.remote_address, ADDRESS_2);
         });
     }
 
@@ -481,10 +544,12 @@ mod test {
         block_on_locally(async {
             // arrange: one pending direct connection, and one background connection, from each address
             let mut attempts = ConnectionAttempts::new();
-            let pending_conn_1 = attempts.register_direct_connection(CLIENT_1, ADDRESS_1).unwrap();
-            let pending_conn_2 = attempts.register_direct_connection(CLIENT_2, ADDRESS_1).unwrap();
-            attempts.register_background_connection(CLIENT_1, ADDRESS_1).unwrap();
-            attempts.register_background_connection(CLIENT_2, ADDRESS_1).unwrap();
+            let pending_conn_1 =
+                attempts.register_direct_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
+            let pending_conn_2 =
+                attempts.register_direct_connection(CLIENT_2, ADDRESS_1, PARAMETERS).unwrap();
+            attempts.register_background_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
+            attempts.register_background_connection(CLIENT_2, ADDRESS_1, PARAMETERS).unwrap();
 
             // act: remove the first client
             attempts.remove_client(CLIENT_1);
@@ -494,8 +559,51 @@ mod test {
             assert!(try_await(pending_conn_2).await.is_err());
             // assert: two attempts remain, both from the second client
             assert_eq!(attempts.active_attempts().len(), 2);
-            assert_eq!(attempts.active_attempts()[0].client, CLIENT_2);
-            assert_eq!(attempts.active_attempts()[1].client, CLIENT_2);
+            assert_eq!(attempts.active_attempts()[0].0.client, CLIENT_2);
+            assert_eq!(attempts.active_attempts()[1].0.client, CLIENT_2);
+        });
+    }
+
+    #[test]
+    fn test_attempt_ages() {
+        block_on_locally(async {
+            // arrange: one attempt, then another a while later
+            let mut attempts = ConnectionAttempts::new();
+            attempts.register_background_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            attempts.register_background_connection(CLIENT_2, ADDRESS_1, PARAMETERS).unwrap();
+
+            // act
+            let ages = attempts
+                .attempt_ages()
+                .into_iter()
+                .map(|(attempt, age)| (attempt.client, age))
+                .collect::<HashMap<_, _>>();
+
+            // assert
+            assert_eq!(ages[&CLIENT_1], Duration::from_secs(10));
+            assert_eq!(ages[&CLIENT_2], Duration::ZERO);
         });
     }
+
+    #[test]
+    fn test_remove_client_returns_removed_attempts() {
+        // arrange
+        let mut attempts = ConnectionAttempts::new();
+        attempts.register_background_connection(CLIENT_1, ADDRESS_1, PARAMETERS).unwrap();
+        attempts.register_background_connection(CLIENT_2, ADDRESS_1, PARAMETERS).unwrap();
+
+        // act
+        let removed = attempts.remove_client(CLIENT_1);
+
+        // assert
+        assert_eq!(
+            removed,
+            vec![ConnectionAttempt {
+                client: CLIENT_1,
+                mode: ConnectionMode::Background,
+                remote_address: ADDRESS_1
+            }]
+        );
+    }
 }
@@ -0,0 +1,271 @@
+//! This module decides which background connection attempts are placed on the
+//! filter accept list when there are more of them than the controller can hold.
+//!
+//! Direct connections always take precedence. The remaining slots go to the
+//! background addresses with the highest effective priority, where the effective
+//! priority is the highest priority of any client interested in the address, plus
+//! the number of rotations it has spent waiting for a slot. Rotating periodically
+//! therefore guarantees that every background attempt is eventually scheduled,
+//! regardless of its priority.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use log::info;
+
+use crate::core::address::AddressWithType;
+
+use super::acceptlist_manager::TargetState;
+
+/// How often background addresses are rotated through the accept list, if it
+/// is over capacity
+pub const ROTATION_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The priority of a client's background connections. Higher values are
+/// scheduled first when the accept list is over capacity.
+pub type ClientPriority = u8;
+
+/// Selects which background connections fit in the accept list
+#[derive(Debug, Default)]
+pub struct BackgroundScheduler {
+    /// The number of addresses the accept list can hold (if limited)
+    capacity: Option<usize>,
+    /// The background addresses currently on the accept list
+    scheduled: HashSet<AddressWithType>,
+    /// The background addresses waiting for a slot, and the number of rotations
+    /// they have waited
+    waiting: HashMap<AddressWithType, u32>,
+}
+
+impl BackgroundScheduler {
+    /// Constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the capacity of the accept list (None if unlimited)
+    pub fn set_capacity(&mut self, capacity: Option<usize>) {
+        self.capacity = capacity;
+    }
+
+    /// Whether some background addresses are currently waiting for a slot
+    pub fn is_over_capacity(&self) -> bool {
+        !self.waiting.is_empty()
+    }
+
+    /// Remove any background addresses from the target state that do not fit in
+    /// the accept list. `priorities` contains the priority of every background
+    /// address in the target state, and `connected` the addresses we are connected
+    /// to (which must not be removed, and do not occupy a slot).
+    pub fn select(
+        &mut self,
+        target: &mut TargetState,
+        priorities: &HashMap<AddressWithType, ClientPriority>,
+        connected: &HashSet<AddressWithType>,
+    ) {
+        let Some(capacity) = self.capacity else {
+            self.scheduled = target.background_list.keys().copied().collect();
+            self.waiting.clear();
+            return;
+        };
+
+        // addresses on the direct list are already on the accept list, so are free
+        let free_slots = capacity.saturating_sub(target.direct_list.len());
+        let mut candidates = target
+            .background_list
+            .keys()
+            .filter(|address| {
+                !target.direct_list.contains_key(address) && !connected.contains(address)
+            })
+            .copied()
+            .collect::<Vec<_>>();
+
+        // prefer the highest effective priority, then addresses that are already
+        // scheduled (to avoid churn), then an arbitrary but stable order
+        candidates.sort_by_key(|address| {
+            let priority = priorities.get(address).copied().unwrap_or_default();
+            let waited = self.waiting.get(address).copied().unwrap_or_default();
+            (
+                std::cmp::Reverse(u32::from(priority) + waited),
+                !self.scheduled.contains(address),
+                address.address,
+                address.address_type as u8,
+            )
+        });
+        let excluded = candidates.split_off(free_slots.min(candidates.len()));
+
+        for address in &excluded {
+            target.background_list.remove(address);
+        }
+        self.waiting.retain(|address, _| excluded.contains(address));
+        for address in excluded {
+            self.waiting.entry(address).or_default();
+        }
+        self.scheduled = target.background_list.keys().copied().collect();
+    }
+
+    /// Age all waiting addresses, so they will displace scheduled ones at the
+    /// next call to select(). Returns whether anything was waiting.
+    pub fn rotate(&mut self) -> bool {
+        if !self.is_over_capacity() {
+            return false;
+        }
+        info!("Rotating {} waiting background connections", self.waiting.len());
+        for waited in self.waiting.values_mut() {
+            *waited += 1;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{connection::le_manager::ConnectionParameters, core::address::AddressType};
+
+    use super::*;
+
+    const PARAMETERS: ConnectionParameters = ConnectionParameters::DEFAULT_BACKGROUND;
+
+    fn address(n: u8) -> AddressWithType {
+        AddressWithType { address: [n, 0, 0, 0, 0, 0], address_type: AddressType::Public }
+    }
+
+    fn target(direct: &[u8], background: &[u8]) -> TargetState {
+        TargetState {
+            direct_list: direct.iter().map(|n| (address(*n), PARAMETERS)).collect(),
+            background_list: background.iter().map(|n| (address(*n), PARAMETERS)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_unlimited_capacity() {
+        // arrange
+        let mut scheduler = BackgroundScheduler::new();
+        let mut target = target(&[], &[1, 2, 3]);
+
+        // act
+        scheduler.select(&mut target, &HashMap::new(), &HashSet::new());
+
+        // assert
+        assert_eq!(target.background_list.len(), 3);
+        assert!(!scheduler.is_over_capacity());
+    }
+
+    #[test]
+    fn test_capacity_limits_background_list() {
+        // arrange
+        let mut scheduler = BackgroundScheduler::new();
+        scheduler.set_capacity(Some(2));
+        let mut target = target(&[], &[1, 2, 3]);
+
+        // act
+        scheduler.select(&mut target, &HashMap::new(), &HashSet::new());
+
+        // assert
+        assert_eq!(target.background_list.len(), 2);
+        assert!(scheduler.is_over_capacity());
+    }
+
+    #[test]
+    fn test_direct_connections_take_precedence() {
+        // arrange
+        let mut scheduler = BackgroundScheduler::new();
+        scheduler.set_capacity(Some(2));
+        let mut target = target(&[1], &[1, 2, 3]);
+
+        // act
+        scheduler.select(&mut target, &HashMap::new(), &HashSet::new());
+
+        // assert: address 1 is free (already on the direct list), plus one other
+        assert_eq!(target.direct_list.len(), 1);
+        assert_eq!(target.background_list.len(), 2);
+        assert!(target.background_list.contains_key(&address(1)));
+    }
+
+    #[test]
+    fn test_priority() {
+        // arrange
+        let mut scheduler = BackgroundScheduler::new();
+        scheduler.set_capacity(Some(1));
+        let mut target = target(&[], &[1, 2, 3]);
+
+        // act
+        scheduler.select(&mut target, &[(address(3), 5)].into(), &HashSet::new());
+
+        // assert
+        assert_eq!(target.background_list.keys().collect::<Vec<_>>(), vec![&address(3)]);
+    }
+
+    #[test]
+    fn test_selection_is_stable_without_rotation() {
+        // arrange
+        let mut scheduler = BackgroundScheduler::new();
+        scheduler.set_capacity(Some(1));
+        let mut first = target(&[], &[1, 2, 3]);
+        scheduler.select(&mut first, &HashMap::new(), &HashSet::new());
+
+        // act: select again without rotating
+        let mut second = target(&[], &[1, 2, 3]);
+        scheduler.select(&mut second, &HashMap::new(), &HashSet::new());
+
+        // assert
+        assert_eq!(first.background_list, second.background_list);
+    }
+
+    #[test]
+    fn test_connected_addresses_are_kept_and_free() {
+        // arrange
+        let mut scheduler = BackgroundScheduler::new();
+        scheduler.set_capacity(Some(1));
+        let mut target = target(&[], &[1, 2, 3]);
+
+        // act: address 3 is connected, and has the lowest priority
+        scheduler.select(
+            &mut target,
+            &[(address(1), 1), (address(2), 1)].into(),
+            &[address(3)].into(),
+        );
+
+        // assert: it is kept, along with one other
+        assert_eq!(target.background_list.len(), 2);
+        assert!(target.background_list.contains_key(&address(3)));
+    }
+
+    #[test]
+    fn test_rotation_does_not_starve_low_priority() {
+        // arrange: one slot, contended by a high priority and two low priority addresses
+        let mut scheduler = BackgroundScheduler::new();
+        scheduler.set_capacity(Some(1));
+        let priorities = [(address(1), 3)].into();
+        let mut ever_scheduled = HashSet::new();
+
+        // act: rotate a number of times
+        for _ in 0..20 {
+            let mut target = target(&[], &[1, 2, 3]);
+            scheduler.select(&mut target, &priorities, &HashSet::new());
+            ever_scheduled.extend(target.background_list.keys().copied());
+            scheduler.rotate();
+        }
+
+        // assert: every address got a slot at some point
+        assert_eq!(ever_scheduled, [address(1), address(2), address(3)].into());
+    }
+
+    #[test]
+    fn test_removed_address_stops_waiting() {
+        // arrange: an address waiting for a slot
+        let mut scheduler = BackgroundScheduler::new();
+        scheduler.set_capacity(Some(1));
+        scheduler.select(&mut target(&[], &[1, 2]), &HashMap::new(), &HashSet::new());
+        assert!(scheduler.is_over_capacity());
+
+        // act: one of the attempts is removed
+        scheduler.select(&mut target(&[], &[1]), &HashMap::new(), &HashSet::new());
+
+        // assert
+        assert!(!scheduler.is_over_capacity());
+        assert!(!scheduler.rotate());
+    }
+}
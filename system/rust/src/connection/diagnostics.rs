@@ -0,0 +1,192 @@
+//! This module contains the events emitted by the connection manager, and
+//! snapshots of its state, so we can tell why a peer is (or is not) being
+//! connected to without adding logs by hand.
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt::{self, Display},
+    rc::Rc,
+    time::Duration,
+};
+
+use log::debug;
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    task::spawn_local,
+    time::Instant,
+};
+
+use crate::core::address::AddressWithType;
+
+use super::{attempt_manager::ConnectionMode, le_manager::ErrorCode, ConnectionManagerClient};
+
+/// An event in the lifecycle of connection attempts and connections
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionManagerEvent {
+    /// A client registered a connection attempt
+    AttemptRegistered {
+        /// The client making the attempt
+        client: ConnectionManagerClient,
+        /// The (identity) address of the peer
+        address: AddressWithType,
+        /// Whether this is a direct or background attempt
+        mode: ConnectionMode,
+    },
+    /// A connection attempt was cancelled, either by its client, by a timeout,
+    /// or because the client was removed
+    AttemptCancelled {
+        /// The client that made the attempt
+        client: ConnectionManagerClient,
+        /// The (identity) address of the peer
+        address: AddressWithType,
+        /// Whether this was a direct or background attempt
+        mode: ConnectionMode,
+    },
+    /// An address was added to the direct or background list of the LE manager
+    AcceptlistAdded {
+        /// The address added
+        address: AddressWithType,
+        /// The list it was added to
+        mode: ConnectionMode,
+    },
+    /// An address was removed from the direct or background list of the LE manager
+    AcceptlistRemoved {
+        /// The address removed
+        address: AddressWithType,
+        /// The list it was removed from
+        mode: ConnectionMode,
+    },
+    /// A connection completed successfully
+    Connected {
+        /// The identity address of the peer
+        address: AddressWithType,
+        /// The address reported in the connection complete event (may be an RPA)
+        reported_address: AddressWithType,
+        /// The clients with attempts pending to this peer
+        clients: Vec<ConnectionManagerClient>,
+    },
+    /// A connection completed with an error
+    ConnectionFailed {
+        /// The identity address of the peer
+        address: AddressWithType,
+        /// The HCI error code
+        error: ErrorCode,
+        /// The clients with attempts pending to this peer
+        clients: Vec<ConnectionManagerClient>,
+    },
+    /// A connection was disconnected
+    Disconnected {
+        /// The address reported when the connection was created
+        address: AddressWithType,
+    },
+}
+
+/// Delivers events to all subscribers that are still listening
+#[derive(Debug, Default)]
+pub struct EventListeners(Vec<UnboundedSender<ConnectionManagerEvent>>);
+
+impl EventListeners {
+    /// Add a subscriber. Events are delivered until the receiver is dropped.
+    pub fn subscribe(&mut self) -> UnboundedReceiver<ConnectionManagerEvent> {
+        let (tx, rx) = unbounded_channel();
+        self.0.push(tx);
+        rx
+    }
+
+    /// Send an event to all subscribers, pruning any that have gone away
+    pub fn emit(&mut self, event: ConnectionManagerEvent) {
+        self.0.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+}
+
+/// How many events are kept by an EventHistory
+const EVENT_HISTORY_SIZE: usize = 50;
+
+/// The most recent events of the connection manager, recorded from a
+/// subscription so they can be included in dumpsys
+#[derive(Clone, Debug, Default)]
+pub struct EventHistory(Rc<RefCell<VecDeque<(Instant, ConnectionManagerEvent)>>>);
+
+impl EventHistory {
+    /// Record the events received from this subscription, until it is closed
+    pub fn record(mut events: UnboundedReceiver<ConnectionManagerEvent>) -> Self {
+        let history = Self::default();
+        let recorder = history.clone();
+        spawn_local(async move {
+            while let Some(event) = events.recv().await {
+                debug!("connection manager event: {event:?}");
+                recorder.push(event);
+            }
+        });
+        history
+    }
+
+    fn push(&self, event: ConnectionManagerEvent) {
+        let mut events = self.0.borrow_mut();
+        if events.len() == EVENT_HISTORY_SIZE {
+            events.pop_front();
+        }
+        events.push_back((Instant::now(), event));
+    }
+}
+
+impl Display for EventHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "recent events:")?;
+        for (time, event) in self.0.borrow().iter() {
+            writeln!(f, "  {:?} ago: {event:?}", time.elapsed())?;
+        }
+        Ok(())
+    }
+}
+
+/// A pending connection attempt
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttemptSnapshot {
+    /// The client making the attempt
+    pub client: ConnectionManagerClient,
+    /// The (identity) address of the peer
+    pub address: AddressWithType,
+    /// Whether this is a direct or background attempt
+    pub mode: ConnectionMode,
+    /// How long ago the attempt was registered
+    pub age: Duration,
+}
+
+/// A snapshot of the state of the connection manager
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionManagerSnapshot {
+    /// All pending connection attempts, oldest first
+    pub attempts: Vec<AttemptSnapshot>,
+    /// The addresses on the direct and background lists of the LE manager
+    pub acceptlist: Vec<(AddressWithType, ConnectionMode)>,
+    /// The current connections, as (identity address, reported address) pairs
+    pub connections: Vec<(AddressWithType, AddressWithType)>,
+}
+
+impl Display for ConnectionManagerSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "attempts:")?;
+        for attempt in &self.attempts {
+            writeln!(
+                f,
+                "  {:?} -> {:?} ({:?}, age {:?})",
+                attempt.client, attempt.address, attempt.mode, attempt.age
+            )?;
+        }
+        writeln!(f, "acceptlist:")?;
+        for (address, mode) in &self.acceptlist {
+            writeln!(f, "  {address:?} ({mode:?})")?;
+        }
+        writeln!(f, "connections:")?;
+        for (identity, reported) in &self.connections {
+            if identity == reported {
+                writeln!(f, "  {identity:?}")?;
+            } else {
+                writeln!(f, "  {identity:?} (via {reported:?})")?;
+            }
+        }
+        Ok(())
+    }
+}
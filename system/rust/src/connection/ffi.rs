@@ -11,12 +11,15 @@ use tokio::{
     task::spawn_local,
 };
 
-use crate::{core::address::AddressWithType, do_in_rust_thread};
+use crate::{core::address::AddressWithType, do_in_rust_thread, dump_from_rust_thread};
 
 use super::{
     attempt_manager::ConnectionMode,
-    le_manager::{ErrorCode, InactiveLeAclManager, LeAclManager, LeAclManagerConnectionCallbacks},
-    ConnectionManagerClient, LeConnection,
+    le_manager::{
+        ConnectionParameters, ErrorCode, InactiveLeAclManager, LeAclManager,
+        LeAclManagerConnectionCallbacks,
+    },
+    ConnectionManagerClient, Irk, LeConnection,
 };
 
 unsafe impl Send for LeAclManagerShim {}
@@ -33,6 +36,25 @@ mod inner {
         type AddressWithType = crate::core::address::AddressWithType;
     }
 
+    /// The scan and connection parameters to use for a connection attempt
+    #[namespace = "bluetooth::connection"]
+    struct LeConnectionParameters {
+        /// Scan interval (0.625ms units)
+        scan_interval: u16,
+        /// Scan window (0.625ms units)
+        scan_window: u16,
+        /// Minimum connection interval (1.25ms units)
+        conn_interval_min: u16,
+        /// Maximum connection interval (1.25ms units)
+        conn_interval_max: u16,
+        /// Peripheral latency (connection events)
+        conn_latency: u16,
+        /// Supervision timeout (10ms units)
+        supervision_timeout: u16,
+        /// Bitmask of initiating PHYs (as in LE Extended Create Connection)
+        initiating_phys: u8,
+    }
+
     #[namespace = "bluetooth::connection"]
     unsafe extern "C++" {
         include!("src/connection/ffi/connection_shim.h");
@@ -47,10 +69,24 @@ mod inner {
         #[cxx_name = "CreateLeConnection"]
         fn create_le_connection(&self, address: AddressWithType, is_direct: bool);
 
+        /// As above, but connecting with the given parameters instead of the system
+        /// defaults, for as long as this direct or background connection is pending
+        #[cxx_name = "CreateLeConnectionWithParameters"]
+        fn create_le_connection_with_parameters(
+            &self,
+            address: AddressWithType,
+            is_direct: bool,
+            parameters: LeConnectionParameters,
+        );
+
         /// Remove address from both direct + background connect lists
         #[cxx_name = "CancelLeConnect"]
         fn cancel_le_connect(&self, address: AddressWithType);
 
+        /// The number of addresses the controller's filter accept list can hold
+        #[cxx_name = "GetLeFilterAcceptListSize"]
+        fn get_le_filter_accept_list_size(&self) -> u8;
+
         /// Register Rust callbacks for connection events
         ///
         /// # Safety
@@ -72,6 +108,10 @@ mod inner {
         fn on_le_connect_fail(&self, address: AddressWithType, status: u8);
         #[cxx_name = "OnLeDisconnection"]
         fn on_disconnect(&self, address: AddressWithType);
+
+        /// Dump the pending attempts, accept list, and connections (for dumpsys)
+        #[cxx_name = "DumpConnectionManager"]
+        fn dump_connection_manager() -> String;
     }
 
     #[namespace = "bluetooth::connection"]
@@ -86,6 +126,9 @@ mod inner {
             remove_background_connection: fn(client_id: u8, address: AddressWithType),
             remove_client: fn(client_id: u8),
             stop_all_connections_to_device: fn(address: AddressWithType),
+            add_bonded_identity: fn(identity: AddressWithType, irk: &[u8]),
+            remove_bonded_identity: fn(identity: AddressWithType),
+            set_client_priority: fn(client_id: u8, priority: u8),
         );
     }
 }
@@ -155,18 +198,63 @@ impl InactiveLeAclManager for LeAclManagerImpl {
     }
 }
 
+impl From<ConnectionParameters> for LeConnectionParameters {
+    fn from(parameters: ConnectionParameters) -> Self {
+        Self {
+            scan_interval: parameters.scan_interval,
+            scan_window: parameters.scan_window,
+            conn_interval_min: parameters.conn_interval_min,
+            conn_interval_max: parameters.conn_interval_max,
+            conn_latency: parameters.conn_latency,
+            supervision_timeout: parameters.supervision_timeout,
+            initiating_phys: parameters.initiating_phys.bits(),
+        }
+    }
+}
+
+impl LeAclManagerImpl {
+    fn create_le_connection(
+        &self,
+        address: AddressWithType,
+        is_direct: bool,
+        parameters: Option<ConnectionParameters>,
+    ) {
+        match parameters {
+            Some(parameters) => {
+                self.0.create_le_connection_with_parameters(address, is_direct, parameters.into())
+            }
+            None => self.0.create_le_connection(address, is_direct),
+        }
+    }
+}
+
 impl LeAclManager for LeAclManagerImpl {
-    fn add_to_direct_list(&self, address: AddressWithType) {
-        self.0.create_le_connection(address, /* is_direct= */ true)
+    fn add_to_direct_list(
+        &self,
+        address: AddressWithType,
+        parameters: Option<ConnectionParameters>,
+    ) {
+        self.create_le_connection(address, /* is_direct= */ true, parameters)
     }
 
-    fn add_to_background_list(&self, address: AddressWithType) {
-        self.0.create_le_connection(address, /* is_direct= */ false)
+    fn add_to_background_list(
+        &self,
+        address: AddressWithType,
+        parameters: Option<ConnectionParameters>,
+    ) {
+        self.create_le_connection(address, /* is_direct= */ false, parameters)
     }
 
     fn remove_from_all_lists(&self, address: AddressWithType) {
         self.0.cancel_le_connect(address)
     }
+
+    fn acceptlist_capacity(&self) -> Option<usize> {
+        match self.0.get_le_filter_accept_list_size() {
+            0 => None,
+            size => Some(size.into()),
+        }
+    }
 }
 
 impl Debug for LeAclManagerImpl {
@@ -175,6 +263,14 @@ impl Debug for LeAclManagerImpl {
     }
 }
 
+/// Synchronously dump the state of the connection manager, and its recent events.
+/// Must not be invoked from the Rust thread itself.
+fn dump_connection_manager() -> String {
+    dump_from_rust_thread("connection manager", |modules| {
+        format!("{}{}", modules.connection_manager.dump(), modules.connection_events)
+    })
+}
+
 /// Registers all connection-manager callbacks into C++ dependencies
 pub fn register_callbacks() {
     RegisterRustApis(
@@ -234,5 +330,25 @@ pub fn register_callbacks() {
                 modules.connection_manager.cancel_unconditionally(address);
             })
         },
+        |identity, irk| {
+            let Ok(irk) = Irk::try_from(irk) else {
+                warn!("Ignoring IRK of {identity:?} with invalid length {}", irk.len());
+                return;
+            };
+            do_in_rust_thread(move |modules| {
+                modules.connection_manager.add_bonded_identity(identity, irk);
+            })
+        },
+        |identity| {
+            do_in_rust_thread(move |modules| {
+                modules.connection_manager.remove_bonded_identity(identity);
+            })
+        },
+        |client, priority| {
+            let client = ConnectionManagerClient::GattClient(client);
+            do_in_rust_thread(move |modules| {
+                modules.connection_manager.set_client_priority(client, priority);
+            })
+        },
     )
 }
@@ -21,6 +21,7 @@
 
 #include "hci/acl_manager.h"
 #include "hci/address_with_type.h"
+#include "hci/controller.h"
 #include "hci/hci_packets.h"
 #include "main/shim/entry.h"
 #ifndef TARGET_FLOSS
@@ -50,6 +51,16 @@ struct LeAclManagerCallbackShim {
 
 using BoxedLeAclManagerCallbackShim = std::unique_ptr<LeAclManagerCallbackShim>;
 
+struct LeConnectionParameters {
+  uint16_t scan_interval;
+  uint16_t scan_window;
+  uint16_t conn_interval_min;
+  uint16_t conn_interval_max;
+  uint16_t conn_latency;
+  uint16_t supervision_timeout;
+  uint8_t initiating_phys;
+};
+
 #else
 
 using BoxedLeAclManagerCallbackShim = ::rust::Box<LeAclManagerCallbackShim>;
@@ -84,8 +95,27 @@ struct LeAclManagerShim::impl : hci::acl_manager::LeAcceptlistCallbacks {
     }
   }
 
-  void CreateLeConnection(core::AddressWithType address, bool is_direct) {
-    acl_manager_->CreateLeConnection(ToCppAddress(address), is_direct);
+  void CreateLeConnection(
+      core::AddressWithType address, bool is_direct,
+      std::optional<hci::acl_manager::LeConnectionParameters> parameters) {
+    acl_manager_->CreateLeConnection(ToCppAddress(address), is_direct,
+                                     parameters);
+  }
+
+  void CreateLeConnectionWithParameters(core::AddressWithType address,
+                                        bool is_direct,
+                                        LeConnectionParameters parameters) {
+    CreateLeConnection(
+        address, is_direct,
+        hci::acl_manager::LeConnectionParameters{
+            .scan_interval = parameters.scan_interval,
+            .scan_window = parameters.scan_window,
+            .conn_interval_min = parameters.conn_interval_min,
+            .conn_interval_max = parameters.conn_interval_max,
+            .conn_latency = parameters.conn_latency,
+            .supervision_timeout = parameters.supervision_timeout,
+            .initiating_phys = parameters.initiating_phys,
+        });
   }
 
   void CancelLeConnect(core::AddressWithType address) {
@@ -132,13 +162,23 @@ LeAclManagerShim::~LeAclManagerShim() = default;
 
 void LeAclManagerShim::CreateLeConnection(core::AddressWithType address,
                                           bool is_direct) const {
-  pimpl_->CreateLeConnection(address, is_direct);
+  pimpl_->CreateLeConnection(address, is_direct, std::nullopt);
+}
+
+void LeAclManagerShim::CreateLeConnectionWithParameters(
+    core::AddressWithType address, bool is_direct,
+    LeConnectionParameters parameters) const {
+  pimpl_->CreateLeConnectionWithParameters(address, is_direct, parameters);
 }
 
 void LeAclManagerShim::CancelLeConnect(core::AddressWithType address) const {
   pimpl_->CancelLeConnect(address);
 }
 
+uint8_t LeAclManagerShim::GetLeFilterAcceptListSize() const {
+  return shim::GetController()->GetLeFilterAcceptListSize();
+}
+
 #ifndef TARGET_FLOSS
 void LeAclManagerShim::RegisterRustCallbacks(
     BoxedLeAclManagerCallbackShim callbacks) {
@@ -167,13 +207,21 @@ void RegisterRustApis(
         remove_background_connection,
     ::rust::Fn<void(uint8_t client_id)> remove_client,
     ::rust::Fn<void(core::AddressWithType address)>
-        stop_all_connections_to_device) {
+        stop_all_connections_to_device,
+    ::rust::Fn<void(core::AddressWithType identity,
+                    ::rust::Slice<const uint8_t> irk)>
+        add_bonded_identity,
+    ::rust::Fn<void(core::AddressWithType identity)> remove_bonded_identity,
+    ::rust::Fn<void(uint8_t client_id, uint8_t priority)> set_client_priority) {
   connection_manager = {start_direct_connection,
                         stop_direct_connection,
                         add_background_connection,
                         remove_background_connection,
                         remove_client,
-                        stop_all_connections_to_device};
+                        stop_all_connections_to_device,
+                        add_bonded_identity,
+                        remove_bonded_identity,
+                        set_client_priority};
 }
 
 core::AddressWithType ResolveRawAddress(RawAddress bd_addr) {
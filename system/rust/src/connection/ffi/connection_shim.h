@@ -26,6 +26,7 @@ namespace bluetooth {
 namespace connection {
 
 struct LeAclManagerCallbackShim;
+struct LeConnectionParameters;
 
 class LeAclManagerShim {
  public:
@@ -34,8 +35,14 @@ class LeAclManagerShim {
 
   void CreateLeConnection(core::AddressWithType address, bool is_direct) const;
 
+  void CreateLeConnectionWithParameters(
+      core::AddressWithType address, bool is_direct,
+      LeConnectionParameters parameters) const;
+
   void CancelLeConnect(core::AddressWithType address) const;
 
+  uint8_t GetLeFilterAcceptListSize() const;
+
 #ifndef TARGET_FLOSS
   void RegisterRustCallbacks(::rust::Box<LeAclManagerCallbackShim> callbacks);
 #endif
@@ -56,7 +63,12 @@ void RegisterRustApis(
         remove_background_connection,
     ::rust::Fn<void(uint8_t client_id)> remove_client,
     ::rust::Fn<void(core::AddressWithType address)>
-        stop_all_connections_to_device);
+        stop_all_connections_to_device,
+    ::rust::Fn<void(core::AddressWithType identity,
+                    ::rust::Slice<const uint8_t> irk)>
+        add_bonded_identity,
+    ::rust::Fn<void(core::AddressWithType identity)> remove_bonded_identity,
+    ::rust::Fn<void(uint8_t client_id, uint8_t priority)> set_client_priority);
 
 struct RustConnectionManager {
   ::rust::Fn<void(uint8_t client_id, core::AddressWithType address)>
@@ -70,6 +82,11 @@ struct RustConnectionManager {
   ::rust::Fn<void(uint8_t client_id)> remove_client;
   ::rust::Fn<void(core::AddressWithType address)>
       stop_all_connections_to_device;
+  ::rust::Fn<void(core::AddressWithType identity,
+                  ::rust::Slice<const uint8_t> irk)>
+      add_bonded_identity;
+  ::rust::Fn<void(core::AddressWithType identity)> remove_bonded_identity;
+  ::rust::Fn<void(uint8_t client_id, uint8_t priority)> set_client_priority;
 };
 
 RustConnectionManager& GetConnectionManager();
@@ -11,9 +11,15 @@
 //!
 //! In addition to the supplied API, when a connection completes to a peer device,
 //! it is removed from the "direct" connect list (based on exact address match).
+//!
+//! Parameters are fixed when an address is added to a list, so to change them the
+//! address must be removed and re-added. Addresses added without parameters use
+//! the defaults of le_impl (which may be tuned through system properties).
 
 use std::fmt::Debug;
 
+use bitflags::bitflags;
+
 use crate::core::address::AddressWithType;
 
 use super::LeConnection;
@@ -27,6 +33,70 @@ impl ErrorCode {
     pub const SUCCESS: Self = ErrorCode(0);
 }
 
+bitflags! {
+    /// The PHYs on which to initiate a connection
+    /// (see: 5.3 Vol 4E 7.8.66 LE Extended Create Connection command)
+    pub struct InitiatingPhys : u8 {
+        /// LE 1M
+        const LE_1M = 0x01;
+        /// LE 2M
+        const LE_2M = 0x02;
+        /// LE Coded
+        const LE_CODED = 0x04;
+    }
+}
+
+/// The scan and connection parameters to use when connecting to a peer
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ConnectionParameters {
+    /// The scan interval, in units of 0.625ms
+    pub scan_interval: u16,
+    /// The scan window, in units of 0.625ms (at most the scan interval)
+    pub scan_window: u16,
+    /// The minimum connection interval, in units of 1.25ms
+    pub conn_interval_min: u16,
+    /// The maximum connection interval, in units of 1.25ms
+    pub conn_interval_max: u16,
+    /// The peripheral latency, in connection events
+    pub conn_latency: u16,
+    /// The supervision timeout, in units of 10ms
+    pub supervision_timeout: u16,
+    /// The PHYs on which to initiate the connection
+    pub initiating_phys: InitiatingPhys,
+}
+
+impl ConnectionParameters {
+    /// Merge the parameters of two attempts to the same peer, so the most
+    /// aggressive choice wins: we scan as often as either attempt wants, use the
+    /// shortest connection interval / latency / supervision timeout, and initiate
+    /// on every PHY either attempt allows.
+    ///
+    /// If both inputs are valid, then so is the output (in particular, the
+    /// supervision timeout remains long enough for the merged interval and latency).
+    pub fn merge(self, other: Self) -> Self {
+        let scan_interval = self.scan_interval.min(other.scan_interval);
+        Self {
+            scan_interval,
+            scan_window: self.scan_window.max(other.scan_window).min(scan_interval),
+            conn_interval_min: self.conn_interval_min.min(other.conn_interval_min),
+            conn_interval_max: self.conn_interval_max.min(other.conn_interval_max),
+            conn_latency: self.conn_latency.min(other.conn_latency),
+            supervision_timeout: self.supervision_timeout.min(other.supervision_timeout),
+            initiating_phys: self.initiating_phys | other.initiating_phys,
+        }
+    }
+
+    /// Merge the parameters requested by two attempts to the same peer. If
+    /// only one of them requested parameters, those are used, since the other
+    /// is happy with whatever le_impl picks.
+    pub fn merge_requested(first: Option<Self>, second: Option<Self>) -> Option<Self> {
+        match (first, second) {
+            (Some(first), Some(second)) => Some(first.merge(second)),
+            (first, second) => first.or(second),
+        }
+    }
+}
+
 /// The LeAclManager before callbacks are registered
 pub trait InactiveLeAclManager {
     /// The type implementing LeAclManager once callbacks are registered
@@ -45,12 +115,24 @@ pub trait LeAclManager: Debug {
     /// WARNING: the connection timeout is set the FIRST time the address is added, and is
     /// NOT RESET! TODO(aryarahul): remove connection timeout from le_impl since it belongs here instead
     /// Precondition: Must NOT be currently connected to this adddress (if connected due to race, is a no-op)
-    fn add_to_direct_list(&self, address: AddressWithType); // CreateLeConnection(is_direct=true)
-    /// Adds an address to the background connect list
-    fn add_to_background_list(&self, address: AddressWithType); // CreateLeConnection(is_direct=false)
+    /// If no parameters are supplied, the system defaults are used.
+    fn add_to_direct_list(
+        &self,
+        address: AddressWithType,
+        parameters: Option<ConnectionParameters>,
+    ); // CreateLeConnection(is_direct=true)
+    /// Adds an address to the background connect list. If no parameters are supplied, the
+    /// system defaults are used.
+    fn add_to_background_list(
+        &self,
+        address: AddressWithType,
+        parameters: Option<ConnectionParameters>,
+    ); // CreateLeConnection(is_direct=false)
     /// Removes address from both the direct + background connect lists
     /// Due to races, it is possible to call this, and THEN get a connection complete with us as central
     fn remove_from_all_lists(&self, address: AddressWithType); // CancelLeConnect
+    /// The number of addresses the controller's filter accept list can hold, if known
+    fn acceptlist_capacity(&self) -> Option<usize>; // Controller::GetLeFilterAcceptListSize
 }
 
 /// The callbacks invoked by the LeAclManager in response to events from the controller
@@ -61,3 +143,73 @@ pub trait LeAclManagerConnectionCallbacks {
     /// supplied on the initial connection.
     fn on_disconnect(&self, address: AddressWithType);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RELAXED: ConnectionParameters = ConnectionParameters {
+        scan_interval: 0x0800,
+        scan_window: 0x0030,
+        conn_interval_min: 0x0018,
+        conn_interval_max: 0x0028,
+        conn_latency: 0x0000,
+        supervision_timeout: 0x01f4,
+        initiating_phys: InitiatingPhys::LE_1M,
+    };
+
+    const AGGRESSIVE: ConnectionParameters = ConnectionParameters {
+        scan_interval: 0x0010,
+        scan_window: 0x0010,
+        conn_interval_min: 0x0006,
+        conn_interval_max: 0x0008,
+        conn_latency: 0,
+        supervision_timeout: 0x0064,
+        initiating_phys: InitiatingPhys::LE_2M,
+    };
+
+    #[test]
+    fn test_merge_most_aggressive_wins() {
+        let merged = RELAXED.merge(AGGRESSIVE);
+
+        assert_eq!(merged.scan_interval, 0x0010);
+        assert_eq!(merged.scan_window, 0x0010);
+        assert_eq!(merged.conn_interval_min, 0x0006);
+        assert_eq!(merged.conn_interval_max, 0x0008);
+        assert_eq!(merged.conn_latency, 0);
+        assert_eq!(merged.supervision_timeout, 0x0064);
+        assert_eq!(merged.initiating_phys, InitiatingPhys::LE_1M | InitiatingPhys::LE_2M);
+    }
+
+    #[test]
+    fn test_merge_is_symmetric() {
+        assert_eq!(RELAXED.merge(AGGRESSIVE), AGGRESSIVE.merge(RELAXED));
+    }
+
+    #[test]
+    fn test_merge_requested_keeps_explicit_parameters() {
+        assert_eq!(ConnectionParameters::merge_requested(None, None), None);
+        assert_eq!(ConnectionParameters::merge_requested(None, Some(RELAXED)), Some(RELAXED));
+        assert_eq!(ConnectionParameters::merge_requested(Some(RELAXED), None), Some(RELAXED));
+        assert_eq!(
+            ConnectionParameters::merge_requested(Some(RELAXED), Some(AGGRESSIVE)),
+            Some(RELAXED.merge(AGGRESSIVE))
+        );
+    }
+
+    #[test]
+    fn test_merge_clamps_scan_window_to_interval() {
+        // arrange: one attempt with a long window, another with a short interval
+        let long_window =
+            ConnectionParameters { scan_interval: 0x0100, scan_window: 0x0100, ..RELAXED };
+        let short_interval =
+            ConnectionParameters { scan_interval: 0x0020, scan_window: 0x0010, ..RELAXED };
+
+        // act
+        let merged = long_window.merge(short_interval);
+
+        // assert: the window never exceeds the interval
+        assert_eq!(merged.scan_interval, 0x0020);
+        assert_eq!(merged.scan_window, 0x0020);
+    }
+}
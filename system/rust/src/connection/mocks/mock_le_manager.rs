@@ -3,13 +3,19 @@
 //! It also enforces all (implicit) invariants of le_impl as documented in le_manager.rs, and
 //! asserts on violation.
 
-use std::{cell::RefCell, collections::HashSet, fmt::Debug, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    rc::Rc,
+};
 
 use crate::{
     connection::{
         attempt_manager::ConnectionMode,
         le_manager::{
-            ErrorCode, InactiveLeAclManager, LeAclManager, LeAclManagerConnectionCallbacks,
+            ConnectionParameters, ErrorCode, InactiveLeAclManager, LeAclManager,
+            LeAclManagerConnectionCallbacks,
         },
         LeConnection,
     },
@@ -39,6 +45,10 @@ impl MockLeAclManager {
         self.inner().current_connection_mode()
     }
 
+    pub fn current_parameters(&self, address: AddressWithType) -> Option<ConnectionParameters> {
+        self.inner().current_parameters(address)
+    }
+
     pub fn on_le_connect(&self, address: AddressWithType, status: ErrorCode) {
         let inner = self.inner();
         inner.on_le_connect(address, status);
@@ -85,8 +95,8 @@ pub struct MockActiveLeAclManager {
 
 #[derive(Clone, Debug)]
 struct MockLeManagerInternalState {
-    direct_connect_list: HashSet<AddressWithType>,
-    background_connect_list: HashSet<AddressWithType>,
+    direct_connect_list: HashMap<AddressWithType, Option<ConnectionParameters>>,
+    background_connect_list: HashMap<AddressWithType, Option<ConnectionParameters>>,
     currently_connected: HashSet<AddressWithType>,
 }
 
@@ -94,8 +104,8 @@ impl MockActiveLeAclManager {
     pub fn new() -> Rc<Self> {
         Rc::new(MockActiveLeAclManager {
             state: RefCell::new(MockLeManagerInternalState {
-                direct_connect_list: HashSet::new(),
-                background_connect_list: HashSet::new(),
+                direct_connect_list: HashMap::new(),
+                background_connect_list: HashMap::new(),
                 currently_connected: HashSet::new(),
             }),
         })
@@ -103,8 +113,26 @@ impl MockActiveLeAclManager {
 
     pub fn current_acceptlist(&self) -> HashSet<AddressWithType> {
         let state = self.state.borrow();
-        &(&state.direct_connect_list | &state.background_connect_list)
-            - (&state.currently_connected)
+        state
+            .direct_connect_list
+            .keys()
+            .chain(state.background_connect_list.keys())
+            .filter(|address| !state.currently_connected.contains(address))
+            .copied()
+            .collect()
+    }
+
+    /// The parameters that le_impl would currently use to connect to this address,
+    /// if any were requested (those of direct and background connections are merged)
+    pub fn current_parameters(&self, address: AddressWithType) -> Option<ConnectionParameters> {
+        let state = self.state.borrow();
+        if state.currently_connected.contains(&address) {
+            return None;
+        }
+        ConnectionParameters::merge_requested(
+            state.direct_connect_list.get(&address).copied().flatten(),
+            state.background_connect_list.get(&address).copied().flatten(),
+        )
     }
 
     pub fn current_connection_mode(&self) -> Option<ConnectionMode> {
@@ -114,9 +142,8 @@ impl MockActiveLeAclManager {
             Some(ConnectionMode::Direct)
         } else if state
             .background_connect_list
-            .difference(&state.currently_connected)
-            .next()
-            .is_some()
+            .keys()
+            .any(|address| !state.currently_connected.contains(address))
         {
             Some(ConnectionMode::Background)
         } else {
@@ -141,23 +168,31 @@ impl MockActiveLeAclManager {
 }
 
 impl LeAclManager for Rc<MockActiveLeAclManager> {
-    fn add_to_direct_list(&self, address: AddressWithType) {
+    fn add_to_direct_list(
+        &self,
+        address: AddressWithType,
+        parameters: Option<ConnectionParameters>,
+    ) {
         let mut state = self.state.borrow_mut();
         assert!(
             !state.currently_connected.contains(&address),
             "Must NOT be currently connected to this address"
         );
-        let ok = state.direct_connect_list.insert(address);
+        let ok = state.direct_connect_list.insert(address, parameters).is_none();
         assert!(ok, "Already in direct connect list");
     }
 
-    fn add_to_background_list(&self, address: AddressWithType) {
+    fn add_to_background_list(
+        &self,
+        address: AddressWithType,
+        parameters: Option<ConnectionParameters>,
+    ) {
         let mut state = self.state.borrow_mut();
         assert!(
             !state.currently_connected.contains(&address),
             "Must NOT be currently connected to this address"
         );
-        let ok = state.background_connect_list.insert(address);
+        let ok = state.background_connect_list.insert(address, parameters).is_none();
         assert!(ok, "Already in background connect list");
     }
 
@@ -167,8 +202,12 @@ impl LeAclManager for Rc<MockActiveLeAclManager> {
             !state.currently_connected.contains(&address),
             "Must NOT be currently connected to this address"
         );
-        let ok1 = state.direct_connect_list.remove(&address);
-        let ok2 = state.background_connect_list.remove(&address);
+        let ok1 = state.direct_connect_list.remove(&address).is_some();
+        let ok2 = state.background_connect_list.remove(&address).is_some();
         assert!(ok1 || ok2, "Present in neither direct nor background connect list");
     }
+
+    fn acceptlist_capacity(&self) -> Option<usize> {
+        None
+    }
 }
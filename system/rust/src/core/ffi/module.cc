@@ -17,10 +17,15 @@
 #include "module.h"
 
 #include <hardware/bt_gatt.h>
+#include <stdio.h>
+
+#include <string>
 
 #include "btcore/include/module.h"
 #include "osi/include/log.h"
 #ifndef TARGET_FLOSS
+#include "main/shim/dumpsys.h"
+#include "src/connection/ffi.rs.h"
 #include "src/connection/ffi/connection_shim.h"
 #include "src/core/ffi.rs.h"
 #include "src/gatt/ffi.rs.h"
@@ -49,6 +54,16 @@ void FutureReady(future_t& future) { future_ready(&future, FUTURE_SUCCESS); }
 }  // namespace bluetooth
 
 namespace {
+bool dumpsys_registered = false;
+
+void Dump(int fd) {
+  LOG_DUMPSYS_TITLE(fd, "Rust ATT bearers");
+  dprintf(fd, "%s", std::string(bluetooth::gatt::dump_att_metrics()).c_str());
+  LOG_DUMPSYS_TITLE(fd, "Rust connection manager");
+  dprintf(fd, "%s",
+          std::string(bluetooth::connection::DumpConnectionManager()).c_str());
+}
+
 future_t* Start() {
   auto fut = future_new();
 
@@ -66,11 +81,17 @@ future_t* Start() {
       std::make_unique<bluetooth::gatt::GattServerCallbacks>(
           *bt_gatt_callbacks->server),
       std::make_unique<bluetooth::connection::LeAclManagerShim>(), *fut);
+  bluetooth::shim::RegisterDumpsysFunction(&rust_module, Dump);
+  dumpsys_registered = true;
 
   return fut;
 }
 
 future_t* Stop() {
+  if (dumpsys_registered) {
+    bluetooth::shim::UnregisterDumpsysFunction(&rust_module);
+    dumpsys_registered = false;
+  }
   bluetooth::rust_shim::stop();
   return nullptr;
 }
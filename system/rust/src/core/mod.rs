@@ -5,6 +5,7 @@ mod ffi;
 pub mod shared_box;
 pub mod shared_mutex;
 pub mod uuid;
+pub mod watchdog;
 
 use std::{pin::Pin, rc::Rc, thread};
 
@@ -13,7 +14,7 @@ use cxx::UniquePtr;
 
 use crate::{
     connection::{LeAclManagerImpl, LeAclManagerShim},
-    gatt::ffi::{AttTransportImpl, GattCallbacksImpl},
+    gatt::ffi::{AttTransportImpl, GapCallbacksImpl, GattCallbacksImpl},
     GlobalModuleRegistry, MainThreadTxMessage, GLOBAL_MODULE_REGISTRY,
 };
 
@@ -28,6 +29,7 @@ fn start(
         thread::spawn(move || {
             GlobalModuleRegistry::start(
                 Rc::new(GattCallbacksImpl(gatt_server_callbacks)),
+                Rc::new(GapCallbacksImpl),
                 Rc::new(AttTransportImpl()),
                 LeAclManagerImpl(le_acl_manager),
                 || {
@@ -6,6 +6,7 @@
 //! lifetime extension.
 
 use std::{
+    fmt::Debug,
     ops::Deref,
     rc::{Rc, Weak},
 };
@@ -76,6 +77,12 @@ impl<T: ?Sized> Clone for WeakBox<T> {
     }
 }
 
+impl<T: ?Sized> Debug for WeakBox<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("WeakBox").finish()
+    }
+}
+
 /// A strong reference to the contents within a SharedBox<>.
 pub struct WeakBoxRef<'a, T: ?Sized>(&'a T, Weak<T>);
 
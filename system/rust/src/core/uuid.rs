@@ -1,5 +1,9 @@
 //! A UUID (See Core Spec 5.3 Vol 1E 2.9.1. Basic Types)
 
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, bail};
+
 use crate::packets::{
     ParseError, Uuid128Builder, Uuid128View, Uuid16Builder, Uuid16View, UuidBuilder, UuidView,
 };
@@ -37,7 +41,23 @@ impl TryFrom<UuidView<'_>> for Uuid {
     type Error = ParseError;
 
     fn try_from(value: UuidView<'_>) -> Result<Self, ParseError> {
-        let bytes = value.get_data_iter().collect::<Vec<_>>();
+        Self::try_from(&value.get_data_iter().collect::<Vec<_>>()[..])
+    }
+}
+
+impl TryFrom<&UuidBuilder> for Uuid {
+    type Error = ParseError;
+
+    fn try_from(value: &UuidBuilder) -> Result<Self, ParseError> {
+        Self::try_from(&value.data[..])
+    }
+}
+
+impl TryFrom<&[u8]> for Uuid {
+    type Error = ParseError;
+
+    /// Parse a UUID from its little-endian representation (2, 4, or 16 bytes)
+    fn try_from(bytes: &[u8]) -> Result<Self, ParseError> {
         Ok(match bytes.len() {
             2 => Self::new(u16::from_le_bytes([bytes[0], bytes[1]]) as u32),
             4 => Self::new(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
@@ -49,6 +69,45 @@ impl TryFrom<UuidView<'_>> for Uuid {
     }
 }
 
+/// UUIDs are displayed in their shortest form: four hex digits if they are
+/// SIG-defined 16-bit UUIDs, otherwise the standard 8-4-4-4-12 form
+impl Display for Uuid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Ok(uuid) = Uuid16Builder::try_from(*self) {
+            return write!(f, "{:04x}", uuid.data);
+        }
+        let val = u128::from_be_bytes(self.0);
+        write!(
+            f,
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            val >> 96,
+            (val >> 80) & 0xFFFF,
+            (val >> 64) & 0xFFFF,
+            (val >> 48) & 0xFFFF,
+            val & 0xFFFF_FFFF_FFFF
+        )
+    }
+}
+
+/// Parses either the 4 or 8 hex digit short forms, or the full 8-4-4-4-12 form
+impl FromStr for Uuid {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.replace('-', "");
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("invalid UUID {s:?}");
+        }
+        match digits.len() {
+            4 | 8 if !s.contains('-') => Ok(Self::new(u32::from_str_radix(&digits, 16)?)),
+            32 if s.len() == 36 && [8, 13, 18, 23].iter().all(|i| s.as_bytes()[*i] == b'-') => {
+                Ok(Self(u128::from_str_radix(&digits, 16)?.to_be_bytes()))
+            }
+            _ => Err(anyhow!("invalid UUID {s:?}")),
+        }
+    }
+}
+
 impl From<Uuid16View<'_>> for Uuid {
     fn from(uuid: Uuid16View) -> Self {
         Self::new(uuid.get_data() as u32)
@@ -180,6 +239,41 @@ mod test {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn test_uuid_from_builder() {
+        let expected = Uuid::new(0x0102);
+        let actual = Uuid::try_from(&UuidBuilder::from(expected)).unwrap();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_uuid16_display_and_parse() {
+        let uuid = Uuid::new(0x2A19);
+        assert_eq!(uuid.to_string(), "2a19");
+        assert_eq!("2A19".parse::<Uuid>().unwrap(), uuid);
+    }
+
+    #[test]
+    fn test_uuid128_display_and_parse() {
+        let text = "12345678-9abc-def0-1234-56789abcdef0";
+        let uuid = text.parse::<Uuid>().unwrap();
+        assert_eq!(uuid.to_string(), text);
+        assert_eq!(uuid.le_bytes()[15], 0x12);
+    }
+
+    #[test]
+    fn test_uuid_parse_base_uuid_as_short() {
+        let uuid = "00002a19-0000-1000-8000-00805f9b34fb".parse::<Uuid>().unwrap();
+        assert_eq!(uuid, Uuid::new(0x2A19));
+    }
+
+    #[test]
+    fn test_uuid_parse_invalid() {
+        assert!("2a1".parse::<Uuid>().is_err());
+        assert!("zz19".parse::<Uuid>().is_err());
+        assert!("12345678-9abcdef0-1234-56789abcdef0".parse::<Uuid>().is_err());
+    }
+
     #[test]
     fn test_uuid_from_invalid_view() {
         let packet =
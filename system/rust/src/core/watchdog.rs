@@ -0,0 +1,372 @@
+//! This module supervises the main Rust thread. Callbacks posted to it are
+//! timed, and the depth of its queue is tracked, so that if a callback or future
+//! blocks the thread we log what it was doing (and optionally abort), rather
+//! than silently dropping all Rust GATT traffic.
+
+use std::{
+    fmt::{self, Display},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{error, info, warn};
+
+/// How often the supervising thread checks the thresholds, and how often the
+/// event loop reports that it is alive
+pub const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The thresholds above which the main thread is considered stalled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    /// How long a single callback, or the event loop as a whole, may go
+    /// without yielding (if limited)
+    pub stall_threshold: Option<Duration>,
+    /// How many callbacks may be waiting to run on the main thread (if limited)
+    pub queue_depth_threshold: Option<usize>,
+    /// Whether to abort the process after reporting a stall
+    pub abort_on_stall: bool,
+}
+
+impl WatchdogConfig {
+    /// Whether any threshold is set, so the main thread needs supervising
+    pub fn is_enabled(&self) -> bool {
+        self.stall_threshold.is_some() || self.queue_depth_threshold.is_some()
+    }
+}
+
+/// Why the main thread was considered stalled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StallReason {
+    /// A callback has been running for longer than the stall threshold
+    SlowCallback,
+    /// The event loop has not run for longer than the stall threshold, but no
+    /// callback is running (e.g. a future is blocking the thread)
+    Unresponsive,
+    /// Too many callbacks are waiting to run
+    QueueBacklog,
+}
+
+/// The state of the main thread when a stall was detected
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StallReport {
+    /// Why the thread was considered stalled
+    pub reason: StallReason,
+    /// The callback that was running (if any), and for how long
+    pub current_callback: Option<(&'static str, Duration)>,
+    /// How long ago the event loop last made progress
+    pub since_heartbeat: Duration,
+    /// The number of callbacks waiting to run
+    pub queue_depth: usize,
+}
+
+impl Display for StallReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Rust main thread stalled: reason={:?}", self.reason)?;
+        match self.current_callback {
+            Some((name, running_for)) => {
+                write!(f, " current_callback={name} running_for={running_for:?}")?
+            }
+            None => write!(f, " current_callback=none")?,
+        }
+        write!(f, " since_heartbeat={:?} queue_depth={}", self.since_heartbeat, self.queue_depth)
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    /// The callback currently running on the main thread, and when it started
+    current_callback: Option<(&'static str, Instant)>,
+    /// The last time the event loop made progress
+    last_heartbeat: Instant,
+    /// Whether the ongoing stall (if any) has already been reported
+    reported: bool,
+}
+
+/// Tracks the activity of the main thread. It is shared between the threads
+/// posting callbacks, the main thread itself, and the supervising thread.
+#[derive(Debug)]
+pub struct Watchdog {
+    config: WatchdogConfig,
+    queue_depth: AtomicUsize,
+    stopped: AtomicBool,
+    state: Mutex<State>,
+}
+
+impl Watchdog {
+    /// Constructor
+    pub fn new(config: WatchdogConfig) -> Self {
+        Self {
+            config,
+            queue_depth: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+            state: Mutex::new(State {
+                current_callback: None,
+                last_heartbeat: Instant::now(),
+                reported: false,
+            }),
+        }
+    }
+
+    /// The thresholds of this watchdog
+    pub fn config(&self) -> WatchdogConfig {
+        self.config
+    }
+
+    /// Record that a callback was posted to the main thread
+    pub fn on_callback_queued(&self) {
+        self.queue_depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record that a callback was dequeued, and is about to run
+    pub fn on_callback_started(&self, name: &'static str) {
+        let _ = self.queue_depth.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |depth| {
+            Some(depth.saturating_sub(1))
+        });
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        state.current_callback = Some((name, now));
+        state.last_heartbeat = now;
+    }
+
+    /// Record that the running callback has returned. Returns how long it ran.
+    pub fn on_callback_finished(&self) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        state.last_heartbeat = now;
+        let Some((name, started)) = state.current_callback.take() else {
+            warn!("callback finished, but none was running");
+            return Duration::ZERO;
+        };
+        let elapsed = now - started;
+        if matches!(self.config.stall_threshold, Some(threshold) if elapsed > threshold) {
+            warn!("callback {name} blocked the main thread for {elapsed:?}");
+        }
+        elapsed
+    }
+
+    /// Record that the event loop is making progress
+    pub fn heartbeat(&self) {
+        self.state.lock().unwrap().last_heartbeat = Instant::now();
+    }
+
+    /// The number of callbacks waiting to run
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth.load(Ordering::Relaxed)
+    }
+
+    /// Check the thresholds as of the given time. Returns a report if the main
+    /// thread is stalled, and this stall has not been reported yet.
+    pub fn check(&self, now: Instant) -> Option<StallReport> {
+        let mut state = self.state.lock().unwrap();
+        let queue_depth = self.queue_depth();
+        let current_callback = state
+            .current_callback
+            .map(|(name, started)| (name, now.saturating_duration_since(started)));
+        let since_heartbeat = now.saturating_duration_since(state.last_heartbeat);
+
+        let reason = if let Some(threshold) = self.config.stall_threshold {
+            match current_callback {
+                Some((_, running_for)) if running_for > threshold => {
+                    Some(StallReason::SlowCallback)
+                }
+                // allow for the heartbeat itself only running every CHECK_INTERVAL
+                None if since_heartbeat > threshold + CHECK_INTERVAL => {
+                    Some(StallReason::Unresponsive)
+                }
+                _ => None,
+            }
+        } else {
+            None
+        };
+        let reason = reason.or(match self.config.queue_depth_threshold {
+            Some(threshold) if queue_depth > threshold => Some(StallReason::QueueBacklog),
+            _ => None,
+        });
+
+        let Some(reason) = reason else {
+            state.reported = false;
+            return None;
+        };
+        if state.reported {
+            return None;
+        }
+        state.reported = true;
+        Some(StallReport { reason, current_callback, since_heartbeat, queue_depth })
+    }
+
+    /// Stop supervising the main thread
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    fn on_stall(&self, report: StallReport) {
+        error!("{report}");
+        if self.config.abort_on_stall {
+            error!("aborting since the Rust main thread is stalled");
+            std::process::abort();
+        }
+    }
+}
+
+/// Spawn a thread that checks on the main thread every CHECK_INTERVAL, until
+/// the watchdog is stopped
+pub fn spawn_supervisor(watchdog: Arc<Watchdog>) {
+    info!("supervising the Rust main thread with {:?}", watchdog.config);
+    thread::spawn(move || {
+        while !watchdog.stopped.load(Ordering::Relaxed) {
+            thread::sleep(CHECK_INTERVAL);
+            if let Some(report) = watchdog.check(Instant::now()) {
+                watchdog.on_stall(report);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const THRESHOLD: Duration = Duration::from_secs(1);
+    const CALLBACK: &str = "bluetooth_core::test::callback";
+
+    fn watchdog() -> Watchdog {
+        Watchdog::new(WatchdogConfig {
+            stall_threshold: Some(THRESHOLD),
+            queue_depth_threshold: Some(2),
+            abort_on_stall: false,
+        })
+    }
+
+    #[test]
+    fn test_no_stall() {
+        // arrange
+        let watchdog = watchdog();
+        watchdog.on_callback_queued();
+        watchdog.on_callback_started(CALLBACK);
+
+        // act
+        let report = watchdog.check(Instant::now());
+
+        // assert
+        assert!(report.is_none());
+    }
+
+    #[test]
+    fn test_slow_callback() {
+        // arrange
+        let watchdog = watchdog();
+        watchdog.on_callback_queued();
+        watchdog.on_callback_started(CALLBACK);
+
+        // act
+        let report = watchdog.check(Instant::now() + THRESHOLD * 2).unwrap();
+
+        // assert
+        assert_eq!(report.reason, StallReason::SlowCallback);
+        assert_eq!(report.current_callback.unwrap().0, CALLBACK);
+        assert!(report.current_callback.unwrap().1 > THRESHOLD);
+        assert_eq!(report.queue_depth, 0);
+    }
+
+    #[test]
+    fn test_unresponsive() {
+        // arrange
+        let watchdog = watchdog();
+        watchdog.heartbeat();
+
+        // act
+        let report = watchdog.check(Instant::now() + THRESHOLD * 2).unwrap();
+
+        // assert
+        assert_eq!(report.reason, StallReason::Unresponsive);
+        assert!(report.current_callback.is_none());
+    }
+
+    #[test]
+    fn test_queue_backlog() {
+        // arrange
+        let watchdog = watchdog();
+        for _ in 0..3 {
+            watchdog.on_callback_queued();
+        }
+
+        // act
+        let report = watchdog.check(Instant::now()).unwrap();
+
+        // assert
+        assert_eq!(report.reason, StallReason::QueueBacklog);
+        assert_eq!(report.queue_depth, 3);
+    }
+
+    #[test]
+    fn test_stall_reported_once() {
+        // arrange
+        let watchdog = watchdog();
+        watchdog.on_callback_started(CALLBACK);
+        watchdog.check(Instant::now() + THRESHOLD * 2).unwrap();
+
+        // act
+        let report = watchdog.check(Instant::now() + THRESHOLD * 3);
+
+        // assert
+        assert!(report.is_none());
+    }
+
+    #[test]
+    fn test_new_stall_reported_after_recovery() {
+        // arrange: a stall that is reported, then recovers
+        let watchdog = watchdog();
+        watchdog.on_callback_started(CALLBACK);
+        watchdog.check(Instant::now() + THRESHOLD * 2).unwrap();
+        watchdog.on_callback_finished();
+        assert!(watchdog.check(Instant::now()).is_none());
+
+        // act: stall again
+        watchdog.on_callback_started(CALLBACK);
+        let report = watchdog.check(Instant::now() + THRESHOLD * 2);
+
+        // assert
+        assert!(report.is_some());
+    }
+
+    #[test]
+    fn test_disabled_thresholds() {
+        // arrange
+        let watchdog = Watchdog::new(WatchdogConfig::default());
+        for _ in 0..3 {
+            watchdog.on_callback_queued();
+        }
+        watchdog.on_callback_started(CALLBACK);
+
+        // act
+        let report = watchdog.check(Instant::now() + THRESHOLD * 2);
+
+        // assert
+        assert!(report.is_none());
+        assert!(!WatchdogConfig::default().is_enabled());
+    }
+
+    #[test]
+    fn test_report_display() {
+        // arrange
+        let report = StallReport {
+            reason: StallReason::SlowCallback,
+            current_callback: Some((CALLBACK, Duration::from_secs(2))),
+            since_heartbeat: Duration::from_secs(2),
+            queue_depth: 5,
+        };
+
+        // act
+        let formatted = report.to_string();
+
+        // assert
+        assert_eq!(
+            formatted,
+            "Rust main thread stalled: reason=SlowCallback current_callback=bluetooth_core::test::callback running_for=2s since_heartbeat=2s queue_depth=5"
+        );
+    }
+}
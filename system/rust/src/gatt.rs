@@ -1,9 +1,11 @@
-//! This module is a simple GATT server that shares the ATT channel with the
-//! existing C++ GATT client. See go/private-gatt-in-platform for the design.
+//! This module is a simple GATT server and client that share the ATT channel
+//! with the existing C++ GATT stack. See go/private-gatt-in-platform for the
+//! design.
 
 pub mod arbiter;
 pub mod callbacks;
 pub mod channel;
+pub mod client;
 pub mod ffi;
 pub mod ids;
 pub mod mocks;
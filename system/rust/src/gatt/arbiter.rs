@@ -1,31 +1,87 @@
 //! This module handles "arbitration" of ATT packets, to determine whether they
 //! should be handled by the primary stack or by the "Private GATT" stack
 
-use std::{collections::HashMap, sync::Mutex};
+use std::{
+    collections::HashMap,
+    ops::RangeInclusive,
+    sync::{Arc, Mutex},
+};
 
-use log::{error, info, trace};
+use bt_common::init_flags::private_gatt_client_is_enabled;
+use log::{error, info, trace, warn};
 
 use crate::{
+    core::{address::AddressWithType, uuid::Uuid},
     do_in_rust_thread,
-    packets::{AttOpcode, OwnedAttView, OwnedPacket},
+    packets::{
+        AttFindByTypeValueRequestView, AttFindInformationRequestView, AttHandleView, AttOpcode,
+        AttReadBlobRequestView, AttReadByGroupTypeRequestView, AttReadByTypeRequestView,
+        AttReadRequestView, AttView, AttWriteCommandView, AttWriteRequestView, OwnedAttView,
+        OwnedPacket, Packet,
+    },
+    ModuleViews,
 };
 
 use super::{
-    ffi::{InterceptAction, StoreCallbacksFromRust},
-    ids::{AdvertiserId, ConnectionId, ServerId, TransportIndex},
+    client::{att_client_bearer::ClientRouting, GattClient},
+    ffi::{GetSharedGapServiceHandles, InterceptAction, StoreCallbacksFromRust},
+    ids::{AdvertiserId, AttHandle, ConnectionId, ServerId, TransportIndex},
     mtu::MtuEvent,
     opcode_types::{classify_opcode, OperationType},
+    server::services::gap::{
+        CENTRAL_ADDRESS_RESOLUTION_UUID, DEVICE_APPEARANCE_UUID, DEVICE_NAME_UUID,
+        PREFERRED_CONNECTION_PARAMETERS_UUID, RPA_ONLY_UUID,
+    },
 };
 
 static ARBITER: Mutex<Option<Arbiter>> = Mutex::new(None);
 
+/// The characteristics of the GAP service. Reads by type of these values are
+/// served by the shared database, even if the peer searches a wider range.
+const GAP_CHARACTERISTIC_UUIDS: [Uuid; 5] = [
+    DEVICE_NAME_UUID,
+    DEVICE_APPEARANCE_UUID,
+    PREFERRED_CONNECTION_PARAMETERS_UUID,
+    CENTRAL_ADDRESS_RESOLUTION_UUID,
+    RPA_ONLY_UUID,
+];
+
+/// How connections to an advertiser with associated servers are isolated from
+/// the shared (legacy) GATT database
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IsolationPolicy {
+    /// The peer only sees the databases of the associated servers
+    #[default]
+    Isolated,
+    /// The peer sees the databases of the associated servers, but reads and
+    /// writes of the GAP service are handled by the shared database (so the
+    /// peer sees the real device name, etc.). Note that the GATT service is
+    /// never shared, since its Service Changed and Database Hash characteristics
+    /// must describe the database the peer actually discovers.
+    IsolatedWithSharedServices,
+}
+
+/// A connection owned by the Rust stack
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct OwnedConnection {
+    /// The conn_id of the primary server, which owns the bearer
+    conn_id: ConnectionId,
+    /// All servers whose databases are visible on this connection, primary first
+    servers: Vec<ServerId>,
+    /// The isolation policy of the advertiser the peer connected to
+    policy: IsolationPolicy,
+}
+
 /// This class is responsible for tracking which connections and advertising we
 /// own, and using this information to decide what packets should be
 /// intercepted, and which should be forwarded to the legacy stack.
 #[derive(Default)]
 pub struct Arbiter {
-    advertiser_to_server: HashMap<AdvertiserId, ServerId>,
-    transport_to_owned_connection: HashMap<TransportIndex, ConnectionId>,
+    advertiser_to_servers: HashMap<AdvertiserId, Vec<ServerId>>,
+    advertiser_policies: HashMap<AdvertiserId, IsolationPolicy>,
+    transport_to_owned_connection: HashMap<TransportIndex, OwnedConnection>,
+    client_routing: HashMap<TransportIndex, Arc<ClientRouting>>,
+    shared_service_handles: Option<RangeInclusive<AttHandle>>,
 }
 
 /// Initialize the Arbiter
@@ -35,6 +91,8 @@ pub fn initialize_arbiter() {
     StoreCallbacksFromRust(
         on_le_connect,
         on_le_disconnect,
+        on_le_bonded,
+        on_bond_removed,
         intercept_packet,
         |tcb_idx| on_mtu_event(TransportIndex(tcb_idx), MtuEvent::OutgoingRequest),
         |tcb_idx, mtu| on_mtu_event(TransportIndex(tcb_idx), MtuEvent::IncomingResponse(mtu)),
@@ -52,41 +110,102 @@ impl Arbiter {
     /// Constructor
     pub fn new() -> Self {
         Arbiter {
-            advertiser_to_server: HashMap::new(),
+            advertiser_to_servers: HashMap::new(),
+            advertiser_policies: HashMap::new(),
             transport_to_owned_connection: HashMap::new(),
+            client_routing: HashMap::new(),
+            shared_service_handles: None,
+        }
+    }
+
+    /// Set the handles of the GAP service in the shared (legacy) database,
+    /// which serves it to connections with
+    /// IsolationPolicy::IsolatedWithSharedServices. If None, the GAP service
+    /// of the isolated databases is used instead.
+    pub fn set_shared_service_handles(&mut self, handles: Option<RangeInclusive<AttHandle>>) {
+        if self.shared_service_handles != handles {
+            info!("shared GAP service handles set to {handles:?}");
+            self.shared_service_handles = handles;
         }
     }
 
     /// Link a given GATT server to an LE advertising set, so incoming
     /// connections to this advertiser will be visible only by the linked
-    /// server
+    /// server(s). If several servers are linked to the same advertiser, the
+    /// peer sees all of their databases, and the first server linked owns the
+    /// connection.
     pub fn associate_server_with_advertiser(
         &mut self,
         server_id: ServerId,
         advertiser_id: AdvertiserId,
     ) {
         info!("associating server {server_id:?} with advertising set {advertiser_id:?}");
-        let old = self.advertiser_to_server.insert(advertiser_id, server_id);
-        if let Some(old) = old {
-            error!("new server {server_id:?} associated with same advertiser {advertiser_id:?}, displacing old server {old:?}");
+        let servers = self.advertiser_to_servers.entry(advertiser_id).or_default();
+        if servers.contains(&server_id) {
+            warn!("server {server_id:?} is already associated with advertiser {advertiser_id:?}");
+            return;
         }
+        servers.push(server_id);
+    }
+
+    /// Set how connections to this advertiser are isolated from the shared
+    /// database. This applies to connections made after this call.
+    pub fn set_isolation_policy(&mut self, advertiser_id: AdvertiserId, policy: IsolationPolicy) {
+        info!("setting isolation policy of advertiser {advertiser_id:?} to {policy:?}");
+        self.advertiser_policies.insert(advertiser_id, policy);
     }
 
     /// Remove all linked advertising sets from the provided server
     pub fn clear_server(&mut self, server_id: ServerId) {
         info!("clearing advertisers associated with {server_id:?}");
-        self.advertiser_to_server.retain(|_, server| *server != server_id);
+        for servers in self.advertiser_to_servers.values_mut() {
+            servers.retain(|server| *server != server_id);
+        }
+        self.advertiser_to_servers.retain(|_, servers| !servers.is_empty());
     }
 
-    /// Clear the server associated with this advertiser, if one exists
+    /// Clear the servers associated with this advertiser (and its isolation
+    /// policy), if any exist
     pub fn clear_advertiser(&mut self, advertiser_id: AdvertiserId) {
-        info!("removing server (if any) associated with advertiser {advertiser_id:?}");
-        self.advertiser_to_server.remove(&advertiser_id);
+        info!("removing servers (if any) associated with advertiser {advertiser_id:?}");
+        self.advertiser_to_servers.remove(&advertiser_id);
+        self.advertiser_policies.remove(&advertiser_id);
     }
 
-    /// Check if this conn_id is currently owned by the Rust stack
+    /// Check if this conn_id is currently owned by the Rust stack (either by
+    /// the primary server of the connection, or by a server sharing it)
     pub fn is_connection_isolated(&self, conn_id: ConnectionId) -> bool {
-        self.transport_to_owned_connection.values().any(|owned_conn_id| *owned_conn_id == conn_id)
+        self.transport_to_owned_connection
+            .get(&conn_id.get_tcb_idx())
+            .map(|connection| connection.servers.contains(&conn_id.get_server_id()))
+            .unwrap_or(false)
+    }
+
+    /// The servers whose databases are visible on this connection (primary
+    /// first), if it is owned by the Rust stack
+    pub fn get_servers(&self, tcb_idx: TransportIndex) -> Vec<ServerId> {
+        self.transport_to_owned_connection
+            .get(&tcb_idx)
+            .map(|connection| connection.servers.clone())
+            .unwrap_or_default()
+    }
+
+    /// The transports whose connection is owned by this server (i.e. it is
+    /// their primary server)
+    pub fn get_transports_owned_by(&self, server_id: ServerId) -> Vec<TransportIndex> {
+        self.transport_to_owned_connection
+            .iter()
+            .filter(|(_, connection)| connection.conn_id.get_server_id() == server_id)
+            .map(|(tcb_idx, _)| *tcb_idx)
+            .collect()
+    }
+
+    /// Hand an isolated connection over to the shared database, so that all
+    /// further ATT traffic is handled by the legacy stack. Returns the conn_id
+    /// that owned it, if it was isolated.
+    pub fn hand_over_connection(&mut self, tcb_idx: TransportIndex) -> Option<ConnectionId> {
+        info!("handing over transport {tcb_idx:?} to the shared database");
+        self.transport_to_owned_connection.remove(&tcb_idx).map(|connection| connection.conn_id)
     }
 
     /// Test to see if a buffer contains a valid ATT packet with an opcode we
@@ -96,9 +215,7 @@ impl Arbiter {
         tcb_idx: TransportIndex,
         packet: Box<[u8]>,
     ) -> Option<OwnedAttView> {
-        if !self.transport_to_owned_connection.contains_key(&tcb_idx) {
-            return None;
-        }
+        let connection = self.transport_to_owned_connection.get(&tcb_idx)?;
 
         let att = OwnedAttView::try_parse(packet).ok()?;
 
@@ -108,6 +225,13 @@ impl Arbiter {
             return None;
         }
 
+        if connection.policy == IsolationPolicy::IsolatedWithSharedServices
+            && self.targets_shared_service(att.view())
+        {
+            // the shared database serves the GAP service
+            return None;
+        }
+
         match classify_opcode(att.view().get_opcode()) {
             OperationType::Command | OperationType::Request | OperationType::Confirmation => {
                 Some(att)
@@ -116,6 +240,58 @@ impl Arbiter {
         }
     }
 
+    /// Check if this request only concerns the shared GAP service, and so
+    /// should be handled by the shared database
+    fn targets_shared_service(&self, packet: AttView<'_>) -> bool {
+        let Some(shared) = &self.shared_service_handles else {
+            return false;
+        };
+        let Some((range, type_)) = get_target_range(packet) else {
+            return false;
+        };
+        if shared.contains(range.start()) && shared.contains(range.end()) {
+            return true;
+        }
+        // e.g. a read by type of the Device Name over the whole database
+        matches!(type_, Some(type_) if GAP_CHARACTERISTIC_UUIDS.contains(&type_))
+            && range.start() <= shared.end()
+            && shared.start() <= range.end()
+    }
+
+    /// Test to see if a buffer contains a valid ATT packet we are interested
+    /// in intercepting (those intended for the Rust GATT client, i.e. the
+    /// response to its outstanding request or a value update it listens to)
+    pub fn try_parse_att_client_packet(
+        &self,
+        tcb_idx: TransportIndex,
+        packet: &[u8],
+    ) -> Option<OwnedAttView> {
+        let routing = self.client_routing.get(&tcb_idx)?;
+        let att = OwnedAttView::try_parse(packet.into()).ok()?;
+        routing.accepts(att.view()).then_some(att)
+    }
+
+    /// Let the Rust GATT client share the client role of this transport with
+    /// the legacy client. Only the packets accepted by its routing are
+    /// intercepted.
+    pub fn claim_client_transport(&mut self, tcb_idx: TransportIndex, routing: Arc<ClientRouting>) {
+        info!("Rust GATT client claiming transport {tcb_idx:?}");
+        if self.client_routing.insert(tcb_idx, routing).is_some() {
+            error!("Rust GATT client already claimed transport {tcb_idx:?}");
+        }
+    }
+
+    /// Check if the Rust GATT client shares the client role of this transport
+    pub fn owns_client_transport(&self, tcb_idx: TransportIndex) -> bool {
+        self.client_routing.contains_key(&tcb_idx)
+    }
+
+    /// Return the client role of this transport to the legacy stack, and
+    /// return whether it was shared with the Rust GATT client
+    pub fn release_client_transport(&mut self, tcb_idx: TransportIndex) -> bool {
+        self.client_routing.remove(&tcb_idx).is_some()
+    }
+
     /// Check if an incoming connection should be intercepted and, if so, on
     /// what conn_id
     pub fn on_le_connect(
@@ -126,11 +302,15 @@ impl Arbiter {
         info!(
             "processing incoming connection on transport {tcb_idx:?} to advertiser {advertiser:?}"
         );
-        let server_id = *self.advertiser_to_server.get(&advertiser)?;
-        info!("connection is isolated to server {server_id:?}");
+        let servers = self.advertiser_to_servers.get(&advertiser)?.clone();
+        let server_id = *servers.first()?;
+        let policy = self.advertiser_policies.get(&advertiser).copied().unwrap_or_default();
+        info!("connection is isolated to servers {servers:?} with policy {policy:?}");
 
         let conn_id = ConnectionId::new(tcb_idx, server_id);
-        let old = self.transport_to_owned_connection.insert(tcb_idx, conn_id);
+        let old = self
+            .transport_to_owned_connection
+            .insert(tcb_idx, OwnedConnection { conn_id, servers, policy });
         if old.is_some() {
             error!("new server {server_id:?} on transport {tcb_idx:?} displacing existing registered connection {conn_id:?}")
         }
@@ -145,19 +325,112 @@ impl Arbiter {
 
     /// Look up the conn_id for a given tcb_idx, if present
     pub fn get_conn_id(&self, tcb_idx: TransportIndex) -> Option<ConnectionId> {
-        self.transport_to_owned_connection.get(&tcb_idx).copied()
+        self.transport_to_owned_connection.get(&tcb_idx).map(|connection| connection.conn_id)
+    }
+}
+
+/// The range of attributes targeted by this request, and the attribute type
+/// it reads (for Read By Type requests), if any
+fn get_target_range(packet: AttView<'_>) -> Option<(RangeInclusive<AttHandle>, Option<Uuid>)> {
+    let handle_range =
+        |start: AttHandleView<'_>, end: AttHandleView<'_>| AttHandle::from(start)..=end.into();
+    let single = |handle: AttHandle| (handle..=handle, None);
+    match packet.get_opcode() {
+        AttOpcode::READ_REQUEST => AttReadRequestView::try_parse(packet)
+            .ok()
+            .map(|request| single(request.get_attribute_handle().into())),
+        AttOpcode::READ_BLOB_REQUEST => AttReadBlobRequestView::try_parse(packet)
+            .ok()
+            .map(|request| single(request.get_attribute_handle().into())),
+        AttOpcode::WRITE_REQUEST => AttWriteRequestView::try_parse(packet)
+            .ok()
+            .map(|request| single(request.get_handle().into())),
+        AttOpcode::WRITE_COMMAND => AttWriteCommandView::try_parse(packet)
+            .ok()
+            .map(|request| single(request.get_handle().into())),
+        AttOpcode::FIND_INFORMATION_REQUEST => {
+            AttFindInformationRequestView::try_parse(packet).ok().map(|request| {
+                (handle_range(request.get_starting_handle(), request.get_ending_handle()), None)
+            })
+        }
+        AttOpcode::FIND_BY_TYPE_VALUE_REQUEST => {
+            AttFindByTypeValueRequestView::try_parse(packet).ok().map(|request| {
+                (handle_range(request.get_starting_handle(), request.get_ending_handle()), None)
+            })
+        }
+        AttOpcode::READ_BY_GROUP_TYPE_REQUEST => {
+            AttReadByGroupTypeRequestView::try_parse(packet).ok().map(|request| {
+                (handle_range(request.get_starting_handle(), request.get_ending_handle()), None)
+            })
+        }
+        AttOpcode::READ_BY_TYPE_REQUEST => {
+            let request = AttReadByTypeRequestView::try_parse(packet).ok()?;
+            Some((
+                handle_range(request.get_starting_handle(), request.get_ending_handle()),
+                request.get_attribute_type().try_into().ok(),
+            ))
+        }
+        _ => None,
     }
 }
 
+/// The handles of the GAP service in the shared database, if registered
+fn shared_gap_service_handles() -> Option<RangeInclusive<AttHandle>> {
+    let (mut start_handle, mut end_handle) = (0, 0);
+    GetSharedGapServiceHandles(&mut start_handle, &mut end_handle)
+        .then(|| AttHandle(start_handle)..=AttHandle(end_handle))
+}
+
 fn on_le_connect(tcb_idx: u8, advertiser: u8) {
-    if let Some(conn_id) = with_arbiter(|arbiter| {
-        arbiter.on_le_connect(TransportIndex(tcb_idx), AdvertiserId(advertiser))
+    let tcb_idx = TransportIndex(tcb_idx);
+    let shared_service_handles = shared_gap_service_handles();
+    if let Some((conn_id, servers)) = with_arbiter(|arbiter| {
+        arbiter.set_shared_service_handles(shared_service_handles);
+        let conn_id = arbiter.on_le_connect(tcb_idx, AdvertiserId(advertiser))?;
+        Some((conn_id, arbiter.get_servers(tcb_idx)))
     }) {
         do_in_rust_thread(move |modules| {
-            if let Err(err) = modules.gatt_module.on_le_connect(conn_id) {
+            if let Err(err) = modules.gatt_module.on_le_connect_shared(conn_id, &servers[1..]) {
+                error!("{err:?}")
+            }
+        })
+    }
+}
+
+/// Get a handle to run Rust GATT client procedures on this transport, sharing
+/// its client role with the legacy client. The transport is only claimed once
+/// a procedure needs it, and is released when it disconnects.
+pub fn open_client_transport(
+    modules: &mut ModuleViews,
+    tcb_idx: TransportIndex,
+) -> Option<GattClient> {
+    if !private_gatt_client_is_enabled() {
+        warn!("cannot open client on {tcb_idx:?} since the Rust GATT client is disabled");
+        return None;
+    }
+    if modules.gatt_client_module.get_bearer(tcb_idx).is_none() {
+        if let Err(err) = modules.gatt_client_module.on_le_connect(tcb_idx) {
+            error!("{err:?}");
+            return None;
+        }
+        let routing = modules.gatt_client_module.get_bearer(tcb_idx)?.routing();
+        with_arbiter(|arbiter| arbiter.claim_client_transport(tcb_idx, routing));
+    }
+    modules.gatt_client_module.get_client(tcb_idx)
+}
+
+/// Hand an isolated connection over to the shared database, tearing down its
+/// bearer in the Rust stack
+pub fn hand_over_connection(tcb_idx: TransportIndex) {
+    if with_arbiter(|arbiter| arbiter.hand_over_connection(tcb_idx)).is_some() {
+        do_in_rust_thread(move |modules| {
+            if let Err(err) = modules.gatt_module.on_le_disconnect(tcb_idx) {
                 error!("{err:?}")
             }
+            modules.gatt_incoming_callbacks.on_le_disconnect(tcb_idx);
         })
+    } else {
+        warn!("cannot hand over {tcb_idx:?} since it is not isolated");
     }
 }
 
@@ -168,12 +441,47 @@ fn on_le_disconnect(tcb_idx: u8) {
             if let Err(err) = modules.gatt_module.on_le_disconnect(tcb_idx) {
                 error!("{err:?}")
             }
+            modules.gatt_incoming_callbacks.on_le_disconnect(tcb_idx);
+        })
+    }
+    if with_arbiter(|arbiter| arbiter.release_client_transport(tcb_idx)) {
+        do_in_rust_thread(move |modules| {
+            if let Err(err) = modules.gatt_client_module.on_le_disconnect(tcb_idx) {
+                error!("{err:?}")
+            }
+        })
+    }
+}
+
+fn on_le_bonded(tcb_idx: u8, identity: AddressWithType) {
+    let tcb_idx = TransportIndex(tcb_idx);
+    if with_arbiter(|arbiter| arbiter.get_conn_id(tcb_idx)).is_some() {
+        do_in_rust_thread(move |modules| {
+            if let Err(err) = modules.gatt_module.on_le_bonded(tcb_idx, identity) {
+                error!("{err:?}")
+            }
         })
     }
 }
 
+fn on_bond_removed(identity: AddressWithType) {
+    do_in_rust_thread(move |modules| modules.gatt_module.on_bond_removed(identity))
+}
+
 fn intercept_packet(tcb_idx: u8, packet: Vec<u8>) -> InterceptAction {
     let tcb_idx = TransportIndex(tcb_idx);
+    if let Some(att) = with_arbiter(|arbiter| arbiter.try_parse_att_client_packet(tcb_idx, &packet))
+    {
+        do_in_rust_thread(move |modules| {
+            trace!("pushing packet to GATT client");
+            if let Some(bearer) = modules.gatt_client_module.get_bearer(tcb_idx) {
+                bearer.handle_packet(att.view())
+            } else {
+                error!("Client bearer for {tcb_idx:?} not found");
+            }
+        });
+        return InterceptAction::Drop;
+    }
     if let Some(att) = with_arbiter(|arbiter| {
         arbiter.try_parse_att_server_packet(tcb_idx, packet.into_boxed_slice())
     }) {
@@ -203,24 +511,54 @@ fn on_mtu_event(tcb_idx: TransportIndex, event: MtuEvent) {
             }
         });
     }
+    // the MTU is shared between the client + server, so the client needs it too.
+    // It never waits on exchanges it didn't start, only takes the MTU they settle on.
+    let client_event = match event {
+        MtuEvent::OutgoingRequest => None,
+        MtuEvent::IncomingResponse(mtu) | MtuEvent::IncomingRequest(mtu) => {
+            Some(MtuEvent::IncomingRequest(mtu))
+        }
+    };
+    if let Some(event) =
+        client_event.filter(|_| with_arbiter(|arbiter| arbiter.owns_client_transport(tcb_idx)))
+    {
+        do_in_rust_thread(move |modules| {
+            let Some(bearer) = modules.gatt_client_module.get_bearer(tcb_idx) else {
+                error!("Client bearer for {tcb_idx:?} not found");
+                return;
+            };
+            if let Err(err) = bearer.handle_mtu_event(event) {
+                error!("{err:?}")
+            }
+        });
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    use tokio::sync::mpsc::UnboundedReceiver;
+
     use crate::{
-        gatt::ids::AttHandle,
+        gatt::{
+            client::att_client_bearer::{AttClientBearer, AttValueUpdate, ValueUpdateKind},
+            ids::AttHandle,
+        },
         packets::{
-            AttBuilder, AttExchangeMtuRequestBuilder, AttOpcode, AttReadRequestBuilder,
-            Serializable,
+            AttAttributeDataChild, AttBuilder, AttExchangeMtuRequestBuilder,
+            AttFindInformationRequestBuilder, AttHandleValueNotificationBuilder, AttOpcode,
+            AttReadByTypeRequestBuilder, AttReadRequestBuilder, AttReadResponseBuilder,
+            AttWriteRequestBuilder, Serializable,
         },
+        utils::packet::build_att_data,
     };
 
     const TCB_IDX: TransportIndex = TransportIndex(1);
     const ANOTHER_TCB_IDX: TransportIndex = TransportIndex(2);
     const ADVERTISER_ID: AdvertiserId = AdvertiserId(3);
     const SERVER_ID: ServerId = ServerId(4);
+    const ANOTHER_SERVER_ID: ServerId = ServerId(6);
 
     const CONN_ID: ConnectionId = ConnectionId::new(TCB_IDX, SERVER_ID);
 
@@ -474,4 +812,362 @@ mod test {
         assert!(conn_id.is_none());
         assert!(!arbiter.is_connection_isolated(CONN_ID));
     }
+
+    fn claim_client_transport_listening_to(
+        arbiter: &mut Arbiter,
+        handle: AttHandle,
+    ) -> UnboundedReceiver<AttValueUpdate> {
+        let bearer = AttClientBearer::new(|_| Ok(()));
+        let listener = bearer.register_value_listener(handle, ValueUpdateKind::Notification);
+        arbiter.claim_client_transport(TCB_IDX, bearer.routing());
+        listener
+    }
+
+    fn build_notification(handle: AttHandle) -> Box<[u8]> {
+        AttBuilder {
+            opcode: AttOpcode::HANDLE_VALUE_NOTIFICATION,
+            _child_: AttHandleValueNotificationBuilder {
+                handle: handle.into(),
+                value: build_att_data(AttAttributeDataChild::RawData([1].into())),
+            }
+            .into(),
+        }
+        .to_vec()
+        .unwrap()
+        .into()
+    }
+
+    fn build_read_response() -> Box<[u8]> {
+        AttBuilder {
+            opcode: AttOpcode::READ_RESPONSE,
+            _child_: AttReadResponseBuilder {
+                value: build_att_data(AttAttributeDataChild::RawData([1].into())),
+            }
+            .into(),
+        }
+        .to_vec()
+        .unwrap()
+        .into()
+    }
+
+    #[test]
+    fn test_client_notification_capture_when_listened() {
+        let mut arbiter = Arbiter::new();
+        let _listener = claim_client_transport_listening_to(&mut arbiter, AttHandle(3));
+
+        let out = arbiter.try_parse_att_client_packet(TCB_IDX, &build_notification(AttHandle(3)));
+
+        assert!(out.is_some());
+    }
+
+    #[test]
+    fn test_client_notification_bypass_when_not_listened() {
+        let mut arbiter = Arbiter::new();
+        let _listener = claim_client_transport_listening_to(&mut arbiter, AttHandle(3));
+
+        let out = arbiter.try_parse_att_client_packet(TCB_IDX, &build_notification(AttHandle(4)));
+
+        assert!(out.is_none());
+    }
+
+    #[test]
+    fn test_client_response_bypass_without_own_request() {
+        let mut arbiter = Arbiter::new();
+        let _listener = claim_client_transport_listening_to(&mut arbiter, AttHandle(3));
+
+        let out = arbiter.try_parse_att_client_packet(TCB_IDX, &build_read_response());
+
+        assert!(out.is_none());
+    }
+
+    #[test]
+    fn test_client_request_bypass_when_claimed() {
+        let mut arbiter = Arbiter::new();
+        let _listener = claim_client_transport_listening_to(&mut arbiter, AttHandle(3));
+        let packet = AttBuilder {
+            opcode: AttOpcode::READ_REQUEST,
+            _child_: AttReadRequestBuilder { attribute_handle: AttHandle(3).into() }.into(),
+        };
+
+        let out = arbiter.try_parse_att_client_packet(TCB_IDX, &packet.to_vec().unwrap());
+
+        assert!(out.is_none());
+    }
+
+    #[test]
+    fn test_client_packet_bypass_when_not_claimed() {
+        let mut arbiter = Arbiter::new();
+
+        let out = arbiter.try_parse_att_client_packet(TCB_IDX, &build_notification(AttHandle(3)));
+
+        assert!(out.is_none());
+        assert!(!arbiter.owns_client_transport(TCB_IDX));
+    }
+
+    #[test]
+    fn test_client_packet_bypass_after_release() {
+        let mut arbiter = Arbiter::new();
+        let _listener = claim_client_transport_listening_to(&mut arbiter, AttHandle(3));
+        let released = arbiter.release_client_transport(TCB_IDX);
+
+        let out = arbiter.try_parse_att_client_packet(TCB_IDX, &build_notification(AttHandle(3)));
+
+        assert!(released);
+        assert!(out.is_none());
+        assert!(!arbiter.owns_client_transport(TCB_IDX));
+    }
+
+    #[test]
+    fn test_shared_advertiser_connect() {
+        let mut arbiter = Arbiter::new();
+        arbiter.associate_server_with_advertiser(SERVER_ID, ADVERTISER_ID);
+        arbiter.associate_server_with_advertiser(ANOTHER_SERVER_ID, ADVERTISER_ID);
+
+        let conn_id = arbiter.on_le_connect(TCB_IDX, ADVERTISER_ID);
+
+        assert_eq!(conn_id, Some(CONN_ID));
+        assert_eq!(arbiter.get_servers(TCB_IDX), vec![SERVER_ID, ANOTHER_SERVER_ID]);
+        assert!(arbiter.is_connection_isolated(ConnectionId::new(TCB_IDX, ANOTHER_SERVER_ID)));
+    }
+
+    #[test]
+    fn test_duplicate_association() {
+        let mut arbiter = Arbiter::new();
+        arbiter.associate_server_with_advertiser(SERVER_ID, ADVERTISER_ID);
+        arbiter.associate_server_with_advertiser(SERVER_ID, ADVERTISER_ID);
+
+        arbiter.on_le_connect(TCB_IDX, ADVERTISER_ID);
+
+        assert_eq!(arbiter.get_servers(TCB_IDX), vec![SERVER_ID]);
+    }
+
+    #[test]
+    fn test_shared_advertiser_primary_server_closed() {
+        let mut arbiter = Arbiter::new();
+        arbiter.associate_server_with_advertiser(SERVER_ID, ADVERTISER_ID);
+        arbiter.associate_server_with_advertiser(ANOTHER_SERVER_ID, ADVERTISER_ID);
+        arbiter.clear_server(SERVER_ID);
+
+        let conn_id = arbiter.on_le_connect(TCB_IDX, ADVERTISER_ID);
+
+        assert_eq!(conn_id, Some(ConnectionId::new(TCB_IDX, ANOTHER_SERVER_ID)));
+        assert_eq!(arbiter.get_servers(TCB_IDX), vec![ANOTHER_SERVER_ID]);
+    }
+
+    fn build_read_request(handle: AttHandle) -> Box<[u8]> {
+        AttBuilder {
+            opcode: AttOpcode::READ_REQUEST,
+            _child_: AttReadRequestBuilder { attribute_handle: handle.into() }.into(),
+        }
+        .to_vec()
+        .unwrap()
+        .into()
+    }
+
+    fn build_write_request(handle: AttHandle) -> Box<[u8]> {
+        AttBuilder {
+            opcode: AttOpcode::WRITE_REQUEST,
+            _child_: AttWriteRequestBuilder {
+                handle: handle.into(),
+                value: build_att_data(AttAttributeDataChild::RawData([1].into())),
+            }
+            .into(),
+        }
+        .to_vec()
+        .unwrap()
+        .into()
+    }
+
+    fn build_find_information_request(start: AttHandle, end: AttHandle) -> Box<[u8]> {
+        AttBuilder {
+            opcode: AttOpcode::FIND_INFORMATION_REQUEST,
+            _child_: AttFindInformationRequestBuilder {
+                starting_handle: start.into(),
+                ending_handle: end.into(),
+            }
+            .into(),
+        }
+        .to_vec()
+        .unwrap()
+        .into()
+    }
+
+    fn build_read_by_type_request(start: AttHandle, end: AttHandle, type_: Uuid) -> Box<[u8]> {
+        AttBuilder {
+            opcode: AttOpcode::READ_BY_TYPE_REQUEST,
+            _child_: AttReadByTypeRequestBuilder {
+                starting_handle: start.into(),
+                ending_handle: end.into(),
+                attribute_type: type_.into(),
+            }
+            .into(),
+        }
+        .to_vec()
+        .unwrap()
+        .into()
+    }
+
+    const SHARED_GAP_SERVICE_HANDLES: RangeInclusive<AttHandle> = AttHandle(20)..=AttHandle(28);
+
+    fn connect_with_shared_services() -> Arbiter {
+        let mut arbiter = Arbiter::new();
+        arbiter.set_shared_service_handles(Some(SHARED_GAP_SERVICE_HANDLES));
+        arbiter.associate_server_with_advertiser(SERVER_ID, ADVERTISER_ID);
+        arbiter.set_isolation_policy(ADVERTISER_ID, IsolationPolicy::IsolatedWithSharedServices);
+        arbiter.on_le_connect(TCB_IDX, ADVERTISER_ID);
+        arbiter
+    }
+
+    #[test]
+    fn test_gap_packet_bypass_with_shared_services() {
+        let arbiter = connect_with_shared_services();
+
+        let gap = arbiter.try_parse_att_server_packet(TCB_IDX, build_read_request(AttHandle(22)));
+        let gatt = arbiter.try_parse_att_server_packet(TCB_IDX, build_read_request(AttHandle(3)));
+        let app = arbiter.try_parse_att_server_packet(TCB_IDX, build_read_request(AttHandle(50)));
+
+        assert!(gap.is_none());
+        assert!(gatt.is_some());
+        assert!(app.is_some());
+    }
+
+    #[test]
+    fn test_gap_write_bypass_with_shared_services() {
+        let arbiter = connect_with_shared_services();
+
+        let out = arbiter.try_parse_att_server_packet(TCB_IDX, build_write_request(AttHandle(22)));
+
+        assert!(out.is_none());
+    }
+
+    #[test]
+    fn test_gap_packet_capture_outside_shared_service_handles() {
+        let arbiter = connect_with_shared_services();
+
+        let out = arbiter.try_parse_att_server_packet(TCB_IDX, build_read_request(AttHandle(30)));
+
+        assert!(out.is_some());
+    }
+
+    #[test]
+    fn test_gap_packet_capture_without_shared_service_handles() {
+        let mut arbiter = connect_with_shared_services();
+        arbiter.set_shared_service_handles(None);
+
+        let out = arbiter.try_parse_att_server_packet(TCB_IDX, build_read_request(AttHandle(22)));
+
+        assert!(out.is_some());
+    }
+
+    #[test]
+    fn test_gap_find_information_bypass_with_shared_services() {
+        let arbiter = connect_with_shared_services();
+
+        let gap = arbiter.try_parse_att_server_packet(
+            TCB_IDX,
+            build_find_information_request(AttHandle(21), AttHandle(28)),
+        );
+        let all = arbiter.try_parse_att_server_packet(
+            TCB_IDX,
+            build_find_information_request(AttHandle(1), AttHandle(0xFFFF)),
+        );
+
+        assert!(gap.is_none());
+        assert!(all.is_some());
+    }
+
+    #[test]
+    fn test_gap_read_by_type_bypass_with_shared_services() {
+        let arbiter = connect_with_shared_services();
+
+        let device_name = arbiter.try_parse_att_server_packet(
+            TCB_IDX,
+            build_read_by_type_request(AttHandle(1), AttHandle(0xFFFF), DEVICE_NAME_UUID),
+        );
+        let other = arbiter.try_parse_att_server_packet(
+            TCB_IDX,
+            build_read_by_type_request(AttHandle(1), AttHandle(0xFFFF), Uuid::new(0x2803)),
+        );
+        let other_in_gap = arbiter.try_parse_att_server_packet(
+            TCB_IDX,
+            build_read_by_type_request(AttHandle(21), AttHandle(28), Uuid::new(0x2803)),
+        );
+
+        assert!(device_name.is_none());
+        assert!(other.is_some());
+        assert!(other_in_gap.is_none());
+    }
+
+    #[test]
+    fn test_gap_read_by_type_capture_outside_shared_service_handles() {
+        let arbiter = connect_with_shared_services();
+
+        let out = arbiter.try_parse_att_server_packet(
+            TCB_IDX,
+            build_read_by_type_request(AttHandle(50), AttHandle(0xFFFF), DEVICE_NAME_UUID),
+        );
+
+        assert!(out.is_some());
+    }
+
+    #[test]
+    fn test_gap_packet_capture_when_isolated() {
+        let mut arbiter = Arbiter::new();
+        arbiter.associate_server_with_advertiser(SERVER_ID, ADVERTISER_ID);
+        arbiter.on_le_connect(TCB_IDX, ADVERTISER_ID);
+
+        let out = arbiter.try_parse_att_server_packet(TCB_IDX, build_read_request(AttHandle(22)));
+
+        assert!(out.is_some());
+    }
+
+    #[test]
+    fn test_isolation_policy_cleared_with_advertiser() {
+        let mut arbiter = Arbiter::new();
+        arbiter.set_isolation_policy(ADVERTISER_ID, IsolationPolicy::IsolatedWithSharedServices);
+        arbiter.clear_advertiser(ADVERTISER_ID);
+        arbiter.associate_server_with_advertiser(SERVER_ID, ADVERTISER_ID);
+        arbiter.on_le_connect(TCB_IDX, ADVERTISER_ID);
+
+        let out = arbiter.try_parse_att_server_packet(TCB_IDX, build_read_request(AttHandle(22)));
+
+        assert!(out.is_some());
+    }
+
+    #[test]
+    fn test_hand_over_connection() {
+        let mut arbiter = Arbiter::new();
+        arbiter.associate_server_with_advertiser(SERVER_ID, ADVERTISER_ID);
+        arbiter.on_le_connect(TCB_IDX, ADVERTISER_ID);
+
+        let conn_id = arbiter.hand_over_connection(TCB_IDX);
+
+        assert_eq!(conn_id, Some(CONN_ID));
+        assert!(!arbiter.is_connection_isolated(CONN_ID));
+        assert!(arbiter
+            .try_parse_att_server_packet(TCB_IDX, build_read_request(AttHandle(1)))
+            .is_none());
+        assert!(!arbiter.on_le_disconnect(TCB_IDX));
+    }
+
+    #[test]
+    fn test_transports_owned_by_server() {
+        let mut arbiter = Arbiter::new();
+        arbiter.associate_server_with_advertiser(SERVER_ID, ADVERTISER_ID);
+        arbiter.associate_server_with_advertiser(ANOTHER_SERVER_ID, ADVERTISER_ID);
+        arbiter.on_le_connect(TCB_IDX, ADVERTISER_ID);
+
+        assert_eq!(arbiter.get_transports_owned_by(SERVER_ID), vec![TCB_IDX]);
+        assert!(arbiter.get_transports_owned_by(ANOTHER_SERVER_ID).is_empty());
+    }
+
+    #[test]
+    fn test_hand_over_non_isolated_connection() {
+        let mut arbiter = Arbiter::new();
+        arbiter.on_le_connect(TCB_IDX, ADVERTISER_ID);
+
+        let conn_id = arbiter.hand_over_connection(TCB_IDX);
+
+        assert!(conn_id.is_none());
+    }
 }
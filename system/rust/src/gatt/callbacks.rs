@@ -4,7 +4,9 @@
 
 mod callback_transaction_manager;
 
-pub use callback_transaction_manager::{CallbackResponseError, CallbackTransactionManager};
+pub use callback_transaction_manager::{
+    CallbackResponseError, CallbackTransactionManager, DEFAULT_TIMEOUT, MAX_TIMEOUT,
+};
 
 use async_trait::async_trait;
 use log::warn;
@@ -59,6 +61,11 @@ pub trait GattCallbacks {
         trans_id: TransactionId,
         decision: TransactionDecision,
     );
+
+    /// Invoked when a pending read/write/execute transaction is abandoned,
+    /// either because it timed out or because the connection dropped. Any
+    /// subsequent response to it will be rejected.
+    fn on_transaction_cancelled(&self, conn_id: ConnectionId, trans_id: TransactionId);
 }
 
 /// The various write types available (requests + commands)
@@ -1,12 +1,21 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc, time::Duration};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+    time::Duration,
+};
 
 use async_trait::async_trait;
-use log::{trace, warn};
-use tokio::{sync::oneshot, time::timeout};
+use log::{info, trace, warn};
+use tokio::{
+    sync::oneshot,
+    time::{timeout, Instant},
+};
 
 use crate::{
     gatt::{
         ids::{AttHandle, ConnectionId, ServerId, TransactionId, TransportIndex},
+        server::metrics::LatencyStats,
         GattCallbacks,
     },
     packets::{AttAttributeDataChild, AttAttributeDataView, AttErrorCode},
@@ -26,6 +35,7 @@ struct PendingTransactionWatcher {
     conn_id: ConnectionId,
     trans_id: TransactionId,
     rx: oneshot::Receiver<Result<AttAttributeDataChild, AttErrorCode>>,
+    started: Instant,
 }
 
 /// This struct converts the asynchronus read/write operations of GattDatastore
@@ -33,6 +43,8 @@ struct PendingTransactionWatcher {
 pub struct CallbackTransactionManager {
     callbacks: Rc<dyn GattCallbacks>,
     pending_transactions: RefCell<PendingTransactionsState>,
+    timeout: Cell<Duration>,
+    datastore_latency: RefCell<HashMap<TransportIndex, LatencyStats>>,
 }
 
 struct PendingTransactionsState {
@@ -40,10 +52,14 @@ struct PendingTransactionsState {
     next_transaction_id: u32,
 }
 
-/// We expect all responses to be provided within this timeout
-/// It should be less than 30s, as that is the ATT timeout that causes
-/// the client to disconnect.
-const TIMEOUT: Duration = Duration::from_secs(15);
+/// We expect all responses to be provided within this timeout, unless
+/// otherwise configured
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The largest timeout that can be configured. The bearer handles one request
+/// at a time, so this must be comfortably less than 30s, as that is the ATT
+/// timeout that causes the client to disconnect.
+pub const MAX_TIMEOUT: Duration = Duration::from_secs(25);
 
 /// The cause of a failure to dispatch a call to send_response()
 #[derive(Debug, PartialEq, Eq)]
@@ -64,6 +80,8 @@ impl CallbackTransactionManager {
                 pending_transactions: HashMap::new(),
                 next_transaction_id: 1,
             }),
+            timeout: Cell::new(DEFAULT_TIMEOUT),
+            datastore_latency: RefCell::new(HashMap::new()),
         }
     }
 
@@ -87,6 +105,39 @@ impl CallbackTransactionManager {
         }
     }
 
+    /// The time taken by the upper layers to respond to transactions on the
+    /// given transport (including transactions that timed out)
+    pub fn get_datastore_latency(&self, tcb_idx: TransportIndex) -> LatencyStats {
+        self.datastore_latency.borrow().get(&tcb_idx).copied().unwrap_or_default()
+    }
+
+    /// Set how long to wait for the upper layers to respond to each
+    /// transaction before returning UNLIKELY_ERROR to the peer. Values above
+    /// MAX_TIMEOUT are clamped.
+    pub fn set_timeout(&self, timeout: Duration) {
+        if timeout > MAX_TIMEOUT {
+            warn!("transaction timeout {timeout:?} is too long, clamping to {MAX_TIMEOUT:?}");
+        }
+        self.timeout.set(timeout.min(MAX_TIMEOUT));
+    }
+
+    /// The current per-transaction timeout
+    pub fn get_timeout(&self) -> Duration {
+        self.timeout.get()
+    }
+
+    /// Invoked when a transport is disconnected. Cancels all pending
+    /// transactions on it (notifying the upper layers) and discards its
+    /// metrics.
+    pub fn on_le_disconnect(&self, tcb_idx: TransportIndex) {
+        let cancelled = self.pending_transactions.borrow_mut().cancel_transactions(tcb_idx);
+        for (conn_id, trans_id) in cancelled {
+            info!("cancelling transaction {trans_id:?} on {conn_id:?} due to disconnection");
+            self.callbacks.on_transaction_cancelled(conn_id, trans_id);
+        }
+        self.datastore_latency.borrow_mut().remove(&tcb_idx);
+    }
+
     /// Get an impl GattDatastore tied to a particular server
     pub fn get_datastore(self: &Rc<Self>, server_id: ServerId) -> impl RawGattDatastore {
         GattDatastoreImpl { callback_transaction_manager: self.clone(), server_id }
@@ -104,18 +155,49 @@ impl PendingTransactionsState {
         let trans_id = self.alloc_transaction_id();
         let (tx, rx) = oneshot::channel();
         self.pending_transactions.insert((conn_id, trans_id), PendingTransaction { response: tx });
-        PendingTransactionWatcher { conn_id, trans_id, rx }
+        PendingTransactionWatcher { conn_id, trans_id, rx, started: Instant::now() }
+    }
+
+    /// Drop all pending transactions on the given transport, so their
+    /// watchers resolve immediately. Returns the cancelled transactions.
+    fn cancel_transactions(
+        &mut self,
+        tcb_idx: TransportIndex,
+    ) -> Vec<(ConnectionId, TransactionId)> {
+        let cancelled = self
+            .pending_transactions
+            .keys()
+            .filter(|(conn_id, _)| conn_id.get_tcb_idx() == tcb_idx)
+            .copied()
+            .collect::<Vec<_>>();
+        for key in &cancelled {
+            self.pending_transactions.remove(key);
+        }
+        cancelled
     }
 }
 
 impl PendingTransactionWatcher {
-    /// Wait for the transaction to resolve, or to hit the timeout. If the
-    /// timeout is reached, clean up state related to transaction watching.
+    /// Wait for the transaction to resolve, be cancelled, or to hit the
+    /// timeout. If the timeout is reached, clean up state related to
+    /// transaction watching and notify the upper layers.
     async fn wait(
         self,
         manager: &CallbackTransactionManager,
     ) -> Result<AttAttributeDataChild, AttErrorCode> {
-        if let Ok(Ok(result)) = timeout(TIMEOUT, self.rx).await {
+        let result = timeout(manager.timeout.get(), self.rx).await;
+        if let Ok(Err(_)) = result {
+            // the transport was disconnected, so there is no one to reply to
+            trace!("transaction {:?} was cancelled", self.trans_id);
+            return Err(AttErrorCode::UNLIKELY_ERROR);
+        }
+        manager
+            .datastore_latency
+            .borrow_mut()
+            .entry(self.conn_id.get_tcb_idx())
+            .or_default()
+            .record(self.started.elapsed());
+        if let Ok(Ok(result)) = result {
             result
         } else {
             manager
@@ -124,6 +206,7 @@ impl PendingTransactionWatcher {
                 .pending_transactions
                 .remove(&(self.conn_id, self.trans_id));
             warn!("no response received from Java after timeout - returning UNLIKELY_ERROR");
+            manager.callbacks.on_transaction_cancelled(self.conn_id, self.trans_id);
             Err(AttErrorCode::UNLIKELY_ERROR)
         }
     }
@@ -0,0 +1,185 @@
+//! This module is a simple GATT client that shares the ATT channel with the
+//! existing C++ GATT stack, so that the C++ GATT client can eventually be
+//! retired.
+
+pub mod att_client_bearer;
+pub mod discovery;
+pub mod read_write;
+pub mod subscription;
+
+use std::{collections::HashMap, rc::Rc};
+
+use anyhow::{bail, Result};
+use log::info;
+
+use crate::core::shared_box::{SharedBox, WeakBox, WeakBoxRef};
+
+use self::att_client_bearer::{AttClientBearer, AttClientError};
+
+use super::{channel::AttTransport, ids::TransportIndex, server::att_server_bearer::SendError};
+
+#[allow(missing_docs)]
+pub struct GattClientModule {
+    connections: HashMap<TransportIndex, SharedBox<AttClientBearer>>,
+    transport: Rc<dyn AttTransport>,
+}
+
+impl GattClientModule {
+    /// Constructor.
+    pub fn new(transport: Rc<dyn AttTransport>) -> Self {
+        Self { connections: HashMap::new(), transport }
+    }
+
+    /// Open a bearer for the client role of this link, once a Rust GATT
+    /// client procedure needs it (see arbiter::open_client_transport)
+    pub fn on_le_connect(&mut self, tcb_idx: TransportIndex) -> Result<()> {
+        info!("client connected on {tcb_idx:?}");
+        if self.connections.contains_key(&tcb_idx) {
+            bail!("got client connection on {tcb_idx:?} but bearer already exists");
+        }
+        let transport = self.transport.clone();
+        let bearer = SharedBox::new(AttClientBearer::new(move |packet| {
+            transport.send_packet(tcb_idx, packet)
+        }));
+        self.connections.insert(tcb_idx, bearer);
+        Ok(())
+    }
+
+    /// Handle an LE link disconnect
+    pub fn on_le_disconnect(&mut self, tcb_idx: TransportIndex) -> Result<()> {
+        info!("client disconnected on {tcb_idx:?}");
+        if self.connections.remove(&tcb_idx).is_none() {
+            bail!("got client disconnection from {tcb_idx:?} but bearer does not exist");
+        }
+        Ok(())
+    }
+
+    /// Get an ATT client bearer for a particular connection
+    pub fn get_bearer(&self, tcb_idx: TransportIndex) -> Option<WeakBoxRef<AttClientBearer>> {
+        self.connections.get(&tcb_idx).map(|bearer| bearer.as_ref())
+    }
+
+    /// Get a handle to run GATT procedures on a particular connection
+    pub fn get_client(&self, tcb_idx: TransportIndex) -> Option<GattClient> {
+        self.connections.get(&tcb_idx).map(|bearer| GattClient::new(bearer.downgrade()))
+    }
+}
+
+/// A handle used to run GATT client procedures on a single connection. The
+/// handle does not keep the connection alive - once the link disconnects, all
+/// procedures fail with SendError::ConnectionDropped.
+#[derive(Clone)]
+pub struct GattClient {
+    bearer: WeakBox<AttClientBearer>,
+}
+
+impl GattClient {
+    /// Constructor, wrapping an ATT client bearer
+    pub fn new(bearer: WeakBox<AttClientBearer>) -> Self {
+        Self { bearer }
+    }
+
+    /// Perform the ATT_EXCHANGE_MTU procedure (Core Spec 5.3 Vol 3G 4.3.1),
+    /// and return the negotiated MTU
+    pub async fn exchange_mtu(&self, client_rx_mtu: u16) -> Result<usize, AttClientError> {
+        self.with_bearer(|bearer| bearer.exchange_mtu(client_rx_mtu))?.await
+    }
+
+    fn mtu(&self) -> Result<usize, AttClientError> {
+        self.with_bearer(|bearer| bearer.mtu())
+    }
+
+    fn with_bearer<T>(
+        &self,
+        f: impl FnOnce(WeakBoxRef<AttClientBearer>) -> T,
+    ) -> Result<T, AttClientError> {
+        self.bearer
+            .with(|bearer| bearer.map(f))
+            .ok_or(AttClientError::SendError(SendError::ConnectionDropped))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{sync::mpsc::UnboundedReceiver, task::spawn_local};
+
+    use crate::{
+        gatt::{ids::AttHandle, mocks::mock_transport::MockAttTransport},
+        packets::{AttBuilder, AttOpcode, AttReadRequestBuilder},
+        utils::task::block_on_locally,
+    };
+
+    use super::*;
+
+    const TCB_IDX: TransportIndex = TransportIndex(1);
+
+    fn create_client_module() -> (GattClientModule, UnboundedReceiver<(TransportIndex, AttBuilder)>)
+    {
+        let (transport, transport_rx) = MockAttTransport::new();
+        (GattClientModule::new(Rc::new(transport)), transport_rx)
+    }
+
+    #[test]
+    fn test_connect_and_send() {
+        block_on_locally(async {
+            // arrange
+            let (mut gatt, mut transport_rx) = create_client_module();
+
+            // act
+            gatt.on_le_connect(TCB_IDX).unwrap();
+            let _pending = spawn_local(
+                gatt.get_bearer(TCB_IDX)
+                    .unwrap()
+                    .send_request(AttReadRequestBuilder { attribute_handle: AttHandle(1).into() }),
+            );
+            let (tcb_idx, packet) = transport_rx.recv().await.unwrap();
+
+            // assert
+            assert_eq!(tcb_idx, TCB_IDX);
+            assert_eq!(packet.opcode, AttOpcode::READ_REQUEST);
+        });
+    }
+
+    #[test]
+    fn test_double_connect() {
+        // arrange
+        let (mut gatt, _transport_rx) = create_client_module();
+        gatt.on_le_connect(TCB_IDX).unwrap();
+
+        // act
+        let res = gatt.on_le_connect(TCB_IDX);
+
+        // assert
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_disconnect_drops_client() {
+        block_on_locally(async {
+            // arrange
+            let (mut gatt, _transport_rx) = create_client_module();
+            gatt.on_le_connect(TCB_IDX).unwrap();
+            let client = gatt.get_client(TCB_IDX).unwrap();
+
+            // act
+            gatt.on_le_disconnect(TCB_IDX).unwrap();
+            let res = client.read(AttHandle(1)).await;
+
+            // assert
+            assert!(gatt.get_bearer(TCB_IDX).is_none());
+            assert!(res.is_err());
+        });
+    }
+
+    #[test]
+    fn test_disconnect_without_connect() {
+        // arrange
+        let (mut gatt, _transport_rx) = create_client_module();
+
+        // act
+        let res = gatt.on_le_disconnect(TCB_IDX);
+
+        // assert
+        assert!(res.is_err());
+    }
+}
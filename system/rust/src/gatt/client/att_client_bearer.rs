@@ -0,0 +1,865 @@
+//! This module handles the client side of an individual connection on the ATT
+//! fixed channel. It serializes outgoing ATT requests (since only one may be
+//! outstanding at a time), matches them with their responses, and dispatches
+//! incoming notifications and indications to any registered listeners.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use log::{trace, warn};
+use tokio::{
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+    time::timeout,
+};
+
+use crate::{
+    core::{
+        shared_box::{WeakBox, WeakBoxRef},
+        shared_mutex::SharedMutex,
+    },
+    gatt::{
+        ids::AttHandle,
+        mtu::{AttMtu, MtuEvent},
+        opcode_types::{classify_opcode, OperationType},
+        server::att_server_bearer::SendError,
+    },
+    packets::{
+        AttBuilder, AttChild, AttErrorCode, AttErrorResponseView, AttExchangeMtuRequestBuilder,
+        AttExchangeMtuResponseView, AttHandleValueConfirmationBuilder,
+        AttHandleValueIndicationView, AttHandleValueNotificationView, AttOpcode, AttView,
+        OwnedAttView, Packet, SerializeError,
+    },
+    utils::packet::HACK_child_to_opcode,
+};
+
+/// As per Core Spec 5.3 Vol 3F 3.3.3, a transaction that has not completed
+/// within 30s has failed, and no further requests may be sent on the bearer
+const ATT_TRANSACTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+// NOTE: this is only true for ATT, not EATT
+const MIN_ATT_MTU: usize = 23;
+
+/// The errors that can occur while performing an ATT client operation
+#[derive(Debug)]
+pub enum AttClientError {
+    /// The server replied with an ATT_ERROR_RSP
+    ErrorResponse {
+        /// The handle that the server reported as the cause of the error
+        handle: AttHandle,
+        /// The reported error
+        error_code: AttErrorCode,
+    },
+    /// The server replied with a malformed response, or with one that does
+    /// not match the outstanding request
+    InvalidResponse,
+    /// No response was received in the given time (30s). No further requests
+    /// can be sent on this bearer.
+    TransactionTimeout,
+    /// The provided data exceeds the MTU limitations
+    DataExceedsMtu {
+        /// The actual max payload size permitted
+        mtu: usize,
+    },
+    /// Failed to send the outgoing request
+    SendError(SendError),
+    /// The characteristic does not support the requested operation
+    NotSupportedByCharacteristic,
+    /// During a long or reliable write, the server did not echo back the
+    /// prepared value we sent, so the queued writes were cancelled
+    PrepareWriteMismatch,
+}
+
+impl AttClientError {
+    /// Whether this error is an ATT_ERROR_RSP with the given error code
+    pub fn is_error_code(&self, code: AttErrorCode) -> bool {
+        matches!(self, AttClientError::ErrorResponse { error_code, .. } if *error_code == code)
+    }
+}
+
+/// Whether a server-initiated value update was a notification or an indication
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ValueUpdateKind {
+    /// ATT_HANDLE_VALUE_NTF
+    Notification,
+    /// ATT_HANDLE_VALUE_IND (confirmed automatically by the bearer)
+    Indication,
+}
+
+/// A value update pushed by the server for a given attribute
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttValueUpdate {
+    /// The attribute whose value was updated
+    pub handle: AttHandle,
+    /// The new value of the attribute
+    pub value: Box<[u8]>,
+    /// Whether the update was a notification or an indication
+    pub kind: ValueUpdateKind,
+}
+
+/// Which of the packets sent by the server are meant for this client, since
+/// the ATT channel is shared with the legacy client. This is shared with the
+/// arbiter, which routes each packet as it arrives.
+#[derive(Debug, Default)]
+pub struct ClientRouting {
+    response_pending: AtomicBool,
+    value_handles: Mutex<HashSet<AttHandle>>,
+}
+
+impl ClientRouting {
+    /// Whether this packet should be routed to this client: the response to
+    /// its outstanding request (only once), or a value update of a handle it
+    /// listens to
+    pub fn accepts(&self, packet: AttView<'_>) -> bool {
+        match classify_opcode(packet.get_opcode()) {
+            OperationType::Response => self.response_pending.swap(false, Ordering::SeqCst),
+            OperationType::Notification => AttHandleValueNotificationView::try_parse(packet)
+                .map_or(false, |notification| self.listens_to(notification.get_handle().into())),
+            OperationType::Indication => AttHandleValueIndicationView::try_parse(packet)
+                .map_or(false, |indication| self.listens_to(indication.get_handle().into())),
+            _ => false,
+        }
+    }
+
+    fn listens_to(&self, handle: AttHandle) -> bool {
+        self.value_handles.lock().unwrap().contains(&handle)
+    }
+}
+
+struct PendingResponse {
+    request_opcode: AttOpcode,
+    tx: oneshot::Sender<Result<OwnedAttView, AttClientError>>,
+}
+
+struct ValueListener {
+    kind: ValueUpdateKind,
+    tx: UnboundedSender<AttValueUpdate>,
+}
+
+/// This represents the client role of a single ATT bearer (currently, always
+/// the unenhanced fixed channel on LE). The request lock ensures that only one
+/// transaction can take place at a time.
+pub struct AttClientBearer {
+    // general
+    send_packet: Box<dyn Fn(AttBuilder) -> Result<(), SerializeError>>,
+    mtu: AttMtu,
+    routing: Arc<ClientRouting>,
+
+    // request state
+    request_lock: SharedMutex<()>,
+    pending_response: RefCell<Option<PendingResponse>>,
+    timed_out: Cell<bool>,
+
+    // notification + indication state
+    value_listeners: RefCell<HashMap<AttHandle, Vec<ValueListener>>>,
+}
+
+impl AttClientBearer {
+    /// Constructor, wrapping an ATT channel (for outgoing packets)
+    pub fn new(send_packet: impl Fn(AttBuilder) -> Result<(), SerializeError> + 'static) -> Self {
+        Self {
+            send_packet: Box::new(send_packet),
+            mtu: AttMtu::new(),
+            routing: Arc::new(ClientRouting::default()),
+
+            request_lock: SharedMutex::new(()),
+            pending_response: RefCell::new(None),
+            timed_out: Cell::new(false),
+
+            value_listeners: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// The MTU that should be used for the next request
+    pub fn mtu(&self) -> usize {
+        self.mtu.snapshot_or_default()
+    }
+
+    /// The routing of the packets sent by the server to this client
+    pub fn routing(&self) -> Arc<ClientRouting> {
+        self.routing.clone()
+    }
+
+    /// Register interest in notifications or indications for a given handle.
+    /// Updates are delivered until the returned receiver is dropped.
+    pub fn register_value_listener(
+        &self,
+        handle: AttHandle,
+        kind: ValueUpdateKind,
+    ) -> UnboundedReceiver<AttValueUpdate> {
+        let (tx, rx) = unbounded_channel();
+        self.routing.value_handles.lock().unwrap().insert(handle);
+        self.value_listeners
+            .borrow_mut()
+            .entry(handle)
+            .or_default()
+            .push(ValueListener { kind, tx });
+        rx
+    }
+
+    /// The kinds of value updates that still have a live listener for this
+    /// handle. Listeners whose receivers have been dropped are pruned.
+    pub fn active_value_listeners(&self, handle: AttHandle) -> Vec<ValueUpdateKind> {
+        let mut value_listeners = self.value_listeners.borrow_mut();
+        let Some(listeners) = value_listeners.get_mut(&handle) else {
+            return vec![];
+        };
+        listeners.retain(|listener| !listener.tx.is_closed());
+        let kinds = listeners.iter().map(|listener| listener.kind).collect::<Vec<_>>();
+        if listeners.is_empty() {
+            value_listeners.remove(&handle);
+            self.routing.value_handles.lock().unwrap().remove(&handle);
+        }
+        kinds
+    }
+
+    fn send_packet(&self, packet: impl Into<AttChild>) -> Result<(), SerializeError> {
+        let child = packet.into();
+        let packet = AttBuilder { opcode: HACK_child_to_opcode(&child), _child_: child };
+        (self.send_packet)(packet)
+    }
+
+    fn dispatch_value_update(&self, update: AttValueUpdate) {
+        let mut value_listeners = self.value_listeners.borrow_mut();
+        let Some(listeners) = value_listeners.get_mut(&update.handle) else {
+            trace!("no listeners for value update on {:?}, dropping", update.handle);
+            return;
+        };
+        listeners.retain(|listener| {
+            listener.kind != update.kind || listener.tx.send(update.clone()).is_ok()
+        });
+    }
+}
+
+impl WeakBoxRef<'_, AttClientBearer> {
+    /// Handle an incoming packet, and send outgoing packets as appropriate
+    /// using the owned ATT channel.
+    pub fn handle_packet(&self, packet: AttView<'_>) {
+        match classify_opcode(packet.get_opcode()) {
+            OperationType::Response => self.handle_response(packet),
+            OperationType::Notification => self.handle_notification(packet),
+            OperationType::Indication => self.handle_indication(packet),
+            OperationType::Command | OperationType::Request | OperationType::Confirmation => {
+                unreachable!("the arbiter should not let us receive these packet types")
+            }
+        }
+    }
+
+    /// Send a request and wait for the corresponding response. If multiple
+    /// calls are outstanding, they are executed in FIFO order. An
+    /// ATT_ERROR_RSP is surfaced as AttClientError::ErrorResponse.
+    pub fn send_request(
+        &self,
+        request: impl Into<AttChild>,
+    ) -> impl Future<Output = Result<OwnedAttView, AttClientError>> {
+        let request = request.into();
+        let locked_request = self.request_lock.lock();
+        let this = self.downgrade();
+
+        async move {
+            // first wait until no other transactions are outstanding
+            let _guard = locked_request.await.ok_or_else(|| {
+                warn!("request cancelled while queued since the connection dropped");
+                AttClientError::SendError(SendError::ConnectionDropped)
+            })?;
+
+            // then, send the request
+            let request_opcode = HACK_child_to_opcode(&request);
+            let pending_response = this.with(|this| {
+                let this = this.ok_or(AttClientError::SendError(SendError::ConnectionDropped))?;
+                if this.timed_out.get() {
+                    warn!("cannot send {request_opcode:?} since a previous transaction timed out");
+                    return Err(AttClientError::TransactionTimeout);
+                }
+                let (tx, rx) = oneshot::channel();
+                this.pending_response.replace(Some(PendingResponse { request_opcode, tx }));
+                this.routing.response_pending.store(true, Ordering::SeqCst);
+                if let Err(err) = this.send_packet(request) {
+                    this.pending_response.take();
+                    this.routing.response_pending.store(false, Ordering::SeqCst);
+                    return Err(AttClientError::SendError(SendError::SerializeError(err)));
+                }
+                Ok(rx)
+            })?;
+
+            // finally, wait for the response
+            match timeout(ATT_TRANSACTION_TIMEOUT, pending_response).await {
+                Ok(Ok(response)) => response,
+                Ok(Err(_)) => {
+                    warn!("connection dropped while waiting for response to {request_opcode:?}");
+                    Err(AttClientError::SendError(SendError::ConnectionDropped))
+                }
+                Err(_) => {
+                    warn!("sent {request_opcode:?} but received no response for 30s");
+                    this.with(|this| {
+                        if let Some(this) = this {
+                            this.timed_out.set(true);
+                            this.pending_response.take();
+                            this.routing.response_pending.store(false, Ordering::SeqCst);
+                        }
+                    });
+                    Err(AttClientError::TransactionTimeout)
+                }
+            }
+        }
+    }
+
+    /// Perform the ATT_EXCHANGE_MTU procedure, and return the negotiated MTU
+    pub fn exchange_mtu(
+        &self,
+        client_rx_mtu: u16,
+    ) -> impl Future<Output = Result<usize, AttClientError>> {
+        let this = self.downgrade();
+        let pending_response =
+            self.send_request(AttExchangeMtuRequestBuilder { mtu: client_rx_mtu });
+        if let Err(err) = self.mtu.handle_event(MtuEvent::OutgoingRequest) {
+            warn!("{err:?}");
+        }
+
+        async move {
+            let response = pending_response.await;
+            let mtu = match &response {
+                Ok(response) => AttExchangeMtuResponseView::try_parse(response.view())
+                    .map(|response| {
+                        (client_rx_mtu as usize).min(response.get_mtu() as usize).max(MIN_ATT_MTU)
+                    })
+                    .map_err(|_| AttClientError::InvalidResponse),
+                Err(_) => Err(AttClientError::InvalidResponse),
+            };
+            this.with(|this| {
+                if let Some(this) = this {
+                    // if the exchange failed, unblock the MTU with its previous value
+                    let mtu =
+                        mtu.as_ref().copied().unwrap_or_else(|_| this.mtu.snapshot_or_default());
+                    if let Err(err) = this.mtu.handle_event(MtuEvent::IncomingResponse(mtu)) {
+                        warn!("{err:?}");
+                    }
+                }
+            });
+            response?;
+            mtu
+        }
+    }
+
+    /// Handle a snooped MTU event, to update the MTU we use for our various
+    /// operations
+    pub fn handle_mtu_event(&self, mtu_event: MtuEvent) -> Result<()> {
+        self.mtu.handle_event(mtu_event)
+    }
+
+    fn handle_response(&self, packet: AttView<'_>) {
+        let Some(PendingResponse { request_opcode, tx }) = self.pending_response.take() else {
+            warn!("got {:?} with no request outstanding, dropping", packet.get_opcode());
+            return;
+        };
+
+        let response = if packet.get_opcode() == AttOpcode::ERROR_RESPONSE {
+            match AttErrorResponseView::try_parse(packet) {
+                Ok(error) if error.get_opcode_in_error() == request_opcode => {
+                    Err(AttClientError::ErrorResponse {
+                        handle: error.get_handle_in_error().into(),
+                        error_code: error.get_error_code(),
+                    })
+                }
+                _ => Err(AttClientError::InvalidResponse),
+            }
+        } else if u8::from(packet.get_opcode()) == u8::from(request_opcode) + 1 {
+            // every ATT response opcode immediately follows the corresponding request opcode
+            Ok(packet.to_owned_packet())
+        } else {
+            warn!("got {:?} in response to {request_opcode:?}", packet.get_opcode());
+            Err(AttClientError::InvalidResponse)
+        };
+
+        if tx.send(response).is_err() {
+            warn!("response to {request_opcode:?} arrived after the requester went away");
+        }
+    }
+
+    fn handle_notification(&self, packet: AttView<'_>) {
+        let Ok(notification) = AttHandleValueNotificationView::try_parse(packet) else {
+            warn!("failed to parse notification, dropping");
+            return;
+        };
+        self.dispatch_value_update(AttValueUpdate {
+            handle: notification.get_handle().into(),
+            value: notification.get_value().get_raw_payload().collect(),
+            kind: ValueUpdateKind::Notification,
+        });
+    }
+
+    fn handle_indication(&self, packet: AttView<'_>) {
+        let Ok(indication) = AttHandleValueIndicationView::try_parse(packet) else {
+            warn!("failed to parse indication, dropping");
+            return;
+        };
+        self.dispatch_value_update(AttValueUpdate {
+            handle: indication.get_handle().into(),
+            value: indication.get_value().get_raw_payload().collect(),
+            kind: ValueUpdateKind::Indication,
+        });
+        // as per Core Spec 5.3 Vol 3F 3.4.7.3, we must always confirm, even if no one is
+        // listening
+        if let Err(err) = self.send_packet(AttHandleValueConfirmationBuilder {}) {
+            warn!("failed to send confirmation {err:?}");
+        }
+    }
+}
+
+impl WeakBox<AttClientBearer> {
+    /// Send a command, which does not expect a response
+    pub fn send_command(&self, command: impl Into<AttChild>) -> Result<(), AttClientError> {
+        self.with(|this| {
+            let this = this.ok_or_else(|| {
+                warn!("connection dropped before command sent");
+                AttClientError::SendError(SendError::ConnectionDropped)
+            })?;
+            if this.timed_out.get() {
+                // Core Spec 5.3 Vol 3F 3.3.3 also forbids commands after a timeout
+                warn!("cannot send command since a previous transaction timed out");
+                return Err(AttClientError::TransactionTimeout);
+            }
+            this.send_packet(command)
+                .map_err(|err| AttClientError::SendError(SendError::SerializeError(err)))
+        })
+    }
+
+    /// Send a request and wait for the corresponding response, failing if the
+    /// connection has already been dropped
+    pub fn send_request(
+        &self,
+        request: impl Into<AttChild>,
+    ) -> impl Future<Output = Result<OwnedAttView, AttClientError>> {
+        let pending_response = self.with(|this| this.map(|this| this.send_request(request)));
+        async move {
+            match pending_response {
+                Some(pending_response) => pending_response.await,
+                None => {
+                    warn!("connection dropped before request sent");
+                    Err(AttClientError::SendError(SendError::ConnectionDropped))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{
+        sync::mpsc::error::TryRecvError,
+        task::spawn_local,
+        time::{sleep, Duration},
+    };
+
+    use super::*;
+
+    use crate::{
+        core::shared_box::SharedBox,
+        packets::{
+            AttAttributeDataChild, AttErrorResponseBuilder, AttExchangeMtuResponseBuilder,
+            AttHandleValueIndicationBuilder, AttHandleValueNotificationBuilder,
+            AttReadRequestBuilder, AttReadResponseBuilder, AttReadResponseView,
+            AttWriteResponseBuilder,
+        },
+        utils::{
+            packet::{build_att_data, build_att_view_or_crash},
+            task::{block_on_locally, try_await},
+        },
+    };
+
+    const HANDLE: AttHandle = AttHandle(3);
+    const ANOTHER_HANDLE: AttHandle = AttHandle(5);
+
+    fn open_connection() -> (SharedBox<AttClientBearer>, UnboundedReceiver<AttBuilder>) {
+        let (tx, rx) = unbounded_channel();
+        let bearer = AttClientBearer::new(move |packet| {
+            tx.send(packet).unwrap();
+            Ok(())
+        })
+        .into();
+        (bearer, rx)
+    }
+
+    fn read_request(handle: AttHandle) -> AttReadRequestBuilder {
+        AttReadRequestBuilder { attribute_handle: handle.into() }
+    }
+
+    fn read_response(data: &[u8]) -> OwnedAttView {
+        build_att_view_or_crash(AttReadResponseBuilder {
+            value: build_att_data(AttAttributeDataChild::RawData(data.into())),
+        })
+    }
+
+    #[test]
+    fn test_single_transaction() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, mut rx) = open_connection();
+
+            // act: send a request, and reply to it
+            let pending_response = spawn_local(bearer.as_ref().send_request(read_request(HANDLE)));
+            assert_eq!(rx.recv().await.unwrap().opcode, AttOpcode::READ_REQUEST);
+            bearer.as_ref().handle_packet(read_response(&[1, 2]).view());
+            let response = pending_response.await.unwrap().unwrap();
+
+            // assert
+            assert_eq!(
+                AttReadResponseView::try_parse(response.view())
+                    .unwrap()
+                    .get_value()
+                    .get_raw_payload()
+                    .collect::<Vec<_>>(),
+                vec![1, 2]
+            );
+        });
+    }
+
+    #[test]
+    fn test_concurrent_requests_are_serialized() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, mut rx) = open_connection();
+
+            // act: send two requests without replying
+            let first = spawn_local(bearer.as_ref().send_request(read_request(HANDLE)));
+            let second = spawn_local(bearer.as_ref().send_request(read_request(ANOTHER_HANDLE)));
+            rx.recv().await.unwrap();
+            // give the second request a chance to be (incorrectly) sent
+            sleep(Duration::from_millis(1)).await;
+
+            // assert: only the first request was sent
+            assert_eq!(rx.try_recv().unwrap_err(), TryRecvError::Empty);
+
+            // act: reply to the first request
+            bearer.as_ref().handle_packet(read_response(&[1]).view());
+            first.await.unwrap().unwrap();
+
+            // assert: the second request is now sent
+            assert_eq!(rx.recv().await.unwrap().opcode, AttOpcode::READ_REQUEST);
+            bearer.as_ref().handle_packet(read_response(&[2]).view());
+            second.await.unwrap().unwrap();
+        });
+    }
+
+    #[test]
+    fn test_error_response() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, mut rx) = open_connection();
+            let pending_response = spawn_local(bearer.as_ref().send_request(read_request(HANDLE)));
+            rx.recv().await.unwrap();
+
+            // act
+            bearer.as_ref().handle_packet(
+                build_att_view_or_crash(AttErrorResponseBuilder {
+                    opcode_in_error: AttOpcode::READ_REQUEST,
+                    handle_in_error: HANDLE.into(),
+                    error_code: AttErrorCode::READ_NOT_PERMITTED,
+                })
+                .view(),
+            );
+            let response = pending_response.await.unwrap();
+
+            // assert
+            assert!(matches!(
+                response,
+                Err(AttClientError::ErrorResponse {
+                    handle: HANDLE,
+                    error_code: AttErrorCode::READ_NOT_PERMITTED
+                })
+            ));
+        });
+    }
+
+    #[test]
+    fn test_mismatched_response() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, mut rx) = open_connection();
+            let pending_response = spawn_local(bearer.as_ref().send_request(read_request(HANDLE)));
+            rx.recv().await.unwrap();
+
+            // act: reply with the response to a different request
+            bearer
+                .as_ref()
+                .handle_packet(build_att_view_or_crash(AttWriteResponseBuilder {}).view());
+            let response = pending_response.await.unwrap();
+
+            // assert
+            assert!(matches!(response, Err(AttClientError::InvalidResponse)));
+        });
+    }
+
+    #[test]
+    fn test_unsolicited_response_is_dropped() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, mut rx) = open_connection();
+
+            // act: receive a response with no request outstanding
+            bearer.as_ref().handle_packet(read_response(&[1]).view());
+
+            // assert: nothing was sent, and the next transaction is unaffected
+            assert_eq!(rx.try_recv().unwrap_err(), TryRecvError::Empty);
+            let pending_response = spawn_local(bearer.as_ref().send_request(read_request(HANDLE)));
+            rx.recv().await.unwrap();
+            bearer.as_ref().handle_packet(read_response(&[2]).view());
+            assert!(pending_response.await.unwrap().is_ok());
+        });
+    }
+
+    #[test]
+    fn test_transaction_timeout() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, mut rx) = open_connection();
+
+            // act: send a request and never reply
+            let response = bearer.as_ref().send_request(read_request(HANDLE)).await;
+
+            // assert: the request timed out, and subsequent requests are not sent
+            assert!(matches!(response, Err(AttClientError::TransactionTimeout)));
+            rx.recv().await.unwrap();
+            let response = bearer.as_ref().send_request(read_request(HANDLE)).await;
+            assert!(matches!(response, Err(AttClientError::TransactionTimeout)));
+            assert_eq!(rx.try_recv().unwrap_err(), TryRecvError::Empty);
+        });
+    }
+
+    #[test]
+    fn test_disconnect_while_pending() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, mut rx) = open_connection();
+            let pending_response = spawn_local(bearer.as_ref().send_request(read_request(HANDLE)));
+            let queued_response =
+                spawn_local(bearer.as_ref().send_request(read_request(ANOTHER_HANDLE)));
+            rx.recv().await.unwrap();
+
+            // act
+            drop(bearer);
+
+            // assert: both the outstanding and the queued request fail
+            assert!(matches!(
+                pending_response.await.unwrap(),
+                Err(AttClientError::SendError(SendError::ConnectionDropped))
+            ));
+            assert!(matches!(
+                queued_response.await.unwrap(),
+                Err(AttClientError::SendError(SendError::ConnectionDropped))
+            ));
+        });
+    }
+
+    #[test]
+    fn test_mtu_exchange() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, mut rx) = open_connection();
+
+            // act
+            let pending_mtu = spawn_local(bearer.as_ref().exchange_mtu(517));
+            let request = rx.recv().await.unwrap();
+            bearer.as_ref().handle_packet(
+                build_att_view_or_crash(AttExchangeMtuResponseBuilder { mtu: 64 }).view(),
+            );
+            let mtu = pending_mtu.await.unwrap().unwrap();
+
+            // assert: the smaller of the two MTUs is used
+            assert_eq!(request.opcode, AttOpcode::EXCHANGE_MTU_REQUEST);
+            assert_eq!(mtu, 64);
+            assert_eq!(bearer.mtu(), 64);
+        });
+    }
+
+    #[test]
+    fn test_mtu_exchange_rejected() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, mut rx) = open_connection();
+
+            // act
+            let pending_mtu = spawn_local(bearer.as_ref().exchange_mtu(517));
+            rx.recv().await.unwrap();
+            bearer.as_ref().handle_packet(
+                build_att_view_or_crash(AttErrorResponseBuilder {
+                    opcode_in_error: AttOpcode::EXCHANGE_MTU_REQUEST,
+                    handle_in_error: AttHandle(0).into(),
+                    error_code: AttErrorCode::REQUEST_NOT_SUPPORTED,
+                })
+                .view(),
+            );
+            let mtu = pending_mtu.await.unwrap();
+
+            // assert: the MTU is unblocked at its previous value
+            assert!(mtu.unwrap_err().is_error_code(AttErrorCode::REQUEST_NOT_SUPPORTED));
+            assert_eq!(bearer.mtu(), MIN_ATT_MTU);
+        });
+    }
+
+    #[test]
+    fn test_notification_dispatch() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, mut rx) = open_connection();
+            let mut listener =
+                bearer.register_value_listener(HANDLE, ValueUpdateKind::Notification);
+            let mut other_listener =
+                bearer.register_value_listener(ANOTHER_HANDLE, ValueUpdateKind::Notification);
+
+            // act
+            bearer.as_ref().handle_packet(
+                build_att_view_or_crash(AttHandleValueNotificationBuilder {
+                    handle: HANDLE.into(),
+                    value: build_att_data(AttAttributeDataChild::RawData([1, 2].into())),
+                })
+                .view(),
+            );
+
+            // assert: only the listener for this handle was notified, and no packets were sent
+            assert_eq!(
+                listener.try_recv().unwrap(),
+                AttValueUpdate {
+                    handle: HANDLE,
+                    value: [1, 2].into(),
+                    kind: ValueUpdateKind::Notification
+                }
+            );
+            assert_eq!(other_listener.try_recv().unwrap_err(), TryRecvError::Empty);
+            assert_eq!(rx.try_recv().unwrap_err(), TryRecvError::Empty);
+        });
+    }
+
+    #[test]
+    fn test_indication_is_confirmed() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, mut rx) = open_connection();
+            let mut listener = bearer.register_value_listener(HANDLE, ValueUpdateKind::Indication);
+
+            // act
+            bearer.as_ref().handle_packet(
+                build_att_view_or_crash(AttHandleValueIndicationBuilder {
+                    handle: HANDLE.into(),
+                    value: build_att_data(AttAttributeDataChild::RawData([3].into())),
+                })
+                .view(),
+            );
+
+            // assert
+            assert_eq!(listener.try_recv().unwrap().kind, ValueUpdateKind::Indication);
+            assert_eq!(rx.try_recv().unwrap().opcode, AttOpcode::HANDLE_VALUE_CONFIRMATION);
+        });
+    }
+
+    #[test]
+    fn test_indication_is_confirmed_without_listener() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, mut rx) = open_connection();
+
+            // act
+            bearer.as_ref().handle_packet(
+                build_att_view_or_crash(AttHandleValueIndicationBuilder {
+                    handle: HANDLE.into(),
+                    value: build_att_data(AttAttributeDataChild::RawData([3].into())),
+                })
+                .view(),
+            );
+
+            // assert
+            assert_eq!(rx.try_recv().unwrap().opcode, AttOpcode::HANDLE_VALUE_CONFIRMATION);
+        });
+    }
+
+    #[test]
+    fn test_dropped_listener_is_pruned() {
+        // arrange
+        let (bearer, _rx) = open_connection();
+        let listener = bearer.register_value_listener(HANDLE, ValueUpdateKind::Notification);
+        let _other = bearer.register_value_listener(HANDLE, ValueUpdateKind::Indication);
+
+        // act
+        drop(listener);
+
+        // assert
+        assert_eq!(bearer.active_value_listeners(HANDLE), vec![ValueUpdateKind::Indication]);
+    }
+
+    #[test]
+    fn test_routing_accepts_only_response_to_own_request() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, mut rx) = open_connection();
+            let routing = bearer.routing();
+            let unsolicited = routing.accepts(read_response(&[1]).view());
+
+            // act: send a request, and see two responses arrive
+            let _pending = spawn_local(bearer.as_ref().send_request(read_request(HANDLE)));
+            rx.recv().await.unwrap();
+            let first = routing.accepts(read_response(&[2]).view());
+            let second = routing.accepts(read_response(&[3]).view());
+
+            // assert: only the response to our request is ours
+            assert!(!unsolicited);
+            assert!(first);
+            assert!(!second);
+        });
+    }
+
+    #[test]
+    fn test_routing_accepts_only_listened_value_updates() {
+        // arrange
+        let (bearer, _rx) = open_connection();
+        let routing = bearer.routing();
+        let notification = |handle: AttHandle| {
+            build_att_view_or_crash(AttHandleValueNotificationBuilder {
+                handle: handle.into(),
+                value: build_att_data(AttAttributeDataChild::RawData([1].into())),
+            })
+        };
+
+        // act
+        let listener = bearer.register_value_listener(HANDLE, ValueUpdateKind::Notification);
+        let listened = routing.accepts(notification(HANDLE).view());
+        let other = routing.accepts(notification(ANOTHER_HANDLE).view());
+        drop(listener);
+        bearer.active_value_listeners(HANDLE);
+        let dropped = routing.accepts(notification(HANDLE).view());
+
+        // assert
+        assert!(listened);
+        assert!(!other);
+        assert!(!dropped);
+    }
+
+    #[test]
+    fn test_request_not_sent_after_drop() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, _rx) = open_connection();
+            let weak = bearer.downgrade();
+            drop(bearer);
+
+            // act
+            let response = try_await(weak.send_request(read_request(HANDLE))).await;
+
+            // assert
+            assert!(matches!(
+                response,
+                Ok(Err(AttClientError::SendError(SendError::ConnectionDropped)))
+            ));
+        });
+    }
+}
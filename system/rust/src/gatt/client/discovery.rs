@@ -0,0 +1,443 @@
+//! This module implements the GATT client discovery procedures
+//! (Core Spec 5.3 Vol 3G 4.4-4.7)
+
+use bitflags::bitflags;
+use log::warn;
+
+use crate::{
+    core::uuid::Uuid,
+    gatt::ids::AttHandle,
+    packets::{
+        AttAttributeDataChild, AttAttributeDataView, AttErrorCode,
+        AttFindByTypeValueRequestBuilder, AttFindByTypeValueResponseView,
+        AttFindInformationLongResponseView, AttFindInformationRequestBuilder,
+        AttFindInformationResponseFormat, AttFindInformationResponseView,
+        AttFindInformationShortResponseView, AttReadByGroupTypeRequestBuilder,
+        AttReadByGroupTypeResponseView, AttReadByTypeRequestBuilder, AttReadByTypeResponseView,
+        AttReadRequestBuilder, AttReadResponseView, GattCharacteristicDeclarationValueView,
+        GattIncludedServiceDeclarationValueView, GattServiceDeclarationValueView, Packet,
+        Uuid16Builder, UuidAsAttDataBuilder, UuidView,
+    },
+    utils::packet::build_att_data,
+};
+
+use super::{att_client_bearer::AttClientError, GattClient};
+
+const PRIMARY_SERVICE_DECLARATION_UUID: Uuid = Uuid::new(0x2800);
+const SECONDARY_SERVICE_DECLARATION_UUID: Uuid = Uuid::new(0x2801);
+const INCLUDED_SERVICE_DECLARATION_UUID: Uuid = Uuid::new(0x2802);
+const CHARACTERISTIC_UUID: Uuid = Uuid::new(0x2803);
+
+/// The descriptor used to enable notifications and indications
+pub const CLIENT_CHARACTERISTIC_CONFIGURATION_UUID: Uuid = Uuid::new(0x2902);
+
+bitflags! {
+    /// The characteristic properties reported by a remote server
+    /// (Core Spec 5.3 Vol 3G 3.3.1.1 Characteristic Properties)
+    pub struct CharacteristicProperties : u8 {
+        /// Value can be broadcast in advertising data
+        const BROADCAST = 0x01;
+        /// Value can be read
+        const READ = 0x02;
+        /// Value can be written using WRITE_CMD
+        const WRITE_WITHOUT_RESPONSE = 0x04;
+        /// Value can be written using WRITE_REQ
+        const WRITE = 0x08;
+        /// Value can be notified
+        const NOTIFY = 0x10;
+        /// Value can be indicated
+        const INDICATE = 0x20;
+        /// Value can be written using SIGNED_WRITE_CMD
+        const AUTHENTICATED_SIGNED_WRITES = 0x40;
+        /// Additional properties are in the Characteristic Extended Properties descriptor
+        const EXTENDED_PROPERTIES = 0x80;
+    }
+}
+
+/// A service discovered on a remote server
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DiscoveredService {
+    /// The handle of the service declaration
+    pub handle: AttHandle,
+    /// The last handle in this service
+    pub end_handle: AttHandle,
+    /// The service UUID
+    pub type_: Uuid,
+}
+
+/// An included service declaration discovered on a remote server
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DiscoveredIncludedService {
+    /// The handle of the include declaration
+    pub handle: AttHandle,
+    /// The service that is included
+    pub service: DiscoveredService,
+}
+
+/// A characteristic discovered on a remote server
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DiscoveredCharacteristic {
+    /// The handle of the characteristic declaration
+    pub handle: AttHandle,
+    /// The handle of the characteristic value
+    pub value_handle: AttHandle,
+    /// The properties of this characteristic
+    pub properties: CharacteristicProperties,
+    /// The characteristic UUID
+    pub type_: Uuid,
+}
+
+/// A characteristic descriptor discovered on a remote server
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DiscoveredDescriptor {
+    /// The handle of the descriptor
+    pub handle: AttHandle,
+    /// The descriptor UUID
+    pub type_: Uuid,
+}
+
+impl GattClient {
+    /// Discover all primary services on the server (Core Spec 5.3 Vol 3G 4.4.1)
+    pub async fn discover_primary_services(
+        &self,
+    ) -> Result<Vec<DiscoveredService>, AttClientError> {
+        self.discover_services_by_group_type(PRIMARY_SERVICE_DECLARATION_UUID).await
+    }
+
+    /// Discover all secondary services on the server, using the same
+    /// procedure as for primary services
+    pub async fn discover_secondary_services(
+        &self,
+    ) -> Result<Vec<DiscoveredService>, AttClientError> {
+        self.discover_services_by_group_type(SECONDARY_SERVICE_DECLARATION_UUID).await
+    }
+
+    /// Discover all primary services with the given UUID
+    /// (Core Spec 5.3 Vol 3G 4.4.2)
+    pub async fn discover_primary_services_by_uuid(
+        &self,
+        uuid: Uuid,
+    ) -> Result<Vec<DiscoveredService>, AttClientError> {
+        let mut out = vec![];
+        let mut starting_handle = AttHandle::MIN;
+        loop {
+            let response = self
+                .bearer
+                .send_request(AttFindByTypeValueRequestBuilder {
+                    starting_handle: starting_handle.into(),
+                    ending_handle: AttHandle::MAX.into(),
+                    attribute_type: Uuid16Builder::try_from(PRIMARY_SERVICE_DECLARATION_UUID)
+                        .expect("declaration UUIDs are always 16-bit"),
+                    attribute_value: build_att_data(uuid_as_att_data(uuid)),
+                })
+                .await;
+            let response = match response {
+                Err(err) if err.is_error_code(AttErrorCode::ATTRIBUTE_NOT_FOUND) => break,
+                response => response?,
+            };
+            let response = AttFindByTypeValueResponseView::try_parse(response.view())
+                .map_err(|_| AttClientError::InvalidResponse)?;
+
+            let mut last_handle = None;
+            for range in response.get_handles_info_iter() {
+                let handle = range.get_found_attribute_handle().into();
+                let end_handle = range.get_group_end_handle().into();
+                out.push(DiscoveredService { handle, end_handle, type_: uuid });
+                last_handle = Some(end_handle);
+            }
+            match next_starting_handle(starting_handle, last_handle)? {
+                Some(next) => starting_handle = next,
+                None => break,
+            }
+        }
+        Ok(out)
+    }
+
+    /// Find all services included by the given service
+    /// (Core Spec 5.3 Vol 3G 4.5.1)
+    pub async fn find_included_services(
+        &self,
+        service: &DiscoveredService,
+    ) -> Result<Vec<DiscoveredIncludedService>, AttClientError> {
+        let declarations = self
+            .read_by_type_in_range(service, INCLUDED_SERVICE_DECLARATION_UUID, |handle, value| {
+                let included = GattIncludedServiceDeclarationValueView::try_parse(value)
+                    .map_err(|_| AttClientError::InvalidResponse)?;
+                let uuid = included.get_uuid();
+                let type_ = if uuid.get_data_iter().next().is_some() {
+                    Some(parse_uuid(uuid)?)
+                } else {
+                    None
+                };
+                Ok((
+                    handle,
+                    AttHandle::from(included.get_handle()),
+                    AttHandle::from(included.get_end_group_handle()),
+                    type_,
+                ))
+            })
+            .await?;
+
+        let mut out = vec![];
+        for (handle, included_handle, end_handle, type_) in declarations {
+            let type_ = match type_ {
+                Some(type_) => type_,
+                // 128-bit UUIDs are omitted from the include declaration, so we must read
+                // them from the service declaration itself
+                None => self.read_service_uuid(included_handle).await?,
+            };
+            out.push(DiscoveredIncludedService {
+                handle,
+                service: DiscoveredService { handle: included_handle, end_handle, type_ },
+            });
+        }
+        Ok(out)
+    }
+
+    /// Discover all characteristics of the given service
+    /// (Core Spec 5.3 Vol 3G 4.6.1)
+    pub async fn discover_characteristics(
+        &self,
+        service: &DiscoveredService,
+    ) -> Result<Vec<DiscoveredCharacteristic>, AttClientError> {
+        self.read_by_type_in_range(service, CHARACTERISTIC_UUID, |handle, value| {
+            let declaration = GattCharacteristicDeclarationValueView::try_parse(value)
+                .map_err(|_| AttClientError::InvalidResponse)?;
+            let properties = declaration.get_properties();
+            let properties = [
+                (properties.get_broadcast(), CharacteristicProperties::BROADCAST),
+                (properties.get_read(), CharacteristicProperties::READ),
+                (
+                    properties.get_write_without_response(),
+                    CharacteristicProperties::WRITE_WITHOUT_RESPONSE,
+                ),
+                (properties.get_write(), CharacteristicProperties::WRITE),
+                (properties.get_notify(), CharacteristicProperties::NOTIFY),
+                (properties.get_indicate(), CharacteristicProperties::INDICATE),
+                (
+                    properties.get_authenticated_signed_writes(),
+                    CharacteristicProperties::AUTHENTICATED_SIGNED_WRITES,
+                ),
+                (
+                    properties.get_extended_properties(),
+                    CharacteristicProperties::EXTENDED_PROPERTIES,
+                ),
+            ]
+            .into_iter()
+            .filter(|(bit, _)| *bit != 0)
+            .fold(CharacteristicProperties::empty(), |acc, (_, flag)| acc | flag);
+            Ok(DiscoveredCharacteristic {
+                handle,
+                value_handle: declaration.get_handle().into(),
+                properties,
+                type_: parse_uuid(declaration.get_uuid())?,
+            })
+        })
+        .await
+    }
+
+    /// Discover all characteristics of the given service with the given UUID
+    /// (Core Spec 5.3 Vol 3G 4.6.2)
+    pub async fn discover_characteristics_by_uuid(
+        &self,
+        service: &DiscoveredService,
+        uuid: Uuid,
+    ) -> Result<Vec<DiscoveredCharacteristic>, AttClientError> {
+        Ok(self
+            .discover_characteristics(service)
+            .await?
+            .into_iter()
+            .filter(|characteristic| characteristic.type_ == uuid)
+            .collect())
+    }
+
+    /// Discover all descriptors of the given characteristic, which ends at
+    /// end_handle (i.e. just before the next characteristic declaration, or at
+    /// the end of the service) (Core Spec 5.3 Vol 3G 4.7.1)
+    pub async fn discover_descriptors(
+        &self,
+        characteristic: &DiscoveredCharacteristic,
+        end_handle: AttHandle,
+    ) -> Result<Vec<DiscoveredDescriptor>, AttClientError> {
+        let mut out = vec![];
+        let Some(mut starting_handle) = characteristic.value_handle.0.checked_add(1).map(AttHandle)
+        else {
+            return Ok(out);
+        };
+        while starting_handle <= end_handle {
+            let response = self
+                .bearer
+                .send_request(AttFindInformationRequestBuilder {
+                    starting_handle: starting_handle.into(),
+                    ending_handle: end_handle.into(),
+                })
+                .await;
+            let response = match response {
+                Err(err) if err.is_error_code(AttErrorCode::ATTRIBUTE_NOT_FOUND) => break,
+                response => response?,
+            };
+            let response = AttFindInformationResponseView::try_parse(response.view())
+                .map_err(|_| AttClientError::InvalidResponse)?;
+
+            let descriptors = match response.get_format() {
+                AttFindInformationResponseFormat::SHORT => {
+                    AttFindInformationShortResponseView::try_parse(response)
+                        .map_err(|_| AttClientError::InvalidResponse)?
+                        .get_data_iter()
+                        .map(|entry| DiscoveredDescriptor {
+                            handle: entry.get_handle().into(),
+                            type_: entry.get_uuid().into(),
+                        })
+                        .collect::<Vec<_>>()
+                }
+                AttFindInformationResponseFormat::LONG => {
+                    AttFindInformationLongResponseView::try_parse(response)
+                        .map_err(|_| AttClientError::InvalidResponse)?
+                        .get_data_iter()
+                        .map(|entry| DiscoveredDescriptor {
+                            handle: entry.get_handle().into(),
+                            type_: entry.get_uuid().into(),
+                        })
+                        .collect::<Vec<_>>()
+                }
+            };
+            let last_handle = descriptors.last().map(|descriptor| descriptor.handle);
+            out.extend(descriptors);
+            match next_starting_handle(starting_handle, last_handle)? {
+                Some(next) => starting_handle = next,
+                None => break,
+            }
+        }
+        Ok(out)
+    }
+
+    async fn discover_services_by_group_type(
+        &self,
+        group_type: Uuid,
+    ) -> Result<Vec<DiscoveredService>, AttClientError> {
+        let mut out = vec![];
+        let mut starting_handle = AttHandle::MIN;
+        loop {
+            let response = self
+                .bearer
+                .send_request(AttReadByGroupTypeRequestBuilder {
+                    starting_handle: starting_handle.into(),
+                    ending_handle: AttHandle::MAX.into(),
+                    attribute_group_type: group_type.into(),
+                })
+                .await;
+            let response = match response {
+                Err(err) if err.is_error_code(AttErrorCode::ATTRIBUTE_NOT_FOUND) => break,
+                response => response?,
+            };
+            let response = AttReadByGroupTypeResponseView::try_parse(response.view())
+                .map_err(|_| AttClientError::InvalidResponse)?;
+
+            let mut last_handle = None;
+            for element in response.get_data_iter() {
+                let declaration = GattServiceDeclarationValueView::try_parse(element.get_value())
+                    .map_err(|_| AttClientError::InvalidResponse)?;
+                let end_handle = element.get_end_group_handle().into();
+                out.push(DiscoveredService {
+                    handle: element.get_handle().into(),
+                    end_handle,
+                    type_: parse_uuid(declaration.get_uuid())?,
+                });
+                last_handle = Some(end_handle);
+            }
+            match next_starting_handle(starting_handle, last_handle)? {
+                Some(next) => starting_handle = next,
+                None => break,
+            }
+        }
+        Ok(out)
+    }
+
+    /// Read all attributes of the given type within the service, and parse
+    /// each of them
+    async fn read_by_type_in_range<T>(
+        &self,
+        service: &DiscoveredService,
+        type_: Uuid,
+        parse: impl Fn(AttHandle, AttAttributeDataView<'_>) -> Result<T, AttClientError>,
+    ) -> Result<Vec<T>, AttClientError> {
+        let mut out = vec![];
+        let mut starting_handle = service.handle;
+        while starting_handle <= service.end_handle {
+            let response = self
+                .bearer
+                .send_request(AttReadByTypeRequestBuilder {
+                    starting_handle: starting_handle.into(),
+                    ending_handle: service.end_handle.into(),
+                    attribute_type: type_.into(),
+                })
+                .await;
+            let response = match response {
+                Err(err) if err.is_error_code(AttErrorCode::ATTRIBUTE_NOT_FOUND) => break,
+                response => response?,
+            };
+            let response = AttReadByTypeResponseView::try_parse(response.view())
+                .map_err(|_| AttClientError::InvalidResponse)?;
+
+            let mut last_handle = None;
+            for element in response.get_data_iter() {
+                let handle = element.get_handle().into();
+                out.push(parse(handle, element.get_value())?);
+                last_handle = Some(handle);
+            }
+            match next_starting_handle(starting_handle, last_handle)? {
+                Some(next) => starting_handle = next,
+                None => break,
+            }
+        }
+        Ok(out)
+    }
+
+    async fn read_service_uuid(&self, handle: AttHandle) -> Result<Uuid, AttClientError> {
+        let response = self
+            .bearer
+            .send_request(AttReadRequestBuilder { attribute_handle: handle.into() })
+            .await?;
+        let response = AttReadResponseView::try_parse(response.view())
+            .map_err(|_| AttClientError::InvalidResponse)?;
+        let declaration = GattServiceDeclarationValueView::try_parse(response.get_value())
+            .map_err(|_| AttClientError::InvalidResponse)?;
+        parse_uuid(declaration.get_uuid())
+    }
+}
+
+/// Determine where the next request of a discovery procedure should start,
+/// or None if the procedure is complete
+fn next_starting_handle(
+    starting_handle: AttHandle,
+    last_handle: Option<AttHandle>,
+) -> Result<Option<AttHandle>, AttClientError> {
+    let Some(last_handle) = last_handle else {
+        warn!("got an empty response during discovery");
+        return Err(AttClientError::InvalidResponse);
+    };
+    if last_handle < starting_handle {
+        // otherwise a misbehaving server could make us loop forever
+        warn!("got {last_handle:?} during discovery starting at {starting_handle:?}");
+        return Err(AttClientError::InvalidResponse);
+    }
+    Ok(last_handle.0.checked_add(1).map(AttHandle))
+}
+
+fn parse_uuid(uuid: UuidView<'_>) -> Result<Uuid, AttClientError> {
+    let uuid_len = uuid.get_data_iter().count();
+    if uuid_len != 2 && uuid_len != 16 {
+        warn!("got UUID of invalid length {uuid_len}");
+        return Err(AttClientError::InvalidResponse);
+    }
+    uuid.try_into().map_err(|_| AttClientError::InvalidResponse)
+}
+
+/// UUIDs are sent in their 16-bit form where possible (Core Spec 5.3 Vol 3G
+/// 4.4.2)
+fn uuid_as_att_data(uuid: Uuid) -> AttAttributeDataChild {
+    match Uuid16Builder::try_from(uuid) {
+        Ok(uuid) => AttAttributeDataChild::RawData(uuid.data.to_le_bytes().into()),
+        Err(uuid) => UuidAsAttDataBuilder { uuid: uuid.into() }.into(),
+    }
+}
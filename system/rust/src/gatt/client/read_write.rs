@@ -0,0 +1,500 @@
+//! This module implements the GATT client read and write procedures
+//! (Core Spec 5.3 Vol 3G 4.8-4.9)
+
+use log::warn;
+
+use crate::{
+    gatt::ids::AttHandle,
+    packets::{
+        AttAttributeDataChild, AttErrorCode, AttExecuteWriteFlags, AttExecuteWriteRequestBuilder,
+        AttPrepareWriteRequestBuilder, AttPrepareWriteResponseView, AttReadBlobRequestBuilder,
+        AttReadBlobResponseView, AttReadRequestBuilder, AttReadResponseView,
+        AttWriteCommandBuilder, AttWriteRequestBuilder, AttWriteResponseView, Packet,
+    },
+    utils::packet::build_att_data,
+};
+
+use super::{att_client_bearer::AttClientError, GattClient};
+
+impl GattClient {
+    /// Read a characteristic value or descriptor (Core Spec 5.3 Vol 3G 4.8.1).
+    /// At most ATT_MTU-1 bytes are returned.
+    pub async fn read(&self, handle: AttHandle) -> Result<Box<[u8]>, AttClientError> {
+        let response = self
+            .bearer
+            .send_request(AttReadRequestBuilder { attribute_handle: handle.into() })
+            .await?;
+        let response = AttReadResponseView::try_parse(response.view())
+            .map_err(|_| AttClientError::InvalidResponse)?;
+        Ok(response.get_value().get_raw_payload().collect())
+    }
+
+    /// Read a characteristic value or descriptor that may be longer than
+    /// ATT_MTU-1 bytes (Core Spec 5.3 Vol 3G 4.8.3)
+    pub async fn read_long(&self, handle: AttHandle) -> Result<Box<[u8]>, AttClientError> {
+        let mtu = self.mtu()?;
+        let mut value = self.read(handle).await?.into_vec();
+        // a response shorter than ATT_MTU-1 means we have the entire value
+        let mut last_chunk_len = value.len();
+        while last_chunk_len == mtu - 1 {
+            let Ok(offset) = u16::try_from(value.len()) else {
+                warn!("value of {handle:?} exceeds the maximum attribute length");
+                return Err(AttClientError::InvalidResponse);
+            };
+            let response = self
+                .bearer
+                .send_request(AttReadBlobRequestBuilder { attribute_handle: handle.into(), offset })
+                .await;
+            let response = match response {
+                // the value happened to be exactly ATT_MTU-1 bytes long
+                Err(err) if err.is_error_code(AttErrorCode::ATTRIBUTE_NOT_LONG) => break,
+                response => response?,
+            };
+            let response = AttReadBlobResponseView::try_parse(response.view())
+                .map_err(|_| AttClientError::InvalidResponse)?;
+            let chunk = response.get_value().get_raw_payload().collect::<Vec<_>>();
+            last_chunk_len = chunk.len();
+            value.extend(chunk);
+        }
+        Ok(value.into_boxed_slice())
+    }
+
+    /// Write a characteristic value or descriptor, and wait for the server to
+    /// acknowledge it (Core Spec 5.3 Vol 3G 4.9.3)
+    pub async fn write(&self, handle: AttHandle, value: &[u8]) -> Result<(), AttClientError> {
+        // As per Core Spec 5.3 Vol 3F 3.4.5.1, the value must be at most ATT_MTU-3
+        let mtu = self.mtu()?;
+        if value.len() > mtu - 3 {
+            return Err(AttClientError::DataExceedsMtu { mtu: mtu - 3 });
+        }
+        let response = self
+            .bearer
+            .send_request(AttWriteRequestBuilder {
+                handle: handle.into(),
+                value: build_att_data(AttAttributeDataChild::RawData(value.into())),
+            })
+            .await?;
+        AttWriteResponseView::try_parse(response.view())
+            .map_err(|_| AttClientError::InvalidResponse)?;
+        Ok(())
+    }
+
+    /// Write a characteristic value without waiting for any acknowledgement
+    /// (Core Spec 5.3 Vol 3G 4.9.1)
+    pub fn write_without_response(
+        &self,
+        handle: AttHandle,
+        value: &[u8],
+    ) -> Result<(), AttClientError> {
+        // As per Core Spec 5.3 Vol 3F 3.4.5.3, the value must be at most ATT_MTU-3
+        let mtu = self.mtu()?;
+        if value.len() > mtu - 3 {
+            return Err(AttClientError::DataExceedsMtu { mtu: mtu - 3 });
+        }
+        self.bearer.send_command(AttWriteCommandBuilder {
+            handle: handle.into(),
+            value: build_att_data(AttAttributeDataChild::RawData(value.into())),
+        })
+    }
+
+    /// Write a characteristic value or descriptor that may be longer than
+    /// ATT_MTU-3 bytes, using queued writes (Core Spec 5.3 Vol 3G 4.9.4)
+    pub async fn write_long(&self, handle: AttHandle, value: &[u8]) -> Result<(), AttClientError> {
+        self.reliable_write(&[(handle, value)]).await
+    }
+
+    /// Atomically write several values, verifying that the server received
+    /// each of them correctly before committing (Core Spec 5.3 Vol 3G
+    /// 4.9.5). If any write fails, all queued writes are cancelled.
+    pub async fn reliable_write(
+        &self,
+        writes: &[(AttHandle, &[u8])],
+    ) -> Result<(), AttClientError> {
+        if let Err(err) = self.prepare_writes(writes).await {
+            // the queue may be partially populated, so we clear it, but the original error
+            // is more interesting than any failure to cancel
+            if let Err(cancel_err) = self.execute_write(AttExecuteWriteFlags::CANCEL).await {
+                warn!("failed to cancel queued writes {cancel_err:?}");
+            }
+            return Err(err);
+        }
+        self.execute_write(AttExecuteWriteFlags::EXECUTE).await
+    }
+
+    async fn prepare_writes(&self, writes: &[(AttHandle, &[u8])]) -> Result<(), AttClientError> {
+        // As per Core Spec 5.3 Vol 3F 3.4.6.1, each part must be at most ATT_MTU-5
+        let chunk_size = self.mtu()? - 5;
+        for (handle, value) in writes {
+            // even empty values must be prepared, so that they are written on execute
+            let chunks = value.chunks(chunk_size).map(Some).chain(value.is_empty().then_some(None));
+            for (i, chunk) in chunks.enumerate() {
+                let chunk = chunk.unwrap_or_default();
+                let Ok(offset) = u16::try_from(i * chunk_size) else {
+                    return Err(AttClientError::DataExceedsMtu { mtu: u16::MAX as usize });
+                };
+                let response = self
+                    .bearer
+                    .send_request(AttPrepareWriteRequestBuilder {
+                        handle: (*handle).into(),
+                        offset,
+                        value: build_att_data(AttAttributeDataChild::RawData(chunk.into())),
+                    })
+                    .await?;
+                let response = AttPrepareWriteResponseView::try_parse(response.view())
+                    .map_err(|_| AttClientError::InvalidResponse)?;
+                if AttHandle::from(response.get_handle()) != *handle
+                    || response.get_offset() != offset
+                    || !response.get_value().get_raw_payload().eq(chunk.iter().copied())
+                {
+                    warn!(
+                        "server did not echo back prepared write to {handle:?} at offset {offset}"
+                    );
+                    return Err(AttClientError::PrepareWriteMismatch);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn execute_write(&self, flags: AttExecuteWriteFlags) -> Result<(), AttClientError> {
+        self.bearer.send_request(AttExecuteWriteRequestBuilder { flags }).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{
+        sync::mpsc::{error::TryRecvError, UnboundedReceiver},
+        task::spawn_local,
+    };
+
+    use super::*;
+
+    use crate::{
+        core::shared_box::SharedBox,
+        gatt::client::att_client_bearer::AttClientBearer,
+        packets::{
+            AttBuilder, AttChild, AttErrorResponseBuilder, AttExecuteWriteResponseBuilder,
+            AttOpcode, AttPrepareWriteResponseBuilder, AttReadBlobResponseBuilder,
+            AttReadResponseBuilder, AttWriteResponseBuilder, Serializable,
+        },
+        utils::{
+            packet::build_att_view_or_crash,
+            task::{block_on_locally, try_await},
+        },
+    };
+
+    const HANDLE: AttHandle = AttHandle(3);
+    const ANOTHER_HANDLE: AttHandle = AttHandle(5);
+
+    // with the default MTU (23), each prepared write carries 18 bytes
+    const CHUNK_SIZE: usize = 18;
+
+    fn open_client() -> (SharedBox<AttClientBearer>, GattClient, UnboundedReceiver<AttBuilder>) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let bearer = SharedBox::new(AttClientBearer::new(move |packet| {
+            tx.send(packet).unwrap();
+            Ok(())
+        }));
+        let client = GattClient::new(bearer.downgrade());
+        (bearer, client, rx)
+    }
+
+    fn raw(data: &[u8]) -> AttAttributeDataChild {
+        AttAttributeDataChild::RawData(data.into())
+    }
+
+    fn echo_prepare_write(packet: &AttBuilder) -> AttPrepareWriteResponseBuilder {
+        let AttChild::AttPrepareWriteRequest(request) = &packet._child_ else {
+            unreachable!("{packet:?}")
+        };
+        AttPrepareWriteResponseBuilder {
+            handle: request.handle.clone(),
+            offset: request.offset,
+            value: request.value.clone(),
+        }
+    }
+
+    #[test]
+    fn test_read() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, client, mut rx) = open_client();
+
+            // act
+            let pending_read = spawn_local(async move { client.read(HANDLE).await });
+            let request = rx.recv().await.unwrap();
+            bearer.as_ref().handle_packet(
+                build_att_view_or_crash(AttReadResponseBuilder {
+                    value: build_att_data(raw(&[1, 2])),
+                })
+                .view(),
+            );
+
+            // assert
+            assert_eq!(request.opcode, AttOpcode::READ_REQUEST);
+            assert_eq!(pending_read.await.unwrap().unwrap(), [1, 2].into());
+        });
+    }
+
+    #[test]
+    fn test_read_long() {
+        block_on_locally(async {
+            // arrange: a value of ATT_MTU-1 + 4 bytes
+            let (bearer, client, mut rx) = open_client();
+            let value = (0..26).collect::<Vec<u8>>();
+
+            // act
+            let pending_read = spawn_local(async move { client.read_long(HANDLE).await });
+            assert_eq!(rx.recv().await.unwrap().opcode, AttOpcode::READ_REQUEST);
+            bearer.as_ref().handle_packet(
+                build_att_view_or_crash(AttReadResponseBuilder {
+                    value: build_att_data(raw(&value[..22])),
+                })
+                .view(),
+            );
+            let blob_request = rx.recv().await.unwrap();
+            bearer.as_ref().handle_packet(
+                build_att_view_or_crash(AttReadBlobResponseBuilder {
+                    value: build_att_data(raw(&value[22..])),
+                })
+                .view(),
+            );
+
+            // assert
+            assert_eq!(
+                blob_request._child_,
+                AttReadBlobRequestBuilder { attribute_handle: HANDLE.into(), offset: 22 }.into()
+            );
+            assert_eq!(pending_read.await.unwrap().unwrap(), value.into());
+            assert_eq!(rx.try_recv().unwrap_err(), TryRecvError::Empty);
+        });
+    }
+
+    #[test]
+    fn test_read_long_attribute_not_long() {
+        block_on_locally(async {
+            // arrange: a value of exactly ATT_MTU-1 bytes
+            let (bearer, client, mut rx) = open_client();
+            let value = [1; 22];
+
+            // act
+            let pending_read = spawn_local(async move { client.read_long(HANDLE).await });
+            rx.recv().await.unwrap();
+            bearer.as_ref().handle_packet(
+                build_att_view_or_crash(AttReadResponseBuilder {
+                    value: build_att_data(raw(&value)),
+                })
+                .view(),
+            );
+            assert_eq!(rx.recv().await.unwrap().opcode, AttOpcode::READ_BLOB_REQUEST);
+            bearer.as_ref().handle_packet(
+                build_att_view_or_crash(AttErrorResponseBuilder {
+                    opcode_in_error: AttOpcode::READ_BLOB_REQUEST,
+                    handle_in_error: HANDLE.into(),
+                    error_code: AttErrorCode::ATTRIBUTE_NOT_LONG,
+                })
+                .view(),
+            );
+
+            // assert
+            assert_eq!(pending_read.await.unwrap().unwrap(), value.into());
+        });
+    }
+
+    #[test]
+    fn test_write() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, client, mut rx) = open_client();
+
+            // act
+            let pending_write = spawn_local(async move { client.write(HANDLE, &[1, 2]).await });
+            let request = rx.recv().await.unwrap();
+            bearer
+                .as_ref()
+                .handle_packet(build_att_view_or_crash(AttWriteResponseBuilder {}).view());
+
+            // assert
+            assert_eq!(
+                request._child_,
+                AttWriteRequestBuilder {
+                    handle: HANDLE.into(),
+                    value: build_att_data(raw(&[1, 2]))
+                }
+                .into()
+            );
+            assert!(pending_write.await.unwrap().is_ok());
+        });
+    }
+
+    #[test]
+    fn test_write_exceeding_mtu() {
+        block_on_locally(async {
+            // arrange
+            let (_bearer, client, mut rx) = open_client();
+
+            // act
+            let res = client.write(HANDLE, &[0; 21]).await;
+
+            // assert
+            assert!(matches!(res, Err(AttClientError::DataExceedsMtu { mtu: 20 })));
+            assert_eq!(rx.try_recv().unwrap_err(), TryRecvError::Empty);
+        });
+    }
+
+    #[test]
+    fn test_write_without_response() {
+        // arrange
+        let (_bearer, client, mut rx) = open_client();
+
+        // act
+        client.write_without_response(HANDLE, &[1, 2]).unwrap();
+
+        // assert
+        assert_eq!(rx.try_recv().unwrap().opcode, AttOpcode::WRITE_COMMAND);
+    }
+
+    #[test]
+    fn test_write_long() {
+        block_on_locally(async {
+            // arrange: a value spanning two prepared writes
+            let (bearer, client, mut rx) = open_client();
+            let value = (0..(CHUNK_SIZE + 4) as u8).collect::<Vec<_>>();
+            let expected = value.clone();
+
+            // act
+            let pending_write = spawn_local(async move { client.write_long(HANDLE, &value).await });
+            let mut prepared = vec![];
+            for _ in 0..2 {
+                let request = rx.recv().await.unwrap();
+                bearer
+                    .as_ref()
+                    .handle_packet(build_att_view_or_crash(echo_prepare_write(&request)).view());
+                prepared.push(request);
+            }
+            let execute = rx.recv().await.unwrap();
+            bearer
+                .as_ref()
+                .handle_packet(build_att_view_or_crash(AttExecuteWriteResponseBuilder {}).view());
+
+            // assert
+            assert_eq!(
+                prepared[0]._child_,
+                AttPrepareWriteRequestBuilder {
+                    handle: HANDLE.into(),
+                    offset: 0,
+                    value: build_att_data(raw(&expected[..CHUNK_SIZE]))
+                }
+                .into()
+            );
+            assert_eq!(
+                prepared[1]._child_,
+                AttPrepareWriteRequestBuilder {
+                    handle: HANDLE.into(),
+                    offset: CHUNK_SIZE as u16,
+                    value: build_att_data(raw(&expected[CHUNK_SIZE..]))
+                }
+                .into()
+            );
+            assert_eq!(
+                execute._child_,
+                AttExecuteWriteRequestBuilder { flags: AttExecuteWriteFlags::EXECUTE }.into()
+            );
+            assert!(pending_write.await.unwrap().is_ok());
+        });
+    }
+
+    #[test]
+    fn test_reliable_write_mismatch_cancels() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, client, mut rx) = open_client();
+
+            // act: echo back a corrupted value for the second write
+            let pending_write = spawn_local(async move {
+                client.reliable_write(&[(HANDLE, &[1, 2][..]), (ANOTHER_HANDLE, &[3, 4][..])]).await
+            });
+            let request = rx.recv().await.unwrap();
+            bearer
+                .as_ref()
+                .handle_packet(build_att_view_or_crash(echo_prepare_write(&request)).view());
+            let request = rx.recv().await.unwrap();
+            let mut response = echo_prepare_write(&request);
+            response.value = build_att_data(raw(&[3, 5]));
+            bearer.as_ref().handle_packet(build_att_view_or_crash(response).view());
+            let cancel = rx.recv().await.unwrap();
+            bearer
+                .as_ref()
+                .handle_packet(build_att_view_or_crash(AttExecuteWriteResponseBuilder {}).view());
+
+            // assert
+            assert_eq!(
+                cancel._child_,
+                AttExecuteWriteRequestBuilder { flags: AttExecuteWriteFlags::CANCEL }.into()
+            );
+            assert!(matches!(
+                pending_write.await.unwrap(),
+                Err(AttClientError::PrepareWriteMismatch)
+            ));
+        });
+    }
+
+    #[test]
+    fn test_reliable_write_error_cancels() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, client, mut rx) = open_client();
+
+            // act
+            let pending_write =
+                spawn_local(async move { client.reliable_write(&[(HANDLE, &[1, 2][..])]).await });
+            rx.recv().await.unwrap();
+            bearer.as_ref().handle_packet(
+                build_att_view_or_crash(AttErrorResponseBuilder {
+                    opcode_in_error: AttOpcode::PREPARE_WRITE_REQUEST,
+                    handle_in_error: HANDLE.into(),
+                    error_code: AttErrorCode::PREPARE_QUEUE_FULL,
+                })
+                .view(),
+            );
+            let cancel = rx.recv().await.unwrap();
+            bearer
+                .as_ref()
+                .handle_packet(build_att_view_or_crash(AttExecuteWriteResponseBuilder {}).view());
+
+            // assert
+            assert_eq!(cancel.opcode, AttOpcode::EXECUTE_WRITE_REQUEST);
+            assert!(pending_write
+                .await
+                .unwrap()
+                .unwrap_err()
+                .is_error_code(AttErrorCode::PREPARE_QUEUE_FULL));
+        });
+    }
+
+    #[test]
+    fn test_write_long_empty_value() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, client, mut rx) = open_client();
+
+            // act
+            let pending_write =
+                try_await(async move { client.write_long(HANDLE, &[]).await }).await.unwrap_err();
+            let request = rx.recv().await.unwrap();
+            bearer
+                .as_ref()
+                .handle_packet(build_att_view_or_crash(echo_prepare_write(&request)).view());
+            rx.recv().await.unwrap();
+            bearer
+                .as_ref()
+                .handle_packet(build_att_view_or_crash(AttExecuteWriteResponseBuilder {}).view());
+
+            // assert: a single empty part was prepared
+            assert_eq!(request.size_in_bits().unwrap(), 5 * 8);
+            assert!(pending_write.await.is_ok());
+        });
+    }
+}
@@ -0,0 +1,234 @@
+//! This module implements subscription to characteristic value notifications
+//! and indications (Core Spec 5.3 Vol 3G 4.10-4.11), including management of
+//! the Client Characteristic Configuration descriptor (CCCD)
+
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::{
+    gatt::ids::AttHandle,
+    packets::{
+        AttWriteRequestBuilder, AttWriteResponseView, GattClientCharacteristicConfigurationBuilder,
+        Packet,
+    },
+    utils::packet::build_att_data,
+};
+
+use super::{
+    att_client_bearer::{AttClientError, AttValueUpdate, ValueUpdateKind},
+    discovery::{CharacteristicProperties, DiscoveredCharacteristic},
+    GattClient,
+};
+
+impl GattClient {
+    /// Subscribe to notifications or indications of a characteristic value.
+    /// The CCCD at cccd_handle is written to enable every kind of update that
+    /// currently has a subscriber on this characteristic.
+    ///
+    /// Updates are delivered until the returned receiver is dropped. To also
+    /// disable them on the server, pass the receiver to unsubscribe().
+    pub async fn subscribe(
+        &self,
+        characteristic: &DiscoveredCharacteristic,
+        cccd_handle: AttHandle,
+        kind: ValueUpdateKind,
+    ) -> Result<UnboundedReceiver<AttValueUpdate>, AttClientError> {
+        let required_property = match kind {
+            ValueUpdateKind::Notification => CharacteristicProperties::NOTIFY,
+            ValueUpdateKind::Indication => CharacteristicProperties::INDICATE,
+        };
+        if !characteristic.properties.contains(required_property) {
+            return Err(AttClientError::NotSupportedByCharacteristic);
+        }
+
+        // register first, so we don't miss any updates sent as soon as the CCCD is written
+        let listener = self.with_bearer(|bearer| {
+            bearer.register_value_listener(characteristic.value_handle, kind)
+        })?;
+        // if this fails, the listener is dropped and will be pruned next time
+        self.write_cccd(characteristic.value_handle, cccd_handle).await?;
+        Ok(listener)
+    }
+
+    /// Drop a subscription, and update the CCCD so the server only sends the
+    /// kinds of updates that still have subscribers
+    pub async fn unsubscribe(
+        &self,
+        characteristic: &DiscoveredCharacteristic,
+        cccd_handle: AttHandle,
+        listener: UnboundedReceiver<AttValueUpdate>,
+    ) -> Result<(), AttClientError> {
+        drop(listener);
+        self.write_cccd(characteristic.value_handle, cccd_handle).await
+    }
+
+    async fn write_cccd(
+        &self,
+        value_handle: AttHandle,
+        cccd_handle: AttHandle,
+    ) -> Result<(), AttClientError> {
+        let kinds = self.with_bearer(|bearer| bearer.active_value_listeners(value_handle))?;
+        let response = self
+            .bearer
+            .send_request(AttWriteRequestBuilder {
+                handle: cccd_handle.into(),
+                value: build_att_data(GattClientCharacteristicConfigurationBuilder {
+                    notification: kinds.contains(&ValueUpdateKind::Notification).into(),
+                    indication: kinds.contains(&ValueUpdateKind::Indication).into(),
+                }),
+            })
+            .await?;
+        AttWriteResponseView::try_parse(response.view())
+            .map_err(|_| AttClientError::InvalidResponse)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{
+        sync::mpsc::{error::TryRecvError, unbounded_channel},
+        task::spawn_local,
+    };
+
+    use super::*;
+
+    use crate::{
+        core::{shared_box::SharedBox, uuid::Uuid},
+        gatt::client::att_client_bearer::AttClientBearer,
+        packets::{
+            AttAttributeDataChild, AttBuilder, AttChild, AttHandleValueNotificationBuilder,
+            AttWriteResponseBuilder,
+        },
+        utils::{packet::build_att_view_or_crash, task::block_on_locally},
+    };
+
+    const CHARACTERISTIC: DiscoveredCharacteristic = DiscoveredCharacteristic {
+        handle: AttHandle(2),
+        value_handle: AttHandle(3),
+        properties: CharacteristicProperties::NOTIFY.union(CharacteristicProperties::INDICATE),
+        type_: Uuid::new(0x1234),
+    };
+    const CCCD_HANDLE: AttHandle = AttHandle(4);
+
+    fn open_client() -> (SharedBox<AttClientBearer>, GattClient, UnboundedReceiver<AttBuilder>) {
+        let (tx, rx) = unbounded_channel();
+        let bearer = SharedBox::new(AttClientBearer::new(move |packet| {
+            tx.send(packet).unwrap();
+            Ok(())
+        }));
+        let client = GattClient::new(bearer.downgrade());
+        (bearer, client, rx)
+    }
+
+    fn cccd_write(notification: u8, indication: u8) -> AttChild {
+        AttWriteRequestBuilder {
+            handle: CCCD_HANDLE.into(),
+            value: build_att_data(GattClientCharacteristicConfigurationBuilder {
+                notification,
+                indication,
+            }),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_subscribe() {
+        block_on_locally(async {
+            // arrange
+            let (bearer, client, mut rx) = open_client();
+
+            // act
+            let pending_subscription = spawn_local(async move {
+                client.subscribe(&CHARACTERISTIC, CCCD_HANDLE, ValueUpdateKind::Notification).await
+            });
+            let request = rx.recv().await.unwrap();
+            bearer
+                .as_ref()
+                .handle_packet(build_att_view_or_crash(AttWriteResponseBuilder {}).view());
+            let mut listener = pending_subscription.await.unwrap().unwrap();
+            bearer.as_ref().handle_packet(
+                build_att_view_or_crash(AttHandleValueNotificationBuilder {
+                    handle: CHARACTERISTIC.value_handle.into(),
+                    value: build_att_data(AttAttributeDataChild::RawData([1].into())),
+                })
+                .view(),
+            );
+
+            // assert
+            assert_eq!(request._child_, cccd_write(1, 0));
+            assert_eq!(listener.recv().await.unwrap().value, [1].into());
+        });
+    }
+
+    #[test]
+    fn test_subscribe_unions_flags() {
+        block_on_locally(async {
+            // arrange: an existing notification subscription
+            let (bearer, client, mut rx) = open_client();
+            let _notifications = bearer.register_value_listener(
+                CHARACTERISTIC.value_handle,
+                ValueUpdateKind::Notification,
+            );
+
+            // act
+            let pending_subscription = spawn_local(async move {
+                client.subscribe(&CHARACTERISTIC, CCCD_HANDLE, ValueUpdateKind::Indication).await
+            });
+            let request = rx.recv().await.unwrap();
+            bearer
+                .as_ref()
+                .handle_packet(build_att_view_or_crash(AttWriteResponseBuilder {}).view());
+            pending_subscription.await.unwrap().unwrap();
+
+            // assert: both notifications and indications are enabled
+            assert_eq!(request._child_, cccd_write(1, 1));
+        });
+    }
+
+    #[test]
+    fn test_unsubscribe_keeps_remaining_flags() {
+        block_on_locally(async {
+            // arrange: a notification and an indication subscription
+            let (bearer, client, mut rx) = open_client();
+            let _notifications = bearer.register_value_listener(
+                CHARACTERISTIC.value_handle,
+                ValueUpdateKind::Notification,
+            );
+            let indications = bearer
+                .register_value_listener(CHARACTERISTIC.value_handle, ValueUpdateKind::Indication);
+
+            // act: drop the indication subscription
+            let pending_unsubscription = spawn_local(async move {
+                client.unsubscribe(&CHARACTERISTIC, CCCD_HANDLE, indications).await
+            });
+            let request = rx.recv().await.unwrap();
+            bearer
+                .as_ref()
+                .handle_packet(build_att_view_or_crash(AttWriteResponseBuilder {}).view());
+            pending_unsubscription.await.unwrap().unwrap();
+
+            // assert: only notifications are still enabled
+            assert_eq!(request._child_, cccd_write(1, 0));
+        });
+    }
+
+    #[test]
+    fn test_subscribe_unsupported() {
+        block_on_locally(async {
+            // arrange
+            let (_bearer, client, mut rx) = open_client();
+            let characteristic = DiscoveredCharacteristic {
+                properties: CharacteristicProperties::NOTIFY,
+                ..CHARACTERISTIC
+            };
+
+            // act
+            let res =
+                client.subscribe(&characteristic, CCCD_HANDLE, ValueUpdateKind::Indication).await;
+
+            // assert
+            assert!(matches!(res, Err(AttClientError::NotSupportedByCharacteristic)));
+            assert_eq!(rx.try_recv().unwrap_err(), TryRecvError::Empty);
+        });
+    }
+}
@@ -13,7 +13,7 @@ use log::{error, info, trace, warn};
 use tokio::task::spawn_local;
 
 use crate::{
-    do_in_rust_thread,
+    do_in_rust_thread, dump_from_rust_thread,
     packets::{
         AttAttributeDataChild, AttAttributeDataView, AttBuilder, AttErrorCode, Serializable,
         SerializeError,
@@ -28,7 +28,12 @@ use super::{
     server::{
         gatt_database::{
             AttPermissions, GattCharacteristicWithHandle, GattDescriptorWithHandle,
-            GattServiceWithHandle,
+            GattIncludedServiceWithHandle, GattServiceWithHandle,
+            CHARACTERISTIC_EXTENDED_PROPERTIES_UUID,
+        },
+        services::{
+            gap::{self, GapCallbacks, PreferredConnectionParameters},
+            gatt::{BondedClientState, BondedClientStorage},
         },
         IndicationError,
     },
@@ -49,6 +54,11 @@ mod inner {
         type Uuid = crate::core::uuid::Uuid;
     }
 
+    #[namespace = "bluetooth::core"]
+    extern "C++" {
+        type AddressWithType = crate::core::address::AddressWithType;
+    }
+
     /// The GATT entity backing the value of a user-controlled
     /// attribute
     #[derive(Debug)]
@@ -102,10 +112,37 @@ mod inner {
         #[cxx_name = "OnExecute"]
         fn on_execute(self: &GattServerCallbacks, conn_id: u16, trans_id: u32, execute: bool);
 
+        /// This callback is invoked when a transaction will no longer be
+        /// answered, since it timed out or its connection went away
+        #[cxx_name = "OnTransactionCancelled"]
+        fn on_transaction_cancelled(self: &GattServerCallbacks, conn_id: u16, trans_id: u32);
+
         /// This callback is invoked when an indication has been sent and the
         /// peer device has confirmed it, or if some error occurred.
         #[cxx_name = "OnIndicationSentConfirmation"]
         fn on_indication_sent_confirmation(self: &GattServerCallbacks, conn_id: u16, status: i32);
+
+        /// Invoked when a peer writes the Device Name of the GAP service. It
+        /// renames the adapter if `persist` is set.
+        #[cxx_name = "OnGapDeviceNameWritten"]
+        fn on_gap_device_name_written(name: &[u8], persist: bool);
+
+        /// Invoked when a peer writes the Appearance of the GAP service
+        #[cxx_name = "OnGapAppearanceWritten"]
+        fn on_gap_appearance_written(appearance: u16);
+
+        /// Load the persisted state of a bonded client of the GATT service
+        /// (empty if there is none)
+        #[cxx_name = "LoadGattClientState"]
+        fn load_gatt_client_state(identity: AddressWithType) -> Vec<u8>;
+
+        /// Persist the state of a bonded client of the GATT service
+        #[cxx_name = "StoreGattClientState"]
+        fn store_gatt_client_state(identity: AddressWithType, state: &[u8]);
+
+        /// Remove the persisted state of a bonded client of the GATT service
+        #[cxx_name = "RemoveGattClientState"]
+        fn remove_gatt_client_state(identity: AddressWithType);
     }
 
     /// What action the arbiter should take in response to an incoming packet
@@ -119,6 +156,44 @@ mod inner {
         Drop = 1u32,
     }
 
+    /// How connections to an advertiser are isolated from the shared database
+    #[derive(Debug)]
+    #[namespace = "bluetooth::gatt"]
+    enum IsolationPolicy {
+        /// The peer only sees the databases of the servers associated with the advertiser
+        Isolated,
+        /// As above, but the GAP service is served by the shared database
+        IsolatedWithSharedServices,
+    }
+
+    /// The values served by the GAP service of the Rust GATT servers
+    #[namespace = "bluetooth::gatt"]
+    struct GapConfiguration {
+        device_name: Vec<u8>,
+        /// If false, peers cannot read the device name
+        device_name_readable: bool,
+        device_name_writable: bool,
+        /// If true, a name written by a peer renames the adapter persistently
+        persist_written_device_name: bool,
+        appearance: u16,
+        appearance_writable: bool,
+
+        /// If false, the Peripheral Preferred Connection Parameters
+        /// characteristic is absent
+        has_preferred_connection_parameters: bool,
+        min_interval: u16,
+        max_interval: u16,
+        peripheral_latency: u16,
+        supervision_timeout: u16,
+
+        /// If false, the Central Address Resolution characteristic is absent
+        has_central_address_resolution: bool,
+        central_address_resolution: bool,
+
+        /// If true, the Resolvable Private Address Only characteristic is present
+        rpa_only: bool,
+    }
+
     /// The type of GATT record supplied over FFI
     #[derive(Debug)]
     #[namespace = "bluetooth::gatt"]
@@ -137,6 +212,8 @@ mod inner {
         uuid: Uuid,
         record_type: GattRecordType,
         attribute_handle: u16,
+        /// For included services, the handle of the included service declaration
+        start_handle: u16,
 
         properties: u8,
         extended_properties: u16,
@@ -153,6 +230,8 @@ mod inner {
         fn StoreCallbacksFromRust(
             on_le_connect: fn(tcb_idx: u8, advertiser: u8),
             on_le_disconnect: fn(tcb_idx: u8),
+            on_le_bonded: fn(tcb_idx: u8, identity: AddressWithType),
+            on_bond_removed: fn(identity: AddressWithType),
             intercept_packet: fn(tcb_idx: u8, packet: Vec<u8>) -> InterceptAction,
             on_outgoing_mtu_req: fn(tcb_idx: u8),
             on_incoming_mtu_resp: fn(tcb_idx: u8, mtu: usize),
@@ -161,6 +240,10 @@ mod inner {
 
         /// Send an outgoing packet on the specified tcb_idx
         fn SendPacketToPeer(tcb_idx: u8, packet: Vec<u8>);
+
+        /// Look up the handles of the GAP service in the shared (legacy)
+        /// database. Returns false if it is not registered.
+        fn GetSharedGapServiceHandles(start_handle: &mut u16, end_handle: &mut u16) -> bool;
     }
 
     #[namespace = "bluetooth::gatt"]
@@ -177,10 +260,19 @@ mod inner {
 
         // connection
         fn is_connection_isolated(conn_id: u16) -> bool;
+        fn hand_over_connection(server_id: u8, conn_id: u16);
+
+        // GAP service
+        fn set_gap_configuration(configuration: GapConfiguration);
+        fn set_gap_device_name(name: &[u8]);
 
         // arbitration
         fn associate_server_with_advertiser(server_id: u8, advertiser_id: u8);
+        fn set_advertiser_isolation_policy(advertiser_id: u8, policy: IsolationPolicy);
         fn clear_advertiser(advertiser_id: u8);
+
+        // debugging
+        fn dump_att_metrics() -> String;
     }
 }
 
@@ -265,6 +357,51 @@ impl GattCallbacks for GattCallbacksImpl {
             },
         )
     }
+
+    fn on_transaction_cancelled(&self, conn_id: ConnectionId, trans_id: TransactionId) {
+        trace!("on_transaction_cancelled ({conn_id:?}, {trans_id:?})");
+        self.0.as_ref().unwrap().on_transaction_cancelled(conn_id.0, trans_id.0)
+    }
+}
+
+/// Implementation of GapCallbacks wrapping the corresponding C++ methods
+pub struct GapCallbacksImpl;
+
+impl GapCallbacks for GapCallbacksImpl {
+    fn on_device_name_written(&self, tcb_idx: TransportIndex, name: &[u8], persist: bool) {
+        trace!("on_device_name_written ({tcb_idx:?}, {persist})");
+        on_gap_device_name_written(name, persist);
+    }
+
+    fn on_appearance_written(&self, tcb_idx: TransportIndex, appearance: u16) {
+        trace!("on_appearance_written ({tcb_idx:?}, {appearance:#06x})");
+        on_gap_appearance_written(appearance);
+    }
+}
+
+/// Implementation of BondedClientStorage wrapping the corresponding C++ methods
+pub struct BondedClientStorageImpl;
+
+impl BondedClientStorage for BondedClientStorageImpl {
+    fn load(&self, identity: AddressWithType) -> Option<BondedClientState> {
+        let bytes = load_gatt_client_state(identity);
+        if bytes.is_empty() {
+            return None;
+        }
+        let state = BondedClientState::from_bytes(&bytes);
+        if state.is_none() {
+            warn!("discarding invalid GATT client state of {identity:?}");
+        }
+        state
+    }
+
+    fn store(&self, identity: AddressWithType, state: BondedClientState) {
+        store_gatt_client_state(identity, &state.to_bytes());
+    }
+
+    fn remove(&self, identity: AddressWithType) {
+        remove_gatt_client_state(identity);
+    }
 }
 
 /// Implementation of AttTransport wrapping the corresponding C++ method
@@ -312,6 +449,11 @@ fn close_server(server_id: u8) {
         with_arbiter(move |arbiter| arbiter.clear_server(server_id));
     }
 
+    // connections owned by this server can no longer be served by it
+    for tcb_idx in with_arbiter(|arbiter| arbiter.get_transports_owned_by(server_id)) {
+        arbiter::hand_over_connection(tcb_idx);
+    }
+
     do_in_rust_thread(move |modules| {
         if let Err(err) = modules.gatt_module.close_gatt_server(server_id) {
             error!("{err:?}")
@@ -320,9 +462,20 @@ fn close_server(server_id: u8) {
 }
 
 fn consume_descriptors<'a>(
+    characteristic: &GattRecord,
     records: &mut Peekable<impl Iterator<Item = &'a GattRecord>>,
 ) -> Vec<GattDescriptorWithHandle> {
     let mut out = vec![];
+    // the stack adds the Characteristic Extended Properties descriptor right
+    // after the value, but JNI does not report it back to us
+    if characteristic.properties & 0x80 != 0 {
+        out.push(GattDescriptorWithHandle {
+            handle: AttHandle(characteristic.attribute_handle + 1),
+            type_: CHARACTERISTIC_EXTENDED_PROPERTIES_UUID,
+            permissions: AttPermissions::READABLE,
+            static_value: Some(characteristic.extended_properties.to_le_bytes().into()),
+        });
+    }
     while let Some(GattRecord { uuid, attribute_handle, permissions, .. }) =
         records.next_if(|record| record.record_type == GattRecordType::Descriptor)
     {
@@ -334,6 +487,7 @@ fn consume_descriptors<'a>(
             handle: AttHandle(*attribute_handle),
             type_: *uuid,
             permissions: att_permissions,
+            static_value: None,
         })
     }
     out
@@ -341,6 +495,7 @@ fn consume_descriptors<'a>(
 
 fn records_to_service(service_records: &[GattRecord]) -> Result<GattServiceWithHandle> {
     let mut characteristics = vec![];
+    let mut included_services = vec![];
     let mut service_handle_uuid = None;
 
     let mut service_records = service_records.iter().peekable();
@@ -351,14 +506,31 @@ fn records_to_service(service_records: &[GattRecord]) -> Result<GattServiceWithH
                 if service_handle_uuid.is_some() {
                     bail!("got service registration but with duplicate primary service! {service_records:?}".to_string());
                 }
-                service_handle_uuid = Some((record.attribute_handle, record.uuid));
+                service_handle_uuid = Some((record.attribute_handle, record.uuid, false));
+            }
+            GattRecordType::SecondaryService => {
+                if service_handle_uuid.is_some() {
+                    bail!(
+                        "got service registration but with duplicate service! {service_records:?}"
+                    );
+                }
+                service_handle_uuid = Some((record.attribute_handle, record.uuid, true));
+            }
+            GattRecordType::IncludedService => {
+                if !characteristics.is_empty() {
+                    bail!("Got included service after a characteristic declaration");
+                }
+                included_services.push(GattIncludedServiceWithHandle {
+                    handle: AttHandle(record.attribute_handle),
+                    service_handle: AttHandle(record.start_handle),
+                });
             }
             GattRecordType::Characteristic => {
                 characteristics.push(GattCharacteristicWithHandle {
                     handle: AttHandle(record.attribute_handle),
                     type_: record.uuid,
                     permissions: AttPermissions::from_bits_truncate(record.properties),
-                    descriptors: consume_descriptors(&mut service_records),
+                    descriptors: consume_descriptors(record, &mut service_records),
                 });
             }
             GattRecordType::Descriptor => {
@@ -370,11 +542,17 @@ fn records_to_service(service_records: &[GattRecord]) -> Result<GattServiceWithH
         }
     }
 
-    let Some((handle, uuid)) = service_handle_uuid else {
+    let Some((handle, uuid, secondary)) = service_handle_uuid else {
         bail!("got service registration but with no primary service! {characteristics:?}".to_string())
     };
 
-    Ok(GattServiceWithHandle { handle: AttHandle(handle), type_: uuid, characteristics })
+    Ok(GattServiceWithHandle {
+        handle: AttHandle(handle),
+        type_: uuid,
+        secondary,
+        included_services,
+        characteristics,
+    })
 }
 
 fn add_service(server_id: u8, service_records: Vec<GattRecord>) {
@@ -438,6 +616,22 @@ fn is_connection_isolated(conn_id: u16) -> bool {
     with_arbiter(|arbiter| arbiter.is_connection_isolated(ConnectionId(conn_id)))
 }
 
+fn hand_over_connection(server_id: u8, conn_id: u16) {
+    if !rust_event_loop_is_enabled() {
+        return;
+    }
+
+    let conn_id = ConnectionId(conn_id);
+    if conn_id.get_server_id() != ServerId(server_id)
+        || !with_arbiter(|arbiter| arbiter.is_connection_isolated(conn_id))
+    {
+        warn!("server {server_id} does not own {conn_id:?}, so cannot hand it over");
+        return;
+    }
+
+    arbiter::hand_over_connection(conn_id.get_tcb_idx());
+}
+
 fn send_response(_server_id: u8, conn_id: u16, trans_id: u32, status: u8, value: &[u8]) {
     if !rust_event_loop_is_enabled() {
         return;
@@ -500,6 +694,26 @@ fn associate_server_with_advertiser(server_id: u8, advertiser_id: u8) {
     })
 }
 
+fn set_advertiser_isolation_policy(advertiser_id: u8, policy: IsolationPolicy) {
+    if !rust_event_loop_is_enabled() {
+        return;
+    }
+
+    let policy = match policy {
+        IsolationPolicy::Isolated => arbiter::IsolationPolicy::Isolated,
+        IsolationPolicy::IsolatedWithSharedServices => {
+            arbiter::IsolationPolicy::IsolatedWithSharedServices
+        }
+        _ => {
+            error!("unexpected isolation policy {policy:?}");
+            return;
+        }
+    };
+    arbiter::with_arbiter(move |arbiter| {
+        arbiter.set_isolation_policy(AdvertiserId(advertiser_id), policy)
+    })
+}
+
 fn clear_advertiser(advertiser_id: u8) {
     if !rust_event_loop_is_enabled() {
         return;
@@ -508,6 +722,69 @@ fn clear_advertiser(advertiser_id: u8) {
     arbiter::with_arbiter(move |arbiter| arbiter.clear_advertiser(AdvertiserId(advertiser_id)))
 }
 
+fn set_gap_configuration(configuration: GapConfiguration) {
+    if !rust_event_loop_is_enabled() {
+        return;
+    }
+
+    let configuration = gap_configuration_from_ffi(configuration);
+    do_in_rust_thread(move |modules| {
+        if let Err(err) = modules.gatt_module.set_gap_configuration(configuration) {
+            error!("{err:?}")
+        }
+    })
+}
+
+/// Update the device name served by the GAP service, keeping the rest of its
+/// configuration
+fn set_gap_device_name(name: &[u8]) {
+    if !rust_event_loop_is_enabled() {
+        return;
+    }
+
+    let name = name.to_vec();
+    do_in_rust_thread(move |modules| {
+        let configuration = gap::GapConfiguration {
+            device_name: name,
+            ..modules.gatt_module.get_gap_configuration()
+        };
+        if let Err(err) = modules.gatt_module.set_gap_configuration(configuration) {
+            error!("{err:?}")
+        }
+    })
+}
+
+fn gap_configuration_from_ffi(configuration: GapConfiguration) -> gap::GapConfiguration {
+    gap::GapConfiguration {
+        device_name: configuration.device_name,
+        device_name_readable: configuration.device_name_readable,
+        device_name_writable: configuration.device_name_writable,
+        persist_written_device_name: configuration.persist_written_device_name,
+        appearance: configuration.appearance,
+        appearance_writable: configuration.appearance_writable,
+        preferred_connection_parameters: configuration
+            .has_preferred_connection_parameters
+            .then_some(PreferredConnectionParameters {
+                min_interval: configuration.min_interval,
+                max_interval: configuration.max_interval,
+                peripheral_latency: configuration.peripheral_latency,
+                supervision_timeout: configuration.supervision_timeout,
+            }),
+        central_address_resolution: configuration
+            .has_central_address_resolution
+            .then_some(configuration.central_address_resolution),
+        rpa_only: configuration.rpa_only,
+    }
+}
+
+/// Synchronously dump the ATT metrics of all connections. Must not be invoked
+/// from the Rust thread itself.
+fn dump_att_metrics() -> String {
+    dump_from_rust_thread("ATT metrics", |modules| {
+        modules.gatt_module.dump_metrics(&modules.gatt_incoming_callbacks)
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -529,6 +806,26 @@ mod test {
             uuid,
             record_type: GattRecordType::PrimaryService,
             attribute_handle: handle.0,
+            start_handle: 0,
+            properties: 0,
+            extended_properties: 0,
+            permissions: 0,
+        }
+    }
+
+    fn make_secondary_service_record(uuid: Uuid, handle: AttHandle) -> GattRecord {
+        GattRecord {
+            record_type: GattRecordType::SecondaryService,
+            ..make_service_record(uuid, handle)
+        }
+    }
+
+    fn make_included_service_record(handle: AttHandle, service_handle: AttHandle) -> GattRecord {
+        GattRecord {
+            uuid: SERVICE_UUID,
+            record_type: GattRecordType::IncludedService,
+            attribute_handle: handle.0,
+            start_handle: service_handle.0,
             properties: 0,
             extended_properties: 0,
             permissions: 0,
@@ -540,6 +837,7 @@ mod test {
             uuid,
             record_type: GattRecordType::Characteristic,
             attribute_handle: handle.0,
+            start_handle: 0,
             properties,
             extended_properties: 0,
             permissions: 0,
@@ -551,6 +849,7 @@ mod test {
             uuid,
             record_type: GattRecordType::Descriptor,
             attribute_handle: handle.0,
+            start_handle: 0,
             properties: 0,
             extended_properties: 0,
             permissions,
@@ -573,6 +872,36 @@ mod test {
         assert_eq!(service.characteristics.len(), 0);
     }
 
+    #[test]
+    fn test_secondary_service() {
+        let service =
+            records_to_service(&[make_secondary_service_record(SERVICE_UUID, SERVICE_HANDLE)])
+                .unwrap();
+
+        assert_eq!(service.handle, SERVICE_HANDLE);
+        assert!(service.secondary);
+    }
+
+    #[test]
+    fn test_included_service() {
+        let service = records_to_service(&[
+            make_service_record(SERVICE_UUID, AttHandle(10)),
+            make_included_service_record(AttHandle(11), AttHandle(1)),
+            make_characteristic_record(CHARACTERISTIC_UUID, AttHandle(13), 0),
+        ])
+        .unwrap();
+
+        assert!(!service.secondary);
+        assert_eq!(
+            service.included_services,
+            vec![GattIncludedServiceWithHandle {
+                handle: AttHandle(11),
+                service_handle: AttHandle(1)
+            }]
+        );
+        assert_eq!(service.characteristics.len(), 1);
+    }
+
     #[test]
     fn test_dupe_primary_service() {
         let res = records_to_service(&[
@@ -711,6 +1040,28 @@ mod test {
         assert_eq!(service.characteristics[1].descriptors[0].handle, AttHandle(5));
     }
 
+    #[test]
+    fn test_extended_properties_descriptor() {
+        let service = records_to_service(&[
+            make_service_record(SERVICE_UUID, AttHandle(1)),
+            GattRecord {
+                extended_properties: 0x0001,
+                ..make_characteristic_record(CHARACTERISTIC_UUID, AttHandle(3), 0x82)
+            },
+            make_descriptor_record(DESCRIPTOR_UUID, AttHandle(5), 0),
+        ])
+        .unwrap();
+
+        let descriptors = &service.characteristics[0].descriptors;
+        assert_eq!(descriptors.len(), 2);
+        assert_eq!(descriptors[0].handle, AttHandle(4));
+        assert_eq!(descriptors[0].type_, CHARACTERISTIC_EXTENDED_PROPERTIES_UUID);
+        assert_eq!(descriptors[0].permissions, AttPermissions::READABLE);
+        assert_eq!(descriptors[0].static_value.as_deref(), Some([1, 0].as_slice()));
+        assert_eq!(descriptors[1].handle, AttHandle(5));
+        assert_eq!(descriptors[1].static_value, None);
+    }
+
     #[test]
     fn test_unexpected_descriptor() {
         let res = records_to_service(&[
@@ -720,4 +1071,69 @@ mod test {
 
         assert!(res.is_err());
     }
+
+    #[test]
+    fn test_gap_configuration_from_ffi() {
+        let configuration = gap_configuration_from_ffi(GapConfiguration {
+            device_name: b"name".to_vec(),
+            device_name_readable: true,
+            device_name_writable: false,
+            persist_written_device_name: true,
+            appearance: 0x03C1,
+            appearance_writable: true,
+            has_preferred_connection_parameters: true,
+            min_interval: 1,
+            max_interval: 2,
+            peripheral_latency: 3,
+            supervision_timeout: 4,
+            has_central_address_resolution: false,
+            central_address_resolution: true,
+            rpa_only: true,
+        });
+
+        assert_eq!(
+            configuration,
+            gap::GapConfiguration {
+                device_name: b"name".to_vec(),
+                device_name_readable: true,
+                device_name_writable: false,
+                persist_written_device_name: true,
+                appearance: 0x03C1,
+                appearance_writable: true,
+                preferred_connection_parameters: Some(PreferredConnectionParameters {
+                    min_interval: 1,
+                    max_interval: 2,
+                    peripheral_latency: 3,
+                    supervision_timeout: 4,
+                }),
+                central_address_resolution: None,
+                rpa_only: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_unreadable_device_name_from_ffi() {
+        let configuration = gap_configuration_from_ffi(GapConfiguration {
+            device_name: b"name".to_vec(),
+            device_name_readable: false,
+            device_name_writable: false,
+            persist_written_device_name: false,
+            appearance: 0,
+            appearance_writable: false,
+            has_preferred_connection_parameters: false,
+            min_interval: 0,
+            max_interval: 0,
+            peripheral_latency: 0,
+            supervision_timeout: 0,
+            has_central_address_resolution: false,
+            central_address_resolution: false,
+            rpa_only: false,
+        });
+
+        assert_eq!(
+            configuration,
+            gap::GapConfiguration { device_name: b"name".to_vec(), ..Default::default() }
+        );
+    }
 }
@@ -17,21 +17,34 @@
 #include <base/functional/bind.h>
 #include <base/location.h>
 
+#include <algorithm>
 #include <cstdint>
+#include <cstring>
+#include <iterator>
 #include <optional>
+#include <string>
+#include <vector>
 
 #include "include/hardware/bluetooth.h"
 #include "include/hardware/bt_common_types.h"
 #include "include/hardware/bt_gatt_client.h"
 #include "include/hardware/bt_gatt_server.h"
 #include "os/log.h"
+#include "btif/include/btif_api.h"
+#include "btif/include/btif_common.h"
+#include "btif/include/btif_storage.h"
 #include "rust/cxx.h"
+#include "src/gatt/ffi.rs.h"
+#include "stack/include/ble_hci_link_interface.h"
+#include "stack/include/gap_api.h"
 #include "stack/include/gatt_api.h"
 #include "types/bluetooth/uuid.h"
 #include "types/raw_address.h"
 
 bt_status_t do_in_jni_thread(const base::Location& from_here,
                              base::OnceClosure task);
+bt_status_t do_in_main_thread(const base::Location& from_here,
+                              base::OnceClosure task);
 
 namespace {
 std::optional<RawAddress> AddressOfConnection(uint16_t conn_id) {
@@ -45,6 +58,21 @@ std::optional<RawAddress> AddressOfConnection(uint16_t conn_id) {
   }
   return remote_bda;
 }
+
+/// The address under which btif stores the bond of the peer with this identity
+RawAddress BondedAddressOf(bluetooth::core::AddressWithType identity) {
+  auto array = identity.address;
+  std::reverse(array.begin(), array.end());
+  tBLE_BD_ADDR address{
+      .type = identity.address_type == bluetooth::core::AddressType::Public
+                  ? BLE_ADDR_PUBLIC
+                  : BLE_ADDR_RANDOM,
+      .bda = RawAddress(array),
+  };
+  btm_identity_addr_to_random_pseudo_from_address_with_type(
+      &address, /* refresh= */ false);
+  return address.bda;
+}
 }  // namespace
 
 namespace bluetooth {
@@ -135,5 +163,127 @@ void GattServerCallbacks::OnExecute(uint16_t conn_id, uint32_t trans_id,
                             addr.value(), execute));
 }
 
+void GattServerCallbacks::OnTransactionCancelled(uint16_t conn_id,
+                                                 uint32_t trans_id) const {
+  if (callbacks.request_cancelled_cb == nullptr) {
+    LOG_WARN("Dropping cancellation of transaction %d on connection %d",
+             trans_id, conn_id);
+    return;
+  }
+
+  do_in_jni_thread(FROM_HERE, base::Bind(callbacks.request_cancelled_cb,
+                                         conn_id, trans_id));
+}
+
+void OnGapDeviceNameWritten(::rust::Slice<const uint8_t> name,
+                            bool persist) {
+  // the name may contain NULs, so is copied by length
+  std::vector<uint8_t> local_name(name.begin(), name.end());
+  do_in_main_thread(
+      FROM_HERE,
+      base::BindOnce(
+          [](std::vector<uint8_t> local_name, bool persist) {
+            bt_property_t property{
+                .type = BT_PROPERTY_BDNAME,
+                .len = static_cast<int>(local_name.size()),
+                .val = local_name.data(),
+            };
+            if (persist) {
+              // rename the adapter, so the legacy GAP service and the upper
+              // layers see the new name
+              btif_set_adapter_property(&property);
+              return;
+            }
+
+            // the legacy stack expects a NUL-terminated name
+            std::string legacy_name(local_name.begin(), local_name.end());
+            tGAP_BLE_ATTR_VALUE value{};
+            value.p_dev_name = (uint8_t*)legacy_name.c_str();
+            GAP_BleAttrDBUpdate(GATT_UUID_GAP_DEVICE_NAME, &value);
+
+            // the name is not persisted, but let the upper layers know it
+            // changed
+            btif_adapter_properties_evt(BT_STATUS_SUCCESS, 1, &property);
+          },
+          std::move(local_name), persist));
+}
+
+void OnGapAppearanceWritten(uint16_t appearance) {
+  do_in_main_thread(
+      FROM_HERE, base::BindOnce(
+                     [](uint16_t appearance) {
+                       tGAP_BLE_ATTR_VALUE value{};
+                       value.icon = appearance;
+                       GAP_BleAttrDBUpdate(GATT_UUID_GAP_ICON, &value);
+
+                       // the appearance is not persisted, but let the upper
+                       // layers know it changed
+                       bt_property_t property{
+                           .type = BT_PROPERTY_APPEARANCE,
+                           .len = sizeof(appearance),
+                           .val = &appearance,
+                       };
+                       btif_adapter_properties_evt(BT_STATUS_SUCCESS, 1,
+                                                   &property);
+                     },
+                     appearance));
+}
+
+void SetGapConfiguration(const bt_gap_configuration_t& configuration) {
+  GapConfiguration ffi_configuration{
+      .device_name_readable = configuration.device_name_readable,
+      .device_name_writable = configuration.device_name_writable,
+      .persist_written_device_name = configuration.persist_written_device_name,
+      .appearance = configuration.appearance,
+      .appearance_writable = configuration.appearance_writable,
+      .has_preferred_connection_parameters =
+          configuration.has_preferred_connection_parameters,
+      .min_interval = configuration.min_interval,
+      .max_interval = configuration.max_interval,
+      .peripheral_latency = configuration.peripheral_latency,
+      .supervision_timeout = configuration.supervision_timeout,
+      .has_central_address_resolution =
+          configuration.has_central_address_resolution,
+      .central_address_resolution = configuration.central_address_resolution,
+      .rpa_only = configuration.rpa_only,
+  };
+  const uint8_t* name = configuration.device_name.name;
+  std::copy(name,
+            name + strnlen((const char*)name,
+                           sizeof(configuration.device_name.name)),
+            std::back_inserter(ffi_configuration.device_name));
+  set_gap_configuration(std::move(ffi_configuration));
+}
+
+::rust::Vec<uint8_t> LoadGattClientState(core::AddressWithType identity) {
+  auto state = btif_storage_get_gatt_cl_rust_state(BondedAddressOf(identity));
+  ::rust::Vec<uint8_t> out;
+  std::copy(state.begin(), state.end(), std::back_inserter(out));
+  return out;
+}
+
+void StoreGattClientState(core::AddressWithType identity,
+                          ::rust::Slice<const uint8_t> state) {
+  std::vector<uint8_t> value(state.begin(), state.end());
+  do_in_main_thread(FROM_HERE, base::BindOnce(
+                                   [](core::AddressWithType identity,
+                                      std::vector<uint8_t> value) {
+                                     btif_storage_set_gatt_cl_rust_state(
+                                         BondedAddressOf(identity),
+                                         std::move(value));
+                                   },
+                                   identity, std::move(value)));
+}
+
+void RemoveGattClientState(core::AddressWithType identity) {
+  // posted like stores, so it cannot be overtaken by one still pending
+  do_in_main_thread(FROM_HERE, base::BindOnce(
+                                   [](core::AddressWithType identity) {
+                                     btif_storage_remove_gatt_cl_rust_state(
+                                         BondedAddressOf(identity));
+                                   },
+                                   identity));
+}
+
 }  // namespace gatt
 }  // namespace bluetooth
@@ -21,6 +21,7 @@
 #include "include/hardware/bt_gatt_client.h"
 #include "include/hardware/bt_gatt_server.h"
 #include "rust/cxx.h"
+#include "rust/src/core/ffi/types.h"
 
 namespace bluetooth {
 namespace gatt {
@@ -52,9 +53,33 @@ class GattServerCallbacks {
 
   void OnExecute(uint16_t conn_id, uint32_t trans_id, bool execute) const;
 
+  void OnTransactionCancelled(uint16_t conn_id, uint32_t trans_id) const;
+
  private:
   const btgatt_server_callbacks_t& callbacks;
 };
 
+/// Invoked when a peer writes the Device Name of the Rust GAP service. The
+/// adapter is renamed if `persist` is set, otherwise the upper layers are only
+/// told about the new name.
+void OnGapDeviceNameWritten(::rust::Slice<const uint8_t> name, bool persist);
+
+/// Invoked when a peer writes the Appearance of the Rust GAP service
+void OnGapAppearanceWritten(uint16_t appearance);
+
+/// Update the configuration of the Rust GAP service
+void SetGapConfiguration(const bt_gap_configuration_t& configuration);
+
+/// Load the persisted state of a bonded client of the Rust GATT service
+/// (empty if there is none)
+::rust::Vec<uint8_t> LoadGattClientState(core::AddressWithType identity);
+
+/// Persist the state of a bonded client of the Rust GATT service
+void StoreGattClientState(core::AddressWithType identity,
+                          ::rust::Slice<const uint8_t> state);
+
+/// Remove the persisted state of a bonded client of the Rust GATT service
+void RemoveGattClientState(core::AddressWithType identity);
+
 }  // namespace gatt
 }  // namespace bluetooth
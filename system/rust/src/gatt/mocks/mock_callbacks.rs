@@ -41,6 +41,8 @@ pub enum MockCallbackEvents {
     OnIndicationSentConfirmation(ConnectionId, Result<(), IndicationError>),
     /// GattCallbacks#on_execute invoked
     OnExecute(ConnectionId, TransactionId, TransactionDecision),
+    /// GattCallbacks#on_transaction_cancelled invoked
+    OnTransactionCancelled(ConnectionId, TransactionId),
 }
 
 impl GattCallbacks for MockCallbacks {
@@ -94,4 +96,8 @@ impl GattCallbacks for MockCallbacks {
     ) {
         self.0.send(MockCallbackEvents::OnExecute(conn_id, trans_id, decision)).unwrap()
     }
+
+    fn on_transaction_cancelled(&self, conn_id: ConnectionId, trans_id: TransactionId) {
+        self.0.send(MockCallbackEvents::OnTransactionCancelled(conn_id, trans_id)).unwrap()
+    }
 }
@@ -3,11 +3,15 @@
 use std::ops::RangeInclusive;
 
 use crate::{
-    core::shared_box::{WeakBox, WeakBoxRef},
+    core::{
+        address::AddressWithType,
+        shared_box::{WeakBox, WeakBoxRef},
+    },
     gatt::{
         ids::{AttHandle, TransportIndex},
         server::{
             att_server_bearer::AttServerBearer,
+            database_hash::DatabaseHash,
             gatt_database::{AttDatabaseImpl, GattDatabaseCallbacks},
         },
     },
@@ -31,8 +35,14 @@ pub enum MockCallbackEvents {
     OnLeConnect(TransportIndex, WeakBox<AttServerBearer<AttDatabaseImpl>>),
     /// GattDatabaseCallbacks#on_le_disconnect invoked
     OnLeDisconnect(TransportIndex),
+    /// GattDatabaseCallbacks#on_le_bonded invoked
+    OnLeBonded(TransportIndex, AddressWithType),
+    /// GattDatabaseCallbacks#on_bond_removed invoked
+    OnBondRemoved(AddressWithType),
     /// GattDatabaseCallbacks#on_service_change invoked
     OnServiceChange(RangeInclusive<AttHandle>),
+    /// GattDatabaseCallbacks#on_database_hash_change invoked
+    OnDatabaseHashChange(DatabaseHash),
 }
 
 impl GattDatabaseCallbacks for MockCallbacks {
@@ -48,7 +58,19 @@ impl GattDatabaseCallbacks for MockCallbacks {
         self.0.send(MockCallbackEvents::OnLeDisconnect(tcb_idx)).ok().unwrap();
     }
 
+    fn on_le_bonded(&self, tcb_idx: TransportIndex, identity: AddressWithType) {
+        self.0.send(MockCallbackEvents::OnLeBonded(tcb_idx, identity)).ok().unwrap();
+    }
+
+    fn on_bond_removed(&self, identity: AddressWithType) {
+        self.0.send(MockCallbackEvents::OnBondRemoved(identity)).ok().unwrap();
+    }
+
     fn on_service_change(&self, range: RangeInclusive<AttHandle>) {
         self.0.send(MockCallbackEvents::OnServiceChange(range)).ok().unwrap();
     }
+
+    fn on_database_hash_change(&self, hash: DatabaseHash) {
+        self.0.send(MockCallbackEvents::OnDatabaseHashChange(hash)).ok().unwrap();
+    }
 }
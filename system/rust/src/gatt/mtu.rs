@@ -14,6 +14,7 @@ use tokio::sync::OwnedMutexGuard;
 use crate::core::shared_mutex::SharedMutex;
 
 /// An MTU event that we have snooped
+#[derive(Copy, Clone)]
 pub enum MtuEvent {
     /// We have sent an MTU_REQ
     OutgoingRequest,
@@ -3,10 +3,14 @@
 
 mod att_database;
 pub mod att_server_bearer;
+pub mod change_awareness;
+pub mod database_hash;
 pub mod gatt_database;
 mod indication_handler;
+pub mod metrics;
 mod request_handler;
 pub mod services;
+pub mod static_database;
 mod transactions;
 
 mod command_handler;
@@ -16,7 +20,10 @@ mod test;
 use std::{collections::HashMap, rc::Rc};
 
 use crate::{
-    core::shared_box::{SharedBox, WeakBox, WeakBoxRef},
+    core::{
+        address::AddressWithType,
+        shared_box::{SharedBox, WeakBox, WeakBoxRef},
+    },
     gatt::{ids::ConnectionId, server::gatt_database::GattDatabase},
 };
 
@@ -24,16 +31,22 @@ use self::{
     super::ids::ServerId,
     att_server_bearer::AttServerBearer,
     gatt_database::{AttDatabaseImpl, GattServiceWithHandle},
-    services::register_builtin_services,
+    metrics::AttMetricsSnapshot,
+    services::{
+        gap::{reregister_gap_service, GapCallbacks, GapConfiguration, GapService},
+        gatt::{BondedClientStorage, InMemoryBondedClientStorage},
+        register_builtin_services,
+    },
+    static_database::{StaticDatabase, StaticDatastore},
 };
 
 use super::{
-    callbacks::RawGattDatastore,
+    callbacks::{CallbackTransactionManager, RawGattDatastore},
     channel::AttTransport,
     ids::{AttHandle, TransportIndex},
 };
 use anyhow::{anyhow, bail, Result};
-use log::info;
+use log::{info, warn};
 
 pub use indication_handler::IndicationError;
 
@@ -41,23 +54,49 @@ pub use indication_handler::IndicationError;
 pub struct GattModule {
     connections: HashMap<TransportIndex, GattConnection>,
     databases: HashMap<ServerId, SharedBox<GattDatabase>>,
+    static_datastores: HashMap<ServerId, Rc<StaticDatastore>>,
+    gap_service: Rc<GapService>,
+    bonded_clients: Rc<dyn BondedClientStorage>,
     transport: Rc<dyn AttTransport>,
 }
 
 struct GattConnection {
     bearer: SharedBox<AttServerBearer<AttDatabaseImpl>>,
-    database: WeakBox<GattDatabase>,
+    /// The databases visible on this connection (primary first)
+    databases: Vec<WeakBox<GattDatabase>>,
 }
 
 impl GattModule {
     /// Constructor.
     pub fn new(transport: Rc<dyn AttTransport>) -> Self {
-        Self { connections: HashMap::new(), databases: HashMap::new(), transport }
+        Self {
+            connections: HashMap::new(),
+            databases: HashMap::new(),
+            static_datastores: HashMap::new(),
+            gap_service: Rc::new(GapService::new()),
+            bonded_clients: Rc::new(InMemoryBondedClientStorage::default()),
+            transport,
+        }
     }
 
     /// Handle LE link connect
     pub fn on_le_connect(&mut self, conn_id: ConnectionId) -> Result<()> {
-        info!("connected on conn_id {conn_id:?}");
+        self.on_le_connect_shared(conn_id, &[])
+    }
+
+    /// Handle LE link connect, on a connection shared between the server of
+    /// the conn_id (which owns the bearer) and some other servers. The peer sees
+    /// the services of all of them.
+    pub fn on_le_connect_shared(
+        &mut self,
+        conn_id: ConnectionId,
+        shared_with: &[ServerId],
+    ) -> Result<()> {
+        if shared_with.is_empty() {
+            info!("connected on conn_id {conn_id:?}");
+        } else {
+            info!("connected on conn_id {conn_id:?}, shared with {shared_with:?}");
+        }
         let database = self.databases.get(&conn_id.get_server_id());
         let Some(database) = database else {
             bail!(
@@ -65,17 +104,35 @@ impl GattModule {
                 conn_id.get_server_id(),
             );
         };
+        let mut databases = vec![database];
+        for server_id in shared_with {
+            match self.databases.get(server_id) {
+                Some(database) => databases.push(database),
+                None => warn!("cannot share connection with {server_id:?} since it does not exist"),
+            }
+        }
 
         // TODO(aryarahul): do not pass in conn_id at all, derive it using the IsolationManager instead
         let tcb_idx = conn_id.get_tcb_idx();
 
         let transport = self.transport.clone();
-        let bearer = SharedBox::new(AttServerBearer::new(
-            database.get_att_database(tcb_idx),
-            move |packet| transport.send_packet(tcb_idx, packet),
-        ));
-        database.on_bearer_ready(tcb_idx, bearer.as_ref());
-        self.connections.insert(tcb_idx, GattConnection { bearer, database: database.downgrade() });
+        let att_database = match databases.as_slice() {
+            [database] => database.get_att_database(tcb_idx),
+            databases => AttDatabaseImpl::new_shared(databases, tcb_idx),
+        };
+        let bearer = SharedBox::new(AttServerBearer::new(att_database, move |packet| {
+            transport.send_packet(tcb_idx, packet)
+        }));
+        for database in &databases {
+            database.on_bearer_ready(tcb_idx, bearer.as_ref());
+        }
+        self.connections.insert(
+            tcb_idx,
+            GattConnection {
+                bearer,
+                databases: databases.iter().map(|database| database.downgrade()).collect(),
+            },
+        );
         Ok(())
     }
 
@@ -87,10 +144,40 @@ impl GattModule {
             bail!("got disconnection from {tcb_idx:?} but bearer does not exist");
         };
         drop(connection.bearer);
-        connection.database.with(|db| db.map(|db| db.on_bearer_dropped(tcb_idx)));
+        for database in connection.databases {
+            database.with(|db| db.map(|db| db.on_bearer_dropped(tcb_idx)));
+        }
+        for datastore in self.static_datastores.values() {
+            datastore.clear_prepared_writes(tcb_idx);
+        }
         Ok(())
     }
 
+    /// Handle the peer on an existing LE link being bonded, either because it
+    /// was already bonded when it connected, or because pairing has completed
+    pub fn on_le_bonded(
+        &mut self,
+        tcb_idx: TransportIndex,
+        identity: AddressWithType,
+    ) -> Result<()> {
+        info!("peer on {tcb_idx:?} is bonded");
+        let Some(connection) = self.connections.get(&tcb_idx) else {
+            bail!("got bonding on {tcb_idx:?} but bearer does not exist");
+        };
+        for database in &connection.databases {
+            database.with(|db| db.map(|db| db.on_bearer_bonded(tcb_idx, identity)));
+        }
+        Ok(())
+    }
+
+    /// Handle a bond being removed, so that any state persisted for that peer
+    /// is discarded
+    pub fn on_bond_removed(&mut self, identity: AddressWithType) {
+        for database in self.databases.values() {
+            database.on_bond_removed(identity);
+        }
+    }
+
     /// Register a new GATT service on a given server
     pub fn register_gatt_service(
         &mut self,
@@ -116,10 +203,31 @@ impl GattModule {
             .remove_service_at_handle(service_handle)
     }
 
+    /// Load the services described by a JSON StaticDatabase into a given
+    /// server, with their values served from memory
+    pub fn load_static_database(&mut self, server_id: ServerId, json: &str) -> Result<()> {
+        let database = self
+            .databases
+            .get(&server_id)
+            .ok_or_else(|| anyhow!("server {server_id:?} not opened"))?;
+        let datastore = self.static_datastores.entry(server_id).or_default();
+        StaticDatabase::from_json(json)?.load_into(database, datastore.clone())
+    }
+
+    /// Dump the services of a given server as a JSON StaticDatabase
+    pub fn dump_database(&self, server_id: ServerId) -> Result<String> {
+        let database = self
+            .databases
+            .get(&server_id)
+            .ok_or_else(|| anyhow!("server {server_id:?} not opened"))?;
+        StaticDatabase::dump(database, self.static_datastores.get(&server_id).map(|x| x.as_ref()))
+            .to_json()
+    }
+
     /// Open a GATT server
     pub fn open_gatt_server(&mut self, server_id: ServerId) -> Result<()> {
         let mut db = GattDatabase::new();
-        register_builtin_services(&mut db)?;
+        register_builtin_services(&mut db, &self.gap_service, &self.bonded_clients)?;
         let old = self.databases.insert(server_id, db.into());
         if old.is_some() {
             bail!("GATT server {server_id:?} already exists but was re-opened, clobbering old value...")
@@ -127,9 +235,40 @@ impl GattModule {
         Ok(())
     }
 
+    /// Update the values served by the GAP service of every GATT server. If
+    /// this adds or removes characteristics (or changes their permissions),
+    /// connected peers are told of the change.
+    pub fn set_gap_configuration(&mut self, configuration: GapConfiguration) -> Result<()> {
+        if !self.gap_service.set_configuration(configuration) {
+            return Ok(());
+        }
+        for (server_id, database) in &self.databases {
+            reregister_gap_service(database, &self.gap_service)
+                .map_err(|err| anyhow!("failed to update GAP service of {server_id:?}: {err}"))?;
+        }
+        Ok(())
+    }
+
+    /// Get the values currently served by the GAP service
+    pub fn get_gap_configuration(&self) -> GapConfiguration {
+        self.gap_service.configuration()
+    }
+
+    /// Register the callbacks invoked when a peer writes a GAP value
+    pub fn set_gap_callbacks(&mut self, callbacks: Rc<dyn GapCallbacks>) {
+        self.gap_service.set_callbacks(callbacks);
+    }
+
+    /// Set where the GATT service of every GATT server keeps the state of bonded
+    /// clients. Only affects servers opened afterwards.
+    pub fn set_bonded_client_storage(&mut self, storage: Rc<dyn BondedClientStorage>) {
+        self.bonded_clients = storage;
+    }
+
     /// Close a GATT server
     pub fn close_gatt_server(&mut self, server_id: ServerId) -> Result<()> {
         let old = self.databases.remove(&server_id);
+        self.static_datastores.remove(&server_id);
         if old.is_none() {
             bail!("GATT server {server_id:?} did not exist")
         };
@@ -137,6 +276,28 @@ impl GattModule {
         Ok(())
     }
 
+    /// Get the metrics recorded on the ATT bearer of a particular connection
+    pub fn get_metrics(&self, tcb_idx: TransportIndex) -> Option<AttMetricsSnapshot> {
+        self.connections.get(&tcb_idx).map(|connection| connection.bearer.metrics().snapshot())
+    }
+
+    /// Dump the metrics of all connected ATT bearers, alongside the latency of
+    /// the upper-layer datastore on each
+    pub fn dump_metrics(&self, callbacks: &CallbackTransactionManager) -> String {
+        let mut tcb_idxs = self.connections.keys().copied().collect::<Vec<_>>();
+        tcb_idxs.sort_by_key(|tcb_idx| tcb_idx.0);
+        tcb_idxs
+            .into_iter()
+            .map(|tcb_idx| {
+                format!(
+                    "{tcb_idx:?}:\n{}datastore: {}\n",
+                    self.connections[&tcb_idx].bearer.metrics().snapshot(),
+                    callbacks.get_datastore_latency(tcb_idx)
+                )
+            })
+            .collect()
+    }
+
     /// Get an ATT bearer for a particular connection
     pub fn get_bearer(
         &self,
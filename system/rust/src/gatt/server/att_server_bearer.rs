@@ -2,11 +2,11 @@
 //! It handles ATT transactions and unacknowledged operations, backed by an
 //! AttDatabase (that may in turn be backed by an upper-layer protocol)
 
-use std::{cell::Cell, future::Future};
+use std::{cell::Cell, future::Future, rc::Rc, time::Duration};
 
 use anyhow::Result;
 use log::{error, trace, warn};
-use tokio::task::spawn_local;
+use tokio::{task::spawn_local, time::Instant};
 
 use crate::{
     core::{
@@ -27,8 +27,10 @@ use crate::{
 
 use super::{
     att_database::AttDatabase,
+    change_awareness::ChangeAwareness,
     command_handler::AttCommandHandler,
     indication_handler::{ConfirmationWatcher, IndicationError, IndicationHandler},
+    metrics::AttMetrics,
     request_handler::AttRequestHandler,
 };
 
@@ -63,6 +65,12 @@ pub struct AttServerBearer<T: AttDatabase> {
 
     // command handler (across all bearers)
     command_handler: AttCommandHandler<T>,
+
+    // robust caching state
+    change_awareness: Rc<ChangeAwareness>,
+
+    // instrumentation
+    metrics: Rc<AttMetrics>,
 }
 
 impl<T: AttDatabase + Clone + 'static> AttServerBearer<T> {
@@ -73,9 +81,12 @@ impl<T: AttDatabase + Clone + 'static> AttServerBearer<T> {
         send_packet: impl Fn(AttBuilder) -> Result<(), SerializeError> + 'static,
     ) -> Self {
         let (indication_handler, pending_confirmation) = IndicationHandler::new(db.clone());
+        let mtu = AttMtu::new();
+        let metrics = AttMetrics::default();
+        metrics.on_mtu_changed(mtu.snapshot_or_default());
         Self {
             send_packet: Box::new(send_packet),
-            mtu: AttMtu::new(),
+            mtu,
 
             curr_request: AttRequestState::Idle(AttRequestHandler::new(db.clone())).into(),
 
@@ -83,9 +94,24 @@ impl<T: AttDatabase + Clone + 'static> AttServerBearer<T> {
             pending_confirmation,
 
             command_handler: AttCommandHandler::new(db),
+
+            change_awareness: Rc::new(ChangeAwareness::default()),
+
+            metrics: Rc::new(metrics),
         }
     }
 
+    /// The change-aware state of the client on this bearer, used to implement
+    /// robust caching
+    pub fn change_awareness(&self) -> Rc<ChangeAwareness> {
+        self.change_awareness.clone()
+    }
+
+    /// The metrics recorded on this bearer
+    pub fn metrics(&self) -> Rc<AttMetrics> {
+        self.metrics.clone()
+    }
+
     fn send_packet(&self, packet: impl Into<AttChild>) -> Result<(), SerializeError> {
         let child = packet.into();
         let packet = AttBuilder { opcode: HACK_child_to_opcode(&child), _child_: child };
@@ -99,7 +125,10 @@ impl<T: AttDatabase + Clone + 'static> WeakBoxRef<'_, AttServerBearer<T>> {
     pub fn handle_packet(&self, packet: AttView<'_>) {
         match classify_opcode(packet.get_opcode()) {
             OperationType::Command => {
-                self.command_handler.process_packet(packet);
+                self.metrics.on_command(packet.get_opcode());
+                if self.change_awareness.should_process(packet) {
+                    self.command_handler.process_packet(packet);
+                }
             }
             OperationType::Request => {
                 self.handle_request(packet);
@@ -123,6 +152,7 @@ impl<T: AttDatabase + Clone + 'static> WeakBoxRef<'_, AttServerBearer<T>> {
 
         let locked_indication_handler = self.indication_handler.lock();
         let pending_mtu = self.mtu.snapshot();
+        let metrics = self.metrics.clone();
         let this = self.downgrade();
 
         async move {
@@ -142,30 +172,61 @@ impl<T: AttDatabase + Clone + 'static> WeakBoxRef<'_, AttServerBearer<T>> {
                     IndicationError::SendError(SendError::ConnectionDropped)
                 })?;
             // finally, send, and wait for a response
-            indication_handler.send(handle, data, mtu, |packet| this.try_send_packet(packet)).await
+            let start = Instant::now();
+            let result = indication_handler
+                .send(handle, data, mtu, |packet| this.try_send_packet(packet))
+                .await;
+            metrics.on_indication_completed(result.is_ok(), start.elapsed());
+            result
         }
     }
 
     /// Handle a snooped MTU event, to update the MTU we use for our various
     /// operations
     pub fn handle_mtu_event(&self, mtu_event: MtuEvent) -> Result<()> {
-        self.mtu.handle_event(mtu_event)
+        self.mtu.handle_event(mtu_event)?;
+        self.metrics.on_mtu_changed(self.mtu.snapshot_or_default());
+        Ok(())
     }
 
     fn handle_request(&self, packet: AttView<'_>) {
         let curr_request = self.curr_request.replace(AttRequestState::Pending(None));
         self.curr_request.replace(match curr_request {
+            AttRequestState::Idle(request_handler)
+                if !self.change_awareness.should_process(packet) =>
+            {
+                let reply = AttErrorResponseBuilder {
+                    opcode_in_error: packet.get_opcode(),
+                    handle_in_error: AttHandle(0).into(),
+                    error_code: AttErrorCode::DATABASE_OUT_OF_SYNC,
+                };
+                self.metrics.on_request_completed(
+                    packet.get_opcode(),
+                    &reply.clone().into(),
+                    Duration::ZERO,
+                );
+                if let Err(err) = self.send_packet(reply) {
+                    error!("failed to send DATABASE_OUT_OF_SYNC {err:?}");
+                }
+                AttRequestState::Idle(request_handler)
+            }
             AttRequestState::Idle(mut request_handler) => {
                 // even if the MTU is updated afterwards, 5.3 3F 3.4.2.2 states that the
                 // request-time MTU should be used
                 let mtu = self.mtu.snapshot_or_default();
                 let packet = packet.to_owned_packet();
                 let this = self.downgrade();
+                let start = Instant::now();
                 let task = spawn_local(async move {
                     trace!("starting ATT transaction");
                     let reply = request_handler.process_packet(packet.view(), mtu).await;
                     this.with(|this| {
                         this.map(|this| {
+                            this.metrics.on_request_completed(
+                                packet.view().get_opcode(),
+                                &reply,
+                                start.elapsed(),
+                            );
                             match this.send_packet(reply) {
                                 Ok(_) => {
                                     trace!("reply packet sent")
@@ -328,6 +389,8 @@ mod test {
             GattServiceWithHandle {
                 handle: AttHandle(1),
                 type_: Uuid::new(1),
+                secondary: false,
+                included_services: vec![],
                 characteristics: vec![
                     GattCharacteristicWithHandle {
                         handle: VALID_HANDLE,
@@ -675,4 +738,71 @@ mod test {
             assert_eq!(rx.recv().await.unwrap().opcode, AttOpcode::HANDLE_VALUE_INDICATION);
         });
     }
+
+    #[test]
+    fn test_request_metrics() {
+        block_on_locally(async {
+            // arrange
+            let (conn, mut rx) = open_connection();
+
+            // act: one successful and one failed read
+            for handle in [VALID_HANDLE, INVALID_HANDLE] {
+                conn.as_ref().handle_packet(
+                    build_att_view_or_crash(AttReadRequestBuilder {
+                        attribute_handle: handle.into(),
+                    })
+                    .view(),
+                );
+                rx.recv().await.unwrap();
+            }
+
+            // assert
+            let metrics = conn.metrics().snapshot();
+            let stats = metrics.get_opcode_stats(AttOpcode::READ_REQUEST).unwrap();
+            assert_eq!(stats.count, 2);
+            assert_eq!(stats.latency.count, 2);
+            assert_eq!(stats.errors.get(&AttErrorCode::INVALID_HANDLE.into()), Some(&1));
+        });
+    }
+
+    #[test]
+    fn test_indication_metrics() {
+        block_on_locally(async {
+            // arrange
+            let (conn, mut rx) = open_connection();
+
+            // act: send an indication and confirm it
+            let pending_send =
+                spawn_local(conn.as_ref().send_indication(
+                    VALID_HANDLE,
+                    AttAttributeDataChild::RawData([1, 2, 3].into()),
+                ));
+            rx.recv().await.unwrap();
+            conn.as_ref().handle_packet(
+                build_att_view_or_crash(AttHandleValueConfirmationBuilder {}).view(),
+            );
+            pending_send.await.unwrap().unwrap();
+
+            // assert
+            let metrics = conn.metrics().snapshot();
+            assert_eq!(metrics.indications.count, 1);
+            assert_eq!(metrics.indication_failures, 0);
+        });
+    }
+
+    #[test]
+    fn test_mtu_metrics() {
+        block_on_locally(async {
+            // arrange
+            let (conn, _rx) = open_connection();
+            let default_mtu = conn.metrics().snapshot().mtu;
+
+            // act
+            conn.as_ref().handle_mtu_event(MtuEvent::IncomingRequest(100)).unwrap();
+
+            // assert
+            assert_eq!(default_mtu, 23);
+            assert_eq!(conn.metrics().snapshot().mtu, 100);
+        });
+    }
 }
@@ -0,0 +1,208 @@
+//! This module tracks whether a client is change-aware, and gates its requests
+//! if it has enabled robust caching but is not (see Core Spec 5.3 Vol 3G
+//! 2.5.2.1 Robust Caching)
+
+use std::cell::Cell;
+
+use log::{info, warn};
+
+use crate::{
+    core::uuid::Uuid,
+    gatt::{
+        ids::AttHandle,
+        opcode_types::{classify_opcode, OperationType},
+    },
+    packets::{AttOpcode, AttReadByTypeRequestView, AttReadRequestView, AttView, Packet},
+};
+
+use super::services::gatt::{DATABASE_HASH_HANDLE, DATABASE_HASH_UUID};
+
+/// The change-aware state of the client on a single bearer. A new client is
+/// change-aware, since it cannot have cached anything yet.
+pub struct ChangeAwareness {
+    robust_caching_enabled: Cell<bool>,
+    change_aware: Cell<bool>,
+}
+
+impl Default for ChangeAwareness {
+    fn default() -> Self {
+        Self { robust_caching_enabled: Cell::new(false), change_aware: Cell::new(true) }
+    }
+}
+
+impl ChangeAwareness {
+    /// Whether the client has enabled robust caching, via the Client
+    /// Supported Features characteristic
+    pub fn is_robust_caching_enabled(&self) -> bool {
+        self.robust_caching_enabled.get()
+    }
+
+    /// Update whether the client has enabled robust caching
+    pub fn set_robust_caching_enabled(&self, enabled: bool) {
+        self.robust_caching_enabled.set(enabled)
+    }
+
+    /// Whether the client is aware of the current state of the database
+    pub fn is_change_aware(&self) -> bool {
+        self.change_aware.get()
+    }
+
+    /// Update whether the client is aware of the current state of the database
+    pub fn set_change_aware(&self, change_aware: bool) {
+        if self.change_aware.replace(change_aware) != change_aware {
+            info!("client is now change-{}", if change_aware { "aware" } else { "unaware" });
+        }
+    }
+
+    /// Check whether an incoming packet should be processed. A request that
+    /// should not be processed must be rejected with DATABASE_OUT_OF_SYNC,
+    /// after which the client is considered change-aware. Commands that
+    /// should not be processed are silently dropped.
+    pub fn should_process(&self, packet: AttView<'_>) -> bool {
+        if !self.is_robust_caching_enabled() || self.is_change_aware() {
+            return true;
+        }
+        match classify_opcode(packet.get_opcode()) {
+            OperationType::Request => {
+                if is_database_hash_read(packet) {
+                    return true;
+                }
+                warn!("rejecting {:?} from change-unaware client", packet.get_opcode());
+                self.set_change_aware(true);
+                false
+            }
+            OperationType::Command => {
+                warn!("dropping {:?} from change-unaware client", packet.get_opcode());
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+/// A change-unaware client is allowed to read the Database Hash, to determine
+/// if it needs to rediscover the database
+fn is_database_hash_read(packet: AttView<'_>) -> bool {
+    match packet.get_opcode() {
+        AttOpcode::READ_BY_TYPE_REQUEST => {
+            let Ok(request) = AttReadByTypeRequestView::try_parse(packet) else {
+                return false;
+            };
+            let range = AttHandle::from(request.get_starting_handle())
+                ..=AttHandle::from(request.get_ending_handle());
+            range.contains(&DATABASE_HASH_HANDLE)
+                && matches!(Uuid::try_from(request.get_attribute_type()), Ok(uuid) if uuid == DATABASE_HASH_UUID)
+        }
+        AttOpcode::READ_REQUEST => AttReadRequestView::try_parse(packet)
+            .map(|request| AttHandle::from(request.get_attribute_handle()) == DATABASE_HASH_HANDLE)
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        packets::{
+            AttAttributeDataChild, AttReadByTypeRequestBuilder, AttReadRequestBuilder,
+            AttWriteCommandBuilder,
+        },
+        utils::packet::{build_att_data, build_att_view_or_crash},
+    };
+
+    const UNRELATED_HANDLE: AttHandle = AttHandle(20);
+
+    fn unaware_with_robust_caching() -> ChangeAwareness {
+        let change_awareness = ChangeAwareness::default();
+        change_awareness.set_robust_caching_enabled(true);
+        change_awareness.set_change_aware(false);
+        change_awareness
+    }
+
+    fn read_request(handle: AttHandle) -> AttReadRequestBuilder {
+        AttReadRequestBuilder { attribute_handle: handle.into() }
+    }
+
+    #[test]
+    fn test_unaware_without_robust_caching_processes_requests() {
+        // arrange
+        let change_awareness = ChangeAwareness::default();
+        change_awareness.set_change_aware(false);
+
+        // act
+        let res = change_awareness
+            .should_process(build_att_view_or_crash(read_request(UNRELATED_HANDLE)).view());
+
+        // assert
+        assert!(res);
+    }
+
+    #[test]
+    fn test_unaware_rejects_request_then_becomes_aware() {
+        // arrange
+        let change_awareness = unaware_with_robust_caching();
+        let request = build_att_view_or_crash(read_request(UNRELATED_HANDLE));
+
+        // act
+        let first = change_awareness.should_process(request.view());
+        let second = change_awareness.should_process(request.view());
+
+        // assert
+        assert!(!first);
+        assert!(second);
+        assert!(change_awareness.is_change_aware());
+    }
+
+    #[test]
+    fn test_unaware_drops_commands() {
+        // arrange
+        let change_awareness = unaware_with_robust_caching();
+
+        // act
+        let res = change_awareness.should_process(
+            build_att_view_or_crash(AttWriteCommandBuilder {
+                handle: UNRELATED_HANDLE.into(),
+                value: build_att_data(AttAttributeDataChild::RawData([1].into())),
+            })
+            .view(),
+        );
+
+        // assert: dropped, and still unaware
+        assert!(!res);
+        assert!(!change_awareness.is_change_aware());
+    }
+
+    #[test]
+    fn test_unaware_can_read_database_hash_by_type() {
+        // arrange
+        let change_awareness = unaware_with_robust_caching();
+
+        // act
+        let res = change_awareness.should_process(
+            build_att_view_or_crash(AttReadByTypeRequestBuilder {
+                starting_handle: AttHandle(1).into(),
+                ending_handle: AttHandle(0xFFFF).into(),
+                attribute_type: DATABASE_HASH_UUID.into(),
+            })
+            .view(),
+        );
+
+        // assert
+        assert!(res);
+    }
+
+    #[test]
+    fn test_unaware_can_read_database_hash_by_handle() {
+        // arrange
+        let change_awareness = unaware_with_robust_caching();
+
+        // act
+        let res = change_awareness
+            .should_process(build_att_view_or_crash(read_request(DATABASE_HASH_HANDLE)).view());
+
+        // assert
+        assert!(res);
+    }
+}
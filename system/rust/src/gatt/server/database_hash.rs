@@ -0,0 +1,193 @@
+//! This module computes the Database Hash of a GATT database, as defined in
+//! Core Spec 5.3 Vol 3G 7.3
+
+use aes::Aes128;
+use cmac::{Cmac, Mac};
+use log::warn;
+
+use crate::{
+    core::uuid::Uuid,
+    packets::{AttAttributeDataChild, Serializable, Uuid16Builder},
+};
+
+use super::{
+    att_database::AttAttribute,
+    gatt_database::{
+        CHARACTERISTIC_EXTENDED_PROPERTIES_UUID, CHARACTERISTIC_UUID,
+        INCLUDED_SERVICE_DECLARATION_UUID, PRIMARY_SERVICE_DECLARATION_UUID,
+        SECONDARY_SERVICE_DECLARATION_UUID,
+    },
+};
+
+/// Descriptors whose handle and type (but not value) contribute to the hash
+/// (Characteristic User Description, Client Characteristic Configuration,
+/// Server Characteristic Configuration, Characteristic Presentation Format,
+/// Characteristic Aggregate Format)
+const HASHED_DESCRIPTOR_UUIDS: [Uuid; 5] =
+    [Uuid::new(0x2901), Uuid::new(0x2902), Uuid::new(0x2903), Uuid::new(0x2904), Uuid::new(0x2905)];
+
+/// The hash of a GATT database, stored in the order it is sent over the air
+/// (least significant octet first)
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DatabaseHash(pub [u8; 16]);
+
+impl DatabaseHash {
+    /// Compute the hash over the supplied attributes, which must be sorted by
+    /// handle. Declaration and Characteristic Extended Properties attributes
+    /// must be accompanied by their static value.
+    pub fn compute<'a>(
+        attributes: impl IntoIterator<Item = (&'a AttAttribute, Option<&'a AttAttributeDataChild>)>,
+    ) -> Self {
+        let mut message = vec![];
+        for (attribute, value) in attributes {
+            let is_hashed_with_value = [
+                PRIMARY_SERVICE_DECLARATION_UUID,
+                SECONDARY_SERVICE_DECLARATION_UUID,
+                INCLUDED_SERVICE_DECLARATION_UUID,
+                CHARACTERISTIC_UUID,
+                CHARACTERISTIC_EXTENDED_PROPERTIES_UUID,
+            ]
+            .contains(&attribute.type_);
+
+            if is_hashed_with_value {
+                push_handle_and_type(&mut message, attribute);
+                match value.map(|value| value.to_vec()) {
+                    Some(Ok(value)) => message.extend(value),
+                    _ => warn!("attribute {:?} has no static value", attribute.handle),
+                }
+            } else if HASHED_DESCRIPTOR_UUIDS.contains(&attribute.type_) {
+                push_handle_and_type(&mut message, attribute);
+            }
+        }
+
+        let mut mac = Cmac::<Aes128>::new(&[0; 16].into());
+        mac.update(&message);
+        let mut hash: [u8; 16] = mac.finalize().into_bytes().into();
+        hash.reverse();
+        Self(hash)
+    }
+}
+
+fn push_handle_and_type(message: &mut Vec<u8>, attribute: &AttAttribute) {
+    message.extend(attribute.handle.0.to_le_bytes());
+    // all hashed attribute types are SIG-defined, so can be expressed in 16 bits
+    let type_ = Uuid16Builder::try_from(attribute.type_).map(|uuid| uuid.data).unwrap_or_default();
+    message.extend(type_.to_le_bytes());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        gatt::{ids::AttHandle, server::att_database::AttPermissions},
+        packets::{GattServiceDeclarationValueBuilder, UuidBuilder},
+    };
+
+    const SERVICE_TYPE: Uuid = Uuid::new(0x1234);
+    const ANOTHER_SERVICE_TYPE: Uuid = Uuid::new(0x5678);
+    const CHARACTERISTIC_TYPE: Uuid = Uuid::new(0x9ABC);
+
+    fn service(handle: AttHandle) -> AttAttribute {
+        AttAttribute {
+            handle,
+            type_: PRIMARY_SERVICE_DECLARATION_UUID,
+            permissions: AttPermissions::READABLE,
+        }
+    }
+
+    fn service_value(type_: Uuid) -> AttAttributeDataChild {
+        GattServiceDeclarationValueBuilder { uuid: UuidBuilder::from(type_) }.into()
+    }
+
+    #[test]
+    fn test_descriptor_hash() {
+        // arrange
+        let cccd = AttAttribute {
+            handle: AttHandle(4),
+            type_: Uuid::new(0x2902),
+            permissions: AttPermissions::READABLE,
+        };
+
+        // act
+        let hash = DatabaseHash::compute([(&cccd, None)]);
+
+        // assert: AES-CMAC of [04 00 02 29] with a zero key, least significant octet first
+        assert_eq!(
+            hash,
+            DatabaseHash([
+                0x97, 0x2b, 0xea, 0x8e, 0x68, 0x93, 0x97, 0x67, 0xfc, 0x21, 0xc3, 0x0d, 0x0e, 0x21,
+                0x0e, 0x11
+            ])
+        );
+    }
+
+    #[test]
+    fn test_service_type_changes_hash() {
+        // arrange
+        let attr = service(AttHandle(1));
+        let value = service_value(SERVICE_TYPE);
+        let another_value = service_value(ANOTHER_SERVICE_TYPE);
+
+        // act
+        let hash = DatabaseHash::compute([(&attr, Some(&value))]);
+        let another_hash = DatabaseHash::compute([(&attr, Some(&another_value))]);
+
+        // assert
+        assert_ne!(hash, another_hash);
+    }
+
+    #[test]
+    fn test_service_handle_changes_hash() {
+        // arrange
+        let attr = service(AttHandle(1));
+        let another_attr = service(AttHandle(2));
+        let value = service_value(SERVICE_TYPE);
+
+        // act
+        let hash = DatabaseHash::compute([(&attr, Some(&value))]);
+        let another_hash = DatabaseHash::compute([(&another_attr, Some(&value))]);
+
+        // assert
+        assert_ne!(hash, another_hash);
+    }
+
+    #[test]
+    fn test_extended_properties_value_changes_hash() {
+        // arrange
+        let attr = AttAttribute {
+            handle: AttHandle(4),
+            type_: CHARACTERISTIC_EXTENDED_PROPERTIES_UUID,
+            permissions: AttPermissions::READABLE,
+        };
+        let reliable_write = AttAttributeDataChild::RawData([1, 0].into());
+        let writable_auxiliaries = AttAttributeDataChild::RawData([2, 0].into());
+
+        // act
+        let hash = DatabaseHash::compute([(&attr, Some(&reliable_write))]);
+        let another_hash = DatabaseHash::compute([(&attr, Some(&writable_auxiliaries))]);
+
+        // assert
+        assert_ne!(hash, another_hash);
+    }
+
+    #[test]
+    fn test_characteristic_value_not_hashed() {
+        // arrange
+        let attr = service(AttHandle(1));
+        let value = service_value(SERVICE_TYPE);
+        let characteristic_value = AttAttribute {
+            handle: AttHandle(3),
+            type_: CHARACTERISTIC_TYPE,
+            permissions: AttPermissions::READABLE,
+        };
+
+        // act
+        let hash = DatabaseHash::compute([(&attr, Some(&value))]);
+        let hash_with_value =
+            DatabaseHash::compute([(&attr, Some(&value)), (&characteristic_value, None)]);
+
+        // assert
+        assert_eq!(hash, hash_with_value);
+    }
+}
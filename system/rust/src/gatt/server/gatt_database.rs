@@ -10,6 +10,7 @@ use log::{error, warn};
 
 use crate::{
     core::{
+        address::AddressWithType,
         shared_box::{SharedBox, WeakBox, WeakBoxRef},
         uuid::Uuid,
     },
@@ -21,13 +22,15 @@ use crate::{
     packets::{
         AttAttributeDataChild, AttAttributeDataView, AttErrorCode,
         GattCharacteristicDeclarationValueBuilder, GattCharacteristicPropertiesBuilder,
-        GattServiceDeclarationValueBuilder, UuidBuilder,
+        GattIncludedServiceDeclarationValueBuilder, GattServiceDeclarationValueBuilder,
+        Uuid16Builder, UuidBuilder,
     },
 };
 
 use super::{
     att_database::{AttAttribute, AttDatabase},
     att_server_bearer::AttServerBearer,
+    database_hash::DatabaseHash,
 };
 
 pub use super::att_database::AttPermissions;
@@ -36,27 +39,45 @@ pub use super::att_database::AttPermissions;
 pub const PRIMARY_SERVICE_DECLARATION_UUID: Uuid = Uuid::new(0x2800);
 /// Secondary Service Declaration from Bluetooth Assigned Numbers 3.5 Declarations
 pub const SECONDARY_SERVICE_DECLARATION_UUID: Uuid = Uuid::new(0x2801);
+/// Included Service Declaration from Bluetooth Assigned Numbers 3.5 Declarations
+pub const INCLUDED_SERVICE_DECLARATION_UUID: Uuid = Uuid::new(0x2802);
 /// Characteristic Declaration from Bluetooth Assigned Numbers 3.5 Declarations
 pub const CHARACTERISTIC_UUID: Uuid = Uuid::new(0x2803);
+/// Characteristic Extended Properties from Bluetooth Assigned Numbers 3.7 Descriptors
+pub const CHARACTERISTIC_EXTENDED_PROPERTIES_UUID: Uuid = Uuid::new(0x2900);
 
-/// A GattService (currently, only primary services are supported) has an
-/// identifying UUID and a list of contained characteristics, as well as a
+/// A GattService (primary or secondary) has an identifying UUID, a list of
+/// included services and a list of contained characteristics, as well as a
 /// handle (indicating the attribute where the service declaration will live)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct GattServiceWithHandle {
     /// The handle of the service declaration
     pub handle: AttHandle,
     /// The type of the service
     pub type_: Uuid,
+    /// Whether this is a secondary service (only meant to be included by others)
+    pub secondary: bool,
+    /// The services included by this service, whose declarations must come
+    /// before any characteristic
+    pub included_services: Vec<GattIncludedServiceWithHandle>,
     /// A list of contained characteristics (that must have handles between the
     /// service declaration handle, and that of the next service)
     pub characteristics: Vec<GattCharacteristicWithHandle>,
 }
 
+/// An include declaration, referencing a service that is already registered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GattIncludedServiceWithHandle {
+    /// The handle of the include declaration
+    pub handle: AttHandle,
+    /// The handle of the declaration of the included service
+    pub service_handle: AttHandle,
+}
+
 /// A GattCharacteristic consists of a handle (where the value attribute lives),
 /// a UUID identifying its type, and permissions indicating what operations can
 /// be performed
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct GattCharacteristicWithHandle {
     /// The handle of the characteristic value attribute. The characteristic
     /// declaration is one before this handle.
@@ -73,7 +94,7 @@ pub struct GattCharacteristicWithHandle {
 /// GattCharacteristic) It is guaranteed that the handle of the GattDescriptor
 /// is after the handle of the characteristic value attribute, and before the
 /// next characteristic/service declaration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct GattDescriptorWithHandle {
     /// The handle of the descriptor.
     pub handle: AttHandle,
@@ -81,6 +102,10 @@ pub struct GattDescriptorWithHandle {
     pub type_: Uuid,
     /// The permissions (read/write) indicate what operations can be performed.
     pub permissions: AttPermissions,
+    /// The value of the descriptor, if it is served by the database itself
+    /// rather than by the datastore (e.g. Characteristic Extended Properties,
+    /// whose value is part of the Database Hash)
+    pub static_value: Option<Box<[u8]>>,
 }
 
 /// The GattDatabase implements AttDatabase, and converts attribute reads/writes
@@ -96,6 +121,37 @@ struct GattDatabaseSchema {
     attributes: BTreeMap<AttHandle, AttAttributeWithBackingValue>,
 }
 
+fn is_service_declaration(type_: Uuid) -> bool {
+    type_ == PRIMARY_SERVICE_DECLARATION_UUID || type_ == SECONDARY_SERVICE_DECLARATION_UUID
+}
+
+impl GattDatabaseSchema {
+    /// The type and end group handle of the service declared at the supplied
+    /// handle, if any
+    fn get_service_range(&self, service_handle: AttHandle) -> Option<(Uuid, AttHandle)> {
+        let declaration = self.attributes.get(&service_handle)?;
+        if !is_service_declaration(declaration.attribute.type_) {
+            return None;
+        }
+        let AttAttributeBackingValue::Static(AttAttributeDataChild::GattServiceDeclarationValue(
+            value,
+        )) = &declaration.value
+        else {
+            return None;
+        };
+        let type_ = Uuid::try_from(&value.uuid).ok()?;
+        let end_handle = self
+            .attributes
+            .range(service_handle..)
+            .skip(1)
+            .take_while(|(_, attribute)| !is_service_declaration(attribute.attribute.type_))
+            .last()
+            .map(|(handle, _)| *handle)
+            .unwrap_or(service_handle);
+        Some((type_, end_handle))
+    }
+}
+
 #[derive(Clone)]
 enum AttAttributeBackingValue {
     Static(AttAttributeDataChild),
@@ -125,8 +181,15 @@ pub trait GattDatabaseCallbacks {
     );
     /// A peer device has disconnected from this database
     fn on_le_disconnect(&self, tcb_idx: TransportIndex);
+    /// The connected peer device on the given bearer is bonded, with the
+    /// given identity address
+    fn on_le_bonded(&self, tcb_idx: TransportIndex, identity: AddressWithType);
+    /// The bond with the given peer device has been removed
+    fn on_bond_removed(&self, identity: AddressWithType);
     /// The attributes in the specified range have changed
     fn on_service_change(&self, range: RangeInclusive<AttHandle>);
+    /// The Database Hash has changed, following a service change
+    fn on_database_hash_change(&self, hash: DatabaseHash);
 }
 
 impl GattDatabase {
@@ -159,6 +222,41 @@ impl GattDatabase {
         }
     }
 
+    /// When the peer on an existing connection is known to be bonded (either
+    /// at connection time or once pairing completes)
+    pub fn on_bearer_bonded(&self, tcb_idx: TransportIndex, identity: AddressWithType) {
+        for listener in self.listeners.borrow().iter() {
+            listener.on_le_bonded(tcb_idx, identity);
+        }
+    }
+
+    /// When the bond with a peer has been removed
+    pub fn on_bond_removed(&self, identity: AddressWithType) {
+        for listener in self.listeners.borrow().iter() {
+            listener.on_bond_removed(identity);
+        }
+    }
+
+    /// Compute the Database Hash over the current contents of the database
+    pub fn get_database_hash(&self) -> DatabaseHash {
+        let schema = self.schema.borrow();
+        DatabaseHash::compute(schema.attributes.values().map(|attr| {
+            let value = match &attr.value {
+                AttAttributeBackingValue::Static(value) => Some(value),
+                _ => None,
+            };
+            (&attr.attribute, value)
+        }))
+    }
+
+    fn notify_service_change(&self, range: RangeInclusive<AttHandle>) {
+        let hash = self.get_database_hash();
+        for listener in self.listeners.borrow().iter() {
+            listener.on_service_change(range.clone());
+            listener.on_database_hash_change(hash);
+        }
+    }
+
     /// Add a service with pre-allocated handles (for co-existence with C++) backed by the supplied datastore
     /// Assumes that the characteristic DECLARATION handles are one less than
     /// the characteristic handles.
@@ -182,7 +280,11 @@ impl GattDatabase {
         add_attribute(
             AttAttribute {
                 handle: service.handle,
-                type_: PRIMARY_SERVICE_DECLARATION_UUID,
+                type_: if service.secondary {
+                    SECONDARY_SERVICE_DECLARATION_UUID
+                } else {
+                    PRIMARY_SERVICE_DECLARATION_UUID
+                },
                 permissions: AttPermissions::READABLE,
             },
             AttAttributeBackingValue::Static(
@@ -191,6 +293,34 @@ impl GattDatabase {
             ),
         );
 
+        // included services
+        for included_service in service.included_services {
+            let Some((type_, end_group_handle)) =
+                self.schema.borrow().get_service_range(included_service.service_handle)
+            else {
+                bail!("included service {:?} is not registered", included_service.service_handle);
+            };
+            // the UUID is only present if it can be expressed in 16 bits
+            let uuid = Uuid16Builder::try_from(type_)
+                .map(|uuid| uuid.data.to_le_bytes().to_vec())
+                .unwrap_or_default();
+            add_attribute(
+                AttAttribute {
+                    handle: included_service.handle,
+                    type_: INCLUDED_SERVICE_DECLARATION_UUID,
+                    permissions: AttPermissions::READABLE,
+                },
+                AttAttributeBackingValue::Static(
+                    GattIncludedServiceDeclarationValueBuilder {
+                        handle: included_service.service_handle.into(),
+                        end_group_handle: end_group_handle.into(),
+                        uuid: UuidBuilder { data: uuid.into_iter().collect() },
+                    }
+                    .into(),
+                ),
+            );
+        }
+
         // characteristics
         for characteristic in service.characteristics {
             characteristics.push(characteristic.clone());
@@ -219,7 +349,13 @@ impl GattDatabase {
                             notify: 0,
                             indicate: characteristic.permissions.indicate().into(),
                             authenticated_signed_writes: 0,
-                            extended_properties: 0,
+                            extended_properties: characteristic
+                                .descriptors
+                                .iter()
+                                .any(|descriptor| {
+                                    descriptor.type_ == CHARACTERISTIC_EXTENDED_PROPERTIES_UUID
+                                })
+                                .into(),
                         },
                         handle: characteristic.handle.into(),
                         uuid: characteristic.type_.into(),
@@ -246,7 +382,12 @@ impl GattDatabase {
                         type_: descriptor.type_,
                         permissions: descriptor.permissions,
                     },
-                    AttAttributeBackingValue::DynamicDescriptor(datastore.clone()),
+                    match descriptor.static_value {
+                        Some(value) => {
+                            AttAttributeBackingValue::Static(AttAttributeDataChild::RawData(value))
+                        }
+                        None => AttAttributeBackingValue::DynamicDescriptor(datastore.clone()),
+                    },
                 );
             }
         }
@@ -272,11 +413,9 @@ impl GattDatabase {
         // notify listeners if any attribute changed
         let added_handles = attributes.into_iter().map(|attr| attr.0).collect::<Vec<_>>();
         if !added_handles.is_empty() {
-            for listener in self.listeners.borrow().iter() {
-                listener.on_service_change(
-                    *added_handles.iter().min().unwrap()..=*added_handles.iter().max().unwrap(),
-                );
-            }
+            self.notify_service_change(
+                *added_handles.iter().min().unwrap()..=*added_handles.iter().max().unwrap(),
+            );
         }
 
         Ok(())
@@ -292,7 +431,7 @@ impl GattDatabase {
             .values()
             .find(|attribute| {
                 attribute.attribute.handle > service_handle
-                    && attribute.attribute.type_ == PRIMARY_SERVICE_DECLARATION_UUID
+                    && is_service_declaration(attribute.attribute.type_)
             })
             .map(|service| service.attribute.handle);
 
@@ -313,13 +452,89 @@ impl GattDatabase {
 
         // notify listeners if any attribute changed
         if let Some(largest_service_handle) = largest_service_handle {
-            for listener in self.listeners.borrow().iter() {
-                listener.on_service_change(service_handle..=largest_service_handle);
-            }
+            self.notify_service_change(service_handle..=largest_service_handle);
         }
 
         Ok(())
     }
+
+    /// Reconstruct the services currently registered in the database from
+    /// their attributes (e.g. so that the database can be dumped)
+    pub fn get_services(&self) -> Vec<GattServiceWithHandle> {
+        let schema = self.schema.borrow();
+        let mut services: Vec<GattServiceWithHandle> = vec![];
+        let mut next_is_characteristic_value = false;
+
+        for AttAttributeWithBackingValue { attribute, value } in schema.attributes.values() {
+            if is_service_declaration(attribute.type_) {
+                let AttAttributeBackingValue::Static(
+                    AttAttributeDataChild::GattServiceDeclarationValue(declaration),
+                ) = value
+                else {
+                    error!("service declaration {:?} has unexpected value", attribute.handle);
+                    continue;
+                };
+                let Ok(type_) = Uuid::try_from(&declaration.uuid) else {
+                    error!("service declaration {:?} has invalid UUID", attribute.handle);
+                    continue;
+                };
+                services.push(GattServiceWithHandle {
+                    handle: attribute.handle,
+                    type_,
+                    secondary: attribute.type_ == SECONDARY_SERVICE_DECLARATION_UUID,
+                    included_services: vec![],
+                    characteristics: vec![],
+                });
+                next_is_characteristic_value = false;
+                continue;
+            }
+
+            let Some(service) = services.last_mut() else {
+                warn!("attribute {:?} does not belong to any service", attribute.handle);
+                continue;
+            };
+
+            if attribute.type_ == INCLUDED_SERVICE_DECLARATION_UUID {
+                let AttAttributeBackingValue::Static(
+                    AttAttributeDataChild::GattIncludedServiceDeclarationValue(declaration),
+                ) = value
+                else {
+                    error!("include declaration {:?} has unexpected value", attribute.handle);
+                    continue;
+                };
+                service.included_services.push(GattIncludedServiceWithHandle {
+                    handle: attribute.handle,
+                    service_handle: AttHandle(declaration.handle.handle),
+                });
+            } else if attribute.type_ == CHARACTERISTIC_UUID {
+                next_is_characteristic_value = true;
+            } else if next_is_characteristic_value {
+                service.characteristics.push(GattCharacteristicWithHandle {
+                    handle: attribute.handle,
+                    type_: attribute.type_,
+                    permissions: attribute.permissions,
+                    descriptors: vec![],
+                });
+                next_is_characteristic_value = false;
+            } else if let Some(characteristic) = service.characteristics.last_mut() {
+                characteristic.descriptors.push(GattDescriptorWithHandle {
+                    handle: attribute.handle,
+                    type_: attribute.type_,
+                    permissions: attribute.permissions,
+                    static_value: match value {
+                        AttAttributeBackingValue::Static(AttAttributeDataChild::RawData(value)) => {
+                            Some(value.clone())
+                        }
+                        _ => None,
+                    },
+                });
+            } else {
+                warn!("descriptor {:?} does not belong to any characteristic", attribute.handle);
+            }
+        }
+
+        services
+    }
 }
 
 impl SharedBox<GattDatabase> {
@@ -329,38 +544,57 @@ impl SharedBox<GattDatabase> {
     /// Note: After the AttDatabaseImpl is constructed, we MUST call on_bearer_ready() with
     /// the resultant bearer, so that the listeners get the correct sequence of callbacks.
     pub fn get_att_database(&self, tcb_idx: TransportIndex) -> AttDatabaseImpl {
-        AttDatabaseImpl { gatt_db: self.downgrade(), tcb_idx }
+        AttDatabaseImpl { gatt_dbs: vec![self.downgrade()], tcb_idx }
     }
 }
 
-/// An implementation of AttDatabase wrapping an underlying GattDatabase
+/// An implementation of AttDatabase wrapping one or more underlying GattDatabases
 pub struct AttDatabaseImpl {
-    gatt_db: WeakBox<GattDatabase>,
+    /// The backing databases, in priority order
+    gatt_dbs: Vec<WeakBox<GattDatabase>>,
     tcb_idx: TransportIndex,
 }
 
+impl AttDatabaseImpl {
+    /// Generate an impl AttDatabase exposing the attributes of several backing
+    /// GattDatabases, for a connection shared between several GATT servers.
+    ///
+    /// Where several databases contain the same handle (e.g. the built-in GAP and
+    /// GATT services), reads and writes are only served by the first, since the
+    /// peer sees a single attribute.
+    ///
+    /// Note: as with get_att_database(), we MUST call on_bearer_ready() on every
+    /// database with the resultant bearer.
+    pub fn new_shared(gatt_dbs: &[&SharedBox<GattDatabase>], tcb_idx: TransportIndex) -> Self {
+        Self { gatt_dbs: gatt_dbs.iter().map(|gatt_db| gatt_db.downgrade()).collect(), tcb_idx }
+    }
+
+    /// Find this attribute in the first (still open) backing database
+    /// containing it
+    fn find_attribute(&self, handle: AttHandle) -> Option<AttAttributeWithBackingValue> {
+        self.gatt_dbs.iter().find_map(|gatt_db| {
+            gatt_db.with(|gatt_db| {
+                // db may have been closed
+                gatt_db?.schema.borrow().attributes.get(&handle).cloned()
+            })
+        })
+    }
+}
+
 #[async_trait(?Send)]
 impl AttDatabase for AttDatabaseImpl {
     async fn read_attribute(
         &self,
         handle: AttHandle,
     ) -> Result<AttAttributeDataChild, AttErrorCode> {
-        let value = self.gatt_db.with(|gatt_db| {
-            let Some(gatt_db) = gatt_db else {
-                // db must have been closed
-                return Err(AttErrorCode::INVALID_HANDLE);
-            };
-            let services = gatt_db.schema.borrow();
-            let Some(attr) = services.attributes.get(&handle) else {
-                return Err(AttErrorCode::INVALID_HANDLE);
-            };
-            if !attr.attribute.permissions.readable() {
-                return Err(AttErrorCode::READ_NOT_PERMITTED);
-            }
-            Ok(attr.value.clone())
-        })?;
+        let Some(attr) = self.find_attribute(handle) else {
+            return Err(AttErrorCode::INVALID_HANDLE);
+        };
+        if !attr.attribute.permissions.readable() {
+            return Err(AttErrorCode::READ_NOT_PERMITTED);
+        }
 
-        match value {
+        match attr.value {
             AttAttributeBackingValue::Static(val) => return Ok(val),
             AttAttributeBackingValue::DynamicCharacteristic(datastore) => {
                 datastore
@@ -390,22 +624,14 @@ impl AttDatabase for AttDatabaseImpl {
         handle: AttHandle,
         data: AttAttributeDataView<'_>,
     ) -> Result<(), AttErrorCode> {
-        let value = self.gatt_db.with(|gatt_db| {
-            let Some(gatt_db) = gatt_db else {
-                // db must have been closed
-                return Err(AttErrorCode::INVALID_HANDLE);
-            };
-            let services = gatt_db.schema.borrow();
-            let Some(attr) = services.attributes.get(&handle) else {
-                return Err(AttErrorCode::INVALID_HANDLE);
-            };
-            if !attr.attribute.permissions.writable_with_response() {
-                return Err(AttErrorCode::WRITE_NOT_PERMITTED);
-            }
-            Ok(attr.value.clone())
-        })?;
+        let Some(attr) = self.find_attribute(handle) else {
+            return Err(AttErrorCode::INVALID_HANDLE);
+        };
+        if !attr.attribute.permissions.writable_with_response() {
+            return Err(AttErrorCode::WRITE_NOT_PERMITTED);
+        }
 
-        match value {
+        match attr.value {
             AttAttributeBackingValue::Static(val) => {
                 error!("A static attribute {val:?} is marked as writable - ignoring it and rejecting the write...");
                 return Err(AttErrorCode::WRITE_NOT_PERMITTED);
@@ -436,28 +662,16 @@ impl AttDatabase for AttDatabaseImpl {
     }
 
     fn write_no_response_attribute(&self, handle: AttHandle, data: AttAttributeDataView<'_>) {
-        let value = self.gatt_db.with(|gatt_db| {
-            let Some(gatt_db) = gatt_db else {
-                // db must have been closed
-                return None;
-            };
-            let services = gatt_db.schema.borrow();
-            let Some(attr) = services.attributes.get(&handle) else {
-                warn!("cannot find handle {handle:?}");
-                return None;
-            };
-            if !attr.attribute.permissions.writable_without_response() {
-                warn!("trying to write without response to {handle:?}, which doesn't support it");
-                return None;
-            }
-            Some(attr.value.clone())
-        });
-
-        let Some(value) = value else {
+        let Some(attr) = self.find_attribute(handle) else {
+            warn!("cannot find handle {handle:?}");
             return;
         };
+        if !attr.attribute.permissions.writable_without_response() {
+            warn!("trying to write without response to {handle:?}, which doesn't support it");
+            return;
+        }
 
-        match value {
+        match attr.value {
             AttAttributeBackingValue::Static(val) => {
                 error!("A static attribute {val:?} is marked as writable - ignoring it and rejecting the write...");
             }
@@ -481,34 +695,45 @@ impl AttDatabase for AttDatabaseImpl {
     }
 
     fn list_attributes(&self) -> Vec<AttAttribute> {
-        self.gatt_db.with(|db| {
-            db.map(|db| db.schema.borrow().attributes.values().map(|attr| attr.attribute).collect())
-                .unwrap_or_default()
-        })
+        // the first database containing each handle takes precedence
+        let mut attributes = BTreeMap::new();
+        for gatt_db in &self.gatt_dbs {
+            gatt_db.with(|db| {
+                let Some(db) = db else {
+                    return;
+                };
+                for (handle, attr) in db.schema.borrow().attributes.iter() {
+                    attributes.entry(*handle).or_insert(attr.attribute);
+                }
+            });
+        }
+        attributes.into_values().collect()
     }
 }
 
 impl Clone for AttDatabaseImpl {
     fn clone(&self) -> Self {
-        Self { gatt_db: self.gatt_db.clone(), tcb_idx: self.tcb_idx }
+        Self { gatt_dbs: self.gatt_dbs.clone(), tcb_idx: self.tcb_idx }
     }
 }
 
 impl AttDatabaseImpl {
     /// When the bearer owning this AttDatabase is invalidated,
-    /// we must notify the listeners tied to our GattDatabase.
+    /// we must notify the listeners tied to our GattDatabases.
     ///
-    /// Note: AttDatabases referring to the backing GattDatabase
+    /// Note: AttDatabases referring to the backing GattDatabases
     /// may still exist after bearer invalidation, but the bearer will
     /// no longer exist (so packets can no longer be sent/received).
     pub fn on_bearer_dropped(&self) {
-        self.gatt_db.with(|db| {
-            db.map(|db| {
-                for listener in db.listeners.borrow().iter() {
-                    listener.on_le_disconnect(self.tcb_idx)
-                }
-            })
-        });
+        for gatt_db in &self.gatt_dbs {
+            gatt_db.with(|db| {
+                db.map(|db| {
+                    for listener in db.listeners.borrow().iter() {
+                        listener.on_le_disconnect(self.tcb_idx)
+                    }
+                })
+            });
+        }
     }
 }
 
@@ -562,6 +787,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: SERVICE_HANDLE,
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![],
                 },
                 Rc::new(gatt_datastore),
@@ -600,6 +827,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: AttHandle(1),
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: AttHandle(3),
                         type_: CHARACTERISTIC_TYPE,
@@ -615,6 +844,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: AttHandle(4),
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: AttHandle(6),
                         type_: CHARACTERISTIC_TYPE,
@@ -630,6 +861,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: AttHandle(7),
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: AttHandle(9),
                         type_: CHARACTERISTIC_TYPE,
@@ -678,6 +911,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: SERVICE_HANDLE,
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: CHARACTERISTIC_VALUE_HANDLE,
                         type_: CHARACTERISTIC_TYPE,
@@ -751,6 +986,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: SERVICE_HANDLE,
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: CHARACTERISTIC_VALUE_HANDLE,
                         type_: CHARACTERISTIC_TYPE,
@@ -796,6 +1033,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: SERVICE_HANDLE,
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: CHARACTERISTIC_VALUE_HANDLE,
                         type_: CHARACTERISTIC_TYPE,
@@ -841,6 +1080,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: SERVICE_HANDLE,
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: CHARACTERISTIC_VALUE_HANDLE,
                         type_: CHARACTERISTIC_TYPE,
@@ -868,6 +1109,8 @@ mod test {
             GattServiceWithHandle {
                 handle: SERVICE_HANDLE,
                 type_: SERVICE_TYPE,
+                secondary: false,
+                included_services: vec![],
                 characteristics: vec![GattCharacteristicWithHandle {
                     handle: SERVICE_HANDLE,
                     type_: CHARACTERISTIC_TYPE,
@@ -892,6 +1135,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: SERVICE_HANDLE,
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![],
                 },
                 gatt_datastore.clone(),
@@ -902,6 +1147,8 @@ mod test {
             GattServiceWithHandle {
                 handle: SERVICE_HANDLE,
                 type_: SERVICE_TYPE,
+                secondary: false,
+                included_services: vec![],
                 characteristics: vec![],
             },
             gatt_datastore,
@@ -920,6 +1167,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: SERVICE_HANDLE,
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: CHARACTERISTIC_VALUE_HANDLE,
                         type_: CHARACTERISTIC_TYPE,
@@ -974,6 +1223,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: SERVICE_HANDLE,
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: CHARACTERISTIC_VALUE_HANDLE,
                         type_: CHARACTERISTIC_TYPE,
@@ -1015,6 +1266,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: SERVICE_HANDLE,
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: CHARACTERISTIC_VALUE_HANDLE,
                         type_: CHARACTERISTIC_TYPE,
@@ -1046,6 +1299,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: SERVICE_HANDLE,
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: CHARACTERISTIC_VALUE_HANDLE,
                         type_: CHARACTERISTIC_TYPE,
@@ -1054,6 +1309,7 @@ mod test {
                             handle: DESCRIPTOR_HANDLE,
                             type_: DESCRIPTOR_TYPE,
                             permissions: AttPermissions::READABLE,
+                            static_value: None,
                         }],
                     }],
                 },
@@ -1095,6 +1351,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: SERVICE_HANDLE,
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: CHARACTERISTIC_VALUE_HANDLE,
                         type_: CHARACTERISTIC_TYPE,
@@ -1103,6 +1361,7 @@ mod test {
                             handle: DESCRIPTOR_HANDLE,
                             type_: DESCRIPTOR_TYPE,
                             permissions: AttPermissions::WRITABLE_WITH_RESPONSE,
+                            static_value: None,
                         }],
                     }],
                 },
@@ -1144,6 +1403,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: AttHandle(1),
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![
                         GattCharacteristicWithHandle {
                             handle: AttHandle(3),
@@ -1153,6 +1414,7 @@ mod test {
                                 handle: AttHandle(4),
                                 type_: DESCRIPTOR_TYPE,
                                 permissions: AttPermissions::READABLE,
+                                static_value: None,
                             }],
                         },
                         GattCharacteristicWithHandle {
@@ -1164,12 +1426,14 @@ mod test {
                                     handle: AttHandle(7),
                                     type_: DESCRIPTOR_TYPE,
                                     permissions: AttPermissions::WRITABLE_WITH_RESPONSE,
+                                    static_value: None,
                                 },
                                 GattDescriptorWithHandle {
                                     handle: AttHandle(8),
                                     type_: DESCRIPTOR_TYPE,
                                     permissions: AttPermissions::READABLE
                                         | AttPermissions::WRITABLE_WITH_RESPONSE,
+                                    static_value: None,
                                 },
                             ],
                         },
@@ -1216,6 +1480,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: AttHandle(1),
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: AttHandle(3),
                         type_: CHARACTERISTIC_TYPE,
@@ -1233,6 +1499,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: AttHandle(4),
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: AttHandle(6),
                         type_: CHARACTERISTIC_TYPE,
@@ -1346,6 +1614,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: AttHandle(4),
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: AttHandle(6),
                         type_: CHARACTERISTIC_TYPE,
@@ -1378,6 +1648,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: AttHandle(4),
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: AttHandle(6),
                         type_: CHARACTERISTIC_TYPE,
@@ -1393,6 +1665,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: AttHandle(8),
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: AttHandle(10),
                         type_: CHARACTERISTIC_TYPE,
@@ -1428,6 +1702,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: AttHandle(4),
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: AttHandle(6),
                         type_: CHARACTERISTIC_TYPE,
@@ -1463,6 +1739,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: AttHandle(4),
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![],
                 },
                 Rc::new(datastore),
@@ -1492,6 +1770,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: SERVICE_HANDLE,
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: CHARACTERISTIC_VALUE_HANDLE,
                         type_: CHARACTERISTIC_TYPE,
@@ -1530,6 +1810,8 @@ mod test {
                 GattServiceWithHandle {
                     handle: SERVICE_HANDLE,
                     type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
                     characteristics: vec![GattCharacteristicWithHandle {
                         handle: CHARACTERISTIC_VALUE_HANDLE,
                         type_: CHARACTERISTIC_TYPE,
@@ -1551,4 +1833,242 @@ mod test {
         // assert: no callback was sent
         assert_eq!(data_events.try_recv().unwrap_err(), TryRecvError::Empty);
     }
+
+    #[test]
+    fn test_get_services() {
+        // arrange: db with a service containing a characteristic with a descriptor
+        let (gatt_datastore, _) = MockDatastore::new();
+        let gatt_db = SharedBox::new(GattDatabase::new());
+        gatt_db
+            .add_service_with_handles(
+                GattServiceWithHandle {
+                    handle: SERVICE_HANDLE,
+                    type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
+                    characteristics: vec![GattCharacteristicWithHandle {
+                        handle: CHARACTERISTIC_VALUE_HANDLE,
+                        type_: CHARACTERISTIC_TYPE,
+                        permissions: AttPermissions::READABLE | AttPermissions::INDICATE,
+                        descriptors: vec![GattDescriptorWithHandle {
+                            handle: DESCRIPTOR_HANDLE,
+                            type_: DESCRIPTOR_TYPE,
+                            permissions: AttPermissions::WRITABLE_WITH_RESPONSE,
+                            static_value: None,
+                        }],
+                    }],
+                },
+                Rc::new(gatt_datastore),
+            )
+            .unwrap();
+
+        // act: reconstruct the services
+        let services = gatt_db.get_services();
+
+        // assert: the service was reconstructed as registered
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].handle, SERVICE_HANDLE);
+        assert_eq!(services[0].type_, SERVICE_TYPE);
+        assert_eq!(services[0].characteristics.len(), 1);
+        let characteristic = &services[0].characteristics[0];
+        assert_eq!(characteristic.handle, CHARACTERISTIC_VALUE_HANDLE);
+        assert_eq!(characteristic.type_, CHARACTERISTIC_TYPE);
+        assert_eq!(characteristic.permissions, AttPermissions::READABLE | AttPermissions::INDICATE);
+        assert_eq!(characteristic.descriptors.len(), 1);
+        assert_eq!(characteristic.descriptors[0].handle, DESCRIPTOR_HANDLE);
+        assert_eq!(characteristic.descriptors[0].type_, DESCRIPTOR_TYPE);
+        assert_eq!(
+            characteristic.descriptors[0].permissions,
+            AttPermissions::WRITABLE_WITH_RESPONSE
+        );
+    }
+
+    fn add_secondary_and_including_services(gatt_db: &GattDatabase) {
+        let (gatt_datastore, _) = MockDatastore::new();
+        let gatt_datastore = Rc::new(gatt_datastore);
+        gatt_db
+            .add_service_with_handles(
+                GattServiceWithHandle {
+                    handle: AttHandle(10),
+                    type_: SERVICE_TYPE,
+                    secondary: true,
+                    included_services: vec![],
+                    characteristics: vec![GattCharacteristicWithHandle {
+                        handle: AttHandle(12),
+                        type_: CHARACTERISTIC_TYPE,
+                        permissions: AttPermissions::READABLE,
+                        descriptors: vec![],
+                    }],
+                },
+                gatt_datastore.clone(),
+            )
+            .unwrap();
+        gatt_db
+            .add_service_with_handles(
+                GattServiceWithHandle {
+                    handle: AttHandle(20),
+                    type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![GattIncludedServiceWithHandle {
+                        handle: AttHandle(21),
+                        service_handle: AttHandle(10),
+                    }],
+                    characteristics: vec![],
+                },
+                gatt_datastore,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_secondary_and_included_service_declarations() {
+        // arrange
+        let gatt_db = SharedBox::new(GattDatabase::new());
+        add_secondary_and_including_services(&gatt_db);
+        let att_db = gatt_db.get_att_database(TCB_IDX);
+
+        // act
+        let attrs = att_db.list_attributes();
+        let include_value = tokio_test::block_on(att_db.read_attribute(AttHandle(21)));
+
+        // assert
+        assert_eq!(attrs[0].type_, SECONDARY_SERVICE_DECLARATION_UUID);
+        assert_eq!(attrs[3].type_, PRIMARY_SERVICE_DECLARATION_UUID);
+        assert_eq!(attrs[4].type_, INCLUDED_SERVICE_DECLARATION_UUID);
+        assert_eq!(
+            include_value,
+            Ok(AttAttributeDataChild::GattIncludedServiceDeclarationValue(
+                GattIncludedServiceDeclarationValueBuilder {
+                    handle: AttHandle(10).into(),
+                    end_group_handle: AttHandle(12).into(),
+                    uuid: UuidBuilder { data: 0x1234u16.to_le_bytes().into_iter().collect() },
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_include_of_missing_service_rejected() {
+        // arrange
+        let (gatt_datastore, _) = MockDatastore::new();
+        let gatt_db = SharedBox::new(GattDatabase::new());
+
+        // act
+        let res = gatt_db.add_service_with_handles(
+            GattServiceWithHandle {
+                handle: SERVICE_HANDLE,
+                type_: SERVICE_TYPE,
+                secondary: false,
+                included_services: vec![GattIncludedServiceWithHandle {
+                    handle: AttHandle(2),
+                    service_handle: AttHandle(10),
+                }],
+                characteristics: vec![],
+            },
+            Rc::new(gatt_datastore),
+        );
+
+        // assert
+        assert!(res.is_err());
+        assert!(gatt_db.get_services().is_empty());
+    }
+
+    #[test]
+    fn test_get_secondary_and_included_services() {
+        // arrange
+        let gatt_db = SharedBox::new(GattDatabase::new());
+        add_secondary_and_including_services(&gatt_db);
+
+        // act
+        let services = gatt_db.get_services();
+
+        // assert
+        assert_eq!(services.len(), 2);
+        assert!(services[0].secondary);
+        assert_eq!(services[0].characteristics.len(), 1);
+        assert!(!services[1].secondary);
+        assert_eq!(
+            services[1].included_services,
+            vec![GattIncludedServiceWithHandle {
+                handle: AttHandle(21),
+                service_handle: AttHandle(10)
+            }]
+        );
+        assert!(services[1].characteristics.is_empty());
+    }
+
+    #[test]
+    fn test_remove_service_before_secondary_service() {
+        // arrange
+        let gatt_db = SharedBox::new(GattDatabase::new());
+        add_secondary_and_including_services(&gatt_db);
+        let (gatt_datastore, _) = MockDatastore::new();
+        gatt_db
+            .add_service_with_handles(
+                GattServiceWithHandle {
+                    handle: SERVICE_HANDLE,
+                    type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
+                    characteristics: vec![],
+                },
+                Rc::new(gatt_datastore),
+            )
+            .unwrap();
+
+        // act
+        gatt_db.remove_service_at_handle(SERVICE_HANDLE).unwrap();
+
+        // assert: the secondary service following it was kept
+        assert_eq!(gatt_db.get_services().len(), 2);
+    }
+
+    #[test]
+    fn test_shared_database_writes_only_first_database() {
+        // arrange: two databases containing the same writable characteristic
+        let make_db = || {
+            let (gatt_datastore, data_evts) = MockDatastore::new();
+            let gatt_db = SharedBox::new(GattDatabase::new());
+            gatt_db
+                .add_service_with_handles(
+                    GattServiceWithHandle {
+                        handle: SERVICE_HANDLE,
+                        type_: SERVICE_TYPE,
+                        secondary: false,
+                        included_services: vec![],
+                        characteristics: vec![GattCharacteristicWithHandle {
+                            handle: CHARACTERISTIC_VALUE_HANDLE,
+                            type_: CHARACTERISTIC_TYPE,
+                            permissions: AttPermissions::WRITABLE_WITH_RESPONSE,
+                            descriptors: vec![],
+                        }],
+                    },
+                    Rc::new(gatt_datastore),
+                )
+                .unwrap();
+            (gatt_db, data_evts)
+        };
+        let (gatt_db_1, mut data_evts_1) = make_db();
+        let (gatt_db_2, mut data_evts_2) = make_db();
+        let att_db = AttDatabaseImpl::new_shared(&[&gatt_db_1, &gatt_db_2], TCB_IDX);
+        let data = build_view_or_crash(build_att_data(AttAttributeDataChild::RawData([1].into())));
+
+        // act: write to the shared database
+        block_on_locally(async {
+            let cloned_data = data.view().to_owned_packet();
+            spawn_local(async move {
+                att_db
+                    .write_attribute(CHARACTERISTIC_VALUE_HANDLE, cloned_data.view())
+                    .await
+                    .unwrap();
+            });
+            let MockDatastoreEvents::Write(_, _, _, _, reply) = data_evts_1.recv().await.unwrap() else {
+                unreachable!();
+            };
+            reply.send(Ok(())).unwrap();
+        });
+
+        // assert: the second database was not written
+        assert_eq!(data_evts_2.try_recv().unwrap_err(), TryRecvError::Empty);
+    }
 }
@@ -0,0 +1,260 @@
+//! This module records metrics for a single ATT bearer (request counts, error
+//! codes, latencies, and the MTU), so we can tell whether a slow GATT operation
+//! is due to the stack, the upper-layer datastore, or the peer.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt::{self, Display},
+    time::Duration,
+};
+
+use crate::packets::{AttChild, AttErrorCode, AttOpcode};
+
+/// Aggregate statistics over a series of latency samples
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// The number of samples
+    pub count: u64,
+    /// The sum of all samples
+    pub total: Duration,
+    /// The largest sample
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    /// Add a sample
+    pub fn record(&mut self, latency: Duration) {
+        self.count += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    /// The mean of all samples, if any
+    pub fn mean(&self) -> Option<Duration> {
+        self.total.checked_div(self.count.try_into().ok()?)
+    }
+}
+
+impl Display for LatencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mean() {
+            Some(mean) => write!(f, "n={} mean={mean:?} max={:?}", self.count, self.max),
+            None => write!(f, "n=0"),
+        }
+    }
+}
+
+/// Statistics for a single ATT opcode received on a bearer
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OpcodeStats {
+    /// The number of PDUs received with this opcode
+    pub count: u64,
+    /// The number of times each error code was returned (requests only)
+    pub errors: BTreeMap<u8, u64>,
+    /// The time from receiving each request to sending its response
+    /// (requests only)
+    pub latency: LatencyStats,
+}
+
+/// A snapshot of the metrics recorded on a single ATT bearer
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttMetricsSnapshot {
+    /// The current MTU
+    pub mtu: usize,
+    /// Statistics for each opcode received, keyed by the raw opcode
+    pub opcodes: BTreeMap<u8, OpcodeStats>,
+    /// The time from sending each indication to receiving its confirmation
+    pub indications: LatencyStats,
+    /// The number of indications that were not confirmed
+    pub indication_failures: u64,
+}
+
+impl AttMetricsSnapshot {
+    /// The statistics for a given opcode, if any PDUs were received with it
+    pub fn get_opcode_stats(&self, opcode: AttOpcode) -> Option<&OpcodeStats> {
+        self.opcodes.get(&opcode.into())
+    }
+}
+
+impl Display for AttMetricsSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "mtu: {}", self.mtu)?;
+        for (opcode, stats) in &self.opcodes {
+            write!(f, "{:?}: count={}", DisplayOpcode(*opcode), stats.count)?;
+            if stats.latency.count > 0 {
+                write!(f, " latency: {}", stats.latency)?;
+            }
+            for (error, count) in &stats.errors {
+                write!(f, " {:?}={count}", DisplayErrorCode(*error))?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "indications: {} failures={}", self.indications, self.indication_failures)
+    }
+}
+
+struct DisplayOpcode(u8);
+
+impl fmt::Debug for DisplayOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match AttOpcode::try_from(self.0) {
+            Ok(opcode) => write!(f, "{opcode:?}"),
+            Err(_) => write!(f, "{:#04x}", self.0),
+        }
+    }
+}
+
+struct DisplayErrorCode(u8);
+
+impl fmt::Debug for DisplayErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match AttErrorCode::try_from(self.0) {
+            Ok(error) => write!(f, "{error:?}"),
+            Err(_) => write!(f, "{:#04x}", self.0),
+        }
+    }
+}
+
+/// Records metrics for a single ATT bearer
+#[derive(Default)]
+pub struct AttMetrics(RefCell<AttMetricsSnapshot>);
+
+impl AttMetrics {
+    /// Record that a request was received and answered with the supplied
+    /// response (which may be an error)
+    pub fn on_request_completed(&self, opcode: AttOpcode, response: &AttChild, latency: Duration) {
+        let mut metrics = self.0.borrow_mut();
+        let stats = metrics.opcodes.entry(opcode.into()).or_default();
+        stats.count += 1;
+        stats.latency.record(latency);
+        if let AttChild::AttErrorResponse(response) = response {
+            *stats.errors.entry(response.error_code.into()).or_default() += 1;
+        }
+    }
+
+    /// Record that a command was received
+    pub fn on_command(&self, opcode: AttOpcode) {
+        self.0.borrow_mut().opcodes.entry(opcode.into()).or_default().count += 1;
+    }
+
+    /// Record that an indication was sent and either confirmed or not
+    pub fn on_indication_completed(&self, confirmed: bool, latency: Duration) {
+        let mut metrics = self.0.borrow_mut();
+        if confirmed {
+            metrics.indications.record(latency);
+        } else {
+            metrics.indication_failures += 1;
+        }
+    }
+
+    /// Record the current MTU of the bearer
+    pub fn on_mtu_changed(&self, mtu: usize) {
+        self.0.borrow_mut().mtu = mtu;
+    }
+
+    /// Get the metrics recorded so far
+    pub fn snapshot(&self) -> AttMetricsSnapshot {
+        self.0.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{gatt::ids::AttHandle, packets::AttErrorResponseBuilder};
+
+    fn error_response(error_code: AttErrorCode) -> AttChild {
+        AttErrorResponseBuilder {
+            opcode_in_error: AttOpcode::READ_REQUEST,
+            handle_in_error: AttHandle(1).into(),
+            error_code,
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_latency_stats() {
+        // arrange
+        let mut stats = LatencyStats::default();
+
+        // act
+        stats.record(Duration::from_millis(10));
+        stats.record(Duration::from_millis(30));
+
+        // assert
+        assert_eq!(stats.count, 2);
+        assert_eq!(stats.mean(), Some(Duration::from_millis(20)));
+        assert_eq!(stats.max, Duration::from_millis(30));
+    }
+
+    #[test]
+    fn test_empty_latency_stats_has_no_mean() {
+        assert_eq!(LatencyStats::default().mean(), None);
+    }
+
+    #[test]
+    fn test_errors_counted_per_opcode() {
+        // arrange
+        let metrics = AttMetrics::default();
+
+        // act
+        metrics.on_request_completed(
+            AttOpcode::READ_REQUEST,
+            &error_response(AttErrorCode::INVALID_HANDLE),
+            Duration::from_millis(1),
+        );
+        metrics.on_request_completed(
+            AttOpcode::READ_REQUEST,
+            &error_response(AttErrorCode::INVALID_HANDLE),
+            Duration::from_millis(1),
+        );
+        metrics.on_command(AttOpcode::WRITE_COMMAND);
+
+        // assert
+        let snapshot = metrics.snapshot();
+        let read_stats = snapshot.get_opcode_stats(AttOpcode::READ_REQUEST).unwrap();
+        assert_eq!(read_stats.count, 2);
+        assert_eq!(read_stats.errors.get(&AttErrorCode::INVALID_HANDLE.into()), Some(&2));
+        let write_stats = snapshot.get_opcode_stats(AttOpcode::WRITE_COMMAND).unwrap();
+        assert_eq!(write_stats.count, 1);
+        assert_eq!(write_stats.latency.count, 0);
+    }
+
+    #[test]
+    fn test_indications() {
+        // arrange
+        let metrics = AttMetrics::default();
+
+        // act
+        metrics.on_indication_completed(true, Duration::from_millis(5));
+        metrics.on_indication_completed(false, Duration::from_secs(30));
+
+        // assert
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.indications.count, 1);
+        assert_eq!(snapshot.indications.max, Duration::from_millis(5));
+        assert_eq!(snapshot.indication_failures, 1);
+    }
+
+    #[test]
+    fn test_display() {
+        // arrange
+        let metrics = AttMetrics::default();
+        metrics.on_mtu_changed(64);
+        metrics.on_request_completed(
+            AttOpcode::READ_REQUEST,
+            &error_response(AttErrorCode::INVALID_HANDLE),
+            Duration::from_millis(2),
+        );
+
+        // act
+        let dump = metrics.snapshot().to_string();
+
+        // assert
+        assert!(dump.contains("mtu: 64"), "{dump}");
+        assert!(dump.contains("READ_REQUEST: count=1 latency: n=1 mean=2ms max=2ms"), "{dump}");
+        assert!(dump.contains("INVALID_HANDLE=1"), "{dump}");
+    }
+}
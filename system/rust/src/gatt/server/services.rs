@@ -4,15 +4,26 @@
 pub mod gap;
 pub mod gatt;
 
+use std::rc::Rc;
+
 use anyhow::Result;
 
-use self::{gap::register_gap_service, gatt::register_gatt_service};
+use self::{
+    gap::{register_gap_service, GapService},
+    gatt::{register_gatt_service, BondedClientStorage},
+};
 
 use super::gatt_database::GattDatabase;
 
-/// Register all built-in services with the provided database
-pub fn register_builtin_services(database: &mut GattDatabase) -> Result<()> {
-    register_gap_service(database)?;
-    register_gatt_service(database)?;
+/// Register all built-in services with the provided database. The GAP service
+/// is backed by the supplied instance, and the GATT service keeps the state of
+/// bonded clients in the supplied storage, both shared between all databases.
+pub fn register_builtin_services(
+    database: &mut GattDatabase,
+    gap_service: &Rc<GapService>,
+    bonded_clients: &Rc<dyn BondedClientStorage>,
+) -> Result<()> {
+    register_gap_service(database, gap_service)?;
+    register_gatt_service(database, bonded_clients.clone())?;
     Ok(())
 }
@@ -1,9 +1,10 @@
 //! The GAP service as defined in Core Spec 5.3 Vol 3C Section 12
 
-use std::rc::Rc;
+use std::{cell::RefCell, rc::Rc};
 
 use anyhow::Result;
 use async_trait::async_trait;
+use log::{info, warn};
 
 use crate::{
     core::uuid::Uuid,
@@ -18,12 +19,17 @@ use crate::{
     packets::{AttAttributeDataChild, AttAttributeDataView, AttErrorCode},
 };
 
-struct GapService;
-
 // Must lie in the range specified by GATT_GAP_START_HANDLE from legacy stack
 const GAP_SERVICE_HANDLE: AttHandle = AttHandle(20);
 const DEVICE_NAME_HANDLE: AttHandle = AttHandle(22);
 const DEVICE_APPEARANCE_HANDLE: AttHandle = AttHandle(24);
+// The optional characteristics keep fixed handles whether or not they are present
+const PREFERRED_CONNECTION_PARAMETERS_HANDLE: AttHandle = AttHandle(26);
+const CENTRAL_ADDRESS_RESOLUTION_HANDLE: AttHandle = AttHandle(28);
+const RPA_ONLY_HANDLE: AttHandle = AttHandle(30);
+
+/// The maximum length of the Device Name (5.3 Vol 3C 12.1)
+const MAX_DEVICE_NAME_LENGTH: usize = 248;
 
 /// The UUID used for the GAP service (Assigned Numbers 3.4.1 Services by Name)
 pub const GAP_SERVICE_UUID: Uuid = Uuid::new(0x1800);
@@ -31,6 +37,102 @@ pub const GAP_SERVICE_UUID: Uuid = Uuid::new(0x1800);
 pub const DEVICE_NAME_UUID: Uuid = Uuid::new(0x2A00);
 /// The UUID used for the Device Appearance characteristic (Assigned Numbers 3.8.1 Characteristics by Name)
 pub const DEVICE_APPEARANCE_UUID: Uuid = Uuid::new(0x2A01);
+/// The UUID used for the Peripheral Preferred Connection Parameters characteristic (Assigned Numbers 3.8.1 Characteristics by Name)
+pub const PREFERRED_CONNECTION_PARAMETERS_UUID: Uuid = Uuid::new(0x2A04);
+/// The UUID used for the Central Address Resolution characteristic (Assigned Numbers 3.8.1 Characteristics by Name)
+pub const CENTRAL_ADDRESS_RESOLUTION_UUID: Uuid = Uuid::new(0x2AA6);
+/// The UUID used for the Resolvable Private Address Only characteristic (Assigned Numbers 3.8.1 Characteristics by Name)
+pub const RPA_ONLY_UUID: Uuid = Uuid::new(0x2AC9);
+
+/// The Peripheral Preferred Connection Parameters (5.3 Vol 3C 12.3), in
+/// controller units
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PreferredConnectionParameters {
+    /// Minimum connection interval (units of 1.25ms)
+    pub min_interval: u16,
+    /// Maximum connection interval (units of 1.25ms)
+    pub max_interval: u16,
+    /// Peripheral latency (number of connection events)
+    pub peripheral_latency: u16,
+    /// Supervision timeout (units of 10ms)
+    pub supervision_timeout: u16,
+}
+
+/// The values served by the GAP service, set at runtime by the upper layers
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GapConfiguration {
+    /// The device name
+    pub device_name: Vec<u8>,
+    /// Whether peers may read the device name. Otherwise, reads are rejected,
+    /// since the name may identify the user.
+    pub device_name_readable: bool,
+    /// Whether peers may write the device name
+    pub device_name_writable: bool,
+    /// Whether a name written by a peer should be persisted by the host as
+    /// the adapter name, rather than only served until the next update
+    pub persist_written_device_name: bool,
+    /// The appearance (Assigned Numbers 2.6), 0x0000 ("Unknown") by default
+    pub appearance: u16,
+    /// Whether peers may write the appearance
+    pub appearance_writable: bool,
+    /// If set, the Peripheral Preferred Connection Parameters characteristic
+    /// is present with these values
+    pub preferred_connection_parameters: Option<PreferredConnectionParameters>,
+    /// If set, the Central Address Resolution characteristic is present, and
+    /// indicates whether we support address resolution as a central
+    pub central_address_resolution: Option<bool>,
+    /// Whether the Resolvable Private Address Only characteristic is present
+    /// (i.e. we only use RPAs as local addresses once bonded)
+    pub rpa_only: bool,
+}
+
+/// Invoked when a peer writes a value of the GAP service, so the host can
+/// update its own state (e.g. the adapter name)
+pub trait GapCallbacks {
+    /// A peer wrote the device name, which the host should persist if
+    /// `persist` is set
+    fn on_device_name_written(&self, tcb_idx: TransportIndex, name: &[u8], persist: bool);
+    /// A peer wrote the appearance
+    fn on_appearance_written(&self, tcb_idx: TransportIndex, appearance: u16);
+}
+
+/// The GAP service. A single instance backs the GAP service of every GATT
+/// server, so they all serve the same values.
+#[derive(Default)]
+pub struct GapService {
+    configuration: RefCell<GapConfiguration>,
+    callbacks: RefCell<Option<Rc<dyn GapCallbacks>>>,
+}
+
+impl GapService {
+    /// Constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The current configuration
+    pub fn configuration(&self) -> GapConfiguration {
+        self.configuration.borrow().clone()
+    }
+
+    /// Update the configuration. Returns whether the definition of the
+    /// service changed, in which case it must be registered again in every
+    /// database (see reregister_gap_service()).
+    pub fn set_configuration(&self, configuration: GapConfiguration) -> bool {
+        info!("updating GAP configuration to {configuration:?}");
+        let old = self.configuration.replace(configuration);
+        service_definition(&old) != service_definition(&self.configuration.borrow())
+    }
+
+    /// Register the callbacks invoked when a peer writes a GAP value
+    pub fn set_callbacks(&self, callbacks: Rc<dyn GapCallbacks>) {
+        self.callbacks.replace(Some(callbacks));
+    }
+
+    fn callbacks(&self) -> Option<Rc<dyn GapCallbacks>> {
+        self.callbacks.borrow().clone()
+    }
+}
 
 #[async_trait(?Send)]
 impl GattDatastore for GapService {
@@ -40,55 +142,168 @@ impl GattDatastore for GapService {
         handle: AttHandle,
         _: AttributeBackingType,
     ) -> Result<AttAttributeDataChild, AttErrorCode> {
+        let configuration = self.configuration.borrow();
         match handle {
             DEVICE_NAME_HANDLE => {
-                // for non-bonded peers, don't let them read the device name
-                // TODO(aryarahul): support discoverability, when we make this the main GATT server
-                Err(AttErrorCode::INSUFFICIENT_AUTHENTICATION)
+                if !configuration.device_name_readable {
+                    // for non-bonded peers, don't let them read the device name
+                    // TODO(aryarahul): support discoverability, when we make this the main GATT server
+                    return Err(AttErrorCode::INSUFFICIENT_AUTHENTICATION);
+                }
+                Ok(AttAttributeDataChild::RawData(configuration.device_name.clone().into()))
             }
-            // 0x0000 from AssignedNumbers => "Unknown"
-            DEVICE_APPEARANCE_HANDLE => Ok(AttAttributeDataChild::RawData([0x00, 0x00].into())),
+            DEVICE_APPEARANCE_HANDLE => {
+                Ok(AttAttributeDataChild::RawData(configuration.appearance.to_le_bytes().into()))
+            }
+            PREFERRED_CONNECTION_PARAMETERS_HANDLE => {
+                let Some(parameters) = configuration.preferred_connection_parameters else {
+                    return Err(AttErrorCode::INVALID_HANDLE);
+                };
+                Ok(AttAttributeDataChild::RawData(
+                    [
+                        parameters.min_interval,
+                        parameters.max_interval,
+                        parameters.peripheral_latency,
+                        parameters.supervision_timeout,
+                    ]
+                    .into_iter()
+                    .flat_map(u16::to_le_bytes)
+                    .collect(),
+                ))
+            }
+            CENTRAL_ADDRESS_RESOLUTION_HANDLE => {
+                let Some(supported) = configuration.central_address_resolution else {
+                    return Err(AttErrorCode::INVALID_HANDLE);
+                };
+                Ok(AttAttributeDataChild::RawData([supported.into()].into()))
+            }
+            // 0x00 is the only defined value: "only RPAs will be used as local
+            // addresses after bonding"
+            RPA_ONLY_HANDLE => Ok(AttAttributeDataChild::RawData([0x00].into())),
             _ => unreachable!("unexpected handle read"),
         }
     }
 
     async fn write(
         &self,
-        _: TransportIndex,
-        _: AttHandle,
+        tcb_idx: TransportIndex,
+        handle: AttHandle,
         _: AttributeBackingType,
-        _: AttAttributeDataView<'_>,
+        data: AttAttributeDataView<'_>,
     ) -> Result<(), AttErrorCode> {
-        unreachable!("no GAP data should be writable")
+        let value = data.get_raw_payload().collect::<Vec<_>>();
+        match handle {
+            DEVICE_NAME_HANDLE => {
+                if !self.configuration.borrow().device_name_writable {
+                    return Err(AttErrorCode::WRITE_NOT_PERMITTED);
+                }
+                if value.len() > MAX_DEVICE_NAME_LENGTH {
+                    return Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH);
+                }
+                info!("peer on {tcb_idx:?} updated the device name");
+                let persist = {
+                    let mut configuration = self.configuration.borrow_mut();
+                    configuration.device_name = value.clone();
+                    configuration.persist_written_device_name
+                };
+                if let Some(callbacks) = self.callbacks() {
+                    callbacks.on_device_name_written(tcb_idx, &value, persist);
+                }
+            }
+            DEVICE_APPEARANCE_HANDLE => {
+                if !self.configuration.borrow().appearance_writable {
+                    return Err(AttErrorCode::WRITE_NOT_PERMITTED);
+                }
+                let Ok(value) = <[u8; 2]>::try_from(value) else {
+                    return Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH);
+                };
+                let appearance = u16::from_le_bytes(value);
+                info!("peer on {tcb_idx:?} updated the appearance to {appearance:#06x}");
+                self.configuration.borrow_mut().appearance = appearance;
+                if let Some(callbacks) = self.callbacks() {
+                    callbacks.on_appearance_written(tcb_idx, appearance);
+                }
+            }
+            _ => {
+                warn!("write to read-only GAP handle {handle:?}");
+                return Err(AttErrorCode::WRITE_NOT_PERMITTED);
+            }
+        }
+        Ok(())
     }
 }
 
-/// Register the GAP service in the provided GATT database.
-pub fn register_gap_service(database: &mut GattDatabase) -> Result<()> {
-    database.add_service_with_handles(
-        // GAP Service
-        GattServiceWithHandle {
-            handle: GAP_SERVICE_HANDLE,
-            type_: GAP_SERVICE_UUID,
-            // Device Name
-            characteristics: vec![
-                GattCharacteristicWithHandle {
-                    handle: DEVICE_NAME_HANDLE,
-                    type_: DEVICE_NAME_UUID,
-                    permissions: AttPermissions::READABLE,
-                    descriptors: vec![],
-                },
-                // Appearance
-                GattCharacteristicWithHandle {
-                    handle: DEVICE_APPEARANCE_HANDLE,
-                    type_: DEVICE_APPEARANCE_UUID,
-                    permissions: AttPermissions::READABLE,
-                    descriptors: vec![],
-                },
-            ],
+/// The definition of the GAP service with the given configuration
+fn service_definition(configuration: &GapConfiguration) -> GattServiceWithHandle {
+    let writable_if = |writable: bool| {
+        if writable {
+            AttPermissions::READABLE | AttPermissions::WRITABLE_WITH_RESPONSE
+        } else {
+            AttPermissions::READABLE
+        }
+    };
+
+    let mut characteristics = vec![
+        // Device Name
+        GattCharacteristicWithHandle {
+            handle: DEVICE_NAME_HANDLE,
+            type_: DEVICE_NAME_UUID,
+            permissions: writable_if(configuration.device_name_writable),
+            descriptors: vec![],
+        },
+        // Appearance
+        GattCharacteristicWithHandle {
+            handle: DEVICE_APPEARANCE_HANDLE,
+            type_: DEVICE_APPEARANCE_UUID,
+            permissions: writable_if(configuration.appearance_writable),
+            descriptors: vec![],
         },
-        Rc::new(GapService),
-    )
+    ];
+    if configuration.preferred_connection_parameters.is_some() {
+        characteristics.push(GattCharacteristicWithHandle {
+            handle: PREFERRED_CONNECTION_PARAMETERS_HANDLE,
+            type_: PREFERRED_CONNECTION_PARAMETERS_UUID,
+            permissions: AttPermissions::READABLE,
+            descriptors: vec![],
+        });
+    }
+    if configuration.central_address_resolution.is_some() {
+        characteristics.push(GattCharacteristicWithHandle {
+            handle: CENTRAL_ADDRESS_RESOLUTION_HANDLE,
+            type_: CENTRAL_ADDRESS_RESOLUTION_UUID,
+            permissions: AttPermissions::READABLE,
+            descriptors: vec![],
+        });
+    }
+    if configuration.rpa_only {
+        characteristics.push(GattCharacteristicWithHandle {
+            handle: RPA_ONLY_HANDLE,
+            type_: RPA_ONLY_UUID,
+            permissions: AttPermissions::READABLE,
+            descriptors: vec![],
+        });
+    }
+
+    GattServiceWithHandle {
+        handle: GAP_SERVICE_HANDLE,
+        type_: GAP_SERVICE_UUID,
+        secondary: false,
+        included_services: vec![],
+        characteristics,
+    }
+}
+
+/// Register the GAP service in the provided GATT database, backed by the
+/// shared GapService.
+pub fn register_gap_service(database: &mut GattDatabase, service: &Rc<GapService>) -> Result<()> {
+    database.add_service_with_handles(service_definition(&service.configuration()), service.clone())
+}
+
+/// Replace the GAP service in the provided GATT database, after its
+/// definition changed. Connected peers are told of the change.
+pub fn reregister_gap_service(database: &GattDatabase, service: &Rc<GapService>) -> Result<()> {
+    database.remove_service_at_handle(GAP_SERVICE_HANDLE)?;
+    database.add_service_with_handles(service_definition(&service.configuration()), service.clone())
 }
 
 #[cfg(test)]
@@ -101,19 +316,69 @@ mod test {
             att_database::AttDatabase,
             gatt_database::{GattDatabase, CHARACTERISTIC_UUID, PRIMARY_SERVICE_DECLARATION_UUID},
         },
-        utils::task::block_on_locally,
+        utils::{
+            packet::{build_att_data, build_view_or_crash},
+            task::block_on_locally,
+        },
     };
 
     const TCB_IDX: TransportIndex = TransportIndex(1);
 
-    fn init_dbs() -> (SharedBox<GattDatabase>, impl AttDatabase) {
+    const PARAMETERS: PreferredConnectionParameters = PreferredConnectionParameters {
+        min_interval: 0x0018,
+        max_interval: 0x0028,
+        peripheral_latency: 0x0001,
+        supervision_timeout: 0x01F4,
+    };
+
+    #[derive(Default)]
+    struct MockGapCallbacks {
+        names: RefCell<Vec<(Vec<u8>, bool)>>,
+        appearances: RefCell<Vec<u16>>,
+    }
+
+    impl GapCallbacks for MockGapCallbacks {
+        fn on_device_name_written(&self, _: TransportIndex, name: &[u8], persist: bool) {
+            self.names.borrow_mut().push((name.to_vec(), persist));
+        }
+
+        fn on_appearance_written(&self, _: TransportIndex, appearance: u16) {
+            self.appearances.borrow_mut().push(appearance);
+        }
+    }
+
+    fn init_dbs_with_configuration(
+        configuration: GapConfiguration,
+    ) -> (Rc<GapService>, SharedBox<GattDatabase>, impl AttDatabase) {
+        let service = Rc::new(GapService::new());
+        service.set_configuration(configuration);
         let mut gatt_database = GattDatabase::new();
-        register_gap_service(&mut gatt_database).unwrap();
+        register_gap_service(&mut gatt_database, &service).unwrap();
         let gatt_database = SharedBox::new(gatt_database);
         let att_database = gatt_database.get_att_database(TCB_IDX);
+        (service, gatt_database, att_database)
+    }
+
+    fn init_dbs() -> (SharedBox<GattDatabase>, impl AttDatabase) {
+        let (_, gatt_database, att_database) =
+            init_dbs_with_configuration(GapConfiguration::default());
         (gatt_database, att_database)
     }
 
+    async fn write(
+        att_db: &impl AttDatabase,
+        handle: AttHandle,
+        value: &[u8],
+    ) -> Result<(), AttErrorCode> {
+        att_db
+            .write_attribute(
+                handle,
+                build_view_or_crash(build_att_data(AttAttributeDataChild::RawData(value.into())))
+                    .view(),
+            )
+            .await
+    }
+
     #[test]
     fn test_gap_service_discovery() {
         // arrange
@@ -162,4 +427,218 @@ mod test {
         // assert: the name is not readable
         assert_eq!(name, Ok(AttAttributeDataChild::RawData([0x00, 0x00].into())));
     }
+
+    #[test]
+    fn test_optional_characteristics_discovery() {
+        // arrange
+        let (_, _gatt_db, att_db) = init_dbs_with_configuration(GapConfiguration {
+            preferred_connection_parameters: Some(PARAMETERS),
+            central_address_resolution: Some(true),
+            rpa_only: true,
+            ..Default::default()
+        });
+
+        // act
+        let attrs = att_db.list_attributes();
+
+        // assert: 1 service + (5 characteristics) * (declaration + value attrs) = 11 attrs
+        assert_eq!(attrs.len(), 11);
+        assert_eq!(attrs[6].handle, PREFERRED_CONNECTION_PARAMETERS_HANDLE);
+        assert_eq!(attrs[6].type_, PREFERRED_CONNECTION_PARAMETERS_UUID);
+        assert_eq!(attrs[8].handle, CENTRAL_ADDRESS_RESOLUTION_HANDLE);
+        assert_eq!(attrs[8].type_, CENTRAL_ADDRESS_RESOLUTION_UUID);
+        assert_eq!(attrs[10].handle, RPA_ONLY_HANDLE);
+        assert_eq!(attrs[10].type_, RPA_ONLY_UUID);
+    }
+
+    #[test]
+    fn test_read_optional_characteristics() {
+        // arrange
+        let (_, _gatt_db, att_db) = init_dbs_with_configuration(GapConfiguration {
+            preferred_connection_parameters: Some(PARAMETERS),
+            central_address_resolution: Some(true),
+            rpa_only: true,
+            ..Default::default()
+        });
+
+        // act
+        let parameters =
+            block_on_locally(att_db.read_attribute(PREFERRED_CONNECTION_PARAMETERS_HANDLE));
+        let central_address_resolution =
+            block_on_locally(att_db.read_attribute(CENTRAL_ADDRESS_RESOLUTION_HANDLE));
+        let rpa_only = block_on_locally(att_db.read_attribute(RPA_ONLY_HANDLE));
+
+        // assert: values are little-endian
+        assert_eq!(
+            parameters,
+            Ok(AttAttributeDataChild::RawData(
+                [0x18, 0x00, 0x28, 0x00, 0x01, 0x00, 0xF4, 0x01].into()
+            ))
+        );
+        assert_eq!(central_address_resolution, Ok(AttAttributeDataChild::RawData([1].into())));
+        assert_eq!(rpa_only, Ok(AttAttributeDataChild::RawData([0].into())));
+    }
+
+    #[test]
+    fn test_read_configured_device_name() {
+        // arrange
+        let (_, _gatt_db, att_db) = init_dbs_with_configuration(GapConfiguration {
+            device_name: b"name".to_vec(),
+            device_name_readable: true,
+            appearance: 0x03C1,
+            ..Default::default()
+        });
+
+        // act
+        let name = block_on_locally(att_db.read_attribute(DEVICE_NAME_HANDLE));
+        let appearance = block_on_locally(att_db.read_attribute(DEVICE_APPEARANCE_HANDLE));
+
+        // assert
+        assert_eq!(name, Ok(AttAttributeDataChild::RawData(b"name".to_vec().into())));
+        assert_eq!(appearance, Ok(AttAttributeDataChild::RawData([0xC1, 0x03].into())));
+    }
+
+    #[test]
+    fn test_device_name_not_writable_by_default() {
+        // arrange
+        let (_gatt_db, att_db) = init_dbs();
+
+        // act
+        let res = block_on_locally(write(&att_db, DEVICE_NAME_HANDLE, b"name"));
+
+        // assert
+        assert_eq!(res, Err(AttErrorCode::WRITE_NOT_PERMITTED));
+    }
+
+    #[test]
+    fn test_write_device_name() {
+        block_on_locally(async {
+            // arrange
+            let (service, _gatt_db, att_db) = init_dbs_with_configuration(GapConfiguration {
+                device_name_readable: true,
+                device_name_writable: true,
+                ..Default::default()
+            });
+            let callbacks = Rc::new(MockGapCallbacks::default());
+            service.set_callbacks(callbacks.clone());
+
+            // act
+            write(&att_db, DEVICE_NAME_HANDLE, b"name").await.unwrap();
+            let name = att_db.read_attribute(DEVICE_NAME_HANDLE).await;
+
+            // assert: the host was told not to persist it, and the new name is served
+            assert_eq!(*callbacks.names.borrow(), vec![(b"name".to_vec(), false)]);
+            assert_eq!(name, Ok(AttAttributeDataChild::RawData(b"name".to_vec().into())));
+        });
+    }
+
+    #[test]
+    fn test_write_persisted_device_name() {
+        block_on_locally(async {
+            // arrange
+            let (service, _gatt_db, att_db) = init_dbs_with_configuration(GapConfiguration {
+                device_name_writable: true,
+                persist_written_device_name: true,
+                ..Default::default()
+            });
+            let callbacks = Rc::new(MockGapCallbacks::default());
+            service.set_callbacks(callbacks.clone());
+
+            // act
+            write(&att_db, DEVICE_NAME_HANDLE, b"name").await.unwrap();
+
+            // assert: the host was told to persist it
+            assert_eq!(*callbacks.names.borrow(), vec![(b"name".to_vec(), true)]);
+        });
+    }
+
+    #[test]
+    fn test_write_device_name_too_long() {
+        // arrange
+        let (_, _gatt_db, att_db) = init_dbs_with_configuration(GapConfiguration {
+            device_name_writable: true,
+            ..Default::default()
+        });
+
+        // act
+        let res = block_on_locally(write(
+            &att_db,
+            DEVICE_NAME_HANDLE,
+            &[b'a'; MAX_DEVICE_NAME_LENGTH + 1],
+        ));
+
+        // assert
+        assert_eq!(res, Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH));
+    }
+
+    #[test]
+    fn test_write_appearance() {
+        block_on_locally(async {
+            // arrange
+            let (service, _gatt_db, att_db) = init_dbs_with_configuration(GapConfiguration {
+                appearance_writable: true,
+                ..Default::default()
+            });
+            let callbacks = Rc::new(MockGapCallbacks::default());
+            service.set_callbacks(callbacks.clone());
+
+            // act
+            write(&att_db, DEVICE_APPEARANCE_HANDLE, &[0xC1, 0x03]).await.unwrap();
+
+            // assert
+            assert_eq!(*callbacks.appearances.borrow(), vec![0x03C1]);
+            assert_eq!(service.configuration().appearance, 0x03C1);
+        });
+    }
+
+    #[test]
+    fn test_write_appearance_wrong_length() {
+        // arrange
+        let (_, _gatt_db, att_db) = init_dbs_with_configuration(GapConfiguration {
+            appearance_writable: true,
+            ..Default::default()
+        });
+
+        // act
+        let res = block_on_locally(write(&att_db, DEVICE_APPEARANCE_HANDLE, &[0xC1]));
+
+        // assert
+        assert_eq!(res, Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH));
+    }
+
+    #[test]
+    fn test_set_configuration_reports_definition_change() {
+        // arrange
+        let service = GapService::new();
+
+        // act
+        let value_changed = service.set_configuration(GapConfiguration {
+            device_name: b"name".to_vec(),
+            ..Default::default()
+        });
+        let definition_changed = service.set_configuration(GapConfiguration {
+            device_name: b"name".to_vec(),
+            rpa_only: true,
+            ..Default::default()
+        });
+
+        // assert: only adding a characteristic changes the definition
+        assert!(!value_changed);
+        assert!(definition_changed);
+    }
+
+    #[test]
+    fn test_reregister_gap_service() {
+        // arrange
+        let (service, gatt_db, att_db) = init_dbs_with_configuration(GapConfiguration::default());
+        service.set_configuration(GapConfiguration { rpa_only: true, ..Default::default() });
+
+        // act
+        reregister_gap_service(&gatt_db, &service).unwrap();
+
+        // assert
+        let attrs = att_db.list_attributes();
+        assert_eq!(attrs.len(), 7);
+        assert_eq!(attrs[6].type_, RPA_ONLY_UUID);
+    }
 }
@@ -1,14 +1,21 @@
 //! The GATT service as defined in Core Spec 5.3 Vol 3G Section 7
 
-use std::{cell::RefCell, collections::HashMap, ops::RangeInclusive, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    ops::RangeInclusive,
+    rc::Rc,
+};
 
 use anyhow::Result;
 use async_trait::async_trait;
-use log::{error, warn};
+use bitflags::bitflags;
+use log::{error, info, warn};
 use tokio::task::spawn_local;
 
 use crate::{
     core::{
+        address::AddressWithType,
         shared_box::{WeakBox, WeakBoxRef},
         uuid::Uuid,
     },
@@ -18,6 +25,8 @@ use crate::{
         ids::{AttHandle, TransportIndex},
         server::{
             att_server_bearer::AttServerBearer,
+            change_awareness::ChangeAwareness,
+            database_hash::DatabaseHash,
             gatt_database::{
                 AttDatabaseImpl, AttPermissions, GattCharacteristicWithHandle, GattDatabase,
                 GattDatabaseCallbacks, GattDescriptorWithHandle, GattServiceWithHandle,
@@ -31,29 +40,122 @@ use crate::{
     },
 };
 
-#[derive(Default)]
 struct GattService {
     clients: RefCell<HashMap<TransportIndex, ClientState>>,
+    bonded_clients: Rc<dyn BondedClientStorage>,
+    database_hash: Cell<DatabaseHash>,
 }
 
 #[derive(Clone)]
 struct ClientState {
     bearer: WeakBox<AttServerBearer<AttDatabaseImpl>>,
+    change_awareness: Rc<ChangeAwareness>,
+    registered_for_service_change: bool,
+    supported_features: ClientSupportedFeatures,
+    identity: Option<AddressWithType>,
+}
+
+/// The state of a bonded client that must persist across connections
+/// (Core Spec 5.3 Vol 3G 2.5.2 Attribute Caching)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BondedClientState {
     registered_for_service_change: bool,
+    supported_features: ClientSupportedFeatures,
+    /// The Database Hash when this client was last change-aware, if ever
+    database_hash: Option<DatabaseHash>,
+}
+
+impl BondedClientState {
+    /// Serialize, for storage
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes =
+            vec![self.registered_for_service_change.into(), self.supported_features.bits()];
+        if let Some(hash) = self.database_hash {
+            bytes.extend_from_slice(&hash.0);
+        }
+        bytes
+    }
+
+    /// Deserialize the output of to_bytes(), if valid
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let [registered_for_service_change, supported_features, database_hash @ ..] = bytes else {
+            return None;
+        };
+        Some(Self {
+            registered_for_service_change: *registered_for_service_change != 0,
+            supported_features: ClientSupportedFeatures::from_bits_truncate(*supported_features),
+            database_hash: match database_hash {
+                [] => None,
+                hash => Some(DatabaseHash(hash.try_into().ok()?)),
+            },
+        })
+    }
+}
+
+/// Persists the state of bonded clients, so it survives restarts of the stack
+pub trait BondedClientStorage {
+    /// Load the state saved for a bonded client, if any
+    fn load(&self, identity: AddressWithType) -> Option<BondedClientState>;
+    /// Save the state of a bonded client
+    fn store(&self, identity: AddressWithType, state: BondedClientState);
+    /// Discard the state saved for a client whose bond was removed
+    fn remove(&self, identity: AddressWithType);
+}
+
+/// A BondedClientStorage that does not outlive the stack
+#[derive(Default)]
+pub struct InMemoryBondedClientStorage(RefCell<HashMap<AddressWithType, BondedClientState>>);
+
+impl BondedClientStorage for InMemoryBondedClientStorage {
+    fn load(&self, identity: AddressWithType) -> Option<BondedClientState> {
+        self.0.borrow().get(&identity).copied()
+    }
+
+    fn store(&self, identity: AddressWithType, state: BondedClientState) {
+        self.0.borrow_mut().insert(identity, state);
+    }
+
+    fn remove(&self, identity: AddressWithType) {
+        self.0.borrow_mut().remove(&identity);
+    }
+}
+
+bitflags! {
+    /// The features a client can enable via the Client Supported Features
+    /// characteristic (Core Spec 5.3 Vol 3G 7.2 Table 7.6)
+    struct ClientSupportedFeatures : u8 {
+        /// The client supports robust caching
+        const ROBUST_CACHING = 1 << 0;
+        /// The client supports Enhanced ATT bearers
+        const ENHANCED_ATT_BEARER = 1 << 1;
+        /// The client supports receiving Multiple Handle Value Notifications
+        const MULTIPLE_HANDLE_VALUE_NOTIFICATIONS = 1 << 2;
+    }
 }
 
 // Must lie in the range specified by GATT_GATT_START_HANDLE from legacy stack
 const GATT_SERVICE_HANDLE: AttHandle = AttHandle(1);
 const SERVICE_CHANGE_HANDLE: AttHandle = AttHandle(3);
 const SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE: AttHandle = AttHandle(4);
+const CLIENT_SUPPORTED_FEATURES_HANDLE: AttHandle = AttHandle(6);
+/// The handle of the Database Hash characteristic value
+pub const DATABASE_HASH_HANDLE: AttHandle = AttHandle(8);
 
 /// The UUID used for the GATT service (Assigned Numbers 3.4.1 Services by Name)
 pub const GATT_SERVICE_UUID: Uuid = Uuid::new(0x1801);
 /// The UUID used for the Service Changed characteristic (Assigned Numbers 3.8.1 Characteristics by Name)
 pub const SERVICE_CHANGE_UUID: Uuid = Uuid::new(0x2A05);
+/// The UUID used for the Client Supported Features characteristic (Assigned Numbers 3.8.1 Characteristics by Name)
+pub const CLIENT_SUPPORTED_FEATURES_UUID: Uuid = Uuid::new(0x2B29);
+/// The UUID used for the Database Hash characteristic (Assigned Numbers 3.8.1 Characteristics by Name)
+pub const DATABASE_HASH_UUID: Uuid = Uuid::new(0x2B2A);
 /// The UUID used for the Client Characteristic Configuration descriptor (Assigned Numbers 3.7 Descriptors)
 pub const CLIENT_CHARACTERISTIC_CONFIGURATION_UUID: Uuid = Uuid::new(0x2902);
 
+/// The range sent in a Service Changed indication to a bonded client that
+/// missed changes while disconnected, since we do not know what it has cached
+const ALL_HANDLES: RangeInclusive<AttHandle> = AttHandle(1)..=AttHandle(0xFFFF);
+
 #[async_trait(?Send)]
 impl GattDatastore for GattService {
     async fn read(
@@ -62,20 +164,34 @@ impl GattDatastore for GattService {
         handle: AttHandle,
         _: AttributeBackingType,
     ) -> Result<AttAttributeDataChild, AttErrorCode> {
-        if handle == SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE {
-            Ok(GattClientCharacteristicConfigurationBuilder {
-                notification: 0,
-                indication: self
-                    .clients
-                    .borrow()
-                    .get(&tcb_idx)
-                    .map(|state| state.registered_for_service_change)
-                    .unwrap_or(false)
-                    .into(),
+        let clients = self.clients.borrow();
+        let state = clients.get(&tcb_idx);
+        match handle {
+            SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE => {
+                Ok(GattClientCharacteristicConfigurationBuilder {
+                    notification: 0,
+                    indication: state
+                        .map(|state| state.registered_for_service_change)
+                        .unwrap_or(false)
+                        .into(),
+                }
+                .into())
             }
-            .into())
-        } else {
-            unreachable!()
+            CLIENT_SUPPORTED_FEATURES_HANDLE => Ok(AttAttributeDataChild::RawData(
+                [state
+                    .map(|state| state.supported_features)
+                    .unwrap_or(ClientSupportedFeatures::empty())
+                    .bits()]
+                .into(),
+            )),
+            DATABASE_HASH_HANDLE => {
+                // 5.3 Vol 3G 2.5.2.1: a client that reads the hash is now change-aware
+                if let Some(state) = state {
+                    state.change_awareness.set_change_aware(true);
+                }
+                Ok(AttAttributeDataChild::RawData(self.database_hash.get().0.into()))
+            }
+            _ => unreachable!(),
         }
     }
 
@@ -86,23 +202,92 @@ impl GattDatastore for GattService {
         _: AttributeBackingType,
         data: AttAttributeDataView<'_>,
     ) -> Result<(), AttErrorCode> {
-        if handle == SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE {
-            let ccc =
-                GattClientCharacteristicConfigurationView::try_parse(data).map_err(|err| {
-                    warn!("failed to parse CCC descriptor, got: {err:?}");
-                    AttErrorCode::APPLICATION_ERROR
-                })?;
-            let mut clients = self.clients.borrow_mut();
-            let state = clients.get_mut(&tcb_idx);
-            let Some(mut state) = state else {
-                error!("Received write request from disconnected client...");
-                return Err(AttErrorCode::UNLIKELY_ERROR);
-            };
-            state.registered_for_service_change = ccc.get_indication() != 0;
-            Ok(())
-        } else {
-            unreachable!()
+        let mut clients = self.clients.borrow_mut();
+        let state = clients.get_mut(&tcb_idx);
+        let Some(state) = state else {
+            error!("Received write request from disconnected client...");
+            return Err(AttErrorCode::UNLIKELY_ERROR);
+        };
+        match handle {
+            SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE => {
+                let ccc =
+                    GattClientCharacteristicConfigurationView::try_parse(data).map_err(|err| {
+                        warn!("failed to parse CCC descriptor, got: {err:?}");
+                        AttErrorCode::APPLICATION_ERROR
+                    })?;
+                state.registered_for_service_change = ccc.get_indication() != 0;
+            }
+            CLIENT_SUPPORTED_FEATURES_HANDLE => {
+                let Some(bits) = data.get_raw_payload().next() else {
+                    return Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH);
+                };
+                let features = ClientSupportedFeatures::from_bits_truncate(bits);
+                // 5.3 Vol 3G 7.2: a client shall not clear any bits it has set
+                if !features.contains(state.supported_features) {
+                    warn!("client tried to clear supported features, rejecting");
+                    return Err(AttErrorCode::VALUE_NOT_ALLOWED);
+                }
+                state.supported_features = features;
+                state.change_awareness.set_robust_caching_enabled(
+                    features.contains(ClientSupportedFeatures::ROBUST_CACHING),
+                );
+            }
+            _ => unreachable!(),
         }
+        let state = state.clone();
+        drop(clients);
+        self.persist_client_state(&state);
+        Ok(())
+    }
+}
+
+impl GattService {
+    /// Save the state of a bonded client, so it can be restored when it
+    /// reconnects
+    fn persist_client_state(&self, state: &ClientState) {
+        let Some(identity) = state.identity else {
+            return;
+        };
+        let previous_hash =
+            self.bonded_clients.load(identity).and_then(|state| state.database_hash);
+        self.bonded_clients.store(
+            identity,
+            BondedClientState {
+                registered_for_service_change: state.registered_for_service_change,
+                supported_features: state.supported_features,
+                database_hash: if state.change_awareness.is_change_aware() {
+                    Some(self.database_hash.get())
+                } else {
+                    previous_hash
+                },
+            },
+        );
+    }
+
+    /// Send a Service Changed indication to a registered client, marking it
+    /// as change-aware once it is confirmed
+    fn send_service_changed(&self, client: &ClientState, range: RangeInclusive<AttHandle>) {
+        client.bearer.with(|bearer| match bearer {
+            Some(bearer) => {
+                let indication = bearer.send_indication(
+                    SERVICE_CHANGE_HANDLE,
+                    GattServiceChangedBuilder {
+                        start_handle: (*range.start()).into(),
+                        end_handle: (*range.end()).into(),
+                    }
+                    .into(),
+                );
+                let change_awareness = client.change_awareness.clone();
+                spawn_local(async move {
+                    if indication.await.is_ok() {
+                        change_awareness.set_change_aware(true);
+                    }
+                });
+            }
+            None => {
+                error!("Registered client's bearer has been destructed")
+            }
+        });
     }
 }
 
@@ -112,65 +297,139 @@ impl GattDatabaseCallbacks for GattService {
         tcb_idx: TransportIndex,
         bearer: WeakBoxRef<AttServerBearer<AttDatabaseImpl>>,
     ) {
-        // TODO(aryarahul): registered_for_service_change may not be false for bonded devices
+        // if the peer is bonded, its state is restored in on_le_bonded()
         self.clients.borrow_mut().insert(
             tcb_idx,
-            ClientState { bearer: bearer.downgrade(), registered_for_service_change: false },
+            ClientState {
+                bearer: bearer.downgrade(),
+                change_awareness: bearer.change_awareness(),
+                registered_for_service_change: false,
+                supported_features: ClientSupportedFeatures::empty(),
+                identity: None,
+            },
         );
     }
 
     fn on_le_disconnect(&self, tcb_idx: TransportIndex) {
-        self.clients.borrow_mut().remove(&tcb_idx);
+        if let Some(state) = self.clients.borrow_mut().remove(&tcb_idx) {
+            self.persist_client_state(&state);
+        }
+    }
+
+    fn on_le_bonded(&self, tcb_idx: TransportIndex, identity: AddressWithType) {
+        let mut clients = self.clients.borrow_mut();
+        let Some(state) = clients.get_mut(&tcb_idx) else {
+            warn!("got bonding for {tcb_idx:?} but client is not connected");
+            return;
+        };
+        if state.identity == Some(identity) {
+            // already restored (we hear of the bond again once the link is encrypted)
+            return;
+        }
+        state.identity = Some(identity);
+
+        let Some(bonded_state) = self.bonded_clients.load(identity) else {
+            // newly bonded, so just save the state from this connection
+            let state = state.clone();
+            drop(clients);
+            self.persist_client_state(&state);
+            return;
+        };
+
+        // restore the state from the previous connection
+        state.registered_for_service_change = bonded_state.registered_for_service_change;
+        state.supported_features = bonded_state.supported_features;
+        state.change_awareness.set_robust_caching_enabled(
+            bonded_state.supported_features.contains(ClientSupportedFeatures::ROBUST_CACHING),
+        );
+        let change_aware = bonded_state.database_hash == Some(self.database_hash.get());
+        state.change_awareness.set_change_aware(change_aware);
+        info!("restored state of bonded client on {tcb_idx:?}, change-aware: {change_aware}");
+
+        // tell the client about any changes it missed while disconnected
+        let state = state.clone();
+        drop(clients);
+        if !change_aware && state.registered_for_service_change {
+            self.send_service_changed(&state, ALL_HANDLES);
+        }
+    }
+
+    fn on_bond_removed(&self, identity: AddressWithType) {
+        self.bonded_clients.remove(identity);
+        for state in self.clients.borrow_mut().values_mut() {
+            if state.identity == Some(identity) {
+                state.identity = None;
+            }
+        }
     }
 
     fn on_service_change(&self, range: RangeInclusive<AttHandle>) {
         for (conn_id, client) in self.clients.borrow().clone() {
+            client.change_awareness.set_change_aware(false);
             if client.registered_for_service_change {
-                client.bearer.with(|bearer| match bearer {
-                    Some(bearer) => {
-                        spawn_local(
-                            bearer.send_indication(
-                                SERVICE_CHANGE_HANDLE,
-                                GattServiceChangedBuilder {
-                                    start_handle: (*range.start()).into(),
-                                    end_handle: (*range.end()).into(),
-                                }
-                                .into(),
-                            ),
-                        );
-                    }
-                    None => {
-                        error!("Registered client's bearer has been destructed ({conn_id:?})")
-                    }
-                });
+                info!("sending service changed indication to {conn_id:?}");
+                self.send_service_changed(&client, range.clone());
             }
         }
     }
+
+    fn on_database_hash_change(&self, hash: DatabaseHash) {
+        self.database_hash.set(hash);
+    }
 }
 
-/// Register the GATT service in the provided GATT database.
-pub fn register_gatt_service(database: &mut GattDatabase) -> Result<()> {
-    let this = Rc::new(GattService::default());
+/// Register the GATT service in the provided GATT database. The state of bonded
+/// clients is kept in the supplied storage.
+pub fn register_gatt_service(
+    database: &mut GattDatabase,
+    bonded_clients: Rc<dyn BondedClientStorage>,
+) -> Result<()> {
+    let this = Rc::new(GattService {
+        clients: RefCell::default(),
+        bonded_clients,
+        database_hash: Cell::default(),
+    });
+    // register first, so we observe the Database Hash once our service is added
+    database.register_listener(this.clone());
     database.add_service_with_handles(
         // GATT Service
         GattServiceWithHandle {
             handle: GATT_SERVICE_HANDLE,
             type_: GATT_SERVICE_UUID,
-            // Service Changed Characteristic
-            characteristics: vec![GattCharacteristicWithHandle {
-                handle: SERVICE_CHANGE_HANDLE,
-                type_: SERVICE_CHANGE_UUID,
-                permissions: AttPermissions::INDICATE,
-                descriptors: vec![GattDescriptorWithHandle {
-                    handle: SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE,
-                    type_: CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+            secondary: false,
+            included_services: vec![],
+            characteristics: vec![
+                // Service Changed Characteristic
+                GattCharacteristicWithHandle {
+                    handle: SERVICE_CHANGE_HANDLE,
+                    type_: SERVICE_CHANGE_UUID,
+                    permissions: AttPermissions::INDICATE,
+                    descriptors: vec![GattDescriptorWithHandle {
+                        handle: SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE,
+                        type_: CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+                        permissions: AttPermissions::READABLE
+                            | AttPermissions::WRITABLE_WITH_RESPONSE,
+                        static_value: None,
+                    }],
+                },
+                // Client Supported Features Characteristic
+                GattCharacteristicWithHandle {
+                    handle: CLIENT_SUPPORTED_FEATURES_HANDLE,
+                    type_: CLIENT_SUPPORTED_FEATURES_UUID,
                     permissions: AttPermissions::READABLE | AttPermissions::WRITABLE_WITH_RESPONSE,
-                }],
-            }],
+                    descriptors: vec![],
+                },
+                // Database Hash Characteristic
+                GattCharacteristicWithHandle {
+                    handle: DATABASE_HASH_HANDLE,
+                    type_: DATABASE_HASH_UUID,
+                    permissions: AttPermissions::READABLE,
+                    descriptors: vec![],
+                },
+            ],
         },
-        this.clone(),
+        this,
     )?;
-    database.register_listener(this);
     Ok(())
 }
 #[cfg(test)]
@@ -180,6 +439,7 @@ mod test {
     use super::*;
 
     use crate::{
+        core::address::AddressType,
         core::shared_box::SharedBox,
         gatt::{
             mocks::mock_datastore::MockDatastore,
@@ -190,21 +450,27 @@ mod test {
                 },
             },
         },
-        packets::{AttBuilder, AttChild},
+        packets::{AttBuilder, AttChild, AttReadRequestBuilder, AttReadResponseBuilder},
         utils::{
-            packet::{build_att_data, build_view_or_crash},
+            packet::{build_att_data, build_att_view_or_crash, build_view_or_crash},
             task::{block_on_locally, try_await},
         },
     };
 
     const TCB_IDX: TransportIndex = TransportIndex(1);
+    const IDENTITY: AddressWithType =
+        AddressWithType { address: [1, 2, 3, 4, 5, 6], address_type: AddressType::Public };
     const ANOTHER_TCB_IDX: TransportIndex = TransportIndex(2);
     const SERVICE_TYPE: Uuid = Uuid::new(0x1234);
     const CHARACTERISTIC_TYPE: Uuid = Uuid::new(0x5678);
 
     fn init_gatt_db() -> SharedBox<GattDatabase> {
+        init_gatt_db_with_storage(Rc::new(InMemoryBondedClientStorage::default()))
+    }
+
+    fn init_gatt_db_with_storage(storage: Rc<dyn BondedClientStorage>) -> SharedBox<GattDatabase> {
         let mut gatt_database = GattDatabase::new();
-        register_gatt_service(&mut gatt_database).unwrap();
+        register_gatt_service(&mut gatt_database, storage).unwrap();
         SharedBox::new(gatt_database)
     }
 
@@ -232,22 +498,31 @@ mod test {
         // act: discover all services
         let attrs = att_db.list_attributes();
 
-        // assert: 1 service + 1 char decl + 1 char value + 1 char descriptor = 4 attrs
-        assert_eq!(attrs.len(), 4);
+        // assert: 1 service + 3 char decls + 3 char values + 1 char descriptor = 8 attrs
+        assert_eq!(attrs.len(), 8);
         // assert: value handles are correct
         assert_eq!(attrs[0].handle, GATT_SERVICE_HANDLE);
         assert_eq!(attrs[2].handle, SERVICE_CHANGE_HANDLE);
+        assert_eq!(attrs[5].handle, CLIENT_SUPPORTED_FEATURES_HANDLE);
+        assert_eq!(attrs[7].handle, DATABASE_HASH_HANDLE);
         // assert: types are correct
         assert_eq!(attrs[0].type_, PRIMARY_SERVICE_DECLARATION_UUID);
         assert_eq!(attrs[1].type_, CHARACTERISTIC_UUID);
         assert_eq!(attrs[2].type_, SERVICE_CHANGE_UUID);
         assert_eq!(attrs[3].type_, CLIENT_CHARACTERISTIC_CONFIGURATION_UUID);
+        assert_eq!(attrs[5].type_, CLIENT_SUPPORTED_FEATURES_UUID);
+        assert_eq!(attrs[7].type_, DATABASE_HASH_UUID);
         // assert: permissions of value attrs are correct
         assert_eq!(attrs[2].permissions, AttPermissions::INDICATE);
         assert_eq!(
             attrs[3].permissions,
             AttPermissions::READABLE | AttPermissions::WRITABLE_WITH_RESPONSE
         );
+        assert_eq!(
+            attrs[5].permissions,
+            AttPermissions::READABLE | AttPermissions::WRITABLE_WITH_RESPONSE
+        );
+        assert_eq!(attrs[7].permissions, AttPermissions::READABLE);
     }
 
     #[test]
@@ -261,7 +536,8 @@ mod test {
             block_on_locally(att_db.read_attribute(SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE)).unwrap();
 
         // assert: we are not registered for either indications/notifications
-        let AttAttributeDataChild::GattClientCharacteristicConfiguration(configuration) = resp else {
+        let AttAttributeDataChild::GattClientCharacteristicConfiguration(configuration) = resp
+        else {
             unreachable!()
         };
         assert_eq!(
@@ -300,7 +576,8 @@ mod test {
             block_on_locally(att_db.read_attribute(SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE)).unwrap();
 
         // assert: we are registered for indications
-        let AttAttributeDataChild::GattClientCharacteristicConfiguration(configuration) = resp else {
+        let AttAttributeDataChild::GattClientCharacteristicConfiguration(configuration) = resp
+        else {
             unreachable!()
         };
         assert_eq!(
@@ -344,7 +621,8 @@ mod test {
             block_on_locally(att_db.read_attribute(SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE)).unwrap();
 
         // assert: we are not registered for indications
-        let AttAttributeDataChild::GattClientCharacteristicConfiguration(configuration) = resp else {
+        let AttAttributeDataChild::GattClientCharacteristicConfiguration(configuration) = resp
+        else {
             unreachable!()
         };
         assert_eq!(
@@ -369,6 +647,8 @@ mod test {
                     GattServiceWithHandle {
                         handle: AttHandle(15),
                         type_: SERVICE_TYPE,
+                        secondary: false,
+                        included_services: vec![],
                         characteristics: vec![GattCharacteristicWithHandle {
                             handle: AttHandle(17),
                             type_: CHARACTERISTIC_TYPE,
@@ -413,6 +693,8 @@ mod test {
                     GattServiceWithHandle {
                         handle: AttHandle(15),
                         type_: SERVICE_TYPE,
+                        secondary: false,
+                        included_services: vec![],
                         characteristics: vec![GattCharacteristicWithHandle {
                             handle: AttHandle(17),
                             type_: CHARACTERISTIC_TYPE,
@@ -451,6 +733,8 @@ mod test {
                     GattServiceWithHandle {
                         handle: AttHandle(15),
                         type_: SERVICE_TYPE,
+                        secondary: false,
+                        included_services: vec![],
                         characteristics: vec![GattCharacteristicWithHandle {
                             handle: AttHandle(17),
                             type_: CHARACTERISTIC_TYPE,
@@ -493,6 +777,8 @@ mod test {
                     GattServiceWithHandle {
                         handle: AttHandle(15),
                         type_: SERVICE_TYPE,
+                        secondary: false,
+                        included_services: vec![],
                         characteristics: vec![GattCharacteristicWithHandle {
                             handle: AttHandle(17),
                             type_: CHARACTERISTIC_TYPE,
@@ -511,4 +797,275 @@ mod test {
             assert!(rx2.recv().await.is_none());
         });
     }
+
+    fn add_service(gatt_db: &SharedBox<GattDatabase>) {
+        let (gatt_datastore, _) = MockDatastore::new();
+        gatt_db
+            .add_service_with_handles(
+                GattServiceWithHandle {
+                    handle: AttHandle(15),
+                    type_: SERVICE_TYPE,
+                    secondary: false,
+                    included_services: vec![],
+                    characteristics: vec![GattCharacteristicWithHandle {
+                        handle: AttHandle(17),
+                        type_: CHARACTERISTIC_TYPE,
+                        permissions: AttPermissions::empty(),
+                        descriptors: vec![],
+                    }],
+                },
+                Rc::new(gatt_datastore),
+            )
+            .unwrap();
+    }
+
+    async fn write_supported_features(
+        att_db: &impl AttDatabase,
+        bits: u8,
+    ) -> Result<(), AttErrorCode> {
+        att_db
+            .write_attribute(
+                CLIENT_SUPPORTED_FEATURES_HANDLE,
+                build_view_or_crash(build_att_data(AttAttributeDataChild::RawData([bits].into())))
+                    .view(),
+            )
+            .await
+    }
+
+    #[test]
+    fn test_read_database_hash() {
+        block_on_locally(async {
+            // arrange
+            let gatt_db = init_gatt_db();
+            let (att_db, _bearer, _rx) = add_connection(&gatt_db, TCB_IDX);
+
+            // act: read the hash before and after adding a service
+            let hash = att_db.read_attribute(DATABASE_HASH_HANDLE).await.unwrap();
+            add_service(&gatt_db);
+            let new_hash = att_db.read_attribute(DATABASE_HASH_HANDLE).await.unwrap();
+
+            // assert: the hash reflects the current database
+            assert_ne!(hash, new_hash);
+            assert_eq!(
+                new_hash,
+                AttAttributeDataChild::RawData(gatt_db.get_database_hash().0.into())
+            );
+        });
+    }
+
+    #[test]
+    fn test_write_supported_features() {
+        block_on_locally(async {
+            // arrange
+            let gatt_db = init_gatt_db();
+            let (att_db, bearer, _rx) = add_connection(&gatt_db, TCB_IDX);
+
+            // act: enable robust caching
+            write_supported_features(&att_db, 0b001).await.unwrap();
+            let resp = att_db.read_attribute(CLIENT_SUPPORTED_FEATURES_HANDLE).await.unwrap();
+
+            // assert
+            assert_eq!(resp, AttAttributeDataChild::RawData([0b001].into()));
+            assert!(bearer.change_awareness().is_robust_caching_enabled());
+        });
+    }
+
+    #[test]
+    fn test_cannot_clear_supported_features() {
+        block_on_locally(async {
+            // arrange: enable robust caching
+            let gatt_db = init_gatt_db();
+            let (att_db, _bearer, _rx) = add_connection(&gatt_db, TCB_IDX);
+            write_supported_features(&att_db, 0b001).await.unwrap();
+
+            // act: try to disable it again
+            let res = write_supported_features(&att_db, 0b010).await;
+
+            // assert: rejected, and unchanged
+            assert_eq!(res, Err(AttErrorCode::VALUE_NOT_ALLOWED));
+            assert_eq!(
+                att_db.read_attribute(CLIENT_SUPPORTED_FEATURES_HANDLE).await.unwrap(),
+                AttAttributeDataChild::RawData([0b001].into())
+            );
+        });
+    }
+
+    #[test]
+    fn test_change_unaware_client_gets_out_of_sync_error() {
+        block_on_locally(async {
+            // arrange: a client with robust caching enabled, that is now change-unaware
+            let gatt_db = init_gatt_db();
+            let (att_db, bearer, mut rx) = add_connection(&gatt_db, TCB_IDX);
+            write_supported_features(&att_db, 0b001).await.unwrap();
+            add_service(&gatt_db);
+            let request = build_att_view_or_crash(AttReadRequestBuilder {
+                attribute_handle: SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE.into(),
+            });
+
+            // act: send two requests
+            bearer.as_ref().handle_packet(request.view());
+            let first_resp = rx.recv().await.unwrap();
+            bearer.as_ref().handle_packet(request.view());
+            let second_resp = rx.recv().await.unwrap();
+
+            // assert: the first is rejected, but after that the client is change-aware
+            let AttChild::AttErrorResponse(first_resp) = first_resp._child_ else {
+                unreachable!();
+            };
+            assert_eq!(first_resp.error_code, AttErrorCode::DATABASE_OUT_OF_SYNC);
+            assert_eq!(
+                second_resp._child_,
+                AttReadResponseBuilder {
+                    value: build_att_data(GattClientCharacteristicConfigurationBuilder {
+                        notification: 0,
+                        indication: 0
+                    })
+                }
+                .into()
+            );
+        });
+    }
+
+    #[test]
+    fn test_reading_database_hash_makes_client_change_aware() {
+        block_on_locally(async {
+            // arrange: a client with robust caching enabled, that is now change-unaware
+            let gatt_db = init_gatt_db();
+            let (att_db, bearer, _rx) = add_connection(&gatt_db, TCB_IDX);
+            write_supported_features(&att_db, 0b001).await.unwrap();
+            add_service(&gatt_db);
+
+            // act
+            att_db.read_attribute(DATABASE_HASH_HANDLE).await.unwrap();
+
+            // assert
+            assert!(bearer.change_awareness().is_change_aware());
+        });
+    }
+
+    #[test]
+    fn test_bonded_client_restores_state_on_reconnect() {
+        block_on_locally(async {
+            // arrange: a bonded client registers for indications and enables robust caching,
+            // then disconnects
+            let gatt_db = init_gatt_db();
+            let (att_db, bearer, _rx) = add_connection(&gatt_db, TCB_IDX);
+            gatt_db.on_bearer_bonded(TCB_IDX, IDENTITY);
+            register_for_indication(&att_db, SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE).await.unwrap();
+            write_supported_features(&att_db, 0b001).await.unwrap();
+            drop(bearer);
+            gatt_db.on_bearer_dropped(TCB_IDX);
+
+            // act: reconnect
+            let (att_db, bearer, mut rx) = add_connection(&gatt_db, ANOTHER_TCB_IDX);
+            gatt_db.on_bearer_bonded(ANOTHER_TCB_IDX, IDENTITY);
+            let resp = att_db.read_attribute(SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE).await.unwrap();
+
+            // assert: the registration is restored, and nothing has changed
+            let AttAttributeDataChild::GattClientCharacteristicConfiguration(configuration) = resp
+            else {
+                unreachable!()
+            };
+            assert_eq!(
+                configuration,
+                GattClientCharacteristicConfigurationBuilder { notification: 0, indication: 1 }
+            );
+            assert!(bearer.change_awareness().is_robust_caching_enabled());
+            assert!(bearer.change_awareness().is_change_aware());
+            assert!(try_await(async move { rx.recv().await }).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_bonded_client_told_of_changes_while_disconnected() {
+        block_on_locally(async {
+            // arrange: a bonded client registers for indications, then disconnects
+            let gatt_db = init_gatt_db();
+            let (att_db, bearer, _rx) = add_connection(&gatt_db, TCB_IDX);
+            gatt_db.on_bearer_bonded(TCB_IDX, IDENTITY);
+            register_for_indication(&att_db, SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE).await.unwrap();
+            drop(bearer);
+            gatt_db.on_bearer_dropped(TCB_IDX);
+
+            // act: change the database, then reconnect
+            add_service(&gatt_db);
+            let (_att_db, bearer, mut rx) = add_connection(&gatt_db, ANOTHER_TCB_IDX);
+            gatt_db.on_bearer_bonded(ANOTHER_TCB_IDX, IDENTITY);
+
+            // assert: the client is change-unaware, and is sent a service changed indication
+            assert!(!bearer.change_awareness().is_change_aware());
+            let resp = rx.recv().await.unwrap();
+            let AttChild::AttHandleValueIndication(resp) = resp._child_ else {
+                unreachable!();
+            };
+            let AttAttributeDataChild::GattServiceChanged(resp) = resp.value._child_ else {
+                unreachable!();
+            };
+            assert_eq!(resp.start_handle.handle, 0x0001);
+            assert_eq!(resp.end_handle.handle, 0xFFFF);
+        });
+    }
+
+    #[test]
+    fn test_removed_bond_not_restored() {
+        block_on_locally(async {
+            // arrange: a bonded client registers for indications, then disconnects and unbonds
+            let gatt_db = init_gatt_db();
+            let (att_db, bearer, _rx) = add_connection(&gatt_db, TCB_IDX);
+            gatt_db.on_bearer_bonded(TCB_IDX, IDENTITY);
+            register_for_indication(&att_db, SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE).await.unwrap();
+            drop(bearer);
+            gatt_db.on_bearer_dropped(TCB_IDX);
+            gatt_db.on_bond_removed(IDENTITY);
+
+            // act: reconnect and bond again
+            let (att_db, _bearer, _rx) = add_connection(&gatt_db, ANOTHER_TCB_IDX);
+            gatt_db.on_bearer_bonded(ANOTHER_TCB_IDX, IDENTITY);
+            let resp = att_db.read_attribute(SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE).await.unwrap();
+
+            // assert: the registration was not restored
+            assert_eq!(
+                resp,
+                GattClientCharacteristicConfigurationBuilder { notification: 0, indication: 0 }
+                    .into()
+            );
+        });
+    }
+
+    #[test]
+    fn test_bonded_client_state_survives_restart() {
+        block_on_locally(async {
+            // arrange: a bonded client registers for indications and enables robust caching
+            let storage = Rc::new(InMemoryBondedClientStorage::default());
+            let gatt_db = init_gatt_db_with_storage(storage.clone());
+            let (att_db, _bearer, _rx) = add_connection(&gatt_db, TCB_IDX);
+            gatt_db.on_bearer_bonded(TCB_IDX, IDENTITY);
+            register_for_indication(&att_db, SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE).await.unwrap();
+            write_supported_features(&att_db, 0b001).await.unwrap();
+
+            // act: restart, reloading the state from storage, then reconnect
+            let stored = BondedClientState::from_bytes(&storage.load(IDENTITY).unwrap().to_bytes());
+            drop(gatt_db);
+            let storage = Rc::new(InMemoryBondedClientStorage::default());
+            storage.store(IDENTITY, stored.unwrap());
+            let gatt_db = init_gatt_db_with_storage(storage);
+            let (att_db, bearer, _rx) = add_connection(&gatt_db, TCB_IDX);
+            gatt_db.on_bearer_bonded(TCB_IDX, IDENTITY);
+            let resp = att_db.read_attribute(SERVICE_CHANGE_CCC_DESCRIPTOR_HANDLE).await.unwrap();
+
+            // assert: the registration and supported features are restored
+            assert_eq!(
+                resp,
+                GattClientCharacteristicConfigurationBuilder { notification: 0, indication: 1 }
+                    .into()
+            );
+            assert!(bearer.change_awareness().is_robust_caching_enabled());
+        });
+    }
+
+    #[test]
+    fn test_bonded_client_state_from_invalid_bytes() {
+        assert_eq!(BondedClientState::from_bytes(&[]), None);
+        assert_eq!(BondedClientState::from_bytes(&[1, 1, 2, 3]), None);
+    }
 }
@@ -0,0 +1,824 @@
+//! This module allows a whole GATT database to be described declaratively (as
+//! JSON), so it can be loaded into a GattDatabase without writing any code, and
+//! so a live GattDatabase can be dumped in the same format. Static values are
+//! served from an in-memory datastore.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::uuid::Uuid,
+    gatt::{
+        callbacks::{GattWriteRequestType, RawGattDatastore, TransactionDecision},
+        ffi::AttributeBackingType,
+        ids::{AttHandle, TransportIndex},
+    },
+    packets::{AttAttributeDataChild, AttAttributeDataView, AttErrorCode},
+};
+
+use super::gatt_database::{
+    AttPermissions, GattCharacteristicWithHandle, GattDatabase, GattDescriptorWithHandle,
+    GattIncludedServiceWithHandle, GattServiceWithHandle, CHARACTERISTIC_EXTENDED_PROPERTIES_UUID,
+};
+
+/// A declarative description of a GATT database
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticDatabase {
+    /// The services in the database. Included services must be listed before
+    /// the services including them.
+    pub services: Vec<StaticService>,
+}
+
+/// A primary or secondary service, as described in a StaticDatabase
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticService {
+    /// The handle of the service declaration
+    pub handle: u16,
+    /// The type of the service (e.g. "180f", or a full 128-bit UUID)
+    pub uuid: String,
+    /// Whether this is a secondary service
+    #[serde(default)]
+    pub secondary: bool,
+    /// The services included by this service
+    #[serde(default)]
+    pub included_services: Vec<StaticIncludedService>,
+    /// The characteristics contained in this service
+    #[serde(default)]
+    pub characteristics: Vec<StaticCharacteristic>,
+}
+
+/// An include declaration, as described in a StaticDatabase
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticIncludedService {
+    /// The handle of the include declaration
+    pub handle: u16,
+    /// The handle of the declaration of the included service
+    pub service_handle: u16,
+}
+
+/// A characteristic, as described in a StaticDatabase
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticCharacteristic {
+    /// The handle of the characteristic value. The declaration is one before
+    /// this handle.
+    pub handle: u16,
+    /// The type of the characteristic value
+    pub uuid: String,
+    /// The operations that can be performed on the value
+    #[serde(default)]
+    pub permissions: Vec<StaticPermission>,
+    /// The initial value, as a hex string (empty if not specified)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// The descriptors associated with this characteristic
+    #[serde(default)]
+    pub descriptors: Vec<StaticDescriptor>,
+}
+
+/// A descriptor, as described in a StaticDatabase
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticDescriptor {
+    /// The handle of the descriptor
+    pub handle: u16,
+    /// The type of the descriptor
+    pub uuid: String,
+    /// The operations that can be performed on the descriptor
+    #[serde(default)]
+    pub permissions: Vec<StaticPermission>,
+    /// The initial value, as a hex string (empty if not specified)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+/// The textual form of AttPermissions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StaticPermission {
+    /// See AttPermissions::READABLE
+    Read,
+    /// See AttPermissions::WRITABLE_WITHOUT_RESPONSE
+    WriteWithoutResponse,
+    /// See AttPermissions::WRITABLE_WITH_RESPONSE
+    Write,
+    /// See AttPermissions::INDICATE
+    Indicate,
+}
+
+const PERMISSIONS: [(StaticPermission, AttPermissions); 4] = [
+    (StaticPermission::Read, AttPermissions::READABLE),
+    (StaticPermission::WriteWithoutResponse, AttPermissions::WRITABLE_WITHOUT_RESPONSE),
+    (StaticPermission::Write, AttPermissions::WRITABLE_WITH_RESPONSE),
+    (StaticPermission::Indicate, AttPermissions::INDICATE),
+];
+
+fn to_att_permissions(permissions: &[StaticPermission]) -> AttPermissions {
+    PERMISSIONS
+        .iter()
+        .filter(|(permission, _)| permissions.contains(permission))
+        .fold(AttPermissions::empty(), |acc, (_, bits)| acc | *bits)
+}
+
+fn from_att_permissions(permissions: AttPermissions) -> Vec<StaticPermission> {
+    PERMISSIONS
+        .iter()
+        .filter(|(_, bits)| permissions.contains(*bits))
+        .map(|(permission, _)| *permission)
+        .collect()
+}
+
+fn parse_hex(value: &str) -> Result<Vec<u8>> {
+    value
+        .as_bytes()
+        .chunks(2)
+        .map(|byte| {
+            std::str::from_utf8(byte)
+                .ok()
+                .filter(|byte| byte.len() == 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| anyhow!("invalid hex value {value:?}"))
+        })
+        .collect()
+}
+
+fn to_hex(value: &[u8]) -> String {
+    value.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+impl StaticDatabase {
+    /// Parse a database from its JSON description
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Serialize the database into JSON
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Describe the services currently in the supplied database. Values are
+    /// taken from the datastore, if one is supplied and contains them -
+    /// attributes backed by another datastore (e.g. the upper layers) are
+    /// dumped without a value.
+    pub fn dump(database: &GattDatabase, datastore: Option<&StaticDatastore>) -> Self {
+        let value_of = |handle: AttHandle| {
+            datastore.and_then(|datastore| datastore.get_value(handle)).map(|value| to_hex(&value))
+        };
+        Self {
+            services: database
+                .get_services()
+                .into_iter()
+                .map(|service| StaticService {
+                    handle: service.handle.0,
+                    uuid: service.type_.to_string(),
+                    secondary: service.secondary,
+                    included_services: service
+                        .included_services
+                        .into_iter()
+                        .map(|included_service| StaticIncludedService {
+                            handle: included_service.handle.0,
+                            service_handle: included_service.service_handle.0,
+                        })
+                        .collect(),
+                    characteristics: service
+                        .characteristics
+                        .into_iter()
+                        .map(|characteristic| StaticCharacteristic {
+                            handle: characteristic.handle.0,
+                            uuid: characteristic.type_.to_string(),
+                            permissions: from_att_permissions(characteristic.permissions),
+                            value: value_of(characteristic.handle),
+                            descriptors: characteristic
+                                .descriptors
+                                .into_iter()
+                                .map(|descriptor| StaticDescriptor {
+                                    handle: descriptor.handle.0,
+                                    uuid: descriptor.type_.to_string(),
+                                    permissions: from_att_permissions(descriptor.permissions),
+                                    value: descriptor
+                                        .static_value
+                                        .as_deref()
+                                        .map(to_hex)
+                                        .or_else(|| value_of(descriptor.handle)),
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Add all services to the supplied database, with their values served
+    /// from the supplied datastore.
+    ///
+    /// Services already present at the same handle with the same type (e.g.
+    /// the built-in GATT and GAP services, if this description came from a
+    /// dump) are skipped. If any other service cannot be added, the services
+    /// added so far are removed again.
+    pub fn load_into(&self, database: &GattDatabase, datastore: Rc<StaticDatastore>) -> Result<()> {
+        let existing = database
+            .get_services()
+            .into_iter()
+            .map(|service| (service.handle, service.type_))
+            .collect::<Vec<_>>();
+
+        let mut services = vec![];
+        for service in &self.services {
+            let (service, values) = service.to_service_with_handle()?;
+            if existing.contains(&(service.handle, service.type_)) {
+                info!("skipping service {:?} since it already exists", service.handle);
+                continue;
+            }
+            services.push((service, values));
+        }
+
+        let mut added = vec![];
+        for (service, values) in services {
+            let handle = service.handle;
+            if let Err(err) = database.add_service_with_handles(service, datastore.clone()) {
+                for handle in added {
+                    if let Err(err) = database.remove_service_at_handle(handle) {
+                        warn!("failed to roll back service {handle:?}: {err:?}");
+                    }
+                }
+                return Err(err.context(format!("failed to add service {handle:?}")));
+            }
+            for (handle, value) in values {
+                datastore.set_value(handle, value);
+            }
+            added.push(handle);
+        }
+        Ok(())
+    }
+}
+
+/// The initial values of the attributes in a service
+type AttributeValues = Vec<(AttHandle, Vec<u8>)>;
+
+impl StaticService {
+    fn to_service_with_handle(&self) -> Result<(GattServiceWithHandle, AttributeValues)> {
+        let parse_uuid = |uuid: &str, handle: u16| {
+            uuid.parse::<Uuid>().with_context(|| format!("attribute {handle:#x}"))
+        };
+        let parse_value = |value: &Option<String>, handle: u16| {
+            value
+                .as_deref()
+                .map(parse_hex)
+                .transpose()
+                .map(|value| (AttHandle(handle), value.unwrap_or_default()))
+                .with_context(|| format!("attribute {handle:#x}"))
+        };
+
+        let mut values = vec![];
+        let mut characteristics = vec![];
+        for characteristic in &self.characteristics {
+            values.push(parse_value(&characteristic.value, characteristic.handle)?);
+            let mut descriptors = vec![];
+            for descriptor in &characteristic.descriptors {
+                let type_ = parse_uuid(&descriptor.uuid, descriptor.handle)?;
+                let value = parse_value(&descriptor.value, descriptor.handle)?;
+                // the extended properties are part of the database itself (and
+                // of its hash), so they cannot live in the datastore
+                let static_value = if type_ == CHARACTERISTIC_EXTENDED_PROPERTIES_UUID {
+                    Some(value.1.into())
+                } else {
+                    values.push(value);
+                    None
+                };
+                descriptors.push(GattDescriptorWithHandle {
+                    handle: AttHandle(descriptor.handle),
+                    type_,
+                    permissions: to_att_permissions(&descriptor.permissions),
+                    static_value,
+                });
+            }
+            if characteristic.handle <= self.handle + 1 {
+                bail!(
+                    "characteristic {:#x} leaves no room for its declaration after service {:#x}",
+                    characteristic.handle,
+                    self.handle
+                );
+            }
+            characteristics.push(GattCharacteristicWithHandle {
+                handle: AttHandle(characteristic.handle),
+                type_: parse_uuid(&characteristic.uuid, characteristic.handle)?,
+                permissions: to_att_permissions(&characteristic.permissions),
+                descriptors,
+            });
+        }
+
+        Ok((
+            GattServiceWithHandle {
+                handle: AttHandle(self.handle),
+                type_: parse_uuid(&self.uuid, self.handle)?,
+                secondary: self.secondary,
+                included_services: self
+                    .included_services
+                    .iter()
+                    .map(|included_service| GattIncludedServiceWithHandle {
+                        handle: AttHandle(included_service.handle),
+                        service_handle: AttHandle(included_service.service_handle),
+                    })
+                    .collect(),
+                characteristics,
+            },
+            values,
+        ))
+    }
+}
+
+/// An in-memory datastore holding the values of characteristics and
+/// descriptors loaded from a StaticDatabase. Values are shared between all
+/// connections, so a write from one peer is visible to every other peer.
+#[derive(Default)]
+pub struct StaticDatastore {
+    values: RefCell<BTreeMap<AttHandle, Vec<u8>>>,
+    prepared_writes: RefCell<HashMap<TransportIndex, Vec<PreparedWrite>>>,
+}
+
+struct PreparedWrite {
+    handle: AttHandle,
+    offset: u32,
+    data: Vec<u8>,
+}
+
+impl StaticDatastore {
+    /// Constructor
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Get the current value of an attribute
+    pub fn get_value(&self, handle: AttHandle) -> Option<Vec<u8>> {
+        self.values.borrow().get(&handle).cloned()
+    }
+
+    /// Set the current value of an attribute
+    pub fn set_value(&self, handle: AttHandle, value: Vec<u8>) {
+        self.values.borrow_mut().insert(handle, value);
+    }
+
+    /// Discard the writes prepared on a connection that went away without
+    /// executing them
+    pub fn clear_prepared_writes(&self, tcb_idx: TransportIndex) {
+        self.prepared_writes.borrow_mut().remove(&tcb_idx);
+    }
+}
+
+#[async_trait(?Send)]
+impl RawGattDatastore for StaticDatastore {
+    async fn read(
+        &self,
+        _: TransportIndex,
+        handle: AttHandle,
+        offset: u32,
+        _: AttributeBackingType,
+    ) -> Result<AttAttributeDataChild, AttErrorCode> {
+        let values = self.values.borrow();
+        let Some(value) = values.get(&handle) else {
+            warn!("read from {handle:?}, which has no static value");
+            return Err(AttErrorCode::UNLIKELY_ERROR);
+        };
+        let Some(value) = value.get(offset as usize..) else {
+            return Err(AttErrorCode::INVALID_OFFSET);
+        };
+        Ok(AttAttributeDataChild::RawData(value.into()))
+    }
+
+    async fn write(
+        &self,
+        tcb_idx: TransportIndex,
+        handle: AttHandle,
+        _: AttributeBackingType,
+        write_type: GattWriteRequestType,
+        data: AttAttributeDataView<'_>,
+    ) -> Result<(), AttErrorCode> {
+        let data = data.get_raw_payload().collect::<Vec<_>>();
+        match write_type {
+            GattWriteRequestType::Request => self.set_value(handle, data),
+            GattWriteRequestType::Prepare { offset } => self
+                .prepared_writes
+                .borrow_mut()
+                .entry(tcb_idx)
+                .or_default()
+                .push(PreparedWrite { handle, offset, data }),
+        }
+        Ok(())
+    }
+
+    fn write_no_response(
+        &self,
+        _: TransportIndex,
+        handle: AttHandle,
+        _: AttributeBackingType,
+        data: AttAttributeDataView<'_>,
+    ) {
+        self.set_value(handle, data.get_raw_payload().collect());
+    }
+
+    async fn execute(
+        &self,
+        tcb_idx: TransportIndex,
+        decision: TransactionDecision,
+    ) -> Result<(), AttErrorCode> {
+        let prepared_writes = self.prepared_writes.borrow_mut().remove(&tcb_idx);
+        let TransactionDecision::Execute = decision else {
+            return Ok(());
+        };
+
+        // validate every write before committing any of them, so the execution is atomic
+        let mut values = self.values.borrow().clone();
+        for PreparedWrite { handle, offset, data } in prepared_writes.into_iter().flatten() {
+            let value = values.entry(handle).or_default();
+            if offset as usize > value.len() {
+                return Err(AttErrorCode::INVALID_OFFSET);
+            }
+            value.truncate(offset as usize);
+            value.extend(data);
+        }
+        self.values.replace(values);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        core::shared_box::SharedBox,
+        gatt::server::att_database::AttDatabase,
+        utils::{
+            packet::{build_att_data, build_view_or_crash},
+            task::block_on_locally,
+        },
+    };
+
+    const TCB_IDX: TransportIndex = TransportIndex(1);
+    const ANOTHER_TCB_IDX: TransportIndex = TransportIndex(2);
+
+    const SERVICE_HANDLE: AttHandle = AttHandle(40);
+    const CHARACTERISTIC_HANDLE: AttHandle = AttHandle(42);
+    const DESCRIPTOR_HANDLE: AttHandle = AttHandle(43);
+
+    const DATABASE: &str = r#"{
+        "services": [
+            {
+                "handle": 40,
+                "uuid": "180f",
+                "characteristics": [
+                    {
+                        "handle": 42,
+                        "uuid": "2a19",
+                        "permissions": ["read", "write"],
+                        "value": "64",
+                        "descriptors": [
+                            {
+                                "handle": 43,
+                                "uuid": "12345678-9abc-def0-1234-56789abcdef0",
+                                "permissions": ["read"],
+                                "value": "0102030405"
+                            }
+                        ]
+                    }
+                ]
+            }
+        ]
+    }"#;
+
+    fn load_database() -> (SharedBox<GattDatabase>, Rc<StaticDatastore>) {
+        let gatt_db = SharedBox::new(GattDatabase::new());
+        let datastore = Rc::new(StaticDatastore::new());
+        StaticDatabase::from_json(DATABASE)
+            .unwrap()
+            .load_into(&gatt_db, datastore.clone())
+            .unwrap();
+        (gatt_db, datastore)
+    }
+
+    fn raw_data(value: &[u8]) -> AttAttributeDataChild {
+        AttAttributeDataChild::RawData(value.into())
+    }
+
+    #[test]
+    fn test_load_services() {
+        // arrange
+        let (gatt_db, _) = load_database();
+
+        // act
+        let services = gatt_db.get_services();
+
+        // assert
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].handle, SERVICE_HANDLE);
+        assert_eq!(services[0].type_, Uuid::new(0x180F));
+        let characteristic = &services[0].characteristics[0];
+        assert_eq!(characteristic.handle, CHARACTERISTIC_HANDLE);
+        assert_eq!(characteristic.type_, Uuid::new(0x2A19));
+        assert_eq!(
+            characteristic.permissions,
+            AttPermissions::READABLE | AttPermissions::WRITABLE_WITH_RESPONSE
+        );
+        assert_eq!(characteristic.descriptors[0].handle, DESCRIPTOR_HANDLE);
+        assert_eq!(
+            characteristic.descriptors[0].type_.to_string(),
+            "12345678-9abc-def0-1234-56789abcdef0"
+        );
+    }
+
+    #[test]
+    fn test_read_static_values() {
+        block_on_locally(async {
+            // arrange
+            let (gatt_db, _) = load_database();
+            let att_db = gatt_db.get_att_database(TCB_IDX);
+
+            // act
+            let characteristic = att_db.read_attribute(CHARACTERISTIC_HANDLE).await;
+            let descriptor = att_db.read_attribute(DESCRIPTOR_HANDLE).await;
+
+            // assert
+            assert_eq!(characteristic, Ok(raw_data(&[0x64])));
+            assert_eq!(descriptor, Ok(raw_data(&[1, 2, 3, 4, 5])));
+        });
+    }
+
+    #[test]
+    fn test_write_is_visible_to_other_connections() {
+        block_on_locally(async {
+            // arrange
+            let (gatt_db, _) = load_database();
+            let att_db = gatt_db.get_att_database(TCB_IDX);
+            let another_att_db = gatt_db.get_att_database(ANOTHER_TCB_IDX);
+            let data = build_view_or_crash(build_att_data(raw_data(&[0x32])));
+
+            // act
+            att_db.write_attribute(CHARACTERISTIC_HANDLE, data.view()).await.unwrap();
+            let value = another_att_db.read_attribute(CHARACTERISTIC_HANDLE).await;
+
+            // assert
+            assert_eq!(value, Ok(raw_data(&[0x32])));
+        });
+    }
+
+    #[test]
+    fn test_read_with_offset() {
+        block_on_locally(async {
+            // arrange
+            let datastore = StaticDatastore::new();
+            datastore.set_value(DESCRIPTOR_HANDLE, vec![1, 2, 3]);
+
+            // act
+            let tail = datastore
+                .read(TCB_IDX, DESCRIPTOR_HANDLE, 2, AttributeBackingType::Descriptor)
+                .await;
+            let end = datastore
+                .read(TCB_IDX, DESCRIPTOR_HANDLE, 3, AttributeBackingType::Descriptor)
+                .await;
+            let past_end = datastore
+                .read(TCB_IDX, DESCRIPTOR_HANDLE, 4, AttributeBackingType::Descriptor)
+                .await;
+
+            // assert
+            assert_eq!(tail, Ok(raw_data(&[3])));
+            assert_eq!(end, Ok(raw_data(&[])));
+            assert_eq!(past_end, Err(AttErrorCode::INVALID_OFFSET));
+        });
+    }
+
+    #[test]
+    fn test_prepared_writes_applied_on_execute() {
+        block_on_locally(async {
+            // arrange
+            let datastore = StaticDatastore::new();
+            datastore.set_value(CHARACTERISTIC_HANDLE, vec![1, 2, 3]);
+            let first = build_view_or_crash(build_att_data(raw_data(&[4, 5])));
+            let second = build_view_or_crash(build_att_data(raw_data(&[6])));
+
+            // act
+            for (offset, data) in [(1, &first), (3, &second)] {
+                RawGattDatastore::write(
+                    &datastore,
+                    TCB_IDX,
+                    CHARACTERISTIC_HANDLE,
+                    AttributeBackingType::Characteristic,
+                    GattWriteRequestType::Prepare { offset },
+                    data.view(),
+                )
+                .await
+                .unwrap();
+            }
+            let before_execute = datastore.get_value(CHARACTERISTIC_HANDLE);
+            datastore.execute(TCB_IDX, TransactionDecision::Execute).await.unwrap();
+
+            // assert
+            assert_eq!(before_execute, Some(vec![1, 2, 3]));
+            assert_eq!(datastore.get_value(CHARACTERISTIC_HANDLE), Some(vec![1, 4, 5, 6]));
+        });
+    }
+
+    #[test]
+    fn test_invalid_prepared_write_not_applied() {
+        block_on_locally(async {
+            // arrange
+            let datastore = StaticDatastore::new();
+            datastore.set_value(CHARACTERISTIC_HANDLE, vec![1, 2, 3]);
+            let data = build_view_or_crash(build_att_data(raw_data(&[4])));
+            for offset in [0, 5] {
+                RawGattDatastore::write(
+                    &datastore,
+                    TCB_IDX,
+                    CHARACTERISTIC_HANDLE,
+                    AttributeBackingType::Characteristic,
+                    GattWriteRequestType::Prepare { offset },
+                    data.view(),
+                )
+                .await
+                .unwrap();
+            }
+
+            // act
+            let res = datastore.execute(TCB_IDX, TransactionDecision::Execute).await;
+
+            // assert: nothing was written, not even the valid write
+            assert_eq!(res, Err(AttErrorCode::INVALID_OFFSET));
+            assert_eq!(datastore.get_value(CHARACTERISTIC_HANDLE), Some(vec![1, 2, 3]));
+        });
+    }
+
+    #[test]
+    fn test_prepared_writes_cleared_on_disconnect() {
+        block_on_locally(async {
+            // arrange
+            let datastore = StaticDatastore::new();
+            datastore.set_value(CHARACTERISTIC_HANDLE, vec![1, 2, 3]);
+            let data = build_view_or_crash(build_att_data(raw_data(&[4])));
+            RawGattDatastore::write(
+                &datastore,
+                TCB_IDX,
+                CHARACTERISTIC_HANDLE,
+                AttributeBackingType::Characteristic,
+                GattWriteRequestType::Prepare { offset: 0 },
+                data.view(),
+            )
+            .await
+            .unwrap();
+
+            // act
+            datastore.clear_prepared_writes(TCB_IDX);
+            datastore.execute(TCB_IDX, TransactionDecision::Execute).await.unwrap();
+
+            // assert
+            assert!(datastore.prepared_writes.borrow().is_empty());
+            assert_eq!(datastore.get_value(CHARACTERISTIC_HANDLE), Some(vec![1, 2, 3]));
+        });
+    }
+
+    #[test]
+    fn test_secondary_and_included_services_round_trip() {
+        // arrange
+        let database = StaticDatabase::from_json(
+            r#"{
+                "services": [
+                    { "handle": 10, "uuid": "180f", "secondary": true },
+                    {
+                        "handle": 20,
+                        "uuid": "180a",
+                        "included_services": [{ "handle": 21, "service_handle": 10 }]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let gatt_db = GattDatabase::new();
+        let datastore = Rc::new(StaticDatastore::new());
+
+        // act
+        database.load_into(&gatt_db, datastore.clone()).unwrap();
+        let dump = StaticDatabase::dump(&gatt_db, Some(&datastore));
+
+        // assert
+        assert_eq!(dump, database);
+    }
+
+    #[test]
+    fn test_dump_round_trip() {
+        // arrange
+        let (gatt_db, datastore) = load_database();
+
+        // act
+        let dump = StaticDatabase::dump(&gatt_db, Some(&datastore));
+        let reparsed = StaticDatabase::from_json(&dump.to_json().unwrap()).unwrap();
+
+        // assert
+        assert_eq!(dump, StaticDatabase::from_json(DATABASE).unwrap());
+        assert_eq!(reparsed, dump);
+    }
+
+    #[test]
+    fn test_dump_without_datastore_omits_values() {
+        // arrange
+        let (gatt_db, _) = load_database();
+
+        // act
+        let dump = StaticDatabase::dump(&gatt_db, None);
+
+        // assert
+        let characteristic = &dump.services[0].characteristics[0];
+        assert_eq!(characteristic.value, None);
+        assert_eq!(characteristic.descriptors[0].value, None);
+    }
+
+    #[test]
+    fn test_extended_properties_held_by_database() {
+        block_on_locally(async {
+            // arrange
+            let database = StaticDatabase::from_json(
+                &DATABASE
+                    .replace("12345678-9abc-def0-1234-56789abcdef0", "2900")
+                    .replace("0102030405", "0100"),
+            )
+            .unwrap();
+            let gatt_db = SharedBox::new(GattDatabase::new());
+            let datastore = Rc::new(StaticDatastore::new());
+            database.load_into(&gatt_db, datastore.clone()).unwrap();
+
+            // act
+            let value = gatt_db.get_att_database(TCB_IDX).read_attribute(DESCRIPTOR_HANDLE).await;
+            let dump = StaticDatabase::dump(&gatt_db, Some(&datastore));
+
+            // assert
+            assert_eq!(value, Ok(raw_data(&[1, 0])));
+            assert_eq!(datastore.get_value(DESCRIPTOR_HANDLE), None);
+            assert_eq!(dump, database);
+        });
+    }
+
+    #[test]
+    fn test_reload_dump_skips_existing_services() {
+        // arrange
+        let (gatt_db, datastore) = load_database();
+        let dump = StaticDatabase::dump(&gatt_db, Some(&datastore));
+
+        // act
+        let res = dump.load_into(&gatt_db, datastore);
+
+        // assert
+        assert!(res.is_ok());
+        assert_eq!(gatt_db.get_services().len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_uuid_rejected() {
+        // arrange
+        let database = StaticDatabase::from_json(&DATABASE.replace("2a19", "2a1")).unwrap();
+        let gatt_db = GattDatabase::new();
+
+        // act
+        let res = database.load_into(&gatt_db, Rc::new(StaticDatastore::new()));
+
+        // assert
+        assert!(res.is_err());
+        assert!(gatt_db.get_services().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_value_rejected() {
+        // arrange
+        let database = StaticDatabase::from_json(&DATABASE.replace("\"64\"", "\"6\"")).unwrap();
+        let gatt_db = GattDatabase::new();
+
+        // act
+        let res = database.load_into(&gatt_db, Rc::new(StaticDatastore::new()));
+
+        // assert
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_failed_load_is_rolled_back() {
+        // arrange: a second service overlapping with the first
+        let mut database = StaticDatabase::from_json(DATABASE).unwrap();
+        let mut overlapping = database.services[0].clone();
+        overlapping.uuid = "180a".into();
+        overlapping.handle = DESCRIPTOR_HANDLE.0;
+        overlapping.characteristics.clear();
+        database.services.push(overlapping);
+        let gatt_db = GattDatabase::new();
+
+        // act
+        let res = database.load_into(&gatt_db, Rc::new(StaticDatastore::new()));
+
+        // assert: the first service was removed again
+        assert!(res.is_err());
+        assert!(gatt_db.get_services().is_empty());
+    }
+}
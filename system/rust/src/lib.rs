@@ -15,14 +15,25 @@
 //! The core event loop for Rust modules. Here Rust modules are started in
 //! dependency order.
 
-use bt_common::init_flags::rust_event_loop_is_enabled;
+use bt_common::init_flags::{
+    get_gatt_server_transaction_timeout_ms, get_rust_event_loop_queue_depth_threshold,
+    get_rust_event_loop_stall_threshold_ms, rust_event_loop_abort_on_stall_is_enabled,
+    rust_event_loop_is_enabled,
+};
 use connection::le_manager::InactiveLeAclManager;
-use gatt::{channel::AttTransport, GattCallbacks};
+use gatt::{channel::AttTransport, server::services::gap::GapCallbacks, GattCallbacks};
 use log::{info, warn};
 use tokio::task::LocalSet;
 
-use self::core::shared_box::SharedBox;
-use std::{rc::Rc, sync::Mutex};
+use self::core::{
+    shared_box::SharedBox,
+    watchdog::{self, Watchdog, WatchdogConfig},
+};
+use std::{
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tokio::runtime::Builder;
 
 use tokio::sync::mpsc;
@@ -43,6 +54,8 @@ pub mod utils;
 /// The owner of the main Rust thread on which all Rust modules run
 struct GlobalModuleRegistry {
     pub task_tx: MainThreadTx,
+    /// Supervises the main thread, and counts the callbacks posted to it
+    pub watchdog: Arc<Watchdog>,
 }
 
 /// The ModuleViews lets us access all publicly accessible Rust modules from
@@ -55,8 +68,12 @@ pub struct ModuleViews<'a> {
     pub gatt_incoming_callbacks: Rc<gatt::callbacks::CallbackTransactionManager>,
     /// Proxies calls into GATT server
     pub gatt_module: &'a mut gatt::server::GattModule,
+    /// Proxies calls into GATT client
+    pub gatt_client_module: &'a mut gatt::client::GattClientModule,
     /// Proxies calls into connection manager
     pub connection_manager: SharedBox<connection::ConnectionManager>,
+    /// The recent events of the connection manager, for dumpsys
+    pub connection_events: connection::EventHistory,
 }
 
 static GLOBAL_MODULE_REGISTRY: Mutex<Option<GlobalModuleRegistry>> = Mutex::new(None);
@@ -68,6 +85,7 @@ impl GlobalModuleRegistry {
     /// in JNI modules.
     pub fn start(
         gatt_callbacks: Rc<dyn GattCallbacks>,
+        gap_callbacks: Rc<dyn GapCallbacks>,
         att_transport: Rc<dyn AttTransport>,
         le_acl_manager: impl InactiveLeAclManager,
         on_started: impl FnOnce(),
@@ -80,7 +98,11 @@ impl GlobalModuleRegistry {
         let local = LocalSet::new();
 
         let (tx, mut rx) = mpsc::unbounded_channel();
-        let prev_registry = GLOBAL_MODULE_REGISTRY.lock().unwrap().replace(Self { task_tx: tx });
+        let watchdog = Arc::new(Watchdog::new(watchdog_config_from_init_flags()));
+        let prev_registry = GLOBAL_MODULE_REGISTRY
+            .lock()
+            .unwrap()
+            .replace(Self { task_tx: tx, watchdog: watchdog.clone() });
 
         // initialization should only happen once
         assert!(prev_registry.is_none());
@@ -94,9 +116,23 @@ impl GlobalModuleRegistry {
             // Then follow the pure-Rust modules
             let gatt_incoming_callbacks =
                 Rc::new(gatt::callbacks::CallbackTransactionManager::new(gatt_callbacks.clone()));
+            match u64::try_from(get_gatt_server_transaction_timeout_ms()) {
+                Ok(timeout_ms) if timeout_ms > 0 => {
+                    gatt_incoming_callbacks.set_timeout(Duration::from_millis(timeout_ms))
+                }
+                _ => warn!(
+                    "ignoring invalid GATT server transaction timeout {}ms",
+                    get_gatt_server_transaction_timeout_ms()
+                ),
+            }
             let gatt_module = &mut gatt::server::GattModule::new(att_transport.clone());
+            gatt_module.set_gap_callbacks(gap_callbacks);
+            gatt_module.set_bonded_client_storage(Rc::new(gatt::ffi::BondedClientStorageImpl));
+            let gatt_client_module = &mut gatt::client::GattClientModule::new(att_transport);
 
             let connection_manager = connection::ConnectionManager::new(le_acl_manager);
+            let connection_events =
+                connection::EventHistory::record(connection_manager.subscribe());
 
             // All modules that are visible from incoming JNI / top-level interfaces should
             // be exposed here
@@ -104,9 +140,24 @@ impl GlobalModuleRegistry {
                 gatt_outgoing_callbacks: gatt_callbacks,
                 gatt_incoming_callbacks,
                 gatt_module,
+                gatt_client_module,
                 connection_manager,
+                connection_events,
             };
 
+            // let the watchdog know the event loop is alive even if no callbacks
+            // are posted, so it can tell when a future blocks the thread
+            if watchdog.config().is_enabled() {
+                watchdog::spawn_supervisor(watchdog.clone());
+                let watchdog = watchdog.clone();
+                tokio::task::spawn_local(async move {
+                    loop {
+                        watchdog.heartbeat();
+                        tokio::time::sleep(watchdog::CHECK_INTERVAL).await;
+                    }
+                });
+            }
+
             // notify upper layer that we are ready to receive messages
             on_started();
 
@@ -116,7 +167,11 @@ impl GlobalModuleRegistry {
             info!("starting Tokio event loop");
             while let Some(message) = rx.recv().await {
                 match message {
-                    MainThreadTxMessage::Callback(f) => f(&mut modules),
+                    MainThreadTxMessage::Callback(name, f) => {
+                        watchdog.on_callback_started(name);
+                        f(&mut modules);
+                        watchdog.on_callback_finished();
+                    }
                     MainThreadTxMessage::Stop => {
                         GLOBAL_MODULE_REGISTRY.lock().unwrap().take();
                         break;
@@ -124,13 +179,33 @@ impl GlobalModuleRegistry {
                 }
             }
         });
+        watchdog.stop();
         warn!("Rust thread queue has stopped, shutting down executor thread");
     }
 }
 
+/// Build the thresholds of the main thread watchdog. Non-positive values
+/// disable the corresponding check, and both are disabled by default so the
+/// watchdog threads don't wake up unless a threshold is configured.
+fn watchdog_config_from_init_flags() -> WatchdogConfig {
+    let stall_threshold_ms = get_rust_event_loop_stall_threshold_ms();
+    let queue_depth_threshold = get_rust_event_loop_queue_depth_threshold();
+    WatchdogConfig {
+        stall_threshold: u64::try_from(stall_threshold_ms)
+            .ok()
+            .filter(|ms| *ms > 0)
+            .map(Duration::from_millis),
+        queue_depth_threshold: usize::try_from(queue_depth_threshold)
+            .ok()
+            .filter(|depth| *depth > 0),
+        abort_on_stall: rust_event_loop_abort_on_stall_is_enabled(),
+    }
+}
+
 type BoxedMainThreadCallback = Box<dyn for<'a> FnOnce(&'a mut ModuleViews) + Send + 'static>;
 enum MainThreadTxMessage {
-    Callback(BoxedMainThreadCallback),
+    /// A callback, along with its name (for stall reports)
+    Callback(&'static str, BoxedMainThreadCallback),
     Stop,
 }
 type MainThreadTx = mpsc::UnboundedSender<MainThreadTxMessage>;
@@ -144,6 +219,11 @@ thread_local! {
     /// This will be lazily initialized on first use from each client thread
     static MAIN_THREAD_TX: MainThreadTx =
         GLOBAL_MODULE_REGISTRY.lock().unwrap().as_ref().expect("stack not initialized").task_tx.clone();
+
+    /// The watchdog of the Rust thread, which counts the callbacks posted by
+    /// do_in_rust_thread. Lazily initialized, like MAIN_THREAD_TX.
+    static MAIN_THREAD_WATCHDOG: Arc<Watchdog> =
+        GLOBAL_MODULE_REGISTRY.lock().unwrap().as_ref().expect("stack not initialized").watchdog.clone();
 }
 
 /// Posts a callback to the Rust thread and gives it access to public Rust
@@ -163,8 +243,34 @@ where
         warn!("ignoring do_in_rust_thread() invocation since Rust loop is inactive");
         return;
     }
-    let ret = MAIN_THREAD_TX.with(|tx| tx.send(MainThreadTxMessage::Callback(Box::new(f))));
+    MAIN_THREAD_WATCHDOG.with(|watchdog| watchdog.on_callback_queued());
+    let ret = MAIN_THREAD_TX
+        .with(|tx| tx.send(MainThreadTxMessage::Callback(std::any::type_name::<F>(), Box::new(f))));
     if ret.is_err() {
         panic!("Rust call failed");
     }
 }
+
+/// How long dumpsys will block waiting for the Rust thread
+const DUMP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Synchronously dump the state of some Rust modules, for dumpsys. `name`
+/// describes what is dumped, in case the Rust thread does not respond in time.
+/// Must not be invoked from the Rust thread itself.
+pub fn dump_from_rust_thread<F>(name: &'static str, f: F) -> String
+where
+    F: for<'a> FnOnce(&'a mut ModuleViews) -> String + Send + 'static,
+{
+    if !rust_event_loop_is_enabled() {
+        return String::new();
+    }
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    do_in_rust_thread(move |modules| {
+        let _ = tx.send(f(modules));
+    });
+    rx.recv_timeout(DUMP_TIMEOUT).unwrap_or_else(|err| {
+        warn!("failed to dump {name}: {err:?}");
+        format!("{name} unavailable ({err:?})\n")
+    })
+}
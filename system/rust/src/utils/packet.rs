@@ -43,6 +43,13 @@ pub fn HACK_child_to_opcode(child: &AttChild) -> AttOpcode {
         AttChild::AttExchangeMtuRequest(_) => AttOpcode::EXCHANGE_MTU_REQUEST,
         AttChild::AttExchangeMtuResponse(_) => AttOpcode::EXCHANGE_MTU_RESPONSE,
         AttChild::AttWriteCommand(_) => AttOpcode::WRITE_COMMAND,
+        AttChild::AttReadBlobRequest(_) => AttOpcode::READ_BLOB_REQUEST,
+        AttChild::AttReadBlobResponse(_) => AttOpcode::READ_BLOB_RESPONSE,
+        AttChild::AttPrepareWriteRequest(_) => AttOpcode::PREPARE_WRITE_REQUEST,
+        AttChild::AttPrepareWriteResponse(_) => AttOpcode::PREPARE_WRITE_RESPONSE,
+        AttChild::AttExecuteWriteRequest(_) => AttOpcode::EXECUTE_WRITE_REQUEST,
+        AttChild::AttExecuteWriteResponse(_) => AttOpcode::EXECUTE_WRITE_RESPONSE,
+        AttChild::AttHandleValueNotification(_) => AttOpcode::HANDLE_VALUE_NOTIFICATION,
     }
 }
 
@@ -6,7 +6,7 @@ use bluetooth_core::{
     gatt::{
         callbacks::{
             CallbackResponseError, CallbackTransactionManager, GattWriteRequestType, GattWriteType,
-            RawGattDatastore, TransactionDecision,
+            RawGattDatastore, TransactionDecision, DEFAULT_TIMEOUT, MAX_TIMEOUT,
         },
         ffi::AttributeBackingType,
         ids::{AttHandle, ConnectionId, ServerId, TransactionId, TransportIndex},
@@ -23,6 +23,9 @@ const SERVER_ID: ServerId = ServerId(2);
 
 const CONN_ID: ConnectionId = ConnectionId::new(TCB_IDX, SERVER_ID);
 
+const ANOTHER_TCB_IDX: TransportIndex = TransportIndex(3);
+const ANOTHER_CONN_ID: ConnectionId = ConnectionId::new(ANOTHER_TCB_IDX, SERVER_ID);
+
 const HANDLE_1: AttHandle = AttHandle(3);
 const BACKING_TYPE: AttributeBackingType = AttributeBackingType::Descriptor;
 
@@ -60,10 +63,10 @@ fn test_read_characteristic_callback() {
         });
 
         // assert: verify the read callback is received
-        let MockCallbackEvents::OnServerRead(
-            CONN_ID, _, HANDLE_1, BACKING_TYPE, OFFSET,
-        ) = callbacks_rx.recv().await.unwrap() else {
-          unreachable!()
+        let MockCallbackEvents::OnServerRead(CONN_ID, _, HANDLE_1, BACKING_TYPE, OFFSET) =
+            callbacks_rx.recv().await.unwrap()
+        else {
+            unreachable!()
         };
     });
 }
@@ -240,9 +243,15 @@ fn test_write_characteristic_callback() {
 
         // assert: verify the write callback is received
         let MockCallbackEvents::OnServerWrite(
-            CONN_ID, _, HANDLE_1, BACKING_TYPE, GattWriteType::Request(WRITE_REQUEST_TYPE), recv_data
-        ) = callbacks_rx.recv().await.unwrap() else {
-          unreachable!()
+            CONN_ID,
+            _,
+            HANDLE_1,
+            BACKING_TYPE,
+            GattWriteType::Request(WRITE_REQUEST_TYPE),
+            recv_data,
+        ) = callbacks_rx.recv().await.unwrap()
+        else {
+            unreachable!()
         };
         assert_eq!(
             recv_data.view().get_raw_payload().collect::<Vec<_>>(),
@@ -366,9 +375,15 @@ fn test_write_no_response_callback() {
 
         // assert: verify the write callback is received
         let MockCallbackEvents::OnServerWrite(
-            CONN_ID, _, HANDLE_1, BACKING_TYPE, GattWriteType::Command, recv_data
-        ) = callbacks_rx.recv().await.unwrap() else {
-          unreachable!()
+            CONN_ID,
+            _,
+            HANDLE_1,
+            BACKING_TYPE,
+            GattWriteType::Command,
+            recv_data,
+        ) = callbacks_rx.recv().await.unwrap()
+        else {
+            unreachable!()
         };
         assert_eq!(
             recv_data.view().get_raw_payload().collect::<Vec<_>>(),
@@ -392,10 +407,10 @@ fn test_execute_characteristic_callback() {
         });
 
         // assert: verify the execute callback is received
-        let MockCallbackEvents::OnExecute(
-            CONN_ID, _, TransactionDecision::Cancel
-        ) = callbacks_rx.recv().await.unwrap() else {
-          unreachable!()
+        let MockCallbackEvents::OnExecute(CONN_ID, _, TransactionDecision::Cancel) =
+            callbacks_rx.recv().await.unwrap()
+        else {
+            unreachable!()
         };
     });
 }
@@ -424,3 +439,195 @@ fn test_execute_characteristic_response() {
         assert_eq!(pending_execute.await.unwrap(), Err(AttErrorCode::WRITE_NOT_PERMITTED));
     });
 }
+
+#[test]
+fn test_datastore_latency_recorded() {
+    start_test(async {
+        // arrange
+        let (callback_manager, mut callbacks_rx) = initialize_manager_with_connection();
+
+        // act: respond to a read after a delay
+        let datastore = callback_manager.get_datastore(SERVER_ID);
+        let pending_read =
+            spawn_local(
+                async move { datastore.read(TCB_IDX, HANDLE_1, OFFSET, BACKING_TYPE).await },
+            );
+        let trans_id = pull_trans_id(&mut callbacks_rx).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        callback_manager
+            .send_response(CONN_ID, trans_id, Ok(AttAttributeDataChild::RawData([1].into())))
+            .unwrap();
+        pending_read.await.unwrap().unwrap();
+
+        // assert: the delay was recorded against the transport
+        let latency = callback_manager.get_datastore_latency(TCB_IDX);
+        assert_eq!(latency.count, 1);
+        assert!(latency.max >= Duration::from_millis(100));
+        assert!(latency.max < Duration::from_millis(200));
+    });
+}
+
+#[test]
+fn test_datastore_latency_includes_timeouts() {
+    start_test(async {
+        // arrange
+        let (callback_manager, _callbacks_rx) = initialize_manager_with_connection();
+
+        // act: let a read time out
+        let datastore = callback_manager.get_datastore(SERVER_ID);
+        datastore.read(TCB_IDX, HANDLE_1, OFFSET, BACKING_TYPE).await.unwrap_err();
+
+        // assert
+        let latency = callback_manager.get_datastore_latency(TCB_IDX);
+        assert_eq!(latency.count, 1);
+        assert!(latency.max > Duration::from_secs(14));
+    });
+}
+
+#[test]
+fn test_datastore_latency_cleared_on_disconnect() {
+    start_test(async {
+        // arrange
+        let (callback_manager, _callbacks_rx) = initialize_manager_with_connection();
+        let datastore = callback_manager.get_datastore(SERVER_ID);
+        datastore.read(TCB_IDX, HANDLE_1, OFFSET, BACKING_TYPE).await.unwrap_err();
+
+        // act
+        callback_manager.on_le_disconnect(TCB_IDX);
+
+        // assert
+        assert_eq!(callback_manager.get_datastore_latency(TCB_IDX).count, 0);
+    });
+}
+
+#[test]
+fn test_configured_timeout() {
+    start_test(async {
+        // arrange
+        let (callback_manager, mut callbacks_rx) = initialize_manager_with_connection();
+        callback_manager.set_timeout(Duration::from_secs(2));
+
+        // act: start an operation and never respond to it
+        let time_sent = Instant::now();
+        let datastore = callback_manager.get_datastore(SERVER_ID);
+        let pending =
+            spawn_local(
+                async move { datastore.read(TCB_IDX, HANDLE_1, OFFSET, BACKING_TYPE).await },
+            );
+        let trans_id = pull_trans_id(&mut callbacks_rx).await;
+
+        // assert: that we time out after 2s, and the app is told
+        assert_eq!(pending.await.unwrap(), Err(AttErrorCode::UNLIKELY_ERROR));
+        let time_slept = Instant::now().duration_since(time_sent);
+        assert!(time_slept > Duration::from_secs(1));
+        assert!(time_slept < Duration::from_secs(3));
+        let MockCallbackEvents::OnTransactionCancelled(CONN_ID, cancelled_trans_id) =
+            callbacks_rx.recv().await.unwrap()
+        else {
+            unreachable!()
+        };
+        assert_eq!(cancelled_trans_id, trans_id);
+    });
+}
+
+#[test]
+fn test_timeout_is_clamped() {
+    start_test(async {
+        // arrange
+        let (callback_manager, _callbacks_rx) = initialize_manager_with_connection();
+        assert_eq!(callback_manager.get_timeout(), DEFAULT_TIMEOUT);
+
+        // act: try to configure a timeout longer than the ATT timeout
+        callback_manager.set_timeout(Duration::from_secs(60));
+
+        // assert
+        assert_eq!(callback_manager.get_timeout(), MAX_TIMEOUT);
+        assert!(MAX_TIMEOUT < Duration::from_secs(30));
+    });
+}
+
+#[test]
+fn test_late_response_after_configured_timeout() {
+    start_test(async {
+        // arrange
+        let (callback_manager, mut callbacks_rx) = initialize_manager_with_connection();
+        callback_manager.set_timeout(Duration::from_secs(1));
+        let datastore = callback_manager.get_datastore(SERVER_ID);
+        let pending =
+            spawn_local(
+                async move { datastore.read(TCB_IDX, HANDLE_1, OFFSET, BACKING_TYPE).await },
+            );
+        let trans_id = pull_trans_id(&mut callbacks_rx).await;
+        assert_eq!(pending.await.unwrap(), Err(AttErrorCode::UNLIKELY_ERROR));
+
+        // act: the app responds after the timeout
+        let resp = callback_manager.send_response(
+            CONN_ID,
+            trans_id,
+            Ok(AttAttributeDataChild::RawData([1, 2].into())),
+        );
+
+        // assert: the response is rejected
+        assert_eq!(resp, Err(CallbackResponseError::NonExistentTransaction(trans_id)));
+    });
+}
+
+#[test]
+fn test_disconnect_cancels_pending_transaction() {
+    start_test(async {
+        // arrange: start an operation
+        let (callback_manager, mut callbacks_rx) = initialize_manager_with_connection();
+        let datastore = callback_manager.get_datastore(SERVER_ID);
+        let pending =
+            spawn_local(
+                async move { datastore.read(TCB_IDX, HANDLE_1, OFFSET, BACKING_TYPE).await },
+            );
+        let trans_id = pull_trans_id(&mut callbacks_rx).await;
+
+        // act: disconnect while it is pending
+        let time_disconnected = Instant::now();
+        callback_manager.on_le_disconnect(TCB_IDX);
+
+        // assert: the operation resolves immediately, and the app is told
+        assert_eq!(pending.await.unwrap(), Err(AttErrorCode::UNLIKELY_ERROR));
+        assert!(Instant::now().duration_since(time_disconnected) < Duration::from_secs(1));
+        let MockCallbackEvents::OnTransactionCancelled(CONN_ID, cancelled_trans_id) =
+            callbacks_rx.recv().await.unwrap()
+        else {
+            unreachable!()
+        };
+        assert_eq!(cancelled_trans_id, trans_id);
+        // a late response is rejected
+        assert_eq!(
+            callback_manager.send_response(CONN_ID, trans_id, Err(AttErrorCode::INVALID_HANDLE)),
+            Err(CallbackResponseError::NonExistentTransaction(trans_id))
+        );
+        // and the cancelled transaction is not counted towards datastore latency
+        assert_eq!(callback_manager.get_datastore_latency(TCB_IDX).count, 0);
+    });
+}
+
+#[test]
+fn test_disconnect_does_not_cancel_other_transports() {
+    start_test(async {
+        // arrange: start an operation on another transport
+        let (callback_manager, mut callbacks_rx) = initialize_manager_with_connection();
+        let datastore = callback_manager.get_datastore(SERVER_ID);
+        let pending = spawn_local(async move {
+            datastore.read(ANOTHER_TCB_IDX, HANDLE_1, OFFSET, BACKING_TYPE).await
+        });
+        let trans_id = pull_trans_id(&mut callbacks_rx).await;
+
+        // act: disconnect the first transport, then respond
+        callback_manager.on_le_disconnect(TCB_IDX);
+        let resp = callback_manager.send_response(
+            ANOTHER_CONN_ID,
+            trans_id,
+            Err(AttErrorCode::INVALID_HANDLE),
+        );
+
+        // assert: the operation on the other transport was unaffected
+        assert_eq!(resp, Ok(()));
+        assert_eq!(pending.await.unwrap(), Err(AttErrorCode::INVALID_HANDLE));
+    });
+}
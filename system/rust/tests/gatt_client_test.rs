@@ -0,0 +1,328 @@
+use std::rc::Rc;
+
+use bluetooth_core::{
+    core::uuid::Uuid,
+    gatt::{
+        client::{
+            att_client_bearer::{AttValueUpdate, ValueUpdateKind},
+            discovery::{
+                CharacteristicProperties, DiscoveredCharacteristic, DiscoveredDescriptor,
+                DiscoveredService, CLIENT_CHARACTERISTIC_CONFIGURATION_UUID,
+            },
+            GattClient, GattClientModule,
+        },
+        ffi::AttributeBackingType,
+        ids::{AttHandle, ConnectionId, ServerId, TransportIndex},
+        mocks::{
+            mock_datastore::{MockDatastore, MockDatastoreEvents},
+            mock_transport::MockAttTransport,
+        },
+        server::{
+            gatt_database::{
+                AttPermissions, GattCharacteristicWithHandle, GattDescriptorWithHandle,
+                GattServiceWithHandle,
+            },
+            services::gatt::{GATT_SERVICE_UUID, SERVICE_CHANGE_UUID},
+            GattModule,
+        },
+    },
+    packets::{
+        AttAttributeDataChild, AttBuilder, GattServiceChangedBuilder, OwnedAttView, OwnedPacket,
+        Serializable,
+    },
+    utils::packet::build_att_data,
+};
+
+use tokio::{sync::mpsc::UnboundedReceiver, task::spawn_local};
+use utils::start_test;
+
+mod utils;
+
+const TCB_IDX: TransportIndex = TransportIndex(1);
+const SERVER_ID: ServerId = ServerId(2);
+const CONN_ID: ConnectionId = ConnectionId::new(TCB_IDX, SERVER_ID);
+
+const SERVICE_HANDLE: AttHandle = AttHandle(10);
+const CHARACTERISTIC_HANDLE: AttHandle = AttHandle(12);
+const DESCRIPTOR_HANDLE: AttHandle = AttHandle(13);
+const ANOTHER_SERVICE_HANDLE: AttHandle = AttHandle(30);
+
+const SERVICE_TYPE: Uuid = Uuid::new(0x0102);
+const CHARACTERISTIC_TYPE: Uuid = Uuid::new(0x0103);
+const DESCRIPTOR_TYPE: Uuid = Uuid::new(0x0104);
+const LONG_SERVICE_TYPE: Uuid = Uuid::new(0x01020304);
+
+const DATA: [u8; 4] = [1, 2, 3, 4];
+
+const SERVICE: DiscoveredService = DiscoveredService {
+    handle: SERVICE_HANDLE,
+    end_handle: DESCRIPTOR_HANDLE,
+    type_: SERVICE_TYPE,
+};
+
+/// Links the Rust GATT client to the Rust GATT server over a loopback ATT
+/// channel, and returns the server-side datastore events
+fn start_loopback() -> (GattModule, GattClientModule, UnboundedReceiver<MockDatastoreEvents>) {
+    let (server_transport, mut server_rx) = MockAttTransport::new();
+    let (client_transport, mut client_rx) = MockAttTransport::new();
+    let mut gatt = GattModule::new(Rc::new(server_transport));
+    let mut client = GattClientModule::new(Rc::new(client_transport));
+
+    gatt.open_gatt_server(SERVER_ID).unwrap();
+    let (datastore, data_rx) = MockDatastore::new();
+    gatt.register_gatt_service(
+        SERVER_ID,
+        GattServiceWithHandle {
+            handle: SERVICE_HANDLE,
+            type_: SERVICE_TYPE,
+            secondary: false,
+            included_services: vec![],
+            characteristics: vec![GattCharacteristicWithHandle {
+                handle: CHARACTERISTIC_HANDLE,
+                type_: CHARACTERISTIC_TYPE,
+                permissions: AttPermissions::READABLE | AttPermissions::WRITABLE_WITH_RESPONSE,
+                descriptors: vec![GattDescriptorWithHandle {
+                    handle: DESCRIPTOR_HANDLE,
+                    type_: DESCRIPTOR_TYPE,
+                    permissions: AttPermissions::READABLE,
+                    static_value: None,
+                }],
+            }],
+        },
+        datastore,
+    )
+    .unwrap();
+    gatt.on_le_connect(CONN_ID).unwrap();
+    client.on_le_connect(TCB_IDX).unwrap();
+
+    let server_bearer = gatt.get_bearer(TCB_IDX).unwrap().downgrade();
+    let client_bearer = client.get_bearer(TCB_IDX).unwrap().downgrade();
+    spawn_local(async move {
+        while let Some((_, packet)) = server_rx.recv().await {
+            let packet = loopback(packet);
+            client_bearer.with(|bearer| bearer.unwrap().handle_packet(packet.view()));
+        }
+    });
+    spawn_local(async move {
+        while let Some((_, packet)) = client_rx.recv().await {
+            let packet = loopback(packet);
+            server_bearer.with(|bearer| bearer.unwrap().handle_packet(packet.view()));
+        }
+    });
+
+    (gatt, client, data_rx)
+}
+
+fn loopback(packet: AttBuilder) -> OwnedAttView {
+    OwnedAttView::try_parse(packet.to_vec().unwrap().into_boxed_slice()).unwrap()
+}
+
+fn get_client(client: &GattClientModule) -> GattClient {
+    client.get_client(TCB_IDX).unwrap()
+}
+
+#[test]
+fn test_discover_primary_services() {
+    start_test(async move {
+        // arrange
+        let (_gatt, client, _data_rx) = start_loopback();
+
+        // act
+        let services = get_client(&client).discover_primary_services().await.unwrap();
+
+        // assert: the builtin services are found along with the registered one
+        assert!(services.contains(&SERVICE));
+        assert!(services.iter().any(|service| service.type_ == GATT_SERVICE_UUID));
+        assert!(services.windows(2).all(|pair| pair[0].end_handle < pair[1].handle));
+    });
+}
+
+#[test]
+fn test_discover_primary_services_by_uuid() {
+    start_test(async move {
+        // arrange: a service with a 128-bit UUID, since the server stores all UUIDs
+        // in their 128-bit form
+        let (mut gatt, client, _data_rx) = start_loopback();
+        let (datastore, _) = MockDatastore::new();
+        gatt.register_gatt_service(
+            SERVER_ID,
+            GattServiceWithHandle {
+                handle: ANOTHER_SERVICE_HANDLE,
+                type_: LONG_SERVICE_TYPE,
+                secondary: false,
+                included_services: vec![],
+                characteristics: vec![],
+            },
+            datastore,
+        )
+        .unwrap();
+
+        // act
+        let services =
+            get_client(&client).discover_primary_services_by_uuid(LONG_SERVICE_TYPE).await.unwrap();
+
+        // assert
+        assert_eq!(
+            services,
+            vec![DiscoveredService {
+                handle: ANOTHER_SERVICE_HANDLE,
+                end_handle: ANOTHER_SERVICE_HANDLE,
+                type_: LONG_SERVICE_TYPE
+            }]
+        );
+    });
+}
+
+#[test]
+fn test_discover_secondary_services() {
+    start_test(async move {
+        // arrange
+        let (_gatt, client, _data_rx) = start_loopback();
+
+        // act
+        let services = get_client(&client).discover_secondary_services().await.unwrap();
+
+        // assert: the Rust server only exposes primary services
+        assert_eq!(services, vec![]);
+    });
+}
+
+#[test]
+fn test_discover_characteristics_and_descriptors() {
+    start_test(async move {
+        // arrange
+        let (_gatt, client, _data_rx) = start_loopback();
+        let client = get_client(&client);
+
+        // act
+        let characteristics = client.discover_characteristics(&SERVICE).await.unwrap();
+        let descriptors =
+            client.discover_descriptors(&characteristics[0], SERVICE.end_handle).await.unwrap();
+        let included_services = client.find_included_services(&SERVICE).await.unwrap();
+
+        // assert
+        assert_eq!(
+            characteristics,
+            vec![DiscoveredCharacteristic {
+                handle: AttHandle(CHARACTERISTIC_HANDLE.0 - 1),
+                value_handle: CHARACTERISTIC_HANDLE,
+                properties: CharacteristicProperties::READ | CharacteristicProperties::WRITE,
+                type_: CHARACTERISTIC_TYPE,
+            }]
+        );
+        assert_eq!(
+            descriptors,
+            vec![DiscoveredDescriptor { handle: DESCRIPTOR_HANDLE, type_: DESCRIPTOR_TYPE }]
+        );
+        assert_eq!(included_services, vec![]);
+    });
+}
+
+#[test]
+fn test_read_and_write_characteristic() {
+    start_test(async move {
+        // arrange
+        let (_gatt, client, mut data_rx) = start_loopback();
+        let client = get_client(&client);
+
+        // act: read
+        let pending_read = spawn_local({
+            let client = client.clone();
+            async move { client.read(CHARACTERISTIC_HANDLE).await }
+        });
+        let MockDatastoreEvents::Read(
+            TCB_IDX,
+            CHARACTERISTIC_HANDLE,
+            AttributeBackingType::Characteristic,
+            reply,
+        ) = data_rx.recv().await.unwrap()
+        else {
+            unreachable!()
+        };
+        reply.send(Ok(AttAttributeDataChild::RawData(DATA.into()))).unwrap();
+        let value = pending_read.await.unwrap().unwrap();
+
+        // act: write
+        let pending_write =
+            spawn_local(async move { client.write(CHARACTERISTIC_HANDLE, &DATA).await });
+        let MockDatastoreEvents::Write(
+            TCB_IDX,
+            CHARACTERISTIC_HANDLE,
+            AttributeBackingType::Characteristic,
+            data,
+            reply,
+        ) = data_rx.recv().await.unwrap()
+        else {
+            unreachable!()
+        };
+        reply.send(Ok(())).unwrap();
+
+        // assert
+        assert_eq!(value, DATA.into());
+        assert_eq!(data.view().get_raw_payload().collect::<Vec<_>>(), DATA);
+        assert!(pending_write.await.unwrap().is_ok());
+    });
+}
+
+#[test]
+fn test_subscribe_to_service_changed() {
+    start_test(async move {
+        // arrange: find the service changed characteristic and its CCCD
+        let (mut gatt, client, _data_rx) = start_loopback();
+        let client = get_client(&client);
+        let gatt_service = client
+            .discover_primary_services()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|service| service.type_ == GATT_SERVICE_UUID)
+            .unwrap();
+        let service_changed = client
+            .discover_characteristics_by_uuid(&gatt_service, SERVICE_CHANGE_UUID)
+            .await
+            .unwrap()[0];
+        let cccd = client
+            .discover_descriptors(&service_changed, gatt_service.end_handle)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|descriptor| descriptor.type_ == CLIENT_CHARACTERISTIC_CONFIGURATION_UUID)
+            .unwrap();
+
+        // act: subscribe, then add a service
+        let mut updates = client
+            .subscribe(&service_changed, cccd.handle, ValueUpdateKind::Indication)
+            .await
+            .unwrap();
+        let (datastore, _) = MockDatastore::new();
+        gatt.register_gatt_service(
+            SERVER_ID,
+            GattServiceWithHandle {
+                handle: ANOTHER_SERVICE_HANDLE,
+                type_: SERVICE_TYPE,
+                secondary: false,
+                included_services: vec![],
+                characteristics: vec![],
+            },
+            datastore,
+        )
+        .unwrap();
+        let update = updates.recv().await.unwrap();
+
+        // assert
+        assert_eq!(
+            update,
+            AttValueUpdate {
+                handle: service_changed.value_handle,
+                value: build_att_data(GattServiceChangedBuilder {
+                    start_handle: ANOTHER_SERVICE_HANDLE.into(),
+                    end_handle: ANOTHER_SERVICE_HANDLE.into(),
+                })
+                .to_vec()
+                .unwrap()
+                .into(),
+                kind: ValueUpdateKind::Indication,
+            }
+        );
+    });
+}
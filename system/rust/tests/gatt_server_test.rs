@@ -16,7 +16,7 @@ use bluetooth_core::{
                 GattServiceWithHandle, CHARACTERISTIC_UUID, PRIMARY_SERVICE_DECLARATION_UUID,
             },
             services::{
-                gap::DEVICE_NAME_UUID,
+                gap::{GapConfiguration, DEVICE_NAME_UUID},
                 gatt::{
                     CLIENT_CHARACTERISTIC_CONFIGURATION_UUID, GATT_SERVICE_UUID,
                     SERVICE_CHANGE_UUID,
@@ -54,9 +54,9 @@ const ANOTHER_TCB_IDX: TransportIndex = TransportIndex(2);
 const ANOTHER_SERVER_ID: ServerId = ServerId(3);
 const ANOTHER_CONN_ID: ConnectionId = ConnectionId::new(ANOTHER_TCB_IDX, ANOTHER_SERVER_ID);
 
-const SERVICE_HANDLE: AttHandle = AttHandle(6);
-const CHARACTERISTIC_HANDLE: AttHandle = AttHandle(8);
-const DESCRIPTOR_HANDLE: AttHandle = AttHandle(9);
+const SERVICE_HANDLE: AttHandle = AttHandle(10);
+const CHARACTERISTIC_HANDLE: AttHandle = AttHandle(12);
+const DESCRIPTOR_HANDLE: AttHandle = AttHandle(13);
 
 const SERVICE_TYPE: Uuid = Uuid::new(0x0102);
 const CHARACTERISTIC_TYPE: Uuid = Uuid::new(0x0103);
@@ -83,6 +83,8 @@ fn create_server_and_open_connection(
         GattServiceWithHandle {
             handle: SERVICE_HANDLE,
             type_: SERVICE_TYPE,
+            secondary: false,
+            included_services: vec![],
             characteristics: vec![GattCharacteristicWithHandle {
                 handle: CHARACTERISTIC_HANDLE,
                 type_: CHARACTERISTIC_TYPE,
@@ -93,6 +95,7 @@ fn create_server_and_open_connection(
                     handle: DESCRIPTOR_HANDLE,
                     type_: DESCRIPTOR_TYPE,
                     permissions: AttPermissions::READABLE | AttPermissions::WRITABLE_WITH_RESPONSE,
+                    static_value: None,
                 }],
             }],
         },
@@ -390,6 +393,8 @@ fn test_multiple_servers() {
             GattServiceWithHandle {
                 handle: SERVICE_HANDLE,
                 type_: SERVICE_TYPE,
+                secondary: false,
+                included_services: vec![],
                 characteristics: vec![GattCharacteristicWithHandle {
                     handle: CHARACTERISTIC_HANDLE,
                     type_: CHARACTERISTIC_TYPE,
@@ -446,6 +451,90 @@ fn test_multiple_servers() {
     })
 }
 
+#[test]
+fn test_shared_connection() {
+    start_test(async move {
+        // arrange
+        let (mut gatt, mut transport_rx) = start_gatt_module();
+        let data = AttAttributeDataChild::RawData(DATA.into());
+        let another_data = AttAttributeDataChild::RawData(ANOTHER_DATA.into());
+        // open the default server, without connecting
+        gatt.open_gatt_server(SERVER_ID).unwrap();
+        let (datastore, mut data_rx_1) = MockDatastore::new();
+        gatt.register_gatt_service(
+            SERVER_ID,
+            GattServiceWithHandle {
+                handle: SERVICE_HANDLE,
+                type_: SERVICE_TYPE,
+                secondary: false,
+                included_services: vec![],
+                characteristics: vec![GattCharacteristicWithHandle {
+                    handle: CHARACTERISTIC_HANDLE,
+                    type_: CHARACTERISTIC_TYPE,
+                    permissions: AttPermissions::READABLE,
+                    descriptors: vec![],
+                }],
+            },
+            datastore,
+        )
+        .unwrap();
+        // open a second server with a service at different handles
+        gatt.open_gatt_server(ANOTHER_SERVER_ID).unwrap();
+        let (datastore, mut data_rx_2) = MockDatastore::new();
+        gatt.register_gatt_service(
+            ANOTHER_SERVER_ID,
+            GattServiceWithHandle {
+                handle: AttHandle(50),
+                type_: SERVICE_TYPE,
+                secondary: false,
+                included_services: vec![],
+                characteristics: vec![GattCharacteristicWithHandle {
+                    handle: AttHandle(52),
+                    type_: CHARACTERISTIC_TYPE,
+                    permissions: AttPermissions::READABLE,
+                    descriptors: vec![],
+                }],
+            },
+            datastore,
+        )
+        .unwrap();
+        // connect to both on the same transport
+        gatt.on_le_connect_shared(CONN_ID, &[ANOTHER_SERVER_ID]).unwrap();
+
+        // act: read a characteristic of each server
+        gatt.get_bearer(TCB_IDX).unwrap().handle_packet(
+            build_att_view_or_crash(AttReadRequestBuilder {
+                attribute_handle: CHARACTERISTIC_HANDLE.into(),
+            })
+            .view(),
+        );
+        let MockDatastoreEvents::Read(TCB_IDX, CHARACTERISTIC_HANDLE, _, tx) =
+            data_rx_1.recv().await.unwrap()
+        else {
+            unreachable!()
+        };
+        tx.send(Ok(data)).unwrap();
+        let (_, resp_1) = transport_rx.recv().await.unwrap();
+        gatt.get_bearer(TCB_IDX).unwrap().handle_packet(
+            build_att_view_or_crash(AttReadRequestBuilder {
+                attribute_handle: AttHandle(52).into(),
+            })
+            .view(),
+        );
+        let MockDatastoreEvents::Read(TCB_IDX, AttHandle(52), _, tx) =
+            data_rx_2.recv().await.unwrap()
+        else {
+            unreachable!()
+        };
+        tx.send(Ok(another_data)).unwrap();
+        let (_, resp_2) = transport_rx.recv().await.unwrap();
+
+        // assert: each read was served by the right server
+        assert_eq!(resp_1._child_.to_vec().unwrap(), DATA);
+        assert_eq!(resp_2._child_.to_vec().unwrap(), ANOTHER_DATA);
+    })
+}
+
 #[test]
 fn test_read_device_name() {
     start_test(async move {
@@ -473,6 +562,42 @@ fn test_read_device_name() {
     });
 }
 
+#[test]
+fn test_read_configured_device_name() {
+    start_test(async move {
+        // arrange
+        let (mut gatt, mut transport_rx) = start_gatt_module();
+        create_server_and_open_connection(&mut gatt);
+        gatt.set_gap_configuration(GapConfiguration {
+            device_name: b"name".to_vec(),
+            device_name_readable: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        // act: read the device name
+        gatt.get_bearer(TCB_IDX).unwrap().handle_packet(
+            build_att_view_or_crash(AttReadByTypeRequestBuilder {
+                starting_handle: AttHandle(1).into(),
+                ending_handle: AttHandle(0xFFFF).into(),
+                attribute_type: DEVICE_NAME_UUID.into(),
+            })
+            .view(),
+        );
+        let (tcb_idx, resp) = transport_rx.recv().await.unwrap();
+
+        // assert: the configured name is returned
+        assert_eq!(tcb_idx, TCB_IDX);
+        let AttChild::AttReadByTypeResponse(resp) = resp._child_ else {
+            unreachable!("{resp:?}");
+        };
+        assert_eq!(
+            resp.data[0].value,
+            build_att_data(AttAttributeDataChild::RawData(b"name".to_vec().into()))
+        );
+    });
+}
+
 #[test]
 fn test_ignored_service_change_indication() {
     start_test(async move {
@@ -487,6 +612,8 @@ fn test_ignored_service_change_indication() {
             GattServiceWithHandle {
                 handle: AttHandle(30),
                 type_: SERVICE_TYPE,
+                secondary: false,
+                included_services: vec![],
                 characteristics: vec![],
             },
             datastore,
@@ -593,6 +720,8 @@ fn test_service_change_indication() {
             GattServiceWithHandle {
                 handle: AttHandle(30),
                 type_: SERVICE_TYPE,
+                secondary: false,
+                included_services: vec![],
                 characteristics: vec![],
             },
             datastore,
@@ -613,3 +742,89 @@ fn test_service_change_indication() {
         );
     });
 }
+
+#[test]
+fn test_static_database() {
+    start_test(async move {
+        // arrange: load a static service, then connect
+        let (mut gatt, mut transport_rx) = start_gatt_module();
+        gatt.open_gatt_server(SERVER_ID).unwrap();
+        gatt.load_static_database(
+            SERVER_ID,
+            r#"{
+                "services": [
+                    {
+                        "handle": 10,
+                        "uuid": "0102",
+                        "characteristics": [
+                            {
+                                "handle": 12,
+                                "uuid": "0103",
+                                "permissions": ["read", "write"],
+                                "value": "01020304"
+                            }
+                        ]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        gatt.on_le_connect(CONN_ID).unwrap();
+
+        // act: write the characteristic, then read it back
+        gatt.get_bearer(TCB_IDX).unwrap().handle_packet(
+            build_att_view_or_crash(AttWriteRequestBuilder {
+                handle: CHARACTERISTIC_HANDLE.into(),
+                value: build_att_data(AttAttributeDataChild::RawData(ANOTHER_DATA.into())),
+            })
+            .view(),
+        );
+        let (_, write_resp) = transport_rx.recv().await.unwrap();
+        gatt.get_bearer(TCB_IDX).unwrap().handle_packet(
+            build_att_view_or_crash(AttReadRequestBuilder {
+                attribute_handle: CHARACTERISTIC_HANDLE.into(),
+            })
+            .view(),
+        );
+        let (_, read_resp) = transport_rx.recv().await.unwrap();
+        let dump = gatt.dump_database(SERVER_ID).unwrap();
+
+        // assert: the write was served from memory, and is reflected in the dump
+        assert_eq!(write_resp.opcode, AttOpcode::WRITE_RESPONSE);
+        assert_eq!(
+            read_resp,
+            AttBuilder {
+                opcode: AttOpcode::READ_RESPONSE,
+                _child_: AttReadResponseBuilder {
+                    value: build_att_data(AttAttributeDataChild::RawData(ANOTHER_DATA.into()))
+                }
+                .into()
+            }
+        );
+        assert!(dump.contains(r#""value": "05060708""#), "{dump}");
+        assert!(dump.contains(&format!(r#""uuid": "{}""#, GATT_SERVICE_UUID)), "{dump}");
+    })
+}
+
+#[test]
+fn test_metrics_per_connection() {
+    start_test(async move {
+        // arrange
+        let (mut gatt, mut transport_rx) = start_gatt_module();
+        create_server_and_open_connection(&mut gatt);
+
+        // act: read the service declaration
+        gatt.get_bearer(TCB_IDX).unwrap().handle_packet(
+            build_att_view_or_crash(AttReadRequestBuilder {
+                attribute_handle: SERVICE_HANDLE.into(),
+            })
+            .view(),
+        );
+        transport_rx.recv().await.unwrap();
+
+        // assert: the read was recorded against this connection only
+        let metrics = gatt.get_metrics(TCB_IDX).unwrap();
+        assert_eq!(metrics.get_opcode_stats(AttOpcode::READ_REQUEST).unwrap().count, 1);
+        assert!(gatt.get_metrics(ANOTHER_TCB_IDX).is_none());
+    })
+}
@@ -27,6 +27,7 @@
 #include "stack/gatt/gatt_int.h"
 #include "stack/include/btu.h"  // do_in_main_thread
 #include "stack/include/l2c_api.h"
+#include "stack/include/sdpdefs.h"
 
 namespace bluetooth {
 namespace shim {
@@ -42,6 +43,14 @@ class PassthroughAclArbiter : public AclArbiter {
     // no-op
   }
 
+  virtual void OnLeBonded(uint8_t tcb_idx, tBLE_BD_ADDR identity) override {
+    // no-op
+  }
+
+  virtual void OnBondRemoved(tBLE_BD_ADDR identity) override {
+    // no-op
+  }
+
   virtual InterceptAction InterceptAttPacket(uint8_t tcb_idx,
                                              const BT_HDR* packet) override {
     return InterceptAction::FORWARD;
@@ -69,6 +78,9 @@ namespace {
 struct RustArbiterCallbacks {
   ::rust::Fn<void(uint8_t tcb_idx, uint8_t advertiser)> on_le_connect;
   ::rust::Fn<void(uint8_t tcb_idx)> on_le_disconnect;
+  ::rust::Fn<void(uint8_t tcb_idx, core::AddressWithType identity)>
+      on_le_bonded;
+  ::rust::Fn<void(core::AddressWithType identity)> on_bond_removed;
   ::rust::Fn<InterceptAction(uint8_t tcb_idx, ::rust::Vec<uint8_t> buffer)>
       intercept_packet;
   ::rust::Fn<void(uint8_t tcb_idx)> on_outgoing_mtu_req;
@@ -91,6 +103,16 @@ class RustGattAclArbiter : public AclArbiter {
     callbacks_.on_le_disconnect(tcb_idx);
   }
 
+  virtual void OnLeBonded(uint8_t tcb_idx, tBLE_BD_ADDR identity) override {
+    LOG_INFO("Notifying Rust of bonded LE peer");
+    callbacks_.on_le_bonded(tcb_idx, core::ToRustAddress(identity));
+  }
+
+  virtual void OnBondRemoved(tBLE_BD_ADDR identity) override {
+    LOG_INFO("Notifying Rust of removed bond");
+    callbacks_.on_bond_removed(core::ToRustAddress(identity));
+  }
+
   virtual InterceptAction InterceptAttPacket(uint8_t tcb_idx,
                                              const BT_HDR* packet) override {
     LOG_DEBUG("Intercepting ATT packet and forwarding to Rust");
@@ -145,14 +167,18 @@ class RustGattAclArbiter : public AclArbiter {
 void StoreCallbacksFromRust(
     ::rust::Fn<void(uint8_t tcb_idx, uint8_t advertiser)> on_le_connect,
     ::rust::Fn<void(uint8_t tcb_idx)> on_le_disconnect,
+    ::rust::Fn<void(uint8_t tcb_idx, core::AddressWithType identity)>
+        on_le_bonded,
+    ::rust::Fn<void(core::AddressWithType identity)> on_bond_removed,
     ::rust::Fn<InterceptAction(uint8_t tcb_idx, ::rust::Vec<uint8_t> buffer)>
         intercept_packet,
     ::rust::Fn<void(uint8_t tcb_idx)> on_outgoing_mtu_req,
     ::rust::Fn<void(uint8_t tcb_idx, size_t mtu)> on_incoming_mtu_resp,
     ::rust::Fn<void(uint8_t tcb_idx, size_t mtu)> on_incoming_mtu_req) {
   LOG_INFO("Received callbacks from Rust, registering in Arbiter");
-  callbacks_ = {on_le_connect,       on_le_disconnect,     intercept_packet,
-                on_outgoing_mtu_req, on_incoming_mtu_resp, on_incoming_mtu_req};
+  callbacks_ = {on_le_connect,        on_le_disconnect,    on_le_bonded,
+                on_bond_removed,      intercept_packet,    on_outgoing_mtu_req,
+                on_incoming_mtu_resp, on_incoming_mtu_req};
 }
 
 void SendPacketToPeer(uint8_t tcb_idx, ::rust::Vec<uint8_t> buffer) {
@@ -162,6 +188,19 @@ void SendPacketToPeer(uint8_t tcb_idx, ::rust::Vec<uint8_t> buffer) {
                                tcb_idx, std::move(buffer)));
 }
 
+bool GetSharedGapServiceHandles(uint16_t& start_handle,
+                                uint16_t& end_handle) {
+  const auto gap_service_uuid = Uuid::From16Bit(UUID_SERVCLASS_GAP_SERVER);
+  for (const auto& elem : *gatt_cb.hdl_list_info) {
+    if (elem.asgn_range.svc_uuid == gap_service_uuid) {
+      start_handle = elem.asgn_range.s_handle;
+      end_handle = elem.asgn_range.e_handle;
+      return true;
+    }
+  }
+  return false;
+}
+
 AclArbiter& GetArbiter() {
   return common::init_flags::private_gatt_is_enabled()
              ? static_cast<AclArbiter&>(RustGattAclArbiter::Get())
@@ -23,7 +23,9 @@
 #pragma once
 
 #include "rust/cxx.h"
+#include "rust/src/core/ffi/types.h"
 #include "stack/include/bt_hdr.h"
+#include "types/ble_address_with_type.h"
 #include "types/raw_address.h"
 
 namespace bluetooth {
@@ -41,6 +43,8 @@ class AclArbiter {
  public:
   virtual void OnLeConnect(uint8_t tcb_idx, uint16_t advertiser_id) = 0;
   virtual void OnLeDisconnect(uint8_t tcb_idx) = 0;
+  virtual void OnLeBonded(uint8_t tcb_idx, tBLE_BD_ADDR identity) = 0;
+  virtual void OnBondRemoved(tBLE_BD_ADDR identity) = 0;
   virtual InterceptAction InterceptAttPacket(uint8_t tcb_idx,
                                              const BT_HDR* packet) = 0;
 
@@ -57,6 +61,9 @@ class AclArbiter {
 void StoreCallbacksFromRust(
     ::rust::Fn<void(uint8_t tcb_idx, uint8_t advertiser)> on_le_connect,
     ::rust::Fn<void(uint8_t tcb_idx)> on_le_disconnect,
+    ::rust::Fn<void(uint8_t tcb_idx, core::AddressWithType identity)>
+        on_le_bonded,
+    ::rust::Fn<void(core::AddressWithType identity)> on_bond_removed,
     ::rust::Fn<InterceptAction(uint8_t tcb_idx, ::rust::Vec<uint8_t> buffer)>
         intercept_packet,
     ::rust::Fn<void(uint8_t tcb_idx)> on_outgoing_mtu_req,
@@ -65,6 +72,11 @@ void StoreCallbacksFromRust(
 
 void SendPacketToPeer(uint8_t tcb_idx, ::rust::Vec<uint8_t> buffer);
 
+/// Look up the handles of the GAP service in the shared (legacy) database.
+/// Returns false if it is not registered.
+bool GetSharedGapServiceHandles(uint16_t& start_handle,
+                                uint16_t& end_handle);
+
 AclArbiter& GetArbiter();
 
 }  // namespace arbiter
@@ -38,6 +38,8 @@
 #include "openssl/mem.h"
 #include "osi/include/allocator.h"
 #include "osi/include/properties.h"
+#include "rust/src/connection/ffi/connection_shim.h"
+#include "rust/src/core/ffi/types.h"
 #include "stack/btm/btm_dev.h"
 #include "stack/btm/btm_int_types.h"
 #include "stack/btm/security_device_record.h"
@@ -69,7 +71,21 @@ void gatt_notify_phy_updated(tGATT_STATUS status, uint16_t handle,
 
 namespace {
 constexpr char kBtmLogTag[] = "SEC";
+
+// Let the Rust connection manager match the RPAs of a bonded peer with the
+// connection attempts made to its identity address
+void btm_ble_notify_bonded_identity(const tBTM_SEC_DEV_REC& dev_rec) {
+  if (!bluetooth::common::init_flags::
+          use_unified_connection_manager_is_enabled() ||
+      !(dev_rec.ble.key_type & BTM_LE_KEY_PID)) {
+    return;
+  }
+  bluetooth::connection::GetConnectionManager().add_bonded_identity(
+      bluetooth::core::ToRustAddress(dev_rec.ble.identity_address_with_type),
+      ::rust::Slice<const uint8_t>(dev_rec.ble.keys.irk.data(),
+                                   dev_rec.ble.keys.irk.size()));
 }
+}  // namespace
 
 // Pairing parameters defined in Vol 3, Part H, Chapter 3.5.1 - 3.5.2
 // All present in the exact decimal values, not hex
@@ -195,6 +211,7 @@ void BTM_SecAddBleKey(const RawAddress& bd_addr, tBTM_LE_KEY_VALUE* p_le_key,
   // Only set peer irk. Local irk is always the same.
   if (key_type == BTM_LE_KEY_PID) {
     btm_ble_resolving_list_load_dev(*p_dev_rec);
+    btm_ble_notify_bonded_identity(*p_dev_rec);
   }
 }
 
@@ -1871,6 +1888,8 @@ tBTM_STATUS btm_proc_smp_cback(tSMP_EVT event, const RawAddress& bd_addr,
             p_dev_rec->sec_state = BTM_SEC_STATE_IDLE;
             /* add all bonded device into resolving list if IRK is available*/
             btm_ble_resolving_list_load_dev(*p_dev_rec);
+            btm_ble_notify_bonded_identity(*p_dev_rec);
+            gatt_notify_bonded(bd_addr);
           }
 
           btm_sec_dev_rec_cback_event(p_dev_rec, res, true);
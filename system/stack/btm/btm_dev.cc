@@ -39,11 +39,15 @@
 #include "osi/include/allocator.h"
 #include "osi/include/compat.h"
 #include "rust/src/connection/ffi/connection_shim.h"
+#include "stack/arbiter/acl_arbiter.h"
 #include "stack/include/acl_api.h"
 #include "stack/include/bt_octets.h"
 #include "types/raw_address.h"
 
 extern tBTM_CB btm_cb;
+
+extern const tBLE_BD_ADDR convert_to_address_with_type(
+    const RawAddress& bd_addr, const tBTM_SEC_DEV_REC* p_dev_rec);
 void gatt_consolidate(const RawAddress& identity_addr, const RawAddress& rpa);
 
 namespace {
@@ -177,6 +181,11 @@ bool BTM_SecDeleteDevice(const RawAddress& bd_addr) {
       bluetooth::connection::GetConnectionManager()
           .stop_all_connections_to_device(
               bluetooth::connection::ResolveRawAddress(p_dev_rec->bd_addr));
+      if (p_dev_rec->ble.key_type & BTM_LE_KEY_PID) {
+        bluetooth::connection::GetConnectionManager().remove_bonded_identity(
+            bluetooth::core::ToRustAddress(
+                p_dev_rec->ble.identity_address_with_type));
+      }
     } else {
       BTM_AcceptlistRemove(p_dev_rec->bd_addr);
     }
@@ -184,6 +193,9 @@ bool BTM_SecDeleteDevice(const RawAddress& bd_addr) {
     const auto device_type = p_dev_rec->device_type;
     const auto bond_type = p_dev_rec->bond_type;
 
+    bluetooth::shim::arbiter::GetArbiter().OnBondRemoved(
+        convert_to_address_with_type(bda, p_dev_rec));
+
     /* Clear out any saved BLE keys */
     btm_sec_clear_ble_keys(p_dev_rec);
     wipe_secrets_and_remove(p_dev_rec);
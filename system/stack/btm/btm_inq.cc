@@ -345,6 +345,55 @@ void BTM_EnableInterlacedPageScan() {
   btm_cb.btm_inq_vars.page_scan_type = BTM_SCAN_TYPE_INTERLACED;
 }
 
+void BTM_ApplyScanParameters() {
+  BTM_TRACE_API("BTM_ApplyScanParameters");
+
+  if (!controller_get_interface()->get_is_ready()) return;
+
+  tBTM_INQUIRY_VAR_ST* p_inq = &btm_cb.btm_inq_vars;
+  uint16_t page_scan_type =
+      osi_property_get_int32(PROPERTY_PAGE_SCAN_TYPE, BTM_SCAN_TYPE_INTERLACED);
+  uint16_t inq_scan_type =
+      osi_property_get_int32(PROPERTY_INQ_SCAN_TYPE, BTM_SCAN_TYPE_INTERLACED);
+
+  /* Controllers without interlaced scan only support the standard one */
+  if (!controller_get_interface()->supports_interlaced_inquiry_scan()) {
+    page_scan_type = BTM_SCAN_TYPE_STANDARD;
+    inq_scan_type = BTM_SCAN_TYPE_STANDARD;
+  }
+
+  if (page_scan_type != p_inq->page_scan_type) {
+    btsnd_hcic_write_pagescan_type(page_scan_type);
+    p_inq->page_scan_type = page_scan_type;
+  }
+  if (inq_scan_type != p_inq->inq_scan_type) {
+    btsnd_hcic_write_inqscan_type(inq_scan_type);
+    p_inq->inq_scan_type = inq_scan_type;
+  }
+
+  uint16_t window = osi_property_get_int32(PROPERTY_PAGE_SCAN_WINDOW,
+                                           BTM_DEFAULT_CONN_WINDOW);
+  uint16_t interval = osi_property_get_int32(PROPERTY_PAGE_SCAN_INTERVAL,
+                                             BTM_DEFAULT_CONN_INTERVAL);
+  if ((window != p_inq->page_scan_window) ||
+      (interval != p_inq->page_scan_period)) {
+    btsnd_hcic_write_pagescan_cfg(interval, window);
+    p_inq->page_scan_window = window;
+    p_inq->page_scan_period = interval;
+  }
+
+  window =
+      osi_property_get_int32(PROPERTY_INQ_SCAN_WINDOW, BTM_DEFAULT_DISC_WINDOW);
+  interval = osi_property_get_int32(PROPERTY_INQ_SCAN_INTERVAL,
+                                    BTM_DEFAULT_DISC_INTERVAL);
+  if ((window != p_inq->inq_scan_window) ||
+      (interval != p_inq->inq_scan_period)) {
+    btsnd_hcic_write_inqscan_cfg(interval, window);
+    p_inq->inq_scan_window = window;
+    p_inq->inq_scan_period = interval;
+  }
+}
+
 /*******************************************************************************
  *
  * Function         BTM_SetInquiryMode
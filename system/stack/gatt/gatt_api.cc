@@ -283,6 +283,8 @@ tGATT_STATUS GATTS_AddService(tGATT_IF gatt_if, btgatt_db_element_t* service,
       el->attribute_handle = gatts_add_included_service(
           list.svc_db, p_incl_decl->asgn_range.s_handle,
           p_incl_decl->asgn_range.e_handle, p_incl_decl->asgn_range.svc_uuid);
+      // let the Rust database mirror the include declaration
+      el->start_handle = p_incl_decl->asgn_range.s_handle;
     }
   }
 
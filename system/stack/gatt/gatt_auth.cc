@@ -28,8 +28,10 @@
 #include "gatt_int.h"
 #include "osi/include/allocator.h"
 #include "osi/include/osi.h"
+#include "stack/arbiter/acl_arbiter.h"
 #include "stack/btm/btm_ble_int.h"
 #include "stack/btm/btm_ble_int_types.h"
+#include "stack/btm/btm_dev.h"
 #include "stack/btm/btm_sec.h"
 #include "stack/include/bt_hdr.h"
 #include "types/raw_address.h"
@@ -38,6 +40,9 @@
 
 using base::StringPrintf;
 
+extern const tBLE_BD_ADDR convert_to_address_with_type(
+    const RawAddress& bd_addr, const tBTM_SEC_DEV_REC* p_dev_rec);
+
 /*******************************************************************************
  *
  * Function         gatt_sign_data
@@ -219,6 +224,8 @@ void gatt_notify_enc_cmpl(const RawAddress& bd_addr) {
     return;
   }
 
+  gatt_notify_bonded(bd_addr);
+
   for (uint8_t i = 0; i < GATT_MAX_APPS; i++) {
     if (gatt_cb.cl_rcb[i].in_use && gatt_cb.cl_rcb[i].app_cb.p_enc_cmpl_cb) {
       (*gatt_cb.cl_rcb[i].app_cb.p_enc_cmpl_cb)(gatt_cb.cl_rcb[i].gatt_if,
@@ -239,6 +246,27 @@ void gatt_notify_enc_cmpl(const RawAddress& bd_addr) {
     p_tcb->pending_enc_clcb = new_pending_clcbs;
   }
 }
+
+/*******************************************************************************
+ *
+ * Function         gatt_notify_bonded
+ *
+ * Description      Notify the arbiter that the peer of an LE link is bonded, so
+ *                  that the Rust GATT server can restore (or start persisting)
+ *                  the state of the client.
+ *
+ * Returns
+ *
+ ******************************************************************************/
+void gatt_notify_bonded(const RawAddress& bd_addr) {
+  tGATT_TCB* p_tcb = gatt_find_tcb_by_addr(bd_addr, BT_TRANSPORT_LE);
+  tBTM_SEC_DEV_REC* p_dev_rec = btm_find_dev(bd_addr);
+  if (!p_tcb || !p_dev_rec || !btm_sec_is_a_bonded_dev(bd_addr)) return;
+
+  bluetooth::shim::arbiter::GetArbiter().OnLeBonded(
+      p_tcb->tcb_idx, convert_to_address_with_type(bd_addr, p_dev_rec));
+}
+
 /*******************************************************************************
  *
  * Function         gatt_set_sec_act
@@ -338,6 +338,17 @@ void BTM_EnableInterlacedInquiryScan();
 
 void BTM_EnableInterlacedPageScan();
 
+/*******************************************************************************
+ *
+ * Function         BTM_ApplyScanParameters
+ *
+ * Description      This function writes the page scan and inquiry scan type,
+ *                  interval and window configured in the system properties
+ *                  to the controller, if they differ from the current ones.
+ *
+ ******************************************************************************/
+void BTM_ApplyScanParameters();
+
 /*******************************************************************************
  *
  * Function         BTM_ReadRemoteDeviceName
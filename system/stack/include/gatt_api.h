@@ -1308,6 +1308,10 @@ void gatt_free(void);
 // initiated outside GATT.
 void gatt_notify_enc_cmpl(const RawAddress& bd_addr);
 
+// Notification that the peer of an LE link is bonded, either because pairing
+// has completed or because an existing bond was used to encrypt the link.
+void gatt_notify_bonded(const RawAddress& bd_addr);
+
 /** Reset bg device list. If called after controller reset, set |after_reset| to
  * true, as there is no need to wipe controller acceptlist in this case. */
 void gatt_reset_bgdev_list(bool after_reset);
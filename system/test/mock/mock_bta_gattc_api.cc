@@ -81,7 +81,8 @@ void BTA_GATTC_AppDeregister(tGATT_IF client_if) {
   inc_func_call_count(__func__);
 }
 void BTA_GATTC_AppRegister(tBTA_GATTC_CBACK* p_client_cb,
-                           BtaAppRegisterCallback cb, bool eatt_support) {
+                           BtaAppRegisterCallback cb, bool eatt_support,
+                           bool is_app) {
   inc_func_call_count(__func__);
 }
 void BTA_GATTC_CancelOpen(tGATT_IF client_if, const RawAddress& remote_bda,
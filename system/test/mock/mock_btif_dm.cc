@@ -60,6 +60,10 @@ uint16_t btif_dm_get_connection_state(const RawAddress* bd_addr) {
   inc_func_call_count(__func__);
   return 0;
 }
+void btif_dm_get_remote_security_info(const RawAddress& bd_addr,
+                                      bt_remote_security_info_t* info) {
+  inc_func_call_count(__func__);
+}
 void BTIF_dm_disable() { inc_func_call_count(__func__); }
 void BTIF_dm_enable() { inc_func_call_count(__func__); }
 void BTIF_dm_on_hw_error() { inc_func_call_count(__func__); }
@@ -22,6 +22,7 @@
 #include <cstdint>
 #include <map>
 #include <string>
+#include <vector>
 
 #include "bta/include/bta_hearing_aid_api.h"
 #include "stack/include/bt_octets.h"
@@ -142,6 +143,14 @@ bt_status_t btif_storage_remove_bonded_device(
   inc_func_call_count(__func__);
   return BT_STATUS_SUCCESS;
 }
+bt_status_t btif_storage_set_remote_ctkd(const RawAddress* remote_bd_addr) {
+  inc_func_call_count(__func__);
+  return BT_STATUS_SUCCESS;
+}
+void btif_storage_get_remote_bond_security_info(
+    const RawAddress* remote_bd_addr, bt_remote_security_info_t* info) {
+  inc_func_call_count(__func__);
+}
 bt_status_t btif_storage_remove_hid_info(const RawAddress& remote_bd_addr) {
   inc_func_call_count(__func__);
   return BT_STATUS_SUCCESS;
@@ -199,12 +208,24 @@ void btif_storage_remove_gatt_cl_db_hash(const RawAddress& bd_addr) {
 void btif_storage_remove_gatt_cl_supp_feat(const RawAddress& bd_addr) {
   inc_func_call_count(__func__);
 }
+void btif_storage_remove_gatt_cl_rust_state(const RawAddress& bd_addr) {
+  inc_func_call_count(__func__);
+}
 void btif_storage_remove_hearing_aid(const RawAddress& address) {
   inc_func_call_count(__func__);
 }
 void btif_storage_set_gatt_cl_db_hash(const RawAddress& bd_addr, Octet16 hash) {
   inc_func_call_count(__func__);
 }
+void btif_storage_set_gatt_cl_rust_state(const RawAddress& bd_addr,
+                                         std::vector<uint8_t> state) {
+  inc_func_call_count(__func__);
+}
+std::vector<uint8_t> btif_storage_get_gatt_cl_rust_state(
+    const RawAddress& bd_addr) {
+  inc_func_call_count(__func__);
+  return {};
+}
 void btif_storage_set_gatt_cl_supp_feat(const RawAddress& bd_addr,
                                         uint8_t feat) {
   inc_func_call_count(__func__);
@@ -55,6 +55,11 @@ void bluetooth::shim::ACL_IgnoreLeConnectionFrom(
 void bluetooth::shim::ACL_ConfigureLePrivacy(bool is_le_privacy_enabled) {
   inc_func_call_count(__func__);
 }
+void bluetooth::shim::ACL_SetLePrivacyParameters(
+    bool use_non_resolvable_address,
+    std::chrono::milliseconds rpa_rotation_timeout) {
+  inc_func_call_count(__func__);
+}
 void bluetooth::shim::ACL_WriteData(uint16_t handle, BT_HDR* p_buf) {
   inc_func_call_count(__func__);
 }
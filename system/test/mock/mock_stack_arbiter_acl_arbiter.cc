@@ -26,6 +26,10 @@ class MockAclArbiter : public AclArbiter {
 
   virtual void OnLeDisconnect(uint8_t tcb_idx) override {}
 
+  virtual void OnLeBonded(uint8_t tcb_idx, tBLE_BD_ADDR identity) override {}
+
+  virtual void OnBondRemoved(tBLE_BD_ADDR identity) override {}
+
   virtual InterceptAction InterceptAttPacket(uint8_t tcb_idx,
                                              const BT_HDR* packet) override {
     return InterceptAction::FORWARD;